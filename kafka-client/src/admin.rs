@@ -0,0 +1,492 @@
+use crate::error::{KafkaError, KafkaResult};
+use rdkafka::{
+    ClientConfig,
+    admin::{AdminClient, AdminOptions, AlterConfig, ConfigSource, NewTopic, ResourceSpecifier, TopicReplication},
+    client::DefaultClientContext,
+    types::RDKafkaErrorCode,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+const DESCRIBED_CONFIGS: &[&str] = &[
+    "retention.ms",
+    "retention.bytes",
+    "max.message.bytes",
+    "cleanup.policy",
+    "min.insync.replicas",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicDescription {
+    pub name: String,
+    pub replication_factor: usize,
+    pub partitions: Vec<PartitionDescription>,
+    pub configs: HashMap<String, String>,
+}
+
+impl TopicDescription {
+    /// Compares the topic's `retention.ms` and `retention.bytes` against `minimums`; `-1` means unlimited.
+    pub fn check_retention(&self, minimums: &RetentionMinimums) -> RetentionReport {
+        let config = |key: &str| self.configs.get(key).and_then(|v| v.parse::<i64>().ok());
+        let retention_ms = config("retention.ms");
+        let retention_bytes = config("retention.bytes");
+
+        let mut problems = Vec::new();
+        match retention_ms {
+            Some(ms) if ms >= 0 && ms < minimums.retention_ms => problems.push(format!(
+                "retention.ms is {ms}, below the minimum of {}",
+                minimums.retention_ms
+            )),
+            None => problems.push("retention.ms is unknown".to_owned()),
+            Some(_) => {}
+        }
+        if let Some(min_bytes) = minimums.retention_bytes
+            && let Some(bytes) = retention_bytes
+            && bytes >= 0
+            && bytes < min_bytes
+        {
+            problems.push(format!("retention.bytes is {bytes}, below the minimum of {min_bytes}"));
+        }
+
+        RetentionReport {
+            topic: self.name.clone(),
+            retention_ms,
+            retention_bytes,
+            problems,
+        }
+    }
+}
+
+/// Lowest retention a topic may have before events risk being deleted before they are consumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionMinimums {
+    pub retention_ms: i64,
+    /// Not checked when `None`; size-based retention is usually left unlimited.
+    pub retention_bytes: Option<i64>,
+}
+
+impl Default for RetentionMinimums {
+    fn default() -> Self {
+        Self {
+            retention_ms: 7 * 24 * 60 * 60 * 1000,
+            retention_bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionReport {
+    pub topic: String,
+    pub retention_ms: Option<i64>,
+    pub retention_bytes: Option<i64>,
+    /// Human-readable reasons the retention is insufficient; empty when it is fine.
+    pub problems: Vec<String>,
+}
+
+impl RetentionReport {
+    pub fn is_sufficient(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PartitionDescription {
+    pub id: i32,
+    pub leader: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupDescription {
+    pub group_id: String,
+    pub state: String,
+    pub protocol_type: String,
+    pub protocol: String,
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupMember {
+    pub member_id: String,
+    pub client_id: String,
+    pub client_host: String,
+    /// Assigned partitions keyed by topic.
+    pub assignment: HashMap<String, Vec<i32>>,
+}
+
+pub struct KafkaAdmin {
+    admin: Arc<AdminClient<DefaultClientContext>>,
+}
+
+impl KafkaAdmin {
+    pub fn new(brokers: &str) -> KafkaResult<Self> {
+        if brokers.is_empty() {
+            return Err(KafkaError::InvalidConfig("Brokers cannot be empty".into()));
+        }
+
+        let admin = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create::<AdminClient<DefaultClientContext>>()?;
+
+        tracing::info!(brokers = %brokers, "Kafka admin client started");
+        Ok(Self { admin: Arc::new(admin) })
+    }
+
+    pub async fn list_topics(&self) -> KafkaResult<Vec<String>> {
+        let admin = Arc::clone(&self.admin);
+        tokio::task::spawn_blocking(move || {
+            let metadata = admin.inner().fetch_metadata(None, METADATA_TIMEOUT)?;
+            let mut topics: Vec<String> = metadata
+                .topics()
+                .iter()
+                .map(|t| t.name().to_owned())
+                .filter(|name| !name.starts_with("__"))
+                .collect();
+            topics.sort();
+            Ok(topics)
+        })
+        .await?
+    }
+
+    pub async fn describe_topic(&self, name: &str) -> KafkaResult<TopicDescription> {
+        let admin = Arc::clone(&self.admin);
+        let topic = name.to_owned();
+
+        // Fetching metadata for all topics avoids implicitly creating `name` on brokers with auto-create enabled.
+        let mut description = tokio::task::spawn_blocking(move || {
+            let metadata = admin.inner().fetch_metadata(None, METADATA_TIMEOUT)?;
+            let found = metadata
+                .topics()
+                .iter()
+                .find(|t| t.name() == topic && t.error().is_none())
+                .ok_or_else(|| KafkaError::TopicNotFound(topic.clone()))?;
+
+            let partitions: Vec<PartitionDescription> = found
+                .partitions()
+                .iter()
+                .map(|p| PartitionDescription {
+                    id: p.id(),
+                    leader: p.leader(),
+                    replicas: p.replicas().to_vec(),
+                    isr: p.isr().to_vec(),
+                })
+                .collect();
+
+            Ok::<_, KafkaError>(TopicDescription {
+                replication_factor: partitions.first().map_or(0, |p| p.replicas.len()),
+                name: topic,
+                partitions,
+                configs: HashMap::new(),
+            })
+        })
+        .await??;
+
+        let resources = self
+            .admin
+            .describe_configs(&[ResourceSpecifier::Topic(name)], &AdminOptions::new())
+            .await?;
+        for resource in resources {
+            let resource = resource.map_err(|code| KafkaError::Kafka(rdkafka::error::KafkaError::AdminOp(code)))?;
+            description.configs.extend(
+                resource
+                    .entries
+                    .into_iter()
+                    .filter(|entry| DESCRIBED_CONFIGS.contains(&entry.name.as_str()))
+                    .filter_map(|entry| Some((entry.name, entry.value?))),
+            );
+        }
+
+        Ok(description)
+    }
+
+    /// Describes `name` and checks its retention against `minimums`, logging an error for every shortfall.
+    ///
+    /// With `strict`, insufficient retention is returned as [`KafkaError::InsufficientRetention`] instead of
+    /// only being reported.
+    pub async fn verify_retention(&self, name: &str, minimums: &RetentionMinimums, strict: bool) -> KafkaResult<RetentionReport> {
+        let report = self.describe_topic(name).await?.check_retention(minimums);
+        for problem in &report.problems {
+            tracing::error!(topic = %name, "Kafka topic retention too short: {problem}");
+        }
+        if strict && !report.is_sufficient() {
+            return Err(KafkaError::InsufficientRetention {
+                topic: name.to_owned(),
+                problems: report.problems.join("; "),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Creates `name` unless it already exists. An existing topic with the same partition count is accepted,
+    /// and its retention is brought in line with `retention_ms`; a different partition count is an error.
+    pub async fn ensure_topic(
+        &self,
+        name: &str,
+        partitions: i32,
+        replication: i32,
+        retention_ms: Option<i64>,
+    ) -> KafkaResult<()> {
+        if partitions < 1 || replication < 1 {
+            return Err(KafkaError::InvalidConfig(
+                "Partitions and replication must be at least 1".into(),
+            ));
+        }
+
+        let retention = retention_ms.map(|ms| ms.to_string());
+        let mut topic = NewTopic::new(name, partitions, TopicReplication::Fixed(replication));
+        if let Some(retention) = &retention {
+            topic = topic.set("retention.ms", retention);
+        }
+
+        let results = self.admin.create_topics(&[topic], &AdminOptions::new()).await?;
+        match results.into_iter().next() {
+            Some(Ok(_)) | None => {
+                tracing::info!(topic = %name, partitions, replication, ?retention_ms, "Created Kafka topic");
+                return Ok(());
+            }
+            Some(Err((_, RDKafkaErrorCode::TopicAlreadyExists))) => {}
+            Some(Err((_, code))) => return Err(KafkaError::Kafka(rdkafka::error::KafkaError::AdminOp(code))),
+        }
+
+        let existing = self.describe_topic(name).await?;
+        let existing_partitions = existing.partitions.len() as i32;
+        if existing_partitions != partitions {
+            return Err(KafkaError::PartitionMismatch {
+                topic: name.to_owned(),
+                existing: existing_partitions,
+                requested: partitions,
+            });
+        }
+        if existing.replication_factor as i32 != replication {
+            tracing::warn!(
+                topic = %name,
+                existing = existing.replication_factor,
+                requested = replication,
+                "Existing topic has a different replication factor"
+            );
+        }
+
+        if let Some(retention) = retention
+            && existing.configs.get("retention.ms") != Some(&retention)
+        {
+            self.set_topic_config(name, "retention.ms", &retention).await?;
+            tracing::info!(topic = %name, retention_ms = %retention, "Updated Kafka topic retention");
+        }
+        Ok(())
+    }
+
+    /// Sets one config of topic `name`. AlterConfigs replaces all of a topic's overrides with the ones sent, so
+    /// the current overrides are read back and sent along with the change.
+    async fn set_topic_config(&self, name: &str, key: &str, value: &str) -> KafkaResult<()> {
+        let resources = self
+            .admin
+            .describe_configs(&[ResourceSpecifier::Topic(name)], &AdminOptions::new())
+            .await?;
+        let mut overrides = BTreeMap::new();
+        for resource in resources {
+            let resource = resource.map_err(|code| KafkaError::Kafka(rdkafka::error::KafkaError::AdminOp(code)))?;
+            for entry in resource
+                .entries
+                .into_iter()
+                .filter(|e| e.source == ConfigSource::DynamicTopic)
+            {
+                // Sensitive values are not returned, and sending the rest would drop them.
+                let value = entry.value.ok_or_else(|| {
+                    KafkaError::InvalidConfig(format!("Topic {name} has an unreadable override of {}", entry.name))
+                })?;
+                overrides.insert(entry.name, value);
+            }
+        }
+        overrides.insert(key.to_owned(), value.to_owned());
+
+        let alter = overrides
+            .iter()
+            .fold(AlterConfig::new(ResourceSpecifier::Topic(name)), |alter, (key, value)| {
+                alter.set(key, value)
+            });
+        for result in self.admin.alter_configs(&[alter], &AdminOptions::new()).await? {
+            result.map_err(|(_, code)| KafkaError::Kafka(rdkafka::error::KafkaError::AdminOp(code)))?;
+        }
+        Ok(())
+    }
+
+    /// Creates `name` with `cleanup.policy=compact` unless it already exists, in which case its cleanup policy
+    /// is switched to compaction; only the latest record of each key is then guaranteed to be retained.
+    pub async fn ensure_compacted_topic(&self, name: &str, partitions: i32, replication: i32) -> KafkaResult<()> {
+        self.ensure_topic(name, partitions, replication, None).await?;
+
+        let existing = self.describe_topic(name).await?;
+        if existing.configs.get("cleanup.policy").map(String::as_str) != Some("compact") {
+            self.set_topic_config(name, "cleanup.policy", "compact").await?;
+            tracing::info!(topic = %name, "Enabled log compaction on Kafka topic");
+        }
+        Ok(())
+    }
+
+    pub async fn delete_topic(&self, name: &str) -> KafkaResult<()> {
+        let results = self.admin.delete_topics(&[name], &AdminOptions::new()).await?;
+        for result in results {
+            match result {
+                Ok(_) => {}
+                Err((_, RDKafkaErrorCode::UnknownTopicOrPartition)) => return Err(KafkaError::TopicNotFound(name.to_owned())),
+                Err((_, code)) => return Err(KafkaError::Kafka(rdkafka::error::KafkaError::AdminOp(code))),
+            }
+        }
+        tracing::info!(topic = %name, "Deleted Kafka topic");
+        Ok(())
+    }
+
+    pub async fn describe_group(&self, group_id: &str) -> KafkaResult<GroupDescription> {
+        let admin = Arc::clone(&self.admin);
+        let group_id = group_id.to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let groups = admin.inner().fetch_group_list(Some(&group_id), METADATA_TIMEOUT)?;
+            // The broker answers unknown groups with an empty "Dead" entry rather than an error.
+            let group = groups
+                .groups()
+                .iter()
+                .find(|g| g.name() == group_id && g.state() != "Dead")
+                .ok_or_else(|| KafkaError::GroupNotFound(group_id.clone()))?;
+
+            Ok(GroupDescription {
+                group_id: group.name().to_owned(),
+                state: group.state().to_owned(),
+                protocol_type: group.protocol_type().to_owned(),
+                protocol: group.protocol().to_owned(),
+                members: group
+                    .members()
+                    .iter()
+                    .map(|m| GroupMember {
+                        member_id: m.id().to_owned(),
+                        client_id: m.client_id().to_owned(),
+                        client_host: m.client_host().to_owned(),
+                        assignment: m.assignment().and_then(parse_member_assignment).unwrap_or_default(),
+                    })
+                    .collect(),
+            })
+        })
+        .await?
+    }
+}
+
+/// Decodes a consumer-protocol `MemberAssignment`: version, then `[topic, [partition]]`.
+fn parse_member_assignment(bytes: &[u8]) -> Option<HashMap<String, Vec<i32>>> {
+    let mut reader = Reader(bytes);
+    let _version = reader.i16()?;
+    let topic_count = reader.i32()?;
+
+    let mut assignment = HashMap::new();
+    for _ in 0..topic_count.max(0) {
+        let len = usize::try_from(reader.i16()?).ok()?;
+        let topic = String::from_utf8(reader.take(len)?.to_vec()).ok()?;
+        let partition_count = reader.i32()?;
+        let partitions = (0..partition_count.max(0))
+            .map(|_| reader.i32())
+            .collect::<Option<Vec<_>>>()?;
+        assignment.insert(topic, partitions);
+    }
+    Some(assignment)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_assignment(topics: &[(&str, &[i32])]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(0i16.to_be_bytes());
+        buf.extend((topics.len() as i32).to_be_bytes());
+        for (topic, partitions) in topics {
+            buf.extend((topic.len() as i16).to_be_bytes());
+            buf.extend(topic.as_bytes());
+            buf.extend((partitions.len() as i32).to_be_bytes());
+            for p in *partitions {
+                buf.extend(p.to_be_bytes());
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn parse_assignment_multiple_topics() {
+        let bytes = encode_assignment(&[("images", &[0, 2]), ("channels", &[1])]);
+        let assignment = parse_member_assignment(&bytes).unwrap();
+        assert_eq!(assignment["images"], vec![0, 2]);
+        assert_eq!(assignment["channels"], vec![1]);
+    }
+
+    fn described(configs: &[(&str, &str)]) -> TopicDescription {
+        TopicDescription {
+            name: "images".into(),
+            replication_factor: 1,
+            partitions: Vec::new(),
+            configs: configs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn retention_below_minimum_is_reported() {
+        let minimums = RetentionMinimums {
+            retention_ms: 604_800_000,
+            retention_bytes: Some(1_000_000),
+        };
+        let report = described(&[("retention.ms", "3600000"), ("retention.bytes", "1024")]).check_retention(&minimums);
+
+        assert_eq!(report.retention_ms, Some(3_600_000));
+        assert_eq!(report.problems.len(), 2);
+        assert!(!report.is_sufficient());
+    }
+
+    #[test]
+    fn unlimited_or_long_retention_is_sufficient() {
+        let minimums = RetentionMinimums {
+            retention_ms: 604_800_000,
+            retention_bytes: Some(1_000_000),
+        };
+        assert!(
+            described(&[("retention.ms", "-1"), ("retention.bytes", "-1")])
+                .check_retention(&minimums)
+                .is_sufficient()
+        );
+        assert!(
+            described(&[("retention.ms", "604800000")])
+                .check_retention(&minimums)
+                .is_sufficient()
+        );
+        assert!(!described(&[]).check_retention(&minimums).is_sufficient());
+    }
+
+    #[test]
+    fn parse_assignment_truncated() {
+        let bytes = encode_assignment(&[("images", &[0, 2])]);
+        assert!(parse_member_assignment(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{
+    config::ProducerConfig,
+    consumer::{ConsumedMessage, KafkaConsumer},
+    error::{KafkaError, KafkaResult},
+    producer::KafkaProducer,
+};
+use chrono::Utc;
+use rdkafka::{
+    ClientConfig, Message, Offset, TopicPartitionList,
+    consumer::{BaseConsumer, Consumer},
+    message::OwnedHeaders,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant},
+};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+/// Upper bound for reading the checkpoint topic back on startup.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Durable home of the checkpoints written by [`KafkaConsumer::run_with_checkpoints`].
+///
+/// Blobs are opaque to the store; only the latest one saved under an id has to be returned by `load`.
+/// [`KafkaCheckpointStore`] keeps them in a compacted topic; other backends (a Scylla table, say) implement
+/// this trait in the crate that owns their client.
+pub trait CheckpointStore {
+    fn save(&self, id: &str, blob: Vec<u8>) -> impl Future<Output = KafkaResult<()>> + Send;
+
+    fn load(&self, id: &str) -> impl Future<Output = KafkaResult<Option<Vec<u8>>>> + Send;
+}
+
+/// A consumer's aggregate together with the offsets it reflects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint<S> {
+    pub state: S,
+    /// Next offset to process per topic and partition; every message before it is already folded into `state`.
+    pub offsets: BTreeMap<String, BTreeMap<i32, i64>>,
+    /// Milliseconds since the Unix epoch.
+    pub written_at: i64,
+}
+
+impl<S> Checkpoint<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            state,
+            offsets: BTreeMap::new(),
+            written_at: 0,
+        }
+    }
+
+    pub fn next_offset(&self, topic: &str, partition: i32) -> Option<i64> {
+        self.offsets.get(topic)?.get(&partition).copied()
+    }
+
+    /// Marks the message at `offset` as folded into the state.
+    pub fn record(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.offsets
+            .entry(topic.to_owned())
+            .or_default()
+            .insert(partition, offset + 1);
+    }
+}
+
+/// When [`KafkaConsumer::run_with_checkpoints`] writes a checkpoint: after `every_messages` messages or
+/// `interval` since the last attempt, whichever comes first, and once more on shutdown.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckpointPolicy {
+    pub every_messages: u64,
+    pub interval: Duration,
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> Self {
+        Self {
+            every_messages: 1000,
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Keeps checkpoints in a compacted topic (see [`KafkaAdmin::ensure_compacted_topic`](crate::admin::KafkaAdmin::ensure_compacted_topic)),
+/// keyed by checkpoint id.
+pub struct KafkaCheckpointStore {
+    producer: KafkaProducer,
+    reader: ClientConfig,
+}
+
+impl KafkaCheckpointStore {
+    /// `config.topic` is the checkpoints topic.
+    pub fn new(config: ProducerConfig) -> KafkaResult<Self> {
+        let mut reader = ClientConfig::new();
+        reader
+            .set("bootstrap.servers", &config.brokers)
+            // Never joins the group: partitions are assigned directly and nothing is committed.
+            .set("group.id", format!("{}-reader", config.topic))
+            .set("enable.auto.commit", "false")
+            .set("enable.partition.eof", "false")
+            .set_log_level(config.log_level);
+        if let Some(security) = &config.security {
+            security.apply(&mut reader);
+        }
+        Ok(Self {
+            producer: KafkaProducer::new(config)?,
+            reader,
+        })
+    }
+}
+
+impl CheckpointStore for KafkaCheckpointStore {
+    async fn save(&self, id: &str, blob: Vec<u8>) -> KafkaResult<()> {
+        self.producer
+            .send_raw(Some(id.as_bytes()), &blob, OwnedHeaders::new())
+            .await?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> KafkaResult<Option<Vec<u8>>> {
+        let reader = self.reader.clone();
+        let topic = self.producer.topic().to_owned();
+        let id = id.to_owned();
+        tokio::task::spawn_blocking(move || read_latest(&reader, &topic, &id)).await?
+    }
+}
+
+/// Reads every partition of `topic` up to its current end and returns the last value written under `id`.
+fn read_latest(reader: &ClientConfig, topic: &str, id: &str) -> KafkaResult<Option<Vec<u8>>> {
+    let consumer: BaseConsumer = reader.create()?;
+    let metadata = consumer.fetch_metadata(Some(topic), LOAD_TIMEOUT)?;
+    let partitions: Vec<i32> = metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .collect();
+    if partitions.is_empty() {
+        return Err(KafkaError::TopicNotFound(topic.to_owned()));
+    }
+
+    let mut ends = HashMap::new();
+    let mut assignment = TopicPartitionList::new();
+    for partition in partitions {
+        let (low, high) = consumer.fetch_watermarks(topic, partition, LOAD_TIMEOUT)?;
+        if high > low {
+            ends.insert(partition, high);
+            assignment.add_partition_offset(topic, partition, Offset::Beginning)?;
+        }
+    }
+    consumer.assign(&assignment)?;
+
+    let deadline = Instant::now() + LOAD_TIMEOUT;
+    let mut latest = None;
+    while !ends.is_empty() {
+        if Instant::now() >= deadline {
+            return Err(KafkaError::Timeout(LOAD_TIMEOUT));
+        }
+        let Some(msg) = consumer.poll(Duration::from_millis(100)) else {
+            continue;
+        };
+        let msg = msg?;
+        if msg.key() == Some(id.as_bytes()) {
+            // A tombstone deletes the checkpoint.
+            latest = msg.payload().map(<[u8]>::to_vec);
+        }
+        if ends.get(&msg.partition()).is_some_and(|end| msg.offset() + 1 >= *end) {
+            ends.remove(&msg.partition());
+        }
+    }
+    Ok(latest)
+}
+
+impl KafkaConsumer {
+    /// Folds every message into `state` with `handler` until `shutdown` is cancelled, checkpointing the state
+    /// and the offsets it reflects to `store` under `id` per `policy`. Returns the final state.
+    ///
+    /// On startup the latest checkpoint replaces `state`, and partitions are moved past the offsets it
+    /// records, so a restart resumes the aggregate instead of rebuilding it by replaying. Messages received
+    /// after the last checkpoint are replayed, but the state they are folded into is the checkpointed one,
+    /// so they are not counted twice. Group offsets are only committed after a checkpoint is written, so
+    /// they never get ahead of it. A failed write keeps the state in memory and is retried at the next
+    /// trigger; only durability is delayed. Undecodable messages are logged and skipped.
+    ///
+    /// The aggregate covers the partitions this consumer is assigned, so instances sharing a group need
+    /// their own ids and must keep their assignment (or use [`AssignmentMode::Manual`](crate::config::AssignmentMode::Manual)).
+    pub async fn run_with_checkpoints<T, S, C, F>(
+        &self,
+        id: &str,
+        store: &C,
+        policy: CheckpointPolicy,
+        state: S,
+        mut handler: F,
+        shutdown: CancellationToken,
+    ) -> KafkaResult<S>
+    where
+        T: DeserializeOwned,
+        S: Serialize + DeserializeOwned,
+        C: CheckpointStore,
+        F: FnMut(&mut S, ConsumedMessage<T>),
+    {
+        let mut checkpoint = match store.load(id).await? {
+            Some(blob) => {
+                let checkpoint: Checkpoint<S> =
+                    serde_json::from_slice(&blob).map_err(|source| KafkaError::CorruptCheckpoint {
+                        id: id.to_owned(),
+                        source,
+                    })?;
+                tracing::info!(id, offsets = ?checkpoint.offsets, written_at = checkpoint.written_at, "Restored checkpoint");
+                checkpoint
+            }
+            None => Checkpoint::new(state),
+        };
+
+        let mut positioned = HashSet::new();
+        let mut since_attempt = 0u64;
+        let mut dirty = false;
+        let mut ticker = tokio::time::interval(policy.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            let received = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    if dirty {
+                        dirty = !self.write_checkpoint(id, store, &mut checkpoint).await;
+                        since_attempt = 0;
+                    }
+                    continue;
+                }
+                received = self.recv() => received,
+            };
+            let msg = match received {
+                Ok(msg) => msg,
+                Err(e) => {
+                    tracing::error!(topics = ?self.topics, "Failed to consume message: {e}");
+                    continue;
+                }
+            };
+            let (topic, partition, offset) = (msg.topic(), msg.partition(), msg.offset());
+
+            if let Some(next) = checkpoint.next_offset(topic, partition) {
+                let first = positioned.insert((topic.to_owned(), partition));
+                if offset < next {
+                    // Already folded into the restored state; skip ahead instead of reading the rest one by one.
+                    if first && let Err(e) = self.seek(topic, partition, next).await {
+                        tracing::warn!(topic, partition, next, "Failed to seek to checkpointed offset: {e}");
+                    }
+                    continue;
+                }
+                if first && offset > next {
+                    tracing::warn!(
+                        topic,
+                        partition,
+                        offset,
+                        next,
+                        "Messages after the checkpoint are no longer available"
+                    );
+                }
+            }
+
+            match self.decode::<T>(&msg) {
+                Ok(message) => handler(&mut checkpoint.state, message),
+                Err(e) => tracing::error!(topic, partition, offset, "Skipping undecodable message: {e}"),
+            }
+            checkpoint.record(topic, partition, offset);
+            drop(msg);
+            dirty = true;
+            since_attempt += 1;
+
+            if since_attempt >= policy.every_messages {
+                dirty = !self.write_checkpoint(id, store, &mut checkpoint).await;
+                since_attempt = 0;
+                ticker.reset();
+            }
+        }
+
+        if dirty {
+            self.write_checkpoint(id, store, &mut checkpoint).await;
+        }
+        tracing::info!(id, topics = ?self.topics, "Kafka checkpointing consumer loop stopped");
+        Ok(checkpoint.state)
+    }
+
+    /// Returns whether the checkpoint was written; failures are logged and leave `checkpoint` as it was.
+    async fn write_checkpoint<S: Serialize, C: CheckpointStore>(
+        &self,
+        id: &str,
+        store: &C,
+        checkpoint: &mut Checkpoint<S>,
+    ) -> bool {
+        checkpoint.written_at = Utc::now().timestamp_millis();
+        let blob = match serde_json::to_vec(checkpoint) {
+            Ok(blob) => blob,
+            Err(e) => {
+                tracing::error!(id, "Failed to serialize checkpoint: {e}");
+                return false;
+            }
+        };
+        if let Err(e) = store.save(id, blob).await {
+            tracing::warn!(
+                id,
+                "Failed to write checkpoint, keeping the state in memory until the next attempt: {e}"
+            );
+            return false;
+        }
+        if let Err(e) = self.store_offsets(&checkpoint.offsets).and_then(|()| self.commit()) {
+            tracing::warn!(id, "Checkpoint written but committing its offsets failed: {e}");
+        }
+        tracing::debug!(id, offsets = ?checkpoint.offsets, "Checkpoint written");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_keeps_the_next_offset_per_partition() {
+        let mut checkpoint = Checkpoint::new(0u64);
+        assert_eq!(checkpoint.next_offset("uploads", 0), None);
+
+        checkpoint.record("uploads", 0, 4);
+        checkpoint.record("uploads", 1, 9);
+        checkpoint.record("uploads", 0, 5);
+        assert_eq!(checkpoint.next_offset("uploads", 0), Some(6));
+        assert_eq!(checkpoint.next_offset("uploads", 1), Some(10));
+        assert_eq!(checkpoint.next_offset("other", 0), None);
+    }
+
+    #[test]
+    fn checkpoint_round_trips_through_json() {
+        let mut checkpoint = Checkpoint::new(HashMap::from([("alice".to_owned(), 3u64)]));
+        checkpoint.record("uploads", 2, 41);
+        checkpoint.written_at = 1_700_000_000_000;
+
+        let blob = serde_json::to_vec(&checkpoint).unwrap();
+        let decoded: Checkpoint<HashMap<String, u64>> = serde_json::from_slice(&blob).unwrap();
+        assert_eq!(decoded, checkpoint);
+    }
+}
@@ -1,16 +1,50 @@
-use crate::error::{KafkaError, KafkaResult};
-use rdkafka::config::RDKafkaLogLevel;
+use crate::{
+    error::{KafkaError, KafkaResult},
+    retry::RetryPolicy,
+};
+use chrono::{DateTime, Utc};
+use rdkafka::{ClientConfig, config::RDKafkaLogLevel};
+use std::{collections::HashMap, fmt, path::PathBuf, time::Duration};
+
+const DEFAULT_STATISTICS_INTERVAL_MS: u32 = 5000;
+
+/// Properties the configs own outright; overriding them through the raw map would bypass validation.
+const RESERVED_PROPERTIES: [&str; 2] = ["bootstrap.servers", "group.id"];
+
+fn validate_overrides(overrides: &HashMap<String, String>) -> KafkaResult<()> {
+    for key in overrides.keys() {
+        if key.trim().is_empty() {
+            return Err(KafkaError::InvalidConfig("Property name cannot be empty".into()));
+        }
+        if RESERVED_PROPERTIES.contains(&key.trim()) {
+            return Err(KafkaError::InvalidConfig(format!(
+                "{key} cannot be overridden; set it through the builder"
+            )));
+        }
+    }
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct ConsumerConfig {
     pub brokers: String,
+    /// Empty in [`AssignmentMode::Manual`].
     pub group_id: String,
-    pub input_topic: String,
+    pub topics: Vec<String>,
+    pub assignment: AssignmentMode,
     pub log_level: RDKafkaLogLevel,
     pub session_timeout_ms: u32,
     pub auto_commit: bool,
     pub auto_commit_interval_ms: u32,
     pub auto_offset_reset: OffsetReset,
+    pub delivery: Delivery,
+    pub dead_letter_topic: Option<String>,
+    pub security: Option<SecurityConfig>,
+    pub retry: RetryPolicy,
+    /// How often librdkafka reports statistics to [`KafkaConsumer::stats`](crate::consumer::KafkaConsumer::stats); 0 disables them.
+    pub statistics_interval_ms: u32,
+    /// Raw librdkafka properties, applied after everything else.
+    pub overrides: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +54,159 @@ pub struct ProducerConfig {
     pub message_timeout_ms: u32,
     pub retries: u32,
     pub auto_create_topics: bool,
+    pub log_level: RDKafkaLogLevel,
+    pub security: Option<SecurityConfig>,
+    /// Enables the transactional API (and idempotence); must be stable across restarts of the same producer.
+    pub transactional_id: Option<String>,
+    /// Broker-side transaction timeout, also the timeout of commits and aborts in
+    /// [`KafkaProducer::with_transaction`](crate::producer::KafkaProducer::with_transaction).
+    pub transaction_timeout_ms: u32,
+    /// How often librdkafka reports statistics to [`KafkaProducer::stats`](crate::producer::KafkaProducer::stats); 0 disables them.
+    pub statistics_interval_ms: u32,
+    /// Raw librdkafka properties, applied after everything else.
+    pub overrides: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecurityProtocol {
+    #[default]
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Plaintext => "plaintext",
+            Self::Ssl => "ssl",
+            Self::SaslPlaintext => "sasl_plaintext",
+            Self::SaslSsl => "sasl_ssl",
+        }
+    }
+
+    fn uses_sasl(self) -> bool {
+        matches!(self, Self::SaslPlaintext | Self::SaslSsl)
+    }
+
+    fn uses_ssl(self) -> bool {
+        matches!(self, Self::Ssl | Self::SaslSsl)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaslMechanism {
+    Plain,
+    ScramSha256,
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+            Self::ScramSha256 => "SCRAM-SHA-256",
+            Self::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+/// Broker authentication and transport encryption, shared by producers and consumers.
+///
+/// SASL protocols need `mechanism`, `username` and `password`; SSL protocols use the system trust store
+/// unless `ca_cert_path` is set, and take a client certificate only as a cert/key pair.
+#[derive(Clone, Default)]
+pub struct SecurityConfig {
+    pub protocol: SecurityProtocol,
+    pub mechanism: Option<SaslMechanism>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ca_cert_path: Option<PathBuf>,
+    pub client_cert_path: Option<PathBuf>,
+    pub client_key_path: Option<PathBuf>,
+}
+
+impl fmt::Debug for SecurityConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecurityConfig")
+            .field("protocol", &self.protocol)
+            .field("mechanism", &self.mechanism)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("ca_cert_path", &self.ca_cert_path)
+            .field("client_cert_path", &self.client_cert_path)
+            .field("client_key_path", &self.client_key_path)
+            .finish()
+    }
+}
+
+impl SecurityConfig {
+    pub fn validate(&self) -> KafkaResult<()> {
+        let protocol = self.protocol.as_str();
+        if self.protocol.uses_sasl() {
+            if self.mechanism.is_none() {
+                return Err(KafkaError::InvalidConfig(format!(
+                    "SASL mechanism is required for {protocol}"
+                )));
+            }
+            if self.username.as_deref().is_none_or(str::is_empty) {
+                return Err(KafkaError::InvalidConfig(format!("SASL username is required for {protocol}")));
+            }
+            if self.password.as_deref().is_none_or(str::is_empty) {
+                return Err(KafkaError::InvalidConfig(format!("SASL password is required for {protocol}")));
+            }
+        }
+
+        if self.protocol.uses_ssl() {
+            if self.client_cert_path.is_some() != self.client_key_path.is_some() {
+                return Err(KafkaError::InvalidConfig(
+                    "Client certificate and key must be set together".into(),
+                ));
+            }
+            for path in [&self.ca_cert_path, &self.client_cert_path, &self.client_key_path]
+                .into_iter()
+                .flatten()
+            {
+                if !path.is_file() {
+                    return Err(KafkaError::InvalidConfig(format!(
+                        "Certificate file not found: {}",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, client: &mut ClientConfig) {
+        client.set("security.protocol", self.protocol.as_str());
+
+        if self.protocol.uses_sasl() {
+            if let Some(mechanism) = self.mechanism {
+                client.set("sasl.mechanism", mechanism.as_str());
+            }
+            if let Some(username) = &self.username {
+                client.set("sasl.username", username);
+            }
+            if let Some(password) = &self.password {
+                client.set("sasl.password", password);
+            }
+        }
+
+        if self.protocol.uses_ssl() {
+            let paths = [
+                ("ssl.ca.location", &self.ca_cert_path),
+                ("ssl.certificate.location", &self.client_cert_path),
+                ("ssl.key.location", &self.client_key_path),
+            ];
+            for (key, path) in paths {
+                if let Some(path) = path {
+                    client.set(key, path.display().to_string());
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +215,35 @@ pub enum OffsetReset {
     Latest,
 }
 
+/// How a [`KafkaConsumer`](crate::consumer::KafkaConsumer) gets its partitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssignmentMode {
+    /// Subscribe as a member of this consumer group; partitions follow its rebalances.
+    Group(String),
+    /// Read these partitions of the single input topic without joining any group. Offsets are never
+    /// stored or committed, so the consumer cannot disturb a production group reading the same topic.
+    Manual(Vec<(i32, StartOffset)>),
+}
+
+/// Where a manually assigned partition starts reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartOffset {
+    Beginning,
+    End,
+    Absolute(i64),
+    /// First message at or after this time, or the end of the partition if there is none.
+    Timestamp(DateTime<Utc>),
+}
+
+/// When a consumed message's offset becomes eligible for commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Offsets are stored as soon as a message is received.
+    AtMostOnce,
+    /// Offsets are stored only when the caller acks; auto-commit is disabled.
+    AtLeastOnce,
+}
+
 impl OffsetReset {
     pub fn as_str(self) -> &'static str {
         match self {
@@ -40,12 +256,19 @@ impl OffsetReset {
 pub struct ConsumerConfigBuilder {
     brokers: String,
     group_id: String,
-    input_topic: String,
+    topics: Vec<String>,
+    manual: Option<Vec<(i32, StartOffset)>>,
     log_level: RDKafkaLogLevel,
     session_timeout_ms: u32,
     auto_commit: bool,
     auto_commit_interval_ms: u32,
     auto_offset_reset: OffsetReset,
+    delivery: Delivery,
+    dead_letter_topic: Option<String>,
+    security: Option<SecurityConfig>,
+    retry: RetryPolicy,
+    statistics_interval_ms: u32,
+    overrides: HashMap<String, String>,
 }
 
 impl ConsumerConfigBuilder {
@@ -74,23 +297,119 @@ impl ConsumerConfigBuilder {
         self
     }
 
+    pub fn delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    pub fn dead_letter_topic(mut self, topic: impl Into<String>) -> Self {
+        self.dead_letter_topic = Some(topic.into());
+        self
+    }
+
+    pub fn security(mut self, security: SecurityConfig) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// Handler attempts per message in `run_with_handler` before it is dead-lettered or skipped.
+    pub fn max_retries(mut self, attempts: u32) -> Self {
+        self.retry.max_retries = attempts;
+        self
+    }
+
+    /// Backoff between handler attempts, doubling from `initial_ms` up to `max_ms`.
+    pub fn retry_backoff_ms(mut self, initial_ms: u32, max_ms: u32) -> Self {
+        self.retry.initial_backoff = Duration::from_millis(initial_ms.into());
+        self.retry.max_backoff = Duration::from_millis(max_ms.into());
+        self
+    }
+
+    /// Interval of librdkafka's statistics reports; 0 disables them.
+    pub fn statistics_interval_ms(mut self, ms: u32) -> Self {
+        self.statistics_interval_ms = ms;
+        self
+    }
+
+    /// Sets a raw librdkafka property such as `fetch.max.bytes`, taking precedence over the builder's own
+    /// settings. `bootstrap.servers` and `group.id` are rejected by [`build`](Self::build).
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.insert(key.into(), value.into());
+        self
+    }
+
+    /// [`AssignmentMode::Group`] replaces the builder's group ID; [`AssignmentMode::Manual`] requires
+    /// building with an empty one.
+    pub fn assignment(mut self, mode: AssignmentMode) -> Self {
+        match mode {
+            AssignmentMode::Group(group_id) => {
+                self.group_id = group_id;
+                self.manual = None;
+            }
+            AssignmentMode::Manual(partitions) => self.manual = Some(partitions),
+        }
+        self
+    }
+
     pub fn build(self) -> KafkaResult<ConsumerConfig> {
         if self.brokers.is_empty() {
             return Err(KafkaError::InvalidConfig("Brokers cannot be empty".into()));
         }
-        if self.group_id.is_empty() {
-            return Err(KafkaError::InvalidConfig("Group ID cannot be empty".into()));
+        if self.topics.is_empty() || self.topics.iter().any(String::is_empty) {
+            return Err(KafkaError::InvalidConfig("Topics cannot be empty".into()));
+        }
+        let assignment = match self.manual {
+            None if self.group_id.is_empty() => {
+                return Err(KafkaError::InvalidConfig("Group ID cannot be empty".into()));
+            }
+            None => AssignmentMode::Group(self.group_id.clone()),
+            Some(_) if !self.group_id.is_empty() => {
+                return Err(KafkaError::InvalidConfig(
+                    "Manual assignment cannot be combined with a group ID".into(),
+                ));
+            }
+            Some(_) if self.topics.len() != 1 => {
+                return Err(KafkaError::InvalidConfig("Manual assignment needs exactly one topic".into()));
+            }
+            Some(partitions) if partitions.is_empty() => {
+                return Err(KafkaError::InvalidConfig(
+                    "Manual assignment needs at least one partition".into(),
+                ));
+            }
+            Some(partitions) => AssignmentMode::Manual(partitions),
+        };
+        if let Some(dead_letter_topic) = &self.dead_letter_topic
+            && self.topics.contains(dead_letter_topic)
+        {
+            return Err(KafkaError::InvalidConfig(
+                "Dead letter topic must differ from the input topics".into(),
+            ));
+        }
+        if let Some(security) = &self.security {
+            security.validate()?;
         }
+        if self.retry.max_retries == 0 {
+            return Err(KafkaError::InvalidConfig("Max retries must be at least 1".into()));
+        }
+        validate_overrides(&self.overrides)?;
 
+        let grouped = matches!(assignment, AssignmentMode::Group(_));
         Ok(ConsumerConfig {
             brokers: self.brokers,
             group_id: self.group_id,
-            input_topic: self.input_topic,
+            topics: self.topics,
+            assignment,
             log_level: self.log_level,
             session_timeout_ms: self.session_timeout_ms,
-            auto_commit: self.auto_commit,
+            auto_commit: grouped && self.auto_commit && self.delivery == Delivery::AtMostOnce,
             auto_commit_interval_ms: self.auto_commit_interval_ms,
             auto_offset_reset: self.auto_offset_reset,
+            delivery: self.delivery,
+            dead_letter_topic: self.dead_letter_topic,
+            security: self.security,
+            retry: self.retry,
+            statistics_interval_ms: self.statistics_interval_ms,
+            overrides: self.overrides,
         })
     }
 }
@@ -100,18 +419,62 @@ impl ConsumerConfig {
         brokers: impl Into<String>,
         group_id: impl Into<String>,
         input_topic: impl Into<String>,
+    ) -> ConsumerConfigBuilder {
+        Self::builder_with_topics(brokers, group_id, [input_topic])
+    }
+
+    /// Builder for a consumer subscribed to several topics in one group.
+    pub fn builder_with_topics<S: Into<String>>(
+        brokers: impl Into<String>,
+        group_id: impl Into<String>,
+        topics: impl IntoIterator<Item = S>,
     ) -> ConsumerConfigBuilder {
         ConsumerConfigBuilder {
             brokers: brokers.into(),
             group_id: group_id.into(),
-            input_topic: input_topic.into(),
+            topics: topics.into_iter().map(Into::into).collect(),
+            manual: None,
             log_level: RDKafkaLogLevel::Info,
             session_timeout_ms: 6000,
             auto_commit: true,
             auto_commit_interval_ms: 5000,
             auto_offset_reset: OffsetReset::Earliest,
+            delivery: Delivery::AtMostOnce,
+            dead_letter_topic: None,
+            security: None,
+            retry: RetryPolicy::default(),
+            statistics_interval_ms: DEFAULT_STATISTICS_INTERVAL_MS,
+            overrides: HashMap::new(),
         }
     }
+
+    /// librdkafka properties for a [`KafkaConsumer`](crate::consumer::KafkaConsumer) built from this config.
+    pub fn client_config(&self) -> ClientConfig {
+        // librdkafka refuses to poll without a group.id; a manual consumer never joins or commits to it.
+        let group_id = match &self.assignment {
+            AssignmentMode::Group(group_id) => group_id.clone(),
+            AssignmentMode::Manual(_) => format!("{}-manual", self.topics.join("-")),
+        };
+        let mut client = ClientConfig::new();
+        client
+            .set("group.id", group_id)
+            .set("bootstrap.servers", &self.brokers)
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", self.session_timeout_ms.to_string())
+            .set("enable.auto.commit", self.auto_commit.to_string())
+            .set("auto.commit.interval.ms", self.auto_commit_interval_ms.to_string())
+            .set("enable.auto.offset.store", "false")
+            .set("auto.offset.reset", self.auto_offset_reset.as_str())
+            .set("statistics.interval.ms", self.statistics_interval_ms.to_string())
+            .set_log_level(self.log_level);
+        if let Some(security) = &self.security {
+            security.apply(&mut client);
+        }
+        for (key, value) in &self.overrides {
+            client.set(key, value);
+        }
+        client
+    }
 }
 
 pub struct ProducerConfigBuilder {
@@ -120,6 +483,12 @@ pub struct ProducerConfigBuilder {
     message_timeout_ms: u32,
     retries: u32,
     auto_create_topics: bool,
+    log_level: RDKafkaLogLevel,
+    security: Option<SecurityConfig>,
+    transactional_id: Option<String>,
+    transaction_timeout_ms: u32,
+    statistics_interval_ms: u32,
+    overrides: HashMap<String, String>,
 }
 
 impl ProducerConfigBuilder {
@@ -138,6 +507,39 @@ impl ProducerConfigBuilder {
         self
     }
 
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level.into();
+        self
+    }
+
+    pub fn security(mut self, security: SecurityConfig) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// Interval of librdkafka's statistics reports; 0 disables them.
+    pub fn statistics_interval_ms(mut self, ms: u32) -> Self {
+        self.statistics_interval_ms = ms;
+        self
+    }
+
+    pub fn transactional_id(mut self, id: impl Into<String>) -> Self {
+        self.transactional_id = Some(id.into());
+        self
+    }
+
+    pub fn transaction_timeout_ms(mut self, ms: u32) -> Self {
+        self.transaction_timeout_ms = ms;
+        self
+    }
+
+    /// Sets a raw librdkafka property such as `queue.buffering.max.ms`, taking precedence over the builder's own
+    /// settings. `bootstrap.servers` and `group.id` are rejected by [`build`](Self::build).
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.insert(key.into(), value.into());
+        self
+    }
+
     pub fn build(self) -> KafkaResult<ProducerConfig> {
         if self.brokers.is_empty() {
             return Err(KafkaError::InvalidConfig("Brokers cannot be empty".into()));
@@ -145,6 +547,13 @@ impl ProducerConfigBuilder {
         if self.topic.is_empty() {
             return Err(KafkaError::InvalidConfig("Topic cannot be empty".into()));
         }
+        if self.transactional_id.as_deref().is_some_and(str::is_empty) {
+            return Err(KafkaError::InvalidConfig("Transactional ID cannot be empty".into()));
+        }
+        if let Some(security) = &self.security {
+            security.validate()?;
+        }
+        validate_overrides(&self.overrides)?;
 
         Ok(ProducerConfig {
             brokers: self.brokers,
@@ -152,6 +561,12 @@ impl ProducerConfigBuilder {
             message_timeout_ms: self.message_timeout_ms,
             retries: self.retries,
             auto_create_topics: self.auto_create_topics,
+            log_level: self.log_level,
+            security: self.security,
+            transactional_id: self.transactional_id,
+            transaction_timeout_ms: self.transaction_timeout_ms,
+            statistics_interval_ms: self.statistics_interval_ms,
+            overrides: self.overrides,
         })
     }
 }
@@ -164,7 +579,37 @@ impl ProducerConfig {
             message_timeout_ms: 5000,
             retries: 3,
             auto_create_topics: false,
+            log_level: RDKafkaLogLevel::Info,
+            security: None,
+            transactional_id: None,
+            transaction_timeout_ms: 60_000,
+            statistics_interval_ms: DEFAULT_STATISTICS_INTERVAL_MS,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// librdkafka properties for a [`KafkaProducer`](crate::producer::KafkaProducer) built from this config.
+    pub fn client_config(&self) -> ClientConfig {
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &self.brokers)
+            .set("message.timeout.ms", self.message_timeout_ms.to_string())
+            .set("allow.auto.create.topics", self.auto_create_topics.to_string())
+            .set("retries", self.retries.to_string())
+            .set("statistics.interval.ms", self.statistics_interval_ms.to_string())
+            .set_log_level(self.log_level);
+        if let Some(transactional_id) = &self.transactional_id {
+            client
+                .set("transactional.id", transactional_id)
+                .set("transaction.timeout.ms", self.transaction_timeout_ms.to_string());
+        }
+        if let Some(security) = &self.security {
+            security.apply(&mut client);
+        }
+        for (key, value) in &self.overrides {
+            client.set(key, value);
         }
+        client
     }
 }
 
@@ -1,67 +1,492 @@
 use crate::{
-    config::ConsumerConfig,
+    config::{AssignmentMode, ConsumerConfig, Delivery, ProducerConfig, StartOffset},
     error::{KafkaError, KafkaResult},
+    lag::partition_lag,
+    producer::KafkaProducer,
+    retry::{RetryOutcome, RetryPolicy, handle_with_retries},
+    stats::{ConsumerStats, StatsContext, StatsHandle},
 };
-use futures::Stream;
+use chrono::{DateTime, Utc};
+use futures::{FutureExt, Stream};
 use rdkafka::{
-    ClientConfig, Message,
-    consumer::{Consumer, StreamConsumer},
+    ClientContext, Message, Offset, TopicPartitionList,
+    consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer},
+    error::RDKafkaErrorCode,
+    message::{BorrowedMessage, Header, Headers, OwnedHeaders, OwnedMessage},
+    statistics::Statistics,
 };
 use serde::de::DeserializeOwned;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Upper bound for the blocking seek and offset lookup calls, which run on the blocking pool.
+const SEEK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A decoded message together with its Kafka metadata.
+#[derive(Debug, Clone)]
+pub struct ConsumedMessage<T> {
+    pub message: T,
+    pub headers: HashMap<String, String>,
+    /// Source topic, for consumers subscribed to several.
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the Unix epoch, if the broker provided one.
+    pub timestamp: Option<i64>,
+}
+
+/// Marks a message from [`KafkaConsumer::consume_uncommitted`] as processed.
+///
+/// Dropping it without calling [`Ack::ack`] leaves the offset unstored, so the message is
+/// redelivered after a restart or rebalance unless a later message on the same partition is acked.
+#[must_use = "the message offset is only committed once ack() is called"]
+pub struct Ack<'a> {
+    consumer: &'a KafkaConsumer,
+    topic: String,
+    partition: i32,
+    offset: i64,
+    acked: bool,
+}
+
+impl Ack<'_> {
+    pub fn ack(mut self) -> KafkaResult<()> {
+        if !self.consumer.manual {
+            self.consumer
+                .consumer
+                .store_offset(&self.topic, self.partition, self.offset)?;
+        }
+        if self.consumer.delivery == Delivery::AtLeastOnce {
+            self.consumer.commit_stored(CommitMode::Async)?;
+        }
+        self.acked = true;
+        tracing::debug!(topic = %self.topic, partition = self.partition, offset = self.offset, "Message acked");
+        Ok(())
+    }
+}
+
+impl Drop for Ack<'_> {
+    fn drop(&mut self) {
+        if !self.acked {
+            tracing::debug!(
+                topic = %self.topic,
+                partition = self.partition,
+                offset = self.offset,
+                "Message dropped without ack"
+            );
+        }
+    }
+}
+
+/// Position of one partition in [`KafkaConsumer::current_assignment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssignedPartition {
+    pub topic: String,
+    pub partition: i32,
+    /// Next offset to be fetched; `None` until the consumer has fetched from the partition.
+    pub offset: Option<i64>,
+}
+
+/// Keeps the statistics reports and the partitions handed out by the latest rebalance.
+struct ConsumerState {
+    stats: StatsContext<ConsumerStats>,
+    assigned: RwLock<BTreeSet<(String, i32)>>,
+    /// Set by [`KafkaConsumer::pause`]; partitions assigned while paused start out paused too.
+    paused: AtomicBool,
+}
+
+impl ConsumerState {
+    fn assigned(&self) -> BTreeSet<(String, i32)> {
+        self.assigned.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ClientContext for ConsumerState {
+    fn stats(&self, statistics: Statistics) {
+        self.stats.stats(statistics);
+    }
+}
+
+impl ConsumerContext for ConsumerState {
+    // Eager rebalances revoke everything before assigning; cooperative ones only pass the delta.
+    fn post_rebalance(&self, consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        let mut assigned = self.assigned.write().unwrap_or_else(|e| e.into_inner());
+        match rebalance {
+            Rebalance::Assign(partitions) => {
+                assigned.extend(partitions.elements().iter().map(|p| (p.topic().to_owned(), p.partition())));
+                if self.paused.load(Ordering::Acquire)
+                    && let Err(e) = consumer.pause(partitions)
+                {
+                    tracing::error!("Failed to pause newly assigned partitions: {e}");
+                }
+            }
+            Rebalance::Revoke(partitions) => {
+                for p in partitions.elements() {
+                    assigned.remove(&(p.topic().to_owned(), p.partition()));
+                }
+            }
+            Rebalance::Error(e) => tracing::warn!("Rebalance failed: {e}"),
+        }
+        tracing::info!(partitions = assigned.len(), "Consumer assignment changed");
+    }
+}
 
 pub struct KafkaConsumer {
-    consumer: StreamConsumer,
-    pub input_topic: String,
+    /// Shared with the blocking tasks that seek.
+    consumer: Arc<StreamConsumer<ConsumerState>>,
+    stats: StatsHandle<ConsumerStats>,
+    delivery: Delivery,
+    dead_letter: Option<KafkaProducer>,
+    dead_lettered: AtomicU64,
+    skipped_total: AtomicU64,
+    retry: RetryPolicy,
+    /// Assigned without a group: offsets are never stored or committed.
+    manual: bool,
+    pub topics: Vec<String>,
 }
 
 impl KafkaConsumer {
     pub fn new(config: ConsumerConfig) -> KafkaResult<Self> {
-        let consumer = ClientConfig::new()
-            .set("group.id", &config.group_id)
-            .set("bootstrap.servers", &config.brokers)
-            .set("enable.partition.eof", "false")
-            .set("session.timeout.ms", config.session_timeout_ms.to_string())
-            .set("enable.auto.commit", config.auto_commit.to_string())
-            .set("auto.commit.interval.ms", config.auto_commit_interval_ms.to_string())
-            .set("enable.auto.offset.store", "false")
-            .set("auto.offset.reset", config.auto_offset_reset.as_str())
-            .set_log_level(config.log_level)
-            .create::<StreamConsumer>()?;
-
-        consumer.subscribe(&[&config.input_topic])?;
+        let context = ConsumerState {
+            stats: StatsContext::new(),
+            assigned: RwLock::new(BTreeSet::new()),
+            paused: AtomicBool::new(false),
+        };
+        let stats = context.stats.handle();
+        let consumer: StreamConsumer<_> = config.client_config().create_with_context(context)?;
+
+        match &config.assignment {
+            AssignmentMode::Group(_) => {
+                let topics: Vec<&str> = config.topics.iter().map(String::as_str).collect();
+                consumer.subscribe(&topics)?;
+            }
+            AssignmentMode::Manual(partitions) => assign_manual(&consumer, &config.topics[0], partitions)?,
+        }
+
+        let dead_letter = config
+            .dead_letter_topic
+            .as_deref()
+            .map(|topic| {
+                let mut builder = ProducerConfig::builder(&config.brokers, topic);
+                if let Some(security) = &config.security {
+                    builder = builder.security(security.clone());
+                }
+                KafkaProducer::new(builder.build()?)
+            })
+            .transpose()?;
 
         tracing::info!(
             brokers = %config.brokers,
             group_id = %config.group_id,
-            topic = %config.input_topic,
+            topics = ?config.topics,
+            manual = matches!(config.assignment, AssignmentMode::Manual(_)),
+            delivery = ?config.delivery,
+            dead_letter_topic = ?config.dead_letter_topic,
             "Kafka consumer started"
         );
 
         Ok(Self {
-            consumer,
-            input_topic: config.input_topic,
+            consumer: Arc::new(consumer),
+            stats,
+            delivery: config.delivery,
+            dead_letter,
+            dead_lettered: AtomicU64::new(0),
+            skipped_total: AtomicU64::new(0),
+            retry: config.retry,
+            manual: matches!(config.assignment, AssignmentMode::Manual(_)),
+            topics: config.topics,
         })
     }
 
     pub async fn consume_raw(&self) -> KafkaResult<Vec<u8>> {
-        tracing::debug!("Waiting for message from topic: {}", self.input_topic);
-        let msg = self.consumer.recv().await?;
-        tracing::info!("Received message from partition {}", msg.partition());
-
-        let payload = msg
-            .payload()
-            .ok_or_else(|| KafkaError::EmptyPayload {
-                topic: self.input_topic.to_owned(),
-            })?
-            .to_vec();
-
-        self.consumer.store_offset_from_message(&msg)?;
+        let msg = self.recv().await?;
+        let payload = payload(&msg)?.to_vec();
+        self.store_offset(&msg)?;
         Ok(payload)
     }
 
     pub async fn consume<T: DeserializeOwned>(&self) -> KafkaResult<T> {
-        let payload = self.consume_raw().await?;
-        serde_json::from_slice(&payload).map_err(KafkaError::Serialization)
+        let msg = self.recv().await?;
+        let payload = payload(&msg)?;
+        self.store_offset(&msg)?;
+        deserialize(msg.topic(), payload)
+    }
+
+    pub async fn consume_message<T: DeserializeOwned>(&self) -> KafkaResult<ConsumedMessage<T>> {
+        let msg = self.recv().await?;
+        self.store_offset(&msg)?;
+        self.decode(&msg)
+    }
+
+    /// Like [`Self::consume_message`], but undecodable messages are forwarded to the dead letter
+    /// topic with their original bytes and error metadata headers, and consumption continues.
+    /// Without a configured dead letter topic this behaves exactly like `consume_message`.
+    pub async fn consume_or_dead_letter<T: DeserializeOwned>(&self) -> KafkaResult<ConsumedMessage<T>> {
+        let Some(dead_letter) = &self.dead_letter else {
+            return self.consume_message().await;
+        };
+
+        loop {
+            let msg = self.recv().await?;
+            match self.decode(&msg) {
+                Ok(consumed) => {
+                    self.store_offset(&msg)?;
+                    return Ok(consumed);
+                }
+                Err(err @ (KafkaError::EmptyPayload { .. } | KafkaError::Deserialization { .. })) => {
+                    dead_letter
+                        .send_raw(msg.key(), msg.payload().unwrap_or_default(), dead_letter_headers(&msg, &err))
+                        .await?;
+                    self.store_offset(&msg)?;
+                    let total = self.dead_lettered.fetch_add(1, Ordering::Relaxed) + 1;
+                    tracing::warn!(
+                        topic = %msg.topic(),
+                        partition = msg.partition(),
+                        offset = msg.offset(),
+                        total,
+                        "Message dead-lettered: {err}"
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Number of messages this consumer has forwarded to the dead letter topic.
+    pub fn dead_lettered_count(&self) -> u64 {
+        self.dead_lettered.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages `run_with_handler` gave up on without a dead letter topic to forward them to.
+    pub fn skipped_total(&self) -> u64 {
+        self.skipped_total.load(Ordering::Relaxed)
+    }
+
+    /// Latest librdkafka statistics, including lag per assigned partition; `None` until the first report.
+    pub fn stats(&self) -> Option<ConsumerStats> {
+        self.stats.get()
+    }
+
+    /// Shared view of [`stats`](Self::stats) that outlives moving the consumer into its run loop.
+    pub fn stats_handle(&self) -> StatsHandle<ConsumerStats> {
+        self.stats.clone()
+    }
+
+    /// Receives a message without storing its offset; the caller must [`Ack::ack`] it once processed.
+    pub async fn consume_uncommitted<T: DeserializeOwned>(&self) -> KafkaResult<(ConsumedMessage<T>, Ack<'_>)> {
+        let msg = self.recv().await?;
+        let consumed = self.decode(&msg)?;
+        Ok((consumed, self.ack_for(&msg)))
+    }
+
+    fn ack_for(&self, msg: &impl Message) -> Ack<'_> {
+        Ack {
+            consumer: self,
+            topic: msg.topic().to_owned(),
+            partition: msg.partition(),
+            offset: msg.offset(),
+            acked: false,
+        }
+    }
+
+    /// Synchronously commits all stored (acked) offsets.
+    pub fn commit(&self) -> KafkaResult<()> {
+        self.commit_stored(CommitMode::Sync)
+    }
+
+    /// Stops fetching from every assigned partition, including ones assigned by later rebalances, without
+    /// leaving the group. Messages already received stay deliverable; nothing new arrives until [`resume`](Self::resume).
+    pub fn pause(&self) -> KafkaResult<()> {
+        self.consumer.context().paused.store(true, Ordering::Release);
+        let assignment = self.consumer.assignment()?;
+        if assignment.count() > 0 {
+            self.consumer.pause(&assignment)?;
+        }
+        tracing::info!(topics = ?self.topics, partitions = assignment.count(), "Kafka consumer paused");
+        Ok(())
+    }
+
+    pub fn resume(&self) -> KafkaResult<()> {
+        self.consumer.context().paused.store(false, Ordering::Release);
+        let assignment = self.consumer.assignment()?;
+        if assignment.count() > 0 {
+            self.consumer.resume(&assignment)?;
+        }
+        tracing::info!(topics = ?self.topics, partitions = assignment.count(), "Kafka consumer resumed");
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.consumer.context().paused.load(Ordering::Acquire)
+    }
+
+    /// High watermark minus committed offset for each assigned partition; partitions without a
+    /// committed offset count from their low watermark. Blocks on broker round trips.
+    pub fn lag(&self) -> KafkaResult<HashMap<(String, i32), i64>> {
+        let assigned = self.consumer.context().assigned();
+        if assigned.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut partitions = TopicPartitionList::with_capacity(assigned.len());
+        for (topic, partition) in &assigned {
+            partitions.add_partition(topic, *partition);
+        }
+
+        let committed = self.consumer.committed_offsets(partitions, SEEK_TIMEOUT)?;
+        committed
+            .elements()
+            .iter()
+            .map(|p| {
+                let (low, high) = self.consumer.fetch_watermarks(p.topic(), p.partition(), SEEK_TIMEOUT)?;
+                Ok(((p.topic().to_owned(), p.partition()), partition_lag(p.offset(), low, high)))
+            })
+            .collect()
+    }
+
+    /// Partitions currently assigned, by rebalance or manually, with their fetch positions, sorted by topic and partition.
+    pub fn current_assignment(&self) -> KafkaResult<Vec<AssignedPartition>> {
+        let positions = self.consumer.position()?;
+        Ok(self
+            .consumer
+            .context()
+            .assigned()
+            .into_iter()
+            .map(|(topic, partition)| {
+                let offset = match positions.find_partition(&topic, partition).map(|p| p.offset()) {
+                    Some(Offset::Offset(offset)) => Some(offset),
+                    _ => None,
+                };
+                AssignedPartition {
+                    topic,
+                    partition,
+                    offset,
+                }
+            })
+            .collect())
+    }
+
+    /// Moves one assigned partition to `offset`; the next message received from it is the one at that offset.
+    pub async fn seek(&self, topic: &str, partition: i32, offset: i64) -> KafkaResult<()> {
+        if !self.consumer.context().assigned().contains(&(topic.to_owned(), partition)) {
+            return Err(KafkaError::NotAssigned {
+                topic: topic.to_owned(),
+                partition,
+            });
+        }
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition_offset(topic, partition, Offset::Offset(offset))?;
+        self.seek_partitions(partitions).await
+    }
+
+    /// Replays every assigned partition from its earliest retained message.
+    pub async fn seek_to_beginning(&self) -> KafkaResult<()> {
+        self.seek_partitions(self.assigned_at(Offset::Beginning)?).await
+    }
+
+    /// Moves every assigned partition to its first message at or after `ts`; partitions without
+    /// such a message are moved to their end.
+    pub async fn seek_to_timestamp(&self, ts: DateTime<Utc>) -> KafkaResult<()> {
+        let query = self.assigned_at(Offset::Offset(ts.timestamp_millis()))?;
+        let consumer = Arc::clone(&self.consumer);
+        let offsets = tokio::task::spawn_blocking(move || consumer.offsets_for_times(query, SEEK_TIMEOUT)).await??;
+        self.seek_partitions(offsets).await
+    }
+
+    fn assigned_at(&self, offset: Offset) -> KafkaResult<TopicPartitionList> {
+        let assigned = self.consumer.context().assigned();
+        if assigned.is_empty() {
+            return Err(KafkaError::NoAssignment);
+        }
+        let mut partitions = TopicPartitionList::with_capacity(assigned.len());
+        for (topic, partition) in &assigned {
+            partitions.add_partition_offset(topic, *partition, offset)?;
+        }
+        Ok(partitions)
+    }
+
+    async fn seek_partitions(&self, partitions: TopicPartitionList) -> KafkaResult<()> {
+        let consumer = Arc::clone(&self.consumer);
+        let result = tokio::task::spawn_blocking(move || consumer.seek_partitions(partitions, SEEK_TIMEOUT)).await??;
+        for p in result.elements() {
+            p.error()?;
+            tracing::info!(topic = p.topic(), partition = p.partition(), offset = ?p.offset(), "Consumer seeked");
+        }
+        Ok(())
+    }
+
+    fn store_offset(&self, msg: &BorrowedMessage<'_>) -> KafkaResult<()> {
+        if !self.manual {
+            self.consumer.store_offset_from_message(msg)?;
+        }
+        Ok(())
+    }
+
+    /// Stores the next offset to read for each partition of each topic, replacing whatever was acked before.
+    pub(crate) fn store_offsets(&self, offsets: &BTreeMap<String, BTreeMap<i32, i64>>) -> KafkaResult<()> {
+        let mut partitions = TopicPartitionList::new();
+        for (topic, nexts) in offsets {
+            for (partition, next) in nexts {
+                partitions.add_partition_offset(topic, *partition, Offset::Offset(*next))?;
+            }
+        }
+        if self.manual || partitions.count() == 0 {
+            return Ok(());
+        }
+        self.consumer.store_offsets(&partitions)?;
+        Ok(())
+    }
+
+    fn commit_stored(&self, mode: CommitMode) -> KafkaResult<()> {
+        if self.manual {
+            return Ok(());
+        }
+        match self.consumer.commit_consumer_state(mode) {
+            Err(rdkafka::error::KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    pub(crate) async fn recv(&self) -> KafkaResult<BorrowedMessage<'_>> {
+        tracing::debug!("Waiting for message from topics: {:?}", self.topics);
+        let msg = self.consumer.recv().await?;
+        tracing::info!("Received message from {} partition {}", msg.topic(), msg.partition());
+        Ok(msg)
+    }
+
+    /// Like [`Self::recv`], but `None` instead of waiting when librdkafka has no message buffered.
+    pub(crate) fn try_recv(&self) -> Option<KafkaResult<BorrowedMessage<'_>>> {
+        let msg = self.consumer.recv().now_or_never()?;
+        Some(msg.map_err(KafkaError::from))
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, msg: &impl Message) -> KafkaResult<ConsumedMessage<T>> {
+        let message = deserialize(msg.topic(), payload(msg)?)?;
+
+        let headers = msg
+            .headers()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .filter_map(|h| Some((h.key.to_owned(), String::from_utf8_lossy(h.value?).into_owned())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ConsumedMessage {
+            message,
+            headers,
+            topic: msg.topic().to_owned(),
+            partition: msg.partition(),
+            offset: msg.offset(),
+            timestamp: msg.timestamp().to_millis(),
+        })
     }
 
     pub fn stream<T: DeserializeOwned + 'static>(&self) -> impl Stream<Item = KafkaResult<T>> + '_ {
@@ -71,8 +496,245 @@ impl KafkaConsumer {
         })
     }
 
+    /// Owning variant of [`Self::stream`] for consumers moved into a background task.
+    pub fn into_stream<T: DeserializeOwned + 'static>(self) -> impl Stream<Item = KafkaResult<T>> {
+        futures::stream::unfold(self, |consumer| async move {
+            let result = consumer.consume::<T>().await;
+            Some((result, consumer))
+        })
+    }
+
+    /// Feeds every message to `handler` until `shutdown` is cancelled, acking the ones it handles successfully.
+    ///
+    /// A failing handler is retried with exponential backoff per the configured [`RetryPolicy`]. Once the
+    /// attempts are used up the message is forwarded to the dead letter topic, or, without one, skipped
+    /// with an error log and counted in [`Self::skipped_total`]; only then is its offset committed, so a
+    /// poison message cannot stall its partition. Undecodable messages take the same path without being
+    /// retried. A dead letter send that fails is retried with the same backoff until it goes through, as
+    /// committing later offsets would otherwise lose the message. A message in flight when the token is
+    /// cancelled is finished before the loop exits unless it is waiting out a backoff, in which case it
+    /// stays unacked and is redelivered. Stored offsets are committed on the way out.
+    pub async fn run_with_handler<T, F, Fut, E>(&self, mut handler: F, shutdown: CancellationToken) -> KafkaResult<()>
+    where
+        T: DeserializeOwned + Clone,
+        F: FnMut(ConsumedMessage<T>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        loop {
+            let received = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                received = self.recv() => received,
+            };
+            // Detached so the original bytes are still at hand for dead-lettering after the handler ran.
+            let msg = match received {
+                Ok(msg) => msg.detach(),
+                Err(e) => {
+                    tracing::error!(topics = ?self.topics, "Failed to consume message: {e}");
+                    continue;
+                }
+            };
+            if !self.process(msg, &mut handler, &shutdown).await? {
+                break;
+            }
+        }
+
+        self.commit()?;
+        tracing::info!(topics = ?self.topics, "Kafka consumer loop stopped");
+        Ok(())
+    }
+
+    /// Runs `handler` on one received message per the retry policy, then acks it or gives up on it. Returns
+    /// `false` when shutdown interrupted a backoff and the caller's loop should stop.
+    pub(crate) async fn process<T, F, Fut, E>(
+        &self,
+        msg: OwnedMessage,
+        handler: &mut F,
+        shutdown: &CancellationToken,
+    ) -> KafkaResult<bool>
+    where
+        T: DeserializeOwned + Clone,
+        F: FnMut(ConsumedMessage<T>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let message = match self.decode::<T>(&msg) {
+            Ok(message) => message,
+            // Decoding the same bytes again cannot succeed, so there is nothing to retry.
+            Err(e) => return self.abandon(&msg, 0, &e, shutdown).await,
+        };
+
+        match handle_with_retries(handler, &message, &self.retry, shutdown).await {
+            RetryOutcome::Handled { .. } => self.ack_for(&msg).ack()?,
+            RetryOutcome::Exhausted { attempts, error } => {
+                let err = KafkaError::Handler {
+                    topic: msg.topic().to_owned(),
+                    message: error,
+                };
+                return self.abandon(&msg, attempts, &err, shutdown).await;
+            }
+            RetryOutcome::Interrupted { attempts } => {
+                tracing::info!(
+                    topic = %msg.topic(),
+                    partition = msg.partition(),
+                    offset = msg.offset(),
+                    attempts,
+                    "Shutdown during handler backoff, leaving message unacked"
+                );
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Dead-letters or skips a message that will not be handled, then acks it. Returns `false` when shutdown
+    /// interrupted a failing dead letter send and the message stays unacked.
+    async fn abandon(
+        &self,
+        msg: &OwnedMessage,
+        attempts: u32,
+        err: &KafkaError,
+        shutdown: &CancellationToken,
+    ) -> KafkaResult<bool> {
+        // Acks of later messages on the partition would commit past this one, so it is not left
+        // behind unacked: dead-lettering is retried until it succeeds or shutdown is requested.
+        let mut failures = 0;
+        loop {
+            let Err(e) = self.give_up(msg, attempts, err).await else {
+                self.ack_for(msg).ack()?;
+                return Ok(true);
+            };
+            failures += 1;
+            tracing::error!(
+                topic = %msg.topic(),
+                partition = msg.partition(),
+                offset = msg.offset(),
+                failures,
+                "Failed to dead-letter message, retrying: {e}"
+            );
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    tracing::info!(
+                        topic = %msg.topic(),
+                        partition = msg.partition(),
+                        offset = msg.offset(),
+                        "Shutdown while dead-lettering, leaving message unacked"
+                    );
+                    return Ok(false);
+                }
+                _ = tokio::time::sleep(self.retry.backoff(failures)) => {}
+            }
+        }
+    }
+
+    /// Terminal decision for a message that could not be decoded or whose handler attempts are exhausted.
+    async fn give_up(&self, msg: &OwnedMessage, attempts: u32, err: &KafkaError) -> KafkaResult<()> {
+        if let Some(dead_letter) = &self.dead_letter {
+            dead_letter
+                .send_raw(msg.key(), msg.payload().unwrap_or_default(), dead_letter_headers(msg, err))
+                .await?;
+            let total = self.dead_lettered.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::warn!(
+                topic = %msg.topic(),
+                partition = msg.partition(),
+                offset = msg.offset(),
+                attempts,
+                total,
+                "Message dead-lettered: {err}"
+            );
+        } else {
+            let total = self.skipped_total.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::error!(
+                topic = %msg.topic(),
+                partition = msg.partition(),
+                offset = msg.offset(),
+                attempts,
+                total,
+                "Message skipped: {err}"
+            );
+        }
+        Ok(())
+    }
+
     pub async fn close(self) {
         self.consumer.unsubscribe();
-        tracing::info!(topic = %self.input_topic, "Kafka consumer closed");
+        tracing::info!(topics = ?self.topics, "Kafka consumer closed");
     }
 }
+
+/// Assigns the partitions of a manual consumer, resolving timestamp start offsets first.
+fn assign_manual(consumer: &StreamConsumer<ConsumerState>, topic: &str, partitions: &[(i32, StartOffset)]) -> KafkaResult<()> {
+    let mut by_time = TopicPartitionList::new();
+    for (partition, start) in partitions {
+        if let StartOffset::Timestamp(ts) = start {
+            by_time.add_partition_offset(topic, *partition, Offset::Offset(ts.timestamp_millis()))?;
+        }
+    }
+    let resolved = if by_time.count() > 0 {
+        consumer.offsets_for_times(by_time, SEEK_TIMEOUT)?
+    } else {
+        by_time
+    };
+
+    let mut assignment = TopicPartitionList::with_capacity(partitions.len());
+    for (partition, start) in partitions {
+        let offset = match start {
+            StartOffset::Beginning => Offset::Beginning,
+            StartOffset::End => Offset::End,
+            StartOffset::Absolute(offset) => Offset::Offset(*offset),
+            StartOffset::Timestamp(_) => match resolved.find_partition(topic, *partition) {
+                Some(found) => {
+                    found.error()?;
+                    found.offset()
+                }
+                None => Offset::End,
+            },
+        };
+        assignment.add_partition_offset(topic, *partition, offset)?;
+    }
+    consumer.assign(&assignment)?;
+
+    consumer
+        .context()
+        .assigned
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .extend(partitions.iter().map(|(partition, _)| (topic.to_owned(), *partition)));
+    Ok(())
+}
+
+fn payload<M: Message>(msg: &M) -> KafkaResult<&[u8]> {
+    msg.payload().ok_or_else(|| KafkaError::EmptyPayload {
+        topic: msg.topic().to_owned(),
+    })
+}
+
+fn deserialize<T: DeserializeOwned>(topic: &str, payload: &[u8]) -> KafkaResult<T> {
+    serde_json::from_slice(payload).map_err(|source| KafkaError::Deserialization {
+        topic: topic.to_owned(),
+        payload_len: payload.len(),
+        source,
+    })
+}
+
+/// Original headers plus where the message came from and why it could not be consumed.
+fn dead_letter_headers(msg: &impl Message, err: &KafkaError) -> OwnedHeaders {
+    let metadata = [
+        ("dlq.error", err.to_string()),
+        ("dlq.source_topic", msg.topic().to_owned()),
+        ("dlq.source_partition", msg.partition().to_string()),
+        ("dlq.source_offset", msg.offset().to_string()),
+    ];
+
+    let headers = msg
+        .headers()
+        .map(|h| h.iter().fold(OwnedHeaders::new(), |acc, header| acc.insert(header)))
+        .unwrap_or_default();
+    metadata.iter().fold(headers, |acc, (key, value)| {
+        acc.insert(Header {
+            key,
+            value: Some(value.as_bytes()),
+        })
+    })
+}
@@ -10,10 +10,51 @@ pub enum KafkaError {
     Kafka(#[from] rdkafka::error::KafkaError),
     #[error("Message serialization failed: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Failed to deserialize {payload_len}-byte payload from topic {topic}: {source}")]
+    Deserialization {
+        topic: String,
+        payload_len: usize,
+        #[source]
+        source: serde_json::Error,
+    },
     #[error("Message was canceled or channel closed")]
     CanceledMessage(#[from] futures::channel::oneshot::Canceled),
     #[error("Empty message payload received from topic: {topic}")]
     EmptyPayload { topic: String },
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
+    #[error("Topic not found: {0}")]
+    TopicNotFound(String),
+    #[error("Topic {topic} already exists with {existing} partitions, requested {requested}")]
+    PartitionMismatch { topic: String, existing: i32, requested: i32 },
+    #[error("Topic {topic} retention is below the configured minimum: {problems}")]
+    InsufficientRetention { topic: String, problems: String },
+    #[error("Consumer group not found: {0}")]
+    GroupNotFound(String),
+    #[error("No handler routed for topic: {0}")]
+    NoRoute(String),
+    #[error("Handler for topic {topic} failed: {message}")]
+    Handler { topic: String, message: String },
+    #[error("Partition {topic}/{partition} is not assigned to this consumer")]
+    NotAssigned { topic: String, partition: i32 },
+    #[error("Consumer has no assigned partitions yet")]
+    NoAssignment,
+    #[error("Producer was fenced by a newer instance with the same transactional.id: {0}")]
+    ProducerFenced(String),
+    #[error("Transaction must be aborted: {0}")]
+    TransactionAborted(String),
+    #[error("Producer is not transactional; set a transactional.id")]
+    NotTransactional,
+    #[error("No reply received within {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Checkpoint {id} could not be decoded: {source}")]
+    CorruptCheckpoint {
+        id: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Checkpoint store failed: {0}")]
+    CheckpointStore(String),
+    #[error("Admin task failed: {0}")]
+    TaskJoin(#[from] tokio::task::JoinError),
 }
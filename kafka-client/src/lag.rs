@@ -0,0 +1,97 @@
+use crate::error::{KafkaError, KafkaResult};
+use rdkafka::{
+    ClientConfig, Offset, TopicPartitionList,
+    consumer::{BaseConsumer, Consumer},
+};
+use std::{sync::Arc, time::Duration};
+
+const LAG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Measures how far a consumer group's committed offsets trail a topic's high watermarks.
+///
+/// The probe joins no group and commits nothing; it only reads the group's committed offsets.
+pub struct LagProbe {
+    consumer: Arc<BaseConsumer>,
+    group_id: String,
+    topic: String,
+}
+
+impl LagProbe {
+    pub fn new(brokers: &str, group_id: &str, topic: &str) -> KafkaResult<Self> {
+        if brokers.is_empty() {
+            return Err(KafkaError::InvalidConfig("Brokers cannot be empty".into()));
+        }
+        if group_id.is_empty() {
+            return Err(KafkaError::InvalidConfig("Group ID cannot be empty".into()));
+        }
+
+        let consumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group_id)
+            .set("enable.auto.commit", "false")
+            .create::<BaseConsumer>()?;
+
+        Ok(Self {
+            consumer: Arc::new(consumer),
+            group_id: group_id.to_owned(),
+            topic: topic.to_owned(),
+        })
+    }
+
+    pub fn group_id(&self) -> &str {
+        &self.group_id
+    }
+
+    /// Sum of per-partition lag; partitions the group never committed count from the low watermark.
+    pub async fn total_lag(&self) -> KafkaResult<i64> {
+        let consumer = Arc::clone(&self.consumer);
+        let topic = self.topic.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let metadata = consumer.fetch_metadata(None, LAG_TIMEOUT)?;
+            let found = metadata
+                .topics()
+                .iter()
+                .find(|t| t.name() == topic && t.error().is_none())
+                .ok_or_else(|| KafkaError::TopicNotFound(topic.clone()))?;
+
+            let mut partitions = TopicPartitionList::new();
+            for partition in found.partitions() {
+                partitions.add_partition(&topic, partition.id());
+            }
+
+            let committed = consumer.committed_offsets(partitions, LAG_TIMEOUT)?;
+            let mut total = 0;
+            for elem in committed.elements() {
+                let (low, high) = consumer.fetch_watermarks(&topic, elem.partition(), LAG_TIMEOUT)?;
+                total += partition_lag(elem.offset(), low, high);
+            }
+            Ok(total)
+        })
+        .await?
+    }
+}
+
+pub(crate) fn partition_lag(committed: Offset, low: i64, high: i64) -> i64 {
+    match committed {
+        Offset::Offset(offset) => (high - offset).max(0),
+        _ => (high - low).max(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lag_from_committed_offset() {
+        assert_eq!(partition_lag(Offset::Offset(40), 0, 100), 60);
+        assert_eq!(partition_lag(Offset::Offset(100), 0, 100), 0);
+    }
+
+    #[test]
+    fn uncommitted_partition_counts_retained_messages() {
+        assert_eq!(partition_lag(Offset::Invalid, 20, 100), 80);
+        assert_eq!(partition_lag(Offset::Invalid, 0, 0), 0);
+    }
+}
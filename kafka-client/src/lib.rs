@@ -1,5 +1,13 @@
+pub mod admin;
+pub mod checkpoint;
 pub mod config;
 pub mod consumer;
 pub mod error;
+pub mod lag;
+pub mod priority;
 pub mod producer;
+pub mod retry;
+pub mod router;
+pub mod rpc;
 pub mod schemas;
+pub mod stats;
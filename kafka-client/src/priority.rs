@@ -0,0 +1,222 @@
+use crate::{
+    config::{AssignmentMode, ConsumerConfig},
+    consumer::{ConsumedMessage, KafkaConsumer},
+    error::{KafkaError, KafkaResult},
+};
+use rdkafka::message::BorrowedMessage;
+use serde::de::DeserializeOwned;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio_util::sync::CancellationToken;
+
+/// How [`PriorityConsumer`] shares processing slots between its levels.
+#[derive(Debug, Clone, Copy)]
+pub struct PriorityPolicy {
+    /// Share of slots, in percent, that go to the lower levels whenever they have messages waiting, however
+    /// busy the higher ones are. 0 disables the guard; at most 50.
+    pub min_low_share_percent: u8,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        Self {
+            min_low_share_percent: 10,
+        }
+    }
+}
+
+/// Counters of one level of a [`PriorityConsumer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorityLevelStats {
+    pub topic: String,
+    /// Level 0 is the highest priority.
+    pub level: usize,
+    pub consumed: u64,
+    /// Messages this level got through the starvation guard rather than by priority.
+    pub guarded: u64,
+}
+
+struct Level {
+    consumer: KafkaConsumer,
+    consumed: AtomicU64,
+    guarded: AtomicU64,
+}
+
+/// Consumes several topics by priority, e.g. deletions ahead of a backfill of creations.
+///
+/// Each topic gets its own consumer in the same group. For every slot the levels are polled without
+/// waiting from the highest down, and the first buffered message is handled; only when none has one does
+/// it wait on all of them at once. Every `100 / min_low_share_percent`-th slot polls the lower levels first,
+/// taking turns between them, so a flooded high level cannot starve the rest.
+pub struct PriorityConsumer {
+    levels: Vec<Level>,
+    /// A guard slot every this many slots; 0 without a guard.
+    guard_every: u64,
+    slots: AtomicU64,
+}
+
+impl PriorityConsumer {
+    /// `config.topics` lists the topics from highest to lowest priority; everything else applies to all levels.
+    pub fn new(config: ConsumerConfig, policy: PriorityPolicy) -> KafkaResult<Self> {
+        if matches!(config.assignment, AssignmentMode::Manual(_)) {
+            return Err(KafkaError::InvalidConfig(
+                "Priority consumption needs group assignment".into(),
+            ));
+        }
+        if policy.min_low_share_percent > 50 {
+            return Err(KafkaError::InvalidConfig(format!(
+                "min_low_share_percent must be at most 50, got {}",
+                policy.min_low_share_percent
+            )));
+        }
+
+        let levels = config
+            .topics
+            .iter()
+            .map(|topic| {
+                let consumer = KafkaConsumer::new(ConsumerConfig {
+                    topics: vec![topic.clone()],
+                    ..config.clone()
+                })?;
+                Ok(Level {
+                    consumer,
+                    consumed: AtomicU64::new(0),
+                    guarded: AtomicU64::new(0),
+                })
+            })
+            .collect::<KafkaResult<Vec<_>>>()?;
+
+        let guard_every = match policy.min_low_share_percent {
+            0 => 0,
+            percent => 100u64.div_ceil(percent.into()),
+        };
+        Ok(Self {
+            levels,
+            guard_every,
+            slots: AtomicU64::new(0),
+        })
+    }
+
+    /// Feeds messages to `handler` by priority until `shutdown` is cancelled, with the retry, dead letter and
+    /// ack semantics of [`KafkaConsumer::run_with_handler`]. Route them by topic with a
+    /// [`TopicRouter`](crate::router::TopicRouter).
+    pub async fn run_with_handler<T, F, Fut, E>(&self, mut handler: F, shutdown: CancellationToken) -> KafkaResult<()>
+    where
+        T: DeserializeOwned + Clone,
+        F: FnMut(ConsumedMessage<T>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        loop {
+            let (level, received) = tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                next = self.next() => next,
+            };
+            let level = &self.levels[level];
+            let msg = match received {
+                Ok(msg) => msg.detach(),
+                Err(e) => {
+                    tracing::error!(topics = ?level.consumer.topics, "Failed to consume message: {e}");
+                    continue;
+                }
+            };
+            level.consumed.fetch_add(1, Ordering::Relaxed);
+            if !level.consumer.process(msg, &mut handler, &shutdown).await? {
+                break;
+            }
+        }
+
+        for level in &self.levels {
+            level.consumer.commit()?;
+        }
+        tracing::info!(topics = ?self.topics(), "Kafka priority consumer loop stopped");
+        Ok(())
+    }
+
+    /// Next message and the level it came from.
+    async fn next(&self) -> (usize, KafkaResult<BorrowedMessage<'_>>) {
+        let slot = self.slots.fetch_add(1, Ordering::Relaxed);
+        let guard = self.is_guard_slot(slot);
+        for index in scan_order(self.levels.len(), guard.then(|| slot / self.guard_every)) {
+            let level = &self.levels[index];
+            if let Some(received) = level.consumer.try_recv() {
+                if guard && index > 0 {
+                    level.guarded.fetch_add(1, Ordering::Relaxed);
+                }
+                return (index, received);
+            }
+        }
+
+        let waits = self.levels.iter().map(|level| Box::pin(level.consumer.recv()));
+        let (received, index, _) = futures::future::select_all(waits).await;
+        (index, received)
+    }
+
+    fn is_guard_slot(&self, slot: u64) -> bool {
+        self.guard_every > 0 && self.levels.len() > 1 && slot % self.guard_every == self.guard_every - 1
+    }
+
+    /// Topics from highest to lowest priority.
+    pub fn topics(&self) -> Vec<&str> {
+        self.levels.iter().map(|level| level.consumer.topics[0].as_str()).collect()
+    }
+
+    pub fn stats(&self) -> Vec<PriorityLevelStats> {
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(index, level)| PriorityLevelStats {
+                topic: level.consumer.topics[0].clone(),
+                level: index,
+                consumed: level.consumed.load(Ordering::Relaxed),
+                guarded: level.guarded.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Consumer of one level, e.g. for its lag or librdkafka statistics.
+    pub fn level(&self, index: usize) -> Option<&KafkaConsumer> {
+        self.levels.get(index).map(|level| &level.consumer)
+    }
+
+    pub async fn close(self) {
+        for level in self.levels {
+            level.consumer.close().await;
+        }
+    }
+}
+
+/// Order in which the levels are polled: highest first, or on the `round`-th guard slot the lower levels
+/// first, starting with a different one each round.
+fn scan_order(levels: usize, guard_round: Option<u64>) -> Vec<usize> {
+    match guard_round {
+        Some(round) if levels > 1 => {
+            let lower = levels - 1;
+            let start = (round % lower as u64) as usize;
+            (0..lower).map(|k| 1 + (start + k) % lower).chain([0]).collect()
+        }
+        _ => (0..levels).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regular_slots_scan_from_the_highest_level() {
+        assert_eq!(scan_order(3, None), [0, 1, 2]);
+        assert_eq!(scan_order(1, Some(4)), [0]);
+    }
+
+    #[test]
+    fn guard_slots_rotate_through_the_lower_levels() {
+        assert_eq!(scan_order(2, Some(0)), [1, 0]);
+        assert_eq!(scan_order(2, Some(1)), [1, 0]);
+        assert_eq!(scan_order(3, Some(0)), [1, 2, 0]);
+        assert_eq!(scan_order(3, Some(1)), [2, 1, 0]);
+    }
+}
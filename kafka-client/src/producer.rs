@@ -1,57 +1,404 @@
 use crate::{
     config::ProducerConfig,
     error::{KafkaError, KafkaResult},
+    schemas::KafkaMessage,
+    stats::{ProducerStats, StatsContext, StatsHandle},
 };
 use rdkafka::{
-    ClientConfig,
-    producer::{FutureProducer, FutureRecord, Producer},
+    error::RDKafkaErrorCode,
+    message::{Header, OwnedHeaders},
+    producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer},
 };
 use serde::Serialize;
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// How long to back off when librdkafka's local queue is full during a batch.
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Where [`KafkaProducer::send`] places a message. String keys convert into [`Partitioning::Key`],
+/// so passing a key keeps the usual per-key ordering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Partitioning {
+    /// Hash the key onto a partition, see [`partition_for`].
+    Key(String),
+    /// Unkeyed; librdkafka spreads these across partitions.
+    None,
+    /// Write to this partition without a key.
+    Explicit(i32),
+}
+
+impl From<&str> for Partitioning {
+    fn from(key: &str) -> Self {
+        Self::Key(key.to_owned())
+    }
+}
+
+impl From<&String> for Partitioning {
+    fn from(key: &String) -> Self {
+        Self::Key(key.clone())
+    }
+}
+
+impl From<String> for Partitioning {
+    fn from(key: String) -> Self {
+        Self::Key(key)
+    }
+}
+
+/// The partition librdkafka's default `consistent_random` partitioner picks for a keyed message:
+/// CRC-32 of the key modulo the partition count, which must be positive.
+pub fn partition_for(key: &str, partition_count: i32) -> KafkaResult<i32> {
+    if partition_count <= 0 {
+        return Err(KafkaError::InvalidConfig(format!(
+            "partition count must be positive, got {partition_count}"
+        )));
+    }
+    Ok((crc32(key.as_bytes()) % partition_count as u32) as i32)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
 
 pub struct KafkaProducer {
-    producer: FutureProducer,
+    producer: FutureProducer<StatsContext<ProducerStats>>,
     topic: String,
+    stats: StatsHandle<ProducerStats>,
+    transactional: bool,
+    transaction_timeout: Duration,
+    /// Cleared when a delivery or health check fails to reach the brokers, set again by the next success.
+    healthy: AtomicBool,
+}
+
+/// Sends into the transaction opened by [`KafkaProducer::with_transaction`].
+#[derive(Clone, Copy)]
+pub struct Transaction<'a> {
+    producer: &'a KafkaProducer,
+}
+
+impl Transaction<'_> {
+    pub async fn send<T: Serialize>(&self, partitioning: impl Into<Partitioning>, payload: &T) -> KafkaResult<(i32, i64)> {
+        self.producer.send(partitioning, payload).await
+    }
+
+    pub async fn send_with_headers<T: Serialize>(
+        &self,
+        partitioning: impl Into<Partitioning>,
+        payload: &T,
+        headers: &HashMap<String, String>,
+    ) -> KafkaResult<(i32, i64)> {
+        self.producer.send_with_headers(partitioning, payload, headers).await
+    }
+}
+
+/// Errors meaning the brokers could not be reached, as opposed to a problem with one message.
+fn is_connectivity_error(err: &rdkafka::error::KafkaError) -> bool {
+    matches!(
+        err.rdkafka_error_code(),
+        Some(
+            RDKafkaErrorCode::MessageTimedOut
+                | RDKafkaErrorCode::AllBrokersDown
+                | RDKafkaErrorCode::BrokerTransportFailure
+                | RDKafkaErrorCode::NetworkException
+                | RDKafkaErrorCode::RequestTimedOut
+                | RDKafkaErrorCode::OperationTimedOut
+                | RDKafkaErrorCode::BrokerNotAvailable
+        )
+    )
+}
+
+/// Fatal transactional errors mean another producer took over the transactional.id;
+/// abortable ones leave the producer usable once the transaction is aborted.
+fn transaction_error(err: rdkafka::error::KafkaError) -> KafkaError {
+    match &err {
+        rdkafka::error::KafkaError::Transaction(e) if e.is_fatal() => KafkaError::ProducerFenced(e.to_string()),
+        rdkafka::error::KafkaError::Transaction(e) if e.txn_requires_abort() => KafkaError::TransactionAborted(e.to_string()),
+        _ => KafkaError::Kafka(err),
+    }
 }
 
 impl KafkaProducer {
     pub fn new(config: ProducerConfig) -> KafkaResult<Self> {
-        let producer = ClientConfig::new()
-            .set("bootstrap.servers", &config.brokers)
-            .set("message.timeout.ms", config.message_timeout_ms.to_string())
-            .set("allow.auto.create.topics", config.auto_create_topics.to_string())
-            .set("retries", config.retries.to_string())
-            .create::<FutureProducer>()?;
+        let context = StatsContext::new();
+        let stats = context.handle();
+        let producer = config.client_config().create_with_context(context)?;
 
         tracing::info!(
             brokers = %config.brokers,
             topic = %config.topic,
             retries = config.retries,
+            transactional_id = config.transactional_id.as_deref(),
             "Kafka producer started"
         );
 
         Ok(Self {
             producer,
             topic: config.topic,
+            stats,
+            transactional: config.transactional_id.is_some(),
+            transaction_timeout: Duration::from_millis(config.transaction_timeout_ms.into()),
+            healthy: AtomicBool::new(true),
         })
     }
 
-    pub async fn send<T: Serialize>(&self, key: &str, payload: &T) -> KafkaResult<()> {
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Latest librdkafka statistics; `None` until the first report, or when statistics are disabled.
+    pub fn stats(&self) -> Option<ProducerStats> {
+        self.stats.get()
+    }
+
+    /// Whether the brokers were reachable at the last delivery or [`check_health`](Self::check_health).
+    /// Starts out healthy; callers on a latency-sensitive path can skip sending while this is `false`
+    /// instead of waiting out `message.timeout.ms` on every message.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Acquire)
+    }
+
+    /// Fetches cluster metadata within `timeout` and records whether it succeeded; meant to be polled so an
+    /// unhealthy producer notices when the brokers come back.
+    pub async fn check_health(&self, timeout: Duration) -> bool {
+        let producer = self.producer.clone();
+        // Fetching all topics avoids auto-creating the producer's topic just to probe the cluster.
+        let result = tokio::task::spawn_blocking(move || producer.client().fetch_metadata(None, timeout).map(|_| ())).await;
+        let healthy = match result {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                tracing::debug!(topic = %self.topic, "Kafka health check failed: {e}");
+                false
+            }
+            Err(e) => {
+                tracing::error!(topic = %self.topic, "Kafka health check task failed: {e}");
+                false
+            }
+        };
+        self.set_healthy(healthy);
+        healthy
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        if self.healthy.swap(healthy, Ordering::AcqRel) != healthy {
+            if healthy {
+                tracing::info!(topic = %self.topic, "Kafka brokers are reachable again");
+            } else {
+                tracing::warn!(topic = %self.topic, "Kafka brokers are unreachable, marking producer unhealthy");
+            }
+        }
+    }
+
+    fn record_delivery<T>(&self, result: &KafkaResult<T>) {
+        match result {
+            Ok(_) => self.set_healthy(true),
+            Err(KafkaError::Kafka(e)) if is_connectivity_error(e) => self.set_healthy(false),
+            Err(_) => {}
+        }
+    }
+
+    /// Returns the `(partition, offset)` the message was written to.
+    pub async fn send<T: Serialize>(&self, partitioning: impl Into<Partitioning>, payload: &T) -> KafkaResult<(i32, i64)> {
+        self.send_with_headers(partitioning, payload, &HashMap::new()).await
+    }
+
+    pub async fn send_with_headers<T: Serialize>(
+        &self,
+        partitioning: impl Into<Partitioning>,
+        payload: &T,
+        headers: &HashMap<String, String>,
+    ) -> KafkaResult<(i32, i64)> {
+        let partitioning = partitioning.into();
         let bytes = serde_json::to_vec(payload)?;
-        tracing::debug!(topic = %self.topic, key = %key, "Sending message");
+        tracing::debug!(topic = %self.topic, ?partitioning, headers = headers.len(), "Sending message");
 
-        let record = FutureRecord::to(&self.topic).payload(&bytes).key(key);
-        let delivery_future = self.producer.send_result(record).map_err(|(err, _)| KafkaError::Kafka(err))?;
+        let owned_headers = headers
+            .iter()
+            .fold(OwnedHeaders::new_with_capacity(headers.len()), |acc, (k, v)| {
+                acc.insert(Header {
+                    key: k,
+                    value: Some(v.as_bytes()),
+                })
+            });
+        let record = FutureRecord::<str, _>::to(&self.topic).payload(&bytes).headers(owned_headers);
+        let record = match &partitioning {
+            Partitioning::Key(key) => record.key(key.as_str()),
+            Partitioning::None => record,
+            Partitioning::Explicit(partition) => record.partition(*partition),
+        };
+        let (partition, offset) = self.deliver(record).await?;
+
+        tracing::info!(topic = %self.topic, ?partitioning, partition, offset, "Message sent successfully");
+        Ok((partition, offset))
+    }
 
-        delivery_future
+    /// Forwards bytes as-is, for payloads that must not be re-encoded (e.g. dead letters).
+    pub(crate) async fn send_raw(&self, key: Option<&[u8]>, payload: &[u8], headers: OwnedHeaders) -> KafkaResult<(i32, i64)> {
+        let mut record = FutureRecord::<[u8], [u8]>::to(&self.topic).payload(payload).headers(headers);
+        if let Some(key) = key {
+            record = record.key(key);
+        }
+        self.deliver(record).await
+    }
+
+    async fn deliver<K, P>(&self, record: FutureRecord<'_, K, P>) -> KafkaResult<(i32, i64)>
+    where
+        K: rdkafka::message::ToBytes + ?Sized,
+        P: rdkafka::message::ToBytes + ?Sized,
+    {
+        let delivery_future = self.producer.send_result(record).map_err(|(err, _)| KafkaError::Kafka(err))?;
+        let delivery = delivery_future
             .await
             .map_err(KafkaError::CanceledMessage)?
-            .map_err(|(err, _)| KafkaError::Kafka(err))?;
+            .map(|delivery| (delivery.partition, delivery.offset))
+            .map_err(|(err, _)| KafkaError::Kafka(err));
+        self.record_delivery(&delivery);
+        delivery
+    }
+
+    /// Enqueues every message before awaiting any delivery, keyed by `user_id`.
+    /// Returns `(partition, offset)` or the error for each message, in input order;
+    /// a failed message does not stop the rest of the batch.
+    pub async fn send_batch<T: Serialize>(
+        &self,
+        messages: &[KafkaMessage<T>],
+    ) -> KafkaResult<Vec<Result<(i32, i64), KafkaError>>> {
+        tracing::debug!(topic = %self.topic, count = messages.len(), "Sending batch");
+
+        let mut pending = Vec::with_capacity(messages.len());
+        for message in messages {
+            pending.push(match serde_json::to_vec(message) {
+                Ok(bytes) => self.enqueue(&message.user_id, &bytes).await,
+                Err(e) => Err(KafkaError::Serialization(e)),
+            });
+        }
+
+        let results: Vec<_> = futures::future::join_all(pending.into_iter().map(|enqueued| async move {
+            let delivery = enqueued?
+                .await
+                .map_err(KafkaError::CanceledMessage)?
+                .map_err(|(err, _)| KafkaError::Kafka(err))?;
+            Ok((delivery.partition, delivery.offset))
+        }))
+        .await;
+        // One delivered message proves the brokers are reachable, whatever happened to the others.
+        if let Some(outcome) = results.iter().find(|r| r.is_ok()).or_else(|| results.first()) {
+            self.record_delivery(outcome);
+        }
+
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        tracing::info!(topic = %self.topic, count = results.len(), failed, "Batch sent");
+        Ok(results)
+    }
+
+    async fn enqueue(&self, key: &str, bytes: &[u8]) -> KafkaResult<DeliveryFuture> {
+        let mut record = FutureRecord::to(&self.topic).payload(bytes).key(key);
+        loop {
+            match self.producer.send_result(record) {
+                Ok(delivery) => return Ok(delivery),
+                Err((rdkafka::error::KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    record = returned;
+                    tokio::time::sleep(QUEUE_FULL_BACKOFF).await;
+                }
+                Err((err, _)) => return Err(KafkaError::Kafka(err)),
+            }
+        }
+    }
+
+    /// Registers the transactional.id with the coordinator and fences older producers using it.
+    /// Must complete once before the first [`begin_transaction`](Self::begin_transaction).
+    pub async fn init_transactions(&self, timeout: Duration) -> KafkaResult<()> {
+        self.ensure_transactional()?;
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.init_transactions(timeout))
+            .await?
+            .map_err(transaction_error)?;
+        tracing::info!(topic = %self.topic, "Kafka transactions initialized");
+        Ok(())
+    }
+
+    pub fn begin_transaction(&self) -> KafkaResult<()> {
+        self.ensure_transactional()?;
+        self.producer.begin_transaction().map_err(transaction_error)?;
+        tracing::debug!(topic = %self.topic, "Transaction started");
+        Ok(())
+    }
+
+    /// Like [`send`](Self::send), but refuses to run on a non-transactional producer. Delivery only
+    /// means the message was written; `read_committed` consumers see it after the commit.
+    pub async fn send_in_transaction<T: Serialize>(
+        &self,
+        partitioning: impl Into<Partitioning>,
+        payload: &T,
+    ) -> KafkaResult<(i32, i64)> {
+        self.ensure_transactional()?;
+        self.send(partitioning, payload).await
+    }
 
-        tracing::info!(topic = %self.topic, key = %key, "Message sent successfully");
+    /// Flushes outstanding messages and commits them. On [`KafkaError::TransactionAborted`] the caller
+    /// must [`abort_transaction`](Self::abort_transaction) before starting another one.
+    pub async fn commit_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        self.ensure_transactional()?;
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.commit_transaction(timeout))
+            .await?
+            .map_err(transaction_error)?;
+        tracing::debug!(topic = %self.topic, "Transaction committed");
         Ok(())
     }
 
+    pub async fn abort_transaction(&self, timeout: Duration) -> KafkaResult<()> {
+        self.ensure_transactional()?;
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.abort_transaction(timeout))
+            .await?
+            .map_err(transaction_error)?;
+        tracing::debug!(topic = %self.topic, "Transaction aborted");
+        Ok(())
+    }
+
+    /// Runs `f` inside a transaction: commits when it returns `Ok`, aborts when it (or the commit) fails.
+    /// Commit and abort wait up to the configured `transaction_timeout_ms`.
+    pub async fn with_transaction<'a, F, Fut, R>(&'a self, f: F) -> KafkaResult<R>
+    where
+        F: FnOnce(Transaction<'a>) -> Fut,
+        Fut: Future<Output = KafkaResult<R>>,
+    {
+        self.begin_transaction()?;
+        let result = match f(Transaction { producer: self }).await {
+            Ok(value) => self.commit_transaction(self.transaction_timeout).await.map(|()| value),
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e @ KafkaError::ProducerFenced(_)) => Err(e),
+            Err(e) => {
+                if let Err(abort) = self.abort_transaction(self.transaction_timeout).await {
+                    tracing::error!(topic = %self.topic, error = %e, "Failed to abort transaction: {abort}");
+                }
+                Err(e)
+            }
+            ok => ok,
+        }
+    }
+
+    fn ensure_transactional(&self) -> KafkaResult<()> {
+        if self.transactional {
+            Ok(())
+        } else {
+            Err(KafkaError::NotTransactional)
+        }
+    }
+
     pub fn flush(&self, timeout: Duration) -> KafkaResult<()> {
         self.producer.flush(timeout)?;
         tracing::debug!(topic = %self.topic, "Flush producer");
@@ -65,3 +412,40 @@ impl Drop for KafkaProducer {
         tracing::info!(topic = %self.topic, "Kafka producer closed");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_unreachable_brokers_count_as_unhealthy() {
+        let production = |code| rdkafka::error::KafkaError::MessageProduction(code);
+        assert!(is_connectivity_error(&production(RDKafkaErrorCode::MessageTimedOut)));
+        assert!(is_connectivity_error(&production(RDKafkaErrorCode::AllBrokersDown)));
+        assert!(!is_connectivity_error(&production(RDKafkaErrorCode::MessageSizeTooLarge)));
+        assert!(!is_connectivity_error(&production(RDKafkaErrorCode::UnknownTopicOrPartition)));
+    }
+
+    #[test]
+    fn crc32_matches_reference_vector() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn partition_for_is_stable_and_in_range() {
+        for count in [1, 3, 12] {
+            let partition = partition_for("user_42", count).unwrap();
+            assert!((0..count).contains(&partition));
+            assert_eq!(partition, partition_for("user_42", count).unwrap());
+        }
+        assert_eq!(partition_for("123456789", 7).unwrap(), (0xCBF4_3926u32 % 7) as i32);
+    }
+
+    #[test]
+    fn partition_for_refuses_no_partitions() {
+        for count in [0, -1] {
+            assert!(matches!(partition_for("user_42", count), Err(KafkaError::InvalidConfig(_))));
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::consumer::ConsumedMessage;
+use std::{fmt::Display, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// How often [`KafkaConsumer::run_with_handler`](crate::consumer::KafkaConsumer::run_with_handler) retries a
+/// failing handler before giving up on a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Handler attempts per message, including the first one.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after the `attempt`-th failure (1-based): doubles each time, capped at `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RetryOutcome {
+    Handled {
+        attempts: u32,
+    },
+    /// Every attempt failed; carries the last handler error.
+    Exhausted {
+        attempts: u32,
+        error: String,
+    },
+    /// Shutdown was requested while backing off; no terminal decision was made.
+    Interrupted {
+        attempts: u32,
+    },
+}
+
+pub(crate) async fn handle_with_retries<T, F, Fut, E>(
+    handler: &mut F,
+    message: &ConsumedMessage<T>,
+    policy: &RetryPolicy,
+    shutdown: &CancellationToken,
+) -> RetryOutcome
+where
+    T: Clone,
+    F: FnMut(ConsumedMessage<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    let max_attempts = policy.max_retries.max(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let error = match handler(message.clone()).await {
+            Ok(()) => return RetryOutcome::Handled { attempts: attempt },
+            Err(e) => e.to_string(),
+        };
+        tracing::warn!(
+            topic = %message.topic,
+            partition = message.partition,
+            offset = message.offset,
+            attempt,
+            max_attempts,
+            "Message handler failed: {error}"
+        );
+        if attempt >= max_attempts {
+            return RetryOutcome::Exhausted {
+                attempts: attempt,
+                error,
+            };
+        }
+
+        tokio::select! {
+            _ = shutdown.cancelled() => return RetryOutcome::Interrupted { attempts: attempt },
+            _ = tokio::time::sleep(policy.backoff(attempt)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::Cell,
+        collections::HashMap,
+        future::{Ready, ready},
+        time::Instant,
+    };
+
+    fn message() -> ConsumedMessage<String> {
+        ConsumedMessage {
+            message: "payload".into(),
+            headers: HashMap::new(),
+            topic: "retry-test".into(),
+            partition: 0,
+            offset: 7,
+            timestamp: None,
+        }
+    }
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+        }
+    }
+
+    /// Handler that fails its first `failures` calls, counting every call in `calls`.
+    fn flaky(failures: u32, calls: &Cell<u32>) -> impl FnMut(ConsumedMessage<String>) -> Ready<Result<(), String>> + '_ {
+        move |_| {
+            calls.set(calls.get() + 1);
+            ready(if calls.get() <= failures {
+                Err(format!("failure {}", calls.get()))
+            } else {
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        let delays: Vec<_> = (1..=6).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn recovers_within_budget() {
+        let calls = Cell::new(0);
+        let mut handler = flaky(2, &calls);
+        let outcome = handle_with_retries(&mut handler, &message(), &fast_policy(3), &CancellationToken::new()).await;
+
+        assert_eq!(outcome, RetryOutcome::Handled { attempts: 3 });
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let calls = Cell::new(0);
+        let mut handler = flaky(10, &calls);
+        let outcome = handle_with_retries(&mut handler, &message(), &fast_policy(3), &CancellationToken::new()).await;
+
+        assert_eq!(
+            outcome,
+            RetryOutcome::Exhausted {
+                attempts: 3,
+                error: "failure 3".into()
+            }
+        );
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn zero_retries_still_attempts_once() {
+        let calls = Cell::new(0);
+        let mut handler = flaky(10, &calls);
+        let outcome = handle_with_retries(&mut handler, &message(), &fast_policy(0), &CancellationToken::new()).await;
+
+        assert!(matches!(outcome, RetryOutcome::Exhausted { attempts: 1, .. }));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn shutdown_interrupts_backoff() {
+        let calls = Cell::new(0);
+        let mut handler = flaky(10, &calls);
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(30),
+            max_backoff: Duration::from_secs(30),
+        };
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+
+        let started = Instant::now();
+        let outcome = handle_with_retries(&mut handler, &message(), &policy, &shutdown).await;
+
+        assert_eq!(outcome, RetryOutcome::Interrupted { attempts: 1 });
+        assert_eq!(calls.get(), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    consumer::ConsumedMessage,
+    error::{KafkaError, KafkaResult},
+};
+use futures::future::BoxFuture;
+use std::{collections::HashMap, fmt::Display};
+
+type Handler<T> = Box<dyn Fn(ConsumedMessage<T>) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Dispatches messages from a multi-topic consumer to a handler registered for their source topic.
+///
+/// Meant to be passed to [`KafkaConsumer::run_with_handler`](crate::consumer::KafkaConsumer::run_with_handler)
+/// as `|msg| router.dispatch(msg)`; a handler error leaves the message unacknowledged.
+pub struct TopicRouter<T> {
+    routes: HashMap<String, Handler<T>>,
+}
+
+impl<T: Send + 'static> TopicRouter<T> {
+    pub fn new() -> Self {
+        Self { routes: HashMap::new() }
+    }
+
+    /// Registers `handler` for `topic`, replacing any handler registered before.
+    pub fn route<F, Fut, E>(mut self, topic: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(ConsumedMessage<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display,
+    {
+        let handler = move |msg| -> BoxFuture<'static, Result<(), String>> {
+            let fut = handler(msg);
+            Box::pin(async move { fut.await.map_err(|e| e.to_string()) })
+        };
+        self.routes.insert(topic.into(), Box::new(handler));
+        self
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    pub async fn dispatch(&self, msg: ConsumedMessage<T>) -> KafkaResult<()> {
+        let topic = msg.topic.clone();
+        let handler = self.routes.get(&topic).ok_or_else(|| KafkaError::NoRoute(topic.clone()))?;
+        handler(msg).await.map_err(|message| KafkaError::Handler { topic, message })
+    }
+}
+
+impl<T: Send + 'static> Default for TopicRouter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    fn message(topic: &str, body: &str) -> ConsumedMessage<String> {
+        ConsumedMessage {
+            message: body.to_owned(),
+            headers: HashMap::new(),
+            topic: topic.to_owned(),
+            partition: 0,
+            offset: 0,
+            timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_source_topic() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let seen = Arc::clone(&seen);
+            move |msg: ConsumedMessage<String>| {
+                seen.lock().unwrap().push((name, msg.message));
+                async { Ok::<_, String>(()) }
+            }
+        };
+        let router = TopicRouter::new()
+            .route("images", record("images"))
+            .route("audit", record("audit"));
+
+        router.dispatch(message("audit", "a")).await.unwrap();
+        router.dispatch(message("images", "b")).await.unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), [("audit", "a".to_owned()), ("images", "b".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn unrouted_topic_and_handler_failure_are_errors() {
+        let router = TopicRouter::new().route("images", |_: ConsumedMessage<String>| async { Err("boom") });
+
+        assert!(matches!(router.dispatch(message("chats", "x")).await, Err(KafkaError::NoRoute(t)) if t == "chats"));
+        assert!(matches!(
+            router.dispatch(message("images", "x")).await,
+            Err(KafkaError::Handler { message, .. }) if message == "boom"
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::{ConsumedMessage, KafkaConsumer},
+    error::{KafkaError, KafkaResult},
+    producer::KafkaProducer,
+    schemas::KafkaMessage,
+};
+use dashmap::DashMap;
+use std::{collections::HashMap, convert::Infallible, fmt::Display, sync::Arc, time::Duration};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Header pairing a reply with its request; servers copy it from the request onto the reply.
+pub const CORRELATION_ID_HEADER: &str = "correlation_id";
+
+type PendingReplies = DashMap<String, oneshot::Sender<KafkaMessage>>;
+
+/// Removes a call's pending entry however the call ends: reply, timeout, send error or cancellation.
+struct PendingCall<'a> {
+    pending: &'a PendingReplies,
+    correlation_id: String,
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        self.pending.remove(&self.correlation_id);
+    }
+}
+
+/// Request/reply over a pair of topics: requests are produced to the producer's topic and replies are matched
+/// back to callers by their [`CORRELATION_ID_HEADER`].
+///
+/// The reply consumer runs in a background task until the client is dropped or closed. Every instance must see
+/// all replies addressed to it, so give each one its own group id (or a manual assignment over every reply
+/// partition) rather than sharing a group between instances.
+pub struct KafkaRpcClient {
+    requests: KafkaProducer,
+    pending: Arc<PendingReplies>,
+    shutdown: CancellationToken,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl KafkaRpcClient {
+    pub fn new(requests: ProducerConfig, replies: ConsumerConfig) -> KafkaResult<Self> {
+        let producer = KafkaProducer::new(requests)?;
+        let consumer = KafkaConsumer::new(replies)?;
+        let pending = Arc::new(PendingReplies::new());
+        let shutdown = CancellationToken::new();
+        let listener = tokio::spawn(listen(consumer, Arc::clone(&pending), shutdown.clone()));
+
+        Ok(Self {
+            requests: producer,
+            pending,
+            shutdown,
+            listener: Some(listener),
+        })
+    }
+
+    /// Sends `message` keyed by its `user_id` and waits up to `timeout` for the matching reply.
+    pub async fn call(&self, message: &KafkaMessage, timeout: Duration) -> KafkaResult<KafkaMessage> {
+        let correlation_id = Uuid::now_v7().to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(correlation_id.clone(), tx);
+        let _call = PendingCall {
+            pending: &self.pending,
+            correlation_id: correlation_id.clone(),
+        };
+
+        let headers = HashMap::from([(CORRELATION_ID_HEADER.to_owned(), correlation_id.clone())]);
+        self.requests.send_with_headers(&message.user_id, message, &headers).await?;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            // The sender only goes away with the listener task.
+            Ok(Err(_)) => Err(KafkaError::CanceledMessage(futures::channel::oneshot::Canceled)),
+            Err(_) => {
+                tracing::warn!(correlation_id = %correlation_id, ?timeout, "No reply received in time");
+                Err(KafkaError::Timeout(timeout))
+            }
+        }
+    }
+
+    /// Calls still waiting for their reply.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Stops the reply consumer and waits for it to commit; calls still in flight fail.
+    pub async fn close(mut self) {
+        self.shutdown.cancel();
+        if let Some(listener) = self.listener.take()
+            && let Err(e) = listener.await
+        {
+            tracing::error!("RPC reply listener failed: {e}");
+        }
+    }
+}
+
+impl Drop for KafkaRpcClient {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn listen(consumer: KafkaConsumer, pending: Arc<PendingReplies>, shutdown: CancellationToken) {
+    let result = consumer
+        .run_with_handler(
+            |reply: ConsumedMessage<KafkaMessage>| {
+                match reply.headers.get(CORRELATION_ID_HEADER) {
+                    Some(id) => match pending.remove(id) {
+                        // The caller may have given up between the lookup and the send.
+                        Some((_, tx)) => drop(tx.send(reply.message)),
+                        None => tracing::debug!(correlation_id = %id, "Dropping reply to an unknown or expired call"),
+                    },
+                    None => tracing::warn!(topic = %reply.topic, offset = reply.offset, "Dropping reply without correlation id"),
+                }
+                async { Ok::<_, Infallible>(()) }
+            },
+            shutdown,
+        )
+        .await;
+    if let Err(e) = result {
+        tracing::error!("RPC reply listener stopped: {e}");
+    }
+    consumer.close().await;
+}
+
+/// Answers requests from a [`KafkaRpcClient`], producing each reply with the request's correlation id.
+pub struct KafkaRpcServer {
+    requests: KafkaConsumer,
+    replies: KafkaProducer,
+}
+
+impl KafkaRpcServer {
+    pub fn new(requests: ConsumerConfig, replies: ProducerConfig) -> KafkaResult<Self> {
+        Ok(Self {
+            requests: KafkaConsumer::new(requests)?,
+            replies: KafkaProducer::new(replies)?,
+        })
+    }
+
+    /// Runs `handler` on every request until `shutdown` is cancelled and sends back what it returns, keyed like
+    /// the request. Handler and send failures go through the consumer's retry policy; requests without a
+    /// correlation id cannot be answered and are skipped.
+    pub async fn serve<F, Fut, E>(&self, mut handler: F, shutdown: CancellationToken) -> KafkaResult<()>
+    where
+        F: FnMut(KafkaMessage) -> Fut,
+        Fut: Future<Output = Result<KafkaMessage, E>>,
+        E: Display,
+    {
+        self.requests
+            .run_with_handler(
+                |request: ConsumedMessage<KafkaMessage>| {
+                    let Some(correlation_id) = request.headers.get(CORRELATION_ID_HEADER).cloned() else {
+                        tracing::warn!(topic = %request.topic, offset = request.offset, "Skipping request without correlation id");
+                        return futures::future::Either::Left(async { Ok(()) });
+                    };
+                    let key = request.message.user_id.clone();
+                    let reply = handler(request.message);
+                    futures::future::Either::Right(async move {
+                        let reply = reply.await.map_err(|e| e.to_string())?;
+                        let headers = HashMap::from([(CORRELATION_ID_HEADER.to_owned(), correlation_id)]);
+                        self.replies
+                            .send_with_headers(&key, &reply, &headers)
+                            .await
+                            .map_err(|e| e.to_string())?;
+                        Ok::<_, String>(())
+                    })
+                },
+                shutdown,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_entry_is_removed_when_call_ends() {
+        let pending = PendingReplies::new();
+        let (tx, _rx) = oneshot::channel();
+        pending.insert("abc".into(), tx);
+
+        drop(PendingCall {
+            pending: &pending,
+            correlation_id: "abc".into(),
+        });
+        assert!(pending.is_empty());
+    }
+}
@@ -1,16 +1,57 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+/// Envelope version written by [`KafkaMessage::v1`]; messages from before versioning parse as version 0.
+pub const CURRENT_VERSION: u8 = 1;
+
+/// Well-known [`KafkaMessage::metadata`] keys for image events.
+pub const METADATA_OBJECT_KEY: &str = "object_key";
+pub const METADATA_CONTENT_TYPE: &str = "content_type";
+pub const METADATA_SIZE: &str = "size";
+
+/// Event envelope. New fields are optional with serde defaults, so payloads written by older producers still
+/// parse and older consumers ignore what they do not know.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct KafkaMessage<T = String> {
+    #[serde(default)]
+    pub version: u8,
     pub user_id: String,
     pub action: Action,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
 }
 
 impl<T> KafkaMessage<T> {
+    /// Version 0 message, identical on the wire to what producers wrote before versioning apart from the version field.
     pub fn new(user_id: String, action: Action, data: Option<T>) -> Self {
-        Self { user_id, action, data }
+        Self {
+            version: 0,
+            user_id,
+            action,
+            data,
+            metadata: None,
+        }
+    }
+
+    pub fn v1(user_id: String, action: Action, data: Option<T>, metadata: HashMap<String, String>) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            metadata: Some(metadata),
+            ..Self::new(user_id, action, data)
+        }
+    }
+
+    /// Adds a metadata entry, upgrading the message to the current version.
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.version = CURRENT_VERSION;
+        self.metadata.get_or_insert_default().insert(key.into(), value.into());
+        self
+    }
+
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.as_ref()?.get(key).map(String::as_str)
     }
 }
 
@@ -20,4 +61,6 @@ pub enum Action {
     Create,
     Update,
     Delete,
+    Export,
+    Purge,
 }
@@ -0,0 +1,264 @@
+use rdkafka::{
+    ClientContext,
+    consumer::ConsumerContext,
+    statistics::{Broker, Statistics},
+};
+use std::sync::{Arc, RwLock};
+
+/// Snapshot of a producer's librdkafka statistics, refreshed every `statistics.interval.ms`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProducerStats {
+    /// Seconds since the Unix epoch when librdkafka emitted these statistics.
+    pub time: i64,
+    /// Messages waiting in the local producer queue.
+    pub msg_cnt: u64,
+    /// Messages sent to brokers since the client started.
+    pub txmsgs: i64,
+    /// Requests waiting to be sent, summed across brokers.
+    pub outbuf_cnt: i64,
+    pub brokers: Vec<BrokerStats>,
+}
+
+/// Round-trip times in microseconds over the last statistics window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BrokerStats {
+    pub name: String,
+    pub state: String,
+    pub outbuf_cnt: i64,
+    /// Transmission and receive errors since the client started.
+    pub errors: u64,
+    pub rtt_p50_us: i64,
+    pub rtt_p95_us: i64,
+    pub rtt_p99_us: i64,
+}
+
+/// Snapshot of a consumer's librdkafka statistics, refreshed every `statistics.interval.ms`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerStats {
+    /// Seconds since the Unix epoch when librdkafka emitted these statistics.
+    pub time: i64,
+    /// Messages received since the client started.
+    pub rxmsgs: i64,
+    pub partitions: Vec<PartitionLag>,
+}
+
+/// Distance between the high watermark and the committed offset of one assigned partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub lag: i64,
+}
+
+impl From<&Broker> for BrokerStats {
+    fn from(broker: &Broker) -> Self {
+        let rtt = broker.rtt.clone().unwrap_or_default();
+        Self {
+            name: broker.name.clone(),
+            state: broker.state.clone(),
+            outbuf_cnt: broker.outbuf_cnt,
+            errors: broker.txerrs + broker.rxerrs,
+            rtt_p50_us: rtt.p50,
+            rtt_p95_us: rtt.p95,
+            rtt_p99_us: rtt.p99,
+        }
+    }
+}
+
+impl From<Statistics> for ProducerStats {
+    fn from(stats: Statistics) -> Self {
+        // Bootstrap entries (nodeid -1) duplicate the real brokers once metadata is known.
+        let mut brokers: Vec<BrokerStats> = stats
+            .brokers
+            .values()
+            .filter(|broker| broker.nodeid >= 0)
+            .map(BrokerStats::from)
+            .collect();
+        brokers.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            time: stats.time,
+            msg_cnt: stats.msg_cnt,
+            txmsgs: stats.txmsgs,
+            outbuf_cnt: brokers.iter().map(|broker| broker.outbuf_cnt).sum(),
+            brokers,
+        }
+    }
+}
+
+impl From<Statistics> for ConsumerStats {
+    fn from(stats: Statistics) -> Self {
+        // Partition -1 is librdkafka's internal unassigned queue; a lag of -1 means no committed offset yet.
+        let mut partitions: Vec<PartitionLag> = stats
+            .topics
+            .values()
+            .flat_map(|topic| topic.partitions.values().map(move |p| (&topic.topic, p)))
+            .filter(|(_, p)| p.partition >= 0 && p.consumer_lag >= 0)
+            .map(|(topic, p)| PartitionLag {
+                topic: topic.clone(),
+                partition: p.partition,
+                lag: p.consumer_lag,
+            })
+            .collect();
+        partitions.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        Self {
+            time: stats.time,
+            rxmsgs: stats.rxmsgs,
+            partitions,
+        }
+    }
+}
+
+/// Client context that keeps the latest statistics report, converted to `S`.
+pub struct StatsContext<S> {
+    latest: Arc<RwLock<Option<S>>>,
+}
+
+impl<S> StatsContext<S> {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Shared view of the latest report, `None` until librdkafka emits the first one.
+    pub fn handle(&self) -> StatsHandle<S> {
+        StatsHandle {
+            latest: Arc::clone(&self.latest),
+        }
+    }
+}
+
+impl<S> Default for StatsContext<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> ClientContext for StatsContext<S>
+where
+    S: From<Statistics> + Send + Sync + 'static,
+{
+    fn stats(&self, statistics: Statistics) {
+        *self.latest.write().unwrap_or_else(|e| e.into_inner()) = Some(S::from(statistics));
+    }
+}
+
+impl<S> ConsumerContext for StatsContext<S> where S: From<Statistics> + Send + Sync + 'static {}
+
+/// Cheap to clone; reads the latest report kept by a [`StatsContext`].
+pub struct StatsHandle<S> {
+    latest: Arc<RwLock<Option<S>>>,
+}
+
+impl<S> Clone for StatsHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            latest: Arc::clone(&self.latest),
+        }
+    }
+}
+
+impl<S: Clone> StatsHandle<S> {
+    pub fn get(&self) -> Option<S> {
+        self.latest.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::statistics::{Partition, Topic, Window};
+
+    fn broker(name: &str, nodeid: i32, outbuf_cnt: i64) -> (String, Broker) {
+        let broker = Broker {
+            name: name.into(),
+            nodeid,
+            state: "UP".into(),
+            outbuf_cnt,
+            ..Default::default()
+        };
+        (name.into(), broker)
+    }
+
+    fn partition(partition: i32, consumer_lag: i64) -> (i32, Partition) {
+        let stats = Partition {
+            partition,
+            consumer_lag,
+            ..Default::default()
+        };
+        (partition, stats)
+    }
+
+    #[test]
+    fn producer_stats_sum_broker_queues_and_skip_bootstrap() {
+        let (name, mut b2) = broker("b2:9092/2", 2, 1);
+        b2.txerrs = 1;
+        b2.rxerrs = 2;
+        b2.rtt = Some(Window {
+            p50: 800,
+            p95: 1500,
+            p99: 3000,
+            ..Default::default()
+        });
+        let stats = Statistics {
+            msg_cnt: 4,
+            txmsgs: 120,
+            brokers: [
+                broker("localhost:9092/bootstrap", -1, 9),
+                (name, b2),
+                broker("b1:9092/1", 1, 2),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        let producer = ProducerStats::from(stats);
+        assert_eq!(producer.msg_cnt, 4);
+        assert_eq!(producer.txmsgs, 120);
+        assert_eq!(producer.outbuf_cnt, 3);
+        let names: Vec<_> = producer.brokers.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["b1:9092/1", "b2:9092/2"]);
+        let b2 = &producer.brokers[1];
+        assert_eq!((b2.rtt_p50_us, b2.rtt_p95_us, b2.rtt_p99_us), (800, 1500, 3000));
+        assert_eq!(b2.errors, 3);
+        assert_eq!(producer.brokers[0].rtt_p99_us, 0);
+    }
+
+    #[test]
+    fn consumer_stats_report_known_partition_lag() {
+        let topic = Topic {
+            topic: "images".into(),
+            partitions: [partition(1, 7), partition(0, 3), partition(2, -1), partition(-1, -1)].into(),
+            ..Default::default()
+        };
+        let stats = Statistics {
+            rxmsgs: 50,
+            topics: [("images".to_string(), topic)].into(),
+            ..Default::default()
+        };
+
+        let consumer = ConsumerStats::from(stats);
+        assert_eq!(consumer.rxmsgs, 50);
+        let lags: Vec<_> = consumer
+            .partitions
+            .iter()
+            .map(|p| (p.topic.as_str(), p.partition, p.lag))
+            .collect();
+        assert_eq!(lags, [("images", 0, 3), ("images", 1, 7)]);
+    }
+
+    #[test]
+    fn context_keeps_latest_report() {
+        let context = StatsContext::<ProducerStats>::new();
+        let handle = context.handle();
+        assert!(handle.get().is_none());
+
+        for txmsgs in [1, 5] {
+            context.stats(Statistics {
+                txmsgs,
+                ..Default::default()
+            });
+        }
+        assert_eq!(handle.get().unwrap().txmsgs, 5);
+    }
+}
@@ -0,0 +1,170 @@
+use kafka_client::{
+    admin::{KafkaAdmin, RetentionMinimums},
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    error::KafkaError,
+    producer::KafkaProducer,
+    schemas::{Action, KafkaMessage},
+};
+use rdkafka::{
+    ClientConfig,
+    admin::{AdminClient, AdminOptions, AlterConfig, ResourceSpecifier},
+    client::DefaultClientContext,
+};
+use testcontainers_modules::{
+    kafka::Kafka,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+
+async fn start_kafka() -> anyhow::Result<(ContainerAsync<Kafka>, String)> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    Ok((kafka, format!("{}:{}", host, port)))
+}
+
+/// A plain client, to change topics behind [`KafkaAdmin`]'s back.
+fn admin_client(brokers: &str) -> anyhow::Result<AdminClient<DefaultClientContext>> {
+    Ok(ClientConfig::new().set("bootstrap.servers", brokers).create()?)
+}
+
+async fn create_topic(brokers: &str, name: &str, partitions: i32) -> anyhow::Result<()> {
+    KafkaAdmin::new(brokers)?
+        .ensure_topic(name, partitions, 1, Some(3_600_000))
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_describe_topic_partitions() -> anyhow::Result<()> {
+    let (_kafka, brokers) = start_kafka().await?;
+    create_topic(&brokers, "admin-described", 3).await?;
+
+    let admin = KafkaAdmin::new(&brokers)?;
+    let description = admin.describe_topic("admin-described").await?;
+
+    assert_eq!(description.name, "admin-described");
+    assert_eq!(description.partitions.len(), 3);
+    assert_eq!(description.replication_factor, 1);
+    assert!(description.partitions.iter().all(|p| p.isr.contains(&p.leader)));
+    assert_eq!(description.configs.get("retention.ms").map(String::as_str), Some("3600000"));
+
+    assert!(admin.list_topics().await?.contains(&"admin-described".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_describe_missing_topic() -> anyhow::Result<()> {
+    let (_kafka, brokers) = start_kafka().await?;
+    let admin = KafkaAdmin::new(&brokers)?;
+
+    let result = admin.describe_topic("does-not-exist").await;
+    assert!(matches!(result, Err(KafkaError::TopicNotFound(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_describe_group() -> anyhow::Result<()> {
+    let (_kafka, brokers) = start_kafka().await?;
+    create_topic(&brokers, "admin-group-topic", 2).await?;
+
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, "admin-group-topic").build()?)?;
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "admin-group", "admin-group-topic").build()?)?;
+
+    let message = KafkaMessage::new("admin_user".to_string(), Action::Create, Some("data".to_string()));
+    producer.send(&message.user_id, &message).await?;
+    consumer.consume::<KafkaMessage>().await?;
+
+    let admin = KafkaAdmin::new(&brokers)?;
+    let group = admin.describe_group("admin-group").await?;
+    assert_eq!(group.group_id, "admin-group");
+    assert_eq!(group.members.len(), 1);
+    let assigned: usize = group.members[0].assignment.values().map(Vec::len).sum();
+    assert_eq!(assigned, 2);
+
+    let missing = admin.describe_group("no-such-group").await;
+    assert!(matches!(missing, Err(KafkaError::GroupNotFound(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ensure_describe_delete_topic() -> anyhow::Result<()> {
+    let (_kafka, brokers) = start_kafka().await?;
+    let admin = KafkaAdmin::new(&brokers)?;
+
+    admin.ensure_topic("admin-ensured", 4, 1, Some(3_600_000)).await?;
+    let description = admin.describe_topic("admin-ensured").await?;
+    assert_eq!(description.partitions.len(), 4);
+    assert_eq!(description.configs.get("retention.ms").map(String::as_str), Some("3600000"));
+
+    // Same partition count is accepted and converges retention, keeping the topic's other overrides.
+    let alter = AlterConfig::new(ResourceSpecifier::Topic("admin-ensured"))
+        .set("retention.ms", "3600000")
+        .set("max.message.bytes", "2000000");
+    for result in admin_client(&brokers)?.alter_configs(&[alter], &AdminOptions::new()).await? {
+        result.map_err(|(_, code)| anyhow::anyhow!("alter configs failed: {code}"))?;
+    }
+    admin.ensure_topic("admin-ensured", 4, 1, Some(7_200_000)).await?;
+    let description = admin.describe_topic("admin-ensured").await?;
+    assert_eq!(description.configs.get("retention.ms").map(String::as_str), Some("7200000"));
+    assert_eq!(
+        description.configs.get("max.message.bytes").map(String::as_str),
+        Some("2000000")
+    );
+
+    admin.ensure_compacted_topic("admin-ensured", 4, 1).await?;
+    let description = admin.describe_topic("admin-ensured").await?;
+    assert_eq!(description.configs.get("cleanup.policy").map(String::as_str), Some("compact"));
+    assert_eq!(description.configs.get("retention.ms").map(String::as_str), Some("7200000"));
+
+    let conflict = admin.ensure_topic("admin-ensured", 2, 1, None).await;
+    assert!(matches!(
+        conflict,
+        Err(KafkaError::PartitionMismatch {
+            existing: 4,
+            requested: 2,
+            ..
+        })
+    ));
+
+    admin.delete_topic("admin-ensured").await?;
+    let deleted = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while !matches!(admin.describe_topic("admin-ensured").await, Err(KafkaError::TopicNotFound(_))) {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+    })
+    .await;
+    assert!(deleted.is_ok(), "topic still listed after delete");
+    assert!(matches!(
+        admin.delete_topic("admin-ensured").await,
+        Err(KafkaError::TopicNotFound(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_retention() -> anyhow::Result<()> {
+    let (_kafka, brokers) = start_kafka().await?;
+    let admin = KafkaAdmin::new(&brokers)?;
+    admin.ensure_topic("admin-short-retention", 1, 1, Some(60_000)).await?;
+    let minimums = RetentionMinimums::default();
+
+    let report = admin.verify_retention("admin-short-retention", &minimums, false).await?;
+    assert_eq!(report.retention_ms, Some(60_000));
+    assert!(!report.is_sufficient());
+
+    let strict = admin.verify_retention("admin-short-retention", &minimums, true).await;
+    assert!(matches!(strict, Err(KafkaError::InsufficientRetention { .. })));
+
+    let relaxed = RetentionMinimums {
+        retention_ms: 60_000,
+        retention_bytes: None,
+    };
+    assert!(
+        admin
+            .verify_retention("admin-short-retention", &relaxed, true)
+            .await?
+            .is_sufficient()
+    );
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use kafka_client::{
+    admin::KafkaAdmin,
+    checkpoint::{Checkpoint, CheckpointPolicy, CheckpointStore, KafkaCheckpointStore},
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    error::{KafkaError, KafkaResult},
+    producer::KafkaProducer,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+use testcontainers_modules::{kafka::Kafka, testcontainers::runners::AsyncRunner as _};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Upload {
+    user_id: String,
+}
+
+type UploadsPerUser = HashMap<String, u64>;
+
+const USERS: usize = 5;
+const UPLOADS: usize = 100;
+
+async fn publish_uploads(brokers: &str, topic: &str) -> anyhow::Result<()> {
+    let admin = KafkaAdmin::new(brokers)?;
+    admin.ensure_topic(topic, 2, 1, None).await?;
+    admin.ensure_compacted_topic("checkpoints", 1, 1).await?;
+
+    let producer = KafkaProducer::new(ProducerConfig::builder(brokers, topic).build()?)?;
+    for i in 0..UPLOADS {
+        let upload = Upload {
+            user_id: format!("user-{}", i % USERS),
+        };
+        producer.send(&upload.user_id, &upload).await?;
+    }
+    Ok(())
+}
+
+fn checkpoint_store(brokers: &str) -> KafkaResult<KafkaCheckpointStore> {
+    KafkaCheckpointStore::new(ProducerConfig::builder(brokers, "checkpoints").build()?)
+}
+
+fn count(state: &mut UploadsPerUser, upload: Upload) -> u64 {
+    *state.entry(upload.user_id).or_default() += 1;
+    state.values().sum()
+}
+
+#[tokio::test]
+async fn test_aggregate_resumes_from_checkpoint_after_kill() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let brokers = format!("{}:{}", kafka.get_host().await?, kafka.get_host_port_ipv4(9093).await?);
+    publish_uploads(&brokers, "uploads").await?;
+    let policy = CheckpointPolicy {
+        every_messages: 10,
+        interval: Duration::from_secs(60),
+    };
+
+    // First instance: killed after 55 uploads, so its last checkpoint covers at most 50 of them.
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "quotas", "uploads").build()?)?;
+    let store = checkpoint_store(&brokers)?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let first = tokio::spawn(async move {
+        consumer
+            .run_with_checkpoints(
+                "quotas",
+                &store,
+                policy,
+                UploadsPerUser::new(),
+                |state, msg| {
+                    let _ = tx.send(count(state, msg.message));
+                },
+                CancellationToken::new(),
+            )
+            .await
+    });
+    while tokio::time::timeout(Duration::from_secs(30), rx.recv())
+        .await?
+        .expect("first consumer stopped")
+        < 55
+    {}
+    first.abort();
+    assert!(first.await.unwrap_err().is_cancelled());
+
+    let store = checkpoint_store(&brokers)?;
+    let blob = store.load("quotas").await?.expect("checkpoint written before the kill");
+    let restored: Checkpoint<UploadsPerUser> = serde_json::from_slice(&blob)?;
+    let checkpointed: u64 = restored.state.values().sum();
+    assert!((10..=55).contains(&checkpointed), "checkpointed {checkpointed} uploads");
+
+    // Second instance: picks the aggregate up where the checkpoint left it.
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "quotas", "uploads").build()?)?;
+    let shutdown = CancellationToken::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let second = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_checkpoints(
+                    "quotas",
+                    &store,
+                    policy,
+                    UploadsPerUser::new(),
+                    |state, msg| {
+                        let _ = tx.send(count(state, msg.message));
+                    },
+                    shutdown,
+                )
+                .await
+        }
+    });
+    let mut handled = 0;
+    loop {
+        let total = tokio::time::timeout(Duration::from_secs(30), rx.recv())
+            .await?
+            .expect("second consumer stopped");
+        handled += 1;
+        if total == UPLOADS as u64 {
+            break;
+        }
+    }
+    shutdown.cancel();
+    let state = second.await??;
+
+    // Uploads after the checkpoint were replayed into the checkpointed state, so nothing is counted twice.
+    assert_eq!(handled, UPLOADS as u64 - checkpointed);
+    assert_eq!(state.len(), USERS);
+    assert!(
+        state.values().all(|&uploads| uploads == (UPLOADS / USERS) as u64),
+        "{state:?}"
+    );
+    Ok(())
+}
+
+/// Refuses every write, like a store whose backend is down.
+struct UnavailableStore;
+
+impl CheckpointStore for UnavailableStore {
+    async fn save(&self, _id: &str, _blob: Vec<u8>) -> KafkaResult<()> {
+        Err(KafkaError::CheckpointStore("unavailable".into()))
+    }
+
+    async fn load(&self, _id: &str) -> KafkaResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+#[tokio::test]
+async fn test_failed_checkpoint_keeps_state_in_memory() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let brokers = format!("{}:{}", kafka.get_host().await?, kafka.get_host_port_ipv4(9093).await?);
+    publish_uploads(&brokers, "uploads").await?;
+
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "quotas", "uploads").build()?)?;
+    let shutdown = CancellationToken::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let run = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_checkpoints(
+                    "quotas",
+                    &UnavailableStore,
+                    CheckpointPolicy {
+                        every_messages: 10,
+                        interval: Duration::from_millis(100),
+                    },
+                    UploadsPerUser::new(),
+                    |state, msg| {
+                        let _ = tx.send(count(state, msg.message));
+                    },
+                    shutdown,
+                )
+                .await
+        }
+    });
+    while tokio::time::timeout(Duration::from_secs(30), rx.recv())
+        .await?
+        .expect("consumer stopped")
+        < UPLOADS as u64
+    {}
+    shutdown.cancel();
+
+    let state = run.await??;
+    assert_eq!(state.values().sum::<u64>(), UPLOADS as u64);
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use kafka_client::{
-    config::{ConsumerConfig, LogLevel, ProducerConfig},
-    error::KafkaResult,
+    config::{
+        AssignmentMode, ConsumerConfig, Delivery, LogLevel, ProducerConfig, SaslMechanism, SecurityConfig, SecurityProtocol,
+        StartOffset,
+    },
+    error::{KafkaError, KafkaResult},
+    retry::RetryPolicy,
 };
-use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::{config::RDKafkaLogLevel, consumer::BaseConsumer, producer::FutureProducer};
+use std::time::Duration;
 
 #[test]
 fn test_consumer_config_creation() -> KafkaResult<()> {
@@ -12,7 +17,22 @@ fn test_consumer_config_creation() -> KafkaResult<()> {
 
     assert_eq!(config.brokers, "localhost:9092");
     assert_eq!(config.group_id, "test-group");
-    assert_eq!(config.input_topic, "test-topic");
+    assert_eq!(config.topics, ["test-topic"]);
+    Ok(())
+}
+
+#[test]
+fn test_consumer_config_multiple_topics() -> KafkaResult<()> {
+    let config = ConsumerConfig::builder_with_topics("localhost:9092", "test-group", ["images", "chats", "audit"]).build()?;
+    assert_eq!(config.topics, ["images", "chats", "audit"]);
+
+    let empty = ConsumerConfig::builder_with_topics("localhost:9092", "test-group", Vec::<String>::new()).build();
+    assert!(empty.is_err());
+
+    let looping = ConsumerConfig::builder_with_topics("localhost:9092", "test-group", ["images", "chats"])
+        .dead_letter_topic("chats")
+        .build();
+    assert!(looping.is_err());
     Ok(())
 }
 
@@ -30,6 +50,122 @@ fn test_consumer_config_custom_timeouts() -> KafkaResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_consumer_config_at_least_once_disables_auto_commit() -> KafkaResult<()> {
+    let config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .delivery(Delivery::AtLeastOnce)
+        .build()?;
+
+    assert_eq!(config.delivery, Delivery::AtLeastOnce);
+    assert!(!config.auto_commit);
+    Ok(())
+}
+
+#[test]
+fn test_consumer_config_dead_letter_topic() -> KafkaResult<()> {
+    let config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .dead_letter_topic("test-topic.dlq")
+        .build()?;
+    assert_eq!(config.dead_letter_topic.as_deref(), Some("test-topic.dlq"));
+
+    let looping = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .dead_letter_topic("test-topic")
+        .build();
+    assert!(looping.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_consumer_config_retry_policy() -> KafkaResult<()> {
+    let config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic").build()?;
+    assert_eq!(config.retry, RetryPolicy::default());
+
+    let config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .max_retries(5)
+        .retry_backoff_ms(50, 2000)
+        .build()?;
+    assert_eq!(config.retry.max_retries, 5);
+    assert_eq!(config.retry.initial_backoff, Duration::from_millis(50));
+    assert_eq!(config.retry.max_backoff, Duration::from_secs(2));
+
+    let no_attempts = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .max_retries(0)
+        .build();
+    assert!(matches!(no_attempts, Err(KafkaError::InvalidConfig(_))));
+    Ok(())
+}
+
+#[test]
+fn test_statistics_interval_in_client_config() -> KafkaResult<()> {
+    let consumer = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic").build()?;
+    assert_eq!(consumer.client_config().get("statistics.interval.ms"), Some("5000"));
+
+    let producer = ProducerConfig::builder("localhost:9092", "output-topic")
+        .statistics_interval_ms(0)
+        .build()?;
+    assert_eq!(producer.statistics_interval_ms, 0);
+    assert_eq!(producer.client_config().get("statistics.interval.ms"), Some("0"));
+    Ok(())
+}
+
+#[test]
+fn test_manual_assignment_config() -> KafkaResult<()> {
+    let manual = AssignmentMode::Manual(vec![(0, StartOffset::Beginning), (1, StartOffset::Absolute(42))]);
+    let config = ConsumerConfig::builder("localhost:9092", "", "audit-topic")
+        .assignment(manual.clone())
+        .build()?;
+    assert_eq!(config.assignment, manual);
+    assert!(!config.auto_commit);
+    let client = config.client_config();
+    assert_eq!(client.get("enable.auto.commit"), Some("false"));
+    assert_eq!(client.get("group.id"), Some("audit-topic-manual"));
+
+    let grouped = ConsumerConfig::builder("localhost:9092", "", "audit-topic")
+        .assignment(AssignmentMode::Group("audit-group".into()))
+        .build()?;
+    assert_eq!(grouped.group_id, "audit-group");
+    assert_eq!(grouped.assignment, AssignmentMode::Group("audit-group".into()));
+    Ok(())
+}
+
+#[test]
+fn test_manual_assignment_validation() {
+    let with_group = ConsumerConfig::builder("localhost:9092", "test-group", "audit-topic")
+        .assignment(AssignmentMode::Manual(vec![(0, StartOffset::End)]))
+        .build();
+    assert!(matches!(with_group, Err(KafkaError::InvalidConfig(_))));
+
+    let many_topics = ConsumerConfig::builder_with_topics("localhost:9092", "", ["a", "b"])
+        .assignment(AssignmentMode::Manual(vec![(0, StartOffset::End)]))
+        .build();
+    assert!(matches!(many_topics, Err(KafkaError::InvalidConfig(_))));
+
+    let no_partitions = ConsumerConfig::builder("localhost:9092", "", "audit-topic")
+        .assignment(AssignmentMode::Manual(Vec::new()))
+        .build();
+    assert!(matches!(no_partitions, Err(KafkaError::InvalidConfig(_))));
+}
+
+#[test]
+fn test_transactional_id_in_client_config() -> KafkaResult<()> {
+    let plain = ProducerConfig::builder("localhost:9092", "exports").build()?;
+    assert_eq!(plain.client_config().get("transactional.id"), None);
+
+    let config = ProducerConfig::builder("localhost:9092", "exports")
+        .transactional_id("export-pipeline-0")
+        .transaction_timeout_ms(30_000)
+        .build()?;
+    let client = config.client_config();
+    assert_eq!(client.get("transactional.id"), Some("export-pipeline-0"));
+    assert_eq!(client.get("transaction.timeout.ms"), Some("30000"));
+
+    let empty = ProducerConfig::builder("localhost:9092", "exports")
+        .transactional_id("")
+        .build();
+    assert!(matches!(empty, Err(KafkaError::InvalidConfig(_))));
+    Ok(())
+}
+
 #[test]
 fn test_producer_config_creation() -> KafkaResult<()> {
     let config = ProducerConfig::builder("localhost:9092", "output-topic").build()?;
@@ -73,3 +209,159 @@ fn test_producer_validate() {
     let config = ProducerConfig::builder("", "").build();
     assert!(config.is_err());
 }
+
+fn scram_sasl_ssl() -> SecurityConfig {
+    SecurityConfig {
+        protocol: SecurityProtocol::SaslSsl,
+        mechanism: Some(SaslMechanism::ScramSha512),
+        username: Some("svc-images".into()),
+        password: Some("hunter2".into()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_sasl_requires_credentials() {
+    for missing in [
+        SecurityConfig {
+            mechanism: None,
+            ..scram_sasl_ssl()
+        },
+        SecurityConfig {
+            username: None,
+            ..scram_sasl_ssl()
+        },
+        SecurityConfig {
+            password: Some(String::new()),
+            ..scram_sasl_ssl()
+        },
+    ] {
+        let result = ProducerConfig::builder("localhost:9092", "test-topic")
+            .security(missing)
+            .build();
+        assert!(matches!(result, Err(KafkaError::InvalidConfig(_))));
+    }
+}
+
+#[test]
+fn test_missing_cert_file_names_path() {
+    let security = SecurityConfig {
+        ca_cert_path: Some("/nonexistent/kafka-ca.pem".into()),
+        ..scram_sasl_ssl()
+    };
+    let result = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .security(security)
+        .build();
+
+    match result {
+        Err(KafkaError::InvalidConfig(msg)) => assert!(msg.contains("/nonexistent/kafka-ca.pem"), "{msg}"),
+        other => panic!("expected InvalidConfig, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_client_cert_requires_key() {
+    let security = SecurityConfig {
+        protocol: SecurityProtocol::Ssl,
+        client_cert_path: Some(std::env::current_exe().unwrap()),
+        ..Default::default()
+    };
+    let result = ProducerConfig::builder("localhost:9092", "test-topic")
+        .security(security)
+        .build();
+    assert!(matches!(result, Err(KafkaError::InvalidConfig(_))));
+}
+
+#[test]
+fn test_security_properties_in_client_config() -> KafkaResult<()> {
+    let ca = std::env::current_exe().unwrap();
+    let security = SecurityConfig {
+        ca_cert_path: Some(ca.clone()),
+        ..scram_sasl_ssl()
+    };
+    let config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .security(security)
+        .build()?;
+    let client = config.client_config();
+
+    assert_eq!(client.get("security.protocol"), Some("sasl_ssl"));
+    assert_eq!(client.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+    assert_eq!(client.get("sasl.username"), Some("svc-images"));
+    assert_eq!(client.get("sasl.password"), Some("hunter2"));
+    assert_eq!(client.get("ssl.ca.location"), ca.to_str());
+    assert_eq!(client.get("ssl.certificate.location"), None);
+    assert!(!format!("{config:?}").contains("hunter2"));
+
+    let plain = ProducerConfig::builder("localhost:9092", "test-topic")
+        .build()?
+        .client_config();
+    assert_eq!(plain.get("security.protocol"), None);
+    Ok(())
+}
+
+#[test]
+fn test_secured_clients_can_be_created() -> KafkaResult<()> {
+    let consumer_config = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .security(scram_sasl_ssl())
+        .build()?;
+    let consumer: Result<BaseConsumer, _> = consumer_config.client_config().create();
+    assert!(consumer.is_ok(), "{:?}", consumer.err());
+
+    let producer_config = ProducerConfig::builder("localhost:9092", "test-topic")
+        .security(scram_sasl_ssl())
+        .build()?;
+    let producer: Result<FutureProducer, _> = producer_config.client_config().create();
+    assert!(producer.is_ok(), "{:?}", producer.err());
+    Ok(())
+}
+
+#[test]
+fn test_producer_log_level_in_client_config() -> KafkaResult<()> {
+    let config = ProducerConfig::builder("localhost:9092", "test-topic")
+        .log_level(LogLevel::Debug)
+        .build()?;
+    assert!(matches!(config.log_level, RDKafkaLogLevel::Debug));
+    assert!(matches!(config.client_config().log_level, RDKafkaLogLevel::Debug));
+
+    let default = ProducerConfig::builder("localhost:9092", "test-topic").build()?;
+    assert!(matches!(default.client_config().log_level, RDKafkaLogLevel::Info));
+    Ok(())
+}
+
+#[test]
+fn test_property_overrides_are_applied_last() -> KafkaResult<()> {
+    let producer = ProducerConfig::builder("localhost:9092", "test-topic")
+        .message_timeout_ms(5000)
+        .property("queue.buffering.max.ms", "50")
+        .property("message.max.bytes", "2097152")
+        .property("message.timeout.ms", "15000")
+        .build()?
+        .client_config();
+    assert_eq!(producer.get("queue.buffering.max.ms"), Some("50"));
+    assert_eq!(producer.get("message.max.bytes"), Some("2097152"));
+    assert_eq!(producer.get("message.timeout.ms"), Some("15000"));
+
+    let consumer = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+        .property("fetch.max.bytes", "1048576")
+        .property("session.timeout.ms", "30000")
+        .build()?
+        .client_config();
+    assert_eq!(consumer.get("fetch.max.bytes"), Some("1048576"));
+    assert_eq!(consumer.get("session.timeout.ms"), Some("30000"));
+    Ok(())
+}
+
+#[test]
+fn test_reserved_properties_cannot_be_overridden() {
+    for key in ["bootstrap.servers", "group.id", " group.id ", ""] {
+        let producer = ProducerConfig::builder("localhost:9092", "test-topic")
+            .property(key, "other")
+            .build();
+        assert!(matches!(producer, Err(KafkaError::InvalidConfig(_))), "{key:?}");
+
+        let consumer = ConsumerConfig::builder("localhost:9092", "test-group", "test-topic")
+            .property(key, "other")
+            .build();
+        assert!(matches!(consumer, Err(KafkaError::InvalidConfig(_))), "{key:?}");
+    }
+}
@@ -0,0 +1,104 @@
+use kafka_client::{
+    admin::KafkaAdmin,
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::ConsumedMessage,
+    priority::{PriorityConsumer, PriorityPolicy},
+    producer::KafkaProducer,
+    schemas::{Action, KafkaMessage},
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use testcontainers_modules::{kafka::Kafka, testcontainers::runners::AsyncRunner as _};
+use tokio_util::sync::CancellationToken;
+
+const HIGH: &str = "priority-deletes";
+const LOW: &str = "priority-creates";
+const FLOOD: usize = 2000;
+const TRICKLE: usize = 20;
+
+fn now_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+}
+
+#[tokio::test]
+async fn test_high_priority_stays_fast_under_a_low_priority_flood() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    let admin = KafkaAdmin::new(&brokers)?;
+    for topic in [HIGH, LOW] {
+        admin.ensure_topic(topic, 1, 1, None).await?;
+    }
+    let high = KafkaProducer::new(ProducerConfig::builder(&brokers, HIGH).build()?)?;
+    let low = KafkaProducer::new(ProducerConfig::builder(&brokers, LOW).build()?)?;
+
+    let flood: Vec<_> = (0..FLOOD)
+        .map(|i| KafkaMessage::new("backfill".to_string(), Action::Create, Some(i.to_string())))
+        .collect();
+    low.send_batch(&flood).await?;
+
+    let config = ConsumerConfig::builder_with_topics(&brokers, "priority-group", [HIGH, LOW]).build()?;
+    let consumer = PriorityConsumer::new(config, PriorityPolicy::default())?;
+    let shutdown = CancellationToken::new();
+    let (handled_tx, mut handled_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let task = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            let result = consumer
+                .run_with_handler(
+                    |received: ConsumedMessage<KafkaMessage>| {
+                        let handled_tx = handled_tx.clone();
+                        async move {
+                            // Every message costs the same, so the flood alone takes several seconds.
+                            tokio::time::sleep(Duration::from_millis(5)).await;
+                            handled_tx
+                                .send((received.topic, received.message.data, now_ms()))
+                                .map_err(|e| e.to_string())
+                        }
+                    },
+                    shutdown,
+                )
+                .await;
+            (result, consumer.stats())
+        }
+    });
+
+    // Let the flood start flowing, then trickle in deletions.
+    let first = tokio::time::timeout(Duration::from_secs(60), handled_rx.recv()).await?;
+    assert_eq!(first.map(|(topic, ..)| topic).as_deref(), Some(LOW));
+    for _ in 0..TRICKLE {
+        let message = KafkaMessage::new("user".to_string(), Action::Delete, Some(now_ms().to_string()));
+        high.send(&message.user_id, &message).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let mut high_latencies = Vec::new();
+    let mut low_handled = 1;
+    while high_latencies.len() < TRICKLE {
+        let (topic, data, handled_at) = tokio::time::timeout(Duration::from_secs(30), handled_rx.recv())
+            .await?
+            .expect("consumer stopped");
+        if topic == HIGH {
+            high_latencies.push(handled_at - data.unwrap().parse::<u128>()?);
+        } else {
+            low_handled += 1;
+        }
+    }
+    shutdown.cancel();
+    let (result, stats) = task.await?;
+    result?;
+
+    // Deletions wait for at most a few low-priority messages, not for the backlog.
+    let worst = high_latencies.iter().max().copied().unwrap_or_default();
+    assert!(worst < 2000, "high-priority latency {worst}ms with the flood pending");
+    assert!(low_handled < FLOOD, "the flood drained before the deletions were handled");
+
+    // Meanwhile the flood kept moving, at least through the starvation guard.
+    assert!(low_handled > TRICKLE / 10);
+    assert_eq!(stats[0].topic, HIGH);
+    assert_eq!(stats[0].consumed, TRICKLE as u64);
+    assert!(stats[1].consumed >= low_handled as u64);
+    Ok(())
+}
@@ -1,10 +1,23 @@
 use kafka_client::{
-    config::{ConsumerConfig, ProducerConfig},
-    consumer::KafkaConsumer,
-    producer::KafkaProducer,
+    admin::KafkaAdmin,
+    config::{AssignmentMode, ConsumerConfig, Delivery, ProducerConfig, StartOffset},
+    consumer::{AssignedPartition, ConsumedMessage, KafkaConsumer},
+    error::KafkaError,
+    lag::LagProbe,
+    producer::{KafkaProducer, Partitioning, partition_for},
+    router::TopicRouter,
+    rpc::{KafkaRpcClient, KafkaRpcServer},
     schemas::{Action, KafkaMessage},
 };
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use testcontainers_modules::{kafka::Kafka, testcontainers::runners::AsyncRunner as _};
+use tokio_util::sync::CancellationToken;
+
+async fn create_topic(brokers: &str, name: &str, partitions: i32) -> anyhow::Result<()> {
+    KafkaAdmin::new(brokers)?.ensure_topic(name, partitions, 1, None).await?;
+    Ok(())
+}
 
 #[tokio::test]
 async fn test_producer_consumer_integration() -> anyhow::Result<()> {
@@ -13,18 +26,19 @@ async fn test_producer_consumer_integration() -> anyhow::Result<()> {
     let port = kafka.get_host_port_ipv4(9093).await?;
     let brokers = format!("{}:{}", host, port);
 
-    let producer_config = ProducerConfig::builder(&brokers, "integration-test")
-        .auto_create_topics(true)
-        .build()?;
+    create_topic(&brokers, "integration-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "integration-test").build()?;
     let producer = KafkaProducer::new(producer_config)?;
 
     let consumer_config = ConsumerConfig::builder(&brokers, "test-group", "integration-test").build()?;
     let consumer = KafkaConsumer::new(consumer_config)?;
 
     let test_message = KafkaMessage {
+        version: 0,
         user_id: "integration_user".to_string(),
         action: Action::Create,
         data: Some("integration data".to_string()),
+        metadata: None,
     };
 
     producer.send(&test_message.user_id, &test_message).await?;
@@ -36,3 +50,807 @@ async fn test_producer_consumer_integration() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_headers_round_trip() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "headers-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "headers-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "headers-group", "headers-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+
+    let test_message = KafkaMessage::new("header_user".to_string(), Action::Create, Some("with headers".to_string()));
+    let headers = HashMap::from([("request_id".to_string(), "req-42".to_string())]);
+
+    producer
+        .send_with_headers(&test_message.user_id, &test_message, &headers)
+        .await?;
+    let received = consumer.consume_message::<KafkaMessage>().await?;
+
+    assert_eq!(received.message.user_id, "header_user");
+    assert_eq!(received.headers.get("request_id").map(String::as_str), Some("req-42"));
+    assert!(received.offset >= 0);
+    assert!(received.timestamp.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unacked_message_is_redelivered() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "ack-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "ack-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    for data in ["first", "second"] {
+        let message = KafkaMessage::new("ack_user".to_string(), Action::Create, Some(data.to_string()));
+        producer.send(&message.user_id, &message).await?;
+    }
+
+    let consumer_config = || {
+        ConsumerConfig::builder(&brokers, "ack-group", "ack-test")
+            .delivery(Delivery::AtLeastOnce)
+            .build()
+    };
+
+    // Crash before acking: nothing is committed.
+    let consumer = KafkaConsumer::new(consumer_config()?)?;
+    let (received, ack) = consumer.consume_uncommitted::<KafkaMessage>().await?;
+    assert_eq!(received.message.data.as_deref(), Some("first"));
+    drop(ack);
+    consumer.commit()?;
+    drop(consumer);
+
+    // The replacement sees the same message again, acks it and moves on.
+    let consumer = KafkaConsumer::new(consumer_config()?)?;
+    let (received, ack) = consumer.consume_uncommitted::<KafkaMessage>().await?;
+    assert_eq!(received.message.data.as_deref(), Some("first"));
+    ack.ack()?;
+    consumer.commit()?;
+    drop(consumer);
+
+    let consumer = KafkaConsumer::new(consumer_config()?)?;
+    let (received, ack) = consumer.consume_uncommitted::<KafkaMessage>().await?;
+    assert_eq!(received.message.data.as_deref(), Some("second"));
+    ack.ack()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ImageUploaded {
+    filename: String,
+    size: u64,
+    tags: Vec<String>,
+}
+
+#[tokio::test]
+async fn test_typed_payload_round_trip() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "typed-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "typed-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "typed-group", "typed-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+
+    let data = ImageUploaded {
+        filename: "cat.png".to_string(),
+        size: 2048,
+        tags: vec!["cats".to_string()],
+    };
+    let message = KafkaMessage::new("typed_user".to_string(), Action::Create, Some(data.clone()));
+    producer.send(&message.user_id, &message).await?;
+
+    let received = consumer.consume::<KafkaMessage<ImageUploaded>>().await?;
+    assert_eq!(received, message);
+
+    // A payload that does not match the expected type reports where it came from.
+    producer.send("typed_user", &"not a message").await?;
+    let err = consumer.consume::<KafkaMessage<ImageUploaded>>().await.unwrap_err();
+    assert!(matches!(
+        err,
+        KafkaError::Deserialization { ref topic, payload_len: 15, .. } if topic == "typed-test"
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_send_batch_offsets_are_monotonic() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "batch-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "batch-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+
+    let messages: Vec<KafkaMessage> = (0..1000)
+        .map(|i| KafkaMessage::new(format!("user_{}", i % 10), Action::Create, Some(i.to_string())))
+        .collect();
+
+    let results = producer.send_batch(&messages).await?;
+    producer.flush(std::time::Duration::from_secs(5))?;
+    assert_eq!(results.len(), 1000);
+
+    let mut last_offsets: HashMap<i32, i64> = HashMap::new();
+    for result in results {
+        let (partition, offset) = result?;
+        if let Some(last) = last_offsets.insert(partition, offset) {
+            assert!(offset > last, "offset {offset} not after {last} on partition {partition}");
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_garbage_payload_is_dead_lettered() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "dlq-input", 1).await?;
+    create_topic(&brokers, "dlq-dead", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "dlq-input").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+
+    producer.send("garbage", &[1, 2, 3]).await?;
+    let valid = KafkaMessage::new("dlq_user".to_string(), Action::Create, Some("ok".to_string()));
+    producer.send(&valid.user_id, &valid).await?;
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "dlq-group", "dlq-input")
+        .dead_letter_topic("dlq-dead")
+        .build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+
+    let received = consumer.consume_or_dead_letter::<KafkaMessage>().await?;
+    assert_eq!(received.message, valid);
+    assert_eq!(consumer.dead_lettered_count(), 1);
+
+    let dlq_consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "dlq-reader", "dlq-dead").build()?)?;
+    let dead = dlq_consumer.consume_message::<serde_json::Value>().await?;
+    assert_eq!(dead.message, serde_json::json!([1, 2, 3]));
+    assert_eq!(dead.headers.get("dlq.source_topic").map(String::as_str), Some("dlq-input"));
+    assert!(dead.headers.get("dlq.error").is_some_and(|e| e.contains("deserialize")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_handler_stops_on_cancel() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "handler-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "handler-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    for i in 0..3 {
+        let message = KafkaMessage::new("handler_user".to_string(), Action::Create, Some(i.to_string()));
+        producer.send(&message.user_id, &message).await?;
+    }
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "handler-group", "handler-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let shutdown = CancellationToken::new();
+    let (handled_tx, mut handled_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let task = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_handler(
+                    |received: ConsumedMessage<KafkaMessage>| {
+                        let handled_tx = handled_tx.clone();
+                        async move { handled_tx.send(received.message.data).map_err(|e| e.to_string()) }
+                    },
+                    shutdown,
+                )
+                .await
+        }
+    });
+
+    for expected in ["0", "1", "2"] {
+        let handled = tokio::time::timeout(Duration::from_secs(30), handled_rx.recv()).await?;
+        assert_eq!(handled.flatten().as_deref(), Some(expected));
+    }
+
+    // The loop is now idle waiting for the next message; cancellation must still end it promptly.
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(2), task).await???;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_topic_router_dispatches_by_source_topic() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    for topic in ["router-images", "router-audit"] {
+        create_topic(&brokers, topic, 1).await?;
+        let producer_config = ProducerConfig::builder(&brokers, topic).build()?;
+        let producer = KafkaProducer::new(producer_config)?;
+        let message = KafkaMessage::new("router_user".to_string(), Action::Create, Some(topic.to_string()));
+        producer.send(&message.user_id, &message).await?;
+    }
+
+    let consumer_config =
+        ConsumerConfig::builder_with_topics(&brokers, "router-group", ["router-images", "router-audit"]).build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let (routed_tx, mut routed_rx) = tokio::sync::mpsc::unbounded_channel();
+    let route = |handler: &'static str| {
+        let routed_tx = routed_tx.clone();
+        move |received: ConsumedMessage<KafkaMessage>| {
+            let sent = routed_tx.send((handler, received.message.data));
+            async move { sent.map_err(|e| e.to_string()) }
+        }
+    };
+    let router = TopicRouter::new()
+        .route("router-images", route("images"))
+        .route("router-audit", route("audit"));
+
+    let shutdown = CancellationToken::new();
+    let task = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { consumer.run_with_handler(|msg| router.dispatch(msg), shutdown).await }
+    });
+
+    let mut routed = Vec::new();
+    for _ in 0..2 {
+        let received = tokio::time::timeout(Duration::from_secs(30), routed_rx.recv()).await?;
+        routed.extend(received);
+    }
+    routed.sort();
+    assert_eq!(
+        routed,
+        [
+            ("audit", Some("router-audit".to_string())),
+            ("images", Some("router-images".to_string()))
+        ]
+    );
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(2), task).await???;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_send_partitioning_strategies() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    const PARTITIONS: i32 = 3;
+    create_topic(&brokers, "partitioning-test", PARTITIONS).await?;
+
+    let producer_config = ProducerConfig::builder(&brokers, "partitioning-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    let message: KafkaMessage = KafkaMessage::new("partition_user".to_string(), Action::Create, None);
+
+    for key in ["user_1", "user_2", "user_3", "user_4"] {
+        let (partition, _) = producer.send(key, &message).await?;
+        assert_eq!(partition, partition_for(key, PARTITIONS)?, "key {key}");
+    }
+
+    for explicit in 0..PARTITIONS {
+        let (partition, _) = producer.send(Partitioning::Explicit(explicit), &message).await?;
+        assert_eq!(partition, explicit);
+    }
+
+    let (partition, offset) = producer.send(Partitioning::None, &message).await?;
+    assert!((0..PARTITIONS).contains(&partition));
+    assert!(offset >= 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_handler_skips_poison_message() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "poison-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "poison-test").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    for data in ["poison", "healthy"] {
+        let message = KafkaMessage::new("poison_user".to_string(), Action::Create, Some(data.to_string()));
+        producer.send(&message.user_id, &message).await?;
+    }
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "poison-group", "poison-test")
+        .delivery(Delivery::AtLeastOnce)
+        .max_retries(3)
+        .retry_backoff_ms(10, 50)
+        .build()?;
+    let consumer = Arc::new(KafkaConsumer::new(consumer_config)?);
+    let shutdown = CancellationToken::new();
+    let (attempts_tx, mut attempts_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let task = tokio::spawn({
+        let consumer = Arc::clone(&consumer);
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_handler(
+                    |received: ConsumedMessage<KafkaMessage>| {
+                        let data = received.message.data.unwrap_or_default();
+                        let sent = attempts_tx.send(data.clone());
+                        async move {
+                            sent.map_err(|e| e.to_string())?;
+                            if data == "poison" {
+                                Err("cannot handle poison".to_string())
+                            } else {
+                                Ok(())
+                            }
+                        }
+                    },
+                    shutdown,
+                )
+                .await
+        }
+    });
+
+    let mut attempts = Vec::new();
+    while attempts.last().map(String::as_str) != Some("healthy") {
+        let attempt = tokio::time::timeout(Duration::from_secs(30), attempts_rx.recv()).await?;
+        attempts.extend(attempt);
+    }
+    assert_eq!(attempts, ["poison", "poison", "poison", "healthy"]);
+    assert_eq!(consumer.skipped_total(), 1);
+    assert_eq!(consumer.dead_lettered_count(), 0);
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(2), task).await???;
+
+    // The skipped message was committed, so the group resumes after it rather than retrying it forever.
+    drop(consumer);
+    let marker = KafkaMessage::new("poison_user".to_string(), Action::Create, Some("marker".to_string()));
+    producer.send(&marker.user_id, &marker).await?;
+    let consumer_config = ConsumerConfig::builder(&brokers, "poison-group", "poison-test").build()?;
+    let resumed = KafkaConsumer::new(consumer_config)?;
+    let next: KafkaMessage = tokio::time::timeout(Duration::from_secs(30), resumed.consume()).await??;
+    assert_eq!(next.data.as_deref(), Some("marker"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_handler_dead_letters_malformed_payload() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "malformed-input", 1).await?;
+    create_topic(&brokers, "malformed-dead", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "malformed-input").build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    producer.send("garbage", &[1, 2, 3]).await?;
+    let valid = KafkaMessage::new("malformed_user".to_string(), Action::Create, Some("healthy".to_string()));
+    producer.send(&valid.user_id, &valid).await?;
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "malformed-group", "malformed-input")
+        .delivery(Delivery::AtLeastOnce)
+        .dead_letter_topic("malformed-dead")
+        .build()?;
+    let consumer = Arc::new(KafkaConsumer::new(consumer_config)?);
+    let shutdown = CancellationToken::new();
+    let (handled_tx, mut handled_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let task = tokio::spawn({
+        let consumer = Arc::clone(&consumer);
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_handler(
+                    |received: ConsumedMessage<KafkaMessage>| {
+                        let sent = handled_tx.send(received.message);
+                        async move { sent.map_err(|e| e.to_string()) }
+                    },
+                    shutdown,
+                )
+                .await
+        }
+    });
+
+    let handled = tokio::time::timeout(Duration::from_secs(30), handled_rx.recv()).await?;
+    assert_eq!(handled, Some(valid));
+    assert_eq!(consumer.dead_lettered_count(), 1);
+    assert_eq!(consumer.skipped_total(), 0);
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(2), task).await???;
+
+    let dlq_consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "malformed-reader", "malformed-dead").build()?)?;
+    let dead = tokio::time::timeout(Duration::from_secs(30), dlq_consumer.consume_message::<serde_json::Value>()).await??;
+    assert_eq!(dead.message, serde_json::json!([1, 2, 3]));
+    assert_eq!(
+        dead.headers.get("dlq.source_topic").map(String::as_str),
+        Some("malformed-input")
+    );
+    assert!(dead.headers.get("dlq.error").is_some_and(|e| e.contains("deserialize")));
+
+    Ok(())
+}
+
+/// Polls `read` until `done` holds, for up to a few statistics intervals.
+async fn wait_for_stats<S>(read: impl Fn() -> Option<S>, done: impl Fn(&S) -> bool) -> Option<S> {
+    for _ in 0..40 {
+        if let Some(stats) = read().filter(&done) {
+            return Some(stats);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    None
+}
+
+#[tokio::test]
+async fn test_statistics_track_traffic() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "stats-test", 2).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "stats-test")
+        .statistics_interval_ms(200)
+        .build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    let consumer_config = ConsumerConfig::builder(&brokers, "stats-group", "stats-test")
+        .statistics_interval_ms(200)
+        .build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+
+    for i in 0..10 {
+        let message = KafkaMessage::new(format!("user-{i}"), Action::Create, Some("payload".to_string()));
+        producer.send(&message.user_id, &message).await?;
+    }
+
+    let stats = wait_for_stats(|| producer.stats(), |s| s.txmsgs >= 10).await;
+    let stats = stats.expect("producer statistics should report the sent messages");
+    assert_eq!(stats.msg_cnt, 0);
+    assert!(!stats.brokers.is_empty());
+
+    consumer.consume::<KafkaMessage>().await?;
+    consumer.commit()?;
+    let stats = wait_for_stats(|| consumer.stats(), |s| !s.partitions.is_empty()).await;
+    let stats = stats.expect("consumer statistics should report lag once an offset is committed");
+    assert!(stats.partitions.iter().all(|p| p.topic == "stats-test"));
+    assert!(stats.rxmsgs >= 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_committed_consumer_skips_aborted_transactions() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "transactions-test", 1).await?;
+    let plain = KafkaProducer::new(ProducerConfig::builder(&brokers, "transactions-test").build()?)?;
+    assert!(matches!(plain.begin_transaction(), Err(KafkaError::NotTransactional)));
+
+    let producer_config = ProducerConfig::builder(&brokers, "transactions-test")
+        .transactional_id("transactions-test-0")
+        .build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    producer.init_transactions(Duration::from_secs(30)).await?;
+
+    let message = |data: &str| KafkaMessage::new("tx_user".to_string(), Action::Create, Some(data.to_string()));
+
+    producer
+        .with_transaction(|tx| async move {
+            tx.send("tx_user", &message("committed")).await?;
+            Ok(())
+        })
+        .await?;
+
+    producer.begin_transaction()?;
+    producer.send_in_transaction("tx_user", &message("aborted")).await?;
+    producer.abort_transaction(Duration::from_secs(30)).await?;
+
+    let failed = producer
+        .with_transaction(|tx| async move {
+            tx.send("tx_user", &message("rolled back")).await?;
+            Err::<(), _>(KafkaError::InvalidConfig("export failed".into()))
+        })
+        .await;
+    assert!(matches!(failed, Err(KafkaError::InvalidConfig(_))));
+
+    producer
+        .with_transaction(|tx| async move {
+            tx.send("tx_user", &message("last")).await?;
+            Ok(())
+        })
+        .await?;
+
+    // librdkafka consumers default to isolation.level=read_committed.
+    let consumer_config = ConsumerConfig::builder(&brokers, "transactions-group", "transactions-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let received = consumer.consume::<KafkaMessage>().await?;
+        seen.push(received.data.unwrap_or_default());
+    }
+    assert_eq!(seen, ["committed", "last"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_seek_replays_consumed_messages() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "replay-test", 1).await?;
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, "replay-test").build()?)?;
+    let messages: Vec<KafkaMessage> = (0..10)
+        .map(|i| KafkaMessage::new("replay_user".to_string(), Action::Create, Some(i.to_string())))
+        .collect();
+    for result in producer.send_batch(&messages).await? {
+        result?;
+    }
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "replay-group", "replay-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    assert!(matches!(consumer.seek_to_beginning().await, Err(KafkaError::NoAssignment)));
+
+    let mut first = Vec::new();
+    for _ in 0..10 {
+        first.push(consumer.consume_message::<KafkaMessage>().await?.message.data);
+    }
+    assert_eq!(
+        consumer.current_assignment()?,
+        [AssignedPartition {
+            topic: "replay-test".into(),
+            partition: 0,
+            offset: Some(10),
+        }]
+    );
+
+    consumer.seek_to_beginning().await?;
+    let mut replayed = Vec::new();
+    for _ in 0..10 {
+        replayed.push(consumer.consume_message::<KafkaMessage>().await?.message.data);
+    }
+    assert_eq!(replayed, first);
+
+    consumer.seek("replay-test", 0, 5).await?;
+    assert_eq!(consumer.consume_message::<KafkaMessage>().await?.offset, 5);
+    assert!(matches!(
+        consumer.seek("replay-test", 3, 0).await,
+        Err(KafkaError::NotAssigned { partition: 3, .. })
+    ));
+
+    consumer.seek_to_timestamp(chrono::DateTime::UNIX_EPOCH).await?;
+    assert_eq!(consumer.consume_message::<KafkaMessage>().await?.offset, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_manual_assignment_tails_without_touching_group() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "audit-test", 2).await?;
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, "audit-test").build()?)?;
+    for i in 0..6 {
+        let message = KafkaMessage::new(format!("user_{i}"), Action::Create, Some(i.to_string()));
+        producer.send(Partitioning::Explicit(i % 2), &message).await?;
+    }
+
+    let group_config = ConsumerConfig::builder(&brokers, "audit-prod-group", "audit-test")
+        .delivery(Delivery::AtLeastOnce)
+        .build()?;
+    let group = KafkaConsumer::new(group_config)?;
+    let manual_config = ConsumerConfig::builder(&brokers, "", "audit-test")
+        .assignment(AssignmentMode::Manual(vec![
+            (0, StartOffset::Beginning),
+            (1, StartOffset::Beginning),
+        ]))
+        .build()?;
+    let manual = KafkaConsumer::new(manual_config)?;
+    assert_eq!(manual.current_assignment()?.len(), 2);
+
+    // The group acks only half of the messages while the manual consumer reads all of them.
+    let (tailed, grouped) = tokio::join!(
+        async {
+            let mut seen = Vec::new();
+            for _ in 0..6 {
+                seen.push(manual.consume_message::<KafkaMessage>().await?.message.user_id);
+            }
+            anyhow::Ok(seen)
+        },
+        async {
+            for _ in 0..3 {
+                let (_, ack) = group.consume_uncommitted::<KafkaMessage>().await?;
+                ack.ack()?;
+            }
+            group.commit()?;
+            anyhow::Ok(())
+        }
+    );
+    let mut tailed = tailed?;
+    grouped?;
+    tailed.sort();
+    assert_eq!(tailed, (0..6).map(|i| format!("user_{i}")).collect::<Vec<_>>());
+
+    manual.commit()?;
+    assert_eq!(
+        LagProbe::new(&brokers, "audit-prod-group", "audit-test")?.total_lag().await?,
+        3
+    );
+    assert_eq!(
+        LagProbe::new(&brokers, "audit-test-manual", "audit-test")?
+            .total_lag()
+            .await?,
+        6
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_paused_consumer_accumulates_lag() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "pause-test", 1).await?;
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, "pause-test").build()?)?;
+    let message = |i: i32| KafkaMessage::new("pause_user".to_string(), Action::Create, Some(i.to_string()));
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "pause-group", "pause-test").build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    producer.send("pause_user", &message(0)).await?;
+    consumer.consume::<KafkaMessage>().await?;
+    consumer.commit()?;
+    assert_eq!(consumer.lag()?, HashMap::from([(("pause-test".to_string(), 0), 0)]));
+
+    consumer.pause()?;
+    assert!(consumer.is_paused());
+    for i in 1..=5 {
+        producer.send("pause_user", &message(i)).await?;
+    }
+    assert_eq!(consumer.lag()?[&("pause-test".to_string(), 0)], 5);
+    let idle = tokio::time::timeout(Duration::from_secs(3), consumer.consume::<KafkaMessage>()).await;
+    assert!(idle.is_err(), "paused consumer delivered a message");
+
+    consumer.resume()?;
+    let mut drained = Vec::new();
+    for _ in 0..5 {
+        drained.push(consumer.consume::<KafkaMessage>().await?.data.unwrap_or_default());
+    }
+    assert_eq!(drained, ["1", "2", "3", "4", "5"]);
+    consumer.commit()?;
+    assert_eq!(consumer.lag()?[&("pause-test".to_string(), 0)], 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rpc_call_round_trip_and_timeout() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "rpc-requests", 2).await?;
+    create_topic(&brokers, "rpc-replies", 2).await?;
+    let client = KafkaRpcClient::new(
+        ProducerConfig::builder(&brokers, "rpc-requests").build()?,
+        ConsumerConfig::builder(&brokers, "rpc-client-1", "rpc-replies").build()?,
+    )?;
+
+    let request = |user: &str| KafkaMessage::new(user.to_string(), Action::Update, Some(format!("ping from {user}")));
+    let unanswered = client.call(&request("early_user"), Duration::from_secs(2)).await;
+    assert!(
+        matches!(unanswered, Err(KafkaError::Timeout(_))),
+        "unexpected result: {unanswered:?}"
+    );
+    assert_eq!(client.pending(), 0);
+
+    let server = KafkaRpcServer::new(
+        ConsumerConfig::builder(&brokers, "rpc-server", "rpc-requests").build()?,
+        ProducerConfig::builder(&brokers, "rpc-replies").build()?,
+    )?;
+    let shutdown = CancellationToken::new();
+    let serving = server.serve(
+        |request: KafkaMessage| async move {
+            let data = request.data.map(|d| d.to_uppercase());
+            Ok::<_, String>(KafkaMessage::new(request.user_id, Action::Create, data))
+        },
+        shutdown.clone(),
+    );
+
+    let (request_a, request_b) = (request("user_a"), request("user_b"));
+    let calls = async {
+        let (a, b) = tokio::join!(
+            client.call(&request_a, Duration::from_secs(30)),
+            client.call(&request_b, Duration::from_secs(30)),
+        );
+        shutdown.cancel();
+        anyhow::Ok((a?, b?))
+    };
+    let (served, replies) = tokio::join!(serving, calls);
+    served?;
+    let (a, b) = replies?;
+
+    assert_eq!((a.user_id.as_str(), a.data.as_deref()), ("user_a", Some("PING FROM USER_A")));
+    assert_eq!((b.user_id.as_str(), b.data.as_deref()), ("user_b", Some("PING FROM USER_B")));
+    assert_eq!(client.pending(), 0);
+    client.close().await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_producer_health_follows_broker_availability() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    create_topic(&brokers, "health-test", 1).await?;
+    let producer_config = ProducerConfig::builder(&brokers, "health-test")
+        .message_timeout_ms(2000)
+        .build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+    let message = KafkaMessage::new("health_user".to_string(), Action::Create, Some("payload".to_string()));
+    producer.send("health_user", &message).await?;
+    assert!(producer.is_healthy());
+    assert!(producer.check_health(Duration::from_secs(2)).await);
+
+    kafka.pause().await?;
+    assert!(producer.send("health_user", &message).await.is_err());
+    assert!(!producer.is_healthy());
+    assert!(!producer.check_health(Duration::from_secs(1)).await);
+
+    kafka.unpause().await?;
+    let mut recovered = false;
+    for _ in 0..20 {
+        if producer.check_health(Duration::from_secs(2)).await {
+            recovered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(recovered, "producer did not recover after the broker came back");
+    producer.send("health_user", &message).await?;
+    assert!(producer.is_healthy());
+
+    Ok(())
+}
@@ -1,12 +1,15 @@
-use kafka_client::schemas::{Action, KafkaMessage};
+use kafka_client::schemas::{Action, CURRENT_VERSION, KafkaMessage, METADATA_CONTENT_TYPE, METADATA_OBJECT_KEY, METADATA_SIZE};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[test]
 fn test_kafka_message_serialization() -> anyhow::Result<()> {
     let msg = KafkaMessage {
+        version: 0,
         user_id: "user123".to_owned(),
         action: Action::Create,
         data: Some("test data".to_owned()),
+        metadata: None,
     };
 
     let json = serde_json::to_string(&msg)?;
@@ -47,9 +50,11 @@ fn test_kafka_message_with_generic_data() -> anyhow::Result<()> {
     };
 
     let msg = KafkaMessage {
+        version: 0,
         user_id: "user789".to_string(),
         action: Action::Delete,
         data: Some(custom_data),
+        metadata: None,
     };
 
     let json = serde_json::to_string(&msg)?;
@@ -65,9 +70,11 @@ fn test_kafka_message_with_generic_data() -> anyhow::Result<()> {
 #[test]
 fn test_kafka_message_without_data() -> anyhow::Result<()> {
     let msg: KafkaMessage<String> = KafkaMessage {
+        version: 0,
         user_id: "user000".to_string(),
         action: Action::Delete,
         data: None,
+        metadata: None,
     };
 
     let json = serde_json::to_string(&msg)?;
@@ -82,6 +89,80 @@ fn test_action_serialization() -> anyhow::Result<()> {
     assert_eq!(serde_json::to_string(&Action::Create)?, r#""create""#);
     assert_eq!(serde_json::to_string(&Action::Update)?, r#""update""#);
     assert_eq!(serde_json::to_string(&Action::Delete)?, r#""delete""#);
+    assert_eq!(serde_json::to_string(&Action::Export)?, r#""export""#);
+    assert_eq!(serde_json::to_string(&Action::Purge)?, r#""purge""#);
 
     Ok(())
 }
+
+/// The envelope as consumers deserialized it before versioning, to check new payloads against old readers.
+#[derive(Debug, Deserialize)]
+struct UnversionedMessage {
+    user_id: String,
+    action: Action,
+    data: Option<String>,
+}
+
+fn image_metadata() -> HashMap<String, String> {
+    HashMap::from([
+        (METADATA_OBJECT_KEY.to_owned(), "0193a0e4-7f41".to_owned()),
+        (METADATA_CONTENT_TYPE.to_owned(), "image/png".to_owned()),
+        (METADATA_SIZE.to_owned(), "2048".to_owned()),
+    ])
+}
+
+#[test]
+fn test_unversioned_payload_parses_as_version_0() -> anyhow::Result<()> {
+    let json = r#"{"user_id": "user456", "action": "create", "data": "key"}"#;
+
+    let msg = serde_json::from_str::<KafkaMessage>(json)?;
+    assert_eq!(msg, KafkaMessage::new("user456".into(), Action::Create, Some("key".into())));
+    assert_eq!(msg.version, 0);
+    assert_eq!(msg.metadata, None);
+    Ok(())
+}
+
+#[test]
+fn test_v1_payload_round_trips() -> anyhow::Result<()> {
+    let msg = KafkaMessage::v1("user456".into(), Action::Create, Some("key".to_string()), image_metadata());
+
+    let json = serde_json::to_string(&msg)?;
+    let parsed = serde_json::from_str::<KafkaMessage>(&json)?;
+    assert_eq!(parsed, msg);
+    assert_eq!(parsed.version, CURRENT_VERSION);
+    assert_eq!(parsed.metadata(METADATA_CONTENT_TYPE), Some("image/png"));
+    assert_eq!(parsed.metadata(METADATA_SIZE), Some("2048"));
+    Ok(())
+}
+
+#[test]
+fn test_v1_payload_parses_with_unversioned_reader() -> anyhow::Result<()> {
+    let msg = KafkaMessage::v1("user456".into(), Action::Delete, Some("key".to_string()), image_metadata());
+
+    let old = serde_json::from_str::<UnversionedMessage>(&serde_json::to_string(&msg)?)?;
+    assert_eq!(old.user_id, "user456");
+    assert_eq!(old.action, Action::Delete);
+    assert_eq!(old.data.as_deref(), Some("key"));
+    Ok(())
+}
+
+#[test]
+fn test_version_0_payload_parses_with_unversioned_reader() -> anyhow::Result<()> {
+    let msg = KafkaMessage::new("user456".into(), Action::Update, None::<String>);
+
+    let json = serde_json::to_string(&msg)?;
+    assert!(!json.contains("metadata"));
+    let old = serde_json::from_str::<UnversionedMessage>(&json)?;
+    assert_eq!(old.action, Action::Update);
+    assert_eq!(old.data, None);
+    Ok(())
+}
+
+#[test]
+fn test_with_metadata_upgrades_version() {
+    let msg = KafkaMessage::new("user456".into(), Action::Export, None::<String>).with_metadata(METADATA_SIZE, "10");
+
+    assert_eq!(msg.version, CURRENT_VERSION);
+    assert_eq!(msg.metadata(METADATA_SIZE), Some("10"));
+    assert_eq!(msg.metadata(METADATA_OBJECT_KEY), None);
+}
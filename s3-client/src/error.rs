@@ -1,11 +1,12 @@
 use aws_sdk_s3::{
-    error::{BuildError, SdkError},
+    error::{BuildError, ProvideErrorMetadata, SdkError},
     operation::{
         abort_multipart_upload::AbortMultipartUploadError, complete_multipart_upload::CompleteMultipartUploadError,
         copy_object::CopyObjectError, create_bucket::CreateBucketError, create_multipart_upload::CreateMultipartUploadError,
         delete_bucket::DeleteBucketError, delete_object::DeleteObjectError, delete_objects::DeleteObjectsError,
-        get_object::GetObjectError, head_object::HeadObjectError, list_objects_v2::ListObjectsV2Error,
-        put_object::PutObjectError, upload_part::UploadPartError,
+        get_object::GetObjectError, get_object_legal_hold::GetObjectLegalHoldError, head_bucket::HeadBucketError,
+        head_object::HeadObjectError, list_objects_v2::ListObjectsV2Error, put_object::PutObjectError,
+        put_object_legal_hold::PutObjectLegalHoldError, upload_part::UploadPartError,
     },
     primitives::ByteStreamError,
 };
@@ -37,10 +38,20 @@ pub enum S3Error {
     DeleteObjectError(#[from] SdkError<DeleteObjectError>),
     #[error("Failed to delete multiple objects: {0}")]
     DeleteObjectsError(#[from] SdkError<DeleteObjectsError>),
+    #[error("Bucket is not reachable: {0}")]
+    HeadBucketError(#[from] SdkError<HeadBucketError>),
     #[error("Failed to create bucket: {0}")]
     CreateBucketError(#[from] SdkError<CreateBucketError>),
     #[error("Failed to delete bucket: {0}")]
     DeleteBucketError(#[from] SdkError<DeleteBucketError>),
+    #[error("Failed to get object legal hold: {0}")]
+    GetLegalHoldError(#[from] SdkError<GetObjectLegalHoldError>),
+    #[error("Failed to set object legal hold: {0}")]
+    PutLegalHoldError(#[from] SdkError<PutObjectLegalHoldError>),
+    #[error("Object {0} is under legal hold")]
+    ObjectOnHold(String),
+    #[error("S3 refused to delete {key}: {message}")]
+    DeleteRejected { key: String, message: String },
     #[error("Bucket is not empty - objects still remain inside")]
     BucketNotEmpty,
     #[error("Missing ETag in upload_part response")]
@@ -57,4 +68,19 @@ pub enum S3Error {
     TokioJoin(String),
     #[error("Configuration error: {0}")]
     ConfigError(String),
+    #[error("MinIO admin API error: {0}")]
+    MinioAdmin(String),
+}
+
+impl S3Error {
+    /// The object does not exist, as opposed to S3 failing to answer.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Self::GetObjectError(e) => e.as_service_error().is_some_and(|e| e.is_no_such_key()),
+            Self::HeaderObjectError(e) => e.as_service_error().is_some_and(|e| e.is_not_found()),
+            // Deleting a missing key succeeds; only a version deleted since it was looked up fails.
+            Self::DeleteObjectError(e) => matches!(e.code(), Some("NoSuchKey" | "NoSuchVersion")),
+            _ => false,
+        }
+    }
 }
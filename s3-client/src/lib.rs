@@ -1,4 +1,9 @@
 pub mod error;
+#[cfg(feature = "minio-admin")]
+pub mod minio_admin;
+pub mod pacing;
+pub mod replication;
+pub mod sink;
 
 use aws_config::{Region, retry::RetryConfig, timeout::TimeoutConfig};
 use aws_sdk_s3::{
@@ -7,22 +12,68 @@ use aws_sdk_s3::{
     error::ProvideErrorMetadata,
     operation::complete_multipart_upload::CompleteMultipartUploadOutput,
     primitives::ByteStream,
-    types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier},
+    types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier, ObjectLockLegalHold, ObjectLockLegalHoldStatus},
 };
+use bytes::Bytes;
 use error::{S3Error, S3Result};
-use std::{borrow::Cow, path::Path, time::Duration};
+use pacing::{DEFAULT_MAX_CONCURRENCY, Pacer, PacingState};
+use std::{borrow::Cow, collections::HashMap, path::Path, sync::Arc, time::Duration};
 use tokio::{fs::File, io::AsyncReadExt as _};
 
 const DEFAULT_CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB
+/// Maximum number of keys S3 accepts in one DeleteObjects request.
+const DELETE_OBJECTS_LIMIT: usize = 1000;
 
+#[derive(Default)]
 pub struct S3Object {
     pub data: Vec<u8>,
     pub content_type: Option<String>,
+    pub e_tag: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub last_modified: Option<i64>,
+}
+
+/// What `HeadObject` reports about an existing object.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectHead {
+    /// User metadata (`x-amz-meta-*`).
+    pub metadata: HashMap<String, String>,
+    /// Current version, on versioned (including object lock enabled) buckets.
+    pub version_id: Option<String>,
+    pub legal_hold: bool,
+    pub size: i64,
+    pub e_tag: Option<String>,
+    pub content_type: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub last_modified: Option<i64>,
+}
+
+/// What `ListObjectsV2` reports about each key.
+#[derive(Debug, Clone)]
+pub struct ObjectSummary {
+    pub key: String,
+    pub size: i64,
+    /// Milliseconds since the Unix epoch.
+    pub last_modified: Option<i64>,
+}
+
+/// One page of [`S3::list_objects_page`], in key order.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectPage {
+    pub objects: Vec<ObjectSummary>,
+    /// More keys follow the last one in `objects`.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct S3Metrics {
+    pub pacing: PacingState,
 }
 
 pub struct S3 {
     client: Client,
     bucket: &'static str,
+    pacer: Arc<Pacer>,
 }
 
 impl S3 {
@@ -66,21 +117,53 @@ impl S3 {
 
         tracing::info!(bucket = %bucket, "S3 client initialized");
 
-        Self { client, bucket }
+        Self {
+            client,
+            bucket,
+            pacer: Arc::new(Pacer::new(DEFAULT_MAX_CONCURRENCY)),
+        }
     }
 
     pub fn bucket(&self) -> &str {
         self.bucket
     }
 
+    pub fn metrics(&self) -> S3Metrics {
+        S3Metrics {
+            pacing: self.pacer.state(),
+        }
+    }
+
     pub async fn create_bucket(&self) -> S3Result<()> {
         self.client.create_bucket().bucket(self.bucket).send().await?;
         tracing::info!(bucket = %self.bucket, "Created bucket");
         Ok(())
     }
 
+    /// Creates the bucket with object lock enabled, which also turns on versioning; required for legal holds.
+    pub async fn create_bucket_with_object_lock(&self) -> S3Result<()> {
+        self.client
+            .create_bucket()
+            .bucket(self.bucket)
+            .object_lock_enabled_for_bucket(true)
+            .send()
+            .await?;
+        tracing::info!(bucket = %self.bucket, "Created bucket with object lock");
+        Ok(())
+    }
+
+    /// `HeadBucket` round trip: succeeds when the bucket exists and the credentials can reach it.
+    pub async fn check_bucket(&self) -> S3Result<()> {
+        self.client.head_bucket().bucket(self.bucket).send().await?;
+        Ok(())
+    }
+
     pub async fn object_exists(&self, key: impl Into<String>) -> S3Result<bool> {
-        let result = self.client.head_object().bucket(self.bucket).key(key).send().await;
+        let key = key.into();
+        let result = self
+            .pacer
+            .run(|| self.client.head_object().bucket(self.bucket).key(&key).send())
+            .await;
 
         match result {
             Ok(_) => Ok(true),
@@ -94,6 +177,79 @@ impl S3 {
         }
     }
 
+    /// User metadata (`x-amz-meta-*`) of `key`, or `None` if the object does not exist.
+    pub async fn object_metadata(&self, key: impl Into<String>) -> S3Result<Option<HashMap<String, String>>> {
+        Ok(self.head(key).await?.map(|head| head.metadata))
+    }
+
+    /// Metadata, current version and legal hold status of `key`, or `None` if the object does not exist.
+    pub async fn head(&self, key: impl Into<String>) -> S3Result<Option<ObjectHead>> {
+        let key = key.into();
+        let result = self
+            .pacer
+            .run(|| self.client.head_object().bucket(self.bucket).key(&key).send())
+            .await;
+
+        match result {
+            Ok(head) => Ok(Some(ObjectHead {
+                legal_hold: head.object_lock_legal_hold_status == Some(ObjectLockLegalHoldStatus::On),
+                size: head.content_length.unwrap_or_default(),
+                e_tag: head.e_tag,
+                content_type: head.content_type,
+                last_modified: head.last_modified.and_then(|t| t.to_millis().ok()),
+                version_id: head.version_id,
+                metadata: head.metadata.unwrap_or_default(),
+            })),
+            Err(e) if e.as_service_error().and_then(ProvideErrorMetadata::code) == Some("NotFound") => Ok(None),
+            Err(e) => Err(S3Error::HeaderObjectError(e)),
+        }
+    }
+
+    /// Places or releases a legal hold on the current version of `key`. The bucket must have object lock enabled.
+    pub async fn set_legal_hold(&self, key: impl Into<String>, on: bool) -> S3Result<()> {
+        let key = key.into();
+        let status = if on {
+            ObjectLockLegalHoldStatus::On
+        } else {
+            ObjectLockLegalHoldStatus::Off
+        };
+        let hold = ObjectLockLegalHold::builder().status(status).build();
+        self.pacer
+            .run(|| {
+                self.client
+                    .put_object_legal_hold()
+                    .bucket(self.bucket)
+                    .key(&key)
+                    .legal_hold(hold.clone())
+                    .send()
+            })
+            .await?;
+        tracing::info!(key = %key, on, "Set object legal hold");
+        Ok(())
+    }
+
+    /// Whether the current version of `key` is under legal hold; `false` on buckets without object lock.
+    pub async fn get_legal_hold(&self, key: impl Into<String>) -> S3Result<bool> {
+        let key = key.into();
+        let result = self
+            .pacer
+            .run(|| self.client.get_object_legal_hold().bucket(self.bucket).key(&key).send())
+            .await;
+
+        match result {
+            Ok(output) => Ok(output.legal_hold().and_then(|h| h.status()) == Some(&ObjectLockLegalHoldStatus::On)),
+            Err(e)
+                if matches!(
+                    e.as_service_error().and_then(ProvideErrorMetadata::code),
+                    Some("NoSuchObjectLockConfiguration" | "ObjectLockConfigurationNotFoundError" | "InvalidRequest")
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(S3Error::GetLegalHoldError(e)),
+        }
+    }
+
     pub async fn copy_object(
         &self,
         destination_bucket: impl Into<String>,
@@ -106,12 +262,15 @@ impl S3 {
         let source_key = format!("{}/{}", self.bucket, source_object);
 
         let response = self
-            .client
-            .copy_object()
-            .copy_source(&source_key)
-            .bucket(&destination_bucket)
-            .key(&destination_object)
-            .send()
+            .pacer
+            .run(|| {
+                self.client
+                    .copy_object()
+                    .copy_source(&source_key)
+                    .bucket(&destination_bucket)
+                    .key(&destination_object)
+                    .send()
+            })
             .await?;
 
         let e_tag = response.copy_object_result().and_then(|r| r.e_tag()).unwrap_or("missing");
@@ -131,20 +290,35 @@ impl S3 {
         key: impl Into<String>,
         body: impl Into<ByteStream>,
         content_type: impl Into<String>,
+    ) -> S3Result<()> {
+        self.upload_with_metadata(key, body, content_type, HashMap::new()).await
+    }
+
+    /// Like [`upload`](Self::upload), storing `metadata` as `x-amz-meta-*` headers on the object.
+    pub async fn upload_with_metadata(
+        &self,
+        key: impl Into<String>,
+        body: impl Into<ByteStream>,
+        content_type: impl Into<String>,
+        metadata: HashMap<String, String>,
     ) -> S3Result<()> {
         let key = key.into();
         let content_type = content_type.into();
         let data = body.into().collect().await.map_err(S3Error::from)?.into_bytes();
         let size = data.len();
 
-        self.client
-            .put_object()
-            .bucket(self.bucket)
-            .content_type(&content_type)
-            .content_length(size as i64)
-            .key(&key)
-            .body(ByteStream::from(data))
-            .send()
+        self.pacer
+            .run(|| {
+                self.client
+                    .put_object()
+                    .bucket(self.bucket)
+                    .content_type(&content_type)
+                    .content_length(size as i64)
+                    .key(&key)
+                    .set_metadata((!metadata.is_empty()).then(|| metadata.clone()))
+                    .body(ByteStream::from(data.clone()))
+                    .send()
+            })
             .await?;
 
         tracing::info!("Uploaded file: key={key}, size={size} bytes, content_type={content_type}");
@@ -153,21 +327,82 @@ impl S3 {
 
     pub async fn download(&self, key: impl Into<String>) -> S3Result<S3Object> {
         let key = key.into();
-        let object = self.client.get_object().bucket(self.bucket).key(&key).send().await?;
+        let object = self
+            .pacer
+            .run(|| self.client.get_object().bucket(self.bucket).key(&key).send())
+            .await?;
         let content_type = object.content_type().map(String::from);
+        let e_tag = object.e_tag().map(String::from);
+        let last_modified = object.last_modified().and_then(|t| t.to_millis().ok());
         let data = object.body.collect().await.map_err(S3Error::from)?.to_vec();
         tracing::info!("File downloaded: {}, size: {} bytes", key, data.len());
-        Ok(S3Object { data, content_type })
+        Ok(S3Object {
+            data,
+            content_type,
+            e_tag,
+            last_modified,
+        })
+    }
+
+    /// Bytes `start..=end` of `key`, as for an HTTP `Range: bytes=start-end`. Ranges past the end of the object are
+    /// cut short by S3; a `start` beyond it fails.
+    pub async fn download_range(&self, key: impl Into<String>, start: u64, end: u64) -> S3Result<S3Object> {
+        let key = key.into();
+        let range = format!("bytes={start}-{end}");
+        let object = self
+            .pacer
+            .run(|| self.client.get_object().bucket(self.bucket).key(&key).range(&range).send())
+            .await?;
+        let content_type = object.content_type().map(String::from);
+        let e_tag = object.e_tag().map(String::from);
+        let last_modified = object.last_modified().and_then(|t| t.to_millis().ok());
+        let data = object.body.collect().await.map_err(S3Error::from)?.to_vec();
+        tracing::debug!("File range downloaded: {key}, {range}, {} bytes", data.len());
+        Ok(S3Object {
+            data,
+            content_type,
+            e_tag,
+            last_modified,
+        })
     }
 
+    /// Deletes the current version of `key`, so versioned buckets do not keep it behind a delete marker.
+    /// Fails with [`S3Error::ObjectOnHold`] while the object is under legal hold.
     pub async fn delete_object(&self, key: impl Into<String>) -> S3Result<()> {
         let key = key.into();
-        self.client.delete_object().bucket(self.bucket).key(&key).send().await?;
+        let version_id = match self.head(&key).await? {
+            Some(head) if head.legal_hold => return Err(S3Error::ObjectOnHold(key)),
+            head => head.and_then(|h| h.version_id),
+        };
+
+        let result = self
+            .pacer
+            .run(|| {
+                self.client
+                    .delete_object()
+                    .bucket(self.bucket)
+                    .key(&key)
+                    .set_version_id(version_id.clone())
+                    .send()
+            })
+            .await;
+        if let Err(e) = result {
+            // The hold may have been placed after the HEAD above.
+            if is_access_denied(&e) && self.get_legal_hold(&key).await.unwrap_or(false) {
+                return Err(S3Error::ObjectOnHold(key));
+            }
+            return Err(e.into());
+        }
         tracing::info!("File deleted with key: {key}");
         Ok(())
     }
 
     pub async fn list_objects(&self, max_keys: Option<i32>) -> S3Result<Vec<String>> {
+        self.list_objects_with_prefix("", max_keys).await
+    }
+
+    /// Like [`list_objects`](Self::list_objects), limited to keys starting with `prefix`.
+    pub async fn list_objects_with_prefix(&self, prefix: &str, max_keys: Option<i32>) -> S3Result<Vec<String>> {
         let mut list_objects = Vec::with_capacity(max_keys.unwrap_or(10) as usize);
         let max_keys = max_keys.unwrap_or(1000);
 
@@ -175,6 +410,7 @@ impl S3 {
             .client
             .list_objects_v2()
             .bucket(self.bucket)
+            .prefix(prefix)
             .max_keys(max_keys)
             .into_paginator()
             .send();
@@ -196,22 +432,97 @@ impl S3 {
         Ok(list_objects)
     }
 
+    /// Up to `max_keys` keys starting with `prefix` that sort after `start_after`, with their size and
+    /// modification time. Pass the last key of a page as `start_after` to get the next one.
+    pub async fn list_objects_page(&self, prefix: &str, start_after: Option<&str>, max_keys: i32) -> S3Result<ObjectPage> {
+        let output = self
+            .pacer
+            .run(|| {
+                self.client
+                    .list_objects_v2()
+                    .bucket(self.bucket)
+                    .prefix(prefix)
+                    .set_start_after(start_after.map(ToOwned::to_owned))
+                    .max_keys(max_keys)
+                    .send()
+            })
+            .await
+            .map_err(|err| {
+                tracing::error!(error = ?err, "Failed to list objects");
+                S3Error::ListObjectError(err)
+            })?;
+
+        let objects = output
+            .contents()
+            .iter()
+            .filter_map(|object| {
+                Some(ObjectSummary {
+                    key: object.key()?.to_owned(),
+                    size: object.size().unwrap_or_default(),
+                    last_modified: object.last_modified().and_then(|t| t.to_millis().ok()),
+                })
+            })
+            .collect();
+        Ok(ObjectPage {
+            objects,
+            truncated: output.is_truncated().unwrap_or_default(),
+        })
+    }
+
+    /// Returns how many of `keys` were deleted.
     pub async fn delete_objects(&self, keys: Vec<String>) -> S3Result<usize> {
-        if keys.is_empty() {
-            return Ok(0);
-        }
+        let failed = self.delete_objects_reporting(&keys).await?;
+        Ok(keys.len() - failed.len())
+    }
 
-        let mut delete_object_ids = Vec::new();
-        for key in &keys {
-            let obj_id = ObjectIdentifier::builder().key(key).build()?;
-            delete_object_ids.push(obj_id);
-        }
+    /// Deletes `keys` in chunks of 1000 and returns the keys S3 refused to delete, with the reason.
+    /// Deleting a missing key counts as success. On versioned buckets this only adds delete markers, which
+    /// legal holds do not prevent; use [`delete_versions_reporting`](Self::delete_versions_reporting) there.
+    pub async fn delete_objects_reporting(&self, keys: &[String]) -> S3Result<HashMap<String, S3Error>> {
+        let versions: Vec<(String, Option<String>)> = keys.iter().map(|key| (key.clone(), None)).collect();
+        self.delete_versions_reporting(&versions).await
+    }
 
-        let delete = Delete::builder().set_objects(Some(delete_object_ids)).build()?;
+    /// Like [`delete_objects_reporting`](Self::delete_objects_reporting), deleting the given version of each key
+    /// where one is set. Versions under legal hold are refused and reported as [`S3Error::ObjectOnHold`].
+    pub async fn delete_versions_reporting(&self, objects: &[(String, Option<String>)]) -> S3Result<HashMap<String, S3Error>> {
+        let mut failed = HashMap::new();
+
+        for chunk in objects.chunks(DELETE_OBJECTS_LIMIT) {
+            let mut delete_object_ids = Vec::with_capacity(chunk.len());
+            for (key, version_id) in chunk {
+                delete_object_ids.push(
+                    ObjectIdentifier::builder()
+                        .key(key)
+                        .set_version_id(version_id.clone())
+                        .build()?,
+                );
+            }
+            let delete = Delete::builder().set_objects(Some(delete_object_ids)).quiet(true).build()?;
 
-        self.client.delete_objects().bucket(self.bucket).delete(delete).send().await?;
+            let response = self
+                .pacer
+                .run(|| self.client.delete_objects().bucket(self.bucket).delete(delete.clone()).send())
+                .await?;
 
-        Ok(keys.len())
+            for error in response.errors() {
+                let Some(key) = error.key() else { continue };
+                let reason = if error.code() == Some("AccessDenied") && self.get_legal_hold(key).await.unwrap_or(false) {
+                    S3Error::ObjectOnHold(key.to_owned())
+                } else {
+                    S3Error::DeleteRejected {
+                        key: key.to_owned(),
+                        message: error.message().unwrap_or("delete failed").to_owned(),
+                    }
+                };
+                failed.insert(key.to_owned(), reason);
+            }
+        }
+
+        if !failed.is_empty() {
+            tracing::warn!(bucket = %self.bucket, failed = failed.len(), "Some objects could not be deleted");
+        }
+        Ok(failed)
     }
 
     pub async fn clear_bucket(&self) -> S3Result<Vec<String>> {
@@ -254,13 +565,19 @@ impl S3 {
         }
     }
 
-    async fn start_multipart_upload(&self, key: &str, content_type: &str) -> S3Result<String> {
+    async fn start_multipart_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+        metadata: &HashMap<String, String>,
+    ) -> S3Result<String> {
         let response = self
             .client
             .create_multipart_upload()
             .bucket(self.bucket)
             .key(key)
             .content_type(content_type)
+            .set_metadata((!metadata.is_empty()).then(|| metadata.clone()))
             .send()
             .await?;
 
@@ -268,16 +585,19 @@ impl S3 {
         Ok(upload_id.to_owned())
     }
 
-    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, stream: ByteStream) -> S3Result<(i32, String)> {
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Bytes) -> S3Result<(i32, String)> {
         let resp = self
-            .client
-            .upload_part()
-            .bucket(self.bucket)
-            .key(key)
-            .upload_id(upload_id)
-            .part_number(part_number)
-            .body(stream)
-            .send()
+            .pacer
+            .run(|| {
+                self.client
+                    .upload_part()
+                    .bucket(self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(data.clone()))
+                    .send()
+            })
             .await?;
 
         let e_tag = resp.e_tag().ok_or(S3Error::MissingETag)?;
@@ -335,7 +655,7 @@ impl S3 {
         let key = key.into();
         let content_type = content_type.into();
         let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
-        let upload_id = self.start_multipart_upload(&key, &content_type).await?;
+        let upload_id = self.start_multipart_upload(&key, &content_type, &HashMap::new()).await?;
         let mut parts: Vec<(i32, String)> = vec![];
         let mut file = File::open(&file_path).await?;
         let mut buffer = vec![0u8; chunk_size];
@@ -357,8 +677,8 @@ impl S3 {
                 break;
             }
 
-            let data = buffer[..total_read].to_vec();
-            match self.upload_part(&key, &upload_id, part_number, data.into()).await {
+            let data = Bytes::copy_from_slice(&buffer[..total_read]);
+            match self.upload_part(&key, &upload_id, part_number, data).await {
                 Ok((part_num, e_tag)) => {
                     tracing::debug!(
                         part = part_num,
@@ -412,17 +732,15 @@ impl S3 {
         while start < total_size {
             let end = std::cmp::min(start + chunk_size, total_size) - 1;
             let client = self.client.clone();
+            let pacer = Arc::clone(&self.pacer);
             let bucket = self.bucket;
             let key = key.clone();
             let range_start = start;
 
             let handle = tokio::spawn(async move {
-                let resp = client
-                    .get_object()
-                    .bucket(bucket)
-                    .key(&key)
-                    .range(format!("bytes={range_start}-{end}"))
-                    .send()
+                let range = format!("bytes={range_start}-{end}");
+                let resp = pacer
+                    .run(|| client.get_object().bucket(bucket).key(&key).range(&range).send())
                     .await?;
 
                 let data = resp.body.collect().await?;
@@ -457,3 +775,7 @@ impl S3 {
         Ok(())
     }
 }
+
+fn is_access_denied<E: ProvideErrorMetadata, R>(err: &aws_sdk_s3::error::SdkError<E, R>) -> bool {
+    err.as_service_error().and_then(ProvideErrorMetadata::code) == Some("AccessDenied")
+}
@@ -0,0 +1,361 @@
+use aws_sdk_s3::{config::http::HttpResponse, error::ProvideErrorMetadata, error::SdkError};
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+pub const DEFAULT_MAX_CONCURRENCY: usize = 16;
+const THROTTLE_CODES: &[&str] = &["SlowDown", "RequestLimitExceeded"];
+const THROTTLE_RETRIES: u32 = 3;
+const THROTTLE_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// Throttles arriving within this window after a decrease belong to the same burst and don't shrink the limit again.
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(1);
+/// Quiet time required before each single-permit recovery step.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacingState {
+    pub max_concurrency: usize,
+    pub effective_concurrency: usize,
+    pub throttle_events: u64,
+    pub paused_for: Option<Duration>,
+}
+
+struct Limits {
+    effective: usize,
+    /// Permits that were in use when the limit dropped; they are forgotten on release instead of returned.
+    debt: usize,
+    paused_until: Option<Instant>,
+    last_adjustment: Instant,
+}
+
+/// Client-wide concurrency limiter that halves its permits on S3 throttling and regains them one at a time.
+pub struct Pacer {
+    semaphore: Semaphore,
+    max_permits: usize,
+    limits: Mutex<Limits>,
+    throttle_events: AtomicU64,
+}
+
+pub(crate) struct PacedPermit<'a> {
+    permit: Option<SemaphorePermit<'a>>,
+    pacer: &'a Pacer,
+}
+
+impl Drop for PacedPermit<'_> {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else { return };
+        let mut limits = self.pacer.lock();
+        if limits.debt > 0 {
+            limits.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+impl Pacer {
+    pub fn new(max_permits: usize) -> Self {
+        let max_permits = max_permits.max(1);
+        Self {
+            semaphore: Semaphore::new(max_permits),
+            max_permits,
+            limits: Mutex::new(Limits {
+                effective: max_permits,
+                debt: 0,
+                paused_until: None,
+                last_adjustment: Instant::now(),
+            }),
+            throttle_events: AtomicU64::new(0),
+        }
+    }
+
+    pub fn state(&self) -> PacingState {
+        let now = Instant::now();
+        let limits = self.lock();
+        PacingState {
+            max_concurrency: self.max_permits,
+            effective_concurrency: limits.effective,
+            throttle_events: self.throttle_events.load(Ordering::Relaxed),
+            paused_for: limits.paused_until.and_then(|until| until.checked_duration_since(now)),
+        }
+    }
+
+    /// Runs `op` under a permit, retrying throttled attempts after the server's `Retry-After` or a local backoff.
+    pub(crate) async fn run<T, E, F, Fut>(&self, mut op: F) -> Result<T, SdkError<E, HttpResponse>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, HttpResponse>>>,
+        E: ProvideErrorMetadata,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.acquire().await;
+                op().await
+            };
+
+            let err = match &result {
+                Ok(_) => {
+                    self.record_success(Instant::now());
+                    return result;
+                }
+                Err(err) if is_throttled(err) => err,
+                Err(_) => return result,
+            };
+
+            let retry_after = retry_after(err);
+            self.record_throttle(retry_after, Instant::now());
+            if attempt == THROTTLE_RETRIES {
+                return result;
+            }
+            attempt += 1;
+            tracing::warn!(attempt, retry_after = ?retry_after, "S3 request throttled, retrying");
+            if retry_after.is_none() {
+                tokio::time::sleep(THROTTLE_BACKOFF * 2u32.pow(attempt)).await;
+            }
+        }
+    }
+
+    pub(crate) async fn acquire(&self) -> PacedPermit<'_> {
+        loop {
+            let paused_until = {
+                let mut limits = self.lock();
+                self.recover(&mut limits, Instant::now());
+                limits.paused_until
+            };
+            match paused_until {
+                Some(until) if until > Instant::now() => tokio::time::sleep_until(until.into()).await,
+                _ => break,
+            }
+        }
+
+        let permit = self.semaphore.acquire().await.expect("pacer semaphore is never closed");
+        PacedPermit {
+            permit: Some(permit),
+            pacer: self,
+        }
+    }
+
+    fn record_throttle(&self, retry_after: Option<Duration>, now: Instant) {
+        self.throttle_events.fetch_add(1, Ordering::Relaxed);
+        let mut limits = self.lock();
+
+        if let Some(retry_after) = retry_after {
+            let until = now + retry_after.min(MAX_RETRY_AFTER);
+            limits.paused_until = Some(limits.paused_until.map_or(until, |current| current.max(until)));
+        }
+
+        let in_cooldown = limits.effective < self.max_permits && now < limits.last_adjustment + DECREASE_COOLDOWN;
+        if in_cooldown || limits.effective == 1 {
+            return;
+        }
+
+        let reduced = limits.effective / 2;
+        limits.effective -= reduced;
+        limits.debt += reduced - self.semaphore.forget_permits(reduced);
+        limits.last_adjustment = now;
+        tracing::warn!(
+            effective = limits.effective,
+            max = self.max_permits,
+            "S3 throttling detected, reducing concurrency"
+        );
+    }
+
+    fn record_success(&self, now: Instant) {
+        let mut limits = self.lock();
+        self.recover(&mut limits, now);
+    }
+
+    fn recover(&self, limits: &mut Limits, now: Instant) {
+        if limits.effective == self.max_permits || now < limits.last_adjustment + RECOVERY_INTERVAL {
+            return;
+        }
+
+        limits.effective += 1;
+        if limits.debt > 0 {
+            limits.debt -= 1;
+        } else {
+            self.semaphore.add_permits(1);
+        }
+        limits.last_adjustment = now;
+        tracing::info!(
+            effective = limits.effective,
+            max = self.max_permits,
+            "S3 concurrency recovering"
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Limits> {
+        self.limits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn is_throttled<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> bool {
+    err.as_service_error()
+        .and_then(ProvideErrorMetadata::code)
+        .is_some_and(|code| THROTTLE_CODES.contains(&code))
+}
+
+/// Only the delay-seconds form is honored; HTTP-date values fall back to local backoff.
+fn retry_after<E>(err: &SdkError<E, HttpResponse>) -> Option<Duration> {
+    let value = err.raw_response()?.headers().get("retry-after")?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::{error::ErrorMetadata, operation::get_object::GetObjectError, primitives::SdkBody};
+    use std::sync::{Arc, atomic::AtomicUsize};
+
+    fn slow_down(retry_after: Option<&str>) -> SdkError<GetObjectError, HttpResponse> {
+        let mut raw = HttpResponse::new(503.try_into().unwrap(), SdkBody::empty());
+        if let Some(value) = retry_after {
+            raw.headers_mut().insert("retry-after", value.to_owned());
+        }
+        let err = GetObjectError::generic(ErrorMetadata::builder().code("SlowDown").build());
+        SdkError::service_error(err, raw)
+    }
+
+    /// Test double for an S3 call: fails with `SlowDown` for the first `faults` calls and tracks peak concurrency.
+    #[derive(Default)]
+    struct FaultInjector {
+        faults: AtomicUsize,
+        calls: AtomicUsize,
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl FaultInjector {
+        fn failing(faults: usize) -> Arc<Self> {
+            Arc::new(Self {
+                faults: AtomicUsize::new(faults),
+                ..Default::default()
+            })
+        }
+
+        async fn call(&self) -> Result<(), SdkError<GetObjectError, HttpResponse>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let throttled = self
+                .faults
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if throttled { Err(slow_down(Some("0"))) } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn detects_throttle_codes_and_retry_after() {
+        assert!(is_throttled(&slow_down(None)));
+        assert_eq!(retry_after(&slow_down(Some("3"))), Some(Duration::from_secs(3)));
+        assert_eq!(retry_after(&slow_down(Some("Wed, 21 Oct 2015 07:28:00 GMT"))), None);
+
+        let other = GetObjectError::generic(ErrorMetadata::builder().code("AccessDenied").build());
+        let raw = HttpResponse::new(403.try_into().unwrap(), SdkBody::empty());
+        assert!(!is_throttled(&SdkError::service_error(other, raw)));
+    }
+
+    #[test]
+    fn throttle_halves_limit_and_recovers_gradually() {
+        let pacer = Pacer::new(8);
+        let start = Instant::now();
+
+        pacer.record_throttle(None, start);
+        assert_eq!(pacer.state().effective_concurrency, 4);
+        // Same burst: no further decrease inside the cooldown.
+        pacer.record_throttle(None, start + Duration::from_millis(10));
+        assert_eq!(pacer.state().effective_concurrency, 4);
+        pacer.record_throttle(None, start + DECREASE_COOLDOWN);
+        assert_eq!(pacer.state().effective_concurrency, 2);
+        assert_eq!(pacer.state().throttle_events, 3);
+
+        let throttled_at = start + DECREASE_COOLDOWN;
+        pacer.record_success(throttled_at + RECOVERY_INTERVAL / 2);
+        assert_eq!(pacer.state().effective_concurrency, 2);
+        pacer.record_success(throttled_at + RECOVERY_INTERVAL);
+        assert_eq!(pacer.state().effective_concurrency, 3);
+        pacer.record_success(throttled_at + RECOVERY_INTERVAL + Duration::from_millis(1));
+        assert_eq!(pacer.state().effective_concurrency, 3);
+
+        for step in 2..=10 {
+            pacer.record_success(throttled_at + RECOVERY_INTERVAL * step);
+        }
+        assert_eq!(pacer.state().effective_concurrency, 8);
+        assert_eq!(pacer.semaphore.available_permits(), 8);
+    }
+
+    #[test]
+    fn retry_after_pauses_new_requests() {
+        let pacer = Pacer::new(4);
+        pacer.record_throttle(Some(Duration::from_secs(2)), Instant::now());
+        let paused_for = pacer.state().paused_for.unwrap();
+        assert!(paused_for > Duration::from_secs(1) && paused_for <= Duration::from_secs(2));
+
+        pacer.record_throttle(Some(Duration::from_secs(600)), Instant::now());
+        assert!(pacer.state().paused_for.unwrap() <= MAX_RETRY_AFTER);
+    }
+
+    #[tokio::test]
+    async fn in_flight_permits_are_withheld_after_throttle() {
+        let pacer = Pacer::new(4);
+        let held: Vec<_> = acquire_all(&pacer, 4).await;
+        pacer.record_throttle(None, Instant::now());
+
+        assert_eq!(pacer.state().effective_concurrency, 2);
+        drop(held);
+        assert_eq!(pacer.semaphore.available_permits(), 2);
+    }
+
+    async fn acquire_all(pacer: &Pacer, n: usize) -> Vec<PacedPermit<'_>> {
+        let mut permits = Vec::with_capacity(n);
+        for _ in 0..n {
+            permits.push(pacer.acquire().await);
+        }
+        permits
+    }
+
+    #[tokio::test]
+    async fn concurrency_drops_under_throttling() {
+        let pacer = Arc::new(Pacer::new(8));
+        let injector = FaultInjector::failing(6);
+
+        let mut tasks = Vec::new();
+        for _ in 0..32 {
+            let pacer = Arc::clone(&pacer);
+            let injector = Arc::clone(&injector);
+            tasks.push(tokio::spawn(async move { pacer.run(|| injector.call()).await }));
+        }
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(injector.peak.load(Ordering::SeqCst), 8);
+        assert_eq!(injector.calls.load(Ordering::SeqCst), 38);
+        let state = pacer.state();
+        assert_eq!(state.throttle_events, 6);
+        assert!(state.effective_concurrency < 8);
+
+        // Once throttling stops, a new burst runs at the reduced limit.
+        injector.peak.store(0, Ordering::SeqCst);
+        let mut tasks = Vec::new();
+        for _ in 0..16 {
+            let pacer = Arc::clone(&pacer);
+            let injector = Arc::clone(&injector);
+            tasks.push(tokio::spawn(async move { pacer.run(|| injector.call()).await }));
+        }
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert!(injector.peak.load(Ordering::SeqCst) <= state.effective_concurrency);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    DEFAULT_CHUNK_SIZE, ObjectHead, S3,
+    error::{S3Error, S3Result},
+};
+use bytes::BytesMut;
+
+/// User metadata key under which a replica records the ETag of its source object. Multipart copies get an ETag of
+/// their own, so this is what later up-to-date checks compare against.
+pub const SOURCE_ETAG_METADATA: &str = "source-etag";
+
+/// Result of [`S3::replicate_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replication {
+    Copied {
+        bytes: u64,
+    },
+    /// The target already holds an object with the source's size and ETag; nothing was transferred.
+    UpToDate,
+    /// The object no longer exists in the source bucket.
+    SourceMissing,
+}
+
+impl S3 {
+    /// Copies `key` into `target`, which may live on another endpoint, carrying over its content type and user
+    /// metadata. The body is streamed through multipart uploads, so at most one chunk is held in memory.
+    pub async fn replicate_to(&self, target: &S3, key: &str) -> S3Result<Replication> {
+        let Some(source) = self.head(key).await? else {
+            return Ok(Replication::SourceMissing);
+        };
+        if let Some(existing) = target.head(key).await?
+            && is_replica_of(&existing, &source)
+        {
+            tracing::debug!(key, "Replica is up to date");
+            return Ok(Replication::UpToDate);
+        }
+
+        let object = match self
+            .pacer
+            .run(|| self.client.get_object().bucket(self.bucket).key(key).send())
+            .await
+        {
+            Ok(object) => object,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(Replication::SourceMissing),
+            Err(e) => return Err(e.into()),
+        };
+        let content_type = object.content_type().unwrap_or("application/octet-stream").to_owned();
+        let mut metadata = object.metadata().cloned().unwrap_or_default();
+        if let Some(e_tag) = object.e_tag() {
+            metadata.insert(SOURCE_ETAG_METADATA.to_owned(), e_tag.to_owned());
+        }
+        let mut body = object.body;
+
+        let mut upload_id: Option<String> = None;
+        let copied = async {
+            let mut parts = Vec::new();
+            let mut buffer = BytesMut::with_capacity(DEFAULT_CHUNK_SIZE);
+            let mut total = 0u64;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                total += chunk.len() as u64;
+                buffer.extend_from_slice(&chunk);
+                while buffer.len() >= DEFAULT_CHUNK_SIZE {
+                    let id = match &upload_id {
+                        Some(id) => id.clone(),
+                        None => upload_id
+                            .insert(target.start_multipart_upload(key, &content_type, &metadata).await?)
+                            .clone(),
+                    };
+                    let part = buffer.split_to(DEFAULT_CHUNK_SIZE).freeze();
+                    parts.push(target.upload_part(key, &id, parts.len() as i32 + 1, part).await?);
+                }
+            }
+
+            match &upload_id {
+                None => {
+                    target
+                        .upload_with_metadata(key, buffer.freeze(), &content_type, metadata.clone())
+                        .await?
+                }
+                Some(id) => {
+                    if !buffer.is_empty() {
+                        parts.push(target.upload_part(key, id, parts.len() as i32 + 1, buffer.freeze()).await?);
+                    }
+                    target.complete_multipart_upload(key, id, parts).await?;
+                }
+            }
+            Ok::<_, S3Error>(total)
+        }
+        .await;
+
+        match copied {
+            Ok(bytes) => {
+                tracing::info!(key, bytes, target = %target.bucket, "Replicated object");
+                Ok(Replication::Copied { bytes })
+            }
+            Err(e) => {
+                if let Some(id) = upload_id
+                    && let Err(abort) = target.abort_multipart_upload(key, &id).await
+                {
+                    tracing::warn!(key, "Failed to abort replication upload: {abort}");
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Same size, and either the same ETag or a recorded source ETag matching the source's current one.
+fn is_replica_of(replica: &ObjectHead, source: &ObjectHead) -> bool {
+    let Some(source_e_tag) = &source.e_tag else {
+        return false;
+    };
+    replica.size == source.size
+        && (replica.e_tag.as_ref() == Some(source_e_tag) || replica.metadata.get(SOURCE_ETAG_METADATA) == Some(source_e_tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn head(size: i64, e_tag: &str, source_e_tag: Option<&str>) -> ObjectHead {
+        ObjectHead {
+            size,
+            e_tag: Some(e_tag.into()),
+            metadata: source_e_tag
+                .map(|t| HashMap::from([(SOURCE_ETAG_METADATA.to_owned(), t.to_owned())]))
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matching_size_and_e_tag_is_up_to_date() {
+        let source = head(10, "\"abc\"", None);
+        assert!(is_replica_of(&head(10, "\"abc\"", None), &source));
+        assert!(!is_replica_of(&head(11, "\"abc\"", None), &source));
+        assert!(!is_replica_of(&head(10, "\"def\"", None), &source));
+    }
+
+    #[test]
+    fn multipart_replica_matches_by_recorded_source_e_tag() {
+        let source = head(10, "\"abc\"", None);
+        assert!(is_replica_of(&head(10, "\"xyz-2\"", Some("\"abc\"")), &source));
+        assert!(!is_replica_of(&head(10, "\"xyz-2\"", Some("\"old\"")), &source));
+    }
+
+    #[test]
+    fn source_without_e_tag_is_always_copied() {
+        let source = ObjectHead {
+            size: 10,
+            ..Default::default()
+        };
+        assert!(!is_replica_of(&source, &source));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{DEFAULT_CHUNK_SIZE, S3, error::S3Result};
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+
+/// Uploads an object whose size is not known up front, holding at most one part in memory.
+///
+/// Data is buffered until a part is full; the multipart upload is only started then, so objects that fit in
+/// one part are stored with a single `PutObject` by [`finish`](Self::finish). A failed part or completion
+/// aborts the upload before the error is returned; a sink dropped without `finish` or `abort` leaves an
+/// incomplete upload behind for the bucket's lifecycle rules.
+pub struct MultipartSink<'a> {
+    s3: &'a S3,
+    key: String,
+    content_type: String,
+    metadata: HashMap<String, String>,
+    part_size: usize,
+    buffer: BytesMut,
+    upload_id: Option<String>,
+    parts: Vec<(i32, String)>,
+    written: u64,
+}
+
+impl S3 {
+    pub fn multipart_sink(
+        &self,
+        key: impl Into<String>,
+        content_type: impl Into<String>,
+        metadata: HashMap<String, String>,
+    ) -> MultipartSink<'_> {
+        MultipartSink {
+            s3: self,
+            key: key.into(),
+            content_type: content_type.into(),
+            metadata,
+            part_size: DEFAULT_CHUNK_SIZE,
+            buffer: BytesMut::new(),
+            upload_id: None,
+            parts: Vec::new(),
+            written: 0,
+        }
+    }
+}
+
+impl MultipartSink<'_> {
+    /// Bytes accepted so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Everything written, as long as it still fits in the first part and nothing has been uploaded yet.
+    pub fn buffered(&self) -> Option<&[u8]> {
+        self.upload_id.is_none().then_some(&self.buffer[..])
+    }
+
+    pub async fn write(&mut self, data: Bytes) -> S3Result<()> {
+        self.written += data.len() as u64;
+        self.buffer.extend_from_slice(&data);
+        while self.buffer.len() >= self.part_size {
+            let part = self.buffer.split_to(self.part_size).freeze();
+            if let Err(e) = self.upload_part(part).await {
+                self.abort_after_failure().await;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads what is left and completes the object; returns its size.
+    pub async fn finish(mut self) -> S3Result<u64> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            let data = std::mem::take(&mut self.buffer).freeze();
+            self.s3
+                .upload_with_metadata(&self.key, data, &self.content_type, std::mem::take(&mut self.metadata))
+                .await?;
+            return Ok(self.written);
+        };
+
+        if !self.buffer.is_empty() {
+            let part = std::mem::take(&mut self.buffer).freeze();
+            if let Err(e) = self.upload_part(part).await {
+                self.abort_after_failure().await;
+                return Err(e);
+            }
+        }
+        let parts = std::mem::take(&mut self.parts);
+        let part_count = parts.len();
+        if let Err(e) = self.s3.complete_multipart_upload(&self.key, &upload_id, parts).await {
+            self.abort_after_failure().await;
+            return Err(e);
+        }
+        tracing::info!(key = %self.key, parts = part_count, size = self.written, "Completed streamed multipart upload");
+        Ok(self.written)
+    }
+
+    /// Discards the upload, including any parts already stored.
+    pub async fn abort(self) -> S3Result<()> {
+        match &self.upload_id {
+            Some(upload_id) => self.s3.abort_multipart_upload(&self.key, upload_id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn upload_part(&mut self, data: Bytes) -> S3Result<()> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let upload_id = self
+                    .s3
+                    .start_multipart_upload(&self.key, &self.content_type, &self.metadata)
+                    .await?;
+                self.upload_id.insert(upload_id).clone()
+            }
+        };
+        let part_number = self.parts.len() as i32 + 1;
+        let part = self.s3.upload_part(&self.key, &upload_id, part_number, data).await?;
+        self.parts.push(part);
+        Ok(())
+    }
+
+    async fn abort_after_failure(&mut self) {
+        if let Some(upload_id) = self.upload_id.take()
+            && let Err(e) = self.s3.abort_multipart_upload(&self.key, &upload_id).await
+        {
+            tracing::warn!(key = %self.key, "Failed to abort multipart upload: {e}");
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use s3_client::S3;
+use bytes::Bytes;
+use s3_client::{S3, error::S3Error};
+use std::collections::HashMap;
 use tempfile::NamedTempFile;
 use testcontainers_modules::{minio::MinIO, testcontainers::runners::AsyncRunner as _};
 
@@ -29,6 +31,17 @@ async fn setup_s3() -> anyhow::Result<(testcontainers_modules::testcontainers::C
     Ok((minio, s3))
 }
 
+/// MinIO with a bucket created with object lock enabled, as legal holds require.
+async fn setup_locked_s3() -> anyhow::Result<(testcontainers_modules::testcontainers::ContainerAsync<MinIO>, S3)> {
+    let minio = MinIO::default().start().await?;
+    let host = minio.get_host().await?;
+    let port = minio.get_host_port_ipv4(9000).await?;
+    let endpoint = format!("http://{}:{}", host, port);
+    let s3 = S3::new(ACCESS_KEY, SECRET_KEY, REGION, &endpoint, "locked-bucket").await;
+    s3.create_bucket_with_object_lock().await?;
+    Ok((minio, s3))
+}
+
 #[tokio::test]
 async fn test_upload_and_download() -> anyhow::Result<()> {
     let (_minio, s3) = setup_s3().await?;
@@ -43,6 +56,21 @@ async fn test_upload_and_download() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_download_range() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_s3().await?;
+
+    let data: Vec<u8> = (0..=255).collect();
+    s3.upload("range.bin", data.clone(), "application/octet-stream").await?;
+
+    assert_eq!(s3.download_range("range.bin", 0, 99).await?.data, data[..100]);
+    assert_eq!(s3.download_range("range.bin", 200, 255).await?.data, data[200..]);
+    assert_eq!(s3.download_range("range.bin", 250, 1000).await?.data, data[250..]);
+    assert!(s3.download_range("range.bin", 256, 300).await.is_err());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_object_exists() -> anyhow::Result<()> {
     let (_minio, s3) = setup_s3().await?;
@@ -85,6 +113,30 @@ async fn test_list_objects() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_list_objects_page_after_key() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_s3().await?;
+
+    s3.upload("alice/1", b"1".to_vec(), "text/plain").await?;
+    s3.upload("alice/2", b"22".to_vec(), "text/plain").await?;
+    s3.upload("alice/3", b"333".to_vec(), "text/plain").await?;
+    s3.upload("bob/1", b"1".to_vec(), "text/plain").await?;
+
+    let page = s3.list_objects_page("alice/", None, 2).await?;
+    let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, ["alice/1", "alice/2"]);
+    assert_eq!(page.objects[1].size, 2);
+    assert!(page.objects[0].last_modified.is_some());
+    assert!(page.truncated);
+
+    let page = s3.list_objects_page("alice/", Some("alice/2"), 2).await?;
+    let keys: Vec<&str> = page.objects.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, ["alice/3"]);
+    assert!(!page.truncated);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_delete_objects_batch() -> anyhow::Result<()> {
     let (_minio, s3) = setup_s3().await?;
@@ -101,6 +153,20 @@ async fn test_delete_objects_batch() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_object_metadata_round_trip() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_s3().await?;
+
+    let metadata = std::collections::HashMap::from([("owner".to_string(), "user-1".to_string())]);
+    s3.upload_with_metadata("owned.txt", b"1".to_vec(), "text/plain", metadata.clone())
+        .await?;
+
+    assert_eq!(s3.object_metadata("owned.txt").await?, Some(metadata));
+    assert_eq!(s3.object_metadata("missing.txt").await?, None);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_clear_bucket() -> anyhow::Result<()> {
     let (_minio, s3) = setup_s3().await?;
@@ -163,6 +229,56 @@ async fn test_multipart_upload_and_download() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_multipart_sink_streams_parts() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_s3().await?;
+
+    let data: Vec<u8> = (0..11 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let metadata = HashMap::from([("owner".to_owned(), "alice".to_owned())]);
+    let mut sink = s3.multipart_sink("streamed.bin", "application/octet-stream", metadata);
+    for chunk in data.chunks(700 * 1024) {
+        sink.write(Bytes::copy_from_slice(chunk)).await?;
+    }
+    assert!(sink.buffered().is_none(), "two full parts are uploaded before finishing");
+    assert_eq!(sink.finish().await?, data.len() as u64);
+
+    assert_eq!(s3.download("streamed.bin").await?.data, data);
+    let head = s3.head("streamed.bin").await?.expect("object exists");
+    assert_eq!(head.metadata.get("owner").map(String::as_str), Some("alice"));
+
+    let mut small = s3.multipart_sink("small.bin", "text/plain", HashMap::new());
+    small.write(Bytes::from_static(b"tiny")).await?;
+    assert_eq!(small.buffered(), Some(&b"tiny"[..]));
+    small.finish().await?;
+    assert_eq!(s3.download("small.bin").await?.data, b"tiny");
+
+    let mut aborted = s3.multipart_sink("aborted.bin", "application/octet-stream", HashMap::new());
+    aborted.write(Bytes::from(vec![0u8; 6 * 1024 * 1024])).await?;
+    aborted.abort().await?;
+    assert!(!s3.object_exists("aborted.bin").await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check_bucket() -> anyhow::Result<()> {
+    let (minio, s3) = setup_s3().await?;
+    s3.check_bucket().await?;
+
+    let host = minio.get_host().await?;
+    let port = minio.get_host_port_ipv4(9000).await?;
+    let missing = S3::new(
+        ACCESS_KEY,
+        SECRET_KEY,
+        REGION,
+        format!("http://{host}:{port}"),
+        "missing-bucket",
+    )
+    .await;
+    assert!(matches!(missing.check_bucket().await, Err(S3Error::HeadBucketError(_))));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_delete_bucket() -> anyhow::Result<()> {
     let (_minio, s3) = setup_s3().await?;
@@ -171,3 +287,76 @@ async fn test_delete_bucket() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_legal_hold_blocks_delete() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_locked_s3().await?;
+    s3.upload("held.txt", b"evidence".to_vec(), "text/plain").await?;
+    assert!(!s3.get_legal_hold("held.txt").await?);
+
+    s3.set_legal_hold("held.txt", true).await?;
+    assert!(s3.get_legal_hold("held.txt").await?);
+    assert!(
+        s3.head("held.txt")
+            .await?
+            .is_some_and(|h| h.legal_hold && h.version_id.is_some())
+    );
+
+    let result = s3.delete_object("held.txt").await;
+    assert!(matches!(result, Err(S3Error::ObjectOnHold(key)) if key == "held.txt"));
+    assert_eq!(s3.download("held.txt").await?.data, b"evidence");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_legal_hold_release_allows_delete() -> anyhow::Result<()> {
+    let (_minio, s3) = setup_locked_s3().await?;
+    s3.upload("released.txt", b"evidence".to_vec(), "text/plain").await?;
+    let version = s3.head("released.txt").await?.and_then(|h| h.version_id);
+
+    s3.set_legal_hold("released.txt", true).await?;
+    let failed = s3
+        .delete_versions_reporting(&[("released.txt".to_string(), version.clone())])
+        .await?;
+    assert!(matches!(failed.get("released.txt"), Some(S3Error::ObjectOnHold(_))));
+    assert!(s3.object_exists("released.txt").await?);
+
+    s3.set_legal_hold("released.txt", false).await?;
+    s3.delete_object("released.txt").await?;
+    assert!(!s3.object_exists("released.txt").await?);
+
+    Ok(())
+}
+
+#[cfg(feature = "minio-admin")]
+#[tokio::test]
+async fn test_minio_bucket_quota_and_usage() -> anyhow::Result<()> {
+    use s3_client::minio_admin::{BucketUsage, MinioAdmin};
+
+    let (minio, s3) = setup_s3().await?;
+    let endpoint = format!("http://{}:{}", minio.get_host().await?, minio.get_host_port_ipv4(9000).await?);
+    let admin = MinioAdmin::new(ACCESS_KEY, SECRET_KEY, REGION, &endpoint);
+
+    assert_eq!(admin.get_bucket_quota(BUCKET).await?, None);
+    admin.set_bucket_quota(BUCKET, 1024 * 1024).await?;
+    assert_eq!(admin.get_bucket_quota(BUCKET).await?, Some(1024 * 1024));
+
+    s3.upload("a.txt", b"1".to_vec(), "text/plain").await?;
+    s3.upload("b.txt", b"22".to_vec(), "text/plain").await?;
+    s3.upload("c.txt", b"333".to_vec(), "text/plain").await?;
+
+    // Usage is counted by MinIO's background scanner, which takes a while to get to new objects.
+    let expected = BucketUsage { objects: 3, bytes: 6 };
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(180);
+    let mut usage = admin.bucket_usage(BUCKET).await?;
+    while usage != expected && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        usage = admin.bucket_usage(BUCKET).await?;
+    }
+    assert_eq!(usage, expected);
+
+    admin.set_bucket_quota(BUCKET, 0).await?;
+    assert_eq!(admin.get_bucket_quota(BUCKET).await?, None);
+    Ok(())
+}
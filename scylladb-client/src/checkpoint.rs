@@ -0,0 +1,39 @@
+use crate::{ChatMessageStore, error::ScyllaResult};
+use chrono::Utc;
+use scylla::client::session::Session;
+
+/// One row per checkpoint id, overwritten on every save, so only the latest checkpoint is kept.
+pub(crate) async fn migrate(session: &Session) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS consumer_checkpoints (
+                id TEXT,
+                blob BLOB,
+                written_at TIMESTAMP,
+                PRIMARY KEY (id)
+            )",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+impl ChatMessageStore {
+    /// Replaces the checkpoint stored under `id`; the blob is opaque to the store.
+    pub async fn save_checkpoint(&self, id: &str, blob: &[u8]) -> ScyllaResult<()> {
+        self.session
+            .execute_unpaged(&self.save_checkpoint_stmt, (id, blob, Utc::now()))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn load_checkpoint(&self, id: &str) -> ScyllaResult<Option<Vec<u8>>> {
+        let row = self
+            .session
+            .execute_unpaged(&self.load_checkpoint_stmt, (id,))
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<(Vec<u8>,)>()?;
+        Ok(row.map(|(blob,)| blob))
+    }
+}
@@ -15,6 +15,10 @@ pub enum ScyllaError {
     MaybeFirstRow(#[from] MaybeFirstRowError),
     #[error("Statement preparation error: {0}")]
     Prepare(#[from] PrepareError),
+    #[error("Conditional update timed out and may or may not have been applied: {0}")]
+    LwtTimeout(ExecutionError),
+    #[error("Conditional update failed: {0}")]
+    Lwt(ExecutionError),
     #[error("Paged query execution error: {0}")]
     PagerExecution(#[from] PagerExecutionError),
     #[error("Failed to create Scylla session: {0}")]
@@ -23,4 +27,6 @@ pub enum ScyllaError {
     Rows(#[from] RowsError),
     #[error("Failed to deserialize row column value: {0}")]
     Deserialization(#[from] DeserializationError),
+    #[error("Failed to serialize outbox event: {0}")]
+    Serialization(#[from] serde_json::Error),
 }
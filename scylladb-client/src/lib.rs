@@ -1,17 +1,27 @@
+pub mod checkpoint;
 pub mod error;
-
-use chrono::{DateTime, Utc};
-use error::ScyllaResult;
+pub mod outbox;
+pub mod quotas;
+pub mod topology;
+pub mod user_index;
+pub mod webhooks;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use error::{ScyllaError, ScyllaResult};
+use outbox::OutboxEvent;
 pub use scylla::response::{PagingState, PagingStateResponse};
 use scylla::{
     client::{execution_profile::ExecutionProfileBuilder, session::Session, session_builder::SessionBuilder},
+    errors::{DbError, ExecutionError, RequestAttemptError},
     observability::metrics::Metrics,
     policies::retry::DefaultRetryPolicy,
     statement::{Consistency, batch::Batch, prepared::PreparedStatement},
-    value::CqlTimestamp,
+    value::{CqlTimestamp, CqlValue, Row},
 };
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use topology::{NodeEvent, NodeStatus, Topology};
+use user_index::{UserIndexQueue, UserMessageRef};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +32,10 @@ pub struct ScyllaConfig {
     pub metadata_refresh_interval: Duration,
     pub keyspace: String,
     pub replication_factor: u8,
+    /// `user_messages` entries waiting for the background writer; beyond that they go to `index_repair`.
+    pub user_index_queue_capacity: usize,
+    /// Attempts per `user_messages` entry before it is recorded in `index_repair`.
+    pub user_index_write_attempts: u32,
 }
 
 impl Default for ScyllaConfig {
@@ -33,6 +47,8 @@ impl Default for ScyllaConfig {
             metadata_refresh_interval: Duration::from_secs(10),
             keyspace: "chat".into(),
             replication_factor: 3,
+            user_index_queue_capacity: 10_000,
+            user_index_write_attempts: 5,
         }
     }
 }
@@ -46,6 +62,82 @@ pub struct ChatMessage {
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
     pub is_deleted: bool,
+    /// Users mentioned with `@username`, resolved when the message was created.
+    #[serde(default)]
+    pub mentions: Vec<Uuid>,
+    /// Name the author had when sending; `None` for messages stored before names were recorded.
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// Rows [`ChatMessageStore::get_visible_chat_messages`] reads per call at most, deleted ones included.
+pub const VISIBLE_SCAN_BUDGET: usize = 1000;
+
+/// Result of [`ChatMessageStore::get_visible_chat_messages`].
+#[derive(Debug)]
+pub struct VisibleMessages {
+    /// Non-deleted messages, newest first.
+    pub messages: Vec<ChatMessage>,
+    /// `(created_at, message_id)` of the last row read, to pass as `before` for the next page; `None` once the
+    /// chat is exhausted.
+    pub next_before: Option<(DateTime<Utc>, Uuid)>,
+}
+
+/// Result of [`ChatMessageStore::update_message`].
+#[derive(Debug)]
+pub enum UpdateOutcome {
+    Updated {
+        updated_at: DateTime<Utc>,
+    },
+    /// The message was edited since the caller read it; `current` is what it holds now.
+    Conflict {
+        current: ChatMessage,
+    },
+    NotFound,
+}
+
+type MessageRow = (
+    Uuid,
+    Uuid,
+    Uuid,
+    String,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+    bool,
+    Option<Vec<Uuid>>,
+    Option<String>,
+);
+
+type MessageInsert<'a> = (
+    Uuid,
+    Uuid,
+    Uuid,
+    &'a str,
+    CqlTimestamp,
+    Option<CqlTimestamp>,
+    bool,
+    &'a [Uuid],
+    Option<&'a str>,
+);
+
+/// Bound values of the `messages` and `message_by_id` inserts of a new message.
+type NewMessageValues<'a> = (MessageInsert<'a>, (Uuid, Uuid, CqlTimestamp));
+
+impl From<MessageRow> for ChatMessage {
+    fn from(row: MessageRow) -> Self {
+        let (message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username) = row;
+        Self {
+            message_id,
+            chat_id,
+            user_id,
+            content,
+            created_at,
+            updated_at,
+            is_deleted,
+            mentions: mentions.unwrap_or_default(),
+            username,
+        }
+    }
 }
 
 pub struct ChatMessageStore {
@@ -55,57 +147,42 @@ pub struct ChatMessageStore {
     insert_lookup_stmt: PreparedStatement,
     get_by_id_stmt: PreparedStatement,
     get_by_chat_stmt: PreparedStatement,
+    get_by_chat_before_stmt: PreparedStatement,
+    get_by_chat_same_ts_stmt: PreparedStatement,
     update_content_stmt: PreparedStatement,
+    update_content_if_stmt: PreparedStatement,
     delete_stmt: PreparedStatement,
+    upsert_profile_stmt: PreparedStatement,
+    resolve_usernames_stmt: PreparedStatement,
+    insert_outbox_stmt: PreparedStatement,
+    pending_outbox_stmt: PreparedStatement,
+    delete_outbox_stmt: PreparedStatement,
+    save_checkpoint_stmt: PreparedStatement,
+    load_checkpoint_stmt: PreparedStatement,
+    get_user_msgs_stmt: PreparedStatement,
+    get_repairs_stmt: PreparedStatement,
+    delete_repair_stmt: PreparedStatement,
+    pending_repair_users_stmt: PreparedStatement,
+    user_index: UserIndexQueue,
+    topology: Topology,
 }
 
 impl ChatMessageStore {
     pub async fn new(config: &ScyllaConfig, run_migrations: bool) -> ScyllaResult<Self> {
-        let consistency = if config.replication_factor <= 1 {
-            Consistency::One
-        } else {
-            Consistency::LocalQuorum
-        };
-
-        let profile = ExecutionProfileBuilder::default()
-            .consistency(consistency)
-            .retry_policy(Arc::new(DefaultRetryPolicy::new()))
-            .build();
-
-        let mut builder = SessionBuilder::new()
-            .known_node(&config.uri)
-            .connection_timeout(config.connection_timeout)
-            .cluster_metadata_refresh_interval(config.metadata_refresh_interval)
-            .default_execution_profile_handle(profile.into_handle());
-
-        for node in &config.additional_nodes {
-            tracing::info!("Adding node: {}", node);
-            builder = builder.known_node(node);
-        }
-
-        let session = Arc::new(builder.build().await?);
+        let session = Arc::new(connect(config).await?);
 
         if run_migrations {
             Self::migrate(&session, &config.keyspace, config.replication_factor).await?;
         }
 
-        let store = Self::prepare(&session, &config.keyspace).await?;
+        let store = Self::prepare(&session, config).await?;
+        store.refresh_topology();
 
         Ok(store)
     }
 
     pub async fn migrate(session: &Session, keyspace: &str, replication_factor: u8) -> ScyllaResult<()> {
-        session
-            .query_unpaged(
-                format!(
-                    "CREATE KEYSPACE IF NOT EXISTS {keyspace} \
-                     WITH REPLICATION = {{'class': 'SimpleStrategy', 'replication_factor': {replication_factor}}}"
-                ),
-                &[],
-            )
-            .await?;
-
-        session.query_unpaged(format!("USE {keyspace}"), &[]).await?;
+        create_keyspace(session, keyspace, replication_factor).await?;
 
         session
             .query_unpaged(
@@ -117,11 +194,15 @@ impl ChatMessageStore {
                     content TEXT,
                     updated_at TIMESTAMP,
                     is_deleted BOOLEAN,
+                    mentions LIST<UUID>,
+                    username TEXT,
                     PRIMARY KEY ((chat_id), created_at, message_id)
                 ) WITH CLUSTERING ORDER BY (created_at DESC)",
                 &[],
             )
             .await?;
+        Self::add_column_if_missing(session, keyspace, "messages", "mentions", "LIST<UUID>").await?;
+        Self::add_column_if_missing(session, keyspace, "messages", "username", "TEXT").await?;
 
         session
             .query_unpaged(
@@ -148,16 +229,50 @@ impl ChatMessageStore {
             )
             .await?;
 
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS user_profiles (
+                    username_lower TEXT,
+                    user_id UUID,
+                    username TEXT,
+                    PRIMARY KEY (username_lower)
+                )",
+                &[],
+            )
+            .await?;
+
+        outbox::migrate(session).await?;
+        checkpoint::migrate(session).await?;
+        user_index::migrate(session).await?;
+
         Ok(())
     }
 
-    async fn prepare(session: &Arc<Session>, keyspace: &str) -> ScyllaResult<Self> {
-        session.query_unpaged(format!("USE {keyspace}"), &[]).await?;
+    /// Tables created by an older schema lack columns added since; `ALTER TABLE` has no `IF NOT EXISTS`.
+    async fn add_column_if_missing(session: &Session, keyspace: &str, table: &str, column: &str, kind: &str) -> ScyllaResult<()> {
+        let existing = session
+            .query_unpaged(
+                "SELECT column_name FROM system_schema.columns WHERE keyspace_name = ? AND table_name = ? AND column_name = ?",
+                (keyspace, table, column),
+            )
+            .await?
+            .into_rows_result()?;
+        if existing.rows_num() == 0 {
+            session
+                .query_unpaged(format!("ALTER TABLE {keyspace}.{table} ADD {column} {kind}"), &[])
+                .await?;
+            tracing::info!("Added column {column} to {keyspace}.{table}");
+        }
+        Ok(())
+    }
+
+    async fn prepare(session: &Arc<Session>, config: &ScyllaConfig) -> ScyllaResult<Self> {
+        session.query_unpaged(format!("USE {}", config.keyspace), &[]).await?;
 
         let insert_msg_stmt = session
             .prepare(
-                "INSERT INTO messages (message_id, chat_id, user_id, content, created_at, updated_at, is_deleted)
-                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                "INSERT INTO messages (message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
             )
             .await?;
 
@@ -181,11 +296,25 @@ impl ChatMessageStore {
 
         let get_by_chat_stmt = session
             .prepare(
-                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted
+                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username
                  FROM messages WHERE chat_id = ? LIMIT ?",
             )
             .await?;
 
+        let get_by_chat_before_stmt = session
+            .prepare(
+                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username
+                 FROM messages WHERE chat_id = ? AND created_at < ? LIMIT ?",
+            )
+            .await?;
+
+        let get_by_chat_same_ts_stmt = session
+            .prepare(
+                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username
+                 FROM messages WHERE chat_id = ? AND created_at = ? AND message_id > ? LIMIT ?",
+            )
+            .await?;
+
         let update_content_stmt = session
             .prepare(
                 "UPDATE messages SET content = ?, updated_at = ?
@@ -193,6 +322,14 @@ impl ChatMessageStore {
             )
             .await?;
 
+        let update_content_if_stmt = session
+            .prepare(
+                "UPDATE messages SET content = ?, updated_at = ?
+                 WHERE chat_id = ? AND created_at = ? AND message_id = ?
+                 IF updated_at = ?",
+            )
+            .await?;
+
         let delete_stmt = session
             .prepare(
                 "UPDATE messages SET is_deleted = true, updated_at = ?
@@ -200,6 +337,64 @@ impl ChatMessageStore {
             )
             .await?;
 
+        let upsert_profile_stmt = session
+            .prepare("INSERT INTO user_profiles (username_lower, user_id, username) VALUES (?, ?, ?)")
+            .await?;
+
+        let resolve_usernames_stmt = session
+            .prepare("SELECT username_lower, user_id FROM user_profiles WHERE username_lower IN ?")
+            .await?;
+
+        let insert_outbox_stmt = session
+            .prepare(
+                "INSERT INTO outbox_events (topic, bucket, event_id, key, payload, headers)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .await?;
+
+        let pending_outbox_stmt = session
+            .prepare("SELECT event_id, key, payload, headers FROM outbox_events WHERE topic = ? AND bucket = ? LIMIT ?")
+            .await?;
+
+        let delete_outbox_stmt = session
+            .prepare("DELETE FROM outbox_events WHERE topic = ? AND bucket = ? AND event_id = ?")
+            .await?;
+
+        let save_checkpoint_stmt = session
+            .prepare("INSERT INTO consumer_checkpoints (id, blob, written_at) VALUES (?, ?, ?)")
+            .await?;
+
+        let load_checkpoint_stmt = session.prepare("SELECT blob FROM consumer_checkpoints WHERE id = ?").await?;
+
+        let get_user_msgs_stmt = session
+            .prepare("SELECT message_id, chat_id, created_at FROM user_messages WHERE user_id = ? LIMIT ?")
+            .await?;
+
+        let insert_repair_stmt = session
+            .prepare(
+                "INSERT INTO index_repair (user_id, message_id, chat_id, created_at, failed_at)
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .await?;
+
+        let get_repairs_stmt = session
+            .prepare("SELECT message_id, chat_id, created_at FROM index_repair WHERE user_id = ?")
+            .await?;
+
+        let delete_repair_stmt = session
+            .prepare("DELETE FROM index_repair WHERE user_id = ? AND message_id = ?")
+            .await?;
+
+        let pending_repair_users_stmt = session.prepare("SELECT DISTINCT user_id FROM index_repair LIMIT ?").await?;
+
+        let user_index = UserIndexQueue::start(
+            Arc::clone(session),
+            insert_user_msg_stmt.clone(),
+            insert_repair_stmt,
+            config.user_index_queue_capacity,
+            config.user_index_write_attempts,
+        );
+
         Ok(Self {
             session: Arc::clone(session),
             insert_msg_stmt,
@@ -207,48 +402,131 @@ impl ChatMessageStore {
             insert_lookup_stmt,
             get_by_id_stmt,
             get_by_chat_stmt,
+            get_by_chat_before_stmt,
+            get_by_chat_same_ts_stmt,
             update_content_stmt,
+            update_content_if_stmt,
             delete_stmt,
+            upsert_profile_stmt,
+            resolve_usernames_stmt,
+            insert_outbox_stmt,
+            pending_outbox_stmt,
+            delete_outbox_stmt,
+            save_checkpoint_stmt,
+            load_checkpoint_stmt,
+            get_user_msgs_stmt,
+            get_repairs_stmt,
+            delete_repair_stmt,
+            pending_repair_users_stmt,
+            user_index,
+            topology: Topology::default(),
         })
     }
 
     pub async fn create_message(&self, chat_id: Uuid, user_id: Uuid, content: String) -> ScyllaResult<ChatMessage> {
-        let message_id = Uuid::new_v4();
-        let created_at = Utc::now();
-        let created_ts = CqlTimestamp(created_at.timestamp_millis());
+        self.create_message_with_mentions(chat_id, user_id, None, content, Vec::new())
+            .await
+    }
 
-        let message = ChatMessage {
-            message_id,
-            chat_id,
-            user_id,
-            content: content.clone(),
-            created_at,
-            updated_at: None,
-            is_deleted: false,
-        };
+    pub async fn create_message_with_mentions(
+        &self,
+        chat_id: Uuid,
+        user_id: Uuid,
+        username: Option<String>,
+        content: String,
+        mentions: Vec<Uuid>,
+    ) -> ScyllaResult<ChatMessage> {
+        let message = Self::new_message(chat_id, user_id, username, content, mentions);
+        let mut batch = Batch::default();
+        batch.append_statement(self.insert_msg_stmt.clone());
+        batch.append_statement(self.insert_lookup_stmt.clone());
 
+        self.session.batch(&batch, &Self::message_values(&message)).await?;
+        self.index_message(&message);
+
+        Ok(message)
+    }
+
+    /// Like [`Self::create_message_with_mentions`], and stages the event built by `event` in `outbox_events`
+    /// within the same logged batch, so the event is stored if and only if the message is.
+    pub async fn create_message_with_event<F>(
+        &self,
+        chat_id: Uuid,
+        user_id: Uuid,
+        username: Option<String>,
+        content: String,
+        mentions: Vec<Uuid>,
+        event: F,
+    ) -> ScyllaResult<(ChatMessage, OutboxEvent)>
+    where
+        F: FnOnce(&ChatMessage) -> serde_json::Result<OutboxEvent>,
+    {
+        let message = Self::new_message(chat_id, user_id, username, content, mentions);
+        let event = event(&message)?;
         let mut batch = Batch::default();
         batch.append_statement(self.insert_msg_stmt.clone());
-        batch.append_statement(self.insert_user_msg_stmt.clone());
         batch.append_statement(self.insert_lookup_stmt.clone());
+        batch.append_statement(self.insert_outbox_stmt.clone());
+
+        let (msg_values, lookup_values) = Self::message_values(&message);
+        let outbox_values = (
+            event.topic.as_str(),
+            event.bucket(),
+            event.event_id,
+            event.key.as_str(),
+            event.payload.as_str(),
+            &event.headers,
+        );
+        self.session
+            .batch(&batch, &(msg_values, lookup_values, outbox_values))
+            .await?;
+        self.index_message(&message);
 
-        let batch_values = (
+        Ok((message, event))
+    }
+
+    /// Hands the `user_messages` entry to the background writer; see [`user_index`].
+    fn index_message(&self, message: &ChatMessage) {
+        self.user_index.enqueue(
+            message.user_id,
+            UserMessageRef {
+                message_id: message.message_id,
+                chat_id: message.chat_id,
+                created_at: message.created_at,
+            },
+        );
+    }
+
+    fn new_message(chat_id: Uuid, user_id: Uuid, username: Option<String>, content: String, mentions: Vec<Uuid>) -> ChatMessage {
+        ChatMessage {
+            message_id: Uuid::new_v4(),
+            chat_id,
+            user_id,
+            content,
+            created_at: Utc::now(),
+            updated_at: None,
+            is_deleted: false,
+            mentions,
+            username,
+        }
+    }
+
+    fn message_values(message: &ChatMessage) -> NewMessageValues<'_> {
+        let created_ts = CqlTimestamp(message.created_at.timestamp_millis());
+        (
             (
-                message_id,
-                chat_id,
-                user_id,
-                content.as_str(),
+                message.message_id,
+                message.chat_id,
+                message.user_id,
+                message.content.as_str(),
                 created_ts,
-                None::<CqlTimestamp>,
+                None,
                 false,
+                message.mentions.as_slice(),
+                message.username.as_deref(),
             ),
-            (user_id, created_ts, message_id, chat_id),
-            (message_id, chat_id, created_ts),
-        );
-
-        self.session.batch(&batch, &batch_values).await?;
-
-        Ok(message)
+            (message.message_id, message.chat_id, created_ts),
+        )
     }
 
     pub async fn get_message(&self, message_id: Uuid) -> ScyllaResult<Option<ChatMessage>> {
@@ -264,29 +542,14 @@ impl ChatMessageStore {
         let msg_result = self
             .session
             .query_unpaged(
-                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted
+                "SELECT message_id, chat_id, user_id, content, created_at, updated_at, is_deleted, mentions, username
                  FROM messages WHERE chat_id = ? AND created_at = ? AND message_id = ?",
                 (chat_id, created_cql, message_id),
             )
             .await?;
 
         let msg_rows = msg_result.into_rows_result()?;
-
-        if let Some((message_id, chat_id, user_id, content, created_at, updated_at, is_deleted)) =
-            msg_rows.maybe_first_row::<(Uuid, Uuid, Uuid, String, DateTime<Utc>, Option<DateTime<Utc>>, bool)>()?
-        {
-            return Ok(Some(ChatMessage {
-                message_id,
-                chat_id,
-                user_id,
-                content,
-                created_at,
-                updated_at,
-                is_deleted,
-            }));
-        }
-
-        Ok(None)
+        Ok(msg_rows.maybe_first_row::<MessageRow>()?.map(ChatMessage::from))
     }
 
     pub async fn get_chat_messages(&self, chat_id: Uuid, limit: i32) -> ScyllaResult<Vec<ChatMessage>> {
@@ -295,18 +558,8 @@ impl ChatMessageStore {
         let rows_result = query_result.into_rows_result()?;
         let mut messages = Vec::new();
 
-        for row in rows_result.rows::<(Uuid, Uuid, Uuid, String, DateTime<Utc>, Option<DateTime<Utc>>, bool)>()? {
-            let (message_id, chat_id, user_id, content, created_at, updated_at, is_deleted) = row?;
-
-            messages.push(ChatMessage {
-                message_id,
-                chat_id,
-                user_id,
-                content,
-                created_at,
-                updated_at,
-                is_deleted,
-            });
+        for row in rows_result.rows::<MessageRow>()? {
+            messages.push(ChatMessage::from(row?));
         }
 
         Ok(messages)
@@ -329,41 +582,160 @@ impl ChatMessageStore {
         let rows_result = query_result.into_rows_result()?;
         let mut messages = Vec::new();
 
-        for row in rows_result.rows::<(Uuid, Uuid, Uuid, String, DateTime<Utc>, Option<DateTime<Utc>>, bool)>()? {
-            let (message_id, chat_id, user_id, content, created_at, updated_at, is_deleted) = row?;
-
-            messages.push(ChatMessage {
-                message_id,
-                chat_id,
-                user_id,
-                content,
-                created_at,
-                updated_at,
-                is_deleted,
-            });
+        for row in rows_result.rows::<MessageRow>()? {
+            messages.push(ChatMessage::from(row?));
         }
 
         Ok((messages, paging_response))
     }
 
+    /// Up to `limit` messages of `chat_id` that follow the message (`before_created_at`, `before_message_id`) in
+    /// history order, newest first; passing the last message of a page returns the next older one.
+    ///
+    /// Messages created in the same millisecond as the cursor are ordered by id, so they are read separately
+    /// and not skipped.
+    pub async fn get_chat_messages_before(
+        &self,
+        chat_id: Uuid,
+        before_created_at: DateTime<Utc>,
+        before_message_id: Uuid,
+        limit: i32,
+    ) -> ScyllaResult<Vec<ChatMessage>> {
+        let before_ts = CqlTimestamp(before_created_at.timestamp_millis());
+        let same_ts = self
+            .session
+            .execute_unpaged(&self.get_by_chat_same_ts_stmt, (chat_id, before_ts, before_message_id, limit))
+            .await?;
+        let mut messages = Vec::new();
+        for row in same_ts.into_rows_result()?.rows::<MessageRow>()? {
+            messages.push(ChatMessage::from(row?));
+        }
+
+        let remaining = limit - messages.len() as i32;
+        if remaining > 0 {
+            let older = self
+                .session
+                .execute_unpaged(&self.get_by_chat_before_stmt, (chat_id, before_ts, remaining))
+                .await?;
+            for row in older.into_rows_result()?.rows::<MessageRow>()? {
+                messages.push(ChatMessage::from(row?));
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Up to `limit` non-deleted messages of `chat_id`, newest first, starting after `before` (see
+    /// [`Self::get_chat_messages_before`]) or at the newest message. Pages past deleted rows until `limit` visible
+    /// ones are found, the chat is exhausted or [`VISIBLE_SCAN_BUDGET`] rows were read; in the last case fewer
+    /// messages come back with a `next_before` to continue from.
+    pub async fn get_visible_chat_messages(
+        &self,
+        chat_id: Uuid,
+        limit: usize,
+        before: Option<(DateTime<Utc>, Uuid)>,
+    ) -> ScyllaResult<VisibleMessages> {
+        let mut messages = Vec::with_capacity(limit);
+        let mut cursor = before;
+        let mut scanned = 0;
+        while messages.len() < limit {
+            let page_size = limit.min(VISIBLE_SCAN_BUDGET - scanned);
+            if page_size == 0 {
+                break;
+            }
+            let page = match cursor {
+                None => self.get_chat_messages(chat_id, page_size as i32).await?,
+                Some((created_at, message_id)) => {
+                    self.get_chat_messages_before(chat_id, created_at, message_id, page_size as i32)
+                        .await?
+                }
+            };
+            let exhausted = page.len() < page_size;
+            for message in page {
+                if messages.len() == limit {
+                    break;
+                }
+                scanned += 1;
+                cursor = Some((message.created_at, message.message_id));
+                if !message.is_deleted {
+                    messages.push(message);
+                }
+            }
+            if exhausted && messages.len() < limit {
+                cursor = None;
+                break;
+            }
+        }
+        Ok(VisibleMessages {
+            messages,
+            next_before: cursor,
+        })
+    }
+
+    /// Replaces the content of a message. With `expected_updated_at` the write is a lightweight transaction that
+    /// only applies while the message's `updated_at` still matches it, `created_at` standing for a message that
+    /// was never edited; otherwise the last write wins.
+    ///
+    /// A conditional write that times out may still have been applied and fails with
+    /// [`ScyllaError::LwtTimeout`]; re-read the message before retrying it.
     pub async fn update_message(
         &self,
         chat_id: Uuid,
         created_at: DateTime<Utc>,
         message_id: Uuid,
         new_content: String,
-    ) -> ScyllaResult<()> {
-        let updated_ts = CqlTimestamp(Utc::now().timestamp_millis());
+        expected_updated_at: Option<DateTime<Utc>>,
+    ) -> ScyllaResult<UpdateOutcome> {
         let created_ts = CqlTimestamp(created_at.timestamp_millis());
 
-        self.session
+        let Some(expected) = expected_updated_at else {
+            let updated_at = Utc::now();
+            self.session
+                .execute_unpaged(
+                    &self.update_content_stmt,
+                    (
+                        new_content.as_str(),
+                        CqlTimestamp(updated_at.timestamp_millis()),
+                        chat_id,
+                        created_ts,
+                        message_id,
+                    ),
+                )
+                .await?;
+            return Ok(UpdateOutcome::Updated { updated_at });
+        };
+
+        // Timestamps are stored in milliseconds; an edit within the same one as the previous must still move
+        // `updated_at`, or a second writer holding the old value would pass the condition.
+        let updated_at = Utc::now().max(expected + TimeDelta::milliseconds(1));
+        let expected_ts = (expected != created_at).then(|| CqlTimestamp(expected.timestamp_millis()));
+        let result = self
+            .session
             .execute_unpaged(
-                &self.update_content_stmt,
-                (new_content.as_str(), updated_ts, chat_id, created_ts, message_id),
+                &self.update_content_if_stmt,
+                (
+                    new_content.as_str(),
+                    CqlTimestamp(updated_at.timestamp_millis()),
+                    chat_id,
+                    created_ts,
+                    message_id,
+                    expected_ts,
+                ),
             )
-            .await?;
-
-        Ok(())
+            .await
+            .map_err(lwt_error)?;
+
+        let applied = result
+            .into_rows_result()?
+            .maybe_first_row::<Row>()?
+            .and_then(|row| row.columns.into_iter().next().flatten());
+        if matches!(applied, Some(CqlValue::Boolean(true))) {
+            return Ok(UpdateOutcome::Updated { updated_at });
+        }
+        Ok(match self.get_message(message_id).await? {
+            Some(current) => UpdateOutcome::Conflict { current },
+            None => UpdateOutcome::NotFound,
+        })
     }
 
     pub async fn delete_message(&self, chat_id: Uuid, created_at: DateTime<Utc>, message_id: Uuid) -> ScyllaResult<()> {
@@ -377,6 +749,34 @@ impl ChatMessageStore {
         Ok(())
     }
 
+    /// Records `username` for mention lookups; usernames are matched case-insensitively.
+    pub async fn upsert_user_profile(&self, user_id: Uuid, username: &str) -> ScyllaResult<()> {
+        self.session
+            .execute_unpaged(&self.upsert_profile_stmt, (username.to_lowercase(), user_id, username))
+            .await?;
+        Ok(())
+    }
+
+    /// Maps each known username (lowercased) to its user ID in one query; unknown names are absent.
+    pub async fn resolve_usernames(&self, usernames: &[String]) -> ScyllaResult<HashMap<String, Uuid>> {
+        if usernames.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let lowered: Vec<String> = usernames.iter().map(|name| name.to_lowercase()).collect();
+        let rows = self
+            .session
+            .execute_unpaged(&self.resolve_usernames_stmt, (lowered,))
+            .await?
+            .into_rows_result()?;
+
+        let mut resolved = HashMap::with_capacity(usernames.len());
+        for row in rows.rows::<(String, Uuid)>()? {
+            let (username, user_id) = row?;
+            resolved.insert(username, user_id);
+        }
+        Ok(resolved)
+    }
+
     pub fn session(&self) -> &Session {
         &self.session
     }
@@ -388,6 +788,91 @@ impl ChatMessageStore {
     pub fn get_metrics(&self) -> Arc<Metrics> {
         self.session.get_metrics()
     }
+
+    /// Re-reads the driver's cluster state, which it refreshes every `metadata_refresh_interval`,
+    /// and logs node additions, removals and up/down transitions since the last call.
+    pub fn refresh_topology(&self) -> Vec<NodeEvent> {
+        let cluster = self.session.get_cluster_state();
+        let nodes = cluster
+            .get_nodes_info()
+            .iter()
+            .map(|node| NodeStatus::from(&**node))
+            .collect();
+        self.topology.update(nodes)
+    }
+
+    pub fn nodes(&self) -> Vec<NodeStatus> {
+        self.topology.nodes()
+    }
+
+    pub fn live_nodes(&self) -> usize {
+        self.topology.live_nodes()
+    }
+}
+
+/// Session with the consistency the replication factor allows and the driver's default retries.
+pub(crate) async fn connect(config: &ScyllaConfig) -> ScyllaResult<Session> {
+    let consistency = if config.replication_factor <= 1 {
+        Consistency::One
+    } else {
+        Consistency::LocalQuorum
+    };
+
+    let profile = ExecutionProfileBuilder::default()
+        .consistency(consistency)
+        .retry_policy(Arc::new(DefaultRetryPolicy::new()))
+        .build();
+
+    let mut builder = SessionBuilder::new()
+        .known_node(&config.uri)
+        .connection_timeout(config.connection_timeout)
+        .cluster_metadata_refresh_interval(config.metadata_refresh_interval)
+        .default_execution_profile_handle(profile.into_handle());
+
+    for node in &config.additional_nodes {
+        tracing::info!("Adding node: {}", node);
+        builder = builder.known_node(node);
+    }
+
+    Ok(builder.build().await?)
+}
+
+/// Creates `keyspace` if needed and makes it the session's current one.
+pub(crate) async fn create_keyspace(session: &Session, keyspace: &str, replication_factor: u8) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            format!(
+                "CREATE KEYSPACE IF NOT EXISTS {keyspace} \
+                 WITH REPLICATION = {{'class': 'SimpleStrategy', 'replication_factor': {replication_factor}}}"
+            ),
+            &[],
+        )
+        .await?;
+
+    session.query_unpaged(format!("USE {keyspace}"), &[]).await?;
+    Ok(())
+}
+
+/// Separates timeouts, after which a conditional write may or may not have been applied, from other failures.
+fn lwt_error(e: ExecutionError) -> ScyllaError {
+    let timed_out = match &e {
+        ExecutionError::RequestTimeout(_) => true,
+        // The Paxos round times out as a read in its prepare phase and as a write in the later ones.
+        ExecutionError::LastAttemptError(RequestAttemptError::DbError(db_error, _)) => matches!(
+            db_error,
+            DbError::WriteTimeout { .. }
+                | DbError::ReadTimeout {
+                    consistency: Consistency::Serial | Consistency::LocalSerial,
+                    ..
+                }
+        ),
+        _ => false,
+    };
+    if timed_out {
+        ScyllaError::LwtTimeout(e)
+    } else {
+        ScyllaError::Lwt(e)
+    }
 }
 
 impl Drop for ChatMessageStore {
@@ -0,0 +1,129 @@
+use crate::{ChatMessageStore, error::ScyllaResult};
+use scylla::client::session::Session;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Partitions per topic in `outbox_events`. Events are spread over them by key, so each key keeps its order
+/// while published rows (deleted, hence tombstones) are not all piled up in one partition.
+pub const OUTBOX_BUCKETS: i32 = 16;
+
+/// An event waiting in `outbox_events` to be produced to `topic`, staged in the same batch as the write it
+/// describes so that one is never stored without the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEvent {
+    /// Time-ordered, so rows of a bucket are read back in the order they were staged.
+    pub event_id: Uuid,
+    pub topic: String,
+    pub key: String,
+    /// JSON-encoded message body.
+    pub payload: String,
+    pub headers: HashMap<String, String>,
+}
+
+type OutboxRow = (Uuid, String, String, Option<HashMap<String, String>>);
+
+impl OutboxEvent {
+    pub fn new<T: Serialize>(topic: &str, key: &str, payload: &T) -> serde_json::Result<Self> {
+        Ok(Self {
+            event_id: Uuid::now_v7(),
+            topic: topic.to_owned(),
+            key: key.to_owned(),
+            payload: serde_json::to_string(payload)?,
+            headers: HashMap::new(),
+        })
+    }
+
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn bucket(&self) -> i32 {
+        bucket_for(&self.key)
+    }
+}
+
+/// FNV-1a of the key; must stay stable across releases since rows already written were placed with it.
+fn bucket_for(key: &str) -> i32 {
+    let hash = key
+        .bytes()
+        .fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    (hash % OUTBOX_BUCKETS as u32) as i32
+}
+
+/// Deleting published rows leaves tombstones that every poll of the bucket has to skip, so they are purged
+/// after an hour rather than the default ten days. A row resurrected by a replica that missed the delete is
+/// only published again, which at-least-once consumers already tolerate.
+pub(crate) async fn migrate(session: &Session) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS outbox_events (
+                topic TEXT,
+                bucket INT,
+                event_id UUID,
+                key TEXT,
+                payload TEXT,
+                headers MAP<TEXT, TEXT>,
+                PRIMARY KEY ((topic, bucket), event_id)
+            ) WITH gc_grace_seconds = 3600",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+impl ChatMessageStore {
+    /// Up to `limit` unpublished events of one bucket of `topic`, oldest first.
+    pub async fn pending_outbox_events(&self, topic: &str, bucket: i32, limit: i32) -> ScyllaResult<Vec<OutboxEvent>> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.pending_outbox_stmt, (topic, bucket, limit))
+            .await?
+            .into_rows_result()?;
+
+        let mut events = Vec::new();
+        for row in rows.rows::<OutboxRow>()? {
+            let (event_id, key, payload, headers) = row?;
+            events.push(OutboxEvent {
+                event_id,
+                topic: topic.to_owned(),
+                key,
+                payload,
+                headers: headers.unwrap_or_default(),
+            });
+        }
+        Ok(events)
+    }
+
+    /// Removes a produced event from the outbox.
+    pub async fn mark_outbox_published(&self, event: &OutboxEvent) -> ScyllaResult<()> {
+        self.session
+            .execute_unpaged(
+                &self.delete_outbox_stmt,
+                (event.topic.as_str(), event.bucket(), event.event_id),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_stable_and_in_range() {
+        assert_eq!(bucket_for("chat-1"), bucket_for("chat-1"));
+        assert!((0..1000).all(|i| (0..OUTBOX_BUCKETS).contains(&bucket_for(&i.to_string()))));
+    }
+
+    #[test]
+    fn events_are_time_ordered() {
+        let first = OutboxEvent::new("messages", "chat-1", &1).unwrap();
+        let second = OutboxEvent::new("messages", "chat-1", &2).unwrap();
+        assert!(first.event_id < second.event_id);
+        assert_eq!(first.bucket(), second.bucket());
+        assert_eq!(second.payload, "2");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{ScyllaConfig, connect, create_keyspace, error::ScyllaResult};
+use scylla::{client::session::Session, statement::prepared::PreparedStatement, value::Counter};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// What a user has stored, and the limit set for them in particular.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub used_bytes: i64,
+    /// From `user_quota_overrides`; `None` means the service's default applies.
+    pub limit_override: Option<i64>,
+}
+
+/// `user_quotas` is a counter table, changed by the size of every stored or deleted object;
+/// `user_quota_overrides` holds the limits of users not on the default one.
+pub(crate) async fn migrate(session: &Session) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS user_quotas (
+                user_id UUID,
+                used_bytes COUNTER,
+                PRIMARY KEY (user_id)
+            )",
+            &[],
+        )
+        .await?;
+
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS user_quota_overrides (
+                user_id UUID,
+                limit_bytes BIGINT,
+                PRIMARY KEY (user_id)
+            )",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Per-user storage usage and quota overrides, in the keyspace of [`ScyllaConfig`].
+pub struct QuotaStore {
+    session: Arc<Session>,
+    get_used_stmt: PreparedStatement,
+    add_used_stmt: PreparedStatement,
+    get_override_stmt: PreparedStatement,
+    set_override_stmt: PreparedStatement,
+    delete_override_stmt: PreparedStatement,
+}
+
+impl QuotaStore {
+    pub async fn new(config: &ScyllaConfig, run_migrations: bool) -> ScyllaResult<Self> {
+        let session = Arc::new(connect(config).await?);
+
+        if run_migrations {
+            create_keyspace(&session, &config.keyspace, config.replication_factor).await?;
+            migrate(&session).await?;
+        }
+        session.query_unpaged(format!("USE {}", config.keyspace), &[]).await?;
+
+        Ok(Self {
+            get_used_stmt: session
+                .prepare("SELECT used_bytes FROM user_quotas WHERE user_id = ?")
+                .await?,
+            add_used_stmt: session
+                .prepare("UPDATE user_quotas SET used_bytes = used_bytes + ? WHERE user_id = ?")
+                .await?,
+            get_override_stmt: session
+                .prepare("SELECT limit_bytes FROM user_quota_overrides WHERE user_id = ?")
+                .await?,
+            set_override_stmt: session
+                .prepare("INSERT INTO user_quota_overrides (user_id, limit_bytes) VALUES (?, ?)")
+                .await?,
+            delete_override_stmt: session.prepare("DELETE FROM user_quota_overrides WHERE user_id = ?").await?,
+            session,
+        })
+    }
+
+    pub async fn usage(&self, user_id: Uuid) -> ScyllaResult<QuotaUsage> {
+        let used = self
+            .session
+            .execute_unpaged(&self.get_used_stmt, (user_id,))
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<(Option<Counter>,)>()?;
+        let limit = self
+            .session
+            .execute_unpaged(&self.get_override_stmt, (user_id,))
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<(Option<i64>,)>()?;
+        Ok(QuotaUsage {
+            used_bytes: used.and_then(|(used,)| used).map_or(0, |Counter(used)| used),
+            limit_override: limit.and_then(|(limit,)| limit),
+        })
+    }
+
+    /// Adds `delta` bytes to the user's usage; negative for deletes. Counter updates are not idempotent,
+    /// so a retried call counts twice.
+    pub async fn add_used(&self, user_id: Uuid, delta: i64) -> ScyllaResult<()> {
+        self.session
+            .execute_unpaged(&self.add_used_stmt, (Counter(delta), user_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the user's own limit, or removes it with `None` so the default applies again.
+    pub async fn set_limit_override(&self, user_id: Uuid, limit_bytes: Option<i64>) -> ScyllaResult<()> {
+        match limit_bytes {
+            Some(limit) => {
+                self.session
+                    .execute_unpaged(&self.set_override_stmt, (user_id, limit))
+                    .await?
+            }
+            None => self.session.execute_unpaged(&self.delete_override_stmt, (user_id,)).await?,
+        };
+        Ok(())
+    }
+
+    pub async fn health_check(&self) -> bool {
+        self.session.query_unpaged("SELECT key FROM system.local", &[]).await.is_ok()
+    }
+}
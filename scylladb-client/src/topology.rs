@@ -0,0 +1,139 @@
+use scylla::cluster::Node;
+use serde::Serialize;
+use std::{collections::HashMap, sync::RwLock};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NodeStatus {
+    pub host_id: Uuid,
+    pub address: String,
+    pub datacenter: Option<String>,
+    /// The driver holds at least one open connection to the node.
+    pub up: bool,
+}
+
+impl From<&Node> for NodeStatus {
+    fn from(node: &Node) -> Self {
+        Self {
+            host_id: node.host_id,
+            address: node.address.to_string(),
+            datacenter: node.datacenter.clone(),
+            up: node.is_connected(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    Added(NodeStatus),
+    Removed(NodeStatus),
+    Up(NodeStatus),
+    Down(NodeStatus),
+}
+
+/// Transitions between two observations of the cluster, keyed by host ID.
+pub fn diff(previous: &[NodeStatus], current: &[NodeStatus]) -> Vec<NodeEvent> {
+    let before: HashMap<Uuid, &NodeStatus> = previous.iter().map(|n| (n.host_id, n)).collect();
+    let mut events = Vec::new();
+
+    for node in current {
+        match before.get(&node.host_id) {
+            None => events.push(NodeEvent::Added(node.clone())),
+            Some(old) if old.up && !node.up => events.push(NodeEvent::Down(node.clone())),
+            Some(old) if !old.up && node.up => events.push(NodeEvent::Up(node.clone())),
+            Some(_) => {}
+        }
+    }
+    for node in previous {
+        if !current.iter().any(|n| n.host_id == node.host_id) {
+            events.push(NodeEvent::Removed(node.clone()));
+        }
+    }
+    events
+}
+
+/// Last observed node list, refreshed by [`ChatMessageStore::refresh_topology`](crate::ChatMessageStore::refresh_topology).
+#[derive(Default)]
+pub struct Topology {
+    nodes: RwLock<Vec<NodeStatus>>,
+}
+
+impl Topology {
+    pub fn nodes(&self) -> Vec<NodeStatus> {
+        self.nodes.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn live_nodes(&self) -> usize {
+        self.nodes
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|n| n.up)
+            .count()
+    }
+
+    /// Stores `current` and logs every transition since the previous observation.
+    pub fn update(&self, mut current: Vec<NodeStatus>) -> Vec<NodeEvent> {
+        current.sort_by_key(|n| n.host_id);
+        let mut nodes = self.nodes.write().unwrap_or_else(|e| e.into_inner());
+        let events = diff(&nodes, &current);
+        for event in &events {
+            log_event(event);
+        }
+        *nodes = current;
+        events
+    }
+}
+
+fn log_event(event: &NodeEvent) {
+    match event {
+        NodeEvent::Added(n) => tracing::info!(host_id = %n.host_id, address = %n.address, up = n.up, "Scylla node added"),
+        NodeEvent::Removed(n) => tracing::warn!(host_id = %n.host_id, address = %n.address, "Scylla node removed"),
+        NodeEvent::Up(n) => tracing::info!(host_id = %n.host_id, address = %n.address, "Scylla node up"),
+        NodeEvent::Down(n) => tracing::warn!(host_id = %n.host_id, address = %n.address, "Scylla node down"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u128, up: bool) -> NodeStatus {
+        NodeStatus {
+            host_id: Uuid::from_u128(id),
+            address: format!("10.0.0.{id}:9042"),
+            datacenter: Some("dc1".into()),
+            up,
+        }
+    }
+
+    #[test]
+    fn first_observation_adds_every_node() {
+        let events = diff(&[], &[node(1, true), node(2, false)]);
+        assert_eq!(events, [NodeEvent::Added(node(1, true)), NodeEvent::Added(node(2, false))]);
+    }
+
+    #[test]
+    fn status_flips_are_up_and_down_events() {
+        let events = diff(&[node(1, true), node(2, false)], &[node(1, false), node(2, true)]);
+        assert_eq!(events, [NodeEvent::Down(node(1, false)), NodeEvent::Up(node(2, true))]);
+    }
+
+    #[test]
+    fn departed_nodes_are_removed_and_unchanged_nodes_are_quiet() {
+        let events = diff(&[node(1, true), node(2, true)], &[node(1, true), node(3, true)]);
+        assert_eq!(events, [NodeEvent::Added(node(3, true)), NodeEvent::Removed(node(2, true))]);
+    }
+
+    #[test]
+    fn topology_tracks_live_count() {
+        let topology = Topology::default();
+        topology.update(vec![node(2, true), node(1, false)]);
+        assert_eq!(topology.live_nodes(), 1);
+        assert_eq!(topology.nodes()[0].host_id, Uuid::from_u128(1));
+
+        let events = topology.update(vec![node(1, true), node(2, true)]);
+        assert_eq!(events, [NodeEvent::Up(node(1, true))]);
+        assert_eq!(topology.live_nodes(), 2);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{ChatMessageStore, error::ScyllaResult};
+use chrono::{DateTime, Utc};
+use scylla::{client::session::Session, statement::prepared::PreparedStatement, value::CqlTimestamp};
+use serde::Serialize;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinSet,
+};
+use uuid::Uuid;
+
+/// Delay before the first retry of an index write; doubled for every further attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+/// Entries written concurrently by the background writer.
+const WRITE_CONCURRENCY: usize = 64;
+
+/// A row of `user_messages`: where to find a message a user sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct UserMessageRef {
+    pub message_id: Uuid,
+    pub chat_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Counters of the `user_messages` write-behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UserIndexStats {
+    /// Entries queued or being written, including their retries.
+    pub queue_depth: u64,
+    pub written: u64,
+    pub retries: u64,
+    /// Entries given up on and recorded in `index_repair`.
+    pub failed: u64,
+    /// Entries whose `index_repair` record could not be written either; only a full rebuild finds them.
+    pub lost: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    pending: AtomicU64,
+    written: AtomicU64,
+    retries: AtomicU64,
+    failed: AtomicU64,
+    lost: AtomicU64,
+}
+
+struct IndexEntry {
+    user_id: Uuid,
+    message: UserMessageRef,
+}
+
+struct IndexWriter {
+    session: Arc<Session>,
+    insert_stmt: PreparedStatement,
+    insert_repair_stmt: PreparedStatement,
+    max_attempts: u32,
+    counters: Counters,
+}
+
+/// Writes `user_messages` entries behind the message inserts, so creating a message waits for the primary
+/// rows only. A full queue or an entry that keeps failing ends up in `index_repair`, from where
+/// [`ChatMessageStore::repair_user_index`] backfills it.
+pub(crate) struct UserIndexQueue {
+    tx: mpsc::Sender<IndexEntry>,
+    writer: Arc<IndexWriter>,
+}
+
+impl UserIndexQueue {
+    /// Spawns the background writer, which stops once the queue is dropped and drained.
+    pub(crate) fn start(
+        session: Arc<Session>,
+        insert_stmt: PreparedStatement,
+        insert_repair_stmt: PreparedStatement,
+        capacity: usize,
+        max_attempts: u32,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let writer = Arc::new(IndexWriter {
+            session,
+            insert_stmt,
+            insert_repair_stmt,
+            max_attempts: max_attempts.max(1),
+            counters: Counters::default(),
+        });
+        tokio::spawn(Arc::clone(&writer).run(rx));
+        Self { tx, writer }
+    }
+
+    pub(crate) fn enqueue(&self, user_id: Uuid, message: UserMessageRef) {
+        let entry = IndexEntry { user_id, message };
+        self.writer.counters.pending.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.tx.try_send(entry) {
+            self.writer.counters.pending.fetch_sub(1, Ordering::Relaxed);
+            let entry = match e {
+                TrySendError::Full(entry) => {
+                    tracing::warn!(user_id = %entry.user_id, "User index queue is full, recording the entry for repair");
+                    entry
+                }
+                TrySendError::Closed(entry) => entry,
+            };
+            tokio::spawn(Arc::clone(&self.writer).record_miss(entry));
+        }
+    }
+
+    pub(crate) fn stats(&self) -> UserIndexStats {
+        let counters = &self.writer.counters;
+        UserIndexStats {
+            queue_depth: counters.pending.load(Ordering::Relaxed),
+            written: counters.written.load(Ordering::Relaxed),
+            retries: counters.retries.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            lost: counters.lost.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl IndexWriter {
+    async fn run(self: Arc<Self>, mut rx: mpsc::Receiver<IndexEntry>) {
+        let mut entries = Vec::with_capacity(WRITE_CONCURRENCY);
+        while rx.recv_many(&mut entries, WRITE_CONCURRENCY).await > 0 {
+            let mut writes = JoinSet::new();
+            for entry in entries.drain(..) {
+                writes.spawn(Arc::clone(&self).write(entry));
+            }
+            writes.join_all().await;
+        }
+        tracing::info!("User index writer stopped");
+    }
+
+    async fn write(self: Arc<Self>, entry: IndexEntry) {
+        self.write_with_retries(entry).await;
+        self.counters.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    async fn write_with_retries(self: &Arc<Self>, entry: IndexEntry) {
+        let mut backoff = RETRY_BACKOFF;
+        for attempt in 1..=self.max_attempts {
+            match self.session.execute_unpaged(&self.insert_stmt, entry_values(&entry)).await {
+                Ok(_) => {
+                    self.counters.written.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Err(e) if attempt < self.max_attempts => {
+                    tracing::debug!(user_id = %entry.user_id, attempt, "User index write failed, retrying: {e}");
+                    self.counters.retries.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    tracing::warn!(user_id = %entry.user_id, attempts = attempt, "Giving up on user index write: {e}");
+                }
+            }
+        }
+        Arc::clone(self).record_miss(entry).await;
+    }
+
+    async fn record_miss(self: Arc<Self>, entry: IndexEntry) {
+        let values = (
+            entry.user_id,
+            entry.message.message_id,
+            entry.message.chat_id,
+            CqlTimestamp(entry.message.created_at.timestamp_millis()),
+            CqlTimestamp(Utc::now().timestamp_millis()),
+        );
+        match self.session.execute_unpaged(&self.insert_repair_stmt, values).await {
+            Ok(_) => {
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                self.counters.lost.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    user_id = %entry.user_id,
+                    message_id = %entry.message.message_id,
+                    "Failed to record missing user index entry: {e}"
+                );
+            }
+        }
+    }
+}
+
+fn entry_values(entry: &IndexEntry) -> (Uuid, CqlTimestamp, Uuid, Uuid) {
+    (
+        entry.user_id,
+        CqlTimestamp(entry.message.created_at.timestamp_millis()),
+        entry.message.message_id,
+        entry.message.chat_id,
+    )
+}
+
+pub(crate) async fn migrate(session: &Session) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS index_repair (
+                user_id UUID,
+                message_id UUID,
+                chat_id UUID,
+                created_at TIMESTAMP,
+                failed_at TIMESTAMP,
+                PRIMARY KEY ((user_id), message_id)
+            )",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+impl ChatMessageStore {
+    pub fn user_index_stats(&self) -> UserIndexStats {
+        self.user_index.stats()
+    }
+
+    /// Up to `limit` newest entries of `user_id` in `user_messages`.
+    pub async fn get_user_messages(&self, user_id: Uuid, limit: i32) -> ScyllaResult<Vec<UserMessageRef>> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.get_user_msgs_stmt, (user_id, limit))
+            .await?
+            .into_rows_result()?;
+        let refs = rows
+            .rows::<(Uuid, Uuid, DateTime<Utc>)>()?
+            .map(|row| {
+                row.map(|(message_id, chat_id, created_at)| UserMessageRef {
+                    message_id,
+                    chat_id,
+                    created_at,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(refs)
+    }
+
+    /// Users with entries waiting in `index_repair`, for a repair job to pass to [`Self::repair_user_index`].
+    pub async fn users_pending_index_repair(&self, limit: i32) -> ScyllaResult<Vec<Uuid>> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.pending_repair_users_stmt, (limit,))
+            .await?
+            .into_rows_result()?;
+        Ok(rows
+            .rows::<(Uuid,)>()?
+            .map(|row| row.map(|(user_id,)| user_id))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Writes the `user_messages` entries of `user_id` recorded in `index_repair` and clears them there;
+    /// returns how many were restored.
+    pub async fn repair_user_index(&self, user_id: Uuid) -> ScyllaResult<usize> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.get_repairs_stmt, (user_id,))
+            .await?
+            .into_rows_result()?;
+        let missing = rows.rows::<(Uuid, Uuid, DateTime<Utc>)>()?.collect::<Result<Vec<_>, _>>()?;
+
+        for &(message_id, chat_id, created_at) in &missing {
+            let entry = IndexEntry {
+                user_id,
+                message: UserMessageRef {
+                    message_id,
+                    chat_id,
+                    created_at,
+                },
+            };
+            self.session
+                .execute_unpaged(&self.insert_user_msg_stmt, entry_values(&entry))
+                .await?;
+            self.session
+                .execute_unpaged(&self.delete_repair_stmt, (user_id, message_id))
+                .await?;
+        }
+        if !missing.is_empty() {
+            tracing::info!(%user_id, restored = missing.len(), "Repaired user index");
+        }
+        Ok(missing.len())
+    }
+}
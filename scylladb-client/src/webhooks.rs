@@ -0,0 +1,342 @@
+use crate::{ScyllaConfig, connect, create_keyspace, error::ScyllaResult};
+use chrono::{DateTime, Utc};
+use scylla::{
+    client::session::Session,
+    statement::{batch::Batch, prepared::PreparedStatement},
+};
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// How long delivery attempts stay in `webhook_deliveries`.
+pub const DELIVERY_LOG_TTL_SECS: i32 = 7 * 24 * 3600;
+
+/// An endpoint notified of an account's image events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Webhook {
+    pub webhook_id: Uuid,
+    pub account_id: Uuid,
+    pub url: String,
+    /// Key of the payload signatures; never returned by the admin API.
+    #[serde(skip)]
+    pub secret: String,
+    /// Event names the endpoint receives, e.g. `image.created`.
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn new(account_id: Uuid, url: String, secret: String, mut events: Vec<String>) -> Self {
+        events.sort();
+        events.dedup();
+        Self {
+            webhook_id: Uuid::now_v7(),
+            account_id,
+            url,
+            secret,
+            events,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn subscribes_to(&self, event: &str) -> bool {
+        self.events.iter().any(|e| e == event)
+    }
+}
+
+/// One attempt to deliver an event to a webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebhookDelivery {
+    pub webhook_id: Uuid,
+    /// Time-ordered, so the log reads back newest first.
+    pub attempt_id: Uuid,
+    /// Shared by every attempt at the same event.
+    pub delivery_id: Uuid,
+    pub event: String,
+    /// 1-based; attempts skipped by an open circuit are recorded with the attempt they would have been.
+    pub attempt: i32,
+    /// HTTP status of the response, if there was one.
+    pub status_code: Option<i32>,
+    /// Why the attempt failed, when it did not get a 2xx.
+    pub error: Option<String>,
+    pub duration_ms: i64,
+    pub attempted_at: DateTime<Utc>,
+}
+
+impl WebhookDelivery {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+type WebhookRow = (Uuid, Uuid, String, String, Option<Vec<String>>, DateTime<Utc>);
+
+type DeliveryRow = (Uuid, Uuid, String, i32, Option<i32>, Option<String>, i64, DateTime<Utc>);
+
+impl From<WebhookRow> for Webhook {
+    fn from(row: WebhookRow) -> Self {
+        let (account_id, webhook_id, url, secret, events, created_at) = row;
+        Self {
+            webhook_id,
+            account_id,
+            url,
+            secret,
+            events: events.unwrap_or_default(),
+            created_at,
+        }
+    }
+}
+
+/// Webhooks are read per account for every image event, and looked up by id through `webhook_by_id`.
+/// Delivery attempts expire after [`DELIVERY_LOG_TTL_SECS`].
+pub(crate) async fn migrate(session: &Session) -> ScyllaResult<()> {
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS webhooks (
+                account_id UUID,
+                webhook_id UUID,
+                url TEXT,
+                secret TEXT,
+                events SET<TEXT>,
+                created_at TIMESTAMP,
+                PRIMARY KEY ((account_id), webhook_id)
+            )",
+            &[],
+        )
+        .await?;
+
+    session
+        .query_unpaged(
+            "CREATE TABLE IF NOT EXISTS webhook_by_id (
+                webhook_id UUID,
+                account_id UUID,
+                PRIMARY KEY (webhook_id)
+            )",
+            &[],
+        )
+        .await?;
+
+    session
+        .query_unpaged(
+            format!(
+                "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                    webhook_id UUID,
+                    attempt_id UUID,
+                    delivery_id UUID,
+                    event TEXT,
+                    attempt INT,
+                    status_code INT,
+                    error TEXT,
+                    duration_ms BIGINT,
+                    attempted_at TIMESTAMP,
+                    PRIMARY KEY ((webhook_id), attempt_id)
+                ) WITH CLUSTERING ORDER BY (attempt_id DESC) AND default_time_to_live = {DELIVERY_LOG_TTL_SECS}"
+            ),
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Webhook subscriptions and their delivery log, in the keyspace of [`ScyllaConfig`].
+pub struct WebhookStore {
+    session: Arc<Session>,
+    insert_stmt: PreparedStatement,
+    insert_by_id_stmt: PreparedStatement,
+    get_account_stmt: PreparedStatement,
+    get_by_account_stmt: PreparedStatement,
+    get_stmt: PreparedStatement,
+    delete_stmt: PreparedStatement,
+    delete_by_id_stmt: PreparedStatement,
+    insert_delivery_stmt: PreparedStatement,
+    get_deliveries_stmt: PreparedStatement,
+}
+
+impl WebhookStore {
+    pub async fn new(config: &ScyllaConfig, run_migrations: bool) -> ScyllaResult<Self> {
+        let session = Arc::new(connect(config).await?);
+
+        if run_migrations {
+            create_keyspace(&session, &config.keyspace, config.replication_factor).await?;
+            migrate(&session).await?;
+        }
+        session.query_unpaged(format!("USE {}", config.keyspace), &[]).await?;
+
+        Ok(Self {
+            insert_stmt: session
+                .prepare(
+                    "INSERT INTO webhooks (account_id, webhook_id, url, secret, events, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            insert_by_id_stmt: session
+                .prepare("INSERT INTO webhook_by_id (webhook_id, account_id) VALUES (?, ?)")
+                .await?,
+            get_account_stmt: session
+                .prepare("SELECT account_id FROM webhook_by_id WHERE webhook_id = ?")
+                .await?,
+            get_by_account_stmt: session
+                .prepare("SELECT account_id, webhook_id, url, secret, events, created_at FROM webhooks WHERE account_id = ?")
+                .await?,
+            get_stmt: session
+                .prepare(
+                    "SELECT account_id, webhook_id, url, secret, events, created_at FROM webhooks
+                     WHERE account_id = ? AND webhook_id = ?",
+                )
+                .await?,
+            delete_stmt: session
+                .prepare("DELETE FROM webhooks WHERE account_id = ? AND webhook_id = ?")
+                .await?,
+            delete_by_id_stmt: session.prepare("DELETE FROM webhook_by_id WHERE webhook_id = ?").await?,
+            insert_delivery_stmt: session
+                .prepare(
+                    "INSERT INTO webhook_deliveries
+                     (webhook_id, attempt_id, delivery_id, event, attempt, status_code, error, duration_ms, attempted_at)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            get_deliveries_stmt: session
+                .prepare(
+                    "SELECT attempt_id, delivery_id, event, attempt, status_code, error, duration_ms, attempted_at
+                     FROM webhook_deliveries WHERE webhook_id = ? LIMIT ?",
+                )
+                .await?,
+            session,
+        })
+    }
+
+    pub async fn create_webhook(&self, webhook: &Webhook) -> ScyllaResult<()> {
+        let mut batch = Batch::default();
+        batch.append_statement(self.insert_stmt.clone());
+        batch.append_statement(self.insert_by_id_stmt.clone());
+        self.session
+            .batch(
+                &batch,
+                (
+                    (
+                        webhook.account_id,
+                        webhook.webhook_id,
+                        webhook.url.as_str(),
+                        webhook.secret.as_str(),
+                        &webhook.events,
+                        webhook.created_at,
+                    ),
+                    (webhook.webhook_id, webhook.account_id),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_webhook(&self, webhook_id: Uuid) -> ScyllaResult<Option<Webhook>> {
+        let Some((account_id,)) = self
+            .session
+            .execute_unpaged(&self.get_account_stmt, (webhook_id,))
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<(Uuid,)>()?
+        else {
+            return Ok(None);
+        };
+        let row = self
+            .session
+            .execute_unpaged(&self.get_stmt, (account_id, webhook_id))
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<WebhookRow>()?;
+        Ok(row.map(Webhook::from))
+    }
+
+    pub async fn account_webhooks(&self, account_id: Uuid) -> ScyllaResult<Vec<Webhook>> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.get_by_account_stmt, (account_id,))
+            .await?
+            .into_rows_result()?;
+        let mut webhooks = Vec::new();
+        for row in rows.rows::<WebhookRow>()? {
+            webhooks.push(row?.into());
+        }
+        Ok(webhooks)
+    }
+
+    /// Removes the webhook; its delivery log is left to expire. Returns whether it existed.
+    pub async fn delete_webhook(&self, webhook_id: Uuid) -> ScyllaResult<bool> {
+        let Some(webhook) = self.get_webhook(webhook_id).await? else {
+            return Ok(false);
+        };
+        let mut batch = Batch::default();
+        batch.append_statement(self.delete_stmt.clone());
+        batch.append_statement(self.delete_by_id_stmt.clone());
+        self.session
+            .batch(&batch, ((webhook.account_id, webhook_id), (webhook_id,)))
+            .await?;
+        Ok(true)
+    }
+
+    pub async fn record_delivery(&self, delivery: &WebhookDelivery) -> ScyllaResult<()> {
+        self.session
+            .execute_unpaged(
+                &self.insert_delivery_stmt,
+                (
+                    delivery.webhook_id,
+                    delivery.attempt_id,
+                    delivery.delivery_id,
+                    delivery.event.as_str(),
+                    delivery.attempt,
+                    delivery.status_code,
+                    delivery.error.as_deref(),
+                    delivery.duration_ms,
+                    delivery.attempted_at,
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The latest `limit` attempts at delivering to `webhook_id`, newest first.
+    pub async fn deliveries(&self, webhook_id: Uuid, limit: i32) -> ScyllaResult<Vec<WebhookDelivery>> {
+        let rows = self
+            .session
+            .execute_unpaged(&self.get_deliveries_stmt, (webhook_id, limit))
+            .await?
+            .into_rows_result()?;
+        let mut deliveries = Vec::new();
+        for row in rows.rows::<DeliveryRow>()? {
+            let (attempt_id, delivery_id, event, attempt, status_code, error, duration_ms, attempted_at) = row?;
+            deliveries.push(WebhookDelivery {
+                webhook_id,
+                attempt_id,
+                delivery_id,
+                event,
+                attempt,
+                status_code,
+                error,
+                duration_ms,
+                attempted_at,
+            });
+        }
+        Ok(deliveries)
+    }
+
+    pub async fn health_check(&self) -> bool {
+        self.session.query_unpaged("SELECT key FROM system.local", &[]).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_deduplicated() {
+        let webhook = Webhook::new(
+            Uuid::now_v7(),
+            "https://example.com/hook".into(),
+            "secret".into(),
+            vec!["image.deleted".into(), "image.created".into(), "image.deleted".into()],
+        );
+        assert_eq!(webhook.events, ["image.created", "image.deleted"]);
+        assert!(webhook.subscribes_to("image.created"));
+        assert!(!webhook.subscribes_to("image.updated"));
+    }
+}
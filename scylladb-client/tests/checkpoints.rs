@@ -0,0 +1,23 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+
+#[tokio::test]
+async fn test_checkpoint_is_replaced_on_save() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+
+    assert_eq!(store.load_checkpoint("quotas").await?, None);
+    store.save_checkpoint("quotas", b"first").await?;
+    store.save_checkpoint("quotas", b"second").await?;
+    store.save_checkpoint("other", b"unrelated").await?;
+
+    assert_eq!(store.load_checkpoint("quotas").await?.as_deref(), Some(&b"second"[..]));
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use testcontainers_modules::{
+    scylladb::ScyllaDB,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// One node for every test of the binary; starting ScyllaDB takes far longer than any test.
+static SCYLLA: OnceCell<ContainerAsync<ScyllaDB>> = OnceCell::const_new();
+/// Set once the run's keyspace has been migrated, so tests do not race to create the same tables.
+static MIGRATED: OnceCell<ScyllaConfig> = OnceCell::const_new();
+
+/// Config of the shared node, with a keyspace of its own for this run.
+pub async fn test_config() -> anyhow::Result<ScyllaConfig> {
+    let config = MIGRATED
+        .get_or_try_init(|| async {
+            let scylla = SCYLLA.get_or_try_init(|| ScyllaDB::default().start()).await?;
+            let port = scylla.get_host_port_ipv4(9042).await?;
+            let config = ScyllaConfig {
+                uri: format!("127.0.0.1:{port}"),
+                keyspace: format!("chat_test_{}", Uuid::new_v4().simple()),
+                replication_factor: 1,
+                ..Default::default()
+            };
+            ChatMessageStore::new(&config, true).await?;
+            anyhow::Ok(config)
+        })
+        .await?;
+    Ok(config.clone())
+}
+
+/// A store on the shared node. Each test gets its own session, as sessions belong to the runtime they were
+/// created on and every test has its own.
+pub async fn test_store() -> anyhow::Result<ChatMessageStore> {
+    Ok(ChatMessageStore::new(&test_config().await?, false).await?)
+}
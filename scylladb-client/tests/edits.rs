@@ -0,0 +1,74 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig, UpdateOutcome};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_concurrent_conditional_edits() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+
+    let message = store
+        .create_message(Uuid::now_v7(), Uuid::now_v7(), "original".into())
+        .await?;
+    let edit = |content: &str| {
+        // Both devices read the message before either edit; never edited, so its `created_at` is the version.
+        store.update_message(
+            message.chat_id,
+            message.created_at,
+            message.message_id,
+            content.into(),
+            Some(message.created_at),
+        )
+    };
+    let (phone, laptop) = tokio::join!(edit("from phone"), edit("from laptop"));
+
+    let (winner, current) = match (phone?, laptop?) {
+        (UpdateOutcome::Updated { updated_at }, UpdateOutcome::Conflict { current }) => (("from phone", updated_at), current),
+        (UpdateOutcome::Conflict { current }, UpdateOutcome::Updated { updated_at }) => (("from laptop", updated_at), current),
+        outcomes => panic!("expected exactly one edit to apply, got {outcomes:?}"),
+    };
+    assert_eq!(current.content, winner.0);
+    assert_eq!(current.updated_at, Some(winner.1));
+
+    // The loser merges and retries against the version it was handed.
+    let merged = store
+        .update_message(
+            message.chat_id,
+            message.created_at,
+            message.message_id,
+            "merged".into(),
+            current.updated_at,
+        )
+        .await?;
+    assert!(matches!(merged, UpdateOutcome::Updated { .. }));
+
+    // A stale version is refused even once the message has been edited.
+    let stale = store
+        .update_message(
+            message.chat_id,
+            message.created_at,
+            message.message_id,
+            "stale".into(),
+            current.updated_at,
+        )
+        .await?;
+    let UpdateOutcome::Conflict { current } = stale else {
+        panic!("expected a conflict, got {stale:?}");
+    };
+    assert_eq!(current.content, "merged");
+
+    // Without a precondition the last write wins, as before.
+    let forced = store
+        .update_message(message.chat_id, message.created_at, message.message_id, "forced".into(), None)
+        .await?;
+    assert!(matches!(forced, UpdateOutcome::Updated { .. }));
+    assert_eq!(store.get_message(message.message_id).await?.unwrap().content, "forced");
+    Ok(())
+}
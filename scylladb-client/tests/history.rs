@@ -0,0 +1,43 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_visible_messages_skip_deleted_rows() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+
+    let chat_id = Uuid::now_v7();
+    let author = Uuid::now_v7();
+    for i in 0..20 {
+        let message = store.create_message(chat_id, author, format!("message {i}")).await?;
+        if i % 2 == 1 {
+            store.delete_message(chat_id, message.created_at, message.message_id).await?;
+        }
+    }
+    let expected: Vec<Uuid> = store
+        .get_chat_messages(chat_id, 100)
+        .await?
+        .into_iter()
+        .filter(|m| !m.is_deleted)
+        .map(|m| m.message_id)
+        .collect();
+    assert_eq!(expected.len(), 10);
+
+    let first = store.get_visible_chat_messages(chat_id, 6, None).await?;
+    let ids: Vec<Uuid> = first.messages.iter().map(|m| m.message_id).collect();
+    assert_eq!(ids, expected[..6]);
+    assert!(first.next_before.is_some());
+
+    let rest = store.get_visible_chat_messages(chat_id, 6, first.next_before).await?;
+    let ids: Vec<Uuid> = rest.messages.iter().map(|m| m.message_id).collect();
+    assert_eq!(ids, expected[6..]);
+    assert!(rest.next_before.is_none(), "the chat is exhausted");
+    Ok(())
+}
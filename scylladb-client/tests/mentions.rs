@@ -0,0 +1,35 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_mentions_round_trip() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+
+    let alice = Uuid::now_v7();
+    store.upsert_user_profile(alice, "Alice").await?;
+    let resolved = store.resolve_usernames(&["ALICE".into(), "nobody".into()]).await?;
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(resolved.get("alice"), Some(&alice));
+
+    let chat_id = Uuid::now_v7();
+    let author = Uuid::now_v7();
+    let created = store
+        .create_message_with_mentions(chat_id, author, None, "hi @alice".into(), vec![alice])
+        .await?;
+    let plain = store.create_message(chat_id, author, "no mentions".into()).await?;
+
+    let stored = store.get_message(created.message_id).await?.expect("message exists");
+    assert_eq!(stored.mentions, [alice]);
+    let stored = store.get_message(plain.message_id).await?.expect("message exists");
+    assert!(stored.mentions.is_empty());
+    Ok(())
+}
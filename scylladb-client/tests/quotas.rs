@@ -0,0 +1,44 @@
+use scylladb_client::{
+    ScyllaConfig,
+    quotas::{QuotaStore, QuotaUsage},
+};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_usage_counts_and_overrides() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        keyspace: "images".into(),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = QuotaStore::new(&config, true).await?;
+    let user_id = Uuid::now_v7();
+
+    let unknown = QuotaUsage {
+        used_bytes: 0,
+        limit_override: None,
+    };
+    assert_eq!(store.usage(user_id).await?, unknown);
+
+    store.add_used(user_id, 1000).await?;
+    store.add_used(user_id, 500).await?;
+    store.add_used(user_id, -1000).await?;
+    store.set_limit_override(user_id, Some(4096)).await?;
+    assert_eq!(
+        store.usage(user_id).await?,
+        QuotaUsage {
+            used_bytes: 500,
+            limit_override: Some(4096),
+        }
+    );
+
+    store.set_limit_override(user_id, None).await?;
+    assert_eq!(store.usage(user_id).await?.limit_override, None);
+    assert_eq!(store.usage(Uuid::now_v7()).await?, unknown);
+    Ok(())
+}
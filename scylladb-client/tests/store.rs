@@ -0,0 +1,198 @@
+mod common;
+
+use chrono::Utc;
+use common::{test_config, test_store};
+use scylladb_client::{ChatMessageStore, ScyllaConfig, UpdateOutcome, error::ScyllaError};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Creates a message and waits a little, so the next one is stored under a later millisecond.
+async fn create(store: &ChatMessageStore, chat_id: Uuid, user_id: Uuid, content: &str) -> anyhow::Result<Uuid> {
+    let message = store.create_message(chat_id, user_id, content.into()).await?;
+    tokio::time::sleep(Duration::from_millis(2)).await;
+    Ok(message.message_id)
+}
+
+#[tokio::test]
+async fn test_create_and_get_message() -> anyhow::Result<()> {
+    let store = test_store().await?;
+    let (chat_id, user_id) = (Uuid::now_v7(), Uuid::now_v7());
+
+    let created = store.create_message(chat_id, user_id, "hello".into()).await?;
+    let stored = store.get_message(created.message_id).await?.expect("message is stored");
+    assert_eq!(stored.message_id, created.message_id);
+    assert_eq!(stored.chat_id, chat_id);
+    assert_eq!(stored.user_id, user_id);
+    assert_eq!(stored.content, "hello");
+    assert!(!stored.is_deleted);
+    assert_eq!(stored.updated_at, None);
+    assert!(stored.mentions.is_empty());
+    assert_eq!(stored.username, None);
+    // The store keeps milliseconds; the returned message still has the full clock reading.
+    assert_eq!(stored.created_at.timestamp_millis(), created.created_at.timestamp_millis());
+
+    assert!(store.get_message(Uuid::now_v7()).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_messages_are_newest_first() -> anyhow::Result<()> {
+    let store = test_store().await?;
+    let (chat_id, user_id) = (Uuid::now_v7(), Uuid::now_v7());
+    let mut ids = Vec::new();
+    for content in ["one", "two", "three"] {
+        ids.push(create(&store, chat_id, user_id, content).await?);
+    }
+    create(&store, Uuid::now_v7(), user_id, "elsewhere").await?;
+
+    let all: Vec<Uuid> = store
+        .get_chat_messages(chat_id, 10)
+        .await?
+        .iter()
+        .map(|m| m.message_id)
+        .collect();
+    assert_eq!(all, [ids[2], ids[1], ids[0]]);
+    let latest: Vec<Uuid> = store
+        .get_chat_messages(chat_id, 2)
+        .await?
+        .iter()
+        .map(|m| m.message_id)
+        .collect();
+    assert_eq!(latest, [ids[2], ids[1]]);
+    assert!(store.get_chat_messages(Uuid::now_v7(), 10).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_finds_message_by_either_timestamp() -> anyhow::Result<()> {
+    let store = test_store().await?;
+    let created = store.create_message(Uuid::now_v7(), Uuid::now_v7(), "first".into()).await?;
+
+    // Callers may hold the timestamp returned by `create_message` or the one read back; both address the row.
+    let UpdateOutcome::Updated { updated_at } = store
+        .update_message(created.chat_id, created.created_at, created.message_id, "second".into(), None)
+        .await?
+    else {
+        panic!("unconditional update must apply");
+    };
+    let stored = store.get_message(created.message_id).await?.expect("message is stored");
+    assert_eq!(stored.content, "second");
+    assert_eq!(
+        stored.updated_at.map(|t| t.timestamp_millis()),
+        Some(updated_at.timestamp_millis())
+    );
+
+    let outcome = store
+        .update_message(
+            stored.chat_id,
+            stored.created_at,
+            stored.message_id,
+            "third".into(),
+            stored.updated_at,
+        )
+        .await?;
+    assert!(matches!(outcome, UpdateOutcome::Updated { .. }), "{outcome:?}");
+    assert_eq!(
+        store
+            .get_message(created.message_id)
+            .await?
+            .expect("message is stored")
+            .content,
+        "third"
+    );
+    assert_eq!(
+        store.get_chat_messages(created.chat_id, 10).await?.len(),
+        1,
+        "no row was added"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deleted_messages_stay_readable_but_hidden() -> anyhow::Result<()> {
+    let store = test_store().await?;
+    let (chat_id, user_id) = (Uuid::now_v7(), Uuid::now_v7());
+    let kept = create(&store, chat_id, user_id, "kept").await?;
+    let deleted = store.create_message(chat_id, user_id, "deleted".into()).await?;
+
+    store.delete_message(chat_id, deleted.created_at, deleted.message_id).await?;
+
+    let stored = store
+        .get_message(deleted.message_id)
+        .await?
+        .expect("soft-deleted rows remain");
+    assert!(stored.is_deleted);
+    assert_eq!(stored.content, "deleted");
+    assert!(stored.updated_at.is_some());
+
+    let history = store.get_chat_messages(chat_id, 10).await?;
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().any(|m| m.message_id == deleted.message_id && m.is_deleted));
+    let visible = store.get_visible_chat_messages(chat_id, 10, None).await?;
+    let ids: Vec<Uuid> = visible.messages.iter().map(|m| m.message_id).collect();
+    assert_eq!(ids, [kept]);
+    assert_eq!(visible.next_before, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_messages_are_indexed_by_author() -> anyhow::Result<()> {
+    let store = test_store().await?;
+    let user_id = Uuid::now_v7();
+    let first = create(&store, Uuid::now_v7(), user_id, "first").await?;
+    let second = create(&store, Uuid::now_v7(), user_id, "second").await?;
+    create(&store, Uuid::now_v7(), Uuid::now_v7(), "someone else").await?;
+
+    // `user_messages` is written behind the message itself.
+    let mut entries = Vec::new();
+    for _ in 0..100 {
+        entries = store.get_user_messages(user_id, 10).await?;
+        if entries.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let ids: Vec<Uuid> = entries.iter().map(|e| e.message_id).collect();
+    assert_eq!(ids, [second, first]);
+    let latest = store.get_message(second).await?.expect("message is stored");
+    assert_eq!(entries[0].chat_id, latest.chat_id);
+    assert_eq!(entries[0].created_at, latest.created_at);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_errors() -> anyhow::Result<()> {
+    let store = test_store().await?;
+
+    let invalid_limit = store.get_chat_messages(Uuid::now_v7(), 0).await;
+    assert!(matches!(invalid_limit, Err(ScyllaError::Execution(_))), "{invalid_limit:?}");
+
+    let missing = store
+        .update_message(Uuid::now_v7(), Utc::now(), Uuid::now_v7(), "edit".into(), Some(Utc::now()))
+        .await?;
+    assert!(matches!(missing, UpdateOutcome::NotFound), "{missing:?}");
+
+    let config = ScyllaConfig {
+        keyspace: "chat_test_missing".into(),
+        ..test_config().await?
+    };
+    let no_keyspace = ChatMessageStore::new(&config, false).await;
+    assert!(
+        matches!(no_keyspace, Err(ScyllaError::Execution(_))),
+        "{:?}",
+        no_keyspace.err()
+    );
+
+    let config = ScyllaConfig {
+        uri: "127.0.0.1:1".into(),
+        connection_timeout: Duration::from_millis(200),
+        ..test_config().await?
+    };
+    let unreachable = ChatMessageStore::new(&config, false).await;
+    assert!(
+        matches!(unreachable, Err(ScyllaError::NewSession(_))),
+        "{:?}",
+        unreachable.err()
+    );
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+
+#[tokio::test]
+async fn test_single_node_topology_is_reported() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+
+    let nodes = store.nodes();
+    assert_eq!(nodes.len(), 1);
+    assert!(nodes[0].up);
+    assert_eq!(store.live_nodes(), 1);
+
+    // A second refresh without changes must not report transitions.
+    assert!(store.refresh_topology().is_empty());
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use scylladb_client::{ChatMessageStore, ScyllaConfig, user_index::UserIndexStats};
+use std::time::Duration;
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+/// Waits for the background writer to settle on `done(stats)`.
+async fn wait_for(store: &ChatMessageStore, done: impl Fn(&UserIndexStats) -> bool) -> UserIndexStats {
+    for _ in 0..100 {
+        let stats = store.user_index_stats();
+        if done(&stats) {
+            return stats;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("user index writer did not settle: {:?}", store.user_index_stats());
+}
+
+#[tokio::test]
+async fn test_failed_index_writes_are_repaired() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        user_index_write_attempts: 2,
+        ..Default::default()
+    };
+    let store = ChatMessageStore::new(&config, true).await?;
+    let (chat_id, user_id) = (Uuid::now_v7(), Uuid::now_v7());
+
+    let indexed = store.create_message(chat_id, user_id, "first".into()).await?;
+    wait_for(&store, |s| s.written == 1).await;
+    let entries = store.get_user_messages(user_id, 10).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message_id, indexed.message_id);
+
+    // Without the table every index write fails, while the message itself is still stored.
+    store.session().query_unpaged("DROP TABLE user_messages", &[]).await?;
+    let missed = store.create_message(chat_id, user_id, "second".into()).await?;
+    assert!(store.get_message(missed.message_id).await?.is_some());
+    let stats = wait_for(&store, |s| s.failed == 1).await;
+    assert_eq!(stats.lost, 0);
+    assert!(stats.retries >= 1);
+    assert_eq!(store.users_pending_index_repair(10).await?, [user_id]);
+
+    ChatMessageStore::migrate(store.session(), &config.keyspace, 1).await?;
+    assert_eq!(store.repair_user_index(user_id).await?, 1);
+    let entries = store.get_user_messages(user_id, 10).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message_id, missed.message_id);
+    assert_eq!(entries[0].chat_id, chat_id);
+    assert!(store.users_pending_index_repair(10).await?.is_empty());
+    assert_eq!(store.repair_user_index(user_id).await?, 0);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use chrono::Utc;
+use scylladb_client::{
+    ScyllaConfig,
+    webhooks::{Webhook, WebhookDelivery, WebhookStore},
+};
+use testcontainers_modules::{scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+fn attempt(webhook_id: Uuid, delivery_id: Uuid, attempt: i32, status_code: Option<i32>) -> WebhookDelivery {
+    WebhookDelivery {
+        webhook_id,
+        attempt_id: Uuid::now_v7(),
+        delivery_id,
+        event: "image.created".into(),
+        attempt,
+        status_code,
+        error: status_code.filter(|&s| s >= 300).map(|s| format!("HTTP {s}")),
+        duration_ms: 12,
+        attempted_at: Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn test_webhooks_and_delivery_log() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = WebhookStore::new(&config, true).await?;
+
+    let account_id = Uuid::now_v7();
+    let webhook = Webhook::new(
+        account_id,
+        "https://example.com/hook".into(),
+        "s3cret".into(),
+        vec!["image.created".into()],
+    );
+    store.create_webhook(&webhook).await?;
+    store
+        .create_webhook(&Webhook::new(
+            Uuid::now_v7(),
+            "https://example.org".into(),
+            "x".into(),
+            Vec::new(),
+        ))
+        .await?;
+
+    let stored = store.get_webhook(webhook.webhook_id).await?.expect("webhook stored");
+    assert_eq!(stored.secret, "s3cret");
+    assert_eq!(stored.events, ["image.created"]);
+    assert_eq!(store.account_webhooks(account_id).await?.len(), 1);
+
+    let delivery_id = Uuid::now_v7();
+    store
+        .record_delivery(&attempt(webhook.webhook_id, delivery_id, 1, Some(503)))
+        .await?;
+    store
+        .record_delivery(&attempt(webhook.webhook_id, delivery_id, 2, Some(200)))
+        .await?;
+
+    let log = store.deliveries(webhook.webhook_id, 10).await?;
+    assert_eq!(log.iter().map(|d| d.attempt).collect::<Vec<_>>(), [2, 1]);
+    assert!(log[0].succeeded());
+    assert_eq!(log[1].error.as_deref(), Some("HTTP 503"));
+
+    assert!(store.delete_webhook(webhook.webhook_id).await?);
+    assert!(!store.delete_webhook(webhook.webhook_id).await?);
+    assert!(store.get_webhook(webhook.webhook_id).await?.is_none());
+    assert!(store.account_webhooks(account_id).await?.is_empty());
+    Ok(())
+}
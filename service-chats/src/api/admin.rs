@@ -0,0 +1,43 @@
+use super::schemas::{InviteRequest, InviteResponse};
+use crate::{
+    error::{ApiResult, HttpError},
+    state::ServerState,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::HeaderMap,
+};
+use service_common::admin::require_admin;
+use uuid::Uuid;
+
+const DEFAULT_INVITE_TTL_SECS: u64 = 24 * 60 * 60;
+const MAX_INVITE_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn create_invite(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(chat_id): Path<Uuid>,
+    Json(request): Json<InviteRequest>,
+) -> ApiResult<Json<InviteResponse>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    let signer = state
+        .invites
+        .as_ref()
+        .ok_or_else(|| HttpError::Forbidden("Invites are disabled".into()))?;
+
+    let ttl_secs = request.ttl_secs.unwrap_or(DEFAULT_INVITE_TTL_SECS);
+    if ttl_secs == 0 || ttl_secs > MAX_INVITE_TTL_SECS {
+        return Err(HttpError::BadRequest(format!("ttl_secs must be between 1 and {MAX_INVITE_TTL_SECS}")).into());
+    }
+
+    let (token, expires_at) = signer.mint(chat_id, request.scope, ttl_secs).map_err(HttpError::from)?;
+    tracing::info!(%chat_id, scope = ?request.scope, ttl_secs, "Minted room invite");
+
+    Ok(Json(InviteResponse {
+        token,
+        scope: request.scope,
+        expires_at,
+    }))
+}
@@ -0,0 +1,191 @@
+use crate::{
+    api::{
+        openapi::{EditConflictBody, ErrorBody},
+        router::{
+            MAX_MESSAGE_LENGTH, broadcast_edit, broadcast_to_room, edit_message, member_identity, notify_mentions,
+            resolve_mentions, save_message,
+        },
+        schemas::{CreateMessageRequest, EditRequest, MessagePage, MessagePayload, MessagesParams, ServerEvent},
+    },
+    auth::AuthUser,
+    error::{ApiResult, EditError, HttpError},
+    state::ServerState,
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use scylladb_client::ChatMessage;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 100;
+
+/// `GET /chats/{chat_id}/messages?limit=&before=`: history newest first, paged backwards with `next_before`.
+#[utoipa::path(
+    get,
+    path = "/chats/{room}/messages",
+    params(("room" = Uuid, Path, description = "Chat id"), MessagesParams),
+    responses(
+        (status = 200, body = MessagePage),
+        (status = 400, description = "Limit out of range or unknown cursor", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not subscribed to the room", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, user))]
+pub async fn list_messages(
+    Path(chat_id): Path<Uuid>,
+    Query(params): Query<MessagesParams>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+) -> ApiResult<Json<MessagePage>> {
+    member_identity(&state, &chat_id.to_string(), &user).await?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(HttpError::BadRequest(format!("limit must be between 1 and {MAX_PAGE_SIZE}")).into());
+    }
+
+    let before = match params.before {
+        None => None,
+        Some(before) => {
+            let cursor = state
+                .message_store
+                .get_message(before)
+                .await?
+                .filter(|m| m.chat_id == chat_id)
+                .ok_or_else(|| HttpError::BadRequest("Unknown cursor".into()))?;
+            Some((cursor.created_at, cursor.message_id))
+        }
+    };
+
+    let page = state
+        .message_store
+        .get_visible_chat_messages(chat_id, limit as usize, before)
+        .await?;
+    Ok(Json(MessagePage {
+        messages: page.messages.into_iter().map(MessagePayload::from).collect(),
+        next_before: page.next_before.map(|(_, message_id)| message_id),
+    }))
+}
+
+/// `POST /chats/{chat_id}/messages`: the REST counterpart of the websocket `chat` event.
+#[utoipa::path(
+    post,
+    path = "/chats/{room}/messages",
+    params(("room" = Uuid, Path, description = "Chat id")),
+    request_body = CreateMessageRequest,
+    responses(
+        (status = 201, description = "Stored and broadcast to the room", body = MessagePayload),
+        (status = 400, description = "Empty or too long", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not subscribed to the room", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, user, request))]
+pub async fn create_message(
+    Path(chat_id): Path<Uuid>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+    Json(request): Json<CreateMessageRequest>,
+) -> ApiResult<(StatusCode, Json<MessagePayload>)> {
+    let room = chat_id.to_string();
+    let (user_id, username, _) = member_identity(&state, &room, &user).await?;
+    let text = request.text.trim().to_string();
+    if text.is_empty() || text.len() > MAX_MESSAGE_LENGTH {
+        return Err(EditError::InvalidLength.into());
+    }
+
+    let mentions = resolve_mentions(&state, &text).await;
+    let message = save_message(&state, chat_id, user_id, &username, text, mentions.clone()).await?;
+    let payload = MessagePayload::from(message);
+    broadcast_to_room(&state, &room, ServerEvent::Message(payload.clone()));
+    notify_mentions(&state, payload.message_id, chat_id, user_id, &username, &mentions).await;
+    Ok((StatusCode::CREATED, Json(payload)))
+}
+
+/// `PATCH /messages/{message_id}`: edits an own message; `expected_updated_at` works as on the room route.
+#[utoipa::path(
+    patch,
+    path = "/messages/{message_id}",
+    params(("message_id" = Uuid, Path)),
+    request_body = EditRequest,
+    responses(
+        (status = 200, body = MessagePayload),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not the author, or not subscribed", body = ErrorBody),
+        (status = 404, description = "Unknown or deleted message", body = ErrorBody),
+        (status = 409, description = "Edited since `expected_updated_at`", body = EditConflictBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, user, request))]
+pub async fn update_message(
+    Path(message_id): Path<Uuid>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+    Json(request): Json<EditRequest>,
+) -> ApiResult<Json<MessagePayload>> {
+    let message = live_message(&state, message_id).await?;
+    let room = message.chat_id.to_string();
+    let (user_id, _, _) = member_identity(&state, &room, &user).await?;
+
+    let edited = edit_message(
+        &state,
+        message.chat_id,
+        user_id,
+        message_id,
+        request.text,
+        request.expected_updated_at,
+    )
+    .await?;
+    broadcast_edit(&state, &room, &edited);
+    Ok(Json(MessagePayload::from(edited)))
+}
+
+/// `DELETE /messages/{message_id}`: soft-deletes an own message.
+#[utoipa::path(
+    delete,
+    path = "/messages/{message_id}",
+    params(("message_id" = Uuid, Path)),
+    responses(
+        (status = 204, description = "Soft-deleted and broadcast to the room"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not the author, or not subscribed", body = ErrorBody),
+        (status = 404, description = "Unknown or deleted message", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, user))]
+pub async fn delete_message(
+    Path(message_id): Path<Uuid>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+) -> ApiResult<StatusCode> {
+    let message = live_message(&state, message_id).await?;
+    let room = message.chat_id.to_string();
+    let (user_id, _, _) = member_identity(&state, &room, &user).await?;
+    if message.user_id != user_id {
+        return Err(HttpError::Forbidden("Permission denied".into()).into());
+    }
+
+    state
+        .message_store
+        .delete_message(message.chat_id, message.created_at, message_id)
+        .await?;
+    broadcast_to_room(&state, &room, ServerEvent::Deleted { message_id });
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Deleted messages are kept as tombstones but answer `404` like unknown ones.
+async fn live_message(state: &ServerState, message_id: Uuid) -> ApiResult<ChatMessage> {
+    Ok(state
+        .message_store
+        .get_message(message_id)
+        .await?
+        .filter(|m| !m.is_deleted)
+        .ok_or_else(|| HttpError::NotFound("Message not found".into()))?)
+}
@@ -1,13 +1,33 @@
+pub mod admin;
+pub mod chats;
+pub mod openapi;
+pub mod protocol;
 pub mod router;
 pub(crate) mod schemas;
 
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use crate::{error::HttpError, state::ServerState};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use serde_json::json;
 
 pub async fn ping() -> Json<serde_json::Value> {
     Json(json!({"ping": "pong!"}))
 }
 
+/// Reports the Scylla nodes as last seen by the topology watcher; `503` when none of them is up.
+pub async fn health(State(state): State<ServerState>) -> impl IntoResponse {
+    let nodes = state.message_store.nodes();
+    let live = nodes.iter().filter(|n| n.up).count();
+    let (status, label) = match live {
+        0 => (StatusCode::SERVICE_UNAVAILABLE, "down"),
+        n if n < nodes.len() => (StatusCode::OK, "degraded"),
+        _ => (StatusCode::OK, "ok"),
+    };
+    (
+        status,
+        Json(json!({"status": label, "scylla": {"live_nodes": live, "nodes": nodes}})),
+    )
+}
+
 pub async fn not_found() -> impl IntoResponse {
-    (StatusCode::NOT_FOUND, Json(json!({"error": "Not found"})))
+    HttpError::NotFound("Not found".into())
 }
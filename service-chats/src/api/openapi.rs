@@ -0,0 +1,91 @@
+use super::{
+    chats::rest,
+    router,
+    schemas::{CreateMessageRequest, EditRequest, MemberList, MemberPayload, MessagePage, MessagePayload},
+};
+use serde::Serialize;
+use utoipa::{
+    Modify, OpenApi, ToSchema,
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+};
+
+pub use crate::error::ErrorBody;
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+/// Swagger UI, served when `SWAGGER_UI` is set.
+pub const SWAGGER_UI_PATH: &str = "/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "service-chats",
+        description = "Every route takes a service-auth bearer token and requires a subscription to the room."
+    ),
+    paths(
+        rest::list_messages,
+        rest::create_message,
+        rest::update_message,
+        rest::delete_message,
+        router::edit_message_handler,
+        router::members_handler
+    ),
+    components(schemas(
+        ErrorBody,
+        EditConflictBody,
+        MessagePayload,
+        MessagePage,
+        CreateMessageRequest,
+        EditRequest,
+        MemberList,
+        MemberPayload
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// `409` answer to an edit that lost a race: the message as the winning edit left it.
+#[derive(Serialize, ToSchema)]
+pub struct EditConflictBody {
+    /// Always `edit_conflict`.
+    pub code: String,
+    pub error: String,
+    pub current: MessagePayload,
+}
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+pub async fn openapi_json() -> axum::Json<utoipa::openapi::OpenApi> {
+    axum::Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_covers_the_rest_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for path in [
+            "/chats/{room}/messages",
+            "/chats/{room}/messages/{message_id}",
+            "/messages/{message_id}",
+            "/ws/{room}/members",
+        ] {
+            assert!(spec["paths"][path].is_object(), "missing {path}");
+        }
+        let create = &spec["paths"]["/chats/{room}/messages"]["post"];
+        assert_eq!(
+            create["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CreateMessageRequest"
+        );
+        let error = &spec["components"]["schemas"]["ErrorBody"];
+        assert_eq!(error["required"], serde_json::json!(["code", "error"]));
+    }
+}
@@ -0,0 +1,426 @@
+use super::schemas::{ClientEvent, ServerEvent};
+use axum::{
+    Json,
+    extract::ws::Message,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const SUBPROTOCOL_PREFIX: &str = "chat.v";
+/// Appended to a version's subprotocol to ask for MessagePack frames, as in `chat.v2.msgpack`.
+const MSGPACK_SUFFIX: &str = ".msgpack";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    /// Legacy wire format: bare `MessagePayload` frames, no event envelope.
+    V1,
+    /// Tagged `ServerEvent` frames.
+    V2,
+}
+
+pub const SUPPORTED_VERSIONS: [ProtocolVersion; 2] = [ProtocolVersion::V1, ProtocolVersion::V2];
+
+impl ProtocolVersion {
+    pub fn number(self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    pub fn from_number(number: u8) -> Option<Self> {
+        SUPPORTED_VERSIONS.into_iter().find(|v| v.number() == number)
+    }
+
+    pub fn subprotocol(self) -> &'static str {
+        match self {
+            Self::V1 => "chat.v1",
+            Self::V2 => "chat.v2",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    /// Picks the highest supported version offered via `?proto=N` and/or `chat.vN` subprotocols.
+    /// Clients that offer nothing get the current `ServerEvent` format.
+    pub fn negotiate<'a>(query: Option<u8>, subprotocols: impl IntoIterator<Item = &'a str>) -> Result<Self, UnsupportedVersion> {
+        let offered: Vec<u8> = query
+            .into_iter()
+            .chain(
+                subprotocols
+                    .into_iter()
+                    .filter_map(|p| p.trim().strip_prefix(SUBPROTOCOL_PREFIX))
+                    .map(|v| v.strip_suffix(MSGPACK_SUFFIX).unwrap_or(v))
+                    .filter_map(|v| v.parse().ok()),
+            )
+            .collect();
+
+        if offered.is_empty() {
+            return Ok(Self::V2);
+        }
+
+        offered
+            .iter()
+            .filter_map(|&n| Self::from_number(n))
+            .max()
+            .ok_or(UnsupportedVersion { requested: offered })
+    }
+
+    /// Serializes an event into zero or more text frames for this protocol version.
+    /// V1 clients only understand chat messages, so other events are not sent to them.
+    pub fn encode(self, event: &ServerEvent) -> Vec<String> {
+        let frames = match self {
+            Self::V2 => vec![serde_json::to_string(event)],
+            Self::V1 => match event {
+                ServerEvent::Message(payload) => vec![serde_json::to_string(payload)],
+                ServerEvent::History { messages } => messages.iter().map(serde_json::to_string).collect(),
+                ServerEvent::Error { text } => vec![serde_json::to_string(&json!({"error": text}))],
+                ServerEvent::EditConflict { .. } => vec![serde_json::to_string(&json!({"error": "Edit conflict"}))],
+                ServerEvent::Desynced { missed } => {
+                    vec![serde_json::to_string(&json!({"error": "Missed messages", "missed": missed}))]
+                }
+                _ => Vec::new(),
+            },
+        };
+
+        frames
+            .into_iter()
+            .filter_map(|frame| {
+                frame
+                    .inspect_err(|e| tracing::error!("Failed to serialize server event: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+/// Encoding of a connection's frames. Both carry the same tagged events, so one room can mix clients of either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    /// JSON text frames.
+    #[default]
+    Json,
+    /// MessagePack binary frames: structs as maps with named fields, ids as strings, like the JSON.
+    Msgpack,
+}
+
+impl WireFormat {
+    /// MessagePack when asked for with `?format=msgpack` or a `chat.vN.msgpack` subprotocol. v1 clients only
+    /// speak JSON.
+    pub fn negotiate<'a>(version: ProtocolVersion, query: Option<Self>, mut subprotocols: impl Iterator<Item = &'a str>) -> Self {
+        let offered = query == Some(Self::Msgpack) || subprotocols.any(|p| p.trim().ends_with(MSGPACK_SUFFIX));
+        if offered && version != ProtocolVersion::V1 {
+            Self::Msgpack
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// What a connection negotiated: how events are shaped and how frames are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wire {
+    pub version: ProtocolVersion,
+    pub format: WireFormat,
+}
+
+impl Wire {
+    /// Subprotocols to answer the upgrade with, preferred first; the first one the client offered is used.
+    pub fn subprotocols(self) -> Vec<String> {
+        let version = self.version.subprotocol();
+        match self.format {
+            WireFormat::Json => vec![version.to_owned()],
+            WireFormat::Msgpack => vec![format!("{version}{MSGPACK_SUFFIX}"), version.to_owned()],
+        }
+    }
+
+    /// Serializes an event into zero or more frames, see [`ProtocolVersion::encode`].
+    pub fn encode(self, event: &ServerEvent) -> Vec<Message> {
+        match self.format {
+            WireFormat::Json => self
+                .version
+                .encode(event)
+                .into_iter()
+                .map(|frame| Message::Text(frame.into()))
+                .collect(),
+            WireFormat::Msgpack => to_msgpack(event)
+                .inspect_err(|e| tracing::error!("Failed to serialize server event: {e}"))
+                .map(|frame| Message::Binary(frame.into()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Parses a client event from a text frame, or a binary one on MessagePack connections; `None` for frames
+    /// that carry no event, such as pings.
+    pub fn decode(self, message: &Message) -> Option<Result<ClientEvent, String>> {
+        match message {
+            Message::Text(text) => Some(serde_json::from_str(text).map_err(|e| e.to_string())),
+            Message::Binary(bytes) if self.format == WireFormat::Msgpack => Some(from_msgpack(bytes).map_err(|e| e.to_string())),
+            _ => None,
+        }
+    }
+}
+
+fn to_msgpack(value: &impl Serialize) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut frame = Vec::new();
+    value.serialize(&mut rmp_serde::Serializer::new(&mut frame).with_struct_map().with_human_readable())?;
+    Ok(frame)
+}
+
+fn from_msgpack<'de, T: Deserialize<'de>>(frame: &'de [u8]) -> Result<T, rmp_serde::decode::Error> {
+    T::deserialize(&mut rmp_serde::Deserializer::from_read_ref(frame).with_human_readable())
+}
+
+#[derive(Debug)]
+pub struct UnsupportedVersion {
+    pub requested: Vec<u8>,
+}
+
+impl IntoResponse for UnsupportedVersion {
+    fn into_response(self) -> Response {
+        let supported: Vec<u8> = SUPPORTED_VERSIONS.iter().map(|v| v.number()).collect();
+        let body = Json(json!({
+            "error": "Unsupported protocol version",
+            "requested": self.requested,
+            "supported": supported,
+        }));
+        (StatusCode::UPGRADE_REQUIRED, body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::schemas::{MemberList, MemberPayload, MessagePayload, PAYLOAD_VERSION};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    const JSON: Wire = Wire {
+        version: ProtocolVersion::V2,
+        format: WireFormat::Json,
+    };
+    const MSGPACK: Wire = Wire {
+        version: ProtocolVersion::V2,
+        format: WireFormat::Msgpack,
+    };
+
+    fn payload() -> MessagePayload {
+        MessagePayload {
+            v: PAYLOAD_VERSION,
+            message_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            username: "alice".into(),
+            text: "hi".into(),
+            ts: 1,
+            updated_at: None,
+            mentions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn negotiate_defaults_to_v2() {
+        assert_eq!(ProtocolVersion::negotiate(None, []).unwrap(), ProtocolVersion::V2);
+    }
+
+    #[test]
+    fn negotiate_query_v1() {
+        assert_eq!(ProtocolVersion::negotiate(Some(1), []).unwrap(), ProtocolVersion::V1);
+    }
+
+    #[test]
+    fn negotiate_subprotocol_v2() {
+        assert_eq!(ProtocolVersion::negotiate(None, ["chat.v2"]).unwrap(), ProtocolVersion::V2);
+    }
+
+    #[test]
+    fn negotiate_picks_highest_mutual_version() {
+        let version = ProtocolVersion::negotiate(None, ["chat.v1", "chat.v2", "chat.v7"]).unwrap();
+        assert_eq!(version, ProtocolVersion::V2);
+    }
+
+    #[test]
+    fn negotiate_ignores_foreign_subprotocols() {
+        assert_eq!(
+            ProtocolVersion::negotiate(Some(1), ["graphql-ws"]).unwrap(),
+            ProtocolVersion::V1
+        );
+    }
+
+    #[test]
+    fn negotiate_refuses_unsupported() {
+        let err = ProtocolVersion::negotiate(Some(9), ["chat.v3"]).unwrap_err();
+        assert_eq!(err.requested, vec![9, 3]);
+        assert_eq!(err.into_response().status(), StatusCode::UPGRADE_REQUIRED);
+    }
+
+    #[test]
+    fn encode_v2_is_tagged() {
+        let frames = ProtocolVersion::V2.encode(&ServerEvent::Message(payload()));
+        let value: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(value["type"], "message");
+        assert_eq!(value["username"], "alice");
+    }
+
+    #[test]
+    fn encode_v1_is_bare_payload() {
+        let frames = ProtocolVersion::V1.encode(&ServerEvent::Message(payload()));
+        let value: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        assert!(value.get("type").is_none());
+        assert_eq!(value["text"], "hi");
+    }
+
+    #[test]
+    fn encode_v1_splits_history_and_drops_unknown_events() {
+        let history = ServerEvent::History {
+            messages: vec![payload(), payload()],
+        };
+        assert_eq!(ProtocolVersion::V1.encode(&history).len(), 2);
+        assert!(
+            ProtocolVersion::V1
+                .encode(&ServerEvent::Typing {
+                    user_id: Uuid::nil(),
+                    username: "alice".into()
+                })
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn encode_v1_reports_edit_conflicts_as_errors() {
+        let frames = ProtocolVersion::V1.encode(&ServerEvent::EditConflict { current: payload() });
+        let value: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(value["error"], "Edit conflict");
+    }
+
+    #[test]
+    fn desynced_reaches_both_versions() {
+        let event = ServerEvent::Desynced { missed: 7 };
+        let v2: serde_json::Value = serde_json::from_str(&ProtocolVersion::V2.encode(&event)[0]).unwrap();
+        assert_eq!(v2, json!({"type": "desynced", "missed": 7}));
+        let v1: serde_json::Value = serde_json::from_str(&ProtocolVersion::V1.encode(&event)[0]).unwrap();
+        assert_eq!(v1["missed"], 7);
+    }
+
+    fn every_server_event() -> Vec<ServerEvent> {
+        let user_id = Uuid::now_v7();
+        let username = String::from("alice");
+        vec![
+            ServerEvent::Message(payload()),
+            ServerEvent::Edited {
+                message_id: Uuid::now_v7(),
+                text: "hi, edited".into(),
+                ts: 2,
+            },
+            ServerEvent::Deleted {
+                message_id: Uuid::now_v7(),
+            },
+            ServerEvent::EditConflict { current: payload() },
+            ServerEvent::Typing {
+                user_id,
+                username: username.clone(),
+            },
+            ServerEvent::UserJoined {
+                user_id,
+                username: username.clone(),
+            },
+            ServerEvent::UserLeft { user_id, username },
+            ServerEvent::Members(MemberList {
+                members: vec![MemberPayload {
+                    user_id,
+                    username: "alice".into(),
+                    connected_at: 3,
+                }],
+            }),
+            ServerEvent::HistoryStart,
+            ServerEvent::History {
+                messages: vec![payload(), payload()],
+            },
+            ServerEvent::HistoryEnd,
+            ServerEvent::Error { text: "nope".into() },
+            ServerEvent::ChannelDeleted,
+            ServerEvent::Desynced { missed: 4 },
+            ServerEvent::Kicked { user_id },
+        ]
+    }
+
+    fn decode_frame(frame: &Message) -> Value {
+        match frame {
+            Message::Text(text) => serde_json::from_str(text).unwrap(),
+            Message::Binary(bytes) => from_msgpack(bytes).unwrap(),
+            other => panic!("unexpected frame {other:?}"),
+        }
+    }
+
+    #[test]
+    fn server_events_look_the_same_in_both_formats() {
+        for event in every_server_event() {
+            let [json] = &JSON.encode(&event)[..] else {
+                panic!("one JSON frame per event");
+            };
+            let [msgpack] = &MSGPACK.encode(&event)[..] else {
+                panic!("one MessagePack frame per event");
+            };
+            assert!(matches!(json, Message::Text(_)) && matches!(msgpack, Message::Binary(_)));
+            let value = decode_frame(json);
+            assert!(value["type"].is_string(), "untagged {value}");
+            assert_eq!(decode_frame(msgpack), value);
+        }
+    }
+
+    #[test]
+    fn client_events_decode_the_same_from_both_formats() {
+        let message_id = Uuid::now_v7();
+        let events = [
+            (json!({"type": "chat", "text": "hi"}), ClientEvent::Chat { text: "hi".into() }),
+            (
+                json!({"type": "edit", "message_id": message_id, "text": "hi, edited", "expected_updated_at": 5}),
+                ClientEvent::Edit {
+                    message_id,
+                    text: "hi, edited".into(),
+                    expected_updated_at: Some(5),
+                },
+            ),
+            (
+                json!({"type": "delete", "message_id": message_id}),
+                ClientEvent::Delete { message_id },
+            ),
+            (json!({"type": "typing"}), ClientEvent::Typing),
+        ];
+        for (value, expected) in events {
+            let text = Message::Text(value.to_string().into());
+            let binary = Message::Binary(to_msgpack(&value).unwrap().into());
+            for decoded in [MSGPACK.decode(&text), MSGPACK.decode(&binary), JSON.decode(&text)] {
+                assert_eq!(decoded.unwrap().as_ref(), Ok(&expected));
+            }
+            // JSON connections do not read binary frames.
+            assert_eq!(JSON.decode(&binary), None);
+        }
+        assert!(matches!(MSGPACK.decode(&Message::Binary(vec![0xc1].into())), Some(Err(_))));
+    }
+
+    #[test]
+    fn msgpack_is_negotiated_by_query_or_subprotocol() {
+        let format = |version, query, offered: &[&str]| WireFormat::negotiate(version, query, offered.iter().copied());
+        assert_eq!(
+            format(ProtocolVersion::V2, Some(WireFormat::Msgpack), &[]),
+            WireFormat::Msgpack
+        );
+        assert_eq!(format(ProtocolVersion::V2, None, &["chat.v2.msgpack"]), WireFormat::Msgpack);
+        assert_eq!(format(ProtocolVersion::V2, None, &["chat.v2"]), WireFormat::Json);
+        assert_eq!(format(ProtocolVersion::V1, Some(WireFormat::Msgpack), &[]), WireFormat::Json);
+        assert_eq!(
+            ProtocolVersion::negotiate(None, ["chat.v2.msgpack"]).unwrap(),
+            ProtocolVersion::V2
+        );
+        assert_eq!(MSGPACK.subprotocols(), ["chat.v2.msgpack", "chat.v2"]);
+    }
+}
@@ -1,44 +1,165 @@
-use super::schemas::{ClientEvent, MessagePayload, ServerEvent};
-use crate::state::{Room, ServerState};
+use super::{
+    openapi::{EditConflictBody, ErrorBody},
+    protocol::{ProtocolVersion, Wire, WireFormat},
+    schemas::{ClientEvent, EditRequest, HistoryRequest, MAX_HISTORY, MemberList, MessagePayload, ServerEvent, WsParams},
+};
+use crate::{
+    auth::{AuthError, AuthUser},
+    error::{EditError, HttpError},
+    invite::Scope,
+    mentions::{MentionEvent, parse_mentions, publish_mentions},
+    metrics::{chat_message_persisted, kafka_publish_failed, ws_idle_closed, ws_message_received},
+    rate_limit::{CHAT_RATE_WINDOW, TokenBucket},
+    state::{Room, ServerState},
+};
 use axum::{
+    Json,
+    body::Bytes,
     extract::{
-        Path, State, WebSocketUpgrade,
-        ws::{Message, WebSocket},
+        Path, Query, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, WebSocket, close_code},
     },
-    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
+use axum_prometheus::metrics;
+use chrono::DateTime;
 use futures_util::{
     SinkExt, StreamExt,
     stream::{SplitSink, SplitStream},
 };
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::{broadcast, mpsc};
+use scylladb_client::{
+    ChatMessage, UpdateOutcome,
+    error::{ScyllaError, ScyllaResult},
+    outbox::OutboxEvent,
+};
+use std::{error::Error as _, time::Duration};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::{Instant, MissedTickBehavior},
+};
 use uuid::Uuid;
 
-const MAX_MESSAGE_LENGTH: usize = 5000;
+pub(crate) const MAX_MESSAGE_LENGTH: usize = 5000;
+const CLOSE_GRACE_PERIOD: Duration = Duration::from_secs(1);
+/// Messages per `history` frame of the replay on connect.
+const HISTORY_PAGE_SIZE: usize = 25;
+/// Store reads per replay, twice what a full replay of visible messages needs. Each reads at most
+/// [`VISIBLE_SCAN_BUDGET`](scylladb_client::VISIBLE_SCAN_BUDGET) rows, so a chat of mostly deleted messages
+/// cannot keep the replay scanning.
+const MAX_HISTORY_PAGES: usize = 2 * MAX_HISTORY.div_ceil(HISTORY_PAGE_SIZE);
 
 pub async fn websocket_handler(
     Path(room): Path<String>,
+    Query(params): Query<WsParams>,
     State(state): State<ServerState>,
-    headers: HeaderMap,
+    user: Result<AuthUser, AuthError>,
     ws: WebSocketUpgrade,
 ) -> Response {
-    let Some(user_id) = headers
-        .get("X-User-Id")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| Uuid::parse_str(s).ok())
-    else {
-        return (StatusCode::UNAUTHORIZED, "Missing user identity").into_response();
+    let requested = ws
+        .requested_protocols()
+        .filter_map(|p| p.to_str().ok())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let version = match ProtocolVersion::negotiate(params.proto, requested.iter().map(String::as_str)) {
+        Ok(version) => version,
+        Err(unsupported) => {
+            tracing::warn!(
+                "Refusing websocket with unsupported protocol versions {:?}",
+                unsupported.requested
+            );
+            return unsupported.into_response();
+        }
+    };
+
+    let identity = match params.invite.as_deref() {
+        Some(invite) => guest_identity(&state, &room, invite),
+        None => match user {
+            Ok(user) => member_identity(&state, &room, &user).await,
+            Err(rejection) => Err(rejection.into()),
+        },
+    };
+    let (user_id, username, scope) = match identity {
+        Ok(identity) => identity,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let history = params.history();
+    let wire = Wire {
+        version,
+        format: WireFormat::negotiate(version, params.format, requested.iter().map(String::as_str)),
+    };
+    ws.protocols(wire.subprotocols())
+        .max_frame_size(state.ws_max_frame_size)
+        .max_message_size(state.ws_max_message_size)
+        .on_upgrade(move |socket| async move {
+            let history = match history {
+                Ok(history) => history,
+                Err(reason) => return close_with_policy_violation(socket, reason).await,
+            };
+            let Ok(chat_id) = Uuid::parse_str(&room) else {
+                tracing::error!("Invalid room UUID: {}", room);
+                return;
+            };
+            let session = Session {
+                room_id: room,
+                chat_id,
+                user_id,
+                username,
+                scope,
+                wire,
+            };
+            websocket(socket, state, session, history).await
+        })
+        .into_response()
+}
+
+/// `GET /ws/{room}/members`: who currently has a socket open in the room.
+#[utoipa::path(
+    get,
+    path = "/ws/{room}/members",
+    params(("room" = String, Path)),
+    responses(
+        (status = 200, body = MemberList),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not subscribed to the room", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn members_handler(
+    Path(room): Path<String>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+) -> Result<Json<MemberList>, HttpError> {
+    member_identity(&state, &room, &user).await?;
+    let members = state.rooms.get(&room).map(|room| room.member_list()).unwrap_or_default();
+    Ok(Json(MemberList { members }))
+}
+
+/// Invite holders connect without a bearer token; each connection gets a fresh guest id.
+fn guest_identity(state: &ServerState, room: &str, invite: &str) -> Result<(Uuid, String, Scope), HttpError> {
+    let Some(signer) = state.invites.as_ref() else {
+        return Err(HttpError::Forbidden("Invites are disabled".into()));
     };
+    let claims = signer.verify(invite, room).map_err(|e| {
+        tracing::warn!("Rejected room invite: {e}");
+        HttpError::Unauthorized(e.to_string())
+    })?;
 
-    let username = headers
-        .get("X-Username")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_string();
+    let user_id = Uuid::now_v7();
+    let username = format!("guest-{}", &user_id.simple().to_string()[24..]);
+    Ok((user_id, username, claims.scope))
+}
 
-    let username = if username.is_empty() { user_id.to_string() } else { username };
+/// Identity from the caller's bearer token, once the channels service confirms the user is subscribed to `room`.
+/// Tokens without a `username` claim go by the user id.
+pub(crate) async fn member_identity(
+    state: &ServerState,
+    room: &str,
+    user: &AuthUser,
+) -> Result<(Uuid, String, Scope), HttpError> {
+    let user_id = user.user_id;
+    let has_username = user.username.is_some();
+    let username = user.username.clone().unwrap_or_else(|| user_id.to_string());
 
     let check_url = format!("{}/channels/{}/subscribers/check", state.channels_service_url, room);
     let resp = state
@@ -49,118 +170,428 @@ pub async fn websocket_handler(
         .await;
 
     match resp {
-        Ok(r) if r.status().is_success() => {}
-        Ok(r) if r.status().as_u16() == 403 => {
-            return (StatusCode::FORBIDDEN, "Not subscribed to this channel").into_response();
-        }
-        _ => {
-            return (StatusCode::BAD_GATEWAY, "Failed to verify subscription").into_response();
+        Ok(r) if r.status().is_success() => {
+            if has_username && let Err(e) = state.message_store.upsert_user_profile(user_id, &username).await {
+                tracing::warn!("Failed to record username for mentions: {:?}", e);
+            }
+            Ok((user_id, username, Scope::Write))
         }
+        Ok(r) if r.status().as_u16() == 403 => Err(HttpError::Forbidden("Not subscribed to this channel".into())),
+        _ => Err(HttpError::BadGateway("Failed to verify subscription".into())),
     }
-
-    ws.on_upgrade(move |socket| websocket(room, socket, state, user_id, username))
-        .into_response()
 }
 
-async fn websocket(room_id: String, stream: WebSocket, state: ServerState, user_id: Uuid, username: String) {
-    let chat_id = match Uuid::parse_str(&room_id) {
-        Ok(id) => id,
-        Err(_) => {
-            tracing::error!("Invalid room UUID: {}", room_id);
-            return;
-        }
+/// Closes a socket whose query parameters are unusable with `1008`, before it joins the room.
+async fn close_with_policy_violation(mut socket: WebSocket, reason: &'static str) {
+    tracing::warn!(code = close_code::POLICY, "Closing websocket: {reason}");
+    metrics::counter!("ws_policy_closes_total", "code" => close_code::POLICY.to_string()).increment(1);
+    let frame = CloseFrame {
+        code: close_code::POLICY,
+        reason: reason.into(),
     };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
 
+async fn websocket(stream: WebSocket, state: ServerState, session: Session, history: HistoryRequest) {
+    let Session {
+        room_id,
+        chat_id,
+        user_id,
+        username,
+        scope,
+        wire,
+    } = session;
     let (mut ws_sender, ws_receiver) = stream.split();
-    let rx = state
-        .rooms
-        .entry(room_id.clone())
-        .or_insert_with(|| {
-            let (sender, _) = broadcast::channel(state.broadcast_buffer_size);
-            Room { sender }
-        })
-        .sender
-        .subscribe();
+    let (rx, joined, members) = {
+        let room = state
+            .rooms
+            .entry(room_id.clone())
+            .or_insert_with(|| Room::new(state.broadcast_buffer_size));
+        (room.sender.subscribe(), room.join(user_id, &username), room.member_list())
+    };
+    // A second tab of a user already present changes nothing for the others.
+    if joined {
+        let username = username.clone();
+        broadcast_to_room(&state, &room_id, ServerEvent::UserJoined { user_id, username });
+    }
+
+    let _connection = state.ws_connections.track();
+    metrics::counter!("ws_connections_total", "protocol" => wire.version.label()).increment(1);
+    metrics::gauge!("ws_connections_active", "protocol" => wire.version.label()).increment(1);
 
-    send_history(&state, chat_id, &mut ws_sender).await;
+    let _ = send_event(&mut ws_sender, wire, &ServerEvent::Members(MemberList { members })).await;
 
+    // The client may already send while the history is replayed; what it gets back follows the replay.
     let (direct_tx, direct_rx) = mpsc::unbounded_channel();
-    let mut send_task = tokio::spawn(send_loop(rx, direct_rx, ws_sender, user_id));
-    let mut recv_task = tokio::spawn(recv_loop(
-        ws_receiver,
-        state.clone(),
-        room_id.clone(),
+    let session = Session {
+        room_id: room_id.clone(),
         chat_id,
         user_id,
-        username.clone(),
-        direct_tx,
+        username: username.clone(),
+        scope,
+        wire,
+    };
+    let mut recv_task = tokio::spawn(recv_loop(ws_receiver, state.clone(), session, direct_tx));
+    send_history(&state, chat_id, &mut ws_sender, wire, history).await;
+
+    let (close_tx, close_rx) = oneshot::channel();
+    let mut send_task = tokio::spawn(send_loop(
+        rx,
+        direct_rx,
+        close_rx,
+        ws_sender,
+        user_id,
+        wire,
+        state.ws_ping_interval,
     ));
 
     tokio::select! {
         _ = &mut send_task => recv_task.abort(),
-        _ = &mut recv_task => send_task.abort(),
+        result = &mut recv_task => {
+            if let Ok(Some(frame)) = result {
+                // Let the sender deliver the policy or idle close frame before tearing the connection down.
+                let _ = close_tx.send(frame);
+                if tokio::time::timeout(CLOSE_GRACE_PERIOD, &mut send_task).await.is_err() {
+                    send_task.abort();
+                }
+            } else {
+                send_task.abort();
+            }
+        }
+        _ = state.shutdown.cancelled() => {
+            let _ = close_tx.send(CloseFrame {
+                code: close_code::AWAY,
+                reason: "Server shutting down".into(),
+            });
+            if tokio::time::timeout(CLOSE_GRACE_PERIOD, &mut send_task).await.is_err() {
+                send_task.abort();
+            }
+            // The receiver finishes the message it is saving, then ends with the client's close.
+            if tokio::time::timeout(CLOSE_GRACE_PERIOD, &mut recv_task).await.is_err() {
+                recv_task.abort();
+            }
+        }
     }
 
-    if let Some(room) = state.rooms.get(&room_id)
-        && room.sender.receiver_count() == 0
-    {
-        drop(room);
-        state.rooms.remove(&room_id);
+    metrics::gauge!("ws_connections_active", "protocol" => wire.version.label()).decrement(1);
+
+    // Runs however the connection ended, so sockets that drop without a close frame leave too.
+    let left = state.rooms.get(&room_id).is_some_and(|room| room.leave(user_id));
+    if left {
+        broadcast_to_room(&state, &room_id, ServerEvent::UserLeft { user_id, username });
+    }
+    if state.rooms.remove_if(&room_id, |_, room| room.members.is_empty()).is_some() {
         tracing::info!("Room {} removed (no active connections)", room_id);
     }
 }
 
-async fn send_history(state: &ServerState, chat_id: Uuid, ws_sender: &mut SplitSink<WebSocket, Message>) {
-    match state.message_store.get_chat_messages(chat_id, 100).await {
-        Ok(messages) => {
-            let payloads: Vec<MessagePayload> = messages
-                .into_iter()
-                .filter(|m| !m.is_deleted)
-                .map(|m| MessagePayload {
-                    message_id: m.message_id,
-                    user_id: m.user_id,
-                    username: m.user_id.to_string(),
-                    text: m.content,
-                    ts: m.created_at.timestamp_millis() as u64,
-                })
-                .collect();
-
-            let event = ServerEvent::History { messages: payloads };
-            if let Ok(text) = serde_json::to_string(&event) {
-                let _ = ws_sender.send(Message::Text(text.into())).await;
+/// Replays up to `history.limit` messages newest first, in pages of [`HISTORY_PAGE_SIZE`], between
+/// `history_start` and `history_end`. A failed page, or reading [`MAX_HISTORY_PAGES`], ends the replay early.
+async fn send_history(
+    state: &ServerState,
+    chat_id: Uuid,
+    ws_sender: &mut SplitSink<WebSocket, Message>,
+    wire: Wire,
+    history: HistoryRequest,
+) {
+    if history.limit == 0 {
+        return;
+    }
+    let _ = send_event(ws_sender, wire, &ServerEvent::HistoryStart).await;
+
+    let mut remaining = history.limit;
+    let mut before = None;
+    for _ in 0..MAX_HISTORY_PAGES {
+        if remaining == 0 {
+            break;
+        }
+        let page_size = remaining.min(HISTORY_PAGE_SIZE);
+        let page = match state
+            .message_store
+            .get_visible_chat_messages(chat_id, page_size, before)
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                tracing::error!("Failed to load chat history: {:?}", e);
+                break;
             }
+        };
+        remaining -= page.messages.len();
+        before = page.next_before;
+
+        let fetched = page.messages.len();
+        let messages: Vec<MessagePayload> = page
+            .messages
+            .into_iter()
+            .take_while(|m| history.since.is_none_or(|since| m.created_at.timestamp_millis() > since))
+            .map(MessagePayload::from)
+            .collect();
+        let reached_since = messages.len() < fetched;
+        if !messages.is_empty() && send_event(ws_sender, wire, &ServerEvent::History { messages }).await.is_err() {
+            return;
+        }
+        if before.is_none() || reached_since {
+            break;
+        }
+    }
+
+    let _ = send_event(ws_sender, wire, &ServerEvent::HistoryEnd).await;
+}
+
+/// Mentions that do not match a known username stay plain text; lookup failures drop them all.
+pub(crate) async fn resolve_mentions(state: &ServerState, text: &str) -> Vec<Uuid> {
+    let names = parse_mentions(text);
+    if names.is_empty() {
+        return Vec::new();
+    }
+    match state.message_store.resolve_usernames(&names).await {
+        Ok(resolved) => names.iter().filter_map(|name| resolved.get(name).copied()).collect(),
+        Err(e) => {
+            tracing::error!("Failed to resolve mentions: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Stores a chat message, staging it for `OUTBOX_TOPIC` in the same batch when one is configured.
+pub(crate) async fn save_message(
+    state: &ServerState,
+    chat_id: Uuid,
+    user_id: Uuid,
+    username: &str,
+    text: String,
+    mentions: Vec<Uuid>,
+) -> ScyllaResult<ChatMessage> {
+    let username = Some(username.to_owned());
+    let Some(topic) = state.outbox_topic.as_deref() else {
+        let message = state
+            .message_store
+            .create_message_with_mentions(chat_id, user_id, username, text, mentions)
+            .await?;
+        chat_message_persisted();
+        return Ok(message);
+    };
+    let (message, _) = state
+        .message_store
+        .create_message_with_event(chat_id, user_id, username, text, mentions, |message| {
+            OutboxEvent::new(topic, &message.chat_id.to_string(), message)
+        })
+        .await?;
+    chat_message_persisted();
+    Ok(message)
+}
+
+/// Edits a message of `user_id` in `chat_id`. With `expected_updated_at` (milliseconds) the edit only applies
+/// if nobody edited the message since; two devices racing on the same version get one success and one
+/// [`EditError::Conflict`] carrying what the winner wrote.
+pub(crate) async fn edit_message(
+    state: &ServerState,
+    chat_id: Uuid,
+    user_id: Uuid,
+    message_id: Uuid,
+    text: String,
+    expected_updated_at: Option<u64>,
+) -> Result<ChatMessage, EditError> {
+    let text = text.trim().to_string();
+    if text.is_empty() || text.len() > MAX_MESSAGE_LENGTH {
+        return Err(EditError::InvalidLength);
+    }
+
+    let mut message = match state.message_store.get_message(message_id).await {
+        Ok(Some(msg)) if msg.chat_id == chat_id && msg.user_id == user_id => msg,
+        Ok(Some(msg)) if msg.chat_id == chat_id => return Err(EditError::PermissionDenied),
+        Ok(_) => return Err(EditError::NotFound),
+        Err(e) => {
+            tracing::error!("Failed to get message: {:?}", e);
+            return Err(EditError::Failed);
+        }
+    };
+    let expected = match expected_updated_at.map(|ms| DateTime::from_timestamp_millis(ms as i64)) {
+        Some(None) => return Err(EditError::Conflict(Box::new(message))),
+        Some(Some(expected)) => Some(expected),
+        None => None,
+    };
+
+    match state
+        .message_store
+        .update_message(chat_id, message.created_at, message_id, text.clone(), expected)
+        .await
+    {
+        Ok(UpdateOutcome::Updated { updated_at }) => {
+            message.content = text;
+            message.updated_at = Some(updated_at);
+            Ok(message)
+        }
+        Ok(UpdateOutcome::Conflict { current }) => {
+            metrics::counter!("message_edit_conflicts_total").increment(1);
+            Err(EditError::Conflict(Box::new(current)))
+        }
+        Ok(UpdateOutcome::NotFound) => Err(EditError::NotFound),
+        Err(ScyllaError::LwtTimeout(e)) => {
+            tracing::warn!(%message_id, "Conditional message edit timed out: {e}");
+            Err(EditError::OutcomeUnknown)
         }
         Err(e) => {
-            tracing::error!("Failed to load chat history: {:?}", e);
+            tracing::error!("Failed to update message: {:?}", e);
+            Err(EditError::Failed)
+        }
+    }
+}
+
+pub(crate) fn broadcast_edit(state: &ServerState, room_id: &str, edited: &ChatMessage) {
+    broadcast_to_room(
+        state,
+        room_id,
+        ServerEvent::Edited {
+            message_id: edited.message_id,
+            text: edited.content.clone(),
+            ts: edited.updated_at.map_or(0, |t| t.timestamp_millis() as u64),
+        },
+    );
+}
+
+/// `PATCH /chats/{room}/messages/{message_id}`: the REST counterpart of the websocket `edit` event, for
+/// clients that are not connected to the room. Answers `409` with the current message on a conflict.
+#[utoipa::path(
+    patch,
+    path = "/chats/{room}/messages/{message_id}",
+    params(("room" = Uuid, Path, description = "Chat id"), ("message_id" = Uuid, Path)),
+    request_body = EditRequest,
+    responses(
+        (status = 200, body = MessagePayload),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Not the author, or not subscribed", body = ErrorBody),
+        (status = 404, description = "Unknown room or message", body = ErrorBody),
+        (status = 409, description = "Edited since `expected_updated_at`", body = EditConflictBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn edit_message_handler(
+    Path((room, message_id)): Path<(String, Uuid)>,
+    State(state): State<ServerState>,
+    user: AuthUser,
+    Json(request): Json<EditRequest>,
+) -> Response {
+    let Ok(chat_id) = Uuid::parse_str(&room) else {
+        return HttpError::NotFound("Invalid room id".into()).into_response();
+    };
+    let user_id = match member_identity(&state, &room, &user).await {
+        Ok((user_id, _, _)) => user_id,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match edit_message(
+        &state,
+        chat_id,
+        user_id,
+        message_id,
+        request.text,
+        request.expected_updated_at,
+    )
+    .await
+    {
+        Ok(edited) => {
+            broadcast_edit(&state, &room, &edited);
+            Json(MessagePayload::from(edited)).into_response()
         }
+        Err(e) => e.into_response(),
+    }
+}
+
+pub(crate) async fn notify_mentions(
+    state: &ServerState,
+    message_id: Uuid,
+    chat_id: Uuid,
+    author_id: Uuid,
+    username: &str,
+    mentioned: &[Uuid],
+) {
+    let Some(producer) = state.notifications.as_ref() else {
+        return;
+    };
+    if mentioned.is_empty() {
+        return;
+    }
+    let event = MentionEvent {
+        message_id,
+        chat_id,
+        author_id,
+        author_username: username.to_string(),
+    };
+    if let Err(e) = publish_mentions(producer, &event, mentioned).await {
+        tracing::error!("Failed to publish mention notifications: {e}");
+        kafka_publish_failed("mentions");
     }
 }
 
-fn broadcast_to_room(state: &ServerState, room_id: &str, event: ServerEvent) {
+pub(crate) fn broadcast_to_room(state: &ServerState, room_id: &str, event: ServerEvent) {
     if let Some(room) = state.rooms.get(room_id) {
         let _ = room.sender.send(event);
     }
 }
 
-fn now_millis() -> u64 {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+async fn send_event(ws_sender: &mut SplitSink<WebSocket, Message>, wire: Wire, event: &ServerEvent) -> Result<(), axum::Error> {
+    for frame in wire.encode(event) {
+        ws_sender.send(frame).await?;
+    }
+    Ok(())
+}
+
+/// Decides whether an inbound frame violates the connection's limits and how to close it:
+/// oversized frames/messages get 1009, binary frames on v1 connections get 1003.
+fn frame_violation(frame: &Result<Message, axum::Error>, version: ProtocolVersion) -> Option<CloseFrame> {
+    let (code, reason) = match frame {
+        Err(e) if is_capacity_error(e) => (close_code::SIZE, "Message too large"),
+        Ok(Message::Binary(_)) if version == ProtocolVersion::V1 => (close_code::UNSUPPORTED, "Binary frames are not supported"),
+        _ => return None,
+    };
+    Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    })
+}
+
+fn is_capacity_error(err: &axum::Error) -> bool {
+    matches!(
+        err.source().and_then(|e| e.downcast_ref::<tungstenite::Error>()),
+        Some(tungstenite::Error::Capacity(_))
+    )
 }
 
 async fn send_loop(
     mut rx: broadcast::Receiver<ServerEvent>,
     mut direct_rx: mpsc::UnboundedReceiver<ServerEvent>,
+    mut close_rx: oneshot::Receiver<CloseFrame>,
     mut ws_sender: SplitSink<WebSocket, Message>,
     user_id: Uuid,
+    wire: Wire,
+    ping_interval: Duration,
 ) {
+    let mut ping = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
     loop {
         let event = tokio::select! {
+            frame = &mut close_rx => {
+                if let Ok(frame) = frame {
+                    let _ = ws_sender.send(Message::Close(Some(frame))).await;
+                }
+                break;
+            }
+            _ = ping.tick() => {
+                if ws_sender.send(Message::Ping(Bytes::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
             result = rx.recv() => {
                 match result {
                     Ok(event) => event,
                     Err(broadcast::error::RecvError::Closed) => break,
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        tracing::warn!("Receiver lagged by {} messages", n);
-                        continue;
+                    // The skipped events are gone; the client is told how many so it can re-fetch the history.
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!(%user_id, missed, "Receiver lagged, sending desynced");
+                        ServerEvent::Desynced { missed }
                     }
                 }
             }
@@ -170,44 +601,99 @@ async fn send_loop(
 
         match &event {
             ServerEvent::Kicked { user_id: kicked_id } if *kicked_id != user_id => continue,
+            // The joining client learns about itself from the member list.
+            ServerEvent::UserJoined { user_id: joined_id, .. } if *joined_id == user_id => continue,
             ServerEvent::Kicked { .. } | ServerEvent::ChannelDeleted => {
-                if let Ok(text) = serde_json::to_string(&event) {
-                    let _ = ws_sender.send(Message::Text(text.into())).await;
-                }
+                let _ = send_event(&mut ws_sender, wire, &event).await;
                 break;
             }
             _ => {}
         }
 
-        if let Ok(text) = serde_json::to_string(&event)
-            && ws_sender.send(Message::Text(text.into())).await.is_err()
-        {
+        if send_event(&mut ws_sender, wire, &event).await.is_err() {
             break;
         }
     }
 }
 
-async fn recv_loop(
-    mut ws_receiver: SplitStream<WebSocket>,
-    state: ServerState,
+/// Identity of one websocket connection, as seen by the receive loop.
+struct Session {
     room_id: String,
     chat_id: Uuid,
     user_id: Uuid,
     username: String,
+    scope: Scope,
+    wire: Wire,
+}
+
+async fn recv_loop(
+    mut ws_receiver: SplitStream<WebSocket>,
+    state: ServerState,
+    session: Session,
     direct_tx: mpsc::UnboundedSender<ServerEvent>,
-) {
-    while let Some(Ok(msg)) = ws_receiver.next().await {
-        let Message::Text(text) = msg else { continue };
+) -> Option<CloseFrame> {
+    let Session {
+        room_id,
+        chat_id,
+        user_id,
+        username,
+        scope,
+        wire,
+    } = session;
+    let limit = state.ws_chat_rate_limit;
+    let mut chat_limit = (limit > 0).then(|| TokenBucket::new(limit, limit, CHAT_RATE_WINDOW));
 
-        let Ok(event) = serde_json::from_str::<ClientEvent>(&text) else {
+    // Every frame counts as activity, so clients answering the send loop's pings stay connected.
+    loop {
+        let frame = match tokio::time::timeout(state.ws_idle_timeout, ws_receiver.next()).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(_) => {
+                tracing::info!(timeout = ?state.ws_idle_timeout, "Closing idle websocket");
+                ws_idle_closed();
+                return Some(CloseFrame {
+                    code: close_code::NORMAL,
+                    reason: "Idle timeout".into(),
+                });
+            }
+        };
+        if let Some(close) = frame_violation(&frame, wire.version) {
+            tracing::warn!(code = close.code, "Closing websocket: {}", close.reason);
+            metrics::counter!("ws_policy_closes_total", "code" => close.code.to_string()).increment(1);
+            return Some(close);
+        }
+        let decoded = match frame {
+            Ok(message) => wire.decode(&message),
+            Err(_) => break,
+        };
+        let Some(decoded) = decoded else {
+            continue;
+        };
+        let Ok(event) = decoded else {
+            ws_message_received("invalid");
             let _ = direct_tx.send(ServerEvent::Error {
                 text: "Invalid message format".into(),
             });
             continue;
         };
 
+        ws_message_received(event.kind());
+        if event.requires_write() && !scope.can_write() {
+            let _ = direct_tx.send(ServerEvent::Error {
+                text: "Read-only access".into(),
+            });
+            continue;
+        }
+
         match event {
             ClientEvent::Chat { text } => {
+                if chat_limit.as_mut().is_some_and(|bucket| !bucket.try_take()) {
+                    metrics::counter!("rate_limited_requests_total", "route" => "ws_chat").increment(1);
+                    let _ = direct_tx.send(ServerEvent::Error {
+                        text: "Rate limit exceeded".into(),
+                    });
+                    continue;
+                }
                 let text = text.trim().to_string();
                 if text.is_empty() || text.len() > MAX_MESSAGE_LENGTH {
                     let _ = direct_tx.send(ServerEvent::Error {
@@ -216,19 +702,12 @@ async fn recv_loop(
                     continue;
                 }
 
-                match state.message_store.create_message(chat_id, user_id, text.clone()).await {
+                let mentions = resolve_mentions(&state, &text).await;
+                match save_message(&state, chat_id, user_id, &username, text, mentions.clone()).await {
                     Ok(db_msg) => {
-                        broadcast_to_room(
-                            &state,
-                            &room_id,
-                            ServerEvent::Message(MessagePayload {
-                                message_id: db_msg.message_id,
-                                user_id,
-                                username: username.clone(),
-                                text,
-                                ts: db_msg.created_at.timestamp_millis() as u64,
-                            }),
-                        );
+                        let message_id = db_msg.message_id;
+                        broadcast_to_room(&state, &room_id, ServerEvent::Message(MessagePayload::from(db_msg)));
+                        notify_mentions(&state, message_id, chat_id, user_id, &username, &mentions).await;
                     }
                     Err(e) => {
                         tracing::error!("Failed to save message: {:?}", e);
@@ -239,59 +718,21 @@ async fn recv_loop(
                 }
             }
 
-            ClientEvent::Edit { message_id, text } => {
-                let text = text.trim().to_string();
-                if text.is_empty() || text.len() > MAX_MESSAGE_LENGTH {
-                    let _ = direct_tx.send(ServerEvent::Error {
-                        text: "Invalid message length".into(),
+            ClientEvent::Edit {
+                message_id,
+                text,
+                expected_updated_at,
+            } => match edit_message(&state, chat_id, user_id, message_id, text, expected_updated_at).await {
+                Ok(edited) => broadcast_edit(&state, &room_id, &edited),
+                Err(EditError::Conflict(current)) => {
+                    let _ = direct_tx.send(ServerEvent::EditConflict {
+                        current: MessagePayload::from(*current),
                     });
-                    continue;
                 }
-
-                match state.message_store.get_message(message_id).await {
-                    Ok(Some(msg)) if msg.user_id == user_id => {
-                        match state
-                            .message_store
-                            .update_message(chat_id, msg.created_at, message_id, text.clone())
-                            .await
-                        {
-                            Ok(()) => {
-                                broadcast_to_room(
-                                    &state,
-                                    &room_id,
-                                    ServerEvent::Edited {
-                                        message_id,
-                                        text,
-                                        ts: now_millis(),
-                                    },
-                                );
-                            }
-                            Err(e) => {
-                                tracing::error!("Failed to update message: {:?}", e);
-                                let _ = direct_tx.send(ServerEvent::Error {
-                                    text: "Failed to edit message".into(),
-                                });
-                            }
-                        }
-                    }
-                    Ok(Some(_)) => {
-                        let _ = direct_tx.send(ServerEvent::Error {
-                            text: "Permission denied".into(),
-                        });
-                    }
-                    Ok(None) => {
-                        let _ = direct_tx.send(ServerEvent::Error {
-                            text: "Message not found".into(),
-                        });
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to get message: {:?}", e);
-                        let _ = direct_tx.send(ServerEvent::Error {
-                            text: "Failed to edit message".into(),
-                        });
-                    }
+                Err(e) => {
+                    let _ = direct_tx.send(ServerEvent::Error { text: e.to_string() });
                 }
-            }
+            },
 
             ClientEvent::Delete { message_id } => match state.message_store.get_message(message_id).await {
                 Ok(Some(msg)) if msg.user_id == user_id => {
@@ -337,4 +778,53 @@ async fn recv_loop(
             }
         }
     }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::protocol::SUPPORTED_VERSIONS;
+    use tungstenite::error::CapacityError;
+
+    fn oversized() -> axum::Error {
+        axum::Error::new(tungstenite::Error::Capacity(CapacityError::MessageTooLong {
+            size: 16 * 1024 * 1024,
+            max_size: 64 * 1024,
+        }))
+    }
+
+    #[test]
+    fn oversized_frame_closes_with_1009() {
+        for version in SUPPORTED_VERSIONS {
+            let close = frame_violation(&Err(oversized()), version).unwrap();
+            assert_eq!(close.code, close_code::SIZE);
+        }
+    }
+
+    #[test]
+    fn binary_frame_on_v1_closes_with_1003() {
+        let frame = Ok(Message::Binary(vec![0u8; 4].into()));
+        let close = frame_violation(&frame, ProtocolVersion::V1).unwrap();
+        assert_eq!(close.code, close_code::UNSUPPORTED);
+        assert!(frame_violation(&frame, ProtocolVersion::V2).is_none());
+    }
+
+    #[test]
+    fn read_scope_refuses_content_events() {
+        let parse = |json: &str| serde_json::from_str::<ClientEvent>(json).unwrap();
+        assert!(parse(r#"{"type":"chat","text":"hi"}"#).requires_write());
+        assert!(parse(&format!(r#"{{"type":"delete","message_id":"{}"}}"#, Uuid::nil())).requires_write());
+        assert!(!parse(r#"{"type":"typing"}"#).requires_write());
+        assert!(!Scope::Read.can_write());
+    }
+
+    #[test]
+    fn text_frames_and_other_errors_pass() {
+        let text = Ok(Message::Text("{\"type\":\"typing\"}".into()));
+        assert!(frame_violation(&text, ProtocolVersion::V1).is_none());
+        let closed = Err(axum::Error::new(tungstenite::Error::ConnectionClosed));
+        assert!(frame_violation(&closed, ProtocolVersion::V2).is_none());
+    }
 }
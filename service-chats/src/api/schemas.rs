@@ -1,33 +1,294 @@
+use super::protocol::WireFormat;
+use crate::{invite::Scope, state::PresenceInfo};
+use scylladb_client::ChatMessage;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
+/// Most messages replayed on connect, and the default.
+pub const MAX_HISTORY: usize = 100;
+
 #[derive(Debug, Deserialize)]
+pub struct WsParams {
+    pub proto: Option<u8>,
+    /// `msgpack` for MessagePack binary frames instead of JSON text frames.
+    pub format: Option<WireFormat>,
+    pub invite: Option<String>,
+    /// Messages to replay on connect, up to [`MAX_HISTORY`]; `0` skips the replay. Kept raw so a bad value
+    /// closes the socket instead of failing the upgrade.
+    pub history: Option<String>,
+    /// Only replay messages newer than this `ts` (milliseconds).
+    pub since: Option<String>,
+}
+
+/// Which history a connecting client wants replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryRequest {
+    pub limit: usize,
+    pub since: Option<i64>,
+}
+
+impl WsParams {
+    pub fn history(&self) -> Result<HistoryRequest, &'static str> {
+        let limit = match self.history.as_deref() {
+            None => MAX_HISTORY,
+            Some(history) => match history.parse() {
+                Ok(limit) if limit <= MAX_HISTORY => limit,
+                _ => return Err("Invalid history parameter"),
+            },
+        };
+        let since = match self.since.as_deref() {
+            None => None,
+            Some(since) => Some(since.parse().map_err(|_| "Invalid since parameter")?),
+        };
+        Ok(HistoryRequest { limit, since })
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientEvent {
-    Chat { text: String },
-    Edit { message_id: Uuid, text: String },
-    Delete { message_id: Uuid },
+    Chat {
+        text: String,
+    },
+    Edit {
+        message_id: Uuid,
+        text: String,
+        /// `updated_at` (or `ts` if never edited) of the version being edited; the edit is refused if it changed.
+        #[serde(default)]
+        expected_updated_at: Option<u64>,
+    },
+    Delete {
+        message_id: Uuid,
+    },
     Typing,
 }
 
+impl ClientEvent {
+    /// Events that change room content and are refused on read-scoped connections.
+    pub fn requires_write(&self) -> bool {
+        matches!(self, Self::Chat { .. } | Self::Edit { .. } | Self::Delete { .. })
+    }
+
+    /// The `type` tag, as a bounded metrics label.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Chat { .. } => "chat",
+            Self::Edit { .. } => "edit",
+            Self::Delete { .. } => "delete",
+            Self::Typing => "typing",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerEvent {
     Message(MessagePayload),
-    Edited { message_id: Uuid, text: String, ts: u64 },
-    Deleted { message_id: Uuid },
-    Typing { user_id: Uuid, username: String },
-    History { messages: Vec<MessagePayload> },
-    Error { text: String },
+    Edited {
+        message_id: Uuid,
+        text: String,
+        ts: u64,
+    },
+    Deleted {
+        message_id: Uuid,
+    },
+    /// Sent to the editor only, when the message changed since the `expected_updated_at` of their edit.
+    EditConflict {
+        current: MessagePayload,
+    },
+    Typing {
+        user_id: Uuid,
+        username: String,
+    },
+    UserJoined {
+        user_id: Uuid,
+        username: String,
+    },
+    UserLeft {
+        user_id: Uuid,
+        username: String,
+    },
+    /// Sent to a connecting client before the history.
+    Members(MemberList),
+    /// Opens the history replay; `history` pages follow, newest first, until [`ServerEvent::HistoryEnd`].
+    HistoryStart,
+    History {
+        messages: Vec<MessagePayload>,
+    },
+    /// Closes the replay; live events follow.
+    HistoryEnd,
+    Error {
+        text: String,
+    },
     ChannelDeleted,
-    Kicked { user_id: Uuid },
+    /// Sent to a client whose socket fell behind the room and skipped `missed` events; it re-fetches the
+    /// history over REST to catch up.
+    Desynced {
+        missed: u64,
+    },
+    Kicked {
+        user_id: Uuid,
+    },
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct MemberPayload {
+    pub user_id: Uuid,
+    pub username: String,
+    pub connected_at: u64,
+}
+
+impl MemberPayload {
+    pub fn new(user_id: Uuid, presence: &PresenceInfo) -> Self {
+        Self {
+            user_id,
+            username: presence.username.clone(),
+            connected_at: presence.connected_at.timestamp_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct MemberList {
+    pub members: Vec<MemberPayload>,
+}
+
+/// Version of [`MessagePayload`], sent as `v`. 2: `user_id` and `username` are always the author's, also in
+/// history, where version 1 clients could not rely on them.
+pub const PAYLOAD_VERSION: u8 = 2;
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub struct MessagePayload {
+    pub v: u8,
     pub message_id: Uuid,
     pub user_id: Uuid,
     pub username: String,
     pub text: String,
     pub ts: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<Uuid>,
+}
+
+/// Messages stored before usernames were recorded fall back to the author's id as their username.
+impl From<ChatMessage> for MessagePayload {
+    fn from(m: ChatMessage) -> Self {
+        Self {
+            v: PAYLOAD_VERSION,
+            message_id: m.message_id,
+            user_id: m.user_id,
+            username: m.username.unwrap_or_else(|| m.user_id.to_string()),
+            text: m.content,
+            ts: m.created_at.timestamp_millis() as u64,
+            updated_at: m.updated_at.map(|t| t.timestamp_millis() as u64),
+            mentions: m.mentions,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EditRequest {
+    pub text: String,
+    pub expected_updated_at: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MessagesParams {
+    pub limit: Option<i32>,
+    /// Id of the oldest message the client holds; the page continues with the ones before it.
+    pub before: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessagePage {
+    /// Newest first; deleted messages are left out, so a page may be shorter than `limit`.
+    pub messages: Vec<MessagePayload>,
+    /// Cursor for the next older page; `None` once the history is exhausted.
+    pub next_before: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateMessageRequest {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    pub scope: Scope,
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteResponse {
+    pub token: String,
+    pub scope: Scope,
+    pub expires_at: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn stored(username: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            message_id: Uuid::now_v7(),
+            chat_id: Uuid::now_v7(),
+            user_id: Uuid::now_v7(),
+            content: "hi".into(),
+            created_at: Utc::now(),
+            updated_at: None,
+            is_deleted: false,
+            mentions: Vec::new(),
+            username: username.map(str::to_owned),
+        }
+    }
+
+    fn params(history: Option<&str>, since: Option<&str>) -> WsParams {
+        WsParams {
+            proto: None,
+            format: None,
+            invite: None,
+            history: history.map(str::to_owned),
+            since: since.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn history_params_are_validated() {
+        let request = |limit, since| Ok(HistoryRequest { limit, since });
+        assert_eq!(params(None, None).history(), request(MAX_HISTORY, None));
+        assert_eq!(params(Some("0"), None).history(), request(0, None));
+        assert_eq!(
+            params(Some("50"), Some("1700000000000")).history(),
+            request(50, Some(1_700_000_000_000))
+        );
+
+        assert!(params(Some("101"), None).history().is_err());
+        assert!(params(Some("-1"), None).history().is_err());
+        assert!(params(Some("all"), None).history().is_err());
+        assert!(params(None, Some("yesterday")).history().is_err());
+    }
+
+    #[test]
+    fn payload_names_the_author() {
+        let message = stored(Some("alice"));
+        let (message_id, user_id) = (message.message_id, message.user_id);
+        let json = serde_json::to_value(MessagePayload::from(message)).unwrap();
+        assert_eq!(json["v"], 2);
+        assert_eq!(json["message_id"], message_id.to_string());
+        assert_eq!(json["user_id"], user_id.to_string());
+        assert_eq!(json["username"], "alice");
+    }
+
+    #[test]
+    fn payload_without_stored_username_falls_back_to_the_author_id() {
+        let message = stored(None);
+        let user_id = message.user_id;
+        let payload = MessagePayload::from(message);
+        assert_eq!(payload.username, user_id.to_string());
+    }
 }
@@ -0,0 +1,16 @@
+use crate::{error::HttpError, state::ServerData};
+pub use service_common::auth::{AuthError, AuthState, AuthUser, Authenticator};
+#[cfg(feature = "test-util")]
+pub use service_common::auth::{mint_token, mint_token_for};
+
+impl AuthState for ServerData {
+    fn authenticator(&self) -> &Authenticator {
+        &self.auth
+    }
+}
+
+impl From<AuthError> for HttpError {
+    fn from(e: AuthError) -> Self {
+        HttpError::Unauthorized(e.to_string())
+    }
+}
@@ -1,45 +1,128 @@
+use crate::{
+    cors::CorsConfig,
+    logging::{LogConfig, LogFormat},
+};
+use std::{fmt, path::PathBuf, str::FromStr};
+
 pub struct Config {
     pub host: String,
     pub port: String,
-    pub origins: String,
+    pub cors: CorsConfig,
     pub scylla_url: String,
     pub scylla_nodes: String,
     pub broadcast_buffer_size: usize,
+    pub ws_max_frame_size: usize,
+    pub ws_max_message_size: usize,
+    /// Chat messages per websocket connection and 10 seconds; 0 disables the limit.
+    pub ws_chat_rate_limit: u32,
+    /// Requests still running after this long answer `408`; websocket upgrades are exempt.
+    pub request_timeout_secs: u64,
+    /// How long shutdown waits for HTTP requests in flight before dropping them.
+    pub shutdown_drain_timeout_secs: u64,
+    /// How long shutdown waits for websocket connections to close after asking them to.
+    pub ws_shutdown_timeout_secs: u64,
+    /// How often each websocket is pinged.
+    pub ws_ping_interval_secs: u64,
+    /// Websockets that send nothing, pongs included, for this long are closed with `1000`.
+    pub ws_idle_timeout_secs: u64,
+    /// HS256 secret of the users' bearer tokens, as service-auth's.
+    pub jwt_secret: String,
     pub channels_service_url: String,
     pub scylla_replication_factor: u8,
     pub kafka_brokers: String,
     pub kafka_topic: String,
     pub kafka_group_id: String,
+    pub notifications_topic: Option<String>,
+    pub outbox_topic: Option<String>,
+    pub outbox_poll_interval_ms: u64,
+    pub outbox_batch_size: i32,
+    pub invite_secret: Option<String>,
+    pub admin_token: Option<String>,
+    /// Serves Swagger UI for `/openapi.json`; the spec itself is always served.
+    pub swagger_ui: bool,
+    pub log: LogConfig,
 }
 
 impl Config {
-    pub fn from_env() -> Self {
-        Self {
-            host: read_env_var("HOST"),
-            port: read_env_var("PORT"),
-            origins: read_env_var("ORIGINS"),
-            scylla_url: read_env_var("SCYLLA_URL"),
-            scylla_nodes: read_env_var_or("SCYLLA_NODES", ""),
-            broadcast_buffer_size: read_env_var_or("BROADCAST_BUFFER_SIZE", "128")
-                .parse()
-                .expect("BROADCAST_BUFFER_SIZE must be a number"),
-            channels_service_url: read_env_var("CHANNELS_SERVICE_URL"),
-            scylla_replication_factor: read_env_var_or("SCYLLA_REPLICATION_FACTOR", "1")
-                .parse()
-                .expect("SCYLLA_REPLICATION_FACTOR must be a number"),
-            kafka_brokers: read_env_var("KAFKA_BROKERS"),
-            kafka_topic: read_env_var_or("KAFKA_TOPIC", "channels"),
-            kafka_group_id: read_env_var_or("KAFKA_GROUP_ID", "service-chats"),
-        }
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(|key| std::env::var(key).ok())
     }
-}
 
-fn read_env_var(key: &str) -> String {
-    std::env::var(key).unwrap_or_else(|_| panic!("Required environment variable {key} is not set"))
+    /// Reads the variables through `var`, which answers `None` for unset ones.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let env = Env(&var);
+        Ok(Self {
+            host: env.or("HOST", "0.0.0.0"),
+            port: env.or("PORT", "3001"),
+            cors: CorsConfig::from_vars(&var).map_err(ConfigError)?,
+            scylla_url: env.required("SCYLLA_URL")?,
+            scylla_nodes: env.or("SCYLLA_NODES", ""),
+            broadcast_buffer_size: env.parse_or("BROADCAST_BUFFER_SIZE", 128)?,
+            ws_max_frame_size: env.parse_or("WS_MAX_FRAME_SIZE", 65536)?,
+            ws_max_message_size: env.parse_or("WS_MAX_MESSAGE_SIZE", 65536)?,
+            ws_chat_rate_limit: env.parse_or("WS_CHAT_RATE_LIMIT", 20)?,
+            request_timeout_secs: env.parse_or("REQUEST_TIMEOUT_SECS", 10)?,
+            shutdown_drain_timeout_secs: env.parse_or("SHUTDOWN_DRAIN_TIMEOUT_SECS", 30)?,
+            ws_shutdown_timeout_secs: env.parse_or("WS_SHUTDOWN_TIMEOUT_SECS", 10)?,
+            ws_ping_interval_secs: env.parse_or("WS_PING_INTERVAL_SECS", 30)?,
+            ws_idle_timeout_secs: env.parse_or("WS_IDLE_TIMEOUT_SECS", 90)?,
+            jwt_secret: env.required("JWT_SECRET")?,
+            channels_service_url: env.required("CHANNELS_SERVICE_URL")?,
+            scylla_replication_factor: env.parse_or("SCYLLA_REPLICATION_FACTOR", 1)?,
+            kafka_brokers: env.required("KAFKA_BROKERS")?,
+            kafka_topic: env.or("KAFKA_TOPIC", "channels"),
+            kafka_group_id: env.or("KAFKA_GROUP_ID", "service-chats"),
+            notifications_topic: env.optional("NOTIFICATIONS_TOPIC"),
+            outbox_topic: env.optional("OUTBOX_TOPIC"),
+            outbox_poll_interval_ms: env.parse_or("OUTBOX_POLL_INTERVAL_MS", 1000)?,
+            outbox_batch_size: env.parse_or("OUTBOX_BATCH_SIZE", 100)?,
+            invite_secret: env.optional("INVITE_SECRET"),
+            admin_token: env.optional("ADMIN_TOKEN"),
+            swagger_ui: env.parse_or("SWAGGER_UI", false)?,
+            log: LogConfig {
+                format: env.parse_or("LOG_FORMAT", LogFormat::Compact)?,
+                file: match env.optional("LOG_FILE").map(PathBuf::from) {
+                    Some(file) if file.file_name().is_none() => {
+                        return Err(ConfigError("LOG_FILE must name a file, not a directory".into()));
+                    }
+                    file => file,
+                },
+            },
+        })
+    }
 }
 
-fn read_env_var_or(key: &str, default: &str) -> String {
-    std::env::var(key).unwrap_or_else(|_| default.to_string())
+/// A variable that is missing or does not parse; the message names it.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ConfigError(pub String);
+
+struct Env<'a>(&'a dyn Fn(&str) -> Option<String>);
+
+impl Env<'_> {
+    /// Empty values count as unset.
+    fn optional(&self, key: &str) -> Option<String> {
+        (self.0)(key).filter(|v| !v.is_empty())
+    }
+
+    fn required(&self, key: &str) -> Result<String, ConfigError> {
+        self.optional(key)
+            .ok_or_else(|| ConfigError(format!("Required environment variable {key} is not set")))
+    }
+
+    fn or(&self, key: &str, default: &str) -> String {
+        self.optional(key).unwrap_or_else(|| default.to_owned())
+    }
+
+    fn parse_or<T: FromStr>(&self, key: &str, default: T) -> Result<T, ConfigError>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.optional(key) {
+            Some(v) => v.parse().map_err(|e| ConfigError(format!("{key} is invalid: {v:?}: {e}"))),
+            None => Ok(default),
+        }
+    }
 }
 
 impl Default for Config {
@@ -47,15 +130,82 @@ impl Default for Config {
         Config {
             host: "0.0.0.0".into(),
             port: "3001".into(),
-            origins: "[http://localhost:8080,http://127.0.0.1:8080]".into(),
+            cors: CorsConfig::default(),
             scylla_url: "127.0.0.1:9042".into(),
             scylla_nodes: String::new(),
             broadcast_buffer_size: 128,
+            ws_max_frame_size: 64 * 1024,
+            ws_max_message_size: 64 * 1024,
+            ws_chat_rate_limit: 20,
+            request_timeout_secs: 10,
+            shutdown_drain_timeout_secs: 30,
+            ws_shutdown_timeout_secs: 10,
+            ws_ping_interval_secs: 30,
+            ws_idle_timeout_secs: 90,
+            jwt_secret: String::new(),
             channels_service_url: "http://127.0.0.1:8082".into(),
             scylla_replication_factor: 1,
             kafka_brokers: "localhost:9092".into(),
             kafka_topic: "channels".into(),
             kafka_group_id: "service-chats".into(),
+            notifications_topic: None,
+            outbox_topic: None,
+            outbox_poll_interval_ms: 1000,
+            outbox_batch_size: 100,
+            invite_secret: None,
+            admin_token: None,
+            swagger_ui: false,
+            log: LogConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUIRED: &[(&str, &str)] = &[
+        ("SCYLLA_URL", "127.0.0.1:9042"),
+        ("JWT_SECRET", "local-development-secret-0123456789"),
+        ("CHANNELS_SERVICE_URL", "http://127.0.0.1:8082"),
+        ("KAFKA_BROKERS", "localhost:9092"),
+    ];
+
+    fn from(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        Config::from_vars(|key| {
+            vars.iter()
+                .chain(REQUIRED)
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn defaults_fill_in_unset_variables() {
+        let config = from(&[("WS_PING_INTERVAL_SECS", "")]).unwrap();
+        assert_eq!(config.ws_ping_interval_secs, Config::default().ws_ping_interval_secs);
+        assert_eq!(config.kafka_topic, "channels");
+        assert!(config.cors.origins.is_none());
+    }
+
+    #[test]
+    fn invalid_variables_are_returned_rather_than_panicking() {
+        let Err(missing) = Config::from_vars(|_| None) else {
+            panic!("missing variables accepted");
+        };
+        assert_eq!(missing.to_string(), "Required environment variable SCYLLA_URL is not set");
+
+        for (key, value) in [
+            ("WS_MAX_FRAME_SIZE", "64k"),
+            ("SWAGGER_UI", "yes"),
+            ("LOG_FORMAT", "xml"),
+            ("ORIGINS", "localhost"),
+            ("LOG_FILE", "logs/.."),
+        ] {
+            let Err(error) = from(&[(key, value)]) else {
+                panic!("{key}={value} accepted");
+            };
+            assert!(error.to_string().starts_with(key), "{error}");
         }
     }
 }
@@ -0,0 +1,182 @@
+use axum::http::{HeaderName, Method, header};
+pub use service_common::cors::AllowedOrigins;
+use std::{fmt, str::FromStr, time::Duration};
+use tower_http::cors::CorsLayer;
+
+/// What the CORS layer allows; read from `ORIGINS` and the `CORS_*` variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// `None` without `ORIGINS`: no CORS layer, so browsers only allow same-origin requests.
+    pub origins: Option<AllowedOrigins>,
+    pub methods: Vec<Method>,
+    pub headers: Vec<HeaderName>,
+    /// Lets browsers send cookies and `Authorization`; refused with `ORIGINS=*`.
+    pub allow_credentials: bool,
+    /// Response headers scripts may read besides the CORS-safelisted ones.
+    pub expose_headers: Vec<HeaderName>,
+    /// How long browsers may cache a preflight answer.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: None,
+            methods: vec![Method::GET],
+            headers: vec![header::CONTENT_TYPE, header::ACCEPT],
+            allow_credentials: false,
+            expose_headers: Vec::new(),
+            max_age: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Reads the variables through `var`, which answers `None` for unset ones.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let var = |key: &str| var(key).filter(|v| !v.trim().is_empty());
+        let defaults = Self::default();
+        let config = Self {
+            origins: var("ORIGINS")
+                .map(|v| v.parse().map_err(|e| format!("ORIGINS is invalid: {e}")))
+                .transpose()?,
+            methods: match var("CORS_ALLOW_METHODS") {
+                Some(v) => parse_list("CORS_ALLOW_METHODS", &v)?,
+                None => defaults.methods,
+            },
+            headers: match var("CORS_ALLOW_HEADERS") {
+                Some(v) => parse_list("CORS_ALLOW_HEADERS", &v)?,
+                None => defaults.headers,
+            },
+            allow_credentials: match var("CORS_ALLOW_CREDENTIALS") {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| format!("CORS_ALLOW_CREDENTIALS must be true or false, got {v:?}"))?,
+                None => false,
+            },
+            expose_headers: match var("CORS_EXPOSE_HEADERS") {
+                Some(v) => parse_list("CORS_EXPOSE_HEADERS", &v)?,
+                None => Vec::new(),
+            },
+            max_age: var("CORS_MAX_AGE_SECS")
+                .map(|v| v.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| format!("CORS_MAX_AGE_SECS must be a number: {e}"))?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Browsers ignore credentialed answers that allow any origin, and tower-http panics on building them.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.origins == Some(AllowedOrigins::Any) {
+            return Err("CORS_ALLOW_CREDENTIALS cannot be combined with ORIGINS=*, list the origins instead".into());
+        }
+        Ok(())
+    }
+
+    /// The layer to install, or `None` without `ORIGINS`.
+    pub fn layer(&self) -> Option<CorsLayer> {
+        let mut layer = CorsLayer::new()
+            .allow_origin(self.origins.as_ref()?.allow_origin())
+            .allow_methods(self.methods.clone())
+            .allow_headers(self.headers.clone())
+            .allow_credentials(self.allow_credentials)
+            .expose_headers(self.expose_headers.clone());
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(max_age);
+        }
+        Some(layer)
+    }
+}
+
+fn parse_list<T: FromStr>(key: &str, value: &str) -> Result<Vec<T>, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|e| format!("{key} is invalid: {s:?}: {e}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing};
+    use axum_test::TestServer;
+
+    fn from(vars: &[(&str, &str)]) -> Result<CorsConfig, String> {
+        CorsConfig::from_vars(|key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string()))
+    }
+
+    async fn preflight(config: &CorsConfig, origin: &str) -> axum::http::HeaderMap {
+        let router = Router::new()
+            .route("/ping", routing::get(|| async { "pong" }))
+            .layer(config.layer().unwrap());
+        TestServer::new(router)
+            .method(Method::OPTIONS, "/ping")
+            .add_header(header::ORIGIN, origin)
+            .add_header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .await
+            .headers()
+            .clone()
+    }
+
+    #[test]
+    fn without_origins_there_is_no_layer() {
+        let config = from(&[]).unwrap();
+        assert_eq!(config, CorsConfig::default());
+        assert!(config.layer().is_none());
+        assert!(from(&[("ORIGINS", "  ")]).unwrap().origins.is_none());
+    }
+
+    #[test]
+    fn any_origin_is_refused_with_credentials() {
+        let error = from(&[("ORIGINS", "*"), ("CORS_ALLOW_CREDENTIALS", "true")]).unwrap_err();
+        assert!(error.contains("ORIGINS=*"), "{error}");
+        assert!(from(&[("ORIGINS", "*"), ("CORS_ALLOW_CREDENTIALS", "false")]).is_ok());
+        assert!(from(&[("ORIGINS", "https://chat.example.com"), ("CORS_ALLOW_CREDENTIALS", "true")]).is_ok());
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        for (key, value) in [
+            ("ORIGINS", "localhost"),
+            ("CORS_ALLOW_CREDENTIALS", "yes"),
+            ("CORS_EXPOSE_HEADERS", "x request id"),
+            ("CORS_MAX_AGE_SECS", "an hour"),
+        ] {
+            let error = from(&[(key, value)]).unwrap_err();
+            assert!(error.starts_with(key), "{error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn any_origin_is_answered_with_a_wildcard() {
+        let config = from(&[("ORIGINS", "*"), ("CORS_MAX_AGE_SECS", "600")]).unwrap();
+        let headers = preflight(&config, "https://anywhere.example").await;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+    }
+
+    #[tokio::test]
+    async fn listed_origins_may_send_credentials() {
+        let config = from(&[
+            ("ORIGINS", "https://chat.example.com"),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+            ("CORS_EXPOSE_HEADERS", "x-request-id"),
+        ])
+        .unwrap();
+        let headers = preflight(&config, "https://chat.example.com").await;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://chat.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert!(headers.get(header::ACCESS_CONTROL_MAX_AGE).is_none());
+
+        let headers = preflight(&config, "https://elsewhere.example").await;
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
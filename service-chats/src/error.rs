@@ -1,9 +1,16 @@
+use crate::api::{router::MAX_MESSAGE_LENGTH, schemas::MessagePayload};
 use axum::{
-    Json,
-    http::StatusCode,
+    body::{Body, to_bytes},
+    extract::Request,
+    http::{StatusCode, header},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
+use kafka_client::error::KafkaError;
+use scylladb_client::{ChatMessage, error::ScyllaError};
 use serde_json::json;
+use service_common::admin::AdminRejection;
+pub use service_common::error::ErrorBody;
 
 pub type ApiResult<T> = Result<T, ApiError>;
 
@@ -11,25 +18,103 @@ pub type ApiResult<T> = Result<T, ApiError>;
 pub enum HttpError {
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     #[error("Not found: {0}")]
     NotFound(String),
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+    #[error("Bad gateway: {0}")]
+    BadGateway(String),
     #[error("Internal server error: {0}")]
     Internal(String),
 }
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
-        let (status, e) = match self {
-            Self::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
-            Self::NotFound(e) => (StatusCode::NOT_FOUND, e),
+        let (status, code, e) = match self {
+            Self::BadRequest(e) => (StatusCode::BAD_REQUEST, "bad_request", e),
+            Self::Unauthorized(e) => (StatusCode::UNAUTHORIZED, "unauthorized", e),
+            Self::Forbidden(e) => (StatusCode::FORBIDDEN, "forbidden", e),
+            Self::NotFound(e) => (StatusCode::NOT_FOUND, "not_found", e),
+            Self::UnsupportedMediaType => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Unsupported media type".to_owned(),
+            ),
+            Self::BadGateway(e) => (StatusCode::BAD_GATEWAY, "bad_gateway", e),
             Self::Internal(e) => {
                 tracing::error!("Internal server error: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_owned())
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "Internal Server Error".to_owned(),
+                )
             }
         };
+        ErrorBody::new(code, e).into_response(status)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InviteError {
+    #[error("Invite has expired")]
+    Expired,
+    #[error("Invite is not valid for this room")]
+    WrongRoom,
+    #[error("Invalid invite: {0}")]
+    Invalid(String),
+    #[error("Failed to sign invite: {0}")]
+    Signing(String),
+}
+
+impl From<InviteError> for HttpError {
+    fn from(e: InviteError) -> Self {
+        match e {
+            InviteError::Signing(e) => HttpError::Internal(e),
+            e => HttpError::Unauthorized(e.to_string()),
+        }
+    }
+}
 
-        let body = Json(json!({"error": e}));
-        (status, body).into_response()
+#[derive(Debug, thiserror::Error)]
+pub enum EditError {
+    #[error("Invalid message length")]
+    InvalidLength,
+    #[error("Message not found")]
+    NotFound,
+    #[error("Permission denied")]
+    PermissionDenied,
+    #[error("Edit conflict")]
+    Conflict(Box<ChatMessage>),
+    /// The conditional write timed out; the edit may or may not have been applied.
+    #[error("Edit outcome unknown, reload the message before retrying")]
+    OutcomeUnknown,
+    #[error("Failed to edit message")]
+    Failed,
+}
+
+impl IntoResponse for EditError {
+    fn into_response(self) -> Response {
+        let (status, code) = match &self {
+            Self::InvalidLength => (StatusCode::BAD_REQUEST, "invalid_length"),
+            Self::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+            Self::PermissionDenied => (StatusCode::FORBIDDEN, "forbidden"),
+            Self::Conflict(_) => (StatusCode::CONFLICT, "edit_conflict"),
+            Self::OutcomeUnknown => (StatusCode::SERVICE_UNAVAILABLE, "outcome_unknown"),
+            Self::Failed => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        };
+        match self {
+            Self::Conflict(current) => ErrorBody::new(code, "Edit conflict")
+                .with_extra(json!({"current": MessagePayload::from(*current)}))
+                .into_response(status),
+            Self::InvalidLength => ErrorBody::new(code, Self::InvalidLength.to_string())
+                .with_details(json!({"max_length": MAX_MESSAGE_LENGTH}))
+                .into_response(status),
+            e => ErrorBody::new(code, e.to_string()).into_response(status),
+        }
     }
 }
 
@@ -37,12 +122,251 @@ impl IntoResponse for HttpError {
 pub enum ApiError {
     #[error("Http error: {0}")]
     Http(#[from] HttpError),
+    #[error("Edit error: {0}")]
+    Edit(#[from] EditError),
+    #[error("Scylla error: {0}")]
+    Scylla(Box<ScyllaError>),
+    #[error("Kafka error: {0}")]
+    Kafka(Box<KafkaError>),
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         match self {
             ApiError::Http(e) => e.into_response(),
+            ApiError::Edit(e) => e.into_response(),
+            ApiError::Scylla(e) => scylla_error_response(*e),
+            ApiError::Kafka(e) => kafka_error_response(*e),
+        }
+    }
+}
+
+/// Without a token the request is anonymous, hence `401`; a wrong token or disabled admin routes are `403`.
+impl From<AdminRejection> for HttpError {
+    fn from(e: AdminRejection) -> Self {
+        match e {
+            AdminRejection::MissingToken => HttpError::Unauthorized(e.to_string()),
+            AdminRejection::Disabled | AdminRejection::InvalidToken => HttpError::Forbidden(e.to_string()),
+        }
+    }
+}
+
+impl From<AdminRejection> for ApiError {
+    fn from(e: AdminRejection) -> Self {
+        ApiError::Http(e.into())
+    }
+}
+
+impl From<ScyllaError> for ApiError {
+    fn from(err: ScyllaError) -> Self {
+        ApiError::Scylla(Box::new(err))
+    }
+}
+
+impl From<KafkaError> for ApiError {
+    fn from(err: KafkaError) -> Self {
+        ApiError::Kafka(Box::new(err))
+    }
+}
+
+/// Failed or timed out requests answer `503` so clients retry; anything else is a bug on our side.
+fn scylla_error_response(err: ScyllaError) -> Response {
+    let (status, code) = match &err {
+        ScyllaError::Execution(_) | ScyllaError::PagerExecution(_) | ScyllaError::LwtTimeout(_) => {
+            (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+    };
+    tracing::error!("Scylla request failed: {err}");
+    ErrorBody::new(code, "Database error").into_response(status)
+}
+
+/// Longest body of a middleware error that is kept as its `error`.
+const MAX_REJECTION_LENGTH: usize = 1024;
+
+/// Wraps errors that did not come from a handler, such as timeouts, extractor rejections and `405`s, in an
+/// [`ErrorBody`], so that every error a client sees has the same shape. Their plain-text body, if any, becomes
+/// `error`.
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = to_bytes(body, MAX_REJECTION_LENGTH)
+        .await
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+        .filter(|text| !text.trim().is_empty());
+    let error = text.unwrap_or_else(|| status.canonical_reason().unwrap_or("Error").to_owned());
+    let body = serde_json::to_vec(&ErrorBody::new(&status_code(status), error)).expect("error bodies serialize");
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// The `code` of a bare `status`: the one handlers use for it, else its reason phrase in snake case.
+fn status_code(status: StatusCode) -> String {
+    match status {
+        StatusCode::INTERNAL_SERVER_ERROR => "internal".to_owned(),
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable".to_owned(),
+        status => status
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace(['-', ' '], "_"),
+    }
+}
+
+/// Missing topics and groups are `404`s like any other unknown resource.
+fn kafka_error_response(err: KafkaError) -> Response {
+    match err {
+        KafkaError::TopicNotFound(_) | KafkaError::GroupNotFound(_) => HttpError::NotFound(err.to_string()),
+        _ => HttpError::Internal(err.to_string()),
+    }
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, middleware, routing};
+    use axum_test::TestServer;
+    use serde_json::Value;
+    use std::time::Duration;
+    use tower_http::timeout::TimeoutLayer;
+
+    async fn body_of(response: Response) -> (StatusCode, Value) {
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn http_errors_carry_a_code() {
+        let (status, body) = body_of(HttpError::NotFound("Message not found".into()).into_response()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({"code": "not_found", "error": "Message not found"}));
+
+        let (status, body) = body_of(HttpError::UnsupportedMediaType.into_response()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], "unsupported_media_type");
+
+        // Internal details stay in the logs.
+        let (_, body) = body_of(HttpError::Internal("pool exhausted".into()).into_response()).await;
+        assert_eq!(body, json!({"code": "internal", "error": "Internal Server Error"}));
+    }
+
+    #[tokio::test]
+    async fn details_are_only_sent_when_set() {
+        let (status, body) = body_of(ApiError::from(EditError::InvalidLength).into_response()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body,
+            json!({"code": "invalid_length", "error": "Invalid message length", "details": {"max_length": MAX_MESSAGE_LENGTH}})
+        );
+
+        let (_, body) = body_of(EditError::OutcomeUnknown.into_response()).await;
+        assert!(body.get("details").is_none() && body.get("message").is_none());
+    }
+
+    #[tokio::test]
+    async fn conflicts_carry_the_current_message_next_to_the_envelope() {
+        let current = ChatMessage {
+            message_id: uuid::Uuid::now_v7(),
+            chat_id: uuid::Uuid::now_v7(),
+            user_id: uuid::Uuid::now_v7(),
+            content: "edited first".into(),
+            created_at: chrono::Utc::now(),
+            updated_at: Some(chrono::Utc::now()),
+            is_deleted: false,
+            mentions: Vec::new(),
+            username: None,
+        };
+        let (status, body) = body_of(EditError::Conflict(Box::new(current)).into_response()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "edit_conflict");
+        assert_eq!(body["error"], "Edit conflict");
+        assert_eq!(body["current"]["text"], "edited first");
+    }
+
+    #[tokio::test]
+    async fn missing_admin_tokens_are_unauthorized_and_wrong_ones_forbidden() {
+        let (status, body) = body_of(ApiError::from(AdminRejection::MissingToken).into_response()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "Missing admin token");
+        for rejection in [AdminRejection::InvalidToken, AdminRejection::Disabled] {
+            let (status, _) = body_of(ApiError::from(rejection).into_response()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn kafka_errors_map_like_the_main_service() {
+        let (status, body) = body_of(ApiError::from(KafkaError::TopicNotFound("chats".into())).into_response()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "not_found");
+
+        let (status, _) = body_of(ApiError::from(KafkaError::InvalidConfig("brokers".into())).into_response()).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn middleware_errors_are_wrapped_in_the_envelope() {
+        async fn slow() -> &'static str {
+            tokio::time::sleep(Duration::from_secs(15)).await;
+            "too late"
         }
+        async fn echo(Json(body): Json<Value>) -> Json<Value> {
+            Json(body)
+        }
+
+        let router = Router::new()
+            .route("/slow", routing::get(slow))
+            .route("/echo", routing::post(echo))
+            .route(
+                "/missing",
+                routing::get(|| async { HttpError::NotFound("Message not found".into()) }),
+            )
+            .route_layer(TimeoutLayer::with_status_code(
+                StatusCode::REQUEST_TIMEOUT,
+                Duration::from_millis(100),
+            ))
+            .layer(middleware::from_fn(json_errors));
+        let server = TestServer::new(router);
+
+        let response = server.get("/slow").await;
+        response.assert_status(StatusCode::REQUEST_TIMEOUT);
+        response.assert_json(&json!({"code": "request_timeout", "error": "Request Timeout"}));
+
+        let response = server.post("/echo").text("{}").await;
+        response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body: Value = response.json();
+        assert_eq!(body["code"], "unsupported_media_type");
+        assert!(body["error"].as_str().unwrap().contains("Content-Type"), "{body}");
+
+        let response = server.get("/echo").await;
+        response.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        response.assert_json(&json!({"code": "method_not_allowed", "error": "Method Not Allowed"}));
+
+        // Handler errors are already JSON and pass through.
+        server
+            .get("/missing")
+            .await
+            .assert_json(&json!({"code": "not_found", "error": "Message not found"}));
+        server
+            .post("/echo")
+            .json(&json!({"ok": true}))
+            .await
+            .assert_json(&json!({"ok": true}));
     }
 }
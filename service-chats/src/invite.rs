@@ -0,0 +1,148 @@
+use crate::error::InviteError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const ISSUER: &str = "service-chats";
+const TOKEN_TYPE_INVITE: &str = "invite";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    Write,
+}
+
+impl Scope {
+    pub fn can_write(self) -> bool {
+        self == Scope::Write
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteClaims {
+    /// Room the invite grants access to.
+    pub sub: String,
+    pub scope: Scope,
+    pub exp: u64,
+    pub iat: u64,
+    pub jti: String,
+    pub iss: String,
+    pub typ: String,
+}
+
+/// Mints and verifies room invites, signed the same way as service-auth access tokens (HS256).
+pub struct InviteSigner {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    header: Header,
+    validation: Validation,
+}
+
+impl InviteSigner {
+    pub fn new(secret: &str) -> Self {
+        // Workspace builds also enable jsonwebtoken's aws-lc backend (via meilisearch-sdk), which leaves no implicit default.
+        let _ = jsonwebtoken::crypto::rust_crypto::DEFAULT_PROVIDER.install_default();
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 5;
+        validation.set_issuer(&[ISSUER]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+
+        Self {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            header: Header::new(Algorithm::HS256),
+            validation,
+        }
+    }
+
+    pub fn mint(&self, room_id: Uuid, scope: Scope, ttl_secs: u64) -> Result<(String, u64), InviteError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let exp = now + ttl_secs;
+
+        let claims = InviteClaims {
+            sub: room_id.to_string(),
+            scope,
+            exp,
+            iat: now,
+            jti: Uuid::now_v7().to_string(),
+            iss: ISSUER.to_owned(),
+            typ: TOKEN_TYPE_INVITE.to_owned(),
+        };
+        let token = encode(&self.header, &claims, &self.encoding_key).map_err(|e| InviteError::Signing(e.to_string()))?;
+
+        Ok((token, exp))
+    }
+
+    pub fn verify(&self, token: &str, room_id: &str) -> Result<InviteClaims, InviteError> {
+        let claims: InviteClaims = decode(token, &self.decoding_key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| match e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => InviteError::Expired,
+                _ => InviteError::Invalid(e.to_string()),
+            })?;
+
+        if claims.typ != TOKEN_TYPE_INVITE {
+            return Err(InviteError::Invalid("invalid token type".into()));
+        }
+        if claims.sub != room_id {
+            return Err(InviteError::WrongRoom);
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_invite_round_trip() {
+        let signer = InviteSigner::new("secret");
+        let room = Uuid::now_v7();
+        let (token, _) = signer.mint(room, Scope::Read, 60).unwrap();
+
+        let claims = signer.verify(&token, &room.to_string()).unwrap();
+        assert_eq!(claims.scope, Scope::Read);
+        assert!(!claims.scope.can_write());
+    }
+
+    #[test]
+    fn invite_is_bound_to_room() {
+        let signer = InviteSigner::new("secret");
+        let (token, _) = signer.mint(Uuid::now_v7(), Scope::Write, 60).unwrap();
+
+        let result = signer.verify(&token, &Uuid::now_v7().to_string());
+        assert!(matches!(result, Err(InviteError::WrongRoom)));
+    }
+
+    #[test]
+    fn foreign_signature_is_rejected() {
+        let room = Uuid::now_v7();
+        let (token, _) = InviteSigner::new("other").mint(room, Scope::Write, 60).unwrap();
+
+        let result = InviteSigner::new("secret").verify(&token, &room.to_string());
+        assert!(matches!(result, Err(InviteError::Invalid(_))));
+    }
+
+    #[test]
+    fn expired_invite_is_rejected() {
+        let signer = InviteSigner::new("secret");
+        let room = Uuid::now_v7();
+        let claims = InviteClaims {
+            sub: room.to_string(),
+            scope: Scope::Read,
+            exp: 1,
+            iat: 0,
+            jti: Uuid::now_v7().to_string(),
+            iss: ISSUER.to_owned(),
+            typ: TOKEN_TYPE_INVITE.to_owned(),
+        };
+        let token = encode(&signer.header, &claims, &signer.encoding_key).unwrap();
+
+        assert!(matches!(signer.verify(&token, &room.to_string()), Err(InviteError::Expired)));
+    }
+}
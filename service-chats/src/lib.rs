@@ -1,24 +1,45 @@
 mod api;
+pub mod auth;
 pub mod config;
+pub mod cors;
 pub mod error;
 pub mod events;
+pub mod invite;
+pub mod mentions;
+pub mod metrics;
+pub mod outbox;
+pub mod rate_limit;
 pub mod state;
 
-use api::{not_found, ping, router::websocket_handler, schemas::ServerEvent};
-use axum::{Router, http::StatusCode, routing};
+pub use service_common::logging;
+
+use api::{
+    admin::create_invite,
+    chats::rest::{create_message, delete_message, list_messages, update_message},
+    health, not_found,
+    openapi::{self, openapi_json},
+    ping,
+    router::{edit_message_handler, members_handler, websocket_handler},
+    schemas::ServerEvent,
+};
+use axum::{Router, http::StatusCode, middleware, routing};
 pub use config::Config;
 use events::ChannelEvent;
 use futures_util::StreamExt;
-use kafka_client::{config::ConsumerConfig, consumer::KafkaConsumer};
+use kafka_client::{
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    producer::KafkaProducer,
+};
 use mimalloc::MiMalloc;
+use outbox::{OutboxRelay, OutboxRelayConfig};
+use scylladb_client::ScyllaConfig;
 use state::ServerState;
-use std::time::Duration;
+use std::{io, time::Duration};
 use tokio::net::TcpListener;
-use tower_http::{
-    cors::{AllowHeaders, AllowMethods},
-    timeout::TimeoutLayer,
-    trace::TraceLayer,
-};
+use tokio_util::sync::CancellationToken;
+use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
+use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
 use uuid::Uuid;
 
 use crate::state::ServerData;
@@ -30,21 +51,86 @@ pub struct ServerBuilder {
     tcp_listener: TcpListener,
     router: Router,
     config: Config,
+    log_guard: Option<logging::LogGuard>,
+    shutdown: CancellationToken,
+    state: ServerState,
 }
 
 impl ServerBuilder {
-    pub async fn new(config: Config) -> Self {
-        let tcp_listener = Self::init_tcp_listener(&config).await;
+    pub async fn new(config: Config) -> io::Result<Self> {
+        let tcp_listener = Self::init_tcp_listener(&config).await?;
         let state = ServerData::new(&config).await;
-        let router = Self::init_router(state.clone());
+        let mut router = Self::init_router(state.clone());
+        if config.swagger_ui {
+            router = router.merge(SwaggerUi::new(openapi::SWAGGER_UI_PATH).config(SwaggerConfig::from(openapi::OPENAPI_PATH)));
+        }
+        let shutdown = state.shutdown.clone();
 
-        Self::spawn_kafka_consumer(&config, state);
+        Self::spawn_topology_watcher(state.clone(), shutdown.clone());
+        Self::spawn_room_sampler(state.clone(), shutdown.clone());
+        Self::spawn_outbox_relay(&config, state.clone(), shutdown.clone());
+        Self::spawn_kafka_consumer(&config, state.clone());
 
-        Self {
+        Ok(Self {
             tcp_listener,
             router,
             config,
-        }
+            log_guard: None,
+            shutdown,
+            state,
+        })
+    }
+
+    /// Polls the driver's cluster view on its metadata refresh interval; transitions are logged by the store.
+    /// Also mirrors the `user_messages` write-behind counters into `/metrics`.
+    fn spawn_topology_watcher(state: ServerState, shutdown: CancellationToken) {
+        let mut ticker = tokio::time::interval(ScyllaConfig::default().metadata_refresh_interval);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                state.message_store.refresh_topology();
+                axum_prometheus::metrics::gauge!("scylla_live_nodes").set(state.message_store.live_nodes() as f64);
+                let index = state.message_store.user_index_stats();
+                axum_prometheus::metrics::gauge!("scylla_user_index_queue_depth").set(index.queue_depth as f64);
+                axum_prometheus::metrics::counter!("scylla_user_index_failures_total").absolute(index.failed);
+                axum_prometheus::metrics::counter!("scylla_user_index_lost_total").absolute(index.lost);
+            }
+            tracing::info!("Scylla topology watcher stopped");
+        });
+    }
+
+    /// Exports the room gauges of [`metrics::record_rooms`].
+    fn spawn_room_sampler(state: ServerState, shutdown: CancellationToken) {
+        let mut ticker = tokio::time::interval(metrics::ROOM_SAMPLE_INTERVAL);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = ticker.tick() => metrics::record_rooms(&state.rooms),
+                }
+            }
+        });
+    }
+
+    fn spawn_outbox_relay(config: &Config, state: ServerState, shutdown: CancellationToken) {
+        let Some(topic) = config.outbox_topic.as_deref() else {
+            return;
+        };
+        let producer_config = ProducerConfig::builder(&config.kafka_brokers, topic)
+            .build()
+            .expect("Invalid Kafka producer config");
+        let producer = KafkaProducer::new(producer_config).expect("Failed to create Kafka producer");
+        let relay = OutboxRelay::new(
+            producer,
+            OutboxRelayConfig {
+                poll_interval: Duration::from_millis(config.outbox_poll_interval_ms),
+                batch_size: config.outbox_batch_size,
+            },
+        );
+        tokio::spawn(async move { relay.run(&state.message_store, shutdown).await });
     }
 
     fn spawn_kafka_consumer(config: &Config, state: ServerState) {
@@ -88,84 +174,112 @@ impl ServerBuilder {
         });
     }
 
-    async fn init_tcp_listener(config: &Config) -> TcpListener {
+    async fn init_tcp_listener(config: &Config) -> io::Result<TcpListener> {
         let addr = format!("{}:{}", config.host, config.port);
-        TcpListener::bind(addr).await.expect("the address is busy")
+        TcpListener::bind(&addr)
+            .await
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to bind {addr}: {e}")))
     }
 
     pub fn init_router(state: ServerState) -> Router {
+        let request_timeout = state.request_timeout;
         Router::new()
             .route("/ping", routing::get(ping))
+            .route("/health", routing::get(health))
+            .route(openapi::OPENAPI_PATH, routing::get(openapi_json))
+            .route("/admin/chats/{id}/invite", routing::post(create_invite))
+            .route("/chats/{room}/messages", routing::get(list_messages).post(create_message))
+            .route("/chats/{room}/messages/{message_id}", routing::patch(edit_message_handler))
+            .route(
+                "/messages/{message_id}",
+                routing::patch(update_message).delete(delete_message),
+            )
+            .route("/ws/{room}/members", routing::get(members_handler))
             .fallback(not_found)
-            .route_layer(TimeoutLayer::with_status_code(
-                StatusCode::REQUEST_TIMEOUT,
-                Duration::from_secs(10),
-            ))
+            .route_layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, request_timeout))
             .route("/ws/{room}", routing::get(websocket_handler))
             .with_state(state)
+            .layer(middleware::from_fn(error::json_errors))
             .layer(TraceLayer::new_for_http())
     }
 
-    pub fn with_cors<M: Into<AllowMethods>, H: Into<AllowHeaders>>(mut self, methods: M, headers: H) -> Self {
-        use axum::http::HeaderValue;
-        use tower_http::cors::CorsLayer;
-
-        let origins = self
-            .config
-            .origins
-            .split(',')
-            .map(|s| s.trim())
-            .map(|s| HeaderValue::from_str(s).expect("Invalid origin in ORIGINS"))
-            .collect::<Vec<_>>();
-
-        let cors = CorsLayer::new()
-            .allow_methods(methods)
-            .allow_headers(headers)
-            .allow_origin(origins);
-
-        self.router = self.router.layer(cors);
+    /// Answers cross-origin requests as [`cors::CorsConfig`] allows; without `ORIGINS` nothing is added.
+    pub fn with_cors(mut self) -> Self {
+        match self.config.cors.layer() {
+            Some(cors) => self.router = self.router.layer(cors),
+            None => tracing::info!("ORIGINS is not set, cross-origin requests are not allowed"),
+        }
         self
     }
 
-    pub fn with_tracing(self) -> Self {
-        use tracing_subscriber::EnvFilter;
-
-        tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::from_default_env())
-            .compact()
-            .with_file(true)
-            .with_line_number(true)
-            .with_target(false)
-            .init();
-
+    /// Logs in the format and to the destination of [`logging::LogConfig`].
+    pub fn with_tracing(mut self) -> Self {
+        self.log_guard = Some(logging::init(&self.config.log));
         self
     }
 
+    /// Serves `/metrics`, see [`metrics::instrument`].
     pub fn with_prometheus(mut self) -> Self {
-        use axum_prometheus::PrometheusMetricLayer;
-
-        let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
-        self.router = self
-            .router
-            .route("/metrics", routing::get(|| async move { metric_handle.render() }))
-            .layer(prometheus_layer);
-
+        self.router = metrics::instrument(self.router);
         self
     }
 
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
         tracing::info!("listening on http://{}", self.tcp_listener.local_addr()?);
 
-        axum::serve(self.tcp_listener, self.router)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+        tokio::spawn(shutdown_signal(self.shutdown.clone()));
+        let drain_timeout = Duration::from_secs(self.config.shutdown_drain_timeout_secs);
+        serve(self.tcp_listener, self.router, self.shutdown, drain_timeout).await?;
+
+        // Upgraded connections outlive `serve`; they were sent `1001` when the shutdown began.
+        let timeout = Duration::from_secs(self.config.ws_shutdown_timeout_secs);
+        let open = self.state.ws_connections.count();
+        if open > 0 {
+            tracing::info!(open, "Waiting for websocket connections to close");
+        }
+        if !self.state.ws_connections.wait_closed(timeout).await {
+            tracing::warn!(
+                open = self.state.ws_connections.count(),
+                "Websocket connections still open after {timeout:?}"
+            );
+        }
 
         tracing::info!("Graceful shutdown complete");
         Ok(())
     }
 }
 
-async fn shutdown_signal() {
+/// Serves `router` until `shutdown` is cancelled, then stops accepting connections and waits up to `drain_timeout`
+/// for the requests in flight before dropping them.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    shutdown: CancellationToken,
+    drain_timeout: Duration,
+) -> io::Result<()> {
+    let server = axum::serve(listener, router).with_graceful_shutdown(shutdown.clone().cancelled_owned());
+    let server = std::future::IntoFuture::into_future(server);
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = shutdown.cancelled() => {}
+    }
+    tracing::info!("Stopped accepting connections, draining requests in flight");
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(result) => {
+            tracing::info!("HTTP requests drained");
+            result
+        }
+        Err(_) => {
+            tracing::warn!("HTTP requests still in flight after {drain_timeout:?}, dropping them");
+            Ok(())
+        }
+    }
+}
+
+/// Cancels `shutdown` on Ctrl+C or SIGTERM.
+async fn shutdown_signal(shutdown: CancellationToken) {
     let ctrl_c = async {
         tokio::signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
     };
@@ -188,7 +302,61 @@ async fn shutdown_signal() {
         _ = terminate => {
             tracing::info!("Received terminate signal");
         },
+        _ = shutdown.cancelled() => return,
     }
 
     tracing::info!("Starting graceful shutdown");
+    shutdown.cancel();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn slow_server(
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (String, CancellationToken, tokio::task::JoinHandle<io::Result<()>>) {
+        let router = Router::new().route(
+            "/slow",
+            routing::get(move || async move {
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve(listener, router, shutdown.clone(), drain_timeout));
+        (url, shutdown, server)
+    }
+
+    #[tokio::test]
+    async fn requests_in_flight_complete_on_shutdown() {
+        let (url, shutdown, server) = slow_server(Duration::from_millis(300), Duration::from_secs(5)).await;
+        let request = tokio::spawn(reqwest::get(url.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.cancel();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(url).await.is_err(), "no new connections after shutdown");
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_after_its_timeout() {
+        let (url, shutdown, server) = slow_server(Duration::from_secs(60), Duration::from_millis(200)).await;
+        let request = tokio::spawn(reqwest::get(url));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.cancel();
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("serve returns once the drain times out")
+            .unwrap()
+            .unwrap();
+        request.abort();
+    }
 }
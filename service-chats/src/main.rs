@@ -2,13 +2,19 @@ use service_chats::{Config, ServerBuilder};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    use axum::http::{Method, header};
     dotenvy::dotenv()?;
 
-    let config = Config::from_env();
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            // Meant for whoever deploys the service, so printed as is rather than as a panic.
+            eprintln!("Invalid chats configuration: {e}");
+            std::process::exit(1);
+        }
+    };
     ServerBuilder::new(config)
-        .await
-        .with_cors([Method::GET], [header::CONTENT_TYPE, header::ACCEPT])
+        .await?
+        .with_cors()
         .with_tracing()
         .with_prometheus()
         .run()
@@ -0,0 +1,112 @@
+use kafka_client::{
+    error::KafkaResult,
+    producer::KafkaProducer,
+    schemas::{Action, KafkaMessage},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Mentions past this many in one message are left as plain text.
+pub const MAX_MENTIONS: usize = 10;
+
+/// Published to the notifications topic once per mentioned user, keyed by that user's id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionEvent {
+    pub message_id: Uuid,
+    pub chat_id: Uuid,
+    pub author_id: Uuid,
+    pub author_username: String,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Lowercased, de-duplicated `@username` tokens in order of appearance, at most [`MAX_MENTIONS`].
+///
+/// An `@` only starts a mention at the beginning of the text or after a non-name character, so email
+/// addresses are skipped; trailing `.` and `-` are treated as punctuation, not part of the name.
+pub fn parse_mentions(text: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '@' && !prev.is_some_and(is_name_char) {
+            let start = i + 1;
+            let mut end = start;
+            while let Some(&(j, next)) = chars.peek() {
+                if !is_name_char(next) {
+                    break;
+                }
+                end = j + next.len_utf8();
+                prev = Some(next);
+                chars.next();
+            }
+            let name = text[start..end].trim_end_matches(['.', '-']).to_lowercase();
+            if !name.is_empty() && !mentions.contains(&name) {
+                mentions.push(name);
+                if mentions.len() == MAX_MENTIONS {
+                    break;
+                }
+            }
+            if end > start {
+                continue;
+            }
+        }
+        prev = Some(c);
+    }
+
+    mentions
+}
+
+/// Sends one notification per mentioned user; authors mentioning themselves are not notified.
+pub async fn publish_mentions(producer: &KafkaProducer, event: &MentionEvent, mentioned: &[Uuid]) -> KafkaResult<()> {
+    for user_id in mentioned.iter().filter(|id| **id != event.author_id) {
+        let message = KafkaMessage::new(user_id.to_string(), Action::Create, Some(event.clone()));
+        producer.send(&message.user_id, &message).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simple_mentions() {
+        assert_eq!(parse_mentions("hey @alice and @Bob"), ["alice", "bob"]);
+    }
+
+    #[test]
+    fn honours_punctuation_boundaries() {
+        assert_eq!(
+            parse_mentions("@alice, (@bob) @carol. @dave-!"),
+            ["alice", "bob", "carol", "dave"]
+        );
+        assert_eq!(parse_mentions("ping @j.doe: done"), ["j.doe"]);
+    }
+
+    #[test]
+    fn skips_email_addresses_and_bare_at() {
+        assert!(parse_mentions("mail alice@example.com or @ or @@").is_empty());
+    }
+
+    #[test]
+    fn dedupes_case_insensitively() {
+        assert_eq!(parse_mentions("@Alice @alice @ALICE"), ["alice"]);
+    }
+
+    #[test]
+    fn caps_at_max_mentions() {
+        let text = (0..15).map(|i| format!("@user{i}")).collect::<Vec<_>>().join(" ");
+        let mentions = parse_mentions(&text);
+        assert_eq!(mentions.len(), MAX_MENTIONS);
+        assert_eq!(mentions.last().unwrap(), "user9");
+    }
+
+    #[test]
+    fn handles_unicode_names() {
+        assert_eq!(parse_mentions("привет @Дмитрий!"), ["дмитрий"]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::state::Room;
+use axum::{Router, routing};
+use axum_prometheus::{
+    PrometheusMetricLayer,
+    metrics::{counter, describe_counter, describe_gauge, gauge},
+    metrics_exporter_prometheus::PrometheusHandle,
+};
+use dashmap::DashMap;
+use service_common::metrics::Recorder;
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+pub const METRICS_PATH: &str = "/metrics";
+
+pub const WS_CONNECTIONS_ACTIVE: &str = "ws_connections_active";
+pub const WS_MESSAGES_TOTAL: &str = "ws_messages_total";
+pub const CHAT_MESSAGES_PERSISTED_TOTAL: &str = "chat_messages_persisted_total";
+pub const KAFKA_PUBLISH_FAILURES_TOTAL: &str = "kafka_publish_failures_total";
+pub const WS_IDLE_CLOSES_TOTAL: &str = "ws_idle_closes_total";
+pub const WS_ROOMS_ACTIVE: &str = "ws_rooms_active";
+pub const WS_ROOM_RECEIVERS_MAX: &str = "ws_room_receivers_max";
+pub const PROCESS_UPTIME_SECONDS: &str = "process_uptime_seconds";
+pub const BUILD_INFO: &str = "build_info";
+
+/// How often [`record_rooms`] is sampled.
+pub const ROOM_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+
+/// Installs the global recorder rendered on `/metrics`, see [`service_common::metrics::install`].
+pub fn install() -> PrometheusHandle {
+    service_common::metrics::install(&Recorder {
+        buckets: &[],
+        on_install: || {
+            describe();
+            STARTED_AT.get_or_init(Instant::now);
+            gauge!(BUILD_INFO, "version" => env!("CARGO_PKG_VERSION")).set(1);
+        },
+    })
+}
+
+/// Measures the requests of every route of `router` and serves [`METRICS_PATH`] next to them. Scrapes are left out
+/// of the request metrics, so they do not measure themselves.
+pub fn instrument(router: Router) -> Router {
+    let handle = install();
+    router
+        .layer(PrometheusMetricLayer::new())
+        .route(METRICS_PATH, routing::get(move || async move { render(&handle) }))
+}
+
+fn render(handle: &PrometheusHandle) -> String {
+    if let Some(started_at) = STARTED_AT.get() {
+        gauge!(PROCESS_UPTIME_SECONDS).set(started_at.elapsed().as_secs_f64());
+    }
+    handle.render()
+}
+
+fn describe() {
+    describe_gauge!(WS_CONNECTIONS_ACTIVE, "Open websocket connections, by protocol");
+    describe_counter!(WS_MESSAGES_TOTAL, "Websocket frames received from clients, by event kind");
+    describe_counter!(CHAT_MESSAGES_PERSISTED_TOTAL, "Chat messages stored, over websocket and REST");
+    describe_counter!(
+        KAFKA_PUBLISH_FAILURES_TOTAL,
+        "Events that could not be published, by producer"
+    );
+    describe_counter!(
+        WS_IDLE_CLOSES_TOTAL,
+        "Websockets closed for sending nothing within the idle timeout"
+    );
+    describe_gauge!(WS_ROOMS_ACTIVE, "Rooms with at least one open socket");
+    describe_gauge!(
+        WS_ROOM_RECEIVERS_MAX,
+        "Sockets subscribed to the broadcasts of the busiest room"
+    );
+    describe_gauge!(
+        PROCESS_UPTIME_SECONDS,
+        "Seconds since the metrics recorder was installed at startup"
+    );
+    describe_gauge!(BUILD_INFO, "Always 1, labelled with the crate version");
+}
+
+/// `kind` is the client event's type, or `invalid` for a frame that did not parse as one.
+pub fn ws_message_received(kind: &'static str) {
+    counter!(WS_MESSAGES_TOTAL, "kind" => kind).increment(1);
+}
+
+pub fn chat_message_persisted() {
+    counter!(CHAT_MESSAGES_PERSISTED_TOTAL).increment(1);
+}
+
+/// `producer` is `mentions` or `outbox`.
+pub fn kafka_publish_failed(producer: &'static str) {
+    counter!(KAFKA_PUBLISH_FAILURES_TOTAL, "producer" => producer).increment(1);
+}
+
+pub fn ws_idle_closed() {
+    counter!(WS_IDLE_CLOSES_TOTAL).increment(1);
+}
+
+/// Room count and the receivers of the busiest room. Rooms come and go with their sockets, so they are summed
+/// up here rather than exported one series each.
+pub fn record_rooms(rooms: &DashMap<String, Room>) {
+    let busiest = rooms.iter().map(|room| room.sender.receiver_count()).max().unwrap_or(0);
+    gauge!(WS_ROOMS_ACTIVE).set(rooms.len() as f64);
+    gauge!(WS_ROOM_RECEIVERS_MAX).set(busiest as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_prometheus::AXUM_HTTP_REQUESTS_DURATION_SECONDS;
+    use axum_test::TestServer;
+
+    #[tokio::test]
+    async fn metrics_render_requests_but_not_scrapes() {
+        let router = Router::new().route("/ping", routing::get(crate::api::ping));
+        let server = TestServer::new(instrument(router));
+
+        server.get("/ping").await.assert_status_ok();
+        server.get(METRICS_PATH).await.assert_status_ok();
+        let rooms = DashMap::new();
+        rooms.insert("quiet".to_owned(), Room::new(4));
+        let busy = Room::new(4);
+        let _receivers = [busy.sender.subscribe(), busy.sender.subscribe()];
+        rooms.insert("busy".to_owned(), busy);
+        record_rooms(&rooms);
+        let response = server.get(METRICS_PATH).await;
+        response.assert_status_ok();
+        assert!(response.header("Content-Type").to_str().unwrap().starts_with("text/plain"));
+
+        let text = response.text();
+        let requests = format!("{AXUM_HTTP_REQUESTS_DURATION_SECONDS}_bucket");
+        assert!(
+            text.lines()
+                .any(|l| l.starts_with(&requests) && l.contains(r#"endpoint="/ping""#)),
+            "{text}"
+        );
+        assert!(!text.contains(r#"endpoint="/metrics""#), "{text}");
+        assert!(text.contains(&format!(r#"{BUILD_INFO}{{version="{}"}} 1"#, env!("CARGO_PKG_VERSION"))));
+        assert!(text.contains(&format!("{PROCESS_UPTIME_SECONDS} ")));
+        assert!(text.contains(&format!("{WS_ROOMS_ACTIVE} 2\n")), "{text}");
+        assert!(text.contains(&format!("{WS_ROOM_RECEIVERS_MAX} 2\n")), "{text}");
+    }
+}
@@ -0,0 +1,95 @@
+use axum_prometheus::metrics;
+use kafka_client::producer::KafkaProducer;
+use scylladb_client::{
+    ChatMessageStore,
+    outbox::{OUTBOX_BUCKETS, OutboxEvent},
+};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutboxRelayConfig {
+    pub poll_interval: Duration,
+    /// Rows read per bucket and poll.
+    pub batch_size: i32,
+}
+
+/// Produces the events staged in `outbox_events` for the producer's topic and deletes each once the brokers
+/// have acknowledged it.
+///
+/// Delivery is at-least-once: a relay stopped between the two steps leaves the row behind, and whichever relay
+/// polls next produces it again. Events of one key share a bucket and are produced in the order they were
+/// staged; a bucket is left for the next poll at its first failure so none overtakes an earlier one. Running
+/// more than one relay per topic is safe but duplicates most events.
+pub struct OutboxRelay {
+    producer: KafkaProducer,
+    config: OutboxRelayConfig,
+}
+
+impl OutboxRelay {
+    pub fn new(producer: KafkaProducer, config: OutboxRelayConfig) -> Self {
+        Self { producer, config }
+    }
+
+    /// One pass over every bucket; returns how many events were published.
+    pub async fn relay_once(&self, store: &ChatMessageStore) -> usize {
+        let mut published = 0;
+        for bucket in 0..OUTBOX_BUCKETS {
+            published += self.relay_bucket(store, bucket).await;
+        }
+        if published > 0 {
+            metrics::counter!("outbox_events_published_total").increment(published as u64);
+        }
+        published
+    }
+
+    async fn relay_bucket(&self, store: &ChatMessageStore, bucket: i32) -> usize {
+        let events = match store
+            .pending_outbox_events(self.producer.topic(), bucket, self.config.batch_size)
+            .await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::error!(bucket, "Failed to read outbox events: {e}");
+                return 0;
+            }
+        };
+
+        let mut published = 0;
+        for event in events {
+            if let Err(e) = self.publish(&event).await {
+                tracing::warn!(bucket, event_id = %event.event_id, "Failed to publish outbox event: {e}");
+                crate::metrics::kafka_publish_failed("outbox");
+                break;
+            }
+            if let Err(e) = store.mark_outbox_published(&event).await {
+                tracing::warn!(bucket, event_id = %event.event_id, "Failed to mark outbox event published: {e}");
+                break;
+            }
+            published += 1;
+        }
+        published
+    }
+
+    async fn publish(&self, event: &OutboxEvent) -> Result<(), String> {
+        let payload: serde_json::Value = serde_json::from_str(&event.payload).map_err(|e| e.to_string())?;
+        self.producer
+            .send_with_headers(&event.key, &payload, &event.headers)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Runs [`Self::relay_once`] every `poll_interval` until `shutdown` is cancelled.
+    pub async fn run(&self, store: &ChatMessageStore, shutdown: CancellationToken) {
+        let mut ticker = tokio::time::interval(self.config.poll_interval);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            self.relay_once(store).await;
+        }
+        tracing::info!(topic = self.producer.topic(), "Outbox relay stopped");
+    }
+}
@@ -0,0 +1,6 @@
+use std::time::Duration;
+
+pub use service_common::rate_limit::TokenBucket;
+
+/// Window of `WS_CHAT_RATE_LIMIT`.
+pub const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
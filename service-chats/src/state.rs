@@ -1,21 +1,154 @@
-use crate::{Config, api::schemas::ServerEvent};
+use crate::{
+    Config,
+    api::schemas::{MemberPayload, ServerEvent},
+    auth::Authenticator,
+    invite::InviteSigner,
+};
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use kafka_client::{config::ProducerConfig, producer::KafkaProducer};
 use scylladb_client::{ChatMessageStore, ScyllaConfig};
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{Notify, broadcast};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 pub type ServerState = Arc<ServerData>;
 
 pub struct Room {
     pub sender: broadcast::Sender<ServerEvent>,
+    /// Users with at least one open socket in the room.
+    pub members: DashMap<Uuid, PresenceInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PresenceInfo {
+    pub username: String,
+    /// When the user's first socket still open joined.
+    pub connected_at: DateTime<Utc>,
+    /// Open sockets of the user, e.g. one per browser tab.
+    pub connections: usize,
+}
+
+impl Room {
+    pub fn new(broadcast_buffer_size: usize) -> Self {
+        let (sender, _) = broadcast::channel(broadcast_buffer_size);
+        Self {
+            sender,
+            members: DashMap::new(),
+        }
+    }
+
+    /// Counts a socket of `user_id`; `true` if the user was not in the room yet.
+    pub fn join(&self, user_id: Uuid, username: &str) -> bool {
+        let mut presence = self.members.entry(user_id).or_insert_with(|| PresenceInfo {
+            username: username.to_owned(),
+            connected_at: Utc::now(),
+            connections: 0,
+        });
+        presence.connections += 1;
+        presence.connections == 1
+    }
+
+    /// Releases a socket of `user_id`; `true` if it was the user's last one.
+    pub fn leave(&self, user_id: Uuid) -> bool {
+        self.members
+            .remove_if_mut(&user_id, |_, presence| {
+                presence.connections = presence.connections.saturating_sub(1);
+                presence.connections == 0
+            })
+            .is_some()
+    }
+
+    /// Members in the order they joined.
+    pub fn member_list(&self) -> Vec<MemberPayload> {
+        let mut members: Vec<MemberPayload> = self
+            .members
+            .iter()
+            .map(|entry| MemberPayload::new(*entry.key(), entry.value()))
+            .collect();
+        members.sort_by_key(|m| m.connected_at);
+        members
+    }
+}
+
+/// Open websocket connections, so shutdown can wait for them to close.
+#[derive(Default)]
+pub struct Connections {
+    open: AtomicUsize,
+    closed: Notify,
+}
+
+/// Counts one connection until dropped.
+pub struct ConnectionGuard<'a>(&'a Connections);
+
+impl Connections {
+    pub fn track(&self) -> ConnectionGuard<'_> {
+        self.open.fetch_add(1, Ordering::AcqRel);
+        ConnectionGuard(self)
+    }
+
+    pub fn count(&self) -> usize {
+        self.open.load(Ordering::Acquire)
+    }
+
+    /// Waits up to `timeout` for every connection to close; `false` if some are still open.
+    pub async fn wait_closed(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let closed = self.closed.notified();
+                if self.count() == 0 {
+                    return;
+                }
+                closed.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.open.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.closed.notify_waiters();
+        }
+    }
 }
 
 pub struct ServerData {
     pub message_store: ChatMessageStore,
     pub rooms: DashMap<String, Room>,
     pub broadcast_buffer_size: usize,
+    /// Requests still running after this long answer `408`.
+    pub request_timeout: Duration,
+    pub ws_max_frame_size: usize,
+    pub ws_max_message_size: usize,
+    /// Chat messages per connection and [`crate::rate_limit::CHAT_RATE_WINDOW`]; 0 disables the limit.
+    pub ws_chat_rate_limit: u32,
+    /// Pings keep sockets behind idle-dropping load balancers open and give clients something to answer.
+    pub ws_ping_interval: Duration,
+    /// Sockets that send no frame, pongs included, for this long are closed with `1000`.
+    pub ws_idle_timeout: Duration,
     pub http_client: reqwest::Client,
+    /// Verifies the bearer tokens of [`crate::auth::AuthUser`] routes.
+    pub auth: Authenticator,
     pub channels_service_url: String,
+    pub invites: Option<InviteSigner>,
+    pub admin_token: Option<String>,
+    /// Producer for mention notifications; `None` unless `NOTIFICATIONS_TOPIC` is set.
+    pub notifications: Option<KafkaProducer>,
+    /// Topic new messages are staged for in the outbox; `None` unless `OUTBOX_TOPIC` is set.
+    pub outbox_topic: Option<String>,
+    /// Cancelled on shutdown; open websockets then close with `1001`.
+    pub shutdown: CancellationToken,
+    pub ws_connections: Connections,
 }
 
 impl ServerData {
@@ -38,12 +171,72 @@ impl ServerData {
             .build()
             .expect("Failed to build HTTP client");
 
+        let notifications = config.notifications_topic.as_deref().map(|topic| {
+            let producer_config = ProducerConfig::builder(&config.kafka_brokers, topic)
+                .build()
+                .expect("Invalid Kafka producer config");
+            KafkaProducer::new(producer_config).expect("Failed to create Kafka producer")
+        });
+
         Arc::new(ServerData {
             message_store,
             rooms: DashMap::with_capacity(10_000),
             broadcast_buffer_size: config.broadcast_buffer_size,
+            request_timeout: Duration::from_secs(config.request_timeout_secs),
+            ws_max_frame_size: config.ws_max_frame_size,
+            ws_max_message_size: config.ws_max_message_size,
+            ws_chat_rate_limit: config.ws_chat_rate_limit,
+            ws_ping_interval: Duration::from_secs(config.ws_ping_interval_secs),
+            ws_idle_timeout: Duration::from_secs(config.ws_idle_timeout_secs),
             http_client,
+            auth: Authenticator::new(&config.jwt_secret),
             channels_service_url: config.channels_service_url.clone(),
+            invites: config.invite_secret.as_deref().map(InviteSigner::new),
+            admin_token: config.admin_token.clone(),
+            notifications,
+            outbox_topic: config.outbox_topic.clone(),
+            shutdown: CancellationToken::new(),
+            ws_connections: Connections::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_leave_with_their_last_socket() {
+        let room = Room::new(4);
+        let (alice, bob) = (Uuid::now_v7(), Uuid::now_v7());
+        assert!(room.join(alice, "alice"));
+        assert!(room.join(bob, "bob"));
+        assert!(!room.join(bob, "bob"));
+
+        assert!(!room.leave(bob));
+        assert_eq!(room.member_list().len(), 2);
+        assert!(room.leave(bob));
+        assert!(!room.leave(bob));
+        let members = room.member_list();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].username, "alice");
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_last_connection() {
+        let connections = Connections::default();
+        assert!(connections.wait_closed(Duration::ZERO).await);
+
+        let first = connections.track();
+        let second = connections.track();
+        assert_eq!(connections.count(), 2);
+        assert!(!connections.wait_closed(Duration::from_millis(10)).await);
+
+        drop(first);
+        let (closed, ()) = tokio::join!(connections.wait_closed(Duration::from_secs(5)), async move {
+            tokio::task::yield_now().await;
+            drop(second);
+        });
+        assert!(closed);
+    }
+}
@@ -0,0 +1,39 @@
+use kafka_client::{
+    admin::KafkaAdmin,
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    producer::KafkaProducer,
+    schemas::{Action, KafkaMessage},
+};
+use service_chats::mentions::{MentionEvent, publish_mentions};
+use testcontainers_modules::{kafka::Kafka, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_mention_notifications_are_published_per_user() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    KafkaAdmin::new(&brokers)?.ensure_topic("notifications", 1, 1, None).await?;
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, "notifications").build()?)?;
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "mentions-test", "notifications").build()?)?;
+
+    let author_id = Uuid::now_v7();
+    let mentioned = Uuid::now_v7();
+    let event = MentionEvent {
+        message_id: Uuid::now_v7(),
+        chat_id: Uuid::now_v7(),
+        author_id,
+        author_username: "alice".into(),
+    };
+    // The author mentioning themselves must not produce a notification.
+    publish_mentions(&producer, &event, &[author_id, mentioned]).await?;
+
+    let received = consumer.consume_message::<KafkaMessage<MentionEvent>>().await?;
+    assert_eq!(received.message.user_id, mentioned.to_string());
+    assert_eq!(received.message.action, Action::Create);
+    assert_eq!(received.message.data, Some(event));
+    Ok(())
+}
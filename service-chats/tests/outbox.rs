@@ -0,0 +1,85 @@
+use kafka_client::{
+    admin::KafkaAdmin,
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    producer::KafkaProducer,
+};
+use scylladb_client::{
+    ChatMessage, ChatMessageStore, ScyllaConfig,
+    outbox::{OUTBOX_BUCKETS, OutboxEvent},
+};
+use service_chats::outbox::{OutboxRelay, OutboxRelayConfig};
+use std::{collections::HashSet, time::Duration};
+use testcontainers_modules::{kafka::Kafka, scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const TOPIC: &str = "chat-messages";
+
+async fn pending(store: &ChatMessageStore) -> anyhow::Result<usize> {
+    let mut total = 0;
+    for bucket in 0..OUTBOX_BUCKETS {
+        total += store.pending_outbox_events(TOPIC, bucket, 100).await?.len();
+    }
+    Ok(total)
+}
+
+#[tokio::test]
+async fn test_new_relay_picks_up_events_left_by_a_dropped_one() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let scylla_port = scylla.get_host_port_ipv4(9042).await?;
+    let store = ChatMessageStore::new(
+        &ScyllaConfig {
+            uri: format!("127.0.0.1:{scylla_port}"),
+            replication_factor: 1,
+            ..Default::default()
+        },
+        true,
+    )
+    .await?;
+
+    let kafka = Kafka::default().start().await?;
+    let brokers = format!("{}:{}", kafka.get_host().await?, kafka.get_host_port_ipv4(9093).await?);
+    KafkaAdmin::new(&brokers)?.ensure_topic(TOPIC, 1, 1, None).await?;
+
+    let chat_id = Uuid::now_v7();
+    let mut staged = HashSet::new();
+    for i in 0..5 {
+        let (message, _) = store
+            .create_message_with_event(chat_id, Uuid::now_v7(), None, format!("message {i}"), Vec::new(), |message| {
+                OutboxEvent::new(TOPIC, &message.chat_id.to_string(), message)
+            })
+            .await?;
+        staged.insert(message.message_id);
+    }
+    assert_eq!(pending(&store).await?, 5);
+
+    let config = OutboxRelayConfig {
+        poll_interval: Duration::from_millis(100),
+        batch_size: 2,
+    };
+    let relay = OutboxRelay::new(KafkaProducer::new(ProducerConfig::builder(&brokers, TOPIC).build()?)?, config);
+    // All events share a key, hence a bucket, so one pass publishes a single batch and stops.
+    assert_eq!(relay.relay_once(&store).await, 2);
+    drop(relay);
+    assert_eq!(pending(&store).await?, 3);
+
+    let relay = OutboxRelay::new(KafkaProducer::new(ProducerConfig::builder(&brokers, TOPIC).build()?)?, config);
+    let consumer = KafkaConsumer::new(ConsumerConfig::builder(&brokers, "outbox-test", TOPIC).build()?)?;
+    let mut received = HashSet::new();
+    tokio::select! {
+        _ = relay.run(&store, CancellationToken::new()) => unreachable!("relay stops only on shutdown"),
+        consumed = async {
+            while received.len() < staged.len() {
+                received.insert(consumer.consume_message::<ChatMessage>().await?.message.message_id);
+            }
+            anyhow::Ok(())
+        } => consumed?,
+    }
+    // Dropping `run` may have interrupted it between producing an event and deleting its row.
+    relay.relay_once(&store).await;
+
+    assert_eq!(received, staged);
+    assert_eq!(pending(&store).await?, 0);
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use axum::{Router, http::StatusCode, routing};
+use axum_test::TestServer;
+use dashmap::DashMap;
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use serde_json::{Value, json};
+use service_chats::{
+    ServerBuilder,
+    auth::{Authenticator, mint_token, mint_token_for},
+    state::{Connections, Room, ServerData, ServerState},
+};
+use std::{sync::Arc, time::Duration};
+use testcontainers_modules::{
+    scylladb::ScyllaDB,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const JWT_SECRET: &str = "test-secret-of-at-least-32-characters";
+
+struct TestContext {
+    server: TestServer,
+    state: ServerState,
+    _scylla: ContainerAsync<ScyllaDB>,
+}
+
+/// Stands in for the channels service, which answers every subscription check with `200`.
+async fn spawn_channels_service() -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let router = Router::new().route("/channels/{id}/subscribers/check", routing::get(|| async { StatusCode::OK }));
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(format!("http://{addr}"))
+}
+
+async fn setup() -> anyhow::Result<TestContext> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let message_store = ChatMessageStore::new(&config, true).await?;
+
+    let state: ServerState = Arc::new(ServerData {
+        message_store,
+        rooms: DashMap::new(),
+        broadcast_buffer_size: 16,
+        request_timeout: Duration::from_secs(10),
+        ws_max_frame_size: 64 * 1024,
+        ws_max_message_size: 64 * 1024,
+        ws_chat_rate_limit: 0,
+        ws_ping_interval: Duration::from_secs(30),
+        ws_idle_timeout: Duration::from_secs(90),
+        http_client: reqwest::Client::new(),
+        auth: Authenticator::new(JWT_SECRET),
+        channels_service_url: spawn_channels_service().await?,
+        invites: None,
+        admin_token: None,
+        notifications: None,
+        outbox_topic: None,
+        shutdown: CancellationToken::new(),
+        ws_connections: Connections::default(),
+    });
+    let server = TestServer::new(ServerBuilder::init_router(Arc::clone(&state)));
+
+    Ok(TestContext {
+        server,
+        state,
+        _scylla: scylla,
+    })
+}
+
+fn token(user_id: Uuid) -> String {
+    mint_token(JWT_SECRET, &user_id.to_string(), 3600)
+}
+
+async fn post_message(ctx: &TestContext, chat_id: Uuid, user_id: Uuid, text: &str) -> Value {
+    let response = ctx
+        .server
+        .post(&format!("/chats/{chat_id}/messages"))
+        .authorization_bearer(mint_token_for(JWT_SECRET, &user_id.to_string(), Some("alice"), 3600))
+        .json(&json!({ "text": text }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    response.json()
+}
+
+#[tokio::test]
+async fn test_message_crud_over_rest() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let user_id = Uuid::now_v7();
+
+    let first = post_message(&ctx, chat_id, user_id, "first").await;
+    let second = post_message(&ctx, chat_id, user_id, "  second  ").await;
+    assert_eq!(second["text"], "second");
+    assert_eq!(second["username"], "alice");
+
+    let response = ctx
+        .server
+        .post(&format!("/chats/{chat_id}/messages"))
+        .authorization_bearer(token(user_id))
+        .json(&json!({ "text": "   " }))
+        .await;
+    response.assert_status_bad_request();
+
+    // Newest first, one per page.
+    let page: Value = ctx
+        .server
+        .get(&format!("/chats/{chat_id}/messages?limit=1"))
+        .authorization_bearer(token(user_id))
+        .await
+        .json();
+    assert_eq!(page["messages"][0]["message_id"], second["message_id"]);
+    let before = page["next_before"].as_str().unwrap();
+    let page: Value = ctx
+        .server
+        .get(&format!("/chats/{chat_id}/messages?limit=1&before={before}"))
+        .authorization_bearer(token(user_id))
+        .await
+        .json();
+    assert_eq!(page["messages"][0]["message_id"], first["message_id"]);
+
+    // Websocket clients of the room see REST edits and deletes.
+    let room = Room::new(16);
+    let mut events = room.sender.subscribe();
+    ctx.state.rooms.insert(chat_id.to_string(), room);
+
+    let message_id = first["message_id"].as_str().unwrap();
+    let response = ctx
+        .server
+        .patch(&format!("/messages/{message_id}"))
+        .authorization_bearer(token(user_id))
+        .json(&json!({ "text": "edited" }))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<Value>()["text"], "edited");
+    let event = serde_json::to_value(events.recv().await?)?;
+    assert_eq!(event["type"], "edited");
+    assert_eq!(event["text"], "edited");
+
+    ctx.server
+        .delete(&format!("/messages/{message_id}"))
+        .authorization_bearer(token(user_id))
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    let event = serde_json::to_value(events.recv().await?)?;
+    assert_eq!(event["type"], "deleted");
+    assert_eq!(event["message_id"], message_id);
+
+    let page: Value = ctx
+        .server
+        .get(&format!("/chats/{chat_id}/messages"))
+        .authorization_bearer(token(user_id))
+        .await
+        .json();
+    assert_eq!(page["messages"].as_array().unwrap().len(), 1);
+    assert!(page["next_before"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_message_is_not_found() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let bearer = token(Uuid::now_v7());
+    let message_id = Uuid::now_v7();
+
+    ctx.server
+        .patch(&format!("/messages/{message_id}"))
+        .authorization_bearer(&bearer)
+        .json(&json!({ "text": "edited" }))
+        .await
+        .assert_status_not_found();
+    ctx.server
+        .delete(&format!("/messages/{message_id}"))
+        .authorization_bearer(&bearer)
+        .await
+        .assert_status_not_found();
+    ctx.server
+        .delete("/messages/not-a-uuid")
+        .authorization_bearer(&bearer)
+        .await
+        .assert_status_bad_request();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_errors_share_the_envelope() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+
+    let response = ctx
+        .server
+        .delete(&format!("/messages/{}", Uuid::now_v7()))
+        .authorization_bearer(token(Uuid::now_v7()))
+        .await;
+    response.assert_status_not_found();
+    assert_eq!(
+        response.json::<Value>(),
+        json!({"code": "not_found", "error": "Message not found"})
+    );
+
+    let response = ctx.server.get(&format!("/chats/{}/messages", Uuid::now_v7())).await;
+    response.assert_status_unauthorized();
+    assert_eq!(
+        response.json::<Value>(),
+        json!({"code": "unauthorized", "error": "Missing bearer token"})
+    );
+
+    let response = ctx.server.get("/nowhere").await;
+    response.assert_status_not_found();
+    assert_eq!(response.json::<Value>(), json!({"code": "not_found", "error": "Not found"}));
+    Ok(())
+}
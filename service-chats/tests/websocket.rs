@@ -0,0 +1,448 @@
+use axum::{Router, http::StatusCode, routing};
+use axum_test::{TestServer, TestWebSocket, WsMessage};
+use dashmap::DashMap;
+use scylladb_client::{ChatMessageStore, ScyllaConfig};
+use serde_json::{Value, json};
+use service_chats::{
+    ServerBuilder,
+    auth::{Authenticator, mint_token, mint_token_for},
+    state::{Connections, ServerData, ServerState},
+};
+use std::{sync::Arc, time::Duration};
+use testcontainers_modules::{
+    scylladb::ScyllaDB,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const JWT_SECRET: &str = "test-secret-of-at-least-32-characters";
+
+/// Stands in for the channels service, which answers every subscription check with `200`.
+async fn spawn_channels_service() -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let router = Router::new().route("/channels/{id}/subscribers/check", routing::get(|| async { StatusCode::OK }));
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(format!("http://{addr}"))
+}
+
+/// Browsers cannot set headers on websocket upgrades, so the token goes in the query.
+async fn open(server: &TestServer, path: &str, user_id: Uuid, username: &str) -> TestWebSocket {
+    let token = mint_token_for(JWT_SECRET, &user_id.to_string(), Some(username), 3600);
+    let separator = if path.contains('?') { '&' } else { '?' };
+    server
+        .get_websocket(&format!("{path}{separator}token={token}"))
+        .await
+        .into_websocket()
+        .await
+}
+
+/// Reads the history replay from `history_start` to `history_end`; the messages of all pages, newest first.
+async fn replay(socket: &mut TestWebSocket) -> Vec<Value> {
+    let start: Value = socket.receive_json().await;
+    assert_eq!(start, json!({"type": "history_start"}));
+    let mut messages = Vec::new();
+    loop {
+        let page: Value = socket.receive_json().await;
+        match page["type"].as_str() {
+            Some("history") => messages.extend(page["messages"].as_array().unwrap().iter().cloned()),
+            Some("history_end") => return messages,
+            other => panic!("unexpected {other:?} during the replay"),
+        }
+    }
+}
+
+/// Opens a socket and reads past the member list and history, which v1 clients do not get.
+async fn connect(server: &TestServer, path: &str, user_id: Uuid, username: &str) -> TestWebSocket {
+    let mut socket = open(server, path, user_id, username).await;
+    if !path.contains("proto=1") {
+        let members: Value = socket.receive_json().await;
+        assert_eq!(members["type"], "members");
+        if !path.contains("history=0") {
+            replay(&mut socket).await;
+        }
+    }
+    socket
+}
+
+/// Next event other than a join or leave.
+async fn receive_event(socket: &mut TestWebSocket) -> Value {
+    loop {
+        let event: Value = socket.receive_json().await;
+        if event["type"] != "user_joined" && event["type"] != "user_left" {
+            return event;
+        }
+    }
+}
+
+async fn members(server: &TestServer, chat_id: Uuid) -> Value {
+    server
+        .get(&format!("/ws/{chat_id}/members"))
+        .authorization_bearer(mint_token(JWT_SECRET, &Uuid::now_v7().to_string(), 3600))
+        .await
+        .json::<Value>()["members"]
+        .clone()
+}
+
+async fn setup() -> anyhow::Result<(ContainerAsync<ScyllaDB>, TestServer)> {
+    setup_with_chat_limit(0).await
+}
+
+async fn setup_with_chat_limit(ws_chat_rate_limit: u32) -> anyhow::Result<(ContainerAsync<ScyllaDB>, TestServer)> {
+    let (scylla, _, server) = setup_with_state(|data| data.ws_chat_rate_limit = ws_chat_rate_limit).await?;
+    Ok((scylla, server))
+}
+
+/// Serves a state that `configure` may change first.
+async fn setup_with_state(
+    configure: impl FnOnce(&mut ServerData),
+) -> anyhow::Result<(ContainerAsync<ScyllaDB>, ServerState, TestServer)> {
+    let scylla = ScyllaDB::default().start().await?;
+    let port = scylla.get_host_port_ipv4(9042).await?;
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{port}"),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let mut data = ServerData {
+        message_store: ChatMessageStore::new(&config, true).await?,
+        rooms: DashMap::new(),
+        broadcast_buffer_size: 16,
+        request_timeout: Duration::from_secs(10),
+        ws_max_frame_size: 64 * 1024,
+        ws_max_message_size: 64 * 1024,
+        ws_chat_rate_limit: 0,
+        ws_ping_interval: Duration::from_secs(30),
+        ws_idle_timeout: Duration::from_secs(90),
+        http_client: reqwest::Client::new(),
+        auth: Authenticator::new(JWT_SECRET),
+        channels_service_url: spawn_channels_service().await?,
+        invites: None,
+        admin_token: None,
+        notifications: None,
+        outbox_topic: None,
+        shutdown: CancellationToken::new(),
+        ws_connections: Connections::default(),
+    };
+    configure(&mut data);
+    let state: ServerState = Arc::new(data);
+    let server = TestServer::builder()
+        .http_transport()
+        .build(ServerBuilder::init_router(Arc::clone(&state)));
+    Ok((scylla, state, server))
+}
+
+#[tokio::test]
+async fn test_edit_and_delete_reach_other_clients() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let (alice_id, bob_id) = (Uuid::now_v7(), Uuid::now_v7());
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), alice_id, "alice").await;
+    let mut bob = connect(&server, &format!("/ws/{chat_id}"), bob_id, "bob").await;
+    let mut legacy = connect(&server, &format!("/ws/{chat_id}?proto=1"), Uuid::now_v7(), "carol").await;
+
+    alice.send_json(&json!({"type": "chat", "text": "hello"})).await;
+    let message: Value = receive_event(&mut bob).await;
+    assert_eq!(message["type"], "message");
+    assert_eq!(message["text"], "hello");
+    let message_id = message["message_id"].as_str().unwrap().to_owned();
+    let _: Value = receive_event(&mut alice).await;
+    // v1 clients still get the bare payload.
+    let payload: Value = legacy.receive_json().await;
+    assert_eq!(payload["message_id"], message_id.as_str());
+    assert!(payload.get("type").is_none());
+
+    // Only the author may change the message.
+    bob.send_json(&json!({"type": "delete", "message_id": message_id})).await;
+    let refused: Value = receive_event(&mut bob).await;
+    assert_eq!(refused, json!({"type": "error", "text": "Permission denied"}));
+
+    alice
+        .send_json(&json!({"type": "edit", "message_id": message_id, "text": "hello, edited"}))
+        .await;
+    let edited: Value = receive_event(&mut bob).await;
+    assert_eq!(edited["type"], "edited");
+    assert_eq!(edited["message_id"], message_id.as_str());
+    assert_eq!(edited["text"], "hello, edited");
+
+    alice.send_json(&json!({"type": "delete", "message_id": message_id})).await;
+    let deleted: Value = receive_event(&mut bob).await;
+    assert_eq!(deleted, json!({"type": "deleted", "message_id": message_id}));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_history_names_the_author() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let alice_id = Uuid::now_v7();
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), alice_id, "alice").await;
+    alice.send_json(&json!({"type": "chat", "text": "hello"})).await;
+    let sent: Value = alice.receive_json().await;
+    assert_eq!(sent["v"], 2);
+    assert_eq!(sent["user_id"], alice_id.to_string());
+
+    let mut bob = open(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "bob").await;
+    let _members: Value = bob.receive_json().await;
+    let history = replay(&mut bob).await;
+    let message = &history[0];
+    assert_eq!(message["v"], 2);
+    assert_eq!(message["message_id"], sent["message_id"]);
+    assert_eq!(message["user_id"], alice_id.to_string());
+    assert_eq!(message["username"], "alice");
+    assert_ne!(message["user_id"], message["message_id"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_presence_follows_joins_and_leaves() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let (alice_id, bob_id) = (Uuid::now_v7(), Uuid::now_v7());
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), alice_id, "alice").await;
+
+    // The member list comes before the history and includes the new client.
+    let mut bob = open(&server, &format!("/ws/{chat_id}"), bob_id, "bob").await;
+    let list: Value = bob.receive_json().await;
+    assert_eq!(list["type"], "members");
+    let names: Vec<&str> = list["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["username"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["alice", "bob"]);
+    assert!(replay(&mut bob).await.is_empty());
+    let joined: Value = alice.receive_json().await;
+    assert_eq!(joined, json!({"type": "user_joined", "user_id": bob_id, "username": "bob"}));
+
+    // A second tab neither joins nor, when closed, leaves.
+    let bob_tab = connect(&server, &format!("/ws/{chat_id}"), bob_id, "bob").await;
+    assert_eq!(members(&server, chat_id).await.as_array().unwrap().len(), 2);
+    bob_tab.close().await;
+
+    // Dropping the last socket without a close frame still counts as leaving.
+    drop(bob);
+    let left: Value = alice.receive_json().await;
+    assert_eq!(left, json!({"type": "user_left", "user_id": bob_id, "username": "bob"}));
+    let remaining = members(&server, chat_id).await;
+    assert_eq!(remaining.as_array().unwrap().len(), 1);
+    assert_eq!(remaining[0]["user_id"], alice_id.to_string());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_messages_over_the_limit_get_an_error_frame() -> anyhow::Result<()> {
+    let (_scylla, server) = setup_with_chat_limit(2).await?;
+    let chat_id = Uuid::now_v7();
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+
+    for text in ["one", "two"] {
+        alice.send_json(&json!({"type": "chat", "text": text})).await;
+        let message: Value = receive_event(&mut alice).await;
+        assert_eq!(message["text"], text);
+    }
+    alice.send_json(&json!({"type": "chat", "text": "three"})).await;
+    let refused: Value = receive_event(&mut alice).await;
+    assert_eq!(refused, json!({"type": "error", "text": "Rate limit exceeded"}));
+
+    // Other events are not limited, and a second connection has its own budget.
+    alice.send_json(&json!({"type": "typing"})).await;
+    assert_eq!(receive_event(&mut alice).await["type"], "typing");
+    let mut tab = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "bob").await;
+    tab.send_json(&json!({"type": "chat", "text": "four"})).await;
+    assert_eq!(receive_event(&mut tab).await["text"], "four");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_sockets_with_1001() -> anyhow::Result<()> {
+    let (_scylla, state, server) = setup_with_state(|_| {}).await?;
+    let chat_id = Uuid::now_v7();
+    let mut socket = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+    socket.send_json(&json!({"type": "chat", "text": "last words"})).await;
+    assert_eq!(receive_event(&mut socket).await["text"], "last words");
+    assert_eq!(state.ws_connections.count(), 1);
+
+    state.shutdown.cancel();
+    let WsMessage::Close(Some(frame)) = socket.receive_message().await else {
+        panic!("expected a close frame");
+    };
+    assert_eq!(u16::from(frame.code), 1001);
+    assert!(state.ws_connections.wait_closed(Duration::from_secs(5)).await);
+    Ok(())
+}
+
+fn short_keepalive(data: &mut ServerData) {
+    data.ws_ping_interval = Duration::from_millis(200);
+    data.ws_idle_timeout = Duration::from_millis(600);
+}
+
+#[tokio::test]
+async fn test_sockets_that_ignore_pings_are_closed_when_idle() -> anyhow::Result<()> {
+    let (_scylla, state, server) = setup_with_state(short_keepalive).await?;
+    let chat_id = Uuid::now_v7();
+    let mut socket = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+
+    // Not reading leaves the pings unanswered.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let frame = loop {
+        match socket.receive_message().await {
+            WsMessage::Close(frame) => break frame,
+            WsMessage::Ping(_) => continue,
+            other => panic!("unexpected {other:?}"),
+        }
+    };
+    assert_eq!(frame.map(|f| u16::from(f.code)), Some(1000));
+    assert!(state.ws_connections.wait_closed(Duration::from_secs(5)).await);
+    assert!(state.rooms.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sockets_answering_pings_stay_open() -> anyhow::Result<()> {
+    let (_scylla, state, server) = setup_with_state(short_keepalive).await?;
+    let chat_id = Uuid::now_v7();
+    let mut socket = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+
+    // Reading answers every ping with a pong.
+    let mut pings = 0;
+    let until = tokio::time::Instant::now() + Duration::from_secs(2);
+    while let Ok(message) = tokio::time::timeout_at(until, socket.receive_message()).await {
+        let WsMessage::Ping(_) = message else {
+            panic!("unexpected {message:?}");
+        };
+        pings += 1;
+    }
+    assert!(pings >= 5, "only {pings} pings in 2s");
+    assert_eq!(state.ws_connections.count(), 1);
+
+    socket.send_json(&json!({"type": "chat", "text": "still here"})).await;
+    let message = loop {
+        match socket.receive_message().await {
+            WsMessage::Text(text) => break serde_json::from_str::<Value>(&text)?,
+            _ => continue,
+        }
+    };
+    assert_eq!(message["text"], "still here");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lagging_sockets_are_told_how_much_they_missed() -> anyhow::Result<()> {
+    let (_scylla, _, server) = setup_with_state(|data| data.broadcast_buffer_size = 2).await?;
+    let chat_id = Uuid::now_v7();
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+    let mut bob = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "bob").await;
+
+    // Bob's events are broadcast back to back, faster than alice's socket drains the two-event channel.
+    for _ in 0..50 {
+        bob.send_json(&json!({"type": "typing"})).await;
+    }
+    let desynced = loop {
+        let event = receive_event(&mut alice).await;
+        if event["type"] == "desynced" {
+            break event;
+        }
+        assert_eq!(event["type"], "typing");
+    };
+    let missed = desynced["missed"].as_u64().unwrap();
+    assert!((1..=48).contains(&missed), "missed {missed} of 50");
+    Ok(())
+}
+
+/// Next event other than a join or leave, from a MessagePack socket.
+async fn receive_msgpack_event(socket: &mut TestWebSocket) -> Value {
+    loop {
+        let WsMessage::Binary(frame) = socket.receive_message().await else {
+            panic!("expected a binary frame");
+        };
+        let event: Value = rmp_serde::from_slice(&frame).unwrap();
+        if event["type"] != "user_joined" && event["type"] != "user_left" {
+            return event;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_json_and_msgpack_clients_share_a_room() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+    let mut bob = open(
+        &server,
+        &format!("/ws/{chat_id}?format=msgpack&history=0"),
+        Uuid::now_v7(),
+        "bob",
+    )
+    .await;
+    assert_eq!(receive_msgpack_event(&mut bob).await["type"], "members");
+
+    let chat = rmp_serde::to_vec_named(&json!({"type": "chat", "text": "from bob"}))?;
+    bob.send_message(WsMessage::Binary(chat.into())).await;
+    let echoed = receive_msgpack_event(&mut bob).await;
+    let received = receive_event(&mut alice).await;
+    assert_eq!(received["type"], "message");
+    assert_eq!(received["text"], "from bob");
+    assert_eq!(echoed, received);
+
+    alice.send_json(&json!({"type": "chat", "text": "from alice"})).await;
+    let received = receive_msgpack_event(&mut bob).await;
+    assert_eq!(received["type"], "message");
+    assert_eq!(received["username"], "alice");
+    assert_eq!(received["text"], "from alice");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_history_window_follows_the_query() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    let mut alice = connect(&server, &format!("/ws/{chat_id}"), Uuid::now_v7(), "alice").await;
+    let mut sent = Vec::new();
+    for i in 0..30 {
+        alice
+            .send_json(&json!({"type": "chat", "text": format!("message {i}")}))
+            .await;
+        sent.push(receive_event(&mut alice).await);
+    }
+
+    // More than a page, in several `history` frames, newest first.
+    let mut bob = open(&server, &format!("/ws/{chat_id}?history=28"), Uuid::now_v7(), "bob").await;
+    let _members: Value = bob.receive_json().await;
+    let history = replay(&mut bob).await;
+    assert_eq!(history.len(), 28);
+    assert_eq!(history[0]["text"], "message 29");
+    assert_eq!(history[27]["text"], "message 2");
+
+    let since = sent[26]["ts"].as_u64().unwrap();
+    let mut carol = open(&server, &format!("/ws/{chat_id}?since={since}"), Uuid::now_v7(), "carol").await;
+    let _members: Value = carol.receive_json().await;
+    let newer = replay(&mut carol).await;
+    assert!(!newer.is_empty() && newer.len() <= 3, "{newer:?}");
+    assert!(newer.iter().all(|m| m["ts"].as_u64().unwrap() > since));
+    assert_eq!(newer[0]["text"], "message 29");
+
+    // No replay at all; the next frame is already live.
+    let mut dave = connect(&server, &format!("/ws/{chat_id}?history=0"), Uuid::now_v7(), "dave").await;
+    alice.send_json(&json!({"type": "chat", "text": "live"})).await;
+    assert_eq!(receive_event(&mut dave).await["text"], "live");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ill_formed_history_params_close_the_socket() -> anyhow::Result<()> {
+    let (_scylla, server) = setup().await?;
+    let chat_id = Uuid::now_v7();
+    for query in ["history=all", "history=101", "since=yesterday"] {
+        let mut socket = open(&server, &format!("/ws/{chat_id}?{query}"), Uuid::now_v7(), "alice").await;
+        let WsMessage::Close(Some(frame)) = socket.receive_message().await else {
+            panic!("expected a close frame for {query}");
+        };
+        assert_eq!(u16::from(frame.code), 1008, "{query}");
+    }
+    assert!(members(&server, chat_id).await.as_array().unwrap().is_empty());
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! The bearer token of `/admin/*` routes, `ADMIN_TOKEN`.
+
+use axum::http::{HeaderMap, header};
+
+/// Why [`require_admin`] refused a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AdminRejection {
+    /// No admin token is configured, so nothing is accepted; answered `403`.
+    #[error("Admin routes are disabled")]
+    Disabled,
+    /// No bearer token was sent; answered `401`.
+    #[error("Missing admin token")]
+    MissingToken,
+    /// A bearer token other than the admin token was sent; answered `403`.
+    #[error("Invalid admin token")]
+    InvalidToken,
+}
+
+/// Accepts requests whose bearer token is `admin_token`, comparing them in constant time.
+pub fn require_admin(headers: &HeaderMap, admin_token: Option<&str>) -> Result<(), AdminRejection> {
+    let expected = admin_token.ok_or(AdminRejection::Disabled)?;
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(AdminRejection::MissingToken)?;
+
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        tracing::warn!("Rejected admin request with invalid token");
+        return Err(AdminRejection::InvalidToken);
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn only_the_admin_token_is_accepted() {
+        assert_eq!(require_admin(&bearer("secret"), Some("secret")), Ok(()));
+        assert_eq!(
+            require_admin(&bearer("secreT"), Some("secret")),
+            Err(AdminRejection::InvalidToken)
+        );
+        assert_eq!(
+            require_admin(&bearer("secret2"), Some("secret")),
+            Err(AdminRejection::InvalidToken)
+        );
+        assert_eq!(
+            require_admin(&HeaderMap::new(), Some("secret")),
+            Err(AdminRejection::MissingToken)
+        );
+        assert_eq!(require_admin(&bearer("secret"), None), Err(AdminRejection::Disabled));
+    }
+}
@@ -0,0 +1,254 @@
+//! Bearer tokens of the user-facing routes: HS256 access tokens signed by service-auth with `JWT_SECRET`.
+
+use crate::error::ErrorBody;
+use axum::{
+    extract::FromRequestParts,
+    http::{StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// `typ` of service-auth access tokens; refresh tokens and room invites carry other types.
+const TOKEN_TYPE_ACCESS: &str = "access";
+/// `iss` of every token service-auth signs.
+const ISSUER: &str = "service-auth";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// The user's UUID.
+    pub sub: String,
+    pub exp: u64,
+    pub iss: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    /// The user's name when the token was issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Why a request was not let in; always answered `401`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing bearer token")]
+    Missing,
+    #[error("Token expired")]
+    Expired,
+    #[error("Invalid token")]
+    Invalid,
+    #[error("Not an access token")]
+    NotAnAccessToken,
+    #[error("Token subject is not a valid UUID")]
+    InvalidSubject,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ErrorBody::new("unauthorized", self.to_string()).into_response(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// Verifies HS256 bearer tokens signed with `JWT_SECRET`, the secret service-auth signs access tokens with.
+#[derive(Clone)]
+pub struct Authenticator {
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl Authenticator {
+    pub fn new(secret: &str) -> Self {
+        // Workspace builds also enable jsonwebtoken's aws-lc backend (via meilisearch-sdk), which leaves no implicit default.
+        let _ = jsonwebtoken::crypto::rust_crypto::DEFAULT_PROVIDER.install_default();
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 5;
+        validation.set_issuer(&[ISSUER]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+
+        Self {
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            validation,
+        }
+    }
+
+    pub fn verify(&self, token: &str) -> Result<AuthUser, AuthError> {
+        let claims = decode::<Claims>(token, &self.decoding_key, &self.validation)
+            .map_err(|e| match e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => AuthError::Expired,
+                _ => AuthError::Invalid,
+            })?
+            .claims;
+        if claims.typ.as_deref().is_some_and(|typ| typ != TOKEN_TYPE_ACCESS) {
+            return Err(AuthError::NotAnAccessToken);
+        }
+        Ok(AuthUser {
+            user_id: claims.sub.parse().map_err(|_| AuthError::InvalidSubject)?,
+            username: claims.username.filter(|name| !name.is_empty()),
+        })
+    }
+}
+
+/// Application state that can verify bearer tokens, which is all [`AuthUser`] needs of it.
+pub trait AuthState: Send + Sync {
+    fn authenticator(&self) -> &Authenticator;
+}
+
+impl AuthState for Authenticator {
+    fn authenticator(&self) -> &Authenticator {
+        self
+    }
+}
+
+impl<T: AuthState> AuthState for Arc<T> {
+    fn authenticator(&self) -> &Authenticator {
+        T::authenticator(self)
+    }
+}
+
+/// The caller, from `Authorization: Bearer <jwt>`. WebSocket upgrades may pass the token as `?token=` instead,
+/// since browsers cannot set headers on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthUser {
+    pub user_id: Uuid,
+    /// `None` for tokens issued without one.
+    pub username: Option<String>,
+}
+
+impl<S: AuthState> FromRequestParts<S> for AuthUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).or_else(|| query_token(parts)).ok_or(AuthError::Missing)?;
+        state.authenticator().verify(&token)
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Option<String> {
+    let value = parts.headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(|token| token.trim().to_owned())
+}
+
+/// Only honoured on WebSocket upgrades, so tokens do not end up in the access logs of plain requests.
+fn query_token(parts: &Parts) -> Option<String> {
+    let upgrade = parts.headers.get(header::UPGRADE)?.to_str().ok()?;
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return None;
+    }
+    parts
+        .uri
+        .query()?
+        .split('&')
+        .find_map(|param| param.strip_prefix("token="))
+        .map(str::to_owned)
+}
+
+/// Signs a token for `sub` that expires `ttl_secs` from now (negative for one that already has), for tests.
+#[cfg(any(test, feature = "test-util"))]
+pub fn mint_token(secret: &str, sub: &str, ttl_secs: i64) -> String {
+    mint_token_for(secret, sub, None, ttl_secs)
+}
+
+/// [`mint_token`] with the `username` claim of service-auth's access tokens.
+#[cfg(any(test, feature = "test-util"))]
+pub fn mint_token_for(secret: &str, sub: &str, username: Option<&str>, ttl_secs: i64) -> String {
+    let _ = jsonwebtoken::crypto::rust_crypto::DEFAULT_PROVIDER.install_default();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let claims = Claims {
+        sub: sub.to_owned(),
+        exp: now.saturating_add_signed(ttl_secs),
+        iss: ISSUER.to_owned(),
+        typ: Some(TOKEN_TYPE_ACCESS.to_owned()),
+        username: username.map(str::to_owned),
+    };
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(Algorithm::HS256),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .expect("HS256 signing does not fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    const SECRET: &str = "test-secret-at-least-32-characters";
+
+    async fn extract(request: Request<()>) -> Result<AuthUser, AuthError> {
+        let (mut parts, _) = request.into_parts();
+        AuthUser::from_request_parts(&mut parts, &Authenticator::new(SECRET)).await
+    }
+
+    #[tokio::test]
+    async fn bearer_header_is_accepted() {
+        let user_id = Uuid::now_v7();
+        let token = mint_token_for(SECRET, &user_id.to_string(), Some("alice"), 60);
+        let request = Request::get("/images").header("Authorization", format!("Bearer {token}"));
+        let user = extract(request.body(()).unwrap()).await.unwrap();
+        assert_eq!(user.user_id, user_id);
+        assert_eq!(user.username.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn query_token_only_on_websocket_upgrades() {
+        let user_id = Uuid::now_v7();
+        let uri = format!("/ws?room=a&token={}", mint_token(SECRET, &user_id.to_string(), 60));
+
+        let upgrade = Request::get(&uri).header("Upgrade", "websocket").body(()).unwrap();
+        assert_eq!(extract(upgrade).await.unwrap().user_id, user_id);
+
+        let plain = Request::get(&uri).body(()).unwrap();
+        assert_eq!(extract(plain).await, Err(AuthError::Missing));
+    }
+
+    #[tokio::test]
+    async fn foreign_tokens_are_refused() {
+        let user_id = Uuid::now_v7().to_string();
+        let other_secret = mint_token("another-secret-of-at-least-32-chars", &user_id, 60);
+        let refresh = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS256),
+            &Claims {
+                sub: user_id.clone(),
+                exp: 4_102_444_800,
+                iss: ISSUER.into(),
+                typ: Some("refresh".into()),
+                username: None,
+            },
+            &jsonwebtoken::EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap();
+
+        for (token, error) in [(other_secret, AuthError::Invalid), (refresh, AuthError::NotAnAccessToken)] {
+            let request = Request::get("/images").header("Authorization", format!("Bearer {token}"));
+            assert_eq!(extract(request.body(()).unwrap()).await, Err(error));
+        }
+        let expired = mint_token(SECRET, &user_id, -60);
+        let request = Request::get("/images").header("Authorization", format!("Bearer {expired}"));
+        assert_eq!(extract(request.body(()).unwrap()).await, Err(AuthError::Expired));
+    }
+
+    #[tokio::test]
+    async fn tokens_from_another_issuer_are_refused() {
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS256),
+            &Claims {
+                sub: Uuid::now_v7().to_string(),
+                exp: 4_102_444_800,
+                iss: "someone-else".into(),
+                typ: Some(TOKEN_TYPE_ACCESS.into()),
+                username: None,
+            },
+            &jsonwebtoken::EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap();
+
+        let request = Request::get("/images").header("Authorization", format!("Bearer {token}"));
+        assert_eq!(extract(request.body(()).unwrap()).await, Err(AuthError::Invalid));
+    }
+}
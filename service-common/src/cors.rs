@@ -0,0 +1,85 @@
+//! `ORIGINS`, the origins the CORS layer of the HTTP services answers.
+
+use axum::http::{HeaderValue, Uri};
+use std::str::FromStr;
+use tower_http::cors::AllowOrigin;
+
+/// `ORIGINS`: a comma-separated list of origins, optionally in brackets, or `*` for any origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl AllowedOrigins {
+    pub fn allow_origin(&self) -> AllowOrigin {
+        match self {
+            Self::Any => AllowOrigin::any(),
+            Self::List(origins) => AllowOrigin::list(origins.clone()),
+        }
+    }
+}
+
+impl FromStr for AllowedOrigins {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+        let origins: Vec<&str> = s.split(',').map(str::trim).filter(|o| !o.is_empty()).collect();
+        match origins[..] {
+            [] => Err("no origins given, use * to allow any".into()),
+            ["*"] => Ok(Self::Any),
+            _ if origins.contains(&"*") => Err("* cannot be combined with other origins".into()),
+            _ => origins
+                .into_iter()
+                .map(parse_origin)
+                .collect::<Result<_, _>>()
+                .map(Self::List),
+        }
+    }
+}
+
+/// `scheme://host[:port]`, the form browsers send in `Origin`; a trailing slash is dropped.
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let uri: Uri = origin.parse().map_err(|e| format!("{origin} is not a URI: {e}"))?;
+    if uri.scheme().is_none() || uri.authority().is_none() || !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+        return Err(format!("{origin} is not an origin, expected scheme://host[:port]"));
+    }
+    HeaderValue::from_str(origin.trim_end_matches('/')).map_err(|e| format!("{origin} is not a header value: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins_accept_lists_brackets_and_any() {
+        let list = |origins: &[&'static str]| AllowedOrigins::List(origins.iter().map(|o| HeaderValue::from_static(o)).collect());
+        assert_eq!("http://localhost:8080".parse(), Ok(list(&["http://localhost:8080"])));
+        assert_eq!(
+            " http://localhost:8080 , https://example.com/ ".parse(),
+            Ok(list(&["http://localhost:8080", "https://example.com"]))
+        );
+        assert_eq!(
+            "[http://localhost:8080,http://127.0.0.1:8080]".parse(),
+            Ok(list(&["http://localhost:8080", "http://127.0.0.1:8080"]))
+        );
+        assert_eq!("*".parse(), Ok(AllowedOrigins::Any));
+        assert_eq!("[*]".parse(), Ok(AllowedOrigins::Any));
+    }
+
+    #[test]
+    fn origins_that_are_not_origins_are_refused() {
+        for origins in [
+            "",
+            "[]",
+            "*,http://localhost:8080",
+            "localhost:8080",
+            "http://bad\u{1}origin",
+            "http://a.com/path",
+        ] {
+            assert!(origins.parse::<AllowedOrigins>().is_err(), "{origins:?} accepted");
+        }
+    }
+}
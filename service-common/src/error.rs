@@ -0,0 +1,93 @@
+//! The JSON body of every error response of the HTTP services.
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+
+/// Body of every error response. `code` is meant for programs and stays stable, `error` for people; `message`
+/// carries what went wrong in more detail where there is any, e.g. the reason an upload was rejected, and
+/// `details` the values a code refers to, e.g. `max_length` for `invalid_length`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Map<String, Value>>,
+    /// Sent next to the envelope's fields, for the few errors that return a resource, like the current state of
+    /// an edited message. Documented by the schema of those responses.
+    #[serde(flatten)]
+    #[schema(ignore)]
+    pub extra: Map<String, Value>,
+}
+
+impl ErrorBody {
+    pub fn new(code: &str, error: impl Into<String>) -> Self {
+        Self {
+            code: code.to_owned(),
+            error: error.into(),
+            message: None,
+            details: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Adds the fields of `details`, which must be a JSON object.
+    pub fn with_details(mut self, details: Value) -> Self {
+        if let Value::Object(details) = details {
+            self.details = Some(details);
+        }
+        self
+    }
+
+    /// Adds the fields of `extra`, which must be a JSON object, next to the envelope's own.
+    pub fn with_extra(mut self, extra: Value) -> Self {
+        if let Value::Object(extra) = extra {
+            self.extra.extend(extra);
+        }
+        self
+    }
+
+    pub fn into_response(self, status: StatusCode) -> Response {
+        (status, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn optional_fields_are_only_sent_when_set() {
+        let body = serde_json::to_value(ErrorBody::new("not_found", "Message not found")).unwrap();
+        assert_eq!(body, json!({"code": "not_found", "error": "Message not found"}));
+
+        let body = ErrorBody::new("upload_rejected", "Upload rejected")
+            .with_message("Signature found")
+            .with_details(json!({"scanner": "clamav"}))
+            .with_extra(json!({"used": 3}));
+        assert_eq!(
+            serde_json::to_value(body).unwrap(),
+            json!({
+                "code": "upload_rejected",
+                "error": "Upload rejected",
+                "message": "Signature found",
+                "details": {"scanner": "clamav"},
+                "used": 3,
+            })
+        );
+    }
+}
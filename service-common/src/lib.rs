@@ -0,0 +1,14 @@
+//! Plumbing the HTTP services and the gateway share.
+
+#[cfg(feature = "http")]
+pub mod admin;
+#[cfg(feature = "auth")]
+pub mod auth;
+#[cfg(feature = "http")]
+pub mod cors;
+#[cfg(feature = "http")]
+pub mod error;
+pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod rate_limit;
@@ -0,0 +1,134 @@
+use std::{path::PathBuf, str::FromStr};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter, util::SubscriberInitExt};
+
+/// How log lines are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// One line per event, spans folded into a prefix.
+    #[default]
+    Compact,
+    /// Multi-line and indented, for reading locally.
+    Pretty,
+    /// One JSON object per line, with the fields of the current span and every span around it.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(Self::Compact),
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format {other:?}, expected compact, pretty or json")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Write to this file, rotated daily as `{name}.{yyyy-mm-dd}`, instead of stdout.
+    pub file: Option<PathBuf>,
+}
+
+/// Writes the lines still buffered for the log file when dropped; hold it until the process exits.
+#[must_use = "buffered log lines are lost once the guard is dropped"]
+pub struct LogGuard(#[allow(dead_code)] Option<WorkerGuard>);
+
+/// Installs the global subscriber described by `config`, filtered by `RUST_LOG`. The log file is written from a
+/// background thread, so requests do not wait on the disk.
+pub fn init(config: &LogConfig) -> LogGuard {
+    let filter = EnvFilter::from_default_env();
+    match &config.file {
+        Some(path) => {
+            let directory = path.parent().unwrap_or_else(|| "".as_ref());
+            let name = path.file_name().expect("the log file path names a file");
+            let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::daily(directory, name));
+            subscriber(config.format, filter, writer, false).init();
+            LogGuard(Some(guard))
+        }
+        None => {
+            subscriber(config.format, filter, std::io::stdout, true).init();
+            LogGuard(None)
+        }
+    }
+}
+
+/// The subscriber [`init`] installs, writing to `writer`.
+pub fn subscriber<W>(format: LogFormat, filter: EnvFilter, writer: W, ansi: bool) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_file(true)
+        .with_line_number(true)
+        .with_target(false);
+    match format {
+        LogFormat::Compact => Box::new(builder.compact().finish()),
+        LogFormat::Pretty => Box::new(builder.pretty().finish()),
+        LogFormat::Json => Box::new(builder.json().with_current_span(true).with_span_list(true).finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_carry_span_fields() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = subscriber(LogFormat::Json, EnvFilter::new("info"), move || writer.clone(), false);
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request", request_id = "req-1").in_scope(|| {
+                tracing::info!(key = "a/b", "Image stored");
+            });
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        for key in ["timestamp", "level", "fields", "filename", "line_number", "span", "spans"] {
+            assert!(line.get(key).is_some(), "{key} missing from {line}");
+        }
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "Image stored");
+        assert_eq!(line["fields"]["key"], "a/b");
+        assert_eq!(line["span"]["request_id"], "req-1");
+    }
+
+    #[test]
+    fn formats_parse_from_their_names() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("pretty".parse(), Ok(LogFormat::Pretty));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
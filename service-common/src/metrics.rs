@@ -0,0 +1,53 @@
+//! The process-wide Prometheus recorder behind `/metrics`.
+
+use axum_prometheus::{
+    AXUM_HTTP_REQUESTS_DURATION_SECONDS,
+    metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle},
+    utils::SECONDS_DURATION_BUCKETS,
+};
+use std::{sync::OnceLock, time::Duration};
+
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// What a service adds to the recorder installed by [`install`].
+pub struct Recorder {
+    /// Histogram buckets by metric name, next to the HTTP request durations'.
+    pub buckets: &'static [(&'static str, &'static [f64])],
+    /// Runs once the recorder is installed, to describe the service's metrics.
+    pub on_install: fn(),
+}
+
+/// Installs the global recorder rendered on `/metrics`. Later calls return the handle of the first, so every
+/// router of a process shares one registry.
+///
+/// Histograms are drained by a thread of their own rather than a task, so the recorder outlives the runtime
+/// that happened to install it.
+pub fn install(recorder: &Recorder) -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            let http: (&str, &[f64]) = (AXUM_HTTP_REQUESTS_DURATION_SECONDS, SECONDS_DURATION_BUCKETS);
+            let handle = std::iter::once(&http)
+                .chain(recorder.buckets)
+                .try_fold(PrometheusBuilder::new(), |builder, (name, buckets)| {
+                    builder.set_buckets_for_metric(Matcher::Full((*name).to_owned()), buckets)
+                })
+                .expect("bucket lists are not empty")
+                .install_recorder()
+                .expect("no other metrics recorder is installed");
+            let upkeep = handle.clone();
+            std::thread::Builder::new()
+                .name("metrics-upkeep".to_owned())
+                .spawn(move || {
+                    loop {
+                        std::thread::sleep(UPKEEP_INTERVAL);
+                        upkeep.run_upkeep();
+                    }
+                })
+                .expect("the upkeep thread starts");
+            (recorder.on_install)();
+            handle
+        })
+        .clone()
+}
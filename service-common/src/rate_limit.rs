@@ -0,0 +1,218 @@
+//! Token buckets: bursts of up to `burst` requests, refilled evenly at `rate` per `per`, so a client cannot save
+//! up more than one burst.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, Hash, RandomState},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Locks of a [`RateLimiter`]; keys hash to one, so unrelated clients rarely wait on each other.
+const SHARDS: usize = 16;
+/// Refilled buckets dropped per check at most, keeping the time a check holds its lock bounded.
+const EVICT_BATCH: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+    burst: f64,
+    per_sec: f64,
+}
+
+impl Rate {
+    fn new(burst: u32, rate: u32, per: Duration) -> Self {
+        Self {
+            burst: f64::from(burst.max(1)),
+            per_sec: f64::from(rate.max(1)) / per.as_secs_f64().max(f64::MIN_POSITIVE),
+        }
+    }
+
+    /// How long an untouched bucket takes to fill up, after which it is the same as none.
+    fn full_after(&self) -> Duration {
+        Duration::from_secs_f64(self.burst / self.per_sec)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rate: &Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.burst,
+            updated: now,
+        }
+    }
+
+    fn take(&mut self, rate: &Rate, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_sec).min(rate.burst);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate.per_sec))
+        }
+    }
+}
+
+/// The bucket of a single client, e.g. of one websocket connection.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: Rate,
+    bucket: Bucket,
+}
+
+impl TokenBucket {
+    pub fn new(burst: u32, rate: u32, per: Duration) -> Self {
+        let rate = Rate::new(burst, rate, per);
+        Self {
+            rate,
+            bucket: Bucket::full(&rate, Instant::now()),
+        }
+    }
+
+    /// Takes a token if one is left.
+    pub fn try_take(&mut self) -> bool {
+        self.bucket.take(&self.rate, Instant::now()).is_ok()
+    }
+}
+
+/// A bucket per key, e.g. per user or client IP. Buckets are dropped once they have refilled, oldest first and a
+/// few per check, so memory follows the clients active within one refill and no check scans them all.
+pub struct RateLimiter<K> {
+    rate: Rate,
+    shards: Box<[Mutex<Shard<K>>]>,
+    hasher: RandomState,
+}
+
+struct Shard<K> {
+    /// Each bucket with the sequence number of its last update.
+    buckets: HashMap<K, (Bucket, u64)>,
+    /// Keys by the sequence number of their last update, so the first ones are the first to refill.
+    by_update: BTreeMap<u64, K>,
+    next_seq: u64,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    pub fn new(burst: u32, rate: u32, per: Duration) -> Self {
+        Self {
+            rate: Rate::new(burst, rate, per),
+            shards: (0..SHARDS)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        buckets: HashMap::new(),
+                        by_update: BTreeMap::new(),
+                        next_seq: 0,
+                    })
+                })
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Takes a token for `key`; when none is left, how long until one is.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    pub fn check_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let shard = &self.shards[self.hasher.hash_one(&key) as usize % self.shards.len()];
+        let mut shard = shard.lock().unwrap();
+        shard.evict(now, self.rate.full_after());
+        shard.take(key, &self.rate, now)
+    }
+
+    /// Buckets currently tracked.
+    pub fn tracked(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().buckets.len()).sum()
+    }
+}
+
+impl<K: Hash + Eq + Clone> Shard<K> {
+    fn evict(&mut self, now: Instant, full_after: Duration) {
+        for _ in 0..EVICT_BATCH {
+            let Some(oldest) = self.by_update.first_entry() else {
+                return;
+            };
+            let (bucket, _) = &self.buckets[oldest.get()];
+            if now.saturating_duration_since(bucket.updated) < full_after {
+                return;
+            }
+            self.buckets.remove(&oldest.remove());
+        }
+    }
+
+    fn take(&mut self, key: K, rate: &Rate, now: Instant) -> Result<(), Duration> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let (bucket, updated_seq) = self
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| (Bucket::full(rate, now), seq));
+        self.by_update.remove(updated_seq);
+        *updated_seq = seq;
+        self.by_update.insert(seq, key);
+        bucket.take(rate, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_refill() {
+        let limiter = RateLimiter::new(3, 2, Duration::from_secs(1));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", start).is_ok());
+        }
+        assert_eq!(limiter.check_at("a", start), Err(Duration::from_millis(500)));
+        assert!(limiter.check_at("b", start).is_ok(), "keys have their own bucket");
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_at("a", later).is_ok());
+        assert!(limiter.check_at("a", later).is_err());
+        // Idle time never adds more than the burst.
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", much_later).is_ok());
+        }
+        assert!(limiter.check_at("a", much_later).is_err());
+    }
+
+    #[test]
+    fn refilled_buckets_are_dropped_oldest_first() {
+        let limiter = RateLimiter::new(10, 10, Duration::from_secs(1));
+        let start = Instant::now();
+        for key in 0..1000 {
+            assert!(limiter.check_at(key, start).is_ok());
+        }
+        let refilling = start + Duration::from_millis(900);
+        assert!(limiter.check_at(1000, refilling).is_ok());
+        assert_eq!(limiter.tracked(), 1001);
+
+        // Each check drops a batch of refilled buckets from its shard, but never one still refilling.
+        let later = start + Duration::from_secs(1);
+        for key in 2000..3000 {
+            assert!(limiter.check_at(key, later).is_ok());
+        }
+        assert_eq!(limiter.tracked(), 1 + 1000);
+    }
+
+    #[test]
+    fn single_buckets_refill_over_their_period() {
+        let mut bucket = TokenBucket::new(3, 3, Duration::from_millis(90));
+        assert!((0..3).all(|_| bucket.try_take()));
+        assert!(!bucket.try_take());
+
+        std::thread::sleep(Duration::from_millis(35));
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::cache::ResponseCache;
+use crate::upstream::UpstreamPool;
+use async_trait::async_trait;
+use http::{Response, StatusCode, header};
+use pingora::apps::http_app::ServeHttp;
+use pingora::protocols::http::ServerSession;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+struct UpstreamStats {
+    name: String,
+    addr: SocketAddr,
+    consecutive_failures: AtomicU32,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Connection outcomes per upstream, fed by the proxy and reported by `/_proxy/status`.
+pub struct UpstreamHealth {
+    upstreams: Vec<UpstreamStats>,
+    started_at: Instant,
+}
+
+impl UpstreamHealth {
+    pub fn new(upstreams: impl IntoIterator<Item = (String, SocketAddr)>) -> Self {
+        Self {
+            upstreams: upstreams
+                .into_iter()
+                .map(|(name, addr)| UpstreamStats {
+                    name,
+                    addr,
+                    consecutive_failures: AtomicU32::new(0),
+                    last_error: Mutex::new(None),
+                })
+                .collect(),
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn record_success(&self, addr: &SocketAddr) {
+        for stats in self.upstreams.iter().filter(|u| u.addr == *addr) {
+            stats.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_failure(&self, addr: &SocketAddr, error: &str) {
+        for stats in self.upstreams.iter().filter(|u| u.addr == *addr) {
+            stats.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+            *stats.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error.to_owned());
+        }
+    }
+
+    pub fn status(&self) -> serde_json::Value {
+        let upstreams: Vec<_> = self
+            .upstreams
+            .iter()
+            .map(|u| {
+                let failures = u.consecutive_failures.load(Ordering::Relaxed);
+                json!({
+                    "name": u.name,
+                    "addr": u.addr.to_string(),
+                    "healthy": failures == 0,
+                    "consecutive_failures": failures,
+                    "last_error": *u.last_error.lock().unwrap_or_else(|e| e.into_inner()),
+                })
+            })
+            .collect();
+        let healthy = upstreams.iter().all(|u| u["healthy"] == true);
+
+        json!({
+            "status": if healthy { "ok" } else { "degraded" },
+            "uptime_secs": self.started_at.elapsed().as_secs(),
+            "upstreams": upstreams,
+        })
+    }
+}
+
+/// Replica readiness per route, as seen by the health checker; reported by `/_proxy/health`.
+pub fn pools_health(pools: &[Arc<UpstreamPool>]) -> serde_json::Value {
+    let routes: Vec<_> = pools
+        .iter()
+        .map(|pool| {
+            let replicas: Vec<_> = pool
+                .addrs()
+                .map(|addr| {
+                    json!({
+                        "addr": addr.to_string(),
+                        "ready": pool.is_ready(addr),
+                        "failed_checks": pool.failed_checks(addr),
+                        "in_flight": pool.in_flight(addr),
+                    })
+                })
+                .collect();
+            json!({
+                "name": pool.name,
+                "health_path": pool.check_path(),
+                "ready_replicas": replicas.iter().filter(|r| r["ready"] == true).count(),
+                "replicas": replicas,
+            })
+        })
+        .collect();
+    let serving = routes.iter().all(|r| r["ready_replicas"] != 0);
+
+    json!({
+        "status": if serving { "ok" } else { "degraded" },
+        "routes": routes,
+    })
+}
+
+pub struct AdminApp {
+    token: Option<String>,
+    health: Arc<UpstreamHealth>,
+    pools: Vec<Arc<UpstreamPool>>,
+    cache: Arc<ResponseCache>,
+}
+
+impl AdminApp {
+    pub fn new(
+        token: Option<String>,
+        health: Arc<UpstreamHealth>,
+        pools: Vec<Arc<UpstreamPool>>,
+        cache: Arc<ResponseCache>,
+    ) -> Self {
+        Self {
+            token,
+            health,
+            pools,
+            cache,
+        }
+    }
+
+    /// `POST /_proxy/cache/purge?prefix=/images/avatars` drops the cached responses for paths under `prefix`.
+    fn purge_cache(&self, query: Option<&str>) -> Response<Vec<u8>> {
+        let prefix = query.and_then(|q| {
+            form_urlencoded::parse(q.as_bytes())
+                .find(|(name, _)| name == "prefix")
+                .map(|(_, value)| value.into_owned())
+        });
+        let Some(prefix) = prefix.filter(|p| p.starts_with('/')) else {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({"error": "prefix must be a path starting with /"}),
+            );
+        };
+        let purged = self.cache.purge(&prefix);
+        tracing::info!(%prefix, purged, "Purged response cache");
+        json_response(StatusCode::OK, json!({"prefix": prefix, "purged": purged}))
+    }
+}
+
+/// Without a configured token the listener relies on being bound to loopback (see `Config::validate`).
+fn authorize(authorization: Option<&str>, token: Option<&str>) -> bool {
+    let Some(expected) = token else {
+        return true;
+    };
+    authorization.and_then(|v| v.strip_prefix("Bearer ")).is_some_and(|provided| {
+        provided.len() == expected.len() && provided.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    })
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Vec<u8>> {
+    let body = body.to_string().into_bytes();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(body)
+        .expect("static response parts are valid")
+}
+
+#[async_trait]
+impl ServeHttp for AdminApp {
+    async fn response(&self, session: &mut ServerSession) -> Response<Vec<u8>> {
+        let req = session.req_header();
+        let authorization = req.headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+        if !authorize(authorization, self.token.as_deref()) {
+            tracing::warn!(path = %req.uri.path(), "Rejected admin request with invalid token");
+            return json_response(StatusCode::UNAUTHORIZED, json!({"error": "Unauthorized"}));
+        }
+
+        match (req.method.as_str(), req.uri.path()) {
+            ("GET", "/_proxy/status") => json_response(StatusCode::OK, self.health.status()),
+            ("GET", "/_proxy/health") => json_response(StatusCode::OK, pools_health(&self.pools)),
+            ("POST", "/_proxy/cache/purge") => self.purge_cache(req.uri.query()),
+            _ => json_response(StatusCode::NOT_FOUND, json!({"error": "Not found"})),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_token_configured_allows_all() {
+        assert!(authorize(None, None));
+    }
+
+    #[test]
+    fn token_must_match() {
+        assert!(authorize(Some("Bearer secret"), Some("secret")));
+        assert!(!authorize(Some("Bearer wrong!"), Some("secret")));
+        assert!(!authorize(Some("secret"), Some("secret")));
+        assert!(!authorize(None, Some("secret")));
+    }
+
+    #[test]
+    fn status_reports_failing_upstream() {
+        let images: SocketAddr = "127.0.0.1:3001".parse().unwrap();
+        let chats: SocketAddr = "127.0.0.1:3002".parse().unwrap();
+        let health = UpstreamHealth::new([("images".into(), images), ("chats".into(), chats)]);
+
+        health.record_failure(&chats, "connection refused");
+        let status = health.status();
+        assert_eq!(status["status"], "degraded");
+        assert_eq!(status["upstreams"][1]["consecutive_failures"], 1);
+        assert_eq!(status["upstreams"][1]["last_error"], "connection refused");
+
+        health.record_success(&chats);
+        assert_eq!(health.status()["status"], "ok");
+    }
+
+    #[test]
+    fn health_reports_replica_readiness() {
+        let addr = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let images = Arc::new(UpstreamPool::new("images", [addr(1), addr(2)], Some("/ping".into())));
+        let chats = Arc::new(UpstreamPool::new("chats", [addr(3)], None));
+        let pools = [Arc::clone(&images), chats];
+        assert_eq!(pools_health(&pools)["status"], "degraded");
+
+        images.record_check(addr(1), true, 1);
+        images.record_check(addr(2), false, 2);
+        let health = pools_health(&pools);
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["routes"][0]["health_path"], "/ping");
+        assert_eq!(health["routes"][0]["ready_replicas"], 1);
+        assert_eq!(health["routes"][0]["replicas"][1]["failed_checks"], 1);
+        assert_eq!(health["routes"][1]["ready_replicas"], 1);
+    }
+}
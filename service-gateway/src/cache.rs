@@ -0,0 +1,479 @@
+use crate::routes::strip_prefix;
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue};
+use lru::LruCache;
+use pingora::http::ResponseHeader;
+use pingora::prelude::RequestHeader;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Headers that describe one connection rather than the response, left out of cached copies.
+const HOP_BY_HOP: [&str; 4] = ["connection", "keep-alive", "transfer-encoding", "content-length"];
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// `GET` responses under these path prefixes are cached; none disables the cache.
+    pub prefixes: Vec<String>,
+    /// Larger responses are passed through without being cached.
+    pub max_entry_bytes: usize,
+    /// Least recently used entries are evicted to keep the cache within this size.
+    pub max_bytes: usize,
+    /// Lifetime of responses without `max-age` or `s-maxage`.
+    pub default_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            prefixes: Vec::new(),
+            max_entry_bytes: 1024 * 1024,
+            max_bytes: 64 * 1024 * 1024,
+            default_ttl_secs: 3600,
+        }
+    }
+}
+
+/// A cached `200` response, without the headers the gateway adds per request.
+pub struct CachedResponse {
+    pub header: ResponseHeader,
+    pub body: Bytes,
+    path: String,
+    /// Request headers named by the response's `Vary`, with the values they had; only requests with the same
+    /// values are answered from this entry.
+    varied: Vec<(HeaderName, Option<HeaderValue>)>,
+    expires: Instant,
+}
+
+impl CachedResponse {
+    fn size(&self) -> usize {
+        let headers: usize = self
+            .header
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        let varied: usize = self
+            .varied
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.as_ref().map_or(0, HeaderValue::len))
+            .sum();
+        self.path.len() + headers + varied + self.body.len()
+    }
+
+    fn matches(&self, req: &RequestHeader) -> bool {
+        self.varied
+            .iter()
+            .all(|(name, value)| req.headers.get(name) == value.as_ref())
+    }
+}
+
+/// Response being read from the upstream, stored once its body is complete.
+pub struct CacheFill {
+    key: String,
+    path: String,
+    varied: Vec<(HeaderName, Option<HeaderValue>)>,
+    header: ResponseHeader,
+    ttl: Duration,
+    body: Vec<u8>,
+}
+
+struct Entries {
+    lru: LruCache<String, Arc<CachedResponse>>,
+    bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.lru.pop(key) {
+            self.bytes -= entry.size();
+        }
+    }
+}
+
+/// In-process LRU cache of `GET` responses, keyed by host and path with query, within a memory budget.
+pub struct ResponseCache {
+    config: CacheConfig,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// Cache key of `req` if its response may be cached: a `GET` of a path below one of the configured prefixes.
+    /// The prefix itself is left out, as that is where listings live.
+    pub fn key(&self, req: &RequestHeader) -> Option<String> {
+        let path = req.uri.path();
+        let below_prefix = |prefix: &String| strip_prefix(path, prefix).is_some_and(|rest| rest.len() > 1);
+        if req.method != http::Method::GET || !self.config.prefixes.iter().any(below_prefix) {
+            return None;
+        }
+        let path_and_query = req.uri.path_and_query().map_or(path, |p| p.as_str());
+        Some(format!(
+            "{} {path_and_query}",
+            crate::routes::request_host(req).unwrap_or_default()
+        ))
+    }
+
+    /// The fresh entry for `key`, marked as recently used, if `req` has the headers it varies on.
+    pub fn get(&self, key: &str, req: &RequestHeader) -> Option<Arc<CachedResponse>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = Arc::clone(entries.lru.get(key)?);
+        if entry.expires <= Instant::now() {
+            entries.remove(key);
+            return None;
+        }
+        entry.matches(req).then_some(entry)
+    }
+
+    /// Starts storing the upstream response `header` for `req`, unless it may not be cached: only `200`s
+    /// are, without `Set-Cookie` or `Vary: *`, and not when `Cache-Control` has `no-store`, `no-cache`,
+    /// `private` or a zero lifetime. Answers to requests carrying credentials are only stored when marked
+    /// `public` or given an `s-maxage` (RFC 9111 §3.5). A response that varies on other headers is stored with
+    /// their values in `req`, replacing the entry for other values.
+    pub fn fill(&self, key: String, req: &RequestHeader, header: &ResponseHeader) -> Option<CacheFill> {
+        if header.status != http::StatusCode::OK || header.headers.contains_key(http::header::SET_COOKIE) {
+            return None;
+        }
+        if has_credentials(req) && !is_shared(header) {
+            return None;
+        }
+        let varied = varied(req, header)?;
+        let length = header
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse::<usize>().ok());
+        if length.is_some_and(|length| length > self.config.max_entry_bytes) {
+            return None;
+        }
+        let ttl = ttl(header, Duration::from_secs(self.config.default_ttl_secs))?;
+
+        let mut header = header.clone();
+        for name in HOP_BY_HOP {
+            header.remove_header(name);
+        }
+        Some(CacheFill {
+            key,
+            path: req.uri.path().to_owned(),
+            varied,
+            header,
+            ttl,
+            body: Vec::with_capacity(length.unwrap_or_default()),
+        })
+    }
+
+    /// Adds a chunk of the response body; `false` once the body is too large to cache.
+    pub fn push_body(&self, fill: &mut CacheFill, chunk: &[u8]) -> bool {
+        if fill.body.len() + chunk.len() > self.config.max_entry_bytes {
+            return false;
+        }
+        fill.body.extend_from_slice(chunk);
+        true
+    }
+
+    /// Stores a complete response, evicting the least recently used entries beyond the memory budget.
+    pub fn insert(&self, fill: CacheFill) {
+        let entry = Arc::new(CachedResponse {
+            header: fill.header,
+            body: fill.body.into(),
+            path: fill.path,
+            varied: fill.varied,
+            expires: Instant::now() + fill.ttl,
+        });
+        let size = entry.size();
+        if size > self.config.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(&fill.key);
+        while entries.bytes + size > self.config.max_bytes {
+            let Some((_, evicted)) = entries.lru.pop_lru() else {
+                break;
+            };
+            entries.bytes -= evicted.size();
+        }
+        entries.bytes += size;
+        entries.lru.put(fill.key, entry);
+    }
+
+    /// Removes the entries for paths under `prefix`, on any host; returns how many there were.
+    pub fn purge(&self, prefix: &str) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let keys: Vec<_> = entries
+            .lru
+            .iter()
+            .filter(|(_, entry)| strip_prefix(&entry.path, prefix).is_some())
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            entries.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Entry count and bytes used.
+    pub fn usage(&self) -> (usize, usize) {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        (entries.lru.len(), entries.bytes)
+    }
+}
+
+/// The headers of `req` that `header` varies on, with their values; `None` for `Vary: *` or a name that is not
+/// a header, which cannot be matched.
+fn varied(req: &RequestHeader, header: &ResponseHeader) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut varied: Vec<(HeaderName, Option<HeaderValue>)> = Vec::new();
+    for value in header.headers.get_all(http::header::VARY) {
+        for name in value.to_str().ok()?.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name == "*" {
+                return None;
+            }
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            if !varied.iter().any(|(seen, _)| *seen == name) {
+                let value = req.headers.get(&name).cloned();
+                varied.push((name, value));
+            }
+        }
+    }
+    Some(varied)
+}
+
+/// Whether `req` authenticates its caller, with a bearer token in the header, cookie or query.
+fn has_credentials(req: &RequestHeader) -> bool {
+    if req.headers.contains_key(http::header::AUTHORIZATION) {
+        return true;
+    }
+    let cookie_token = req
+        .headers
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .any(|cookie| cookie.trim().starts_with("access_token="));
+    let query_token = req.uri.query().is_some_and(|q| {
+        q.split('&')
+            .any(|param| param.starts_with("token=") || param.starts_with("access_token="))
+    });
+    cookie_token || query_token
+}
+
+/// Lowercased `Cache-Control` directives of `header`.
+fn directives(header: &ResponseHeader) -> impl Iterator<Item = String> + '_ {
+    header
+        .headers
+        .get_all(http::header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|d| d.trim().to_ascii_lowercase())
+}
+
+/// Whether `header` lets a shared cache store the answer to an authenticated request.
+fn is_shared(header: &ResponseHeader) -> bool {
+    directives(header).any(|d| d == "public" || d.starts_with("s-maxage="))
+}
+
+/// How long `header` may be served from the cache per its `Cache-Control`, `s-maxage` taking precedence
+/// over `max-age`; `default` without either.
+fn ttl(header: &ResponseHeader, default: Duration) -> Option<Duration> {
+    let mut max_age = None;
+    let mut s_maxage = None;
+    for directive in directives(header) {
+        match directive.split_once('=') {
+            Some(("max-age", secs)) => max_age = secs.trim_matches('"').parse().ok(),
+            Some(("s-maxage", secs)) => s_maxage = secs.trim_matches('"').parse().ok(),
+            None if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => return None,
+            _ => {}
+        }
+    }
+    match s_maxage.or(max_age) {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(max_entry_bytes: usize, max_bytes: usize) -> ResponseCache {
+        ResponseCache::new(CacheConfig {
+            prefixes: vec!["/images".into()],
+            max_entry_bytes,
+            max_bytes,
+            default_ttl_secs: 60,
+        })
+    }
+
+    fn request(method: &str, path: &str) -> RequestHeader {
+        let mut req = RequestHeader::build(method, path.as_bytes(), None).unwrap();
+        req.insert_header("Host", "api.example.com").unwrap();
+        req
+    }
+
+    fn response(cache_control: Option<&str>) -> ResponseHeader {
+        let mut header = ResponseHeader::build(200, None).unwrap();
+        header.insert_header("Content-Type", "image/png").unwrap();
+        if let Some(value) = cache_control {
+            header.insert_header("Cache-Control", value).unwrap();
+        }
+        header
+    }
+
+    /// Looks `key` up for a request without the headers responses may vary on.
+    fn lookup(cache: &ResponseCache, key: &str) -> Option<Arc<CachedResponse>> {
+        cache.get(key, &request("GET", "/"))
+    }
+
+    fn store(cache: &ResponseCache, path: &str, body: &[u8]) -> String {
+        let req = request("GET", path);
+        let key = cache.key(&req).unwrap();
+        let mut fill = cache.fill(key.clone(), &req, &response(None)).unwrap();
+        assert!(cache.push_body(&mut fill, body));
+        cache.insert(fill);
+        key
+    }
+
+    #[test]
+    fn only_gets_under_the_prefixes_are_cached() {
+        let cache = cache(1024, 4096);
+        assert_eq!(
+            cache.key(&request("GET", "/images/a.png?w=100")).as_deref(),
+            Some("api.example.com /images/a.png?w=100")
+        );
+        assert_eq!(cache.key(&request("HEAD", "/images/a.png")), None);
+        assert_eq!(cache.key(&request("POST", "/images/a.png")), None);
+        assert_eq!(cache.key(&request("GET", "/imagesets/a.png")), None);
+        assert_eq!(cache.key(&request("GET", "/images?user_id=1&cursor=2")), None);
+        assert_eq!(cache.key(&request("GET", "/images/?user_id=1")), None);
+        assert_eq!(cache.key(&request("GET", "/channels")), None);
+    }
+
+    #[test]
+    fn cache_control_decides_the_lifetime() {
+        let default = Duration::from_secs(60);
+        assert_eq!(ttl(&response(None), default), Some(default));
+        assert_eq!(
+            ttl(&response(Some("public, max-age=300")), default),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            ttl(&response(Some("max-age=300, s-maxage=30")), default),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(ttl(&response(Some("max-age=0")), default), None);
+        assert_eq!(ttl(&response(Some("Private, max-age=300")), default), None);
+        assert_eq!(ttl(&response(Some("no-store")), default), None);
+
+        let cache = cache(1024, 4096);
+        let req = request("GET", "/images/a.png");
+        let key = || cache.key(&req).unwrap();
+        assert!(cache.fill(key(), &req, &response(None)).is_some());
+        assert!(cache.fill(key(), &req, &response(Some("no-cache"))).is_none());
+        let mut header = response(None);
+        header.insert_header("Set-Cookie", "session=1").unwrap();
+        assert!(cache.fill(key(), &req, &header).is_none());
+        let mut header = response(None);
+        header.set_status(404).unwrap();
+        assert!(cache.fill(key(), &req, &header).is_none());
+        let mut header = response(None);
+        header.insert_header("Content-Length", "2048").unwrap();
+        assert!(cache.fill(key(), &req, &header).is_none());
+    }
+
+    #[test]
+    fn authenticated_responses_are_only_stored_when_shared() {
+        let cache = cache(1024, 4096);
+        let mut bearer = request("GET", "/images/a.png");
+        bearer.insert_header("Authorization", "Bearer abc").unwrap();
+        let mut cookie = request("GET", "/images/a.png");
+        cookie.insert_header("Cookie", "theme=dark; access_token=abc").unwrap();
+        let query = request("GET", "/images/a.png?access_token=abc");
+
+        for req in [&bearer, &cookie, &query] {
+            let key = || cache.key(req).unwrap();
+            assert!(cache.fill(key(), req, &response(None)).is_none());
+            assert!(cache.fill(key(), req, &response(Some("max-age=60"))).is_none());
+            assert!(cache.fill(key(), req, &response(Some("public, max-age=60"))).is_some());
+            assert!(cache.fill(key(), req, &response(Some("s-maxage=60"))).is_some());
+        }
+    }
+
+    #[test]
+    fn entries_are_only_served_to_requests_with_the_varied_headers() {
+        let cache = cache(1024, 4096);
+        let mut req = request("GET", "/images/a.png");
+        req.insert_header("Accept", "image/webp").unwrap();
+        let key = cache.key(&req).unwrap();
+        let mut header = response(None);
+        header.insert_header("Vary", "accept, Origin").unwrap();
+        let mut fill = cache.fill(key.clone(), &req, &header).unwrap();
+        assert!(cache.push_body(&mut fill, b"webp"));
+        cache.insert(fill);
+
+        assert_eq!(&cache.get(&key, &req).unwrap().body[..], b"webp");
+        let mut other = request("GET", "/images/a.png");
+        other.insert_header("Accept", "image/png").unwrap();
+        assert!(cache.get(&key, &other).is_none());
+        let mut from_origin = req.clone();
+        from_origin.insert_header("Origin", "https://app.example.com").unwrap();
+        assert!(
+            cache.get(&key, &from_origin).is_none(),
+            "a header absent when stored must stay absent"
+        );
+
+        let mut header = response(None);
+        header.insert_header("Vary", "Accept, *").unwrap();
+        assert!(cache.fill(key, &req, &header).is_none());
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = cache(1024, 4096);
+        let req = request("GET", "/images/a.png");
+        let key = cache.key(&req).unwrap();
+        let mut fill = cache.fill(key.clone(), &req, &response(None)).unwrap();
+        fill.ttl = Duration::from_millis(1);
+        cache.insert(fill);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(lookup(&cache, &key).is_none());
+        assert_eq!(cache.usage(), (0, 0));
+    }
+
+    #[test]
+    fn large_bodies_are_not_stored_and_old_entries_are_evicted() {
+        let cache = cache(1024, 3000);
+        let req = request("GET", "/images/large.png");
+        let mut fill = cache.fill(cache.key(&req).unwrap(), &req, &response(None)).unwrap();
+        assert!(cache.push_body(&mut fill, &[0; 1000]));
+        assert!(!cache.push_body(&mut fill, &[0; 100]));
+
+        let a = store(&cache, "/images/a.png", &[0; 1000]);
+        let b = store(&cache, "/images/b.png", &[0; 1000]);
+        assert!(lookup(&cache, &a).is_some());
+        let c = store(&cache, "/images/c.png", &[0; 1000]);
+        assert!(lookup(&cache, &b).is_none(), "least recently used entry is evicted");
+        assert!(lookup(&cache, &a).is_some() && lookup(&cache, &c).is_some());
+        assert!(cache.usage().1 <= 3000);
+    }
+
+    #[test]
+    fn purge_clears_a_prefix() {
+        let cache = cache(1024, 4096);
+        let a = store(&cache, "/images/avatars/a.png", b"a");
+        let b = store(&cache, "/images/avatars/b.png", b"b");
+        let c = store(&cache, "/images/c.png", b"c");
+        assert_eq!(cache.purge("/images/avatars"), 2);
+        assert!(lookup(&cache, &a).is_none() && lookup(&cache, &b).is_none());
+        assert_eq!(&lookup(&cache, &c).unwrap().body[..], b"c");
+        assert_eq!(cache.purge("/"), 1);
+        assert_eq!(cache.usage(), (0, 0));
+    }
+}
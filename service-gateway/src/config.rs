@@ -1,35 +1,190 @@
+use crate::cache::CacheConfig;
+use crate::logging::LogConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::upstream::Strategy;
+use http::uri::PathAndQuery;
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
 pub struct Config {
     pub listen_addr: String,
-    pub images_upstream: String,
-    pub chats_upstream: String,
-    pub channels_upstream: String,
-    pub calls_upstream: String,
     pub auth_upstream: String,
-    pub max_req_per_sec: isize,
+    /// Routing table, from `GATEWAY_ROUTES_FILE` or built from the `GATEWAY_<NAME>_*` settings.
+    pub routes: Vec<RouteConfig>,
+    /// Name of the route serving requests no route matches; they get `404` without one.
+    pub default_route: Option<String>,
+    /// File the routes were read from, named in their validation errors.
+    pub routes_file: Option<PathBuf>,
+    pub health_check_interval_ms: u64,
+    /// Consecutive failed readiness checks before a replica leaves rotation.
+    pub health_check_failures: u32,
+    /// Per client IP limit of routes without their own `rate_limit`; none when unset.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Paths no per-IP limit applies to, e.g. `/ping` for load balancer checks.
+    pub rate_limit_exempt_paths: Vec<String>,
+    /// Proxies whose `X-Forwarded-For` names the client.
+    pub trusted_proxies: Vec<IpNet>,
     pub max_body_size: usize,
     pub connection_timeout_secs: u64,
     pub total_connection_timeout_secs: u64,
     pub read_timeout_secs: u64,
     pub write_timeout_secs: u64,
+    /// Further replicas tried after a failed connection, or an early upstream error on `GET`/`HEAD`.
+    pub max_retries: u32,
     pub allowed_origins: Vec<String>,
     pub oauth_callback_url: String,
     pub frontend_url: String,
     pub grace_period_secs: u64,
     pub graceful_shutdown_timeout_secs: u64,
+    pub metrics_addr: String,
+    pub admin_addr: String,
+    pub admin_token: Option<String>,
+    pub cache: CacheConfig,
+    pub log: LogConfig,
+}
+
+/// One entry of the routing table: requests matching `host` and `prefix` go to `upstreams`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Labels the route in logs, metrics and `/_proxy/status`.
+    pub name: String,
+    /// `Host` the request must be for, without port; any host when unset.
+    pub host: Option<String>,
+    /// Leading path segments of the request; any path when unset.
+    pub prefix: Option<String>,
+    /// Replaces the matched `prefix` in the path sent upstream, e.g. `/` to strip it.
+    pub rewrite_prefix: Option<String>,
+    pub upstreams: Vec<SocketAddr>,
+    /// How requests are spread over `upstreams`.
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Readiness path polled on each replica; unset keeps every replica in rotation.
+    pub health_path: Option<String>,
+    /// Shadow upstream receiving a copy of a share of the route's requests.
+    pub mirror: Option<MirrorConfig>,
+    /// Override `GATEWAY_CONN_TIMEOUT_SECS` and `GATEWAY_TOTAL_CONN_TIMEOUT_SECS` for this route.
+    pub connection_timeout_secs: Option<u64>,
+    pub total_connection_timeout_secs: Option<u64>,
+    /// Overrides `GATEWAY_MAX_RETRIES` for this route.
+    pub max_retries: Option<u32>,
+    /// Overrides `GATEWAY_RATE_LIMIT_RPS` and `GATEWAY_RATE_LIMIT_BURST` for this route.
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// Contents of `GATEWAY_ROUTES_FILE`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutesFile {
+    pub routes: Vec<RouteConfig>,
+    pub default_route: Option<String>,
+}
+
+impl RoutesFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let yaml = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        Self::parse(&yaml).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Errors name the field and line, e.g. `routes[1].upstreams[0]: invalid socket address syntax at line 7 column 9`.
+    pub fn parse(yaml: &str) -> Result<Self, String> {
+        serde_yaml::from_str(yaml).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    pub upstream: String,
+    /// Share of requests mirrored, 0 to 100.
+    #[serde(default = "default_mirror_percentage")]
+    pub percentage: u16,
+    /// Requests with larger bodies are not mirrored.
+    #[serde(default = "default_mirror_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+fn default_mirror_percentage() -> u16 {
+    100
 }
 
+fn default_mirror_max_body_bytes() -> usize {
+    65536
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    InvalidAddr { setting: String, addr: String },
+    InsecureAdminListener { addr: String },
+    InvalidHealthPath { setting: String, path: String },
+    InvalidMirrorPercentage { setting: String, percentage: u16 },
+    InvalidRoute { setting: String, reason: &'static str },
+    InvalidCachePrefix { prefix: String },
+    InvalidRateLimit { setting: String },
+    ZeroInterval { setting: &'static str },
+    UnknownDefaultRoute { name: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAddr { setting, addr } => write!(f, "{setting}={addr} is not a valid socket address"),
+            Self::InsecureAdminListener { addr } => write!(
+                f,
+                "GATEWAY_ADMIN_ADDR={addr} is not a loopback address; set GATEWAY_ADMIN_TOKEN to expose the admin listener"
+            ),
+            Self::InvalidHealthPath { setting, path } => write!(f, "{setting}={path} must be an absolute path"),
+            Self::InvalidMirrorPercentage { setting, percentage } => {
+                write!(f, "{setting}={percentage} must be between 0 and 100")
+            }
+            Self::InvalidRoute { setting, reason } => write!(f, "{setting} {reason}"),
+            Self::InvalidRateLimit { setting } => write!(f, "{setting} must be at least 1"),
+            Self::ZeroInterval { setting } => write!(f, "{setting} must be greater than 0"),
+            Self::InvalidCachePrefix { prefix } => write!(f, "GATEWAY_CACHE_PREFIXES entry {prefix} must start with /"),
+            Self::UnknownDefaultRoute { name } => write!(f, "default_route={name} does not name a route"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
     pub fn from_env() -> Self {
+        let routes_file = read_optional_env_var("GATEWAY_ROUTES_FILE").map(PathBuf::from);
+        let (routes, default_route) = match &routes_file {
+            Some(path) => {
+                let file = RoutesFile::load(path).unwrap_or_else(|e| panic!("GATEWAY_ROUTES_FILE is invalid: {e}"));
+                (file.routes, file.default_route)
+            }
+            None => (env_routes(), None),
+        };
         Self {
             listen_addr: read_env_var("GATEWAY_LISTEN_ADDR"),
-            images_upstream: read_env_var("GATEWAY_IMAGES_UPSTREAM"),
-            chats_upstream: read_env_var("GATEWAY_CHATS_UPSTREAM"),
-            channels_upstream: read_env_var("GATEWAY_CHANNELS_UPSTREAM"),
-            calls_upstream: read_env_var("GATEWAY_CALLS_UPSTREAM"),
             auth_upstream: read_env_var("GATEWAY_AUTH_UPSTREAM"),
-            max_req_per_sec: read_env_var("GATEWAY_MAX_REQ_PER_SEC")
+            routes,
+            default_route,
+            routes_file,
+            health_check_interval_ms: std::env::var("GATEWAY_HEALTH_CHECK_INTERVAL_MS")
+                .unwrap_or_else(|_| "1000".into())
+                .parse()
+                .expect("GATEWAY_HEALTH_CHECK_INTERVAL_MS must be a number"),
+            health_check_failures: std::env::var("GATEWAY_HEALTH_CHECK_FAILURES")
+                .unwrap_or_else(|_| "3".into())
                 .parse()
-                .expect("GATEWAY_MAX_REQ_PER_SEC must be a number"),
+                .expect("GATEWAY_HEALTH_CHECK_FAILURES must be a number"),
+            rate_limit: read_rate_limit("GATEWAY").or_else(read_legacy_rate_limit),
+            rate_limit_exempt_paths: parse_list(
+                &std::env::var("GATEWAY_RATE_LIMIT_EXEMPT_PATHS").unwrap_or_else(|_| "/ping".into()),
+            ),
+            trusted_proxies: parse_list(&std::env::var("GATEWAY_TRUSTED_PROXIES").unwrap_or_default())
+                .iter()
+                .map(|net| {
+                    net.parse()
+                        .unwrap_or_else(|e| panic!("GATEWAY_TRUSTED_PROXIES entry {net} is invalid: {e}"))
+                })
+                .collect(),
             max_body_size: read_env_var("GATEWAY_MAX_BODY_SIZE_MB")
                 .parse::<usize>()
                 .expect("GATEWAY_MAX_BODY_SIZE_MB must be a number")
@@ -47,6 +202,10 @@ impl Config {
             write_timeout_secs: read_env_var("GATEWAY_WRITE_TIMEOUT_SECS")
                 .parse()
                 .expect("GATEWAY_WRITE_TIMEOUT_SECS must be a number"),
+            max_retries: std::env::var("GATEWAY_MAX_RETRIES")
+                .unwrap_or_else(|_| "1".into())
+                .parse()
+                .expect("GATEWAY_MAX_RETRIES must be a number"),
             allowed_origins: parse_list(&std::env::var("GATEWAY_ALLOWED_ORIGINS").unwrap_or_default()),
             oauth_callback_url: std::env::var("GATEWAY_OAUTH_CALLBACK_URL")
                 .unwrap_or_else(|_| "http://127.0.0.1:8080/access/oauth/callback".into()),
@@ -59,14 +218,250 @@ impl Config {
                 .unwrap_or_else(|_| "5".into())
                 .parse()
                 .expect("GATEWAY_GRACEFUL_SHUTDOWN_TIMEOUT_SECS must be a number"),
+            metrics_addr: std::env::var("GATEWAY_METRICS_ADDR").unwrap_or_else(|_| "127.0.0.1:9091".into()),
+            admin_addr: std::env::var("GATEWAY_ADMIN_ADDR").unwrap_or_else(|_| "127.0.0.1:9092".into()),
+            admin_token: std::env::var("GATEWAY_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            cache: read_cache(),
+            log: LogConfig {
+                format: std::env::var("GATEWAY_LOG_FORMAT")
+                    .unwrap_or_else(|_| "compact".into())
+                    .parse()
+                    .unwrap_or_else(|e| panic!("GATEWAY_LOG_FORMAT is invalid: {e}")),
+                file: std::env::var("GATEWAY_LOG_FILE")
+                    .ok()
+                    .filter(|f| !f.is_empty())
+                    .map(PathBuf::from)
+                    .inspect(|f| assert!(f.file_name().is_some(), "GATEWAY_LOG_FILE must name a file, not a directory")),
+            },
+        }
+    }
+
+    /// Rejects settings that would expose the admin listener beyond localhost without a token, and routes
+    /// that are ambiguous or could never be served.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        is_loopback("GATEWAY_METRICS_ADDR", &self.metrics_addr)?;
+        if self.health_check_interval_ms == 0 {
+            return Err(ConfigError::ZeroInterval {
+                setting: "GATEWAY_HEALTH_CHECK_INTERVAL_MS",
+            });
+        }
+        if !is_loopback("GATEWAY_ADMIN_ADDR", &self.admin_addr)? && self.admin_token.is_none() {
+            return Err(ConfigError::InsecureAdminListener {
+                addr: self.admin_addr.clone(),
+            });
+        }
+        if let Some(name) = self
+            .default_route
+            .as_ref()
+            .filter(|&name| !self.routes.iter().any(|r| &r.name == name))
+        {
+            return Err(ConfigError::UnknownDefaultRoute { name: name.clone() });
+        }
+        for (index, route) in self.routes.iter().enumerate() {
+            self.validate_route(index, route)?;
+        }
+        if let Some(setting) = invalid_rate_limit(self.rate_limit.as_ref()) {
+            return Err(ConfigError::InvalidRateLimit {
+                setting: format!("GATEWAY_RATE_LIMIT_{setting}"),
+            });
+        }
+        if let Some(prefix) = self.cache.prefixes.iter().find(|p| !p.starts_with('/')) {
+            return Err(ConfigError::InvalidCachePrefix { prefix: prefix.clone() });
+        }
+        Ok(())
+    }
+
+    fn validate_route(&self, index: usize, route: &RouteConfig) -> Result<(), ConfigError> {
+        let invalid = |field, reason| ConfigError::InvalidRoute {
+            setting: self.route_setting(index, field),
+            reason,
+        };
+        if route.name.is_empty() {
+            return Err(invalid("name", "must not be empty"));
+        }
+        if self.routes[..index].iter().any(|r| r.name == route.name) {
+            return Err(invalid("name", "is used by an earlier route"));
+        }
+        if route.host.is_none() && route.prefix.is_none() && self.default_route.as_ref() != Some(&route.name) {
+            return Err(invalid("prefix", "or host is required, except on the default route"));
+        }
+        if route.prefix.as_deref().is_some_and(|p| !p.starts_with('/')) {
+            return Err(invalid("prefix", "must start with /"));
+        }
+        if route
+            .rewrite_prefix
+            .as_deref()
+            .is_some_and(|p| !p.starts_with('/') || p.contains('?') || p.parse::<PathAndQuery>().is_err())
+        {
+            return Err(invalid("rewrite_prefix", "must be a path starting with /"));
+        }
+        if route.upstreams.is_empty() {
+            return Err(invalid("upstreams", "must list at least one address"));
+        }
+        if let Some(path) = route.health_path.as_deref().filter(|p| !p.starts_with('/')) {
+            return Err(ConfigError::InvalidHealthPath {
+                setting: self.route_setting(index, "health_path"),
+                path: path.to_owned(),
+            });
+        }
+        if let Some(setting) = invalid_rate_limit(route.rate_limit.as_ref()) {
+            return Err(ConfigError::InvalidRateLimit {
+                setting: self.route_setting(index, &format!("rate_limit.{}", setting.to_ascii_lowercase())),
+            });
+        }
+        if let Some(mirror) = &route.mirror {
+            if mirror.upstream.parse::<SocketAddr>().is_err() {
+                return Err(ConfigError::InvalidAddr {
+                    setting: self.route_setting(index, "mirror.upstream"),
+                    addr: mirror.upstream.clone(),
+                });
+            }
+            if mirror.percentage > 100 {
+                return Err(ConfigError::InvalidMirrorPercentage {
+                    setting: self.route_setting(index, "mirror.percentage"),
+                    percentage: mirror.percentage,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Where a route field was set: its place in the routes file, or the `GATEWAY_<NAME>_*` variable.
+    fn route_setting(&self, index: usize, field: &str) -> String {
+        match &self.routes_file {
+            Some(file) => format!("{}: routes[{index}].{field}", file.display()),
+            None => format!(
+                "GATEWAY_{}_{}",
+                self.routes[index].name.to_ascii_uppercase(),
+                field.replace('.', "_").to_ascii_uppercase()
+            ),
         }
     }
 }
 
+/// `RPS` or `BURST` when that part of `limit` is zero.
+fn invalid_rate_limit(limit: Option<&RateLimitConfig>) -> Option<&'static str> {
+    let limit = limit?;
+    if limit.rps == 0 {
+        Some("RPS")
+    } else if limit.burst() == 0 {
+        Some("BURST")
+    } else {
+        None
+    }
+}
+
+fn is_loopback(setting: &str, addr: &str) -> Result<bool, ConfigError> {
+    let invalid = || ConfigError::InvalidAddr {
+        setting: setting.to_owned(),
+        addr: addr.to_owned(),
+    };
+    if let Some(port) = addr.strip_prefix("localhost:") {
+        return port.parse::<u16>().map(|_| true).map_err(|_| invalid());
+    }
+    addr.parse::<SocketAddr>()
+        .map(|a| a.ip().is_loopback())
+        .map_err(|_| invalid())
+}
+
 fn read_env_var(key: &str) -> String {
     std::env::var(key).unwrap_or_else(|_| panic!("Required environment variable {key} is not set"))
 }
 
+fn read_optional_env_var(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+/// Without `GATEWAY_ROUTES_FILE`: the images, chats, channels and calls routes, each read from
+/// `GATEWAY_<route>_UPSTREAM`, `GATEWAY_<route>_LB_STRATEGY`, `GATEWAY_<route>_HEALTH_PATH` and
+/// `GATEWAY_<route>_MIRROR_*`.
+fn env_routes() -> Vec<RouteConfig> {
+    [
+        ("images", "/images"),
+        ("chats", "/ws"),
+        ("channels", "/channels"),
+        ("calls", "/rooms"),
+    ]
+    .into_iter()
+    .map(|(name, prefix)| {
+        let route = name.to_ascii_uppercase();
+        RouteConfig {
+            name: name.into(),
+            prefix: Some(prefix.into()),
+            upstreams: crate::parse_upstreams(&read_env_var(&format!("GATEWAY_{route}_UPSTREAM"))),
+            strategy: read_optional_env_var(&format!("GATEWAY_{route}_LB_STRATEGY"))
+                .map(|s| {
+                    s.parse()
+                        .unwrap_or_else(|e| panic!("GATEWAY_{route}_LB_STRATEGY is invalid: {e}"))
+                })
+                .unwrap_or_default(),
+            health_path: read_optional_env_var(&format!("GATEWAY_{route}_HEALTH_PATH")),
+            mirror: read_mirror(&route),
+            rate_limit: read_rate_limit(&format!("GATEWAY_{route}")),
+            ..RouteConfig::default()
+        }
+    })
+    .collect()
+}
+
+/// `GATEWAY_<route>_MIRROR_*`; unset without `GATEWAY_<route>_MIRROR_UPSTREAM`.
+fn read_mirror(route: &str) -> Option<MirrorConfig> {
+    let upstream = read_optional_env_var(&format!("GATEWAY_{route}_MIRROR_UPSTREAM"))?;
+    let percentage_key = format!("GATEWAY_{route}_MIRROR_PERCENTAGE");
+    let max_body_key = format!("GATEWAY_{route}_MIRROR_MAX_BODY_BYTES");
+    Some(MirrorConfig {
+        upstream,
+        percentage: std::env::var(&percentage_key)
+            .unwrap_or_else(|_| "100".into())
+            .parse()
+            .unwrap_or_else(|_| panic!("{percentage_key} must be a number")),
+        max_body_bytes: std::env::var(&max_body_key)
+            .unwrap_or_else(|_| "65536".into())
+            .parse()
+            .unwrap_or_else(|_| panic!("{max_body_key} must be a number")),
+    })
+}
+
+/// `{prefix}_RATE_LIMIT_RPS` and `{prefix}_RATE_LIMIT_BURST`; unset without the former.
+fn read_rate_limit(prefix: &str) -> Option<RateLimitConfig> {
+    let rps_key = format!("{prefix}_RATE_LIMIT_RPS");
+    let burst_key = format!("{prefix}_RATE_LIMIT_BURST");
+    Some(RateLimitConfig {
+        rps: read_optional_env_var(&rps_key)?
+            .parse()
+            .unwrap_or_else(|_| panic!("{rps_key} must be a number")),
+        burst: read_optional_env_var(&burst_key).map(|v| v.parse().unwrap_or_else(|_| panic!("{burst_key} must be a number"))),
+    })
+}
+
+/// `GATEWAY_MAX_REQ_PER_SEC`, the fixed-window limit the per-IP token bucket replaced, read as its rate so
+/// existing deployments stay limited.
+fn read_legacy_rate_limit() -> Option<RateLimitConfig> {
+    Some(RateLimitConfig {
+        rps: read_optional_env_var("GATEWAY_MAX_REQ_PER_SEC")?
+            .parse()
+            .expect("GATEWAY_MAX_REQ_PER_SEC must be a number"),
+        burst: None,
+    })
+}
+
+/// `GATEWAY_CACHE_*`; the cache stays off without `GATEWAY_CACHE_PREFIXES`.
+fn read_cache() -> CacheConfig {
+    let defaults = CacheConfig::default();
+    CacheConfig {
+        prefixes: parse_list(&std::env::var("GATEWAY_CACHE_PREFIXES").unwrap_or_default()),
+        max_entry_bytes: std::env::var("GATEWAY_CACHE_MAX_ENTRY_BYTES")
+            .map(|v| v.parse().expect("GATEWAY_CACHE_MAX_ENTRY_BYTES must be a number"))
+            .unwrap_or(defaults.max_entry_bytes),
+        max_bytes: std::env::var("GATEWAY_CACHE_MAX_BYTES")
+            .map(|v| v.parse().expect("GATEWAY_CACHE_MAX_BYTES must be a number"))
+            .unwrap_or(defaults.max_bytes),
+        default_ttl_secs: std::env::var("GATEWAY_CACHE_TTL_SECS")
+            .map(|v| v.parse().expect("GATEWAY_CACHE_TTL_SECS must be a number"))
+            .unwrap_or(defaults.default_ttl_secs),
+    }
+}
+
 fn parse_list(value: &str) -> Vec<String> {
     value
         .split(',')
@@ -74,3 +469,251 @@ fn parse_list(value: &str) -> Vec<String> {
         .filter(|s| !s.is_empty())
         .collect()
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn config(admin_addr: &str, admin_token: Option<&str>) -> Config {
+        Config {
+            listen_addr: "0.0.0.0:8080".into(),
+            auth_upstream: "127.0.0.1:50051".into(),
+            routes: vec![route("images", "/images", 3001), route("chats", "/ws", 3002)],
+            default_route: None,
+            routes_file: None,
+            health_check_interval_ms: 1000,
+            health_check_failures: 3,
+            rate_limit: None,
+            rate_limit_exempt_paths: vec!["/ping".into()],
+            trusted_proxies: Vec::new(),
+            max_body_size: 1024,
+            connection_timeout_secs: 1,
+            total_connection_timeout_secs: 1,
+            read_timeout_secs: 1,
+            write_timeout_secs: 1,
+            max_retries: 1,
+            allowed_origins: Vec::new(),
+            oauth_callback_url: String::new(),
+            frontend_url: String::new(),
+            grace_period_secs: 1,
+            graceful_shutdown_timeout_secs: 1,
+            metrics_addr: "127.0.0.1:9091".into(),
+            admin_addr: admin_addr.into(),
+            admin_token: admin_token.map(String::from),
+            cache: CacheConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+
+    fn route(name: &str, prefix: &str, port: u16) -> RouteConfig {
+        RouteConfig {
+            name: name.into(),
+            prefix: Some(prefix.into()),
+            upstreams: vec![SocketAddr::from(([127, 0, 0, 1], port))],
+            ..RouteConfig::default()
+        }
+    }
+
+    #[test]
+    fn loopback_admin_without_token_is_allowed() {
+        assert!(config("127.0.0.1:9092", None).validate().is_ok());
+        assert!(config("[::1]:9092", None).validate().is_ok());
+        assert!(config("localhost:9092", None).validate().is_ok());
+    }
+
+    #[test]
+    fn public_admin_without_token_is_refused() {
+        let err = config("0.0.0.0:9092", None).validate().unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::InsecureAdminListener {
+                addr: "0.0.0.0:9092".into()
+            }
+        );
+        assert!(err.to_string().contains("GATEWAY_ADMIN_ADDR"));
+    }
+
+    #[test]
+    fn public_admin_with_token_is_allowed() {
+        assert!(config("0.0.0.0:9092", Some("secret")).validate().is_ok());
+    }
+
+    #[test]
+    fn zero_rate_limit_is_refused() {
+        let mut config = config("127.0.0.1:9092", None);
+        config.rate_limit = Some(RateLimitConfig { rps: 0, burst: None });
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "GATEWAY_RATE_LIMIT_RPS must be at least 1"
+        );
+        config.rate_limit = None;
+        config.routes[1].rate_limit = Some(RateLimitConfig { rps: 5, burst: Some(0) });
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "GATEWAY_CHATS_RATE_LIMIT_BURST must be at least 1"
+        );
+        config.routes_file = Some(PathBuf::from("routes.yaml"));
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "routes.yaml: routes[1].rate_limit.burst must be at least 1"
+        );
+    }
+
+    #[test]
+    fn zero_health_check_interval_is_refused() {
+        let mut config = config("127.0.0.1:9092", None);
+        config.health_check_interval_ms = 0;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "GATEWAY_HEALTH_CHECK_INTERVAL_MS must be greater than 0"
+        );
+    }
+
+    #[test]
+    fn relative_cache_prefix_is_refused() {
+        let mut config = config("127.0.0.1:9092", None);
+        config.cache.prefixes = vec!["/images".into(), "thumbs".into()];
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "GATEWAY_CACHE_PREFIXES entry thumbs must start with /"
+        );
+    }
+
+    #[test]
+    fn relative_health_path_is_refused() {
+        let mut config = config("127.0.0.1:9092", None);
+        config.routes[0].health_path = Some("/health/ready".into());
+        assert!(config.validate().is_ok());
+
+        config.routes[1].health_path = Some("health".into());
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ConfigError::InvalidHealthPath {
+                setting: "GATEWAY_CHATS_HEALTH_PATH".into(),
+                path: "health".into()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_admin_addr_is_refused() {
+        let err = config("not-an-addr", Some("secret")).validate().unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidAddr { setting, .. } if setting == "GATEWAY_ADMIN_ADDR"));
+    }
+
+    #[test]
+    fn invalid_mirror_is_refused() {
+        let mut config = config("127.0.0.1:9092", None);
+        config.routes[1].mirror = Some(MirrorConfig {
+            upstream: "127.0.0.1:4002".into(),
+            percentage: 100,
+            max_body_bytes: 1024,
+        });
+        assert!(config.validate().is_ok());
+
+        config.routes[1].mirror.as_mut().unwrap().percentage = 101;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ConfigError::InvalidMirrorPercentage {
+                setting: "GATEWAY_CHATS_MIRROR_PERCENTAGE".into(),
+                percentage: 101
+            }
+        );
+        // Beyond what a byte holds is refused the same way rather than failing to parse.
+        config.routes[1].mirror.as_mut().unwrap().percentage = 300;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "GATEWAY_CHATS_MIRROR_PERCENTAGE=300 must be between 0 and 100"
+        );
+
+        config.routes[1].mirror.as_mut().unwrap().upstream = "shadow".into();
+        assert!(matches!(
+            config.validate().unwrap_err(),
+            ConfigError::InvalidAddr { setting, .. } if setting == "GATEWAY_CHATS_MIRROR_UPSTREAM"
+        ));
+    }
+
+    #[test]
+    fn example_routes_file_parses() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("routes.example.yaml");
+        let file = RoutesFile::load(&path).unwrap();
+        let names: Vec<_> = file.routes.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["images", "chats", "channels", "calls", "py", "web"]);
+        assert_eq!(file.default_route.as_deref(), Some("web"));
+        assert_eq!(file.routes[1].strategy, Strategy::IpHash);
+        assert_eq!(file.routes[2].strategy, Strategy::RoundRobin);
+        assert_eq!(file.routes[4].rewrite_prefix.as_deref(), Some("/"));
+
+        let images = &file.routes[0];
+        assert_eq!(images.prefix.as_deref(), Some("/images"));
+        assert_eq!(
+            images.upstreams,
+            ["127.0.0.1:3005".parse().unwrap(), "127.0.0.1:3015".parse().unwrap()]
+        );
+        assert_eq!(images.health_path.as_deref(), Some("/health/ready"));
+        assert_eq!(images.total_connection_timeout_secs, Some(30));
+        assert_eq!(images.max_retries, Some(2));
+        assert_eq!(
+            images.rate_limit,
+            Some(RateLimitConfig {
+                rps: 50,
+                burst: Some(100)
+            })
+        );
+        assert_eq!(
+            images.mirror.as_ref().map(|m| (m.percentage, m.max_body_bytes)),
+            Some((10, 65536))
+        );
+
+        let mut config = config("127.0.0.1:9092", None);
+        config.routes = file.routes;
+        config.default_route = file.default_route;
+        config.routes_file = Some(path);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn routes_file_errors_name_the_field() {
+        let err = RoutesFile::parse("routes:\n  - name: images\n    prefix: /images\n    upstreams: [localhost]\n").unwrap_err();
+        assert!(err.starts_with("routes[0].upstreams[0]: "), "{err}");
+        assert!(err.contains("line 4"), "{err}");
+
+        let err = RoutesFile::parse("routes:\n  - name: images\n    prefixx: /images\n    upstreams: []\n").unwrap_err();
+        assert!(err.contains("unknown field `prefixx`"), "{err}");
+
+        let mut config = config("127.0.0.1:9092", None);
+        config.routes_file = Some(PathBuf::from("routes.yaml"));
+        config.routes[1].prefix = Some("ws".into());
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "routes.yaml: routes[1].prefix must start with /"
+        );
+
+        config.routes[1].prefix = Some("/ws".into());
+        config.routes[1].rewrite_prefix = Some("v1".into());
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "routes.yaml: routes[1].rewrite_prefix must be a path starting with /"
+        );
+        config.routes[1].rewrite_prefix = None;
+
+        config.routes[1].prefix = None;
+        assert!(matches!(config.validate().unwrap_err(), ConfigError::InvalidRoute { reason, .. } if reason.contains("host")));
+        config.default_route = Some("chats".into());
+        assert!(config.validate().is_ok());
+
+        config.default_route = Some("web".into());
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ConfigError::UnknownDefaultRoute { name: "web".into() }
+        );
+
+        config.default_route = None;
+        config.routes[1].name = "images".into();
+        config.routes[1].prefix = Some("/ws".into());
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "routes.yaml: routes[1].name is used by an earlier route"
+        );
+    }
+}
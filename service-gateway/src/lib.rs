@@ -1,25 +1,46 @@
+pub mod admin;
 pub mod auth_handler;
+pub mod cache;
 pub mod config;
+mod metrics;
+pub mod mirror;
+pub mod rate_limit;
+pub mod routes;
+pub mod upstream;
+
+pub use service_common::logging;
 
 pub mod proto {
     tonic::include_proto!("auth");
 }
 
+use admin::{AdminApp, UpstreamHealth};
+use cache::{CacheFill, CachedResponse, ResponseCache};
 use config::Config;
+use mirror::MirrorRequest;
+use pingora::apps::http_app::HttpServer;
 use pingora::http::ResponseHeader;
-use pingora::prelude::{Error, HTTPStatus, HttpPeer, ProxyHttp, RequestHeader, Session};
-use pingora_limits::rate::Rate;
+use pingora::prelude::{Error, HTTPStatus, HttpPeer, OrErr, ProxyHttp, RequestHeader, Server, Session, http_proxy_service};
+use pingora::protocols::Digest;
+use pingora::proxy::FailToProxy;
+use pingora::services::background::background_service;
+use pingora::services::listening::Service;
+use pingora::upstreams::peer::Peer;
+use pingora::{ErrorSource, ErrorType};
 use proto::auth_service_client::AuthServiceClient;
-use std::net::SocketAddr;
-use std::sync::{Arc, LazyLock};
-use std::time::Duration;
+use routes::{Route, RouteTable};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::OnceCell;
 use tonic::transport::{Channel, Endpoint};
+use upstream::{HealthChecker, InFlight, UpstreamPool};
 use uuid::Uuid;
 
 pub type PingoraResult<T> = pingora::Result<T>;
 
-static RATE_LIMITER: LazyLock<Rate> = LazyLock::new(|| Rate::new(Duration::from_secs(1)));
+/// Longest client `X-Request-Id` that is passed on; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
 
 fn insert_cors_headers(header: &mut ResponseHeader, origin: Option<&str>, allowed_origins: &[String]) -> PingoraResult<()> {
     let allowed = match origin {
@@ -50,46 +71,57 @@ pub struct RequestCtx {
     pub user_id: Option<String>,
     pub username: Option<String>,
     pub email: Option<String>,
-}
-
-struct Upstream {
-    addr: SocketAddr,
-    is_grpc: bool,
+    /// Route picked for the request, set once an upstream is chosen; `None` for the auth service.
+    pub route: Option<Arc<Route>>,
+    pub started: Instant,
+    pub mirror: Option<MirrorRequest>,
+    /// Counts the request against the replica it was sent to until the request is logged.
+    pub in_flight: Option<InFlight>,
+    /// Replicas the request was sent to, so retries go elsewhere.
+    pub tried: Vec<SocketAddr>,
+    pub retries: u32,
+    /// Cache key of a request that missed the response cache, until its upstream response arrives.
+    pub cache_key: Option<String>,
+    /// Upstream response being stored in the cache as its body streams through.
+    pub cache_fill: Option<CacheFill>,
 }
 
 pub struct Gateway {
-    pub images_upstream: SocketAddr,
-    pub chats_upstream: SocketAddr,
-    pub channels_upstream: SocketAddr,
-    pub calls_upstream: SocketAddr,
+    pub routes: RouteTable,
     pub auth_upstream: SocketAddr,
     pub auth_endpoint: Endpoint,
     auth_client: OnceCell<AuthServiceClient<Channel>>,
     pub config: Arc<Config>,
+    pub health: Arc<UpstreamHealth>,
+    pub cache: Arc<ResponseCache>,
 }
 
 impl Gateway {
-    pub fn new(
-        images_upstream: SocketAddr,
-        chats_upstream: SocketAddr,
-        channels_upstream: SocketAddr,
-        calls_upstream: SocketAddr,
-        auth_upstream: SocketAddr,
-        auth_endpoint: Endpoint,
-        config: Arc<Config>,
-    ) -> Self {
+    pub fn new(auth_upstream: SocketAddr, auth_endpoint: Endpoint, config: Arc<Config>) -> Self {
+        let routes = RouteTable::new(&config);
+        let replicas = routes
+            .routes()
+            .flat_map(|route| route.pool.addrs().map(|addr| (route.name.clone(), addr)))
+            .collect::<Vec<_>>();
+        let health = Arc::new(UpstreamHealth::new(
+            replicas.into_iter().chain([("auth".to_owned(), auth_upstream)]),
+        ));
+
         Self {
-            images_upstream,
-            chats_upstream,
-            channels_upstream,
-            calls_upstream,
+            routes,
             auth_upstream,
             auth_endpoint,
             auth_client: OnceCell::new(),
+            cache: Arc::new(ResponseCache::new(config.cache.clone())),
             config,
+            health,
         }
     }
 
+    pub fn pools(&self) -> Vec<Arc<UpstreamPool>> {
+        self.routes.routes().map(|route| Arc::clone(&route.pool)).collect()
+    }
+
     async fn get_auth_client(&self) -> &AuthServiceClient<Channel> {
         self.auth_client
             .get_or_init(|| async {
@@ -100,6 +132,16 @@ impl Gateway {
     }
 }
 
+/// The client's `X-Request-Id` if it is a short run of visible ASCII, so callers can correlate their own logs;
+/// otherwise the id generated in `new_ctx` is used.
+fn inbound_request_id(req: &RequestHeader) -> Option<String> {
+    req.headers
+        .get("X-Request-Id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_owned)
+}
+
 fn is_public_route(method: &str, path: &str) -> bool {
     if path.starts_with("/auth.") || path.starts_with("/access/") || path == "/ping" || path == "/metrics" {
         return true;
@@ -110,6 +152,11 @@ fn is_public_route(method: &str, path: &str) -> bool {
     false
 }
 
+/// Room invites are verified by service-chats itself, so invite-bearing websocket upgrades skip access-token checks.
+fn is_invite_websocket(path: &str, query: Option<&str>) -> bool {
+    path.starts_with("/ws/") && query.is_some_and(|q| q.split('&').any(|param| param.starts_with("invite=")))
+}
+
 fn extract_token(session: &Session, path: &str) -> Option<String> {
     if let Some(auth) = session.req_header().headers.get("authorization")
         && let Ok(auth_str) = auth.to_str()
@@ -149,54 +196,141 @@ async fn respond_unauthorized(
     Ok(true)
 }
 
+/// Uses up one of the route's retries, if it has any left.
+fn take_retry(ctx: &mut RequestCtx) -> bool {
+    let Some(route) = &ctx.route else { return false };
+    if ctx.retries >= route.max_retries {
+        return false;
+    }
+    ctx.retries += 1;
+    metrics::record_retry(&route.name);
+    true
+}
+
+/// Status pingora's default `fail_to_proxy` answers with; `0` when the client connection is already gone.
+fn error_status(e: &Error) -> u16 {
+    match e.etype() {
+        HTTPStatus(code) => *code,
+        _ => match e.esource() {
+            ErrorSource::Upstream => 502,
+            ErrorSource::Downstream => match e.etype() {
+                ErrorType::WriteError | ErrorType::ReadError | ErrorType::ConnectionClosed => 0,
+                _ => 400,
+            },
+            ErrorSource::Internal | ErrorSource::Unset => 500,
+        },
+    }
+}
+
+async fn respond_json(
+    session: &mut Session,
+    status: u16,
+    body: &serde_json::Value,
+    ctx: &RequestCtx,
+    allowed_origins: &[String],
+) -> PingoraResult<()> {
+    let body = bytes::Bytes::from(body.to_string());
+    let mut header = ResponseHeader::build(status, None)?;
+    header.insert_header("Content-Type", "application/json")?;
+    header.insert_header("Content-Length", body.len().to_string())?;
+    header.insert_header("X-Request-Id", &ctx.request_id)?;
+    insert_cors_headers(&mut header, ctx.origin.as_deref(), allowed_origins)?;
+    session.set_keepalive(None);
+    session.write_response_header(Box::new(header), false).await?;
+    session.write_response_body(Some(body), true).await
+}
+
+/// `429` with the whole seconds until the client may retry.
+async fn respond_rate_limited(
+    session: &mut Session,
+    retry_after: Duration,
+    body: &serde_json::Value,
+    ctx: &RequestCtx,
+    allowed_origins: &[String],
+) -> PingoraResult<()> {
+    let body = bytes::Bytes::from(body.to_string());
+    let mut header = ResponseHeader::build(429, None)?;
+    header.insert_header("Content-Type", "application/json")?;
+    header.insert_header("Content-Length", body.len().to_string())?;
+    header.insert_header("Retry-After", (retry_after.as_secs_f64().ceil() as u64).max(1).to_string())?;
+    header.insert_header("X-Request-Id", &ctx.request_id)?;
+    insert_cors_headers(&mut header, ctx.origin.as_deref(), allowed_origins)?;
+    session.write_response_header(Box::new(header), false).await?;
+    session.write_response_body(Some(body), true).await
+}
+
+/// Answers from the response cache without contacting the upstream.
+async fn respond_cached(
+    session: &mut Session,
+    cached: &CachedResponse,
+    ctx: &RequestCtx,
+    allowed_origins: &[String],
+) -> PingoraResult<()> {
+    let mut header = cached.header.clone();
+    header.insert_header("Content-Length", cached.body.len().to_string())?;
+    header.insert_header("X-Cache", "HIT")?;
+    header.insert_header("X-Request-Id", &ctx.request_id)?;
+    insert_cors_headers(&mut header, ctx.origin.as_deref(), allowed_origins)?;
+    session.write_response_header(Box::new(header), false).await?;
+    session.write_response_body(Some(cached.body.clone()), true).await
+}
+
 impl Gateway {
-    fn rate_limit_key(&self, session: &mut Session) -> String {
-        session
+    fn peer(&self, addr: SocketAddr, connection_timeout: Duration, total_connection_timeout: Duration) -> HttpPeer {
+        let mut peer = HttpPeer::new(addr, false, "".into());
+        peer.options.connection_timeout = Some(connection_timeout);
+        peer.options.total_connection_timeout = Some(total_connection_timeout);
+        peer.options.read_timeout = Some(Duration::from_secs(self.config.read_timeout_secs));
+        peer.options.write_timeout = Some(Duration::from_secs(self.config.write_timeout_secs));
+        peer
+    }
+
+    /// Address the request is from, looking through `X-Forwarded-For` set by `GATEWAY_TRUSTED_PROXIES`.
+    fn client_ip(&self, session: &Session) -> Option<IpAddr> {
+        let peer = session.client_addr()?.as_inet()?.ip();
+        let forwarded_for = session
             .req_header()
             .headers
-            .get("appid")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                session
-                    .client_addr()
-                    .and_then(|addr| addr.as_inet())
-                    .map(|addr| addr.ip().to_string())
-                    .unwrap_or_else(|| "unknown".to_string())
-            })
+            .get("X-Forwarded-For")
+            .and_then(|v| v.to_str().ok());
+        Some(rate_limit::client_ip(peer, forwarded_for, &self.config.trusted_proxies))
     }
 
-    fn route_upstream(&self, path: &str) -> PingoraResult<Upstream> {
-        match path {
-            p if p.starts_with("/images") => Ok(Upstream {
-                addr: self.images_upstream,
-                is_grpc: false,
-            }),
-            p if p.starts_with("/ws") => Ok(Upstream {
-                addr: self.chats_upstream,
-                is_grpc: false,
-            }),
-            p if p.starts_with("/channels") => Ok(Upstream {
-                addr: self.channels_upstream,
-                is_grpc: false,
-            }),
-            p if p.starts_with("/rooms") => Ok(Upstream {
-                addr: self.calls_upstream,
-                is_grpc: false,
-            }),
-            p if p.starts_with("/auth.") => Ok(Upstream {
-                addr: self.auth_upstream,
-                is_grpc: true,
-            }),
-            p if p.starts_with("/access/") => {
-                tracing::warn!(path = %p, "Unexpected /access/ route reached upstream routing");
-                Err(Error::explain(HTTPStatus(500), "Auth route not intercepted"))
-            }
-            _ => {
-                tracing::warn!(path = %path, "Unknown path");
-                Err(Error::explain(HTTPStatus(404), "Not Found"))
-            }
+    /// Spends a token of the client's bucket on the route the request is for: the route and the wait
+    /// for the next token when the bucket is empty.
+    fn route_rate_limit(&self, session: &Session) -> Option<(&Arc<Route>, Duration)> {
+        let req = session.req_header();
+        let path = req.uri.path();
+        if path.starts_with("/auth.")
+            || path.starts_with("/access/")
+            || self
+                .config
+                .rate_limit_exempt_paths
+                .iter()
+                .any(|exempt| routes::strip_prefix(path, exempt).is_some())
+        {
+            return None;
         }
+        let route = self.routes.find(routes::request_host(req), path)?;
+        let retry_after = route.limiter.as_ref()?.check(self.client_ip(session)?).err()?;
+        Some((route, retry_after))
+    }
+
+    /// The route serving `req`, or `None` for the auth service's gRPC methods.
+    fn route_for(&self, req: &RequestHeader) -> PingoraResult<Option<Arc<Route>>> {
+        let path = req.uri.path();
+        if path.starts_with("/auth.") {
+            return Ok(None);
+        }
+        if path.starts_with("/access/") {
+            tracing::warn!(path = %path, "Unexpected /access/ route reached upstream routing");
+            return Err(Error::explain(HTTPStatus(500), "Auth route not intercepted"));
+        }
+        let Some(route) = self.routes.find(routes::request_host(req), path) else {
+            tracing::warn!(path = %path, "Unknown path");
+            return Err(Error::explain(HTTPStatus(404), "Not Found"));
+        };
+        Ok(Some(Arc::clone(route)))
     }
 }
 
@@ -213,28 +347,57 @@ impl ProxyHttp for Gateway {
             user_id: None,
             username: None,
             email: None,
+            route: None,
+            started: Instant::now(),
+            mirror: None,
+            in_flight: None,
+            tried: Vec::new(),
+            retries: 0,
+            cache_key: None,
+            cache_fill: None,
         }
     }
 
     async fn upstream_peer(&self, session: &mut Session, ctx: &mut Self::CTX) -> PingoraResult<Box<HttpPeer>> {
-        let path = session.req_header().uri.path();
-        let route = self.route_upstream(path)?;
-        ctx.is_grpc = route.is_grpc;
-
-        let mut peer = HttpPeer::new(route.addr, false, "".into());
-        peer.options.connection_timeout = Some(Duration::from_secs(self.config.connection_timeout_secs));
-        peer.options.total_connection_timeout = Some(Duration::from_secs(self.config.total_connection_timeout_secs));
-        peer.options.read_timeout = Some(Duration::from_secs(self.config.read_timeout_secs));
-        peer.options.write_timeout = Some(Duration::from_secs(self.config.write_timeout_secs));
-
-        if route.is_grpc {
+        let Some(route) = self.route_for(session.req_header())? else {
+            ctx.is_grpc = true;
+            let mut peer = self.peer(
+                self.auth_upstream,
+                Duration::from_secs(self.config.connection_timeout_secs),
+                Duration::from_secs(self.config.total_connection_timeout_secs),
+            );
             peer.options.alpn = pingora::protocols::ALPN::H2;
+            return Ok(Box::new(peer));
+        };
+        // Retries pick a peer again; the request is only sampled for mirroring once.
+        if ctx.route.is_none()
+            && let Some(mirror) = &route.mirror
+        {
+            ctx.mirror = mirror.capture(session.req_header());
         }
-
-        Ok(Box::new(peer))
+        ctx.route = Some(Arc::clone(&route));
+
+        let Some(addr) = route.pool.select(self.client_ip(session), &ctx.tried) else {
+            tracing::warn!(upstream = %route.name, "No ready replica");
+            return Err(Error::explain(HTTPStatus(503), "No ready upstream"));
+        };
+        if ctx.retries > 0 {
+            tracing::warn!(upstream = %route.name, %addr, retry = ctx.retries, "Retrying request on another replica");
+        }
+        ctx.tried.push(addr);
+        ctx.in_flight = Some(route.pool.start_request(addr));
+        Ok(Box::new(self.peer(
+            addr,
+            route.connection_timeout,
+            route.total_connection_timeout,
+        )))
     }
 
     async fn request_filter(&self, session: &mut Session, ctx: &mut Self::CTX) -> PingoraResult<bool> {
+        if let Some(request_id) = inbound_request_id(session.req_header()) {
+            ctx.request_id = request_id;
+        }
+
         if let Some(value) = session.req_header().headers.get("Content-Length")
             && let Ok(len_str) = value.to_str()
             && let Ok(len) = len_str.parse::<usize>()
@@ -245,18 +408,6 @@ impl ProxyHttp for Gateway {
             return Ok(true);
         }
 
-        let key = self.rate_limit_key(session);
-        let curr_window_requests = RATE_LIMITER.observe(&key, 1);
-        if curr_window_requests > self.config.max_req_per_sec {
-            let mut header = ResponseHeader::build(429, None)?;
-            header.insert_header("X-Rate-Limit-Limit", self.config.max_req_per_sec.to_string())?;
-            header.insert_header("X-Rate-Limit-Remaining", "0")?;
-            header.insert_header("X-Rate-Limit-Reset", "1")?;
-            session.set_keepalive(None);
-            session.write_response_header(Box::new(header), true).await?;
-            return Ok(true);
-        }
-
         ctx.origin = session
             .req_header()
             .headers
@@ -264,6 +415,15 @@ impl ProxyHttp for Gateway {
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string());
 
+        // Before authentication, so scrapers over their limit cost no token validation.
+        if let Some((route, retry_after)) = self.route_rate_limit(session) {
+            tracing::warn!(route = %route.name, client = ?self.client_ip(session), "Client over its rate limit");
+            metrics::record_rate_limited(&route.name);
+            let body = serde_json::json!({"error": "Too many requests", "route": route.name});
+            respond_rate_limited(session, retry_after, &body, ctx, &self.config.allowed_origins).await?;
+            return Ok(true);
+        }
+
         let path = session.req_header().uri.path();
         let method = session.req_header().method.as_str();
 
@@ -284,7 +444,7 @@ impl ProxyHttp for Gateway {
             return auth_handler::handle_auth_route(session, &path, &method, self.get_auth_client().await, &auth_ctx).await;
         }
 
-        if !is_public_route(method, path) {
+        if !is_public_route(method, path) && !is_invite_websocket(path, session.req_header().uri.query()) {
             let Some(token) = extract_token(session, path) else {
                 return respond_unauthorized(session, ctx.origin.as_deref(), &self.config.allowed_origins, &ctx.request_id).await;
             };
@@ -308,6 +468,17 @@ impl ProxyHttp for Gateway {
             }
         }
 
+        // After authentication, so a hit is only served to clients the upstream would have answered.
+        if let Some(key) = self.cache.key(session.req_header()) {
+            if let Some(cached) = self.cache.get(&key, session.req_header()) {
+                metrics::record_cache_lookup(true);
+                respond_cached(session, &cached, ctx, &self.config.allowed_origins).await?;
+                return Ok(true);
+            }
+            metrics::record_cache_lookup(false);
+            ctx.cache_key = Some(key);
+        }
+
         Ok(false)
     }
 
@@ -315,7 +486,7 @@ impl ProxyHttp for Gateway {
         &self,
         _session: &mut Session,
         body: &mut Option<bytes::Bytes>,
-        _end_of_stream: bool,
+        end_of_stream: bool,
         ctx: &mut Self::CTX,
     ) -> PingoraResult<()>
     where
@@ -332,6 +503,11 @@ impl ProxyHttp for Gateway {
                 return Err(Error::explain(HTTPStatus(413), "Stream exceeded limit"));
             }
         }
+        if let Some(mirror) = ctx.mirror.as_mut()
+            && !mirror.push_body(body.as_deref(), end_of_stream)
+        {
+            ctx.mirror = None;
+        }
         Ok(())
     }
 
@@ -383,20 +559,56 @@ impl ProxyHttp for Gateway {
             upstream_request.insert_header("X-Forwarded-Host", &host_str)?;
         }
 
+        if let Some(route) = &ctx.route
+            && let Some(uri) = route
+                .rewrite(&upstream_request.uri)
+                .or_err(ErrorType::InternalError, "Cannot rewrite request path")?
+        {
+            upstream_request.set_uri(uri);
+        }
+
+        if let Some(mirror) = ctx.mirror.as_mut() {
+            mirror.set_header(upstream_request);
+        }
+
         Ok(())
     }
 
     async fn upstream_response_filter(
         &self,
-        _session: &mut Session,
+        session: &mut Session,
         upstream_response: &mut ResponseHeader,
         ctx: &mut Self::CTX,
     ) -> PingoraResult<()> {
+        // Stored before the per-request headers below are added.
+        if let Some(key) = ctx.cache_key.take() {
+            ctx.cache_fill = self.cache.fill(key, session.req_header(), upstream_response);
+            upstream_response.insert_header("X-Cache", "MISS")?;
+        }
         insert_cors_headers(upstream_response, ctx.origin.as_deref(), &self.config.allowed_origins)?;
         upstream_response.insert_header("X-Request-Id", &ctx.request_id)?;
         Ok(())
     }
 
+    fn upstream_response_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<bytes::Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> PingoraResult<Option<Duration>> {
+        if let Some(fill) = ctx.cache_fill.as_mut()
+            && let Some(chunk) = body
+            && !self.cache.push_body(fill, chunk)
+        {
+            ctx.cache_fill = None;
+        }
+        if end_of_stream && let Some(fill) = ctx.cache_fill.take() {
+            self.cache.insert(fill);
+        }
+        Ok(None)
+    }
+
     async fn logging(&self, session: &mut Session, e: Option<&Error>, ctx: &mut Self::CTX) {
         let method = session.req_header().method.as_str();
         let path = session.req_header().uri.path();
@@ -409,6 +621,8 @@ impl ProxyHttp for Gateway {
                 method = %method,
                 path = %path,
                 status = status,
+                latency_ms = ctx.started.elapsed().as_millis() as u64,
+                retries = ctx.retries,
                 client = %client_addr,
                 error = %error,
                 "Request failed"
@@ -419,59 +633,224 @@ impl ProxyHttp for Gateway {
                 method = %method,
                 path = %path,
                 status = status,
+                latency_ms = ctx.started.elapsed().as_millis() as u64,
+                retries = ctx.retries,
                 client = %client_addr,
                 "Request completed"
             );
         }
+
+        ctx.in_flight = None;
+        if let Some(route) = &ctx.route {
+            let status = if status == 0 { "error".to_owned() } else { status.to_string() };
+            metrics::record_upstream(&route.name, false, &status, Some(ctx.started.elapsed()));
+        }
+        // Only once the client has its response, so the copy never delays it.
+        if let Some(mirror) = ctx.mirror.take() {
+            mirror.dispatch();
+        }
+    }
+
+    async fn connected_to_upstream(
+        &self,
+        _session: &mut Session,
+        _reused: bool,
+        peer: &HttpPeer,
+        #[cfg(unix)] _fd: std::os::unix::io::RawFd,
+        #[cfg(windows)] _sock: std::os::windows::io::RawSocket,
+        _digest: Option<&Digest>,
+        _ctx: &mut Self::CTX,
+    ) -> PingoraResult<()> {
+        if let Some(addr) = peer.address().as_inet() {
+            self.health.record_success(addr);
+        }
+        Ok(())
+    }
+
+    async fn fail_to_proxy(&self, session: &mut Session, e: &Error, ctx: &mut Self::CTX) -> FailToProxy {
+        let code = error_status(e);
+        if code == 503
+            && let Some(route) = &ctx.route
+            && !route.pool.has_ready()
+        {
+            let body = serde_json::json!({"error": "No ready upstream", "route": route.name});
+            if let Err(e) = respond_json(session, 503, &body, ctx, &self.config.allowed_origins).await {
+                tracing::error!(error = %e, "Failed to send error response");
+            }
+        } else if code > 0
+            && let Err(e) = session.respond_error(code).await
+        {
+            tracing::error!(error = %e, "Failed to send error response");
+        }
+        FailToProxy {
+            error_code: code,
+            can_reuse_downstream: false,
+        }
     }
 
-    fn fail_to_connect(&self, _session: &mut Session, _peer: &HttpPeer, _ctx: &mut Self::CTX, e: Box<Error>) -> Box<Error> {
+    fn fail_to_connect(&self, _session: &mut Session, peer: &HttpPeer, ctx: &mut Self::CTX, mut e: Box<Error>) -> Box<Error> {
         tracing::error!(error = %e, "Failed to connect to upstream");
+        if let Some(addr) = peer.address().as_inet() {
+            self.health.record_failure(addr, &e.to_string());
+        }
+        // Nothing reached the upstream, so any method can go to another replica.
+        if take_retry(ctx) {
+            e.set_retry(true);
+            return e;
+        }
         Error::explain(HTTPStatus(502), "Bad Gateway")
     }
 
     fn error_while_proxy(
         &self,
         _peer: &HttpPeer,
-        _session: &mut Session,
-        e: Box<Error>,
-        _ctx: &mut Self::CTX,
+        session: &mut Session,
+        mut e: Box<Error>,
+        ctx: &mut Self::CTX,
         _client_reused: bool,
     ) -> Box<Error> {
         tracing::error!(error = %e, "Error while proxying");
+        // The upstream may have acted on the request, so only methods that are safe to repeat are retried,
+        // and only while the client has not seen any of the response.
+        let idempotent = matches!(session.req_header().method, http::Method::GET | http::Method::HEAD);
+        if !idempotent {
+            e.set_retry(false);
+        } else if session.response_written().is_none() && take_retry(ctx) {
+            e.set_retry(true);
+        }
         e
     }
 }
 
-pub fn init_tracing() {
-    use tracing_subscriber::EnvFilter;
-
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .compact()
-        .with_file(true)
-        .with_line_number(true)
-        .with_target(false)
-        .init();
+/// Logs in the format and to the destination of [`logging::LogConfig`].
+pub fn init_tracing(config: &logging::LogConfig) -> logging::LogGuard {
+    logging::init(config)
 }
 
 pub fn parse_upstream(addr: &str) -> SocketAddr {
     addr.parse().unwrap_or_else(|_| panic!("Invalid upstream address: {addr}"))
 }
 
+/// Comma-separated replica addresses of one upstream.
+pub fn parse_upstreams(addrs: &str) -> Vec<SocketAddr> {
+    let parsed: Vec<_> = addrs
+        .split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(parse_upstream)
+        .collect();
+    assert!(!parsed.is_empty(), "Upstream address list is empty");
+    parsed
+}
+
+/// Adds the proxy, readiness checker, metrics and admin services to a bootstrapped server.
+pub fn add_services(server: &mut Server, config: Arc<Config>) {
+    let auth_grpc_uri = format!("http://{}", config.auth_upstream);
+    let auth_endpoint: Endpoint = auth_grpc_uri.parse().expect("Failed to parse auth upstream as gRPC endpoint");
+
+    let gateway = Gateway::new(parse_upstream(&config.auth_upstream), auth_endpoint, Arc::clone(&config));
+
+    let health = Arc::clone(&gateway.health);
+    let cache = Arc::clone(&gateway.cache);
+    let pools = gateway.pools();
+    let checker = HealthChecker::new(
+        pools.clone(),
+        Duration::from_millis(config.health_check_interval_ms),
+        Duration::from_secs(config.connection_timeout_secs),
+        config.health_check_failures,
+    );
+
+    let mut lb = http_proxy_service(&server.configuration, gateway);
+    lb.add_tcp(&config.listen_addr);
+
+    let mut metrics = Service::prometheus_http_service();
+    metrics.add_tcp(&config.metrics_addr);
+
+    let mut admin = Service::new(
+        "Gateway admin".to_string(),
+        HttpServer::new_app(AdminApp::new(config.admin_token.clone(), health, pools, cache)),
+    );
+    admin.add_tcp(&config.admin_addr);
+
+    server.add_service(lb);
+    server.add_service(background_service("upstream readiness", checker));
+    server.add_service(metrics);
+    server.add_service(admin);
+}
+
 pub fn log_config(config: &Config) {
     tracing::info!("--- Gateway configuration ---");
     tracing::info!("listen: {}", config.listen_addr);
-    tracing::info!("images upstream: {}", config.images_upstream);
-    tracing::info!("chats upstream: {}", config.chats_upstream);
-    tracing::info!("channels upstream: {}", config.channels_upstream);
-    tracing::info!("calls upstream: {}", config.calls_upstream);
+    if !config.cache.prefixes.is_empty() {
+        tracing::info!(
+            "response cache: {} up to {} bytes per entry, {} in total",
+            config.cache.prefixes.join(", "),
+            config.cache.max_entry_bytes,
+            config.cache.max_bytes
+        );
+    }
+    if let Some(file) = &config.routes_file {
+        tracing::info!("routes file: {}", file.display());
+    }
+    for route in &config.routes {
+        let upstreams: Vec<_> = route.upstreams.iter().map(SocketAddr::to_string).collect();
+        tracing::info!(
+            "{} route: host {}, prefix {} -> {}",
+            route.name,
+            route.host.as_deref().unwrap_or("*"),
+            route.prefix.as_deref().unwrap_or("*"),
+            upstreams.join(", ")
+        );
+        if let Some(rewrite) = &route.rewrite_prefix {
+            tracing::info!(
+                "{} path rewrite: {} -> {rewrite}",
+                route.name,
+                route.prefix.as_deref().unwrap_or("/")
+            );
+        }
+        if let Some(limit) = route.rate_limit.or(config.rate_limit) {
+            tracing::info!(
+                "{} rate limit: {} rps, burst {} per client IP",
+                route.name,
+                limit.rps,
+                limit.burst()
+            );
+        }
+        if let Some(path) = &route.health_path {
+            tracing::info!(
+                "{} readiness path: {path} every {}ms, out of rotation after {} failures",
+                route.name,
+                config.health_check_interval_ms,
+                config.health_check_failures
+            );
+        }
+        if let Some(mirror) = &route.mirror {
+            tracing::info!(
+                "{} mirror: {} for {}% of requests up to {} bytes",
+                route.name,
+                mirror.upstream,
+                mirror.percentage,
+                mirror.max_body_bytes
+            );
+        }
+    }
+    if let Some(name) = &config.default_route {
+        tracing::info!("default route: {name}");
+    }
     tracing::info!("auth upstream (gRPC): {}", config.auth_upstream);
-    tracing::info!("max req/sec: {}", config.max_req_per_sec);
+    if let Some(limit) = &config.rate_limit {
+        tracing::info!("rate limit: {} req/s per client IP, burst {}", limit.rps, limit.burst());
+    }
     tracing::info!("max body size: {} bytes", config.max_body_size);
     tracing::info!("connection timeout: {}s", config.connection_timeout_secs);
     tracing::info!("total connection timeout: {}s", config.total_connection_timeout_secs);
     tracing::info!("read timeout: {}s", config.read_timeout_secs);
     tracing::info!("write timeout: {}s", config.write_timeout_secs);
+    tracing::info!("metrics listen: {}", config.metrics_addr);
+    tracing::info!(
+        "admin listen: {} (token {})",
+        config.admin_addr,
+        if config.admin_token.is_some() { "required" } else { "not set" }
+    );
     tracing::info!("-----------------------------");
 }
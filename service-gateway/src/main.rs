@@ -1,13 +1,17 @@
-use pingora::prelude::{Opt, Server, http_proxy_service};
-use service_gateway::{Gateway, PingoraResult, config::Config, init_tracing, log_config, parse_upstream};
+use pingora::prelude::{Opt, Server};
+use service_gateway::{PingoraResult, add_services, config::Config, init_tracing, log_config};
 use std::sync::Arc;
-use tonic::transport::Endpoint;
 
 fn main() -> PingoraResult<()> {
     dotenvy::dotenv().ok();
-    init_tracing();
 
     let config = Arc::new(Config::from_env());
+    let _log_guard = init_tracing(&config.log);
+    if let Err(e) = config.validate() {
+        // Meant for whoever deploys the gateway, so printed as is rather than as a panic.
+        eprintln!("Invalid gateway configuration: {e}");
+        std::process::exit(1);
+    }
     log_config(&config);
 
     let opt = Opt::parse_args();
@@ -19,22 +23,6 @@ fn main() -> PingoraResult<()> {
     }
     server.bootstrap();
 
-    let auth_grpc_uri = format!("http://{}", config.auth_upstream);
-    let auth_endpoint: Endpoint = auth_grpc_uri.parse().expect("Failed to parse auth upstream as gRPC endpoint");
-
-    let gateway = Gateway::new(
-        parse_upstream(&config.images_upstream),
-        parse_upstream(&config.chats_upstream),
-        parse_upstream(&config.channels_upstream),
-        parse_upstream(&config.calls_upstream),
-        parse_upstream(&config.auth_upstream),
-        auth_endpoint,
-        Arc::clone(&config),
-    );
-
-    let mut lb = http_proxy_service(&server.configuration, gateway);
-    lb.add_tcp(&config.listen_addr);
-
-    server.add_service(lb);
+    add_services(&mut server, config);
     server.run_forever();
 }
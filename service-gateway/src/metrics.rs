@@ -0,0 +1,71 @@
+use prometheus::{HistogramVec, IntCounterVec, register_histogram_vec, register_int_counter_vec};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static UPSTREAM_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gateway_upstream_requests_total",
+        "Requests sent to an upstream route or its mirror, by response status",
+        &["route", "mirror", "status"]
+    )
+    .expect("gateway_upstream_requests_total is registered once")
+});
+
+static UPSTREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "gateway_upstream_request_duration_seconds",
+        "Time from accepting a request to its upstream or mirror response",
+        &["route", "mirror"]
+    )
+    .expect("gateway_upstream_request_duration_seconds is registered once")
+});
+
+static UPSTREAM_RETRIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gateway_upstream_retries_total",
+        "Requests sent to another replica of a route after a failed attempt",
+        &["route"]
+    )
+    .expect("gateway_upstream_retries_total is registered once")
+});
+
+static CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gateway_cache_lookups_total",
+        "Cacheable requests answered from the response cache (hit) or sent upstream (miss)",
+        &["result"]
+    )
+    .expect("gateway_cache_lookups_total is registered once")
+});
+
+static RATE_LIMITED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gateway_rate_limited_total",
+        "Requests refused with 429 because their client IP was over the route's limit",
+        &["route"]
+    )
+    .expect("gateway_rate_limited_total is registered once")
+});
+
+/// `status` is the response code, or what kept the request from getting one.
+pub(crate) fn record_upstream(route: &str, mirror: bool, status: &str, elapsed: Option<Duration>) {
+    let mirror = if mirror { "true" } else { "false" };
+    UPSTREAM_REQUESTS.with_label_values(&[route, mirror, status]).inc();
+    if let Some(elapsed) = elapsed {
+        UPSTREAM_DURATION
+            .with_label_values(&[route, mirror])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+pub(crate) fn record_retry(route: &str) {
+    UPSTREAM_RETRIES.with_label_values(&[route]).inc();
+}
+
+pub(crate) fn record_cache_lookup(hit: bool) {
+    CACHE_LOOKUPS.with_label_values(&[if hit { "hit" } else { "miss" }]).inc();
+}
+
+pub(crate) fn record_rate_limited(route: &str) {
+    RATE_LIMITED.with_label_values(&[route]).inc();
+}
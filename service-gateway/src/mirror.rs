@@ -0,0 +1,252 @@
+use crate::config::MirrorConfig;
+use crate::metrics;
+use crate::upstream::read_status;
+use bytes::BytesMut;
+use http::request::Parts;
+use pingora::prelude::RequestHeader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+
+/// Marks the copies, so the shadow upstream can tell them from real traffic.
+pub const MIRROR_HEADER: &str = "X-Gateway-Mirror";
+
+/// Copies in flight per route; further ones are dropped instead of queueing behind a slow mirror.
+const MAX_IN_FLIGHT: usize = 256;
+
+/// Hop-by-hop headers, plus the framing that is rewritten for the buffered body.
+const SKIPPED_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+/// Shadow upstream of one route. Copies are sent once the client has its response and their outcome only
+/// shows up in metrics labelled `mirror="true"`.
+pub struct Mirror {
+    pub route: String,
+    addr: SocketAddr,
+    percentage: u64,
+    max_body_bytes: usize,
+    timeout: Duration,
+    seen: AtomicU64,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Mirror {
+    pub fn new(route: String, addr: SocketAddr, config: &MirrorConfig, timeout: Duration) -> Self {
+        Self {
+            route,
+            addr,
+            percentage: u64::from(config.percentage.min(100)),
+            max_body_bytes: config.max_body_bytes,
+            timeout,
+            seen: AtomicU64::new(0),
+            in_flight: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+        }
+    }
+
+    /// Starts a copy of `req` if it is picked; upgrades and bodies declared over the cap are never mirrored.
+    pub fn capture(self: &Arc<Self>, req: &RequestHeader) -> Option<MirrorRequest> {
+        if req.headers.contains_key(http::header::UPGRADE) {
+            return None;
+        }
+        let content_length = req
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+        if content_length.is_some_and(|len| len > self.max_body_bytes) || !self.sample() {
+            return None;
+        }
+        let chunked = req.headers.contains_key(http::header::TRANSFER_ENCODING);
+        Some(MirrorRequest {
+            mirror: Arc::clone(self),
+            parts: None,
+            body: BytesMut::new(),
+            expected_len: content_length.or(if chunked { None } else { Some(0) }),
+            ended: false,
+        })
+    }
+
+    /// Picks `percentage` of every 100 requests, spread evenly rather than in bursts.
+    fn sample(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+        (n + 1) * self.percentage / 100 > n * self.percentage / 100
+    }
+}
+
+/// A copy of one request, filled in as it is proxied.
+pub struct MirrorRequest {
+    mirror: Arc<Mirror>,
+    parts: Option<Parts>,
+    body: BytesMut,
+    /// `None` for chunked bodies, which are only complete once their end is seen.
+    expected_len: Option<usize>,
+    ended: bool,
+}
+
+impl MirrorRequest {
+    /// Records the request as sent to the primary upstream, identity headers included.
+    pub fn set_header(&mut self, header: &RequestHeader) {
+        self.parts = Some(header.as_owned_parts());
+    }
+
+    /// Buffers a body chunk; `false` once the body outgrows the cap and the copy has to be dropped.
+    pub fn push_body(&mut self, chunk: Option<&[u8]>, end_of_stream: bool) -> bool {
+        if let Some(chunk) = chunk {
+            if self.body.len() + chunk.len() > self.mirror.max_body_bytes {
+                return false;
+            }
+            self.body.extend_from_slice(chunk);
+        }
+        self.ended |= end_of_stream;
+        true
+    }
+
+    /// Sends the copy in the background if the whole request was captured.
+    pub fn dispatch(self) {
+        let Some(parts) = self.parts else { return };
+        if !self.ended && self.expected_len != Some(self.body.len()) {
+            tracing::debug!(route = %self.mirror.route, "Request body incomplete, not mirroring");
+            return;
+        }
+        let mirror = self.mirror;
+        let Ok(permit) = Arc::clone(&mirror.in_flight).try_acquire_owned() else {
+            metrics::record_upstream(&mirror.route, true, "dropped", None);
+            return;
+        };
+        let request = encode(&parts, mirror.addr, &self.body);
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let status = match tokio::time::timeout(mirror.timeout, send(mirror.addr, &request)).await {
+                Ok(Ok(status)) => status.to_string(),
+                Ok(Err(e)) => {
+                    tracing::debug!(route = %mirror.route, addr = %mirror.addr, "Mirror request failed: {e}");
+                    "error".to_owned()
+                }
+                Err(_) => "timeout".to_owned(),
+            };
+            metrics::record_upstream(&mirror.route, true, &status, Some(started.elapsed()));
+            drop(permit);
+        });
+    }
+}
+
+/// HTTP/1.1 request with the buffered body and `Connection: close`.
+fn encode(parts: &Parts, addr: SocketAddr, body: &[u8]) -> Vec<u8> {
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut out = format!("{} {path} HTTP/1.1\r\n", parts.method).into_bytes();
+    for (name, value) in parts
+        .headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+    {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    if !parts.headers.contains_key(http::header::HOST) {
+        out.extend_from_slice(format!("host: {addr}\r\n").as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "content-length: {}\r\nconnection: close\r\n{}: 1\r\n\r\n",
+            body.len(),
+            MIRROR_HEADER.to_ascii_lowercase()
+        )
+        .as_bytes(),
+    );
+    out.extend_from_slice(body);
+    out
+}
+
+async fn send(addr: SocketAddr, request: &[u8]) -> std::io::Result<u16> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(request).await?;
+    read_status(&mut stream).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror(percentage: u16, max_body_bytes: usize) -> Arc<Mirror> {
+        let config = MirrorConfig {
+            upstream: "127.0.0.1:1".into(),
+            percentage,
+            max_body_bytes,
+        };
+        Arc::new(Mirror::new(
+            "images".into(),
+            SocketAddr::from(([127, 0, 0, 1], 1)),
+            &config,
+            Duration::from_secs(1),
+        ))
+    }
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> RequestHeader {
+        let mut req = RequestHeader::build(method, b"/images/x?size=64", None).unwrap();
+        for (name, value) in headers {
+            req.insert_header(name.to_string(), *value).unwrap();
+        }
+        req
+    }
+
+    #[test]
+    fn sampling_is_spread_evenly() {
+        let picks = |percentage| {
+            let mirror = mirror(percentage, 0);
+            (0..100).filter(|_| mirror.sample()).count()
+        };
+        assert_eq!(picks(0), 0);
+        assert_eq!(picks(25), 25);
+        assert_eq!(picks(100), 100);
+
+        let mirror = mirror(50, 0);
+        let pattern: Vec<_> = (0..4).map(|_| mirror.sample()).collect();
+        assert_eq!(pattern, [false, true, false, true]);
+    }
+
+    #[test]
+    fn oversized_and_upgrade_requests_are_skipped() {
+        let mirror = mirror(100, 4);
+        assert!(mirror.capture(&request("POST", &[("Content-Length", "5")])).is_none());
+        assert!(mirror.capture(&request("GET", &[("Upgrade", "websocket")])).is_none());
+
+        let mut copy = mirror.capture(&request("POST", &[("Transfer-Encoding", "chunked")])).unwrap();
+        assert!(copy.push_body(Some(b"abc"), false));
+        assert!(!copy.push_body(Some(b"de"), true));
+    }
+
+    #[test]
+    fn copy_reframes_the_body() {
+        let req = request(
+            "POST",
+            &[
+                ("Host", "api.example.com"),
+                ("Transfer-Encoding", "chunked"),
+                ("X-User-Id", "42"),
+            ],
+        );
+        let encoded = String::from_utf8(encode(&req.as_owned_parts(), SocketAddr::from(([127, 0, 0, 1], 1)), b"hello")).unwrap();
+
+        assert!(encoded.starts_with("POST /images/x?size=64 HTTP/1.1\r\n"));
+        assert!(encoded.contains("host: api.example.com\r\n"));
+        assert!(encoded.contains("x-user-id: 42\r\n"));
+        assert!(encoded.contains("content-length: 5\r\n"));
+        assert!(encoded.contains("x-gateway-mirror: 1\r\n"));
+        assert!(!encoded.contains("transfer-encoding"));
+        assert!(encoded.ends_with("\r\n\r\nhello"));
+    }
+}
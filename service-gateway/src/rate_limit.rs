@@ -0,0 +1,67 @@
+use ipnet::IpNet;
+use serde::Deserialize;
+use service_common::rate_limit::RateLimiter;
+use std::net::IpAddr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Requests per second each client IP may sustain.
+    pub rps: u32,
+    /// Requests a client IP may send at once after being idle; `rps` when unset.
+    pub burst: Option<u32>,
+}
+
+impl RateLimitConfig {
+    pub fn burst(&self) -> u32 {
+        self.burst.unwrap_or(self.rps)
+    }
+
+    /// A bucket per client IP.
+    pub fn limiter(&self) -> RateLimiter<IpAddr> {
+        RateLimiter::new(self.burst(), self.rps, Duration::from_secs(1))
+    }
+}
+
+/// The client a request is from: `peer`, or when `peer` is a trusted proxy, the last `X-Forwarded-For`
+/// hop that is not one, so clients cannot pick their own address by prepending to the header.
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpNet]) -> IpAddr {
+    let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !trusted(&peer) {
+        return peer;
+    }
+    let mut client = peer;
+    for hop in forwarded_for.into_iter().flat_map(|v| v.rsplit(',')) {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_from_proxies() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()];
+        let header = Some("198.51.100.1, 203.0.113.7, 10.0.0.2");
+        assert_eq!(client_ip(ip("192.0.2.1"), header, &trusted), ip("192.0.2.1"));
+        assert_eq!(client_ip(ip("10.0.0.1"), header, &trusted), ip("203.0.113.7"));
+        assert_eq!(client_ip(ip("::1"), Some("203.0.113.7"), &trusted), ip("203.0.113.7"));
+        assert_eq!(client_ip(ip("10.0.0.1"), None, &trusted), ip("10.0.0.1"));
+        assert_eq!(client_ip(ip("10.0.0.1"), Some("10.0.0.3"), &trusted), ip("10.0.0.3"));
+        assert_eq!(client_ip(ip("10.0.0.1"), Some("garbage, 10.0.0.3"), &trusted), ip("10.0.0.3"));
+        assert_eq!(client_ip(ip("10.0.0.1"), Some("203.0.113.7"), &[]), ip("10.0.0.1"));
+    }
+}
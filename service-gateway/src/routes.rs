@@ -0,0 +1,306 @@
+use crate::config::{Config, RouteConfig};
+use crate::mirror::Mirror;
+use crate::rate_limit::RateLimitConfig;
+use crate::upstream::UpstreamPool;
+use http::Uri;
+use http::uri::PathAndQuery;
+use pingora::prelude::RequestHeader;
+use service_common::rate_limit::RateLimiter;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A route of the table with its replicas, picked for a request by [`RouteTable::find`].
+pub struct Route {
+    pub name: String,
+    host: Option<String>,
+    prefix: Option<String>,
+    rewrite_prefix: Option<String>,
+    pub pool: Arc<UpstreamPool>,
+    pub mirror: Option<Arc<Mirror>>,
+    pub connection_timeout: Duration,
+    pub total_connection_timeout: Duration,
+    pub max_retries: u32,
+    /// Per client IP limit, from the route's `rate_limit` or `GATEWAY_RATE_LIMIT_*`.
+    pub limiter: Option<RateLimiter<IpAddr>>,
+}
+
+impl Route {
+    fn new(config: &RouteConfig, defaults: &Config) -> Self {
+        let mirror_timeout = Duration::from_secs(defaults.total_connection_timeout_secs + defaults.read_timeout_secs);
+        let mirror = config.mirror.as_ref().map(|mirror| {
+            Arc::new(Mirror::new(
+                config.name.clone(),
+                crate::parse_upstream(&mirror.upstream),
+                mirror,
+                mirror_timeout,
+            ))
+        });
+        Self {
+            name: config.name.clone(),
+            host: config.host.clone(),
+            prefix: config.prefix.clone(),
+            rewrite_prefix: config.rewrite_prefix.clone(),
+            pool: Arc::new(
+                UpstreamPool::new(
+                    config.name.clone(),
+                    config.upstreams.iter().copied(),
+                    config.health_path.clone(),
+                )
+                .with_strategy(config.strategy),
+            ),
+            mirror,
+            connection_timeout: Duration::from_secs(config.connection_timeout_secs.unwrap_or(defaults.connection_timeout_secs)),
+            total_connection_timeout: Duration::from_secs(
+                config
+                    .total_connection_timeout_secs
+                    .unwrap_or(defaults.total_connection_timeout_secs),
+            ),
+            max_retries: config.max_retries.unwrap_or(defaults.max_retries),
+            limiter: config
+                .rate_limit
+                .or(defaults.rate_limit)
+                .as_ref()
+                .map(RateLimitConfig::limiter),
+        }
+    }
+
+    fn matches(&self, host: Option<&str>, path: &str) -> bool {
+        let host_matches = match (&self.host, host) {
+            (None, _) => true,
+            (Some(expected), Some(host)) => expected.eq_ignore_ascii_case(host),
+            (Some(_), None) => false,
+        };
+        host_matches
+            && self
+                .prefix
+                .as_deref()
+                .is_none_or(|prefix| strip_prefix(path, prefix).is_some())
+    }
+
+    /// Host and path routes first, then path-only, then host-only; the longest prefix within each.
+    fn specificity(&self) -> (bool, bool, usize, bool) {
+        let prefix_len = self.prefix.as_deref().map_or(0, |p| p.trim_end_matches('/').len());
+        (
+            self.host.is_some() && self.prefix.is_some(),
+            self.prefix.is_some(),
+            prefix_len,
+            self.host.is_some(),
+        )
+    }
+
+    /// `uri` as sent upstream: with `rewrite_prefix` in place of the matched prefix, when the route has one.
+    pub fn rewrite(&self, uri: &Uri) -> Result<Option<Uri>, http::Error> {
+        let Some(replacement) = &self.rewrite_prefix else {
+            return Ok(None);
+        };
+        let path_and_query = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+        let Some(path) = rewrite_path(path_and_query, self.prefix.as_deref().unwrap_or(""), replacement) else {
+            return Ok(None);
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(PathAndQuery::try_from(path)?);
+        Ok(Some(Uri::from_parts(parts)?))
+    }
+}
+
+/// The rest of `path` after `prefix`, if `prefix` covers whole segments of it; a trailing `/` on `prefix` is ignored.
+pub(crate) fn strip_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix.trim_end_matches('/'))?;
+    (rest.is_empty() || rest.starts_with(['/', '?'])).then_some(rest)
+}
+
+/// `path_and_query` with `prefix` replaced by `replacement`; the rest, query included, is kept byte for byte
+/// so percent-encoding reaches the upstream unchanged.
+fn rewrite_path(path_and_query: &str, prefix: &str, replacement: &str) -> Option<String> {
+    let rest = strip_prefix(path_and_query, prefix)?;
+    let path = format!("{}{rest}", replacement.trim_end_matches('/'));
+    Some(if path.is_empty() || path.starts_with('?') {
+        format!("/{path}")
+    } else {
+        path
+    })
+}
+
+/// Routes of `Config::routes`, matched on host and path prefix.
+pub struct RouteTable {
+    routes: Vec<Arc<Route>>,
+    default: Option<Arc<Route>>,
+}
+
+impl RouteTable {
+    pub fn new(config: &Config) -> Self {
+        let routes: Vec<_> = config.routes.iter().map(|r| Arc::new(Route::new(r, config))).collect();
+        let default = config
+            .default_route
+            .as_ref()
+            .and_then(|name| routes.iter().find(|r| &r.name == name))
+            .cloned();
+        Self { routes, default }
+    }
+
+    /// The most specific matching route (see [`Route::specificity`]), the earlier one on ties; the default
+    /// route when none matches.
+    pub fn find(&self, host: Option<&str>, path: &str) -> Option<&Arc<Route>> {
+        self.routes
+            .iter()
+            .rev()
+            .filter(|r| r.matches(host, path))
+            .max_by_key(|r| r.specificity())
+            .or(self.default.as_ref())
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = &Arc<Route>> {
+        self.routes.iter()
+    }
+}
+
+/// Host the request is for, without port: the `Host` header, or the authority of HTTP/2 requests.
+pub fn request_host(req: &RequestHeader) -> Option<&str> {
+    let host = req
+        .headers
+        .get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri.authority().map(|a| a.as_str()))?;
+    if host.starts_with('[') {
+        return host.split_inclusive(']').next();
+    }
+    Some(host.rsplit_once(':').map_or(host, |(name, _)| name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn route(name: &str, host: Option<&str>, prefix: Option<&str>) -> RouteConfig {
+        RouteConfig {
+            name: name.into(),
+            host: host.map(String::from),
+            prefix: prefix.map(String::from),
+            upstreams: vec![SocketAddr::from(([127, 0, 0, 1], 1))],
+            ..RouteConfig::default()
+        }
+    }
+
+    fn table(routes: Vec<RouteConfig>, default_route: Option<&str>) -> RouteTable {
+        let mut config = crate::config::tests::config("127.0.0.1:9092", None);
+        config.routes = routes;
+        config.default_route = default_route.map(String::from);
+        RouteTable::new(&config)
+    }
+
+    fn find<'a>(table: &'a RouteTable, host: Option<&str>, path: &str) -> Option<&'a str> {
+        table.find(host, path).map(|r| r.name.as_str())
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let table = table(
+            vec![
+                route("images", None, Some("/images")),
+                route("thumbnails", None, Some("/images/thumbs")),
+                route("chats", None, Some("/ws")),
+            ],
+            None,
+        );
+        assert_eq!(find(&table, None, "/images/a.png"), Some("images"));
+        assert_eq!(find(&table, None, "/images/thumbs/a.png"), Some("thumbnails"));
+        assert_eq!(find(&table, Some("api.example.com"), "/ws/room"), Some("chats"));
+        assert_eq!(find(&table, None, "/channels"), None);
+        assert_eq!(find(&table, None, "/images"), Some("images"));
+        assert_eq!(find(&table, None, "/imagesets/a.png"), None);
+    }
+
+    #[test]
+    fn host_and_path_beat_path_beat_host() {
+        let table = table(
+            vec![
+                route("images", None, Some("/images")),
+                route("thumbnails", None, Some("/images/thumbs")),
+                route("cdn", Some("cdn.example.com"), Some("/images")),
+                route("web", Some("app.example.com"), None),
+                route("fallback", None, None),
+            ],
+            Some("fallback"),
+        );
+        assert_eq!(find(&table, Some("CDN.example.com"), "/images/a.png"), Some("cdn"));
+        assert_eq!(find(&table, Some("cdn.example.com"), "/images/thumbs/a.png"), Some("cdn"));
+        assert_eq!(find(&table, Some("cdn.example.com"), "/"), Some("fallback"));
+        assert_eq!(find(&table, Some("app.example.com"), "/images/a.png"), Some("images"));
+        assert_eq!(find(&table, Some("app.example.com"), "/"), Some("web"));
+        assert_eq!(find(&table, None, "/"), Some("fallback"));
+    }
+
+    #[test]
+    fn rewrite_replaces_whole_segments() {
+        let cases = [
+            ("/api/py/users?page=2", "/api/py", "/", Some("/users?page=2")),
+            ("/api/py", "/api/py", "/", Some("/")),
+            ("/api/py?page=2", "/api/py/", "/", Some("/?page=2")),
+            (
+                "/api/py/a%20b%2Fc?q=%C3%A9&x",
+                "/api/py",
+                "/v2/",
+                Some("/v2/a%20b%2Fc?q=%C3%A9&x"),
+            ),
+            ("/api/python/users", "/api/py", "/", None),
+            // A root prefix matches every path, so the replacement is prepended.
+            ("/users?page=2", "/", "/v1", Some("/v1/users?page=2")),
+            ("/", "/", "/", Some("/")),
+            ("/", "/", "/v1", Some("/v1/")),
+            ("/users", "", "/v1", Some("/v1/users")),
+        ];
+        for (path, prefix, replacement, expected) in cases {
+            assert_eq!(
+                rewrite_path(path, prefix, replacement).as_deref(),
+                expected,
+                "{path} {prefix} {replacement}"
+            );
+        }
+    }
+
+    #[test]
+    fn rewrite_keeps_the_rest_of_the_uri() {
+        let mut py = route("py", None, Some("/api/py"));
+        py.rewrite_prefix = Some("/".into());
+        let table = table(vec![py, route("images", None, Some("/images"))], None);
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+
+        let py = table.find(None, "/api/py/users").unwrap();
+        assert_eq!(py.rewrite(&uri("/api/py/users?page=2")).unwrap(), Some(uri("/users?page=2")));
+        assert_eq!(
+            py.rewrite(&uri("http://api.example.com/api/py/users")).unwrap(),
+            Some(uri("http://api.example.com/users"))
+        );
+        let images = table.find(None, "/images/a.png").unwrap();
+        assert_eq!(images.rewrite(&uri("/images/a.png")).unwrap(), None);
+    }
+
+    #[test]
+    fn timeouts_default_to_the_global_ones() {
+        let mut slow = route("images", None, Some("/images"));
+        slow.total_connection_timeout_secs = Some(30);
+        let table = table(vec![slow, route("chats", None, Some("/ws"))], None);
+        let images = table.find(None, "/images").unwrap();
+        assert_eq!(images.connection_timeout, Duration::from_secs(1));
+        assert_eq!(images.total_connection_timeout, Duration::from_secs(30));
+        assert_eq!(
+            table.find(None, "/ws").unwrap().total_connection_timeout,
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn request_host_drops_the_port() {
+        let request = |host: &str| {
+            let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+            req.insert_header("Host", host).unwrap();
+            req
+        };
+        assert_eq!(request_host(&request("api.example.com:8080")), Some("api.example.com"));
+        assert_eq!(request_host(&request("api.example.com")), Some("api.example.com"));
+        assert_eq!(request_host(&request("[::1]:8080")), Some("[::1]"));
+        assert_eq!(request_host(&RequestHeader::build("GET", b"/", None).unwrap()), None);
+    }
+}
@@ -0,0 +1,412 @@
+use async_trait::async_trait;
+use pingora::server::ShutdownWatch;
+use pingora::services::background::BackgroundService;
+use serde::Deserialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How a pool picks among its ready replicas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    #[default]
+    RoundRobin,
+    /// The replica with the fewest requests in flight, round-robin among equals.
+    LeastConnections,
+    /// The same replica for a client IP while the ready set is unchanged, for sticky websocket sessions.
+    /// Clients without an IP address are spread round-robin.
+    IpHash,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(Self::RoundRobin),
+            "least_connections" => Ok(Self::LeastConnections),
+            "ip_hash" => Ok(Self::IpHash),
+            _ => Err(format!(
+                "unknown strategy {s}, expected round_robin, least_connections or ip_hash"
+            )),
+        }
+    }
+}
+
+struct Replica {
+    addr: SocketAddr,
+    ready: AtomicBool,
+    failed_checks: AtomicU32,
+    in_flight: AtomicUsize,
+}
+
+/// Replicas of one upstream service, picked by the pool's [`Strategy`] among those that are ready.
+///
+/// With a check path, replicas start out of rotation and only join once [`HealthChecker`] sees
+/// `200` on that path, leaving it again after a run of failed checks; without one they are always eligible.
+pub struct UpstreamPool {
+    pub name: String,
+    replicas: Vec<Replica>,
+    check_path: Option<String>,
+    strategy: Strategy,
+    next: AtomicUsize,
+}
+
+impl UpstreamPool {
+    pub fn new(name: impl Into<String>, addrs: impl IntoIterator<Item = SocketAddr>, check_path: Option<String>) -> Self {
+        let ready = check_path.is_none();
+        Self {
+            name: name.into(),
+            replicas: addrs
+                .into_iter()
+                .map(|addr| Replica {
+                    addr,
+                    ready: AtomicBool::new(ready),
+                    failed_checks: AtomicU32::new(0),
+                    in_flight: AtomicUsize::new(0),
+                })
+                .collect(),
+            check_path,
+            strategy: Strategy::default(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.replicas.iter().map(|r| r.addr)
+    }
+
+    pub fn check_path(&self) -> Option<&str> {
+        self.check_path.as_deref()
+    }
+
+    /// Ready replica for a request from `client`, or `None` while every replica is warming up or failing
+    /// its check. Replicas in `tried` are only picked again once every other one has been.
+    pub fn select(&self, client: Option<IpAddr>, tried: &[SocketAddr]) -> Option<SocketAddr> {
+        self.pick(client, |r| !tried.contains(&r.addr))
+            .or_else(|| self.pick(client, |_| true))
+    }
+
+    fn pick(&self, client: Option<IpAddr>, eligible: impl Fn(&Replica) -> bool) -> Option<SocketAddr> {
+        let is_ready = |r: &&Replica| r.ready.load(Ordering::Acquire) && eligible(r);
+        if let (Strategy::IpHash, Some(client)) = (self.strategy, client) {
+            // Rendezvous hashing: a replica leaving rotation only moves the clients that were on it.
+            return self
+                .replicas
+                .iter()
+                .filter(is_ready)
+                .max_by_key(|r| affinity(client, r.addr))
+                .map(|r| r.addr);
+        }
+        let count = self.replicas.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut rotation = (0..count).map(|i| &self.replicas[(start + i) % count]).filter(is_ready);
+        match self.strategy {
+            Strategy::LeastConnections => rotation.min_by_key(|r| r.in_flight.load(Ordering::Relaxed)),
+            Strategy::RoundRobin | Strategy::IpHash => rotation.next(),
+        }
+        .map(|r| r.addr)
+    }
+
+    pub fn has_ready(&self) -> bool {
+        self.replicas.iter().any(|r| r.ready.load(Ordering::Acquire))
+    }
+
+    /// Counts a request as in flight on `addr` until the returned guard is dropped.
+    pub fn start_request(self: &Arc<Self>, addr: SocketAddr) -> InFlight {
+        if let Some(replica) = self.replicas.iter().find(|r| r.addr == addr) {
+            replica.in_flight.fetch_add(1, Ordering::Relaxed);
+        }
+        InFlight {
+            pool: Arc::clone(self),
+            addr,
+        }
+    }
+
+    pub fn in_flight(&self, addr: SocketAddr) -> usize {
+        self.replicas
+            .iter()
+            .find(|r| r.addr == addr)
+            .map_or(0, |r| r.in_flight.load(Ordering::Relaxed))
+    }
+
+    pub fn is_ready(&self, addr: SocketAddr) -> bool {
+        self.replicas
+            .iter()
+            .any(|r| r.addr == addr && r.ready.load(Ordering::Acquire))
+    }
+
+    /// Consecutive failed checks of the replica since its last successful one.
+    pub fn failed_checks(&self, addr: SocketAddr) -> u32 {
+        self.replicas
+            .iter()
+            .find(|r| r.addr == addr)
+            .map_or(0, |r| r.failed_checks.load(Ordering::Relaxed))
+    }
+
+    /// Applies a check outcome: a success puts the replica in rotation, and it leaves once `max_failures`
+    /// checks in a row have failed, so a single slow answer does not eject it.
+    pub fn record_check(&self, addr: SocketAddr, passed: bool, max_failures: u32) {
+        for replica in self.replicas.iter().filter(|r| r.addr == addr) {
+            if passed {
+                replica.failed_checks.store(0, Ordering::Relaxed);
+            } else if replica.failed_checks.fetch_add(1, Ordering::Relaxed) + 1 < max_failures {
+                continue;
+            }
+            self.set_ready(addr, passed);
+        }
+    }
+
+    pub fn set_ready(&self, addr: SocketAddr, ready: bool) {
+        for replica in self.replicas.iter().filter(|r| r.addr == addr) {
+            if replica.ready.swap(ready, Ordering::AcqRel) != ready {
+                if ready {
+                    tracing::info!(upstream = %self.name, %addr, "Replica is ready, adding it to rotation");
+                } else {
+                    tracing::warn!(upstream = %self.name, %addr, "Replica is not ready, removing it from rotation");
+                }
+            }
+        }
+    }
+}
+
+fn affinity(client: IpAddr, replica: SocketAddr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (client, replica).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A request proxied to one replica, counted by [`Strategy::LeastConnections`].
+pub struct InFlight {
+    pool: Arc<UpstreamPool>,
+    addr: SocketAddr,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(replica) = self.pool.replicas.iter().find(|r| r.addr == self.addr) {
+            replica.in_flight.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Polls the check path of every pool that has one and updates replica readiness.
+pub struct HealthChecker {
+    pools: Vec<Arc<UpstreamPool>>,
+    interval: Duration,
+    timeout: Duration,
+    max_failures: u32,
+}
+
+impl HealthChecker {
+    pub fn new(pools: Vec<Arc<UpstreamPool>>, interval: Duration, timeout: Duration, max_failures: u32) -> Self {
+        Self {
+            pools: pools.into_iter().filter(|p| p.check_path().is_some()).collect(),
+            interval,
+            timeout,
+            max_failures: max_failures.max(1),
+        }
+    }
+
+    pub async fn check_all(&self) {
+        for pool in &self.pools {
+            let Some(path) = pool.check_path() else { continue };
+            for addr in pool.addrs() {
+                let ready = match tokio::time::timeout(self.timeout, probe(addr, path)).await {
+                    Ok(Ok(status)) => status == 200,
+                    Ok(Err(e)) => {
+                        tracing::debug!(upstream = %pool.name, %addr, "Readiness check failed: {e}");
+                        false
+                    }
+                    Err(_) => false,
+                };
+                pool.record_check(addr, ready, self.max_failures);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BackgroundService for HealthChecker {
+    async fn start(&self, mut shutdown: ShutdownWatch) {
+        if self.pools.is_empty() {
+            return;
+        }
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => break,
+                _ = ticker.tick() => self.check_all().await,
+            }
+        }
+    }
+}
+
+/// Status code of a plain `GET path` against `addr`.
+async fn probe(addr: SocketAddr, path: &str) -> std::io::Result<u16> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    read_status(&mut stream).await
+}
+
+/// Status code from the start of a response, ignoring the rest of it.
+pub(crate) async fn read_status(stream: &mut TcpStream) -> std::io::Result<u16> {
+    let mut head = [0u8; 32];
+    let mut read = 0;
+    while read < 12 {
+        match stream.read(&mut head[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    parse_status(&head[..read]).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed status line"))
+}
+
+fn parse_status(head: &[u8]) -> Option<u16> {
+    let line = std::str::from_utf8(head).ok()?;
+    let mut parts = line.strip_prefix("HTTP/1.")?.split(' ');
+    parts.next()?;
+    parts.next()?.get(..3)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn checked_replicas_start_out_of_rotation() {
+        let pool = UpstreamPool::new("images", [addr(1), addr(2)], Some("/health/ready".into()));
+        assert_eq!(pool.select(None, &[]), None);
+
+        pool.set_ready(addr(2), true);
+        assert!((0..4).all(|_| pool.select(None, &[]) == Some(addr(2))));
+    }
+
+    #[test]
+    fn unchecked_replicas_rotate() {
+        let pool = UpstreamPool::new("images", [addr(1), addr(2)], None);
+        let picks: Vec<_> = (0..4).map(|_| pool.select(None, &[]).unwrap().port()).collect();
+        assert_eq!(picks, [1, 2, 1, 2]);
+    }
+
+    fn spread(picks: impl IntoIterator<Item = SocketAddr>) -> Vec<usize> {
+        let mut counts = vec![0; 3];
+        for pick in picks {
+            counts[usize::from(pick.port()) - 1] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn retries_avoid_tried_replicas() {
+        let pool = UpstreamPool::new("images", [addr(1), addr(2), addr(3)], None);
+        for _ in 0..6 {
+            assert_ne!(pool.select(None, &[addr(1)]), Some(addr(1)));
+        }
+        assert_eq!(pool.select(None, &[addr(1), addr(3)]), Some(addr(2)));
+        assert!(pool.select(None, &[addr(1), addr(2), addr(3)]).is_some());
+
+        let sticky = UpstreamPool::new("chats", [addr(1), addr(2), addr(3)], None).with_strategy(Strategy::IpHash);
+        let client = IpAddr::from([10, 0, 0, 1]);
+        let first = sticky.select(Some(client), &[]).unwrap();
+        let second = sticky.select(Some(client), &[first]).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(sticky.select(Some(client), &[first]), Some(second));
+    }
+
+    #[test]
+    fn round_robin_is_fair() {
+        let pool = UpstreamPool::new("images", [addr(1), addr(2), addr(3)], None);
+        assert_eq!(spread((0..1000).map(|_| pool.select(None, &[]).unwrap())), [334, 333, 333]);
+    }
+
+    #[test]
+    fn least_connections_favours_idle_replicas() {
+        let pool =
+            Arc::new(UpstreamPool::new("images", [addr(1), addr(2), addr(3)], None).with_strategy(Strategy::LeastConnections));
+        let held: Vec<_> = (0..1000)
+            .map(|_| pool.start_request(pool.select(None, &[]).unwrap()))
+            .collect();
+        assert_eq!(spread(held.iter().map(|r| r.addr)), [334, 333, 333]);
+
+        let busy = pool.start_request(addr(1));
+        drop(held);
+        assert_eq!(pool.in_flight(addr(1)), 1);
+        assert!((0..10).all(|_| pool.select(None, &[]) != Some(addr(1))));
+        drop(busy);
+        assert_eq!(pool.in_flight(addr(1)), 0);
+    }
+
+    #[test]
+    fn ip_hash_is_sticky() {
+        let pool = UpstreamPool::new("chats", [addr(1), addr(2), addr(3)], None).with_strategy(Strategy::IpHash);
+        let clients: Vec<IpAddr> = (0..1000u32).map(|i| IpAddr::from((0x0a00_0000 + i).to_be_bytes())).collect();
+        let picks: Vec<_> = clients.iter().map(|&c| pool.select(Some(c), &[]).unwrap()).collect();
+        assert!(
+            clients
+                .iter()
+                .zip(&picks)
+                .all(|(&c, &p)| pool.select(Some(c), &[]) == Some(p))
+        );
+        assert!(
+            spread(picks.iter().copied()).iter().all(|&n| (250..420).contains(&n)),
+            "{:?}",
+            spread(picks.clone())
+        );
+
+        // Only the clients of the replica leaving rotation move.
+        pool.set_ready(addr(3), false);
+        for (&client, &pick) in clients.iter().zip(&picks) {
+            let now = pool.select(Some(client), &[]).unwrap();
+            assert!(now == pick || pick == addr(3));
+            assert_ne!(now, addr(3));
+        }
+    }
+
+    #[test]
+    fn replicas_leave_rotation_after_consecutive_failures() {
+        let pool = UpstreamPool::new("images", [addr(1), addr(2)], Some("/health/ready".into()));
+        pool.record_check(addr(1), true, 3);
+        pool.record_check(addr(2), true, 3);
+
+        pool.record_check(addr(1), false, 3);
+        pool.record_check(addr(1), false, 3);
+        assert!(pool.is_ready(addr(1)));
+        pool.record_check(addr(1), true, 3);
+        assert_eq!(pool.failed_checks(addr(1)), 0);
+
+        for _ in 0..3 {
+            pool.record_check(addr(1), false, 3);
+        }
+        assert!(!pool.is_ready(addr(1)));
+        assert_eq!(pool.failed_checks(addr(1)), 3);
+        assert!((0..4).all(|_| pool.select(None, &[]) == Some(addr(2))));
+
+        pool.record_check(addr(1), true, 3);
+        assert!(pool.is_ready(addr(1)));
+    }
+
+    #[test]
+    fn status_line_parsing() {
+        assert_eq!(parse_status(b"HTTP/1.1 200 OK\r\n"), Some(200));
+        assert_eq!(parse_status(b"HTTP/1.0 503 Service Unavailable"), Some(503));
+        assert_eq!(parse_status(b"SSH-2.0-OpenSSH"), None);
+        assert_eq!(parse_status(b"HTTP/1.1 2"), None);
+    }
+}
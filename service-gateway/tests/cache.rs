@@ -0,0 +1,123 @@
+mod common;
+
+use axum::{Router, extract::Path, http::header, routing};
+use common::{ACCESS_TOKEN, Addrs, USER_ID, gateway};
+use service_gateway::{
+    cache::CacheConfig,
+    config::{Config, RouteConfig},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::net::TcpListener;
+
+/// Images upstream counting the requests it gets; `nostore.png` is served with `Cache-Control: no-store` and the
+/// listing without any.
+async fn images(hits: Arc<AtomicUsize>) -> anyhow::Result<SocketAddr> {
+    let listed = Arc::clone(&hits);
+    let router = Router::new()
+        .route(
+            "/images",
+            routing::get(move || async move { format!("listing #{}", listed.fetch_add(1, Ordering::SeqCst) + 1) }),
+        )
+        .route(
+            "/images/{name}",
+            routing::get(move |Path(name): Path<String>| async move {
+                let count = hits.fetch_add(1, Ordering::SeqCst) + 1;
+                let cache_control = if name == "nostore.png" {
+                    "no-store"
+                } else {
+                    "public, max-age=60"
+                };
+                ([(header::CACHE_CONTROL, cache_control)], format!("{name} #{count}"))
+            }),
+        );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+fn config(upstream: SocketAddr) -> anyhow::Result<Config> {
+    Ok(Config {
+        cache: CacheConfig {
+            prefixes: vec!["/images".into()],
+            ..CacheConfig::default()
+        },
+        ..common::config(vec![RouteConfig {
+            name: "images".into(),
+            prefix: Some("/images".into()),
+            upstreams: vec![upstream],
+            ..RouteConfig::default()
+        }])?
+    })
+}
+
+/// `X-Cache` and body of a download.
+async fn download(client: &reqwest::Client, gateway: &str, name: &str) -> anyhow::Result<(String, String)> {
+    let response = client.get(format!("http://{gateway}/images/{name}")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response.headers().contains_key("x-request-id"));
+    let cache = response
+        .headers()
+        .get("x-cache")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    Ok((cache, response.text().await?))
+}
+
+#[tokio::test]
+async fn test_second_download_is_served_from_the_cache() -> anyhow::Result<()> {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let Addrs {
+        listen: gateway, admin, ..
+    } = gateway(config(images(Arc::clone(&hits)).await?)?).await?;
+    let client = reqwest::Client::new();
+    let download = |name: &'static str| download(&client, &gateway, name);
+
+    assert_eq!(download("a.png").await?, ("MISS".into(), "a.png #1".into()));
+    assert_eq!(download("a.png").await?, ("HIT".into(), "a.png #1".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 1, "the hit must not reach the upstream");
+
+    assert_eq!(download("nostore.png").await?, ("MISS".into(), "nostore.png #2".into()));
+    assert_eq!(download("nostore.png").await?, ("MISS".into(), "nostore.png #3".into()));
+
+    let purged: serde_json::Value = client
+        .post(format!("http://{admin}/_proxy/cache/purge?prefix=%2Fimages"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(purged, serde_json::json!({"prefix": "/images", "purged": 1}));
+    assert_eq!(download("a.png").await?, ("MISS".into(), "a.png #4".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+    let response = client.post(format!("http://{admin}/_proxy/cache/purge")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_listing_reaches_the_upstream_on_every_request() -> anyhow::Result<()> {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let config = Config {
+        auth_upstream: common::auth().await?,
+        ..config(images(Arc::clone(&hits)).await?)?
+    };
+    let Addrs { listen: gateway, .. } = gateway(config).await?;
+    let client = reqwest::Client::new();
+
+    for count in 1..=2 {
+        let response = client
+            .get(format!("http://{gateway}/images?user_id={USER_ID}"))
+            .bearer_auth(ACCESS_TOKEN)
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers().get("x-cache").and_then(|v| v.to_str().ok()), None);
+        assert_eq!(response.text().await?, format!("listing #{count}"));
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
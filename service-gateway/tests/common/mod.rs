@@ -0,0 +1,168 @@
+//! Setup shared by the gateway integration tests; each test binary uses part of it.
+#![allow(dead_code)]
+
+use pingora::prelude::Server;
+use pingora::server::RunArgs;
+use service_gateway::{
+    add_services,
+    cache::CacheConfig,
+    config::{Config, RouteConfig},
+    logging::LogConfig,
+    proto::{self, auth_service_server::AuthService, auth_service_server::AuthServiceServer},
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Response, Status};
+
+pub fn free_addr() -> anyhow::Result<String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.to_string())
+}
+
+/// Serves `routes` on free local ports. The auth service is unreachable, so only paths that skip auth can be
+/// proxied unless a test points `auth_upstream` at [`auth`]; rate limits, health checks and the cache are out of the way unless a test sets them.
+pub fn config(routes: Vec<RouteConfig>) -> anyhow::Result<Config> {
+    Ok(Config {
+        listen_addr: free_addr()?,
+        auth_upstream: "127.0.0.1:1".into(),
+        routes,
+        default_route: None,
+        routes_file: None,
+        health_check_interval_ms: 60_000,
+        health_check_failures: 3,
+        rate_limit: None,
+        rate_limit_exempt_paths: Vec::new(),
+        trusted_proxies: Vec::new(),
+        max_body_size: 1024,
+        connection_timeout_secs: 1,
+        total_connection_timeout_secs: 1,
+        read_timeout_secs: 1,
+        write_timeout_secs: 1,
+        max_retries: 1,
+        allowed_origins: Vec::new(),
+        oauth_callback_url: String::new(),
+        frontend_url: String::new(),
+        grace_period_secs: 1,
+        graceful_shutdown_timeout_secs: 1,
+        metrics_addr: free_addr()?,
+        admin_addr: free_addr()?,
+        admin_token: None,
+        cache: CacheConfig::default(),
+        log: LogConfig::default(),
+    })
+}
+
+/// The one access token the [`auth`] stub accepts.
+pub const ACCESS_TOKEN: &str = "test-access-token";
+pub const USER_ID: &str = "0191d6a0-0000-7000-8000-000000000001";
+
+/// Auth service that validates [`ACCESS_TOKEN`] and implements nothing else.
+struct AuthStub;
+
+#[tonic::async_trait]
+impl AuthService for AuthStub {
+    async fn validate_token(
+        &self,
+        request: Request<proto::ValidateTokenRequest>,
+    ) -> Result<Response<proto::ValidateTokenResponse>, Status> {
+        if request.into_inner().access_token != ACCESS_TOKEN {
+            return Err(Status::unauthenticated("invalid token"));
+        }
+        Ok(Response::new(proto::ValidateTokenResponse {
+            user_id: USER_ID.into(),
+            email: "user@example.com".into(),
+            username: "user".into(),
+            ..Default::default()
+        }))
+    }
+
+    async fn register(&self, _: Request<proto::RegisterRequest>) -> Result<Response<proto::RegisterResponse>, Status> {
+        Err(Status::unimplemented("register"))
+    }
+
+    async fn login(&self, _: Request<proto::LoginRequest>) -> Result<Response<proto::LoginResponse>, Status> {
+        Err(Status::unimplemented("login"))
+    }
+
+    async fn refresh_token(&self, _: Request<proto::RefreshTokenRequest>) -> Result<Response<proto::AuthTokens>, Status> {
+        Err(Status::unimplemented("refresh_token"))
+    }
+
+    async fn logout(&self, _: Request<proto::LogoutRequest>) -> Result<Response<()>, Status> {
+        Err(Status::unimplemented("logout"))
+    }
+
+    async fn o_auth_get_auth_url(
+        &self,
+        _: Request<proto::OAuthGetAuthUrlRequest>,
+    ) -> Result<Response<proto::OAuthGetAuthUrlResponse>, Status> {
+        Err(Status::unimplemented("o_auth_get_auth_url"))
+    }
+
+    async fn o_auth_authenticate(
+        &self,
+        _: Request<proto::OAuthAuthenticateRequest>,
+    ) -> Result<Response<proto::AuthTokens>, Status> {
+        Err(Status::unimplemented("o_auth_authenticate"))
+    }
+
+    async fn get_me(&self, _: Request<proto::GetMeRequest>) -> Result<Response<proto::UserProfile>, Status> {
+        Err(Status::unimplemented("get_me"))
+    }
+
+    async fn update_user(&self, _: Request<proto::UpdateUserRequest>) -> Result<Response<proto::UserProfile>, Status> {
+        Err(Status::unimplemented("update_user"))
+    }
+}
+
+/// Serves the auth stub on a free local port for `auth_upstream`, so authenticated routes can be proxied.
+pub async fn auth() -> anyhow::Result<String> {
+    let addr = free_addr()?;
+    let server = tonic::transport::Server::builder()
+        .add_service(AuthServiceServer::new(AuthStub))
+        .serve(addr.parse()?);
+    tokio::spawn(server);
+    wait_for(&addr).await?;
+    Ok(addr)
+}
+
+async fn wait_for(addr: &str) -> anyhow::Result<()> {
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    anyhow::bail!("nothing ever listened on {addr}")
+}
+
+/// Where a started gateway listens.
+pub struct Addrs {
+    pub listen: String,
+    pub metrics: String,
+    pub admin: String,
+}
+
+/// Runs the gateway on its own thread, as `main` would.
+pub fn start(config: Config) -> Addrs {
+    let addrs = Addrs {
+        listen: config.listen_addr.clone(),
+        metrics: config.metrics_addr.clone(),
+        admin: config.admin_addr.clone(),
+    };
+    let config = Arc::new(config);
+    std::thread::spawn(move || {
+        let mut server = Server::new(None).expect("server");
+        server.bootstrap();
+        add_services(&mut server, config);
+        server.run(RunArgs::default());
+    });
+    addrs
+}
+
+/// Runs a gateway and waits until it accepts connections.
+pub async fn gateway(config: Config) -> anyhow::Result<Addrs> {
+    let addrs = start(config);
+    wait_for(&addrs.listen).await?;
+    Ok(addrs)
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::Path,
+    http::{HeaderMap, Method, StatusCode},
+    routing,
+};
+use common::{Addrs, free_addr, gateway};
+use service_gateway::config::{Config, MirrorConfig, RouteConfig};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+const MAX_MIRROR_BODY: usize = 16;
+
+type Response = (u16, Vec<(String, String)>, String);
+
+async fn serve(router: Router) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+/// Primary replica: echoes what it received, including whether it was marked as a mirror copy.
+async fn primary() -> anyhow::Result<SocketAddr> {
+    let router = Router::new().route(
+        "/images/{name}",
+        routing::any(
+            |method: Method, Path(name): Path<String>, headers: HeaderMap, body: Bytes| async move {
+                let mirrored = headers.contains_key("x-gateway-mirror");
+                (
+                    StatusCode::ACCEPTED,
+                    [("x-echo-name", name)],
+                    format!("{method} {} mirrored={mirrored}", String::from_utf8_lossy(&body)),
+                )
+            },
+        ),
+    );
+    serve(router).await
+}
+
+/// Shadow upstream that reports every copy it gets and answers them all with `500`.
+async fn recorder() -> anyhow::Result<(SocketAddr, mpsc::UnboundedReceiver<(String, HeaderMap, Bytes)>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let router = Router::new().fallback(move |uri: axum::http::Uri, headers: HeaderMap, body: Bytes| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send((uri.to_string(), headers, body));
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    });
+    Ok((serve(router).await?, rx))
+}
+
+/// Accepts connections and never answers.
+async fn black_hole() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    Ok(addr)
+}
+
+fn config(images_upstream: SocketAddr, mirror: Option<String>) -> anyhow::Result<Config> {
+    Ok(Config {
+        ..common::config(vec![RouteConfig {
+            name: "images".into(),
+            prefix: Some("/images".into()),
+            upstreams: vec![images_upstream],
+            mirror: mirror.map(|upstream| MirrorConfig {
+                upstream,
+                percentage: 100,
+                max_body_bytes: MAX_MIRROR_BODY,
+            }),
+            ..RouteConfig::default()
+        }])?
+    })
+}
+
+/// Status, headers apart from the per-request ones, and body.
+async fn send(client: &reqwest::Client, gateway: &str, name: &str, body: &str) -> anyhow::Result<Response> {
+    let response = client
+        .get(format!("http://{gateway}/images/{name}"))
+        .body(body.to_owned())
+        .send()
+        .await?;
+    let status = response.status().as_u16();
+    let mut headers: Vec<_> = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "x-request-id" | "date"))
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_owned()))
+        .collect();
+    headers.sort();
+    Ok((status, headers, response.text().await?))
+}
+
+async fn requests(client: &reqwest::Client, gateway: &str) -> anyhow::Result<Vec<Response>> {
+    Ok(vec![
+        send(client, gateway, "empty", "").await?,
+        send(client, gateway, "small", "hello").await?,
+        send(client, gateway, "large", &"x".repeat(MAX_MIRROR_BODY + 1)).await?,
+    ])
+}
+
+/// Lines of the `mirror="true"` request counter.
+async fn mirror_counters(client: &reqwest::Client, metrics: &str) -> anyhow::Result<Vec<String>> {
+    let text = client.get(format!("http://{metrics}/metrics")).send().await?.text().await?;
+    Ok(text
+        .lines()
+        .filter(|line| line.starts_with("gateway_upstream_requests_total{") && line.contains(r#"mirror="true""#))
+        .map(str::to_owned)
+        .collect())
+}
+
+async fn wait_for_counter(client: &reqwest::Client, metrics: &str, status: &str) -> anyhow::Result<()> {
+    let label = format!(r#"status="{status}""#);
+    for _ in 0..100 {
+        if mirror_counters(client, metrics)
+            .await?
+            .iter()
+            .any(|line| line.contains(&label))
+        {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    anyhow::bail!("no mirror counter with {label}")
+}
+
+#[tokio::test]
+async fn test_mirroring_leaves_responses_unchanged() -> anyhow::Result<()> {
+    let primary = primary().await?;
+    let (shadow, mut copies) = recorder().await?;
+    let plain = gateway(config(primary, None)?).await?.listen;
+    let Addrs {
+        listen: mirrored,
+        metrics,
+        ..
+    } = gateway(config(primary, Some(shadow.to_string()))?).await?;
+
+    let client = reqwest::Client::new();
+    let expected = requests(&client, &plain).await?;
+    assert_eq!(expected[1].0, 202);
+    assert_eq!(expected[1].2, "GET hello mirrored=false");
+    assert_eq!(requests(&client, &mirrored).await?, expected);
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        received.push(
+            tokio::time::timeout(Duration::from_secs(5), copies.recv())
+                .await?
+                .expect("recorder stopped"),
+        );
+    }
+    received.sort_by(|a, b| a.0.cmp(&b.0));
+    let (uri, headers, body) = &received[1];
+    assert_eq!(uri, "/images/small");
+    assert_eq!(body.as_ref(), b"hello");
+    assert_eq!(headers["x-gateway-mirror"], "1");
+    assert!(headers.contains_key("x-request-id"));
+    assert_eq!(received[0].0, "/images/empty");
+    assert!(received[0].2.is_empty());
+
+    // The body over the cap is never copied.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(copies.try_recv().is_err());
+
+    wait_for_counter(&client, &metrics, "500").await
+}
+
+#[tokio::test]
+async fn test_mirror_failures_never_surface() -> anyhow::Result<()> {
+    let primary = primary().await?;
+    let plain = gateway(config(primary, None)?).await?.listen;
+    let Addrs {
+        listen: to_closed_port,
+        metrics,
+        ..
+    } = gateway(config(primary, Some(free_addr()?))?).await?;
+    let to_black_hole = gateway(config(primary, Some(black_hole().await?.to_string()))?).await?.listen;
+
+    let client = reqwest::Client::new();
+    let expected = requests(&client, &plain).await?;
+    for gateway in [&to_closed_port, &to_black_hole] {
+        for _ in 0..3 {
+            let started = Instant::now();
+            assert_eq!(requests(&client, gateway).await?, expected);
+            assert!(
+                started.elapsed() < Duration::from_secs(1),
+                "a stuck mirror delayed the response"
+            );
+        }
+    }
+
+    // Metrics are process-wide, so both failure kinds show up on either gateway.
+    wait_for_counter(&client, &metrics, "error").await?;
+    wait_for_counter(&client, &metrics, "timeout").await
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use axum::Router;
+use common::gateway;
+use service_gateway::{
+    config::{Config, RouteConfig},
+    rate_limit::RateLimitConfig,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+async fn upstream() -> anyhow::Result<SocketAddr> {
+    let router = Router::new().fallback(|| async { "ok" });
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+/// `/images` allows 5 requests per second per client; `web` serves the rest, `/ping` and `/metrics` included,
+/// which need no token.
+fn config(upstream: SocketAddr) -> anyhow::Result<Config> {
+    let route = |name: &str, prefix: Option<&str>| RouteConfig {
+        name: name.into(),
+        prefix: prefix.map(String::from),
+        upstreams: vec![upstream],
+        ..RouteConfig::default()
+    };
+    Ok(Config {
+        default_route: Some("web".into()),
+        rate_limit: Some(RateLimitConfig { rps: 1, burst: Some(1) }),
+        rate_limit_exempt_paths: vec!["/ping".into()],
+        trusted_proxies: vec!["127.0.0.0/8".parse()?],
+        ..common::config(vec![
+            RouteConfig {
+                rate_limit: Some(RateLimitConfig { rps: 5, burst: Some(5) }),
+                ..route("images", Some("/images"))
+            },
+            route("web", None),
+        ])?
+    })
+}
+
+/// Status and `Retry-After` of a request from `client`, as named by the trusted local proxy.
+async fn get(client: &reqwest::Client, gateway: &str, path: &str, from: &str) -> anyhow::Result<(u16, Option<String>)> {
+    let response = client
+        .get(format!("http://{gateway}{path}"))
+        .header("X-Forwarded-For", from)
+        .send()
+        .await?;
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    Ok((response.status().as_u16(), retry_after))
+}
+
+#[tokio::test]
+async fn test_clients_over_the_limit_get_429_until_refilled() -> anyhow::Result<()> {
+    let gateway = gateway(config(upstream().await?)?).await?.listen;
+    let client = reqwest::Client::new();
+    let scraper = "203.0.113.7";
+
+    let mut statuses = Vec::new();
+    for _ in 0..20 {
+        statuses.push(get(&client, &gateway, "/images/a.png", scraper).await?);
+    }
+    assert!(statuses[..5].iter().all(|(status, _)| *status == 200), "{statuses:?}");
+    let limited: Vec<_> = statuses.iter().filter(|(status, _)| *status == 429).collect();
+    assert!(limited.len() >= 10, "{statuses:?}");
+    assert!(
+        limited.iter().all(|(_, retry_after)| retry_after.as_deref() == Some("1")),
+        "{statuses:?}"
+    );
+
+    // Other clients have their own bucket, and exempt paths are never limited.
+    assert_eq!(get(&client, &gateway, "/images/a.png", "203.0.113.8").await?.0, 200);
+    for _ in 0..5 {
+        assert_eq!(get(&client, &gateway, "/ping", scraper).await?.0, 200);
+    }
+    // Routes without their own limit use the global one.
+    assert_eq!(get(&client, &gateway, "/metrics", scraper).await?.0, 200);
+    assert_eq!(get(&client, &gateway, "/metrics", scraper).await?.0, 429);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    for _ in 0..5 {
+        assert_eq!(get(&client, &gateway, "/images/a.png", scraper).await?.0, 200);
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use axum::{Router, http::StatusCode, routing};
+use common::{Addrs, start};
+use service_gateway::{
+    config::{Config, RouteConfig},
+    parse_upstreams,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Stand-in for a service replica: answers `/images/*` with `name` and `/health/ready` per `ready`.
+async fn replica(name: &'static str, ready: Arc<AtomicBool>) -> anyhow::Result<SocketAddr> {
+    let router = Router::new()
+        .route(
+            "/health/ready",
+            routing::get(move || async move {
+                if ready.load(Ordering::Acquire) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            }),
+        )
+        .route("/images/{name}", routing::get(move || async move { name }));
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+fn config(images_upstream: String) -> anyhow::Result<Config> {
+    Ok(Config {
+        health_check_interval_ms: 50,
+        health_check_failures: 2,
+        ..common::config(vec![RouteConfig {
+            name: "images".into(),
+            prefix: Some("/images".into()),
+            upstreams: parse_upstreams(&images_upstream),
+            health_path: Some("/health/ready".into()),
+            ..RouteConfig::default()
+        }])?
+    })
+}
+
+async fn get_image(client: &reqwest::Client, gateway: &str) -> Option<String> {
+    let response = client.get(format!("http://{gateway}/images/x.png")).send().await.ok()?;
+    if response.status() != reqwest::StatusCode::OK {
+        return None;
+    }
+    response.text().await.ok()
+}
+
+#[tokio::test]
+async fn test_traffic_avoids_replica_until_ready() -> anyhow::Result<()> {
+    let warming_ready = Arc::new(AtomicBool::new(false));
+    let ready = replica("ready", Arc::new(AtomicBool::new(true))).await?;
+    let warming = replica("warming", Arc::clone(&warming_ready)).await?;
+
+    let gateway = start(config(format!("{warming},{ready}"))?).listen;
+
+    let client = reqwest::Client::new();
+    let mut first = None;
+    for _ in 0..100 {
+        first = get_image(&client, &gateway).await;
+        if first.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(first.as_deref(), Some("ready"), "gateway never served a request");
+
+    for _ in 0..20 {
+        assert_eq!(get_image(&client, &gateway).await.as_deref(), Some("ready"));
+    }
+
+    warming_ready.store(true, Ordering::Release);
+    let mut joined = false;
+    for _ in 0..100 {
+        if get_image(&client, &gateway).await.as_deref() == Some("warming") {
+            joined = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(joined, "replica never joined rotation after becoming ready");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failing_replica_is_ejected() -> anyhow::Result<()> {
+    let first_ready = Arc::new(AtomicBool::new(true));
+    let second_ready = Arc::new(AtomicBool::new(true));
+    let first = replica("first", Arc::clone(&first_ready)).await?;
+    let second = replica("second", Arc::clone(&second_ready)).await?;
+
+    let Addrs {
+        listen: gateway, admin, ..
+    } = start(config(format!("{first},{second}"))?);
+
+    let client = reqwest::Client::new();
+    let mut served = Vec::new();
+    for _ in 0..100 {
+        if let Some(name) = get_image(&client, &gateway).await {
+            served.push(name);
+        }
+        if served.contains(&"first".to_owned()) && served.contains(&"second".to_owned()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(
+        served.contains(&"first".to_owned()) && served.contains(&"second".to_owned()),
+        "{served:?}"
+    );
+
+    // Two failed checks 50ms apart take the replica out of rotation.
+    first_ready.store(false, Ordering::Release);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    for _ in 0..10 {
+        assert_eq!(get_image(&client, &gateway).await.as_deref(), Some("second"));
+    }
+    let health: serde_json::Value = client
+        .get(format!("http://{admin}/_proxy/health"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["routes"][0]["ready_replicas"], 1);
+    assert_eq!(health["routes"][0]["replicas"][0]["ready"], false);
+
+    second_ready.store(false, Ordering::Release);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let response = client.get(format!("http://{gateway}/images/x.png")).send().await?;
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.headers().contains_key("x-request-id"));
+    let body: serde_json::Value = response.json().await?;
+    assert_eq!(body, serde_json::json!({"error": "No ready upstream", "route": "images"}));
+    Ok(())
+}
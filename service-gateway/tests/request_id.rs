@@ -0,0 +1,77 @@
+mod common;
+
+use axum::{Router, http::HeaderMap, routing};
+use common::start;
+use service_gateway::{
+    config::{Config, RouteConfig},
+    parse_upstreams,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Replica that answers with the `X-Request-Id` it was sent.
+async fn replica() -> anyhow::Result<SocketAddr> {
+    let router = Router::new().route(
+        "/images/{name}",
+        routing::get(|headers: HeaderMap| async move {
+            headers
+                .get("x-request-id")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_owned()
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+fn config(images_upstream: String) -> anyhow::Result<Config> {
+    Ok(Config {
+        ..common::config(vec![RouteConfig {
+            name: "images".into(),
+            prefix: Some("/images".into()),
+            upstreams: parse_upstreams(&images_upstream),
+            ..RouteConfig::default()
+        }])?
+    })
+}
+
+/// Response `X-Request-Id` and the one the replica received.
+async fn get_image(client: &reqwest::Client, gateway: &str, request_id: Option<&str>) -> anyhow::Result<(String, String)> {
+    let mut request = client.get(format!("http://{gateway}/images/x.png"));
+    if let Some(request_id) = request_id {
+        request = request.header("X-Request-Id", request_id);
+    }
+    let response = request.send().await?;
+    let echoed = response.headers()["x-request-id"].to_str()?.to_owned();
+    Ok((echoed, response.text().await?))
+}
+
+#[tokio::test]
+async fn test_request_id_is_kept_or_generated() -> anyhow::Result<()> {
+    let gateway = start(config(replica().await?.to_string())?).listen;
+
+    let client = reqwest::Client::new();
+    let mut kept = None;
+    for _ in 0..100 {
+        if let Ok(ids) = get_image(&client, &gateway, Some("trace-123")).await {
+            kept = Some(ids);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let kept = kept.expect("gateway never served a request");
+    assert_eq!(kept, ("trace-123".to_owned(), "trace-123".to_owned()));
+
+    let (echoed, received) = get_image(&client, &gateway, None).await?;
+    assert_eq!(echoed, received);
+    assert!(uuid::Uuid::parse_str(&echoed).is_ok(), "{echoed}");
+
+    let (echoed, received) = get_image(&client, &gateway, Some("not an id")).await?;
+    assert_eq!(echoed, received);
+    assert_ne!(echoed, "not an id");
+    Ok(())
+}
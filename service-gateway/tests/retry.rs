@@ -0,0 +1,79 @@
+mod common;
+
+use axum::{Router, http::Method};
+use common::{Addrs, free_addr, gateway};
+use service_gateway::config::{Config, RouteConfig};
+use std::net::SocketAddr;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+/// Healthy replica: answers every request with the method it got.
+async fn live() -> anyhow::Result<SocketAddr> {
+    let router = Router::new().fallback(|method: Method| async move { method.to_string() });
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+/// Replica that accepts connections, reads the request and hangs up without answering.
+async fn closing() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).await;
+        }
+    });
+    Ok(addr)
+}
+
+fn config(upstreams: Vec<SocketAddr>) -> anyhow::Result<Config> {
+    Ok(Config {
+        default_route: Some("web".into()),
+        ..common::config(vec![RouteConfig {
+            name: "web".into(),
+            upstreams,
+            max_retries: Some(2),
+            ..RouteConfig::default()
+        }])?
+    })
+}
+
+async fn send(client: &reqwest::Client, method: reqwest::Method, gateway: &str) -> anyhow::Result<(u16, String)> {
+    let response = client.request(method, format!("http://{gateway}/ping")).send().await?;
+    Ok((response.status().as_u16(), response.text().await?))
+}
+
+#[tokio::test]
+async fn test_failed_attempts_move_to_the_next_replica() -> anyhow::Result<()> {
+    let dead: SocketAddr = free_addr()?.parse()?;
+    let Addrs {
+        listen: gateway,
+        metrics,
+        ..
+    } = gateway(config(vec![dead, closing().await?, live().await?])?).await?;
+    let client = reqwest::Client::new();
+
+    // Whichever replica a GET starts on, two retries are enough to reach the live one.
+    for _ in 0..9 {
+        assert_eq!(send(&client, reqwest::Method::GET, &gateway).await?, (200, "GET".to_owned()));
+    }
+
+    // `/ping` needs no token, so a POST gets past auth without an auth service. A POST the closing replica hung up on may have been acted on, so it is not sent again.
+    let mut statuses = Vec::new();
+    for _ in 0..9 {
+        statuses.push(send(&client, reqwest::Method::POST, &gateway).await?.0);
+    }
+    assert!(statuses.contains(&502), "{statuses:?}");
+    assert!(statuses.contains(&200), "{statuses:?}");
+
+    let text = client.get(format!("http://{metrics}/metrics")).send().await?.text().await?;
+    let retries = text
+        .lines()
+        .find_map(|line| line.strip_prefix(r#"gateway_upstream_retries_total{route="web"} "#))
+        .and_then(|count| count.parse::<u64>().ok());
+    assert!(retries.is_some_and(|count| count > 0), "{text}");
+    Ok(())
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use axum::{Router, http::Uri};
+use common::{config, gateway};
+use service_gateway::config::RouteConfig;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Upstream that answers with its name and the path and query it received.
+async fn echo(name: &'static str) -> anyhow::Result<SocketAddr> {
+    let router = Router::new().fallback(move |uri: Uri| async move { format!("{name} {uri}") });
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+fn route(name: &str, host: Option<&str>, prefix: &str, rewrite_prefix: Option<&str>, upstream: SocketAddr) -> RouteConfig {
+    RouteConfig {
+        name: name.into(),
+        host: host.map(String::from),
+        prefix: Some(prefix.into()),
+        rewrite_prefix: rewrite_prefix.map(String::from),
+        upstreams: vec![upstream],
+        ..RouteConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn test_prefix_routes_rewrite_the_upstream_path() -> anyhow::Result<()> {
+    // GETs under /images need no token, so the routes live there to run without an auth service.
+    let rust = echo("rust").await?;
+    let py = echo("py").await?;
+    let cdn = echo("cdn").await?;
+    let gateway = gateway(config(vec![
+        route("rust", None, "/images", None, rust),
+        route("py", None, "/images/py", Some("/"), py),
+        route("cdn", Some("cdn.example.com"), "/images", Some("/static"), cdn),
+    ])?)
+    .await?
+    .listen;
+    let client = reqwest::Client::new();
+    let get = |path: &str, host: Option<&str>| {
+        let mut request = client.get(format!("http://{gateway}{path}"));
+        if let Some(host) = host {
+            request = request.header("Host", host);
+        }
+        async move { anyhow::Ok(request.send().await?.text().await?) }
+    };
+
+    assert_eq!(get("/images/a.png", None).await?, "rust /images/a.png");
+    assert_eq!(
+        get("/images/py/users?page=2&q=a%20b", None).await?,
+        "py /users?page=2&q=a%20b"
+    );
+    assert_eq!(get("/images/py", None).await?, "py /");
+    assert_eq!(get("/images/python.png", None).await?, "rust /images/python.png");
+    assert_eq!(get("/images/py/%E2%9C%93", None).await?, "py /%E2%9C%93");
+    // The host and path route wins over the longer path-only match.
+    assert_eq!(
+        get("/images/py/a.png", Some("cdn.example.com")).await?,
+        "cdn /static/py/a.png"
+    );
+    Ok(())
+}
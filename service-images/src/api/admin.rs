@@ -0,0 +1,207 @@
+use super::{
+    images::key::ImageKey,
+    schemas::{CreateWebhookRequest, DeliveriesParams, LegalHoldRequest, ObjectStatus, QuotaLimitRequest, sanitize_echo},
+};
+use crate::{
+    error::{ApiResult, HttpError},
+    quota::{QuotaStatus, Quotas},
+    state::ServerState,
+    storage::StorageStats,
+    webhooks::{self, Webhooks},
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use kafka_client::admin::{GroupDescription, TopicDescription};
+use scylladb_client::webhooks::{Webhook, WebhookDelivery};
+use service_common::admin::require_admin;
+use uuid::Uuid;
+
+/// Signing secrets shorter than this are refused.
+const MIN_WEBHOOK_SECRET_LENGTH: usize = 16;
+const DEFAULT_DELIVERIES_LIMIT: i32 = 50;
+const MAX_DELIVERIES_LIMIT: i32 = 500;
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn list_kafka_topics(State(state): State<ServerState>, headers: HeaderMap) -> ApiResult<Json<Vec<String>>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(state.kafka_admin.list_topics().await?))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn describe_kafka_topic(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> ApiResult<Json<TopicDescription>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(state.kafka_admin.describe_topic(&name).await?))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn describe_kafka_group(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> ApiResult<Json<GroupDescription>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(state.kafka_admin.describe_group(&id).await?))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn storage_stats(State(state): State<ServerState>, headers: HeaderMap) -> ApiResult<Json<StorageStats>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(state.usage.stats(state.storage.as_ref()).await?))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn describe_object(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    ImageKey(key): ImageKey,
+) -> ApiResult<Json<ObjectStatus>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    let head = state
+        .storage
+        .head(&key)
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Image {} not found", sanitize_echo(&key))))?;
+    Ok(Json(ObjectStatus {
+        key,
+        version_id: head.version_id,
+        legal_hold: head.legal_hold,
+        metadata: head.metadata,
+    }))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn set_object_legal_hold(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    ImageKey(key): ImageKey,
+    Json(request): Json<LegalHoldRequest>,
+) -> ApiResult<Json<ObjectStatus>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    if !state.storage.object_exists(&key).await? {
+        return Err(HttpError::NotFound(format!("Image {} not found", sanitize_echo(&key))).into());
+    }
+    state.storage.set_legal_hold(&key, request.on).await?;
+    tracing::info!(%key, on = request.on, "Image legal hold changed");
+    describe_object(State(state), headers, ImageKey(key)).await
+}
+
+fn quotas(state: &ServerState) -> Result<&Quotas, HttpError> {
+    state
+        .quotas
+        .as_ref()
+        .ok_or_else(|| HttpError::NotFound("Quotas are disabled".into()))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn get_quota(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<QuotaStatus>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(quotas(&state)?.status(user_id).await?))
+}
+
+/// Sets the user's limit, e.g. for a paid tier, or with `null` returns them to `UPLOAD_QUOTA_BYTES`.
+#[tracing::instrument(skip(state, headers))]
+pub async fn set_quota_limit(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<QuotaLimitRequest>,
+) -> ApiResult<Json<QuotaStatus>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    let quotas = quotas(&state)?;
+    let limit = request
+        .limit_bytes
+        .map(i64::try_from)
+        .transpose()
+        .map_err(|_| HttpError::BadRequest("limit_bytes is too large".into()))?;
+    quotas.store.set_limit_override(user_id, limit).await?;
+    tracing::info!(%user_id, limit_bytes = ?request.limit_bytes, "Quota limit changed");
+    Ok(Json(quotas.status(user_id).await?))
+}
+
+fn webhooks(state: &ServerState) -> Result<&Webhooks, HttpError> {
+    state
+        .webhooks
+        .as_ref()
+        .ok_or_else(|| HttpError::NotFound("Webhooks are disabled".into()))
+}
+
+async fn find_webhook(webhooks: &Webhooks, id: Uuid) -> ApiResult<Webhook> {
+    Ok(webhooks
+        .store
+        .get_webhook(id)
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Webhook {id} not found")))?)
+}
+
+#[tracing::instrument(skip(state, headers, request), fields(account_id = %request.account_id))]
+pub async fn create_webhook(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateWebhookRequest>,
+) -> ApiResult<(StatusCode, Json<Webhook>)> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    let webhooks = webhooks(&state)?;
+
+    if request.events.is_empty() {
+        return Err(HttpError::BadRequest("At least one event is required".into()).into());
+    }
+    if let Some(unknown) = request.events.iter().find(|e| !webhooks::EVENTS.contains(&e.as_str())) {
+        return Err(HttpError::BadRequest(format!("Unknown event {}", sanitize_echo(unknown))).into());
+    }
+    if request.secret.len() < MIN_WEBHOOK_SECRET_LENGTH {
+        return Err(HttpError::BadRequest(format!("Secret must be at least {MIN_WEBHOOK_SECRET_LENGTH} characters")).into());
+    }
+    let url = webhooks::validate_url(&request.url, webhooks.allow_private_targets)
+        .await
+        .map_err(|e| HttpError::BadRequest(e.to_string()))?;
+
+    let webhook = Webhook::new(request.account_id, url.to_string(), request.secret, request.events);
+    webhooks.store.create_webhook(&webhook).await?;
+    tracing::info!(webhook_id = %webhook.webhook_id, url = %webhook.url, "Webhook registered");
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn get_webhook(State(state): State<ServerState>, headers: HeaderMap, Path(id): Path<Uuid>) -> ApiResult<Json<Webhook>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    Ok(Json(find_webhook(webhooks(&state)?, id).await?))
+}
+
+#[tracing::instrument(skip(state, headers))]
+pub async fn delete_webhook(State(state): State<ServerState>, headers: HeaderMap, Path(id): Path<Uuid>) -> ApiResult<StatusCode> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    if !webhooks(&state)?.store.delete_webhook(id).await? {
+        return Err(HttpError::NotFound(format!("Webhook {id} not found")).into());
+    }
+    tracing::info!(webhook_id = %id, "Webhook deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delivery attempts of the webhook, newest first.
+#[tracing::instrument(skip(state, headers))]
+pub async fn list_webhook_deliveries(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Query(params): Query<DeliveriesParams>,
+) -> ApiResult<Json<Vec<WebhookDelivery>>> {
+    require_admin(&headers, state.admin_token.as_deref())?;
+    let webhooks = webhooks(&state)?;
+    find_webhook(webhooks, id).await?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+    Ok(Json(webhooks.store.deliveries(id, limit).await?))
+}
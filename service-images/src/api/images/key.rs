@@ -0,0 +1,27 @@
+use crate::{
+    api::router::validate_filename,
+    error::{ApiError, HttpError},
+};
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::request::Parts,
+};
+
+/// Object key of an `/{key}` or `/{user_id}/{key}` route, validated with [`validate_filename`].
+///
+/// Uploads are stored under `{user_id}/{uuid}`; keys without the prefix predate that scheme.
+#[derive(Debug, Clone)]
+pub struct ImageKey(pub String);
+
+impl<S: Send + Sync> FromRequestParts<S> for ImageKey {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(segments) = Path::<Vec<String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| HttpError::BadRequest("Invalid filename".to_owned()))?;
+        let key = segments.join("/");
+        validate_filename(&key)?;
+        Ok(Self(key))
+    }
+}
@@ -0,0 +1,3 @@
+pub mod key;
+pub mod range;
+pub mod sniff;
@@ -0,0 +1,90 @@
+use std::ops::RangeInclusive;
+
+/// One `Range: bytes=` spec; positions are only known to be satisfiable once resolved against the object size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `start-` or `start-end`, `end` inclusive.
+    From { start: u64, end: Option<u64> },
+    /// `-len`: the last `len` bytes.
+    Suffix(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    Single(ByteRange),
+    /// Several specs; not supported, answered with `416`.
+    Multiple,
+}
+
+/// Parses a `Range` header value. Anything that is not a well-formed `bytes` range is `None`, and the header is
+/// ignored as RFC 9110 asks.
+pub fn parse(value: &str) -> Option<RangeRequest> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    if specs.contains(',') {
+        return specs
+            .split(',')
+            .all(|spec| parse_spec(spec.trim()).is_some())
+            .then_some(RangeRequest::Multiple);
+    }
+    parse_spec(specs.trim()).map(RangeRequest::Single)
+}
+
+fn parse_spec(spec: &str) -> Option<ByteRange> {
+    let (start, end) = spec.split_once('-')?;
+    if start.is_empty() {
+        return end.parse().ok().map(ByteRange::Suffix);
+    }
+    let start = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+    Some(ByteRange::From { start, end })
+}
+
+impl ByteRange {
+    /// The bytes to send out of an object of `size` bytes, or `None` if the range is unsatisfiable.
+    pub fn resolve(self, size: u64) -> Option<RangeInclusive<u64>> {
+        let last = size.checked_sub(1)?;
+        match self {
+            Self::From { start, end } if start <= last => Some(start..=end.map_or(last, |end| end.min(last))),
+            Self::From { .. } | Self::Suffix(0) => None,
+            Self::Suffix(len) => Some(size.saturating_sub(len)..=last),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_and_multiple_ranges() {
+        let single = |start, end| Some(RangeRequest::Single(ByteRange::From { start, end }));
+        assert_eq!(parse("bytes=0-99"), single(0, Some(99)));
+        assert_eq!(parse("bytes=100-"), single(100, None));
+        assert_eq!(parse("bytes=-100"), Some(RangeRequest::Single(ByteRange::Suffix(100))));
+        assert_eq!(parse("bytes=0-1, 5-"), Some(RangeRequest::Multiple));
+
+        for ignored in ["items=0-1", "bytes=", "bytes=9-1", "bytes=a-b", "bytes=-", "bytes=0-1,x"] {
+            assert_eq!(parse(ignored), None, "{ignored}");
+        }
+    }
+
+    #[test]
+    fn resolves_against_the_size() {
+        let from = |start, end| ByteRange::From { start, end };
+        assert_eq!(from(0, Some(99)).resolve(1000), Some(0..=99));
+        assert_eq!(from(900, Some(2000)).resolve(1000), Some(900..=999));
+        assert_eq!(from(10, None).resolve(1000), Some(10..=999));
+        assert_eq!(ByteRange::Suffix(100).resolve(1000), Some(900..=999));
+        assert_eq!(ByteRange::Suffix(5000).resolve(1000), Some(0..=999));
+
+        assert_eq!(from(1000, None).resolve(1000), None);
+        assert_eq!(ByteRange::Suffix(0).resolve(1000), None);
+        assert_eq!(from(0, None).resolve(0), None);
+    }
+}
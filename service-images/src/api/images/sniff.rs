@@ -0,0 +1,80 @@
+//! Image type detection from the leading bytes of a file, so uploads are stored under what they are rather
+//! than what the client labelled them.
+
+/// Bytes needed to tell every supported format apart; WebP has the longest signature.
+pub const SNIFF_LEN: usize = 12;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG: &[u8] = b"\xff\xd8\xff";
+const GIF87A: &[u8] = b"GIF87a";
+const GIF89A: &[u8] = b"GIF89a";
+
+/// Content type of the image starting with `head`, or `None` when it is not a PNG, JPEG, GIF or WebP.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(PNG) {
+        Some("image/png")
+    } else if head.starts_with(JPEG) {
+        Some("image/jpeg")
+    } else if head.starts_with(GIF87A) || head.starts_with(GIF89A) {
+        Some("image/gif")
+    } else if head.len() >= SNIFF_LEN && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// The sniffed type of `head`, provided it is the type the client claimed for it.
+pub fn confirm(claimed: &str, head: &[u8]) -> Option<&'static str> {
+    sniff(head).filter(|sniffed| *sniffed == claimed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+    }
+
+    #[test]
+    fn jpeg() {
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF\0"), Some("image/jpeg"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe1\0\x18Exif\0\0"), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn gif() {
+        assert_eq!(sniff(b"GIF87a\x01\0\x01\0\0\0"), Some("image/gif"));
+        assert_eq!(sniff(b"GIF89a\x01\0\x01\0\0\0"), Some("image/gif"));
+    }
+
+    #[test]
+    fn webp() {
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        // Other RIFF containers share the first four bytes.
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+    }
+
+    #[test]
+    fn executable_is_not_an_image() {
+        assert_eq!(sniff(b"\x7fELF\x02\x01\x01\0\0\0\0\0"), None);
+        assert_eq!(sniff(b"MZ\x90\0\x03\0\0\0\x04\0\0\0"), None);
+    }
+
+    #[test]
+    fn truncated_signatures_are_not_recognised() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"\x89PNG"), None);
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEB"), None);
+    }
+
+    #[test]
+    fn mismatched_claims_are_refused() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(confirm("image/png", png), Some("image/png"));
+        assert_eq!(confirm("image/jpeg", png), None);
+        assert_eq!(confirm("image/png", b"\x7fELF\x02\x01\x01\0\0\0\0\0"), None);
+    }
+}
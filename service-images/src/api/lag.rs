@@ -0,0 +1,87 @@
+use crate::{lag::LagSnapshot, state::ServerState};
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Total consumer group lag as a bare number for autoscalers.
+///
+/// Refresh failures never turn into errors: the last known value is served with `X-Kafka-Lag-Stale: true`,
+/// and only a watcher that has never succeeded answers `503`.
+pub async fn kafka_lag(State(state): State<ServerState>) -> Response {
+    render(&state.lag.snapshot())
+}
+
+fn render(snapshot: &LagSnapshot) -> Response {
+    let (status, body) = match snapshot.lag {
+        Some(lag) => (StatusCode::OK, format!("{lag}\n")),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "unknown\n".to_owned()),
+    };
+    let age = snapshot
+        .age
+        .map_or_else(|| "unknown".to_owned(), |age| age.as_secs().to_string());
+
+    (
+        status,
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_owned()),
+            (
+                header::HeaderName::from_static("x-kafka-lag-stale"),
+                snapshot.stale.to_string(),
+            ),
+            (header::HeaderName::from_static("x-kafka-lag-age-seconds"), age),
+            (
+                header::HeaderName::from_static("x-kafka-lag-max-staleness-seconds"),
+                snapshot.max_staleness.as_secs().to_string(),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lag::LagWatcher;
+    use axum::body::to_bytes;
+    use std::time::{Duration, Instant};
+
+    async fn body(response: Response) -> String {
+        String::from_utf8(to_bytes(response.into_body(), 64).await.unwrap().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn renders_plain_lag_with_annotations() {
+        let watcher = LagWatcher::new(Duration::from_secs(60));
+        let now = Instant::now();
+        watcher.record(1234, now);
+
+        let response = render(&watcher.snapshot_at(now + Duration::from_secs(5)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-kafka-lag-stale"], "false");
+        assert_eq!(response.headers()["x-kafka-lag-age-seconds"], "5");
+        assert_eq!(response.headers()["x-kafka-lag-max-staleness-seconds"], "60");
+        assert_eq!(body(response).await, "1234\n");
+    }
+
+    #[tokio::test]
+    async fn stale_value_is_still_served() {
+        let watcher = LagWatcher::new(Duration::from_secs(60));
+        let now = Instant::now();
+        watcher.record(1234, now);
+
+        let response = render(&watcher.snapshot_at(now + Duration::from_secs(120)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-kafka-lag-stale"], "true");
+        assert_eq!(body(response).await, "1234\n");
+    }
+
+    #[tokio::test]
+    async fn unknown_lag_is_unavailable() {
+        let response = render(&LagWatcher::new(Duration::from_secs(60)).snapshot());
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["x-kafka-lag-stale"], "true");
+    }
+}
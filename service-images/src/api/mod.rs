@@ -1,13 +1,58 @@
+pub mod admin;
+pub mod images;
+pub mod lag;
+pub mod openapi;
 pub mod router;
 pub mod schemas;
 
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use crate::{error::HttpError, state::ServerState};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use serde_json::json;
+use std::sync::atomic::Ordering;
 
 pub async fn ping() -> Json<serde_json::Value> {
     Json(json!({"ping": "pong!"}))
 }
 
+/// Reports `degraded` while a Kafka topic's retention is below the configured minimums.
+pub async fn health(State(state): State<ServerState>) -> Json<serde_json::Value> {
+    let degraded = state.kafka_retention.iter().any(|report| !report.is_sufficient());
+    let status = if degraded { "degraded" } else { "ok" };
+    Json(json!({"status": status, "kafka": {"retention": state.kafka_retention}}))
+}
+
+/// Always `200`: the process is up and serving requests, whatever its dependencies.
+pub async fn live() -> Json<serde_json::Value> {
+    Json(json!({"status": "alive"}))
+}
+
+/// `503` until the readiness gate opens, so proxies keep the replica out of rotation until then. Once open,
+/// `503` whenever a required dependency fails its check; see [`crate::readiness::ReadinessProbe`].
+pub async fn ready(State(state): State<ServerState>) -> (StatusCode, Json<serde_json::Value>) {
+    if !state.ready.load(Ordering::Acquire) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"status": "not ready", "kafka_producer": producer_health(&state)})),
+        );
+    }
+
+    let readiness = state.readiness.check(&state).await;
+    let (status, label) = if readiness.ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    };
+    (
+        status,
+        Json(json!({"status": label, "kafka_producer": producer_health(&state), "checks": readiness.checks})),
+    )
+}
+
+/// Producer health as of the last delivery or health probe; informational only.
+fn producer_health(state: &ServerState) -> &'static str {
+    if state.producer.is_healthy() { "healthy" } else { "unhealthy" }
+}
+
 pub async fn not_found() -> impl IntoResponse {
-    (StatusCode::NOT_FOUND, Json(json!({"error": "Not found"})))
+    HttpError::NotFound("Not found".into())
 }
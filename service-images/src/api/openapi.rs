@@ -0,0 +1,79 @@
+use super::{
+    router,
+    schemas::{BatchDeleteRequest, BatchDeleteResponse, DeleteOutcome, ImageEntry, ImageList, KeyOutcome},
+};
+pub use crate::error::ErrorBody;
+use serde::Serialize;
+use utoipa::{
+    Modify, OpenApi, ToSchema,
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+};
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+/// Swagger UI, served when `SWAGGER_UI` is set.
+pub const SWAGGER_UI_PATH: &str = "/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "service-images"),
+    paths(
+        router::upload_image,
+        router::list_images,
+        router::download_image,
+        router::delete_image,
+        router::delete_images_batch
+    ),
+    components(schemas(
+        ErrorBody,
+        QuotaExceededBody,
+        ImageName,
+        UploadForm,
+        ImageList,
+        ImageEntry,
+        BatchDeleteRequest,
+        BatchDeleteResponse,
+        KeyOutcome,
+        DeleteOutcome
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// Body of a `403` upload: the user's stored bytes and their limit.
+#[derive(Serialize, ToSchema)]
+pub struct QuotaExceededBody {
+    /// Always `quota_exceeded`.
+    pub code: String,
+    pub error: String,
+    pub used: u64,
+    pub limit: u64,
+}
+
+/// Body of a successful upload or delete.
+#[derive(Serialize, ToSchema)]
+pub struct ImageName {
+    /// Object key, `{user_id}/{uuid}` for uploads.
+    pub filename: String,
+}
+
+/// Multipart body of an upload: the first part is the image.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// JPEG, PNG, GIF or WebP; the part's content type must match the bytes.
+    #[schema(format = Binary, content_media_type = "application/octet-stream")]
+    pub file: String,
+}
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+pub async fn openapi_json() -> axum::Json<utoipa::openapi::OpenApi> {
+    axum::Json(ApiDoc::openapi())
+}
@@ -1,32 +1,75 @@
-use super::schemas::Image;
+use super::{
+    images::{
+        key::ImageKey,
+        range::{self, RangeRequest},
+        sniff::{self, SNIFF_LEN},
+    },
+    openapi::{ErrorBody, ImageName, QuotaExceededBody, UploadForm},
+    schemas::{
+        BatchDeleteRequest, BatchDeleteResponse, DeleteOutcome, DownloadParams, Image, ImageEntry, ImageList, KeyOutcome,
+        ListImagesParams, sanitize_echo,
+    },
+};
 use crate::{
+    auth::AuthUser,
+    caching::CacheHeaders,
+    deadline::Deadline,
     error::{ApiError, ApiResult, HttpError},
+    interceptor, kafka_health, metrics,
+    quota::QuotaStatus,
+    request_id::RequestId,
     state::ServerState,
+    storage::{ObjectWriter, StorageError},
+    thumbnails::{self, Thumbnail, ThumbnailError},
 };
 use axum::{
-    extract::{Multipart, Path, State},
-    http::HeaderMap,
+    Json,
+    body::Bytes,
+    extract::{
+        Multipart, Query, State,
+        multipart::{Field, MultipartError},
+    },
+    http::{HeaderMap, header},
 };
+use kafka_client::schemas::{Action, KafkaMessage, METADATA_CONTENT_TYPE, METADATA_OBJECT_KEY, METADATA_SIZE};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 const ALLOWED_CONTENT_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
-
-fn extract_user_id(headers: &HeaderMap) -> Result<Uuid, HttpError> {
-    let value = headers
-        .get("X-User-Id")
-        .ok_or_else(|| HttpError::BadRequest("Missing X-User-Id header".into()))?
-        .to_str()
-        .map_err(|_| HttpError::BadRequest("Invalid X-User-Id header".into()))?;
-    value
-        .parse::<Uuid>()
-        .map_err(|_| HttpError::BadRequest("X-User-Id is not a valid UUID".into()))
-}
-
-#[tracing::instrument(skip(state, headers, multipart))]
-pub async fn upload_image(State(state): State<ServerState>, headers: HeaderMap, mut multipart: Multipart) -> ApiResult<Image> {
-    let _user_id = extract_user_id(&headers)?;
-
-    let field = multipart
+/// Object metadata key holding the uploader's user ID.
+const OWNER_METADATA_KEY: &str = "owner";
+/// Object metadata key marking uploads counted against their owner's quota, so deletes only give back what was counted.
+const QUOTA_METADATA_KEY: &str = "quota-counted";
+pub const MAX_BATCH_DELETE_KEYS: usize = 100;
+const DEFAULT_LIST_LIMIT: usize = 20;
+pub const MAX_LIST_LIMIT: usize = 100;
+
+#[utoipa::path(
+    post,
+    path = "/images/upload",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Stored under the returned key", body = ImageName),
+        (status = 400, description = "Malformed multipart body or undecodable image", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Past the user's storage quota", body = QuotaExceededBody),
+        (status = 413, description = "Larger than `MAX_FILE_SIZE`", body = ErrorBody),
+        (status = 415, description = "Not an allowed image type, or not what it claims to be", body = ErrorBody),
+        (status = 422, description = "Refused by the upload interceptor; `message` has the reason", body = ErrorBody),
+        (status = 429, description = "Upload rate limit reached; see `Retry-After`", body = ErrorBody),
+        (status = 503, description = "The content scan timed out and `UPLOAD_SCAN_FAIL_OPEN` is off", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, request_id, multipart))]
+pub async fn upload_image(
+    State(state): State<ServerState>,
+    deadline: Deadline,
+    AuthUser { user_id, .. }: AuthUser,
+    RequestId(request_id): RequestId,
+    mut multipart: Multipart,
+) -> ApiResult<Image> {
+    let mut field = multipart
         .next_field()
         .await
         .map_err(|e| {
@@ -38,60 +81,688 @@ pub async fn upload_image(State(state): State<ServerState>, headers: HeaderMap,
             HttpError::NotFound("File not found".into())
         })?;
 
-    let content_type = field
+    let claimed = field
         .content_type()
         .map(ToString::to_string)
         .unwrap_or_else(|| "application/octet-stream".into());
-    validate_content_type(&content_type)?;
+    validate_content_type(&claimed)?;
+    let head = read_head(&mut field).await?;
+    let Some(content_type) = sniff::confirm(&claimed, &head) else {
+        tracing::warn!(claimed = %sanitize_echo(&claimed), "Upload content does not match its content type");
+        return Err(HttpError::UnsupportedMediaType.into());
+    };
+
+    let quota = match &state.quotas {
+        Some(quotas) => {
+            let status = quotas.status(user_id).await?;
+            status.admit(0)?;
+            Some(status)
+        }
+        None => None,
+    };
+
+    let key = format!("{user_id}/{}", Uuid::now_v7());
+    let mut metadata = HashMap::from([(OWNER_METADATA_KEY.to_owned(), user_id.to_string())]);
+    if quota.is_some() {
+        metadata.insert(QUOTA_METADATA_KEY.to_owned(), "true".to_owned());
+    }
+    let mut sink = state.storage.writer(&key, content_type, metadata);
+    // The interceptor sees the whole file, so a copy is kept; parts already sent are discarded on rejection.
+    let mut copy = state.upload_interceptor.is_some().then(Vec::new);
+    let received = async {
+        deadline
+            .run(|| stream_field(head, field, sink.as_mut(), copy.as_mut(), state.max_file_size, quota.as_ref()))
+            .await?;
+        if let Some(quota) = &quota {
+            quota.admit(sink.written())?;
+        }
+        if let (Some(interceptor), Some(data)) = (&state.upload_interceptor, &copy) {
+            interceptor::inspect(interceptor.as_ref(), &state.scan_policy, content_type, data).await?;
+        }
+        Ok::<_, ApiError>(())
+    };
+    if let Err(e) = received.await {
+        if let Err(abort) = sink.abort().await {
+            tracing::warn!(%key, "Failed to abort upload: {abort}");
+        }
+        return Err(upload_error(e));
+    }
+
+    // Uploads that fit in one part are decoded before they are stored; larger ones were streamed out of memory
+    // and are read back once stored.
+    let thumbnails = match sink.buffered() {
+        Some(data) => Some(render_thumbnails(&state, &Bytes::copy_from_slice(data), content_type).await?),
+        None => None,
+    };
+    let size = deadline
+        .run(|| metrics::s3_timed("upload", sink.finish()))
+        .await
+        .map_err(upload_error)?;
+    let thumbnails = match thumbnails {
+        Some(thumbnails) => thumbnails,
+        None => render_stored(&state, &deadline, &key, content_type).await?,
+    };
+    metrics::image_uploaded(content_type, size);
+    if let Some(quotas) = &state.quotas {
+        quotas.record(user_id, size as i64).await;
+    }
+    store_thumbnails(&state, &deadline, &key, thumbnails).await;
+
+    let event = KafkaMessage::v1(
+        user_id.to_string(),
+        Action::Create,
+        Some(key.clone()),
+        HashMap::from([
+            (METADATA_OBJECT_KEY.to_owned(), key.clone()),
+            (METADATA_CONTENT_TYPE.to_owned(), content_type.to_owned()),
+            (METADATA_SIZE.to_owned(), size.to_string()),
+        ]),
+    );
+    let kafka_headers = HashMap::from([("request_id".to_owned(), request_id)]);
+    kafka_health::publish(&state, &key, event, kafka_headers).await;
 
-    let data = field.bytes().await.map_err(|e| {
-        tracing::error!("Failed to read file bytes: {:?}", e);
-        HttpError::BadRequest("Failed to read uploaded file".into())
-    })?;
+    Ok(Image::Created(key))
+}
+
+/// At least [`SNIFF_LEN`] bytes of the file, or all of it when it is shorter.
+async fn read_head(field: &mut Field<'_>) -> Result<Bytes, HttpError> {
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LEN {
+        match field.chunk().await.map_err(read_error)? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    Ok(Bytes::from(head))
+}
 
-    let key = Uuid::now_v7().to_string();
+/// Feeds `head` and the rest of the file into `sink` chunk by chunk, and into `copy` if given, refusing it as
+/// soon as it grows past `max_file_size` or the room left in `quota`, with the error of the limit it crossed.
+async fn stream_field(
+    head: Bytes,
+    mut field: Field<'_>,
+    sink: &mut dyn ObjectWriter,
+    mut copy: Option<&mut Vec<u8>>,
+    max_file_size: u64,
+    quota: Option<&QuotaStatus>,
+) -> ApiResult<()> {
+    let cap = quota.map_or(max_file_size, |q| max_file_size.min(q.limit.saturating_sub(q.used)));
+    let mut chunk = Some(head);
+    while let Some(data) = chunk {
+        let size = sink.written() + data.len() as u64;
+        if size > cap {
+            if let Some(quota) = quota {
+                quota.admit(size)?;
+            }
+            tracing::warn!(max_file_size, "Rejecting upload over the size limit");
+            return Err(HttpError::PayloadTooLarge(format!("File exceeds the limit of {max_file_size} bytes")).into());
+        }
+        if let Some(copy) = copy.as_deref_mut() {
+            copy.extend_from_slice(&data);
+        }
+        sink.write(data).await?;
+        chunk = field.chunk().await.map_err(read_error)?;
+    }
+    Ok(())
+}
 
-    state.s3.upload(&key, data, &content_type).await.map_err(|e| {
-        tracing::error!("Error uploading to S3: {:?}", e);
-        ApiError::Http(HttpError::Internal("Failed to upload file".into()))
-    })?;
+fn read_error(e: MultipartError) -> HttpError {
+    tracing::error!("Failed to read file bytes: {:?}", e);
+    HttpError::BadRequest("Failed to read uploaded file".into())
+}
 
-    Ok(Image::Created(key))
+fn upload_error(e: ApiError) -> ApiError {
+    match e {
+        ApiError::Storage(e) => {
+            tracing::error!("Error storing upload: {:?}", e);
+            ApiError::Http(HttpError::Internal("Failed to upload file".into()))
+        }
+        e => e,
+    }
 }
 
-pub async fn download_image(State(state): State<ServerState>, Path(filename): Path<String>) -> ApiResult<Image> {
-    validate_filename(&filename)?;
-    let object = state.s3.download(&filename).await?;
+/// With `?size=`, serves that thumbnail, or the original when none was rendered for the image.
+#[utoipa::path(
+    get,
+    path = "/images/{user_id}/{filename}",
+    description = "Keys of legacy uploads have no user prefix and are served on `/images/{filename}`.",
+    params(("user_id" = Uuid, Path), ("filename" = String, Path), DownloadParams),
+    responses(
+        (status = 200, description = "The image, with `ETag` and `Last-Modified`", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 206, description = "The requested `Range`", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 304, description = "Unchanged since `If-None-Match` or `If-Modified-Since`"),
+        (status = 404, description = "No such image", body = ErrorBody),
+        (status = 416, description = "Unsatisfiable or multi-range `Range`", body = ErrorBody),
+    )
+)]
+pub async fn download_image(
+    State(state): State<ServerState>,
+    deadline: Deadline,
+    ImageKey(filename): ImageKey,
+    Query(params): Query<DownloadParams>,
+    headers: HeaderMap,
+) -> ApiResult<Image> {
+    let thumbnail = params.size.map(|size| thumbnails::thumbnail_key(&filename, size));
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(range::parse);
+    if let Some(range) = range {
+        return download_range(&state, &deadline, filename, thumbnail, range).await;
+    }
+
+    let object = match thumbnail {
+        Some(key) => match deadline
+            .run(|| {
+                state
+                    .downloads
+                    .download(&key, || metrics::s3_timed("download", state.storage.download(&key)))
+            })
+            .await
+        {
+            Err(ApiError::Storage(e)) if e.is_not_found() => None,
+            result => Some(result?),
+        },
+        None => None,
+    };
+    let object = match object {
+        Some(object) => object,
+        None => {
+            deadline
+                .run(|| {
+                    state
+                        .downloads
+                        .download(&filename, || metrics::s3_timed("download", state.storage.download(&filename)))
+                })
+                .await?
+        }
+    };
     Ok(Image::File {
         filename,
         data: object.data,
         content_type: object.content_type.unwrap_or_else(|| "application/octet-stream".into()),
+        cache: CacheHeaders {
+            e_tag: object.e_tag,
+            last_modified: object.last_modified,
+            cache_control: state.cache_control.clone(),
+        },
+    })
+}
+
+/// Serves a `Range` request straight from S3, bypassing the download cache: the size is read first, so that
+/// unsatisfiable and multi-range requests get `416` without fetching anything.
+async fn download_range(
+    state: &ServerState,
+    deadline: &Deadline,
+    filename: String,
+    thumbnail: Option<String>,
+    range: RangeRequest,
+) -> ApiResult<Image> {
+    let thumbnail_head = match &thumbnail {
+        Some(key) => deadline.run(|| metrics::s3_timed("head", state.storage.head(key))).await?,
+        None => None,
+    };
+    let (key, head) = match thumbnail_head {
+        Some(head) => (thumbnail.unwrap_or_default(), head),
+        None => match deadline
+            .run(|| metrics::s3_timed("head", state.storage.head(&filename)))
+            .await?
+        {
+            Some(head) => (filename.clone(), head),
+            None => return Err(HttpError::NotFound("Image not found".into()).into()),
+        },
+    };
+    let size = head.size.max(0) as u64;
+    let span = match range {
+        RangeRequest::Single(range) => range.resolve(size),
+        RangeRequest::Multiple => None,
+    };
+    let Some(span) = span else {
+        return Err(HttpError::RangeNotSatisfiable(size).into());
+    };
+
+    let object = deadline
+        .run(|| {
+            metrics::s3_timed(
+                "download_range",
+                state.storage.download_range(&key, *span.start(), *span.end()),
+            )
+        })
+        .await?;
+    Ok(Image::Partial {
+        filename,
+        data: object.data.into(),
+        content_type: head
+            .content_type
+            .or(object.content_type)
+            .unwrap_or_else(|| "application/octet-stream".into()),
+        start: *span.start(),
+        end: *span.end(),
+        size,
+        cache: CacheHeaders {
+            e_tag: head.e_tag,
+            last_modified: head.last_modified,
+            cache_control: state.cache_control.clone(),
+        },
     })
 }
 
-#[tracing::instrument(skip(state, headers))]
+/// Images uploaded by the caller, oldest first: keys sort in upload order since they end in a UUIDv7.
+/// Thumbnails are not listed; legacy keys without the user prefix cannot be. Listings are per user, so shared caches
+/// must not keep them.
+#[utoipa::path(
+    get,
+    path = "/images",
+    params(ListImagesParams),
+    responses(
+        (status = 200, body = ImageList, headers(("Cache-Control" = String, description = "`private, no-store`"))),
+        (status = 400, description = "Limit out of range or invalid cursor", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "`user_id` names another user", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state))]
+pub async fn list_images(
+    State(state): State<ServerState>,
+    deadline: Deadline,
+    AuthUser { user_id, .. }: AuthUser,
+    Query(params): Query<ListImagesParams>,
+) -> ApiResult<([(header::HeaderName, &'static str); 1], Json<ImageList>)> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        return Err(HttpError::BadRequest(format!("Limit must be between 1 and {MAX_LIST_LIMIT}")).into());
+    }
+    if params.user_id.is_some_and(|requested| requested != user_id) {
+        return Err(HttpError::Forbidden("Images of another user cannot be listed".into()).into());
+    }
+    let prefix = format!("{user_id}/");
+    if let Some(cursor) = &params.cursor
+        && (validate_filename(cursor).is_err() || !cursor.starts_with(&prefix))
+    {
+        return Err(HttpError::BadRequest("Invalid cursor".into()).into());
+    }
+
+    let mut objects = Vec::new();
+    let mut start_after = params.cursor;
+    let mut truncated = true;
+    while objects.len() <= limit && truncated {
+        let page = deadline
+            .run(|| {
+                let page = state
+                    .storage
+                    .list_page(&prefix, start_after.as_deref(), MAX_LIST_LIMIT as i32);
+                metrics::s3_timed("list", page)
+            })
+            .await?;
+        truncated = page.truncated;
+        if let Some(last) = page.objects.last() {
+            start_after = Some(last.key.clone());
+        }
+        // Thumbnails live under `{key}/`, one level below the images.
+        objects.extend(page.objects.into_iter().filter(|o| !o.key[prefix.len()..].contains('/')));
+    }
+    let more = truncated || objects.len() > limit;
+    objects.truncate(limit);
+
+    let storage = &state.storage;
+    let heads = futures_util::future::join_all(
+        objects
+            .iter()
+            .map(|o| deadline.run(move || metrics::s3_timed("head", storage.head(&o.key)))),
+    )
+    .await;
+    let mut images = Vec::with_capacity(objects.len());
+    for (object, head) in objects.into_iter().zip(heads) {
+        // Deleted since it was listed.
+        let Some(head) = head? else {
+            continue;
+        };
+        images.push(ImageEntry {
+            key: object.key,
+            size: object.size,
+            content_type: head.content_type.unwrap_or_else(|| "application/octet-stream".into()),
+            uploaded_at: object.last_modified,
+        });
+    }
+    let next_cursor = if more {
+        images.last().map(|image| image.key.clone())
+    } else {
+        None
+    };
+    Ok((
+        [(header::CACHE_CONTROL, "private, no-store")],
+        Json(ImageList { images, next_cursor }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/images/{user_id}/{filename}",
+    description = "Keys of legacy uploads have no user prefix and are deleted on `/images/{filename}`.",
+    params(("user_id" = Uuid, Path), ("filename" = String, Path)),
+    responses(
+        (status = 200, description = "Deleted along with its thumbnails; a `Delete` event is published", body = ImageName),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Uploaded by another user", body = ErrorBody),
+        (status = 404, description = "No such image", body = ErrorBody),
+        (status = 423, description = "Under legal hold", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, request_id))]
 pub async fn delete_image(
     State(state): State<ServerState>,
-    headers: HeaderMap,
-    Path(filename): Path<String>,
+    deadline: Deadline,
+    RequestId(request_id): RequestId,
+    AuthUser { user_id, .. }: AuthUser,
+    ImageKey(filename): ImageKey,
 ) -> ApiResult<Image> {
-    let _user_id = extract_user_id(&headers)?;
-    validate_filename(&filename)?;
-
-    let exists = state.s3.object_exists(&filename).await?;
-    if !exists {
-        tracing::warn!("File not found: {}", filename);
-        return Err(ApiError::Http(HttpError::NotFound(format!("Image {} not found", filename))));
+    let head = deadline
+        .run(|| metrics::s3_timed("head", state.storage.head(&filename)))
+        .await?;
+    let Some(head) = head else {
+        return Err(image_not_found(&filename));
+    };
+    if ownership_outcome(Some(&head.metadata), user_id) == Some(DeleteOutcome::Forbidden) {
+        tracing::warn!(%user_id, "Refused to delete another user's image: {}", sanitize_echo(&filename));
+        return Err(HttpError::Forbidden("Image belongs to another user".into()).into());
+    }
+    // Objects uploaded before owners were recorded are attributed to whoever deletes them.
+    let owner = head
+        .metadata
+        .get(OWNER_METADATA_KEY)
+        .and_then(|owner| owner.parse().ok())
+        .unwrap_or(user_id);
+
+    match deadline
+        .run(|| metrics::s3_timed("delete", state.storage.delete_object(&filename)))
+        .await
+    {
+        Ok(()) => {}
+        // A concurrent delete of the same key got there first and publishes the event.
+        Err(ApiError::Storage(e)) if e.is_not_found() => return Err(image_not_found(&filename)),
+        Err(e) => return Err(e),
+    }
+    state.downloads.invalidate(&filename);
+    delete_thumbnails(&state, std::slice::from_ref(&filename)).await;
+    if let (Some(quotas), Some(owner)) = (&state.quotas, quota_owner(&head.metadata)) {
+        quotas.record(owner, -head.size).await;
     }
 
-    state.s3.delete_object(&filename).await?;
+    let event = KafkaMessage::new(owner.to_string(), Action::Delete, Some(filename.clone()));
+    let kafka_headers = HashMap::from([("request_id".to_owned(), request_id)]);
+    kafka_health::publish(&state, &filename, event, kafka_headers).await;
 
     Ok(Image::Deleted(filename))
 }
 
-fn validate_filename(filename: &str) -> Result<(), HttpError> {
-    if filename.is_empty() || !filename.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
-        tracing::warn!("Invalid filename: {}", filename);
+fn image_not_found(filename: &str) -> ApiError {
+    let filename = sanitize_echo(filename);
+    tracing::warn!("File not found: {}", filename);
+    ApiError::Http(HttpError::NotFound(format!("Image {} not found", filename)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/images/delete-batch",
+    request_body = BatchDeleteRequest,
+    responses(
+        (status = 200, description = "Outcome per key, also when only some were deleted", body = BatchDeleteResponse),
+        (status = 400, description = "No keys, too many or an invalid one", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+#[tracing::instrument(skip(state, request))]
+pub async fn delete_images_batch(
+    State(state): State<ServerState>,
+    deadline: Deadline,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(request): Json<BatchDeleteRequest>,
+) -> ApiResult<Json<BatchDeleteResponse>> {
+    if request.keys.is_empty() || request.keys.len() > MAX_BATCH_DELETE_KEYS {
+        return Err(HttpError::BadRequest(format!("Expected between 1 and {MAX_BATCH_DELETE_KEYS} keys")).into());
+    }
+    let mut seen = HashSet::new();
+    let mut keys = Vec::with_capacity(request.keys.len());
+    for key in request.keys {
+        validate_filename(&key)?;
+        if seen.insert(key.clone()) {
+            keys.push(key);
+        }
+    }
+
+    let storage = &state.storage;
+    let lookups = futures_util::future::join_all(
+        keys.iter()
+            .map(|key| deadline.run(move || metrics::s3_timed("head", storage.head(key)))),
+    )
+    .await;
+    let mut deletable = Vec::new();
+    // Sizes of the deletable objects that count against the caller's quota.
+    let mut counted = HashMap::new();
+    let outcomes: Vec<Option<DeleteOutcome>> = lookups
+        .into_iter()
+        .zip(&keys)
+        .map(|(lookup, key)| {
+            let head = match lookup {
+                Ok(head) => head,
+                Err(e) => {
+                    tracing::error!(key = %key, "Failed to look up image owner: {e}");
+                    return Some(DeleteOutcome::Error);
+                }
+            };
+            let outcome = ownership_outcome(head.as_ref().map(|h| &h.metadata), user_id)
+                .or_else(|| head.as_ref().is_some_and(|h| h.legal_hold).then_some(DeleteOutcome::Locked));
+            if outcome.is_none() {
+                if let Some(head) = head.as_ref().filter(|h| quota_owner(&h.metadata).is_some()) {
+                    counted.insert(key.clone(), head.size);
+                }
+                deletable.push((key.clone(), head.and_then(|h| h.version_id)));
+            }
+            outcome
+        })
+        .collect();
+
+    // Deleting the looked-up versions makes S3 refuse objects put on hold since the lookup.
+    let failed = if deletable.is_empty() {
+        HashMap::new()
+    } else {
+        match deadline
+            .run(|| metrics::s3_timed("delete_batch", state.storage.delete_versions(&deletable)))
+            .await
+        {
+            Ok(failed) => failed
+                .into_iter()
+                .map(|(key, e)| {
+                    let outcome = if matches!(e, StorageError::ObjectOnHold(_)) {
+                        DeleteOutcome::Locked
+                    } else {
+                        tracing::error!(key = %key, "Failed to delete image: {e}");
+                        DeleteOutcome::Error
+                    };
+                    (key, outcome)
+                })
+                .collect(),
+            Err(e) => {
+                tracing::error!("Batch delete failed: {e}");
+                deletable.into_iter().map(|(key, _)| (key, DeleteOutcome::Error)).collect()
+            }
+        }
+    };
+
+    let results: Vec<KeyOutcome> = keys
+        .into_iter()
+        .zip(outcomes)
+        .map(|(key, outcome)| {
+            let status = outcome
+                .or_else(|| failed.get(&key).copied())
+                .unwrap_or(DeleteOutcome::Deleted);
+            KeyOutcome { key, status }
+        })
+        .collect();
+    let removed: Vec<String> = results
+        .iter()
+        .filter(|r| matches!(r.status, DeleteOutcome::Deleted))
+        .map(|r| r.key.clone())
+        .collect();
+    for key in &removed {
+        state.downloads.invalidate(key);
+    }
+    delete_thumbnails(&state, &removed).await;
+    if let Some(quotas) = &state.quotas {
+        let freed: i64 = removed.iter().filter_map(|key| counted.get(key)).sum();
+        quotas.record(user_id, -freed).await;
+    }
+
+    let events: Vec<KafkaMessage> = results
+        .iter()
+        .filter(|r| r.status == DeleteOutcome::Deleted)
+        .map(|r| KafkaMessage::new(user_id.to_string(), Action::Delete, Some(r.key.clone())))
+        .collect();
+    let deleted = events.len();
+    if !events.is_empty() {
+        match state.producer.send_batch(&events).await {
+            Ok(sent) => {
+                for e in sent.into_iter().filter_map(Result::err) {
+                    tracing::error!("Failed to publish image delete event: {e}");
+                    metrics::kafka_publish_failed("error", 1);
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to publish image delete events: {e}");
+                metrics::kafka_publish_failed("error", events.len() as u64);
+            }
+        }
+    }
+
+    tracing::info!(%user_id, requested = results.len(), deleted, "Batch image delete");
+    Ok(Json(BatchDeleteResponse { deleted, results }))
+}
+
+/// Decodes the upload and renders its thumbnails; an image that cannot be decoded is refused before anything
+/// is stored. Uploads are not decoded at all while thumbnails are disabled.
+async fn render_thumbnails(state: &ServerState, data: &Bytes, content_type: &str) -> Result<Vec<Thumbnail>, HttpError> {
+    if state.thumbnail_sizes.is_empty() {
+        return Ok(Vec::new());
+    }
+    thumbnails::generate_blocking(data.clone(), content_type.to_owned(), state.thumbnail_sizes.clone())
+        .await
+        .map_err(|e| match e {
+            ThumbnailError::Decode(e) => {
+                tracing::warn!("Rejecting undecodable image: {e}");
+                HttpError::BadRequest("Invalid image data".into())
+            }
+            e => HttpError::Internal(e.to_string()),
+        })
+}
+
+/// [`render_thumbnails`] for an upload already in storage. The upload is refused and its object deleted again
+/// when it cannot be read back or decoded.
+async fn render_stored(state: &ServerState, deadline: &Deadline, key: &str, content_type: &str) -> ApiResult<Vec<Thumbnail>> {
+    if state.thumbnail_sizes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rendered = async {
+        let object = deadline
+            .run(|| metrics::s3_timed("download", state.storage.download(key)))
+            .await
+            .map_err(upload_error)?;
+        Ok::<_, ApiError>(render_thumbnails(state, &Bytes::from(object.data), content_type).await?)
+    };
+    let e = match rendered.await {
+        Ok(thumbnails) => return Ok(thumbnails),
+        Err(e) => e,
+    };
+    if let Err(delete) = metrics::s3_timed("delete", state.storage.delete_object(key)).await {
+        tracing::warn!(%key, "Failed to delete refused upload: {delete}");
+    }
+    Err(e)
+}
+
+/// Best effort: a thumbnail that failed to upload only means its size is served from the original.
+async fn store_thumbnails(state: &ServerState, deadline: &Deadline, key: &str, thumbnails: Vec<Thumbnail>) {
+    let uploads = thumbnails.into_iter().map(|thumbnail| async move {
+        let thumbnail_key = thumbnails::thumbnail_key(key, thumbnail.size);
+        if let Err(e) = deadline
+            .run(|| {
+                metrics::s3_timed(
+                    "upload",
+                    state
+                        .storage
+                        .upload(&thumbnail_key, thumbnail.data.into(), thumbnail.content_type),
+                )
+            })
+            .await
+        {
+            tracing::warn!(key = %thumbnail_key, "Failed to upload thumbnail: {e}");
+        }
+    });
+    futures_util::future::join_all(uploads).await;
+}
+
+/// Removes every object derived from `keys`, i.e. stored under `{key}/`. Listing the prefix also finds
+/// thumbnails of sizes that are no longer configured; when it fails, the configured sizes are deleted instead.
+pub(crate) async fn delete_thumbnails(state: &ServerState, keys: &[String]) {
+    let listings = futures_util::future::join_all(keys.iter().map(|key| derived_keys(state, key))).await;
+    let thumbnail_keys: Vec<String> = listings.into_iter().flatten().collect();
+    if thumbnail_keys.is_empty() {
+        return;
+    }
+    for key in &thumbnail_keys {
+        state.downloads.invalidate(key);
+    }
+    match metrics::s3_timed("delete_batch", state.storage.delete_objects(&thumbnail_keys)).await {
+        Ok(failed) => {
+            for (key, e) in failed {
+                tracing::warn!(%key, "Failed to delete thumbnail: {e}");
+            }
+        }
+        Err(e) => tracing::warn!("Failed to delete thumbnails: {e}"),
+    }
+}
+
+async fn derived_keys(state: &ServerState, key: &str) -> Vec<String> {
+    let prefix = format!("{key}/");
+    match metrics::s3_timed("list", state.storage.list(&prefix)).await {
+        Ok(keys) => keys,
+        Err(e) => {
+            tracing::warn!(%key, "Failed to list thumbnails, deleting the configured sizes: {e}");
+            thumbnails::thumbnail_keys(key, &state.thumbnail_sizes).collect()
+        }
+    }
+}
+
+/// The user whose quota the object counts against; objects uploaded while quotas were off count against nobody.
+fn quota_owner(metadata: &HashMap<String, String>) -> Option<Uuid> {
+    if !metadata.contains_key(QUOTA_METADATA_KEY) {
+        return None;
+    }
+    metadata.get(OWNER_METADATA_KEY)?.parse().ok()
+}
+
+/// `None` when `user_id` may delete the object. Objects uploaded before owners were recorded have no owner
+/// and stay deletable by anyone.
+fn ownership_outcome(metadata: Option<&HashMap<String, String>>, user_id: Uuid) -> Option<DeleteOutcome> {
+    let Some(metadata) = metadata else {
+        return Some(DeleteOutcome::NotFound);
+    };
+    match metadata.get(OWNER_METADATA_KEY) {
+        Some(owner) if *owner != user_id.to_string() => Some(DeleteOutcome::Forbidden),
+        _ => None,
+    }
+}
+
+/// Accepts `name` or `prefix/name`, both segments made of ASCII letters, digits, `-` and `_`.
+pub(super) fn validate_filename(filename: &str) -> Result<(), HttpError> {
+    let valid_segment = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let valid = match filename.split_once('/') {
+        Some((prefix, name)) => valid_segment(prefix) && valid_segment(name),
+        None => valid_segment(filename),
+    };
+    if !valid {
+        tracing::warn!("Invalid filename: {}", sanitize_echo(filename));
         return Err(HttpError::BadRequest("Invalid filename".to_owned()));
     }
     Ok(())
@@ -99,7 +770,7 @@ fn validate_filename(filename: &str) -> Result<(), HttpError> {
 
 fn validate_content_type(content_type: &str) -> Result<(), HttpError> {
     if !ALLOWED_CONTENT_TYPES.contains(&content_type) {
-        tracing::warn!("Invalid content type: {}", content_type);
+        tracing::warn!("Invalid content type: {}", sanitize_echo(content_type));
         Err(HttpError::UnsupportedMediaType)
     } else {
         Ok(())
@@ -110,6 +781,21 @@ fn validate_content_type(content_type: &str) -> Result<(), HttpError> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn ownership_outcome_per_key() {
+        let owner = Uuid::now_v7();
+        let owned = HashMap::from([(OWNER_METADATA_KEY.to_owned(), owner.to_string())]);
+        let legacy = HashMap::new();
+
+        assert_eq!(ownership_outcome(Some(&owned), owner), None);
+        assert_eq!(
+            ownership_outcome(Some(&owned), Uuid::now_v7()),
+            Some(DeleteOutcome::Forbidden)
+        );
+        assert_eq!(ownership_outcome(Some(&legacy), owner), None);
+        assert_eq!(ownership_outcome(None, owner), Some(DeleteOutcome::NotFound));
+    }
+
     #[test]
     fn validate_filename_valid_alphanumeric() {
         assert!(validate_filename("abc123").is_ok());
@@ -140,6 +826,18 @@ mod tests {
         assert!(validate_filename("../etc/passwd").is_err());
     }
 
+    #[test]
+    fn validate_filename_valid_with_user_prefix() {
+        assert!(validate_filename("01961f3a-7c44-7e38-bdd5-abc123def456/01961f3b-0000-7e38-bdd5-abc123def456").is_ok());
+    }
+
+    #[test]
+    fn validate_filename_with_more_than_one_slash() {
+        assert!(validate_filename("user/image/thumb_128").is_err());
+        assert!(validate_filename("user/").is_err());
+        assert!(validate_filename("/image").is_err());
+    }
+
     #[test]
     fn validate_filename_with_spaces() {
         assert!(validate_filename("file name").is_err());
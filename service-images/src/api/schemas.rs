@@ -1,18 +1,37 @@
+use crate::caching::CacheHeaders;
 use axum::{
     Json,
-    body::Body,
-    http::StatusCode,
+    body::Bytes,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+const MAX_ECHO_LEN: usize = 256;
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
 
 pub enum Image {
     Created(String),
     Deleted(String),
     File {
         filename: String,
-        data: Vec<u8>,
+        data: Bytes,
         content_type: String,
+        cache: CacheHeaders,
+    },
+    /// Bytes `start..=end` of an object of `size` bytes, answering a `Range` request.
+    Partial {
+        filename: String,
+        data: Bytes,
+        content_type: String,
+        start: u64,
+        end: u64,
+        size: u64,
+        cache: CacheHeaders,
     },
 }
 
@@ -25,11 +44,294 @@ impl IntoResponse for Image {
                 filename,
                 data,
                 content_type,
-            } => Response::builder()
-                .header("Content-Disposition", format!("attachment; filename=\"{filename}\""))
-                .header("Content-Type", content_type)
-                .body(Body::from(data))
-                .unwrap(),
+                cache,
+            } => (
+                [
+                    (header::CONTENT_DISPOSITION, content_disposition(&filename)),
+                    (header::CONTENT_TYPE, content_type_value(&content_type)),
+                    // Uploads are stored under their sniffed type; browsers must not second-guess it.
+                    (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+                    (header::ACCEPT_RANGES, HeaderValue::from_static("bytes")),
+                ],
+                cache,
+                data,
+            )
+                .into_response(),
+            Self::Partial {
+                filename,
+                data,
+                content_type,
+                start,
+                end,
+                size,
+                cache,
+            } => (
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    (header::CONTENT_DISPOSITION, content_disposition(&filename)),
+                    (header::CONTENT_TYPE, content_type_value(&content_type)),
+                    (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+                    (header::ACCEPT_RANGES, HeaderValue::from_static("bytes")),
+                    (header::CONTENT_RANGE, content_range(start, end, size)),
+                ],
+                cache,
+                data,
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Storage state of an image as shown to admins.
+#[derive(Debug, Serialize)]
+pub struct ObjectStatus {
+    pub key: String,
+    pub version_id: Option<String>,
+    pub legal_hold: bool,
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadParams {
+    /// Thumbnail size to serve instead of the original, when one was rendered.
+    pub size: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListImagesParams {
+    /// Defaults to the caller, the only user whose images may be listed.
+    pub user_id: Option<Uuid>,
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageEntry {
+    pub key: String,
+    pub size: i64,
+    pub content_type: String,
+    /// Milliseconds since the Unix epoch.
+    pub uploaded_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageList {
+    pub images: Vec<ImageEntry>,
+    /// Last key of this page; absent once there is nothing left to list.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldRequest {
+    pub on: bool,
+}
+
+/// `null` removes the user's own limit, so the default applies again.
+#[derive(Debug, Deserialize)]
+pub struct QuotaLimitRequest {
+    pub limit_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    /// Account whose image events are delivered.
+    pub account_id: Uuid,
+    pub url: String,
+    /// Key of the `X-Webhook-Signature` HMAC.
+    pub secret: String,
+    /// Subset of [`crate::webhooks::EVENTS`].
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveriesParams {
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchDeleteRequest {
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteOutcome {
+    Deleted,
+    NotFound,
+    Forbidden,
+    /// Under legal hold.
+    Locked,
+    Error,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KeyOutcome {
+    pub key: String,
+    pub status: DeleteOutcome,
+}
+
+/// Returned with `200` even when only some keys were deleted; callers inspect `results`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchDeleteResponse {
+    pub deleted: usize,
+    pub results: Vec<KeyOutcome>,
+}
+
+/// Makes a client-supplied identifier safe to echo into error bodies and logs:
+/// control characters (including CR/LF) are dropped and the result is capped at 256 chars.
+pub fn sanitize_echo(value: &str) -> String {
+    let mut chars = value.chars().filter(|c| !c.is_control());
+    let mut out: String = chars.by_ref().take(MAX_ECHO_LEN).collect();
+    if chars.next().is_some() {
+        out.push_str("...");
+    }
+    out
+}
+
+/// Builds an RFC 6266 `Content-Disposition` value with a quoted ASCII fallback
+/// and an RFC 5987 `filename*` parameter carrying the exact UTF-8 name.
+pub fn content_disposition(filename: &str) -> HeaderValue {
+    let filename = sanitize_echo(filename);
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let value = format!(
+        "attachment; filename=\"{fallback}\"; filename*=UTF-8''{}",
+        encode_ext_value(&filename)
+    );
+    HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+fn content_range(start: u64, end: u64, size: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("bytes {start}-{end}/{size}")).expect("digits are a valid header value")
+}
+
+fn content_type_value(content_type: &str) -> HeaderValue {
+    HeaderValue::from_str(content_type).unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_CONTENT_TYPE))
+}
+
+fn encode_ext_value(value: &str) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => out.push(b as char),
+            _ => {
+                out.push('%');
+                out.push(HEX[(b >> 4) as usize] as char);
+                out.push(HEX[(b & 0x0F) as usize] as char);
+            }
         }
     }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disposition(filename: &str) -> String {
+        content_disposition(filename).to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn content_disposition_plain_name() {
+        assert_eq!(
+            disposition("abc-123"),
+            "attachment; filename=\"abc-123\"; filename*=UTF-8''abc-123"
+        );
+    }
+
+    #[test]
+    fn content_disposition_escapes_quotes() {
+        let value = disposition("evil\"; filename=\"x.exe");
+        assert_eq!(value.matches('"').count(), 2);
+        assert!(value.contains("filename*=UTF-8''evil%22%3B%20filename%3D%22x.exe"));
+    }
+
+    #[test]
+    fn content_disposition_strips_newlines() {
+        let value = disposition("name\r\nSet-Cookie: a=b");
+        assert!(!value.contains('\r') && !value.contains('\n'));
+        assert!(value.contains("filename=\"nameSet-Cookie: a=b\""));
+    }
+
+    #[test]
+    fn content_disposition_encodes_unicode() {
+        let value = disposition("фото.png");
+        assert!(value.is_ascii());
+        assert!(value.contains("filename=\"____.png\""));
+        assert!(value.contains("filename*=UTF-8''%D1%84%D0%BE%D1%82%D0%BE.png"));
+    }
+
+    #[test]
+    fn content_disposition_truncates_long_names() {
+        let value = disposition(&"a".repeat(1024));
+        assert!(value.contains(&format!("filename=\"{}...\"", "a".repeat(256))));
+        assert!(value.len() < 1024);
+    }
+
+    #[test]
+    fn sanitize_echo_single_line() {
+        let echoed = sanitize_echo("line1\nline2\r\tend");
+        assert_eq!(echoed, "line1line2end");
+    }
+
+    #[test]
+    fn sanitize_echo_keeps_short_values() {
+        assert_eq!(sanitize_echo("01961f3a-7c44"), "01961f3a-7c44");
+    }
+
+    #[test]
+    fn sanitize_echo_truncates_to_limit() {
+        let echoed = sanitize_echo(&"b".repeat(1024));
+        assert_eq!(echoed.len(), 256 + 3);
+        assert!(echoed.ends_with("..."));
+    }
+
+    #[test]
+    fn file_response_headers_are_well_formed() {
+        let response = Image::File {
+            filename: "a\"b\nc".into(),
+            data: Bytes::from_static(&[1, 2, 3]),
+            content_type: "image/png\r\nX-Injected: 1".into(),
+            cache: CacheHeaders {
+                e_tag: Some("\"abc\"\r\nX-Injected: 1".into()),
+                ..Default::default()
+            },
+        }
+        .into_response();
+
+        assert_eq!(response.headers()[header::CONTENT_TYPE], DEFAULT_CONTENT_TYPE);
+        assert!(response.headers().get("X-Injected").is_none());
+        let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap();
+        assert!(disposition.starts_with("attachment; filename=\"a_bc\""));
+        assert!(response.headers().get(header::ETAG).is_none());
+    }
 }
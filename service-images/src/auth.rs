@@ -0,0 +1,10 @@
+use crate::state::ServerData;
+pub use service_common::auth::{AuthError, AuthState, AuthUser, Authenticator, Claims};
+#[cfg(feature = "test-util")]
+pub use service_common::auth::{mint_token, mint_token_for};
+
+impl AuthState for ServerData {
+    fn authenticator(&self) -> &Authenticator {
+        &self.auth
+    }
+}
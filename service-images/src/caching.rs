@@ -0,0 +1,132 @@
+use axum::{
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue, Method, StatusCode,
+        header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    },
+    middleware::Next,
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+use axum_prometheus::metrics;
+use chrono::DateTime;
+use std::convert::Infallible;
+
+/// `Cache-Control` of image responses unless `IMAGE_CACHE_CONTROL` says otherwise.
+pub const DEFAULT_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Validators and cache policy sent along with an image body.
+#[derive(Debug, Clone, Default)]
+pub struct CacheHeaders {
+    pub e_tag: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub last_modified: Option<i64>,
+    pub cache_control: Option<HeaderValue>,
+}
+
+impl IntoResponseParts for CacheHeaders {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let headers = res.headers_mut();
+        if let Some(e_tag) = self.e_tag.and_then(|e_tag| HeaderValue::from_str(&e_tag).ok()) {
+            headers.insert(ETAG, e_tag);
+        }
+        if let Some(last_modified) = self.last_modified.and_then(http_date) {
+            headers.insert(LAST_MODIFIED, last_modified);
+        }
+        if let Some(cache_control) = self.cache_control {
+            headers.insert(CACHE_CONTROL, cache_control);
+        }
+        Ok(res)
+    }
+}
+
+/// Turns a `200` carrying an `ETag` or `Last-Modified` the client already has into an empty `304`. Only
+/// conditional `GET`s and `HEAD`s are looked at, and every other status passes through untouched.
+pub async fn not_modified(request: Request, next: Next) -> Response {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let if_none_match = request.headers().get(IF_NONE_MATCH).cloned();
+    let if_modified_since = request.headers().get(IF_MODIFIED_SINCE).cloned();
+    if if_none_match.is_none() && if_modified_since.is_none() {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK || !is_fresh(response.headers(), if_none_match, if_modified_since) {
+        return response;
+    }
+    metrics::counter!("image_downloads_not_modified_total").increment(1);
+    let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+    for name in [ETAG, LAST_MODIFIED, CACHE_CONTROL] {
+        if let Some(value) = response.headers().get(&name) {
+            not_modified.headers_mut().insert(name, value.clone());
+        }
+    }
+    not_modified
+}
+
+/// `If-None-Match` wins when both are sent, as RFC 9110 asks; `If-Modified-Since` is compared in whole seconds.
+fn is_fresh(response: &HeaderMap, if_none_match: Option<HeaderValue>, if_modified_since: Option<HeaderValue>) -> bool {
+    if let Some(condition) = if_none_match {
+        let Some(e_tag) = response.get(ETAG).and_then(|v| v.to_str().ok()) else {
+            return false;
+        };
+        return condition
+            .to_str()
+            .is_ok_and(|c| c.trim() == "*" || c.split(',').any(|tag| weak_eq(tag.trim(), e_tag)));
+    }
+    let since = if_modified_since.as_ref().and_then(parse_http_date);
+    let modified = response.get(LAST_MODIFIED).and_then(parse_http_date);
+    matches!((since, modified), (Some(since), Some(modified)) if modified <= since)
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn http_date(millis: i64) -> Option<HeaderValue> {
+    let date = DateTime::from_timestamp_millis(millis)?;
+    HeaderValue::from_str(&date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).ok()
+}
+
+/// Seconds since the Unix epoch.
+fn parse_http_date(value: &HeaderValue) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value.to_str().ok()?).ok().map(|d| d.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(e_tag: &str, last_modified: i64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_str(e_tag).unwrap());
+        headers.insert(LAST_MODIFIED, http_date(last_modified).unwrap());
+        headers
+    }
+
+    #[test]
+    fn dates_round_trip_in_whole_seconds() {
+        let date = http_date(1_700_000_000_999).unwrap();
+        assert_eq!(date, "Tue, 14 Nov 2023 22:13:20 GMT");
+        assert_eq!(parse_http_date(&date), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn e_tags_take_precedence_over_dates() {
+        let headers = response("\"abc\"", 1_700_000_000_500);
+        let value = |s: &str| Some(HeaderValue::from_str(s).unwrap());
+        let date = value("Tue, 14 Nov 2023 22:13:20 GMT");
+
+        assert!(is_fresh(&headers, value("\"abc\""), None));
+        assert!(is_fresh(&headers, value("\"x\", W/\"abc\""), None));
+        assert!(is_fresh(&headers, value("*"), None));
+        assert!(!is_fresh(&headers, value("\"def\""), date.clone()));
+
+        assert!(is_fresh(&headers, None, date));
+        assert!(!is_fresh(&headers, None, value("Tue, 14 Nov 2023 22:13:19 GMT")));
+        assert!(!is_fresh(&headers, None, value("yesterday")));
+    }
+}
@@ -1,8 +1,146 @@
-pub struct Config {
-    pub host: String,
-    pub port: String,
-    pub origins: String,
+use crate::{
+    caching::DEFAULT_CACHE_CONTROL,
+    kafka_health::PublishFailurePolicy,
+    listener::ListenAddr,
+    logging::{LogConfig, LogFormat},
+};
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use kafka_client::admin::RetentionMinimums;
+use s3_client::S3;
+use scylladb_client::ScyllaConfig;
+use std::{fmt, path::PathBuf, str::FromStr};
+
+pub struct ServerConfig {
+    /// Every address the router is served on, from `LISTEN` or else `HOST` and `PORT`.
+    pub listen: Vec<ListenAddr>,
+    pub cors: CorsConfig,
+    /// Keep images in this directory instead of S3; the S3 variables are then optional.
+    pub storage_dir: Option<PathBuf>,
     pub s3: S3Config,
+    pub kafka: KafkaConfig,
+    /// Bearer token for `/admin/*` routes; admin routes are refused when unset.
+    pub admin_token: Option<String>,
+    /// HS256 secret of the bearer tokens on image routes, shared with service-auth.
+    pub jwt_secret: String,
+    /// Mirrors the bucket into a second S3 endpoint when `REPLICA_ENDPOINT_URL` is set.
+    pub replication: Option<ReplicationConfig>,
+    /// Account webhooks for image events when `WEBHOOKS_SCYLLA_URL` is set.
+    pub webhooks: Option<WebhookConfig>,
+    /// Per-user storage quotas when `QUOTA_SCYLLA_URL` is set.
+    pub quotas: Option<QuotaConfig>,
+    pub downloads: DownloadCacheConfig,
+    pub readiness: ReadinessConfig,
+    /// Longest sides of the thumbnails rendered for each upload; empty disables them.
+    pub thumbnail_sizes: Vec<u32>,
+    /// `Cache-Control` of image downloads; `None` sends none.
+    pub cache_control: Option<HeaderValue>,
+    /// Largest accepted upload in bytes; bodies are streamed to S3, so this does not bound memory.
+    pub max_file_size: u64,
+    /// Uploads per user and minute, in bursts of up to as many; 0 disables the limit.
+    pub upload_rate_limit_per_min: u32,
+    /// Serves Swagger UI for `/openapi.json`; the spec itself is always served.
+    pub swagger_ui: bool,
+    pub upload_scan: UploadScanConfig,
+    pub log: LogConfig,
+}
+
+/// What the CORS layer allows.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    pub origins: AllowedOrigins,
+    pub methods: Vec<Method>,
+    pub headers: Vec<HeaderName>,
+}
+
+pub use service_common::cors::AllowedOrigins;
+
+/// Content scanning of uploads before they are stored.
+pub struct UploadScanConfig {
+    /// clamd's `host:port`; uploads are not scanned when unset. Requires the `clamav` feature.
+    pub clamav_addr: Option<String>,
+    /// Longest a scan may take.
+    pub timeout_ms: u64,
+    /// Store uploads unscanned when the scan times out or clamd is unreachable, instead of refusing them.
+    pub fail_open: bool,
+}
+
+/// Coalescing of concurrent downloads and the short-lived cache behind it.
+pub struct DownloadCacheConfig {
+    /// How long a downloaded object keeps being served from memory; 0 disables the cache but not coalescing.
+    pub ttl_ms: u64,
+    /// Total size of cached objects.
+    pub max_bytes: usize,
+    /// Larger objects are shared between concurrent requests but never cached.
+    pub max_object_bytes: usize,
+}
+
+/// Dependency checks of `/health/ready`.
+pub struct ReadinessConfig {
+    /// How long a round of checks answers further probes.
+    pub cache_ms: u64,
+    /// Whether Kafka being down takes the replica out of rotation; uploads can work without it.
+    pub require_kafka: bool,
+}
+
+pub struct ReplicationConfig {
+    pub replica: S3Config,
+    /// Consumer group of the replication worker; separate from `GROUP_ID` so both see every event.
+    pub group_id: String,
+    /// Attempts per event before it is skipped.
+    pub max_attempts: u32,
+    /// Sync objects under this prefix before tailing the topic; set by `--backfill <prefix>`.
+    pub backfill_prefix: Option<String>,
+}
+
+pub struct WebhookConfig {
+    /// Where subscriptions and the delivery log are kept.
+    pub scylla: ScyllaConfig,
+    /// Consumer group of the dispatcher; separate from `GROUP_ID` so both see every event.
+    pub group_id: String,
+    /// Attempts per event and webhook, the first included.
+    pub max_attempts: u32,
+    /// Wait before the second attempt, doubled for each one after.
+    pub retry_backoff_ms: u64,
+    /// Consecutive failed attempts after which an endpoint is skipped for `circuit_cooldown_secs`.
+    pub circuit_failure_threshold: u32,
+    pub circuit_cooldown_secs: u64,
+    /// Allow loopback and private network targets; only for local development.
+    pub allow_private_targets: bool,
+}
+
+pub struct QuotaConfig {
+    /// Where usage counters and per-user limits are kept.
+    pub scylla: ScyllaConfig,
+    /// Bytes each user may store, unless `user_quota_overrides` has a limit for them.
+    pub default_limit_bytes: u64,
+}
+
+pub struct KafkaConfig {
+    pub brokers: String,
+    pub topic: String,
+    /// Optional audit topic consumed by the same group as `topic`.
+    pub audit_topic: Option<String>,
+    /// Consumer group of the background task that reads image events back.
+    pub group_id: String,
+    pub lag_interval_secs: u64,
+    /// Age after which `/metrics/kafka-lag` flags the last known lag as stale.
+    pub lag_max_staleness_secs: u64,
+    /// Also write the lag to this file, for file-based autoscalers.
+    pub lag_file: Option<PathBuf>,
+    /// Refuse to auto-create topics and check the retention of the existing ones at startup.
+    pub require_existing_topic: bool,
+    pub min_retention_ms: i64,
+    pub min_retention_bytes: Option<i64>,
+    /// Fail startup instead of reporting degraded health when retention is below the minimums.
+    pub retention_strict: bool,
+    /// librdkafka statistics interval, also how often they are mirrored into `/metrics`; 0 disables them.
+    pub stats_interval_ms: u32,
+    /// How often the producer probes the brokers; uploads skip publishing while they are unreachable.
+    pub health_check_interval_secs: u64,
+    /// What uploads do with events that cannot be published.
+    pub publish_failure_policy: PublishFailurePolicy,
+    /// Events kept in memory under [`PublishFailurePolicy::Buffer`].
+    pub publish_buffer_size: usize,
 }
 
 pub struct S3Config {
@@ -11,42 +149,523 @@ pub struct S3Config {
     pub region: String,
     pub endpoint_url: String,
     pub bucket: String,
+    /// How often the primary bucket is probed; image event consumption is paused while it is unreachable.
+    /// Unused for the replica.
+    pub health_check_interval_secs: u64,
 }
 
-impl Config {
-    pub fn from_env() -> Self {
-        Self {
-            host: read_env_var("HOST"),
-            port: read_env_var("PORT"),
-            origins: read_env_var("ORIGINS"),
+impl S3Config {
+    pub async fn connect(&self) -> S3 {
+        let bucket: &'static str = Box::leak(self.bucket.clone().into_boxed_str());
+        S3::new(
+            self.access_key.clone(),
+            self.secret_key.clone(),
+            self.region.clone(),
+            self.endpoint_url.clone(),
+            bucket,
+        )
+        .await
+    }
+}
+
+impl KafkaConfig {
+    pub fn retention_minimums(&self) -> RetentionMinimums {
+        RetentionMinimums {
+            retention_ms: self.min_retention_ms,
+            retention_bytes: self.min_retention_bytes,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads the process environment; see [`Self::from_lookup`].
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads every variable through `lookup` and reports all missing or invalid ones together, so a
+    /// misconfigured deployment is fixed in one round.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut env = Env {
+            lookup: &lookup,
+            problems: Vec::new(),
+        };
+        let storage_dir = env.optional("STORAGE_DIR").map(PathBuf::from);
+        let config = Self {
+            listen: listen_from_env(&mut env),
+            cors: CorsConfig {
+                origins: env.parse_required("ORIGINS").unwrap_or(AllowedOrigins::List(Vec::new())),
+                methods: env.parse_list("CORS_ALLOW_METHODS", vec![Method::GET, Method::POST, Method::DELETE]),
+                headers: env.parse_list("CORS_ALLOW_HEADERS", vec![header::CONTENT_TYPE, header::ACCEPT]),
+            },
             s3: S3Config {
-                access_key: read_env_var("ACCESS_KEY"),
-                secret_key: read_env_var("SECRET_KEY"),
-                region: read_env_var("REGION"),
-                endpoint_url: read_env_var("ENDPOINT_URL"),
-                bucket: read_env_var("BUCKET"),
+                access_key: env.required_for_s3("ACCESS_KEY", &storage_dir),
+                secret_key: env.required_for_s3("SECRET_KEY", &storage_dir),
+                region: env.required_for_s3("REGION", &storage_dir),
+                endpoint_url: env.required_for_s3("ENDPOINT_URL", &storage_dir),
+                bucket: env.required_for_s3("BUCKET", &storage_dir),
+                health_check_interval_secs: env.parse_nonzero("S3_HEALTH_CHECK_INTERVAL_SECS", 10),
+            },
+            storage_dir,
+            kafka: KafkaConfig {
+                brokers: env.required("BROKERS"),
+                topic: env.required("TOPIC"),
+                audit_topic: env.optional("AUDIT_TOPIC"),
+                group_id: env.required("GROUP_ID"),
+                lag_interval_secs: env.parse_nonzero("KAFKA_LAG_INTERVAL_SECS", 15),
+                lag_max_staleness_secs: env.parse("KAFKA_LAG_MAX_STALENESS_SECS", 60),
+                lag_file: env.optional("KAFKA_LAG_FILE").map(PathBuf::from),
+                require_existing_topic: env.parse("KAFKA_REQUIRE_EXISTING_TOPIC", false),
+                min_retention_ms: env.parse("KAFKA_MIN_RETENTION_MS", 604_800_000),
+                min_retention_bytes: env.parse_optional("KAFKA_MIN_RETENTION_BYTES"),
+                retention_strict: env.parse("KAFKA_RETENTION_STRICT", false),
+                stats_interval_ms: env.parse("KAFKA_STATS_INTERVAL_MS", 5000),
+                health_check_interval_secs: env.parse_nonzero("KAFKA_HEALTH_CHECK_INTERVAL_SECS", 5),
+                publish_failure_policy: env.parse("KAFKA_PUBLISH_FAILURE_POLICY", PublishFailurePolicy::Ignore),
+                publish_buffer_size: env.parse("KAFKA_PUBLISH_BUFFER_SIZE", 1000),
+            },
+            admin_token: env.optional("ADMIN_TOKEN"),
+            jwt_secret: env.required("JWT_SECRET"),
+            replication: ReplicationConfig::from_env(&mut env),
+            webhooks: WebhookConfig::from_env(&mut env),
+            quotas: QuotaConfig::from_env(&mut env),
+            downloads: DownloadCacheConfig {
+                ttl_ms: env.parse("DOWNLOAD_CACHE_TTL_MS", 5000),
+                max_bytes: env.parse("DOWNLOAD_CACHE_MAX_BYTES", 64 * 1024 * 1024),
+                max_object_bytes: env.parse("DOWNLOAD_CACHE_MAX_OBJECT_BYTES", 8 * 1024 * 1024),
+            },
+            readiness: ReadinessConfig {
+                cache_ms: env.parse("READY_CACHE_MS", 2000),
+                require_kafka: env.parse("READY_REQUIRE_KAFKA", true),
             },
+            thumbnail_sizes: env.parse_list("THUMBNAIL_SIZES", vec![128, 512]),
+            cache_control: match env.get("IMAGE_CACHE_CONTROL") {
+                Some(_) => env.parse_optional("IMAGE_CACHE_CONTROL"),
+                None => Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL)),
+            },
+            max_file_size: env.parse("MAX_FILE_SIZE", 10 * 1024 * 1024),
+            upload_rate_limit_per_min: env.parse("UPLOAD_RATE_LIMIT_PER_MIN", 10),
+            swagger_ui: env.parse("SWAGGER_UI", false),
+            upload_scan: UploadScanConfig::from_env(&mut env),
+            log: log_from_env(&mut env),
+        };
+        if config.storage_dir.is_some() && config.replication.is_some() {
+            env.problems
+                .push("REPLICA_ENDPOINT_URL mirrors the S3 bucket, which is not used with STORAGE_DIR".to_owned());
+        }
+        if env.problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { problems: env.problems })
+        }
+    }
+}
+
+impl UploadScanConfig {
+    fn from_env(env: &mut Env) -> Self {
+        let clamav_addr = env.optional("CLAMAV_ADDR");
+        if clamav_addr.is_some() && !cfg!(feature = "clamav") {
+            env.problems
+                .push("CLAMAV_ADDR is set, but the service was built without the clamav feature".to_owned());
+        }
+        Self {
+            clamav_addr,
+            timeout_ms: env.parse("UPLOAD_SCAN_TIMEOUT_MS", 5000),
+            fail_open: env.parse("UPLOAD_SCAN_FAIL_OPEN", false),
+        }
+    }
+}
+
+impl ReplicationConfig {
+    fn from_env(env: &mut Env) -> Option<Self> {
+        let endpoint_url = env.optional("REPLICA_ENDPOINT_URL")?;
+        Some(Self {
+            replica: S3Config {
+                access_key: env.required("REPLICA_ACCESS_KEY"),
+                secret_key: env.required("REPLICA_SECRET_KEY"),
+                region: env.required("REPLICA_REGION"),
+                endpoint_url,
+                bucket: env.required("REPLICA_BUCKET"),
+                health_check_interval_secs: 0,
+            },
+            group_id: env
+                .optional("REPLICATION_GROUP_ID")
+                .unwrap_or_else(|| format!("{}-replication", env.get("GROUP_ID").unwrap_or_default())),
+            max_attempts: env.parse("REPLICATION_MAX_ATTEMPTS", 5),
+            backfill_prefix: None,
+        })
+    }
+}
+
+impl QuotaConfig {
+    fn from_env(env: &mut Env) -> Option<Self> {
+        let uri = env.optional("QUOTA_SCYLLA_URL")?;
+        Some(Self {
+            scylla: ScyllaConfig {
+                uri,
+                keyspace: env.optional("QUOTA_SCYLLA_KEYSPACE").unwrap_or_else(|| "images".into()),
+                replication_factor: env.parse("QUOTA_SCYLLA_REPLICATION_FACTOR", 1),
+                ..Default::default()
+            },
+            default_limit_bytes: env.parse("UPLOAD_QUOTA_BYTES", 500 * 1024 * 1024),
+        })
+    }
+}
+
+impl WebhookConfig {
+    fn from_env(env: &mut Env) -> Option<Self> {
+        let uri = env.optional("WEBHOOKS_SCYLLA_URL")?;
+        Some(Self {
+            scylla: ScyllaConfig {
+                uri,
+                keyspace: env.optional("WEBHOOKS_SCYLLA_KEYSPACE").unwrap_or_else(|| "images".into()),
+                replication_factor: env.parse("WEBHOOKS_SCYLLA_REPLICATION_FACTOR", 1),
+                ..Default::default()
+            },
+            group_id: env
+                .optional("WEBHOOKS_GROUP_ID")
+                .unwrap_or_else(|| format!("{}-webhooks", env.get("GROUP_ID").unwrap_or_default())),
+            max_attempts: env.parse("WEBHOOK_MAX_ATTEMPTS", 5),
+            retry_backoff_ms: env.parse("WEBHOOK_RETRY_BACKOFF_MS", 1000),
+            circuit_failure_threshold: env.parse("WEBHOOK_CIRCUIT_FAILURE_THRESHOLD", 5),
+            circuit_cooldown_secs: env.parse("WEBHOOK_CIRCUIT_COOLDOWN_SECS", 60),
+            allow_private_targets: env.parse("WEBHOOK_ALLOW_PRIVATE_TARGETS", false),
+        })
+    }
+}
+
+/// Every missing or invalid variable found by [`ServerConfig::from_env`], one per line.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid configuration:{}", .problems.iter().map(|p| format!("\n  - {p}")).collect::<String>())]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+/// Variable lookup that records problems instead of stopping at the first one. Invalid values are
+/// replaced by their default so that reading can go on.
+struct Env<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    problems: Vec<String>,
+}
+
+impl Env<'_> {
+    fn get(&self, key: &str) -> Option<String> {
+        (self.lookup)(key)
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        self.get(key).unwrap_or_else(|| {
+            self.problems.push(format!("{key} is not set"));
+            String::new()
+        })
+    }
+
+    /// Required unless images are kept in `storage_dir`.
+    fn required_for_s3(&mut self, key: &str, storage_dir: &Option<PathBuf>) -> String {
+        match storage_dir {
+            Some(_) => self.get(key).unwrap_or_default(),
+            None => self.required(key),
+        }
+    }
+
+    /// Unset and empty are the same.
+    fn optional(&self, key: &str) -> Option<String> {
+        self.get(key).filter(|v| !v.trim().is_empty())
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str, default: T) -> T
+    where
+        T::Err: fmt::Display,
+    {
+        match self.get(key) {
+            Some(value) => self.parse_value(key, &value).unwrap_or(default),
+            None => default,
         }
     }
+
+    /// Periods and intervals, which cannot be 0.
+    fn parse_nonzero(&mut self, key: &str, default: u64) -> u64 {
+        match self.parse(key, default) {
+            0 => {
+                self.problems.push(format!("{key} must be greater than 0"));
+                default
+            }
+            value => value,
+        }
+    }
+
+    fn parse_required<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.get(key) {
+            Some(value) => self.parse_value(key, &value),
+            None => {
+                self.problems.push(format!("{key} is not set"));
+                None
+            }
+        }
+    }
+
+    fn parse_optional<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let value = self.optional(key)?;
+        self.parse_value(key, &value)
+    }
+
+    /// Comma-separated values; empty entries are skipped, so an empty variable is an empty list.
+    fn parse_list<T: FromStr>(&mut self, key: &str, default: Vec<T>) -> Vec<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let Some(value) = self.get(key) else {
+            return default;
+        };
+        let parsed: Option<Vec<T>> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| self.parse_value(key, s))
+            .collect();
+        parsed.unwrap_or(default)
+    }
+
+    fn parse_value<T: FromStr>(&mut self, key: &str, value: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        value
+            .trim()
+            .parse()
+            .map_err(|e| self.problems.push(format!("{key} is invalid ({value:?}): {e}")))
+            .ok()
+    }
 }
 
-fn read_env_var(key: &str) -> String {
-    std::env::var(key).unwrap_or_else(|_| panic!("Required environment variable {key} is not set"))
+fn log_from_env(env: &mut Env) -> LogConfig {
+    let file = env.optional("LOG_FILE").map(PathBuf::from);
+    if file.as_ref().is_some_and(|f| f.file_name().is_none()) {
+        env.problems.push("LOG_FILE must name a file, not a directory".to_owned());
+    }
+    LogConfig {
+        format: env.parse("LOG_FORMAT", LogFormat::Compact),
+        file: file.filter(|f| f.file_name().is_some()),
+    }
 }
 
-impl Default for Config {
+fn listen_from_env(env: &mut Env) -> Vec<ListenAddr> {
+    if let Some(listen) = env.optional("LISTEN") {
+        return crate::listener::parse_list(&listen).unwrap_or_else(|e| {
+            env.problems.push(format!("LISTEN is invalid: {e}"));
+            Vec::new()
+        });
+    }
+    let (host, port) = (env.required("HOST"), env.required("PORT"));
+    vec![ListenAddr::Tcp(format!("{host}:{port}"))]
+}
+
+impl Default for ServerConfig {
     fn default() -> Self {
-        Config {
-            host: "0.0.0.0".into(),
-            port: "3000".into(),
-            origins: "[http://localhost:8080,http://127.0.0.1:8080]".into(),
+        ServerConfig {
+            listen: vec![ListenAddr::Tcp("0.0.0.0:3000".into())],
+            cors: CorsConfig {
+                origins: AllowedOrigins::List(vec![
+                    HeaderValue::from_static("http://localhost:8080"),
+                    HeaderValue::from_static("http://127.0.0.1:8080"),
+                ]),
+                methods: vec![Method::GET, Method::POST, Method::DELETE],
+                headers: vec![header::CONTENT_TYPE, header::ACCEPT],
+            },
+            storage_dir: None,
             s3: S3Config {
                 access_key: "admin".into(),
                 secret_key: "admin12345".into(),
                 region: "us-east-1".into(),
                 endpoint_url: "http://localhost:9000".into(),
                 bucket: "my-bucket".into(),
+                health_check_interval_secs: 10,
+            },
+            kafka: KafkaConfig {
+                brokers: "localhost:9092".into(),
+                topic: "images".into(),
+                audit_topic: None,
+                group_id: "service-images".into(),
+                lag_interval_secs: 15,
+                lag_max_staleness_secs: 60,
+                lag_file: None,
+                require_existing_topic: false,
+                min_retention_ms: 604_800_000,
+                min_retention_bytes: None,
+                retention_strict: false,
+                stats_interval_ms: 5000,
+                health_check_interval_secs: 5,
+                publish_failure_policy: PublishFailurePolicy::Ignore,
+                publish_buffer_size: 1000,
+            },
+            admin_token: None,
+            jwt_secret: "local-development-secret-0123456789".into(),
+            replication: None,
+            webhooks: None,
+            quotas: None,
+            downloads: DownloadCacheConfig {
+                ttl_ms: 5000,
+                max_bytes: 64 * 1024 * 1024,
+                max_object_bytes: 8 * 1024 * 1024,
+            },
+            readiness: ReadinessConfig {
+                cache_ms: 2000,
+                require_kafka: true,
             },
+            thumbnail_sizes: vec![128, 512],
+            cache_control: Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL)),
+            max_file_size: 10 * 1024 * 1024,
+            upload_rate_limit_per_min: 10,
+            swagger_ui: false,
+            upload_scan: UploadScanConfig {
+                clamav_addr: None,
+                timeout_ms: 5000,
+                fail_open: false,
+            },
+            log: LogConfig::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+        let vars: HashMap<_, _> = vars.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+        ServerConfig::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    const REQUIRED: &[(&str, &str)] = &[
+        ("HOST", "127.0.0.1"),
+        ("PORT", "3000"),
+        ("ORIGINS", "http://localhost:8080"),
+        ("ACCESS_KEY", "admin"),
+        ("SECRET_KEY", "admin12345"),
+        ("REGION", "us-east-1"),
+        ("ENDPOINT_URL", "http://localhost:9000"),
+        ("BUCKET", "images"),
+        ("BROKERS", "localhost:9092"),
+        ("TOPIC", "images"),
+        ("GROUP_ID", "service-images"),
+        ("JWT_SECRET", "local-development-secret-0123456789"),
+    ];
+
+    #[test]
+    fn defaults_fill_in_optional_variables() {
+        let Ok(config) = from_vars(REQUIRED) else {
+            panic!("valid configuration refused");
+        };
+        assert_eq!(config.listen, [ListenAddr::Tcp("127.0.0.1:3000".into())]);
+        assert_eq!(config.thumbnail_sizes, [128, 512]);
+        assert_eq!(config.cors.methods, [Method::GET, Method::POST, Method::DELETE]);
+        assert_eq!(config.kafka.publish_failure_policy, PublishFailurePolicy::Ignore);
+        assert_eq!(config.log.format, LogFormat::Compact);
+        assert!(config.replication.is_none() && config.webhooks.is_none() && config.quotas.is_none());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut vars: Vec<_> = REQUIRED
+            .iter()
+            .copied()
+            .filter(|(k, _)| !["BUCKET", "JWT_SECRET"].contains(k))
+            .collect();
+        vars.extend([
+            ("MAX_FILE_SIZE", "10MB"),
+            ("THUMBNAIL_SIZES", "128,big"),
+            ("KAFKA_PUBLISH_FAILURE_POLICY", "retry"),
+            ("WEBHOOKS_SCYLLA_URL", "localhost:9042"),
+            ("WEBHOOK_ALLOW_PRIVATE_TARGETS", "yes"),
+        ]);
+
+        let Err(error) = from_vars(&vars) else {
+            panic!("invalid configuration accepted");
+        };
+        let keys: Vec<_> = error.problems.iter().map(|p| p.split_whitespace().next().unwrap()).collect();
+        assert_eq!(
+            keys,
+            [
+                "BUCKET",
+                "KAFKA_PUBLISH_FAILURE_POLICY",
+                "JWT_SECRET",
+                "WEBHOOK_ALLOW_PRIVATE_TARGETS",
+                "THUMBNAIL_SIZES",
+                "MAX_FILE_SIZE"
+            ]
+        );
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid configuration:\n  - BUCKET is not set\n")
+        );
+    }
+
+    #[test]
+    fn zero_intervals_are_refused() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([
+            ("KAFKA_LAG_INTERVAL_SECS", "0"),
+            ("S3_HEALTH_CHECK_INTERVAL_SECS", "0"),
+            ("KAFKA_HEALTH_CHECK_INTERVAL_SECS", "0"),
+        ]);
+        let Err(error) = from_vars(&vars) else {
+            panic!("invalid configuration accepted");
+        };
+        assert_eq!(
+            error.problems,
+            [
+                "S3_HEALTH_CHECK_INTERVAL_SECS must be greater than 0",
+                "KAFKA_LAG_INTERVAL_SECS must be greater than 0",
+                "KAFKA_HEALTH_CHECK_INTERVAL_SECS must be greater than 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_origins_are_reported() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("ORIGINS", "http://localhost:8080,not an origin"));
+        vars.push(("CORS_ALLOW_METHODS", "GET,POST,PATCH"));
+        let Err(error) = from_vars(&vars) else {
+            panic!("invalid configuration accepted");
+        };
+        assert_eq!(error.problems.len(), 1);
+        assert!(error.problems[0].starts_with("ORIGINS is invalid"), "{}", error.problems[0]);
+    }
+
+    #[test]
+    fn derived_group_ids_follow_group_id() {
+        let mut vars = REQUIRED.to_vec();
+        vars.extend([("WEBHOOKS_SCYLLA_URL", "localhost:9042"), ("LISTEN", "tcp://0.0.0.0:8080")]);
+        let Ok(config) = from_vars(&vars) else {
+            panic!("valid configuration refused");
+        };
+        assert_eq!(config.webhooks.unwrap().group_id, "service-images-webhooks");
+        assert_eq!(config.listen, [ListenAddr::Tcp("0.0.0.0:8080".into())]);
+    }
+
+    #[test]
+    fn storage_dir_makes_s3_optional() {
+        let s3 = ["ACCESS_KEY", "SECRET_KEY", "REGION", "ENDPOINT_URL", "BUCKET"];
+        let mut vars: Vec<_> = REQUIRED.iter().copied().filter(|(k, _)| !s3.contains(k)).collect();
+        vars.push(("STORAGE_DIR", "/var/lib/images"));
+        let Ok(config) = from_vars(&vars) else {
+            panic!("valid configuration refused");
+        };
+        assert_eq!(config.storage_dir, Some(PathBuf::from("/var/lib/images")));
+
+        vars.push(("REPLICA_ENDPOINT_URL", "http://replica:9000"));
+        let Err(error) = from_vars(&vars) else {
+            panic!("replication without S3 accepted");
+        };
+        assert!(error.problems.last().unwrap().starts_with("REPLICA_ENDPOINT_URL"));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::{ApiError, ApiResult, HttpError};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use std::{convert::Infallible, time::Duration};
+use tokio::time::Instant;
+
+/// Budget of every request; matches the router's `TimeoutLayer`.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Remaining budget in milliseconds set by an upstream caller; capped at [`REQUEST_TIMEOUT`].
+pub const DEADLINE_HEADER: &str = "X-Request-Deadline";
+
+/// Point in time after which nobody is waiting for the response, so storage calls are not worth starting.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        Self(Instant::now() + budget)
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())
+    }
+
+    /// Runs `op` with whatever budget is left; `op` is not started at all once the deadline has passed.
+    pub async fn run<F, Fut, T, E>(&self, op: F) -> ApiResult<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<ApiError>,
+    {
+        let remaining = self.remaining().ok_or(HttpError::DeadlineExceeded)?;
+        match tokio::time::timeout(remaining, op()).await {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => Err(HttpError::DeadlineExceeded.into()),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Deadline {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let budget = parts
+            .headers
+            .get(DEADLINE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|ms| Duration::from_millis(ms).min(REQUEST_TIMEOUT))
+            .unwrap_or(REQUEST_TIMEOUT);
+        Ok(Deadline::after(budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn extract(header: Option<&str>) -> Deadline {
+        let mut request = Request::builder();
+        if let Some(value) = header {
+            request = request.header(DEADLINE_HEADER, value);
+        }
+        let (mut parts, ()) = request.body(()).unwrap().into_parts();
+        Deadline::from_request_parts(&mut parts, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn header_sets_budget_capped_at_request_timeout() {
+        assert!(extract(Some("50")).await.remaining().unwrap() <= Duration::from_millis(50));
+        assert!(extract(Some("600000")).await.remaining().unwrap() <= REQUEST_TIMEOUT);
+        assert!(extract(Some("soon")).await.remaining().unwrap() > Duration::from_secs(9));
+        assert!(extract(Some("0")).await.remaining().is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_deadline_skips_the_call() {
+        let calls = AtomicUsize::new(0);
+        let result = Deadline::after(Duration::ZERO)
+            .run(|| async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Ok::<_, HttpError>(())
+            })
+            .await;
+
+        assert!(matches!(result, Err(ApiError::Http(HttpError::DeadlineExceeded))));
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn slow_call_is_cut_off_at_the_deadline() {
+        let started = Instant::now();
+        let result = Deadline::after(Duration::from_millis(20))
+            .run(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok::<_, HttpError>(())
+            })
+            .await;
+
+        assert!(matches!(result, Err(ApiError::Http(HttpError::DeadlineExceeded))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::storage::{S3Object, StorageResult};
+use axum::body::Bytes;
+use axum_prometheus::metrics;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// A downloaded object; cloning shares the body instead of copying it.
+#[derive(Debug, Clone)]
+pub struct SharedObject {
+    pub data: Bytes,
+    pub content_type: Option<String>,
+    pub e_tag: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub last_modified: Option<i64>,
+}
+
+impl From<S3Object> for SharedObject {
+    fn from(object: S3Object) -> Self {
+        Self {
+            data: Bytes::from(object.data),
+            content_type: object.content_type,
+            e_tag: object.e_tag,
+            last_modified: object.last_modified,
+        }
+    }
+}
+
+struct CacheEntry {
+    object: SharedObject,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    bytes: usize,
+}
+
+impl Cache {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.object.data.len();
+        }
+    }
+
+    fn purge_expired(&mut self, now: Instant) {
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+    }
+
+    /// Drops the entries closest to expiry until `size` more bytes fit under `max_bytes`.
+    fn make_room(&mut self, size: usize, max_bytes: usize) {
+        while self.bytes + size > max_bytes {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, e)| e.expires).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+}
+
+/// Single-flight layer in front of S3 downloads: concurrent requests for one key wait for a single fetch and
+/// share its body, which is then kept for a short TTL to absorb the rest of a burst.
+///
+/// Objects above `max_object_bytes` are still shared with requests that arrive while they are being fetched,
+/// but are not cached. A failed or cancelled fetch is not shared: the waiting requests start over, and one of
+/// them fetches again.
+pub struct DownloadCoalescer {
+    ttl: Duration,
+    max_bytes: usize,
+    max_object_bytes: usize,
+    in_flight: Mutex<HashMap<String, watch::Receiver<Option<SharedObject>>>>,
+    cache: Mutex<Cache>,
+}
+
+enum Role {
+    Leader(watch::Sender<Option<SharedObject>>),
+    Follower(watch::Receiver<Option<SharedObject>>),
+}
+
+/// Unregisters the leader's fetch however it ends, so followers never wait on an abandoned one.
+struct Flight<'a> {
+    coalescer: &'a DownloadCoalescer,
+    key: &'a str,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.coalescer
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.key);
+    }
+}
+
+impl DownloadCoalescer {
+    /// A zero `ttl` disables the cache but keeps coalescing.
+    pub fn new(ttl: Duration, max_bytes: usize, max_object_bytes: usize) -> Self {
+        Self {
+            ttl,
+            max_bytes,
+            max_object_bytes: max_object_bytes.min(max_bytes),
+            in_flight: Mutex::new(HashMap::new()),
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Returns `key` from the cache, from a fetch already in flight, or by running `fetch`.
+    pub async fn download<F, Fut>(&self, key: &str, fetch: F) -> StorageResult<SharedObject>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = StorageResult<S3Object>>,
+    {
+        loop {
+            if let Some(object) = self.cached(key) {
+                metrics::counter!("image_download_cache_hits_total").increment(1);
+                return Ok(object);
+            }
+
+            let role = {
+                let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+                match in_flight.get(key) {
+                    Some(rx) => Role::Follower(rx.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        in_flight.insert(key.to_owned(), rx);
+                        Role::Leader(tx)
+                    }
+                }
+            };
+
+            match role {
+                Role::Follower(mut rx) => {
+                    metrics::counter!("image_downloads_coalesced_total").increment(1);
+                    // An error means the leader failed or was cancelled; try again from the top.
+                    if let Ok(object) = rx.wait_for(Option::is_some).await
+                        && let Some(object) = object.as_ref()
+                    {
+                        return Ok(object.clone());
+                    }
+                }
+                Role::Leader(tx) => {
+                    let _flight = Flight { coalescer: self, key };
+                    let object = SharedObject::from(fetch().await?);
+                    self.store(key, &object);
+                    tx.send_replace(Some(object.clone()));
+                    return Ok(object);
+                }
+            }
+        }
+    }
+
+    /// Forgets the cached copy of `key`, e.g. after it was deleted; other replicas keep theirs until it expires.
+    pub fn invalidate(&self, key: &str) {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+
+    fn cached(&self, key: &str) -> Option<SharedObject> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        match cache.entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.object.clone()),
+            Some(_) => {
+                cache.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: &str, object: &SharedObject) {
+        let size = object.data.len();
+        if self.ttl.is_zero() || size > self.max_object_bytes {
+            return;
+        }
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.remove(key);
+        cache.purge_expired(now);
+        cache.make_room(size, self.max_bytes);
+        cache.bytes += size;
+        cache.entries.insert(
+            key.to_owned(),
+            CacheEntry {
+                object: object.clone(),
+                expires: now + self.ttl,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageError;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Fetch double that counts its calls and answers with `size` bytes after a short delay.
+    fn counting_fetch(
+        calls: &AtomicUsize,
+        size: usize,
+    ) -> impl Fn() -> std::pin::Pin<Box<dyn Future<Output = StorageResult<S3Object>> + Send>> {
+        move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(S3Object {
+                    data: vec![7; size],
+                    content_type: Some("image/png".into()),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads_share_one_fetch() {
+        let coalescer = Arc::new(DownloadCoalescer::new(Duration::ZERO, 1024, 1024));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let downloads = (0..200).map(|_| {
+            let coalescer = Arc::clone(&coalescer);
+            let calls = Arc::clone(&calls);
+            tokio::spawn(async move { coalescer.download("hot.png", counting_fetch(&calls, 16)).await })
+        });
+        for download in futures_util::future::join_all(downloads).await {
+            assert_eq!(download.unwrap().unwrap().data.len(), 16);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_absorbs_bursts_until_ttl() {
+        let coalescer = DownloadCoalescer::new(Duration::from_millis(200), 1024, 1024);
+        let calls = AtomicUsize::new(0);
+
+        coalescer.download("a.png", counting_fetch(&calls, 16)).await.unwrap();
+        coalescer.download("a.png", counting_fetch(&calls, 16)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        coalescer.download("a.png", counting_fetch(&calls, 16)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn large_objects_are_not_cached() {
+        let coalescer = DownloadCoalescer::new(Duration::from_secs(5), 1024, 64);
+        let calls = AtomicUsize::new(0);
+
+        for _ in 0..2 {
+            coalescer.download("big.png", counting_fetch(&calls, 65)).await.unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_evicts_to_stay_under_size_cap() {
+        let coalescer = DownloadCoalescer::new(Duration::from_secs(5), 40, 40);
+        let calls = AtomicUsize::new(0);
+
+        for key in ["a", "b", "c"] {
+            coalescer.download(key, counting_fetch(&calls, 16)).await.unwrap();
+        }
+        assert!(coalescer.cache.lock().unwrap().bytes <= 40);
+        coalescer.download("c", counting_fetch(&calls, 16)).await.unwrap();
+        coalescer.download("a", counting_fetch(&calls, 16)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_fetch_is_not_cached() {
+        let coalescer = DownloadCoalescer::new(Duration::from_secs(5), 1024, 1024);
+        let failing = || async { Err(StorageError::NotFound) };
+
+        assert!(coalescer.download("a.png", failing).await.is_err());
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+        let calls = AtomicUsize::new(0);
+        coalescer.download("a.png", counting_fetch(&calls, 16)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
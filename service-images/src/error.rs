@@ -1,19 +1,67 @@
 #![allow(dead_code)]
 
+use crate::{quota::QuotaStatus, storage::StorageError};
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
-use s3_client::error::S3Error;
+use kafka_client::error::KafkaError;
+use scylladb_client::error::ScyllaError;
 use serde_json::json;
+use service_common::admin::AdminRejection;
+pub use service_common::error::ErrorBody;
+use std::{io, time::Duration};
 
 pub type ApiResult<T> = Result<T, ApiError>;
 
+/// Why [`crate::ServerBuilder::new`] could not start the service.
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error("Failed to create the {what}: {source}")]
+    Kafka {
+        what: &'static str,
+        #[source]
+        source: Box<KafkaError>,
+    },
+    #[error("Kafka topic check failed for {topic}: {source}")]
+    KafkaTopic {
+        topic: String,
+        #[source]
+        source: Box<KafkaError>,
+    },
+    #[error("Failed to connect to the webhook store: {0}")]
+    WebhookStore(Box<ScyllaError>),
+    #[error("Failed to connect to the quota store: {0}")]
+    QuotaStore(Box<ScyllaError>),
+    #[error("Failed to open the storage directory: {0}")]
+    Storage(io::Error),
+    #[error("Failed to bind listeners: {0}")]
+    Bind(#[from] io::Error),
+}
+
+impl ServerError {
+    /// For `map_err` on the construction of a Kafka client.
+    pub(crate) fn kafka(what: &'static str) -> impl FnOnce(KafkaError) -> Self {
+        move |source| Self::Kafka {
+            what,
+            source: Box::new(source),
+        }
+    }
+}
+
+impl From<ScyllaError> for ServerError {
+    fn from(err: ScyllaError) -> Self {
+        ServerError::WebhookStore(Box::new(err))
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum HttpError {
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
     #[error("Forbidden: {0}")]
     Forbidden(String),
     #[error("Not found: {0}")]
@@ -24,24 +72,82 @@ pub enum HttpError {
     NotImplemented,
     #[error("Unsupported media type")]
     UnsupportedMediaType,
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+    #[error("Request deadline exceeded")]
+    DeadlineExceeded,
+    /// Carries the size of the object, sent as `Content-Range: bytes */size`.
+    #[error("Range not satisfiable")]
+    RangeNotSatisfiable(u64),
+    /// Carries how long until the next request is accepted, sent as `Retry-After`.
+    #[error("Too many requests")]
+    TooManyRequests(Duration),
+    /// Refused by the upload interceptor; carries its reason.
+    #[error("Upload rejected: {0}")]
+    UploadRejected(String),
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+    /// The upload would take the user past their storage quota.
+    #[error("Upload quota exceeded: {} of {} bytes used", .0.used, .0.limit)]
+    QuotaExceeded(QuotaStatus),
 }
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
-        let (status, e) = match self {
-            Self::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
-            Self::Forbidden(e) => (StatusCode::FORBIDDEN, e),
-            Self::NotFound(e) => (StatusCode::NOT_FOUND, e),
-            Self::NotImplemented => (StatusCode::NOT_IMPLEMENTED, "Not implemented".to_owned()),
-            Self::UnsupportedMediaType => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type".to_owned()),
+        let (status, code, e) = match self {
+            Self::BadRequest(e) => (StatusCode::BAD_REQUEST, "bad_request", e),
+            Self::Unauthorized(e) => (StatusCode::UNAUTHORIZED, "unauthorized", e),
+            Self::Forbidden(e) => (StatusCode::FORBIDDEN, "forbidden", e),
+            Self::NotFound(e) => (StatusCode::NOT_FOUND, "not_found", e),
+            Self::NotImplemented => (StatusCode::NOT_IMPLEMENTED, "not_implemented", "Not implemented".to_owned()),
+            Self::UnsupportedMediaType => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Unsupported media type".to_owned(),
+            ),
+            Self::PayloadTooLarge(e) => (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", e),
+            Self::DeadlineExceeded => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "deadline_exceeded",
+                "Request deadline exceeded".to_owned(),
+            ),
+            Self::RangeNotSatisfiable(size) => {
+                let body = Json(ErrorBody::new("range_not_satisfiable", "Range not satisfiable"));
+                let content_range = format!("bytes */{size}");
+                return (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, content_range)],
+                    body,
+                )
+                    .into_response();
+            }
+            Self::TooManyRequests(retry_after) => {
+                let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                let body = Json(ErrorBody::new("too_many_requests", "Too many requests"));
+                return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after)], body).into_response();
+            }
+            Self::UploadRejected(reason) => {
+                return ErrorBody::new("upload_rejected", "Upload rejected")
+                    .with_message(reason)
+                    .into_response(StatusCode::UNPROCESSABLE_ENTITY);
+            }
+            Self::QuotaExceeded(status) => {
+                return ErrorBody::new("quota_exceeded", "Upload quota exceeded")
+                    .with_extra(json!({"used": status.used, "limit": status.limit}))
+                    .into_response(StatusCode::FORBIDDEN);
+            }
+            Self::ServiceUnavailable(e) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable", e),
             Self::Internal(e) => {
                 tracing::error!("Internal server error: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_owned())
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "Internal Server Error".to_owned(),
+                )
             }
         };
 
-        let body = Json(json!({"error": e}));
-        (status, body).into_response()
+        ErrorBody::new(code, e).into_response(status)
     }
 }
 
@@ -49,43 +155,74 @@ impl IntoResponse for HttpError {
 pub enum ApiError {
     #[error("Http error: {0}")]
     Http(#[from] HttpError),
-    #[error("S3 error: {0}")]
-    S3(Box<S3Error>),
+    #[error("Storage error: {0}")]
+    Storage(Box<StorageError>),
+    #[error("Kafka error: {0}")]
+    Kafka(Box<KafkaError>),
+    #[error("Scylla error: {0}")]
+    Scylla(Box<ScyllaError>),
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         match self {
             ApiError::Http(e) => e.into_response(),
-            ApiError::S3(e) => s3_error_response(*e),
+            ApiError::Storage(e) => storage_error_response(*e),
+            ApiError::Kafka(e) => kafka_error_response(*e),
+            ApiError::Scylla(e) => HttpError::Internal(e.to_string()).into_response(),
+        }
+    }
+}
+
+/// Without a token the request is anonymous, hence `401`; a wrong token or disabled admin routes are `403`.
+impl From<AdminRejection> for HttpError {
+    fn from(e: AdminRejection) -> Self {
+        match e {
+            AdminRejection::MissingToken => HttpError::Unauthorized(e.to_string()),
+            AdminRejection::Disabled | AdminRejection::InvalidToken => HttpError::Forbidden(e.to_string()),
         }
     }
 }
 
-impl From<S3Error> for ApiError {
-    fn from(err: S3Error) -> Self {
-        ApiError::S3(Box::new(err))
+impl From<AdminRejection> for ApiError {
+    fn from(e: AdminRejection) -> Self {
+        ApiError::Http(e.into())
     }
 }
 
-fn s3_error_response(err: S3Error) -> Response {
-    let (status, error_type) = match &err {
-        S3Error::GetObjectError(_) => (StatusCode::NOT_FOUND, "GetObjectError"),
-        S3Error::HeaderObjectError(_) => (StatusCode::NOT_FOUND, "HeadObjectError"),
-        S3Error::BucketNotEmpty => (StatusCode::CONFLICT, "BucketNotEmpty"),
-        S3Error::MissingETag => (StatusCode::INTERNAL_SERVER_ERROR, "MissingETag"),
-        S3Error::MissingUploadId => (StatusCode::INTERNAL_SERVER_ERROR, "MissingUploadId"),
-        S3Error::IO(_) => (StatusCode::INTERNAL_SERVER_ERROR, "IOError"),
-        S3Error::ByteStreamError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "ByteStreamError"),
-        S3Error::BuildError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "BuildError"),
-        S3Error::TokioJoin(_) => (StatusCode::INTERNAL_SERVER_ERROR, "TokioJoinError"),
-        _ => (StatusCode::BAD_REQUEST, "S3Error"),
-    };
+impl From<StorageError> for ApiError {
+    fn from(err: StorageError) -> Self {
+        ApiError::Storage(Box::new(err))
+    }
+}
 
-    let body = Json(json!({
-        "error": error_type,
-        "message": err.to_string(),
-    }));
+impl From<KafkaError> for ApiError {
+    fn from(err: KafkaError) -> Self {
+        ApiError::Kafka(Box::new(err))
+    }
+}
 
-    (status, body).into_response()
+impl From<ScyllaError> for ApiError {
+    fn from(err: ScyllaError) -> Self {
+        ApiError::Scylla(Box::new(err))
+    }
+}
+
+fn kafka_error_response(err: KafkaError) -> Response {
+    match err {
+        KafkaError::TopicNotFound(_) | KafkaError::GroupNotFound(_) => HttpError::NotFound(err.to_string()),
+        _ => HttpError::Internal(err.to_string()),
+    }
+    .into_response()
+}
+
+fn storage_error_response(err: StorageError) -> Response {
+    let (status, code, error) = match &err {
+        StorageError::NotFound => (StatusCode::NOT_FOUND, "not_found", "Not found"),
+        StorageError::ObjectOnHold(_) => (StatusCode::LOCKED, "object_on_hold", "Object on hold"),
+        StorageError::Io(_) | StorageError::Backend(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal", "Storage error"),
+    };
+    ErrorBody::new(code, error)
+        .with_message(err.to_string())
+        .into_response(status)
 }
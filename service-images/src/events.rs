@@ -0,0 +1,69 @@
+use crate::storage::StorageError;
+use crate::{api::router::delete_thumbnails, state::ServerState};
+use axum_prometheus::metrics;
+use kafka_client::{
+    consumer::ConsumedMessage,
+    schemas::{Action, KafkaMessage},
+};
+use std::convert::Infallible;
+
+/// Default worker for the image events topic. A `Delete` removes the object and its thumbnails, so deletes
+/// published by other producers take effect here too; the service's own deletes find nothing left to do. A
+/// `Create` is written to the audit log.
+pub async fn handle_image_event(state: ServerState, event: ConsumedMessage<KafkaMessage>) -> Result<(), StorageError> {
+    let action = format!("{:?}", event.message.action).to_lowercase();
+    tracing::info!(
+        user_id = %event.message.user_id,
+        action = %action,
+        key = ?event.message.data,
+        request_id = ?event.headers.get("request_id"),
+        partition = event.partition,
+        offset = event.offset,
+        "Image event consumed"
+    );
+    metrics::counter!("image_events_consumed_total", "action" => action).increment(1);
+
+    match (event.message.action, event.message.data.as_deref()) {
+        (Action::Delete, Some(key)) => delete_image(&state, key).await,
+        (Action::Create, Some(key)) => {
+            tracing::info!(
+                target: "audit",
+                user_id = %event.message.user_id,
+                key,
+                timestamp = ?event.timestamp,
+                "Image created"
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn delete_image(state: &ServerState, key: &str) -> Result<(), StorageError> {
+    match crate::metrics::s3_timed("delete", state.storage.delete_object(key)).await {
+        Ok(()) => {}
+        // A hold is placed on purpose; retrying cannot lift it.
+        Err(StorageError::ObjectOnHold(key)) => {
+            tracing::warn!(%key, "Not deleting image on legal hold");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
+    state.downloads.invalidate(key);
+    delete_thumbnails(state, &[key.to_owned()]).await;
+    Ok(())
+}
+
+/// Handles events from the optional audit topic; they share the image event schema and are only logged.
+pub async fn handle_audit_event(event: ConsumedMessage<KafkaMessage>) -> Result<(), Infallible> {
+    tracing::info!(
+        user_id = %event.message.user_id,
+        action = ?event.message.action,
+        topic = %event.topic,
+        partition = event.partition,
+        offset = event.offset,
+        "Audit event consumed"
+    );
+    metrics::counter!("audit_events_consumed_total").increment(1);
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use super::{RejectReason, UploadInterceptor};
+use async_trait::async_trait;
+use std::io;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Bytes per `INSTREAM` chunk; clamd refuses chunks over its `StreamMaxLength`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Scans uploads with clamd's `INSTREAM` command over TCP, one connection per upload.
+pub struct ClamAvInterceptor {
+    addr: String,
+    /// Store uploads unscanned while clamd cannot be reached; otherwise they are refused.
+    fail_open: bool,
+}
+
+impl ClamAvInterceptor {
+    pub fn new(addr: impl Into<String>, fail_open: bool) -> Self {
+        Self {
+            addr: addr.into(),
+            fail_open,
+        }
+    }
+
+    async fn scan(&self, data: &[u8]) -> io::Result<String> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            stream.write_all(chunk).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        Ok(String::from_utf8_lossy(&reply).trim_end_matches('\0').trim().to_owned())
+    }
+}
+
+#[async_trait]
+impl UploadInterceptor for ClamAvInterceptor {
+    async fn inspect(&self, _content_type: &str, data: &[u8]) -> Result<(), RejectReason> {
+        match self.scan(data).await {
+            Ok(reply) => verdict(&reply),
+            Err(e) if self.fail_open => {
+                tracing::warn!(addr = %self.addr, "clamd unreachable, storing upload unscanned: {e}");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!(addr = %self.addr, "clamd unreachable, refusing upload: {e}");
+                Err(RejectReason("Content scanner unavailable".into()))
+            }
+        }
+    }
+}
+
+/// `stream: OK` passes; `stream: <signature> FOUND` and clamd errors are rejections.
+fn verdict(reply: &str) -> Result<(), RejectReason> {
+    let result = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if result == "OK" {
+        return Ok(());
+    }
+    match result.strip_suffix(" FOUND") {
+        Some(signature) => Err(RejectReason(format!("Malware detected: {signature}"))),
+        None => {
+            tracing::error!(reply, "clamd could not scan upload");
+            Err(RejectReason("Content scan failed".into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_map_to_verdicts() {
+        assert_eq!(verdict("stream: OK"), Ok(()));
+        assert_eq!(
+            verdict("stream: Eicar-Test-Signature FOUND"),
+            Err(RejectReason("Malware detected: Eicar-Test-Signature".into()))
+        );
+        assert!(verdict("INSTREAM size limit exceeded. ERROR").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(feature = "clamav")]
+pub mod clamav;
+
+use crate::{error::HttpError, metrics::upload_scanned};
+use async_trait::async_trait;
+use std::{fmt, time::Duration};
+
+/// Inspects every upload once it has been read and validated, before anything is stored. Implementations
+/// see the whole file, at most `MAX_FILE_SIZE` bytes.
+#[async_trait]
+pub trait UploadInterceptor: Send + Sync {
+    async fn inspect(&self, content_type: &str, data: &[u8]) -> Result<(), RejectReason>;
+}
+
+/// Why an upload was refused; sent to the client as the `message` of the `422`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectReason(pub String);
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Accepts everything; uploads behave the same when no interceptor is configured.
+pub struct NoopInterceptor;
+
+#[async_trait]
+impl UploadInterceptor for NoopInterceptor {
+    async fn inspect(&self, _content_type: &str, _data: &[u8]) -> Result<(), RejectReason> {
+        Ok(())
+    }
+}
+
+/// How long an interceptor may take, and what happens to the upload when it takes longer.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanPolicy {
+    pub timeout: Duration,
+    /// Store the upload anyway on timeout; otherwise it is refused with `503`.
+    pub fail_open: bool,
+}
+
+/// Runs `interceptor` under `policy`: a rejection answers `422`, a timeout `503` unless the policy fails open.
+pub async fn inspect(
+    interceptor: &dyn UploadInterceptor,
+    policy: &ScanPolicy,
+    content_type: &str,
+    data: &[u8],
+) -> Result<(), HttpError> {
+    match tokio::time::timeout(policy.timeout, interceptor.inspect(content_type, data)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(reason)) => {
+            tracing::warn!(%reason, size = data.len(), "Upload rejected by interceptor");
+            upload_scanned("rejected");
+            Err(HttpError::UploadRejected(reason.0))
+        }
+        Err(_) if policy.fail_open => {
+            tracing::warn!(timeout = ?policy.timeout, "Upload scan timed out, storing unscanned");
+            upload_scanned("timeout_allowed");
+            Ok(())
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?policy.timeout, "Upload scan timed out, refusing upload");
+            upload_scanned("timeout_refused");
+            Err(HttpError::ServiceUnavailable("Upload scan timed out".into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Slow;
+
+    #[async_trait]
+    impl UploadInterceptor for Slow {
+        async fn inspect(&self, _content_type: &str, _data: &[u8]) -> Result<(), RejectReason> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        }
+    }
+
+    fn policy(fail_open: bool) -> ScanPolicy {
+        ScanPolicy {
+            timeout: Duration::from_millis(10),
+            fail_open,
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts_fail_closed_or_open_per_policy() {
+        let closed = inspect(&Slow, &policy(false), "image/png", b"data").await;
+        assert!(matches!(closed, Err(HttpError::ServiceUnavailable(_))));
+        assert!(inspect(&Slow, &policy(true), "image/png", b"data").await.is_ok());
+        assert!(inspect(&NoopInterceptor, &policy(false), "image/png", b"data").await.is_ok());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::state::ServerState;
+use axum_prometheus::metrics;
+use kafka_client::schemas::KafkaMessage;
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Upper bound of one producer health probe.
+pub(crate) const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What happens to an image event that cannot be published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishFailurePolicy {
+    /// Log and drop it; the upload itself still succeeds.
+    Ignore,
+    /// Keep it in memory and publish it once the producer is healthy again.
+    Buffer,
+}
+
+impl FromStr for PublishFailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "buffer" => Ok(Self::Buffer),
+            other => Err(format!("unknown publish failure policy {other:?}, expected ignore or buffer")),
+        }
+    }
+}
+
+struct UnpublishedEvent {
+    key: String,
+    event: KafkaMessage,
+    headers: HashMap<String, String>,
+}
+
+/// Image events the upload handler could not publish. Under [`PublishFailurePolicy::Buffer`] they are kept,
+/// oldest dropped first beyond `capacity`, until [`check`] finds the producer healthy again.
+pub struct UnpublishedEvents {
+    policy: PublishFailurePolicy,
+    capacity: usize,
+    queue: Mutex<VecDeque<UnpublishedEvent>>,
+}
+
+impl UnpublishedEvents {
+    pub fn new(policy: PublishFailurePolicy, capacity: usize) -> Self {
+        Self {
+            policy,
+            capacity,
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Events waiting to be published.
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn keep(&self, event: UnpublishedEvent) {
+        if self.policy == PublishFailurePolicy::Ignore || self.capacity == 0 {
+            tracing::warn!(key = %event.key, "Dropping unpublished image event");
+            metrics::counter!("image_events_dropped_total").increment(1);
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.len() == self.capacity
+            && let Some(oldest) = queue.pop_front()
+        {
+            tracing::warn!(key = %oldest.key, capacity = self.capacity, "Event buffer is full, dropping oldest event");
+            metrics::counter!("image_events_dropped_total").increment(1);
+        }
+        queue.push_back(event);
+        metrics::gauge!("image_events_buffered").set(queue.len() as f64);
+    }
+
+    fn take_all(&self) -> VecDeque<UnpublishedEvent> {
+        std::mem::take(&mut *self.queue.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Puts events that still could not be sent back in front of any buffered since.
+    fn restore(&self, mut events: VecDeque<UnpublishedEvent>) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        events.append(&mut queue);
+        while events.len() > self.capacity {
+            events.pop_front();
+            metrics::counter!("image_events_dropped_total").increment(1);
+        }
+        *queue = events;
+        metrics::gauge!("image_events_buffered").set(queue.len() as f64);
+    }
+}
+
+/// Publishes an image event, or hands it straight to the failure policy while the producer is unhealthy so
+/// the request does not wait out the message timeout.
+pub async fn publish(state: &ServerState, key: &str, event: KafkaMessage, headers: HashMap<String, String>) {
+    if !state.producer.is_healthy() {
+        tracing::debug!(%key, "Kafka producer is unhealthy, skipping image event publish");
+        crate::metrics::kafka_publish_failed("unhealthy", 1);
+    } else {
+        match state.producer.send_with_headers(key, &event, &headers).await {
+            Ok((partition, offset)) => {
+                tracing::debug!(%key, partition, offset, "Published image event");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to publish image event: {e}");
+                crate::metrics::kafka_publish_failed("error", 1);
+            }
+        }
+    }
+    state.unpublished.keep(UnpublishedEvent {
+        key: key.to_owned(),
+        event,
+        headers,
+    });
+}
+
+/// Probes the producer once and, if it is healthy, publishes the buffered events in order.
+pub async fn check(state: &ServerState) -> bool {
+    let healthy = state.producer.check_health(CHECK_TIMEOUT).await;
+    metrics::gauge!("kafka_producer_healthy").set(if healthy { 1.0 } else { 0.0 });
+    if !healthy || state.unpublished.is_empty() {
+        return healthy;
+    }
+
+    let mut events = state.unpublished.take_all();
+    let total = events.len();
+    while let Some(pending) = events.front() {
+        if let Err(e) = state
+            .producer
+            .send_with_headers(&pending.key, &pending.event, &pending.headers)
+            .await
+        {
+            tracing::warn!(remaining = events.len(), "Failed to publish buffered image events: {e}");
+            break;
+        }
+        events.pop_front();
+    }
+    tracing::info!(published = total - events.len(), total, "Published buffered image events");
+    state.unpublished.restore(events);
+    healthy
+}
+
+/// Runs [`check`] every `interval` until `shutdown` is cancelled, so an unhealthy producer is noticed without
+/// paying a timeout per request and flips back as soon as the brokers answer again.
+pub async fn run(state: ServerState, interval: Duration, shutdown: CancellationToken) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+        check(&state).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kafka_client::schemas::Action;
+
+    fn event(key: &str) -> UnpublishedEvent {
+        UnpublishedEvent {
+            key: key.into(),
+            event: KafkaMessage::new("user".into(), Action::Create, Some(key.into())),
+            headers: HashMap::new(),
+        }
+    }
+
+    fn keys(events: &UnpublishedEvents) -> Vec<String> {
+        events.take_all().into_iter().map(|e| e.key).collect()
+    }
+
+    #[test]
+    fn policy_parses_from_env_values() {
+        assert_eq!("ignore".parse(), Ok(PublishFailurePolicy::Ignore));
+        assert_eq!("buffer".parse(), Ok(PublishFailurePolicy::Buffer));
+        assert!("retry".parse::<PublishFailurePolicy>().is_err());
+    }
+
+    #[test]
+    fn ignore_policy_keeps_nothing() {
+        let events = UnpublishedEvents::new(PublishFailurePolicy::Ignore, 10);
+        events.keep(event("a"));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn buffer_drops_oldest_beyond_capacity() {
+        let events = UnpublishedEvents::new(PublishFailurePolicy::Buffer, 2);
+        for key in ["a", "b", "c"] {
+            events.keep(event(key));
+        }
+        assert_eq!(keys(&events), ["b", "c"]);
+    }
+
+    #[test]
+    fn restored_events_stay_ahead_of_newer_ones() {
+        let events = UnpublishedEvents::new(PublishFailurePolicy::Buffer, 3);
+        events.keep(event("a"));
+        events.keep(event("b"));
+        let taken = events.take_all();
+        events.keep(event("c"));
+        events.restore(taken);
+        assert_eq!(keys(&events), ["a", "b", "c"]);
+    }
+}
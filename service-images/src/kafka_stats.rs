@@ -0,0 +1,61 @@
+use crate::state::ServerState;
+use axum_prometheus::metrics;
+use kafka_client::stats::{ConsumerStats, ProducerStats, StatsHandle};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Mirrors the librdkafka statistics of the image event producer and consumer into Prometheus gauges
+/// every `interval` until `shutdown` is cancelled.
+pub async fn run(
+    state: ServerState,
+    consumer: StatsHandle<ConsumerStats>,
+    group_id: String,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        if let Some(stats) = state.producer.stats() {
+            record_producer(&stats);
+        }
+        if let Some(stats) = consumer.get() {
+            record_consumer(&group_id, &stats);
+        }
+    }
+    tracing::info!("Kafka statistics exporter stopped");
+}
+
+fn record_producer(stats: &ProducerStats) {
+    metrics::gauge!("kafka_producer_queue_messages").set(stats.msg_cnt as f64);
+    metrics::gauge!("kafka_producer_sent_messages").set(stats.txmsgs as f64);
+    metrics::gauge!("kafka_producer_outbuf_requests").set(stats.outbuf_cnt as f64);
+    for broker in &stats.brokers {
+        let rtts = [
+            ("0.5", broker.rtt_p50_us),
+            ("0.95", broker.rtt_p95_us),
+            ("0.99", broker.rtt_p99_us),
+        ];
+        for (quantile, rtt_us) in rtts {
+            metrics::gauge!("kafka_broker_rtt_seconds", "broker" => broker.name.clone(), "quantile" => quantile)
+                .set(rtt_us as f64 / 1_000_000.0);
+        }
+        metrics::gauge!("kafka_broker_errors", "broker" => broker.name.clone()).set(broker.errors as f64);
+    }
+}
+
+fn record_consumer(group_id: &str, stats: &ConsumerStats) {
+    for partition in &stats.partitions {
+        metrics::gauge!(
+            "kafka_consumer_partition_lag",
+            "group" => group_id.to_owned(),
+            "topic" => partition.topic.clone(),
+            "partition" => partition.partition.to_string()
+        )
+        .set(partition.lag as f64);
+    }
+}
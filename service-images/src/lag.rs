@@ -0,0 +1,151 @@
+use axum_prometheus::metrics;
+use kafka_client::lag::LagProbe;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LagSnapshot {
+    /// Last successfully computed total lag, if any.
+    pub lag: Option<i64>,
+    pub age: Option<Duration>,
+    pub max_staleness: Duration,
+    /// Set when the last refresh failed or the value is older than `max_staleness`.
+    pub stale: bool,
+}
+
+/// Last known consumer group lag, refreshed by [`run`] and read by `/metrics/kafka-lag`.
+pub struct LagWatcher {
+    max_staleness: Duration,
+    last: RwLock<Option<(i64, Instant)>>,
+    failing: AtomicBool,
+}
+
+impl LagWatcher {
+    pub fn new(max_staleness: Duration) -> Self {
+        Self {
+            max_staleness,
+            last: RwLock::new(None),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn record(&self, lag: i64, at: Instant) {
+        *self.last.write().unwrap_or_else(|e| e.into_inner()) = Some((lag, at));
+        self.failing.store(false, Ordering::Relaxed);
+    }
+
+    /// Keeps the last known value but marks it stale until the next successful refresh.
+    pub fn record_failure(&self) {
+        self.failing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> LagSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    pub fn snapshot_at(&self, now: Instant) -> LagSnapshot {
+        let last = *self.last.read().unwrap_or_else(|e| e.into_inner());
+        let age = last.map(|(_, at)| now.saturating_duration_since(at));
+        LagSnapshot {
+            lag: last.map(|(lag, _)| lag),
+            age,
+            max_staleness: self.max_staleness,
+            stale: self.failing.load(Ordering::Relaxed) || age.is_none_or(|age| age > self.max_staleness),
+        }
+    }
+}
+
+/// Refreshes `watcher` every `interval` until `shutdown` is cancelled, mirroring the value into
+/// Prometheus gauges and, when configured, into `file` for file-based autoscalers.
+pub async fn run(
+    probe: LagProbe,
+    watcher: Arc<LagWatcher>,
+    interval: Duration,
+    file: Option<PathBuf>,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        match probe.total_lag().await {
+            Ok(lag) => watcher.record(lag, Instant::now()),
+            Err(e) => {
+                tracing::warn!(group_id = %probe.group_id(), "Failed to compute consumer lag: {e}");
+                watcher.record_failure();
+            }
+        }
+
+        let snapshot = watcher.snapshot();
+        let group_id = probe.group_id().to_owned();
+        if let Some(lag) = snapshot.lag {
+            metrics::gauge!("kafka_consumer_lag", "group" => group_id.clone()).set(lag as f64);
+        }
+        metrics::gauge!("kafka_consumer_lag_stale", "group" => group_id).set(if snapshot.stale { 1.0 } else { 0.0 });
+
+        if let (Some(path), Some(lag)) = (&file, snapshot.lag)
+            && let Err(e) = write_lag_file(path, lag).await
+        {
+            tracing::warn!(path = %path.display(), "Failed to write consumer lag file: {e}");
+        }
+    }
+    tracing::info!("Consumer lag watcher stopped");
+}
+
+/// Writes through a temporary file so scalers never read a partially written value.
+async fn write_lag_file(path: &Path, lag: i64) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, format!("{lag}\n")).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopped_watcher_goes_stale_but_keeps_value() {
+        let watcher = LagWatcher::new(Duration::from_secs(60));
+        let now = Instant::now();
+        watcher.record(42, now);
+
+        let snapshot = watcher.snapshot_at(now + Duration::from_secs(61));
+        assert_eq!(snapshot.lag, Some(42));
+        assert!(snapshot.stale);
+    }
+
+    #[test]
+    fn failed_refresh_marks_stale_until_next_success() {
+        let watcher = LagWatcher::new(Duration::from_secs(60));
+        let now = Instant::now();
+        watcher.record(42, now);
+        watcher.record_failure();
+        assert!(watcher.snapshot_at(now).stale);
+
+        watcher.record(7, now);
+        let snapshot = watcher.snapshot_at(now);
+        assert_eq!(snapshot.lag, Some(7));
+        assert!(!snapshot.stale);
+    }
+
+    #[tokio::test]
+    async fn lag_file_holds_plain_value() {
+        let dir = std::env::temp_dir().join(format!("kafka-lag-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("lag");
+
+        write_lag_file(&path, 1234).await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "1234\n");
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,102 +1,356 @@
 mod api;
+pub mod auth;
+pub mod caching;
 pub mod config;
+pub mod deadline;
+pub mod downloads;
 pub mod error;
+pub mod events;
+pub mod interceptor;
+pub mod kafka_health;
+pub mod kafka_stats;
+pub mod lag;
+pub mod listener;
+pub mod metrics;
+pub mod quota;
+pub mod rate_limit;
+pub mod readiness;
+pub mod replication;
+pub mod request_id;
+pub mod s3_health;
+pub mod self_test;
 pub mod state;
+pub mod storage;
+pub mod thumbnails;
+pub mod variant;
+pub mod webhooks;
+
+pub use service_common::logging;
 
 use api::{
-    not_found, ping,
-    router::{delete_image, download_image, upload_image},
+    admin::{
+        create_webhook, delete_webhook, describe_kafka_group, describe_kafka_topic, describe_object, get_quota, get_webhook,
+        list_kafka_topics, list_webhook_deliveries, set_object_legal_hold, set_quota_limit, storage_stats,
+    },
+    health,
+    lag::kafka_lag,
+    live, not_found,
+    openapi::{self, openapi_json},
+    ping, ready,
+    router::{delete_image, delete_images_batch, download_image, list_images, upload_image},
+};
+use axum::{Router, extract::DefaultBodyLimit, http::StatusCode, middleware, routing};
+use config::{CorsConfig, ServerConfig};
+use error::ServerError;
+use kafka_client::{
+    config::ConsumerConfig,
+    consumer::{ConsumedMessage, KafkaConsumer},
+    lag::LagProbe,
+    router::TopicRouter,
+    schemas::KafkaMessage,
 };
-use axum::{Router, http::StatusCode, routing};
-use config::Config;
+use lag::LagWatcher;
+use listener::Listener;
+use logging::LogConfig;
 use mimalloc::MiMalloc;
+use replication::Replicator;
 use state::ServerState;
-use std::time::Duration;
-use tokio::net::TcpListener;
-use tower_http::{
-    cors::{AllowHeaders, AllowMethods},
-    timeout::TimeoutLayer,
-    trace::TraceLayer,
+use std::{
+    fmt::Display,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
 };
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
+use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
+use webhooks::{WebhookDispatcher, Webhooks};
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
 pub struct ServerBuilder {
-    tcp_listener: TcpListener,
+    listeners: Vec<Listener>,
     router: Router,
-    config: Config,
+    cors: CorsConfig,
+    log: LogConfig,
+    log_guard: Option<logging::LogGuard>,
+    shutdown: CancellationToken,
+    event_consumer: EventConsumer,
+    replication_task: Option<JoinHandle<()>>,
+    webhook_task: Option<JoinHandle<()>>,
+    state: ServerState,
+}
+
+/// Image event consumer, created by [`ServerBuilder::new`] but only started by [`ServerBuilder::run`], so
+/// [`ServerBuilder::with_kafka_worker`] can replace the handler first.
+struct EventConsumer {
+    consumer: KafkaConsumer,
+    router: TopicRouter<KafkaMessage>,
+    topic: String,
+    health_interval: Duration,
 }
 
 impl ServerBuilder {
-    pub async fn new(config: Config) -> Self {
-        let tcp_listener = Self::init_tcp_listener(&config).await;
-        let state = state::ServerData::new(&config).await;
-        let router = Self::init_router(state).layer((
-            TraceLayer::new_for_http(),
-            TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, Duration::from_secs(10)),
+    /// Connects the clients, binds the listeners and starts the background consumers other than the image
+    /// event consumer, which starts with [`Self::run`].
+    pub async fn new(config: ServerConfig) -> Result<Self, ServerError> {
+        // Bind only once the state is built, so proxies never reach a replica that cannot serve yet.
+        let state = state::ServerData::new(&config).await?;
+        let listeners = Self::init_listener(&config).await?;
+        let shutdown = CancellationToken::new();
+        // Stops the tasks already spawned if a later one cannot be created.
+        let spawned = shutdown.clone().drop_guard();
+        Self::spawn_lag_watcher(&config, &state, shutdown.clone())?;
+        tokio::spawn(kafka_health::run(
+            Arc::clone(&state),
+            Duration::from_secs(config.kafka.health_check_interval_secs),
+            shutdown.clone(),
+        ));
+        let event_consumer = Self::init_event_consumer(&config, &state, shutdown.clone())?;
+        let replication_task = Self::spawn_replication(&config, shutdown.clone()).await?;
+        let webhook_task = Self::spawn_webhook_dispatcher(&config, &state, shutdown.clone())?;
+        let mut router = Self::init_router(Arc::clone(&state));
+        if config.swagger_ui {
+            router = router.merge(SwaggerUi::new(openapi::SWAGGER_UI_PATH).config(SwaggerConfig::from(openapi::OPENAPI_PATH)));
+        }
+        let router = router.layer((
+            TraceLayer::new_for_http().make_span_with(variant::make_span),
+            TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, deadline::REQUEST_TIMEOUT),
         ));
 
-        Self {
-            tcp_listener,
+        spawned.disarm();
+
+        Ok(Self {
+            listeners,
             router,
-            config,
+            cors: config.cors.clone(),
+            log: config.log.clone(),
+            log_guard: None,
+            shutdown,
+            event_consumer,
+            replication_task,
+            webhook_task,
+            state,
+        })
+    }
+
+    fn spawn_lag_watcher(config: &ServerConfig, state: &ServerState, shutdown: CancellationToken) -> Result<(), ServerError> {
+        let probe = LagProbe::new(&config.kafka.brokers, &config.kafka.group_id, &config.kafka.topic)
+            .map_err(ServerError::kafka("Kafka lag probe"))?;
+        tokio::spawn(lag::run(
+            probe,
+            Arc::clone(&state.lag),
+            Duration::from_secs(config.kafka.lag_interval_secs),
+            config.kafka.lag_file.clone(),
+            shutdown,
+        ));
+        Ok(())
+    }
+
+    fn init_event_consumer(
+        config: &ServerConfig,
+        state: &ServerState,
+        shutdown: CancellationToken,
+    ) -> Result<EventConsumer, ServerError> {
+        let mut router = image_event_route(TopicRouter::new(), &config.kafka.topic, state, events::handle_image_event);
+        if let Some(audit_topic) = &config.kafka.audit_topic {
+            router = router.route(audit_topic, events::handle_audit_event);
+        }
+
+        let consumer_config = ConsumerConfig::builder_with_topics(&config.kafka.brokers, &config.kafka.group_id, router.topics())
+            .statistics_interval_ms(config.kafka.stats_interval_ms)
+            .build()
+            .map_err(ServerError::kafka("Kafka consumer"))?;
+        let consumer = KafkaConsumer::new(consumer_config).map_err(ServerError::kafka("Kafka consumer"))?;
+
+        if config.kafka.stats_interval_ms > 0 {
+            tokio::spawn(kafka_stats::run(
+                Arc::clone(state),
+                consumer.stats_handle(),
+                config.kafka.group_id.clone(),
+                Duration::from_millis(config.kafka.stats_interval_ms.into()),
+                shutdown.clone(),
+            ));
         }
+
+        Ok(EventConsumer {
+            consumer,
+            router,
+            topic: config.kafka.topic.clone(),
+            health_interval: Duration::from_secs(config.s3.health_check_interval_secs),
+        })
+    }
+
+    fn spawn_event_consumer(event_consumer: EventConsumer, state: ServerState, shutdown: CancellationToken) -> JoinHandle<()> {
+        let EventConsumer {
+            consumer,
+            router,
+            health_interval,
+            ..
+        } = event_consumer;
+        tokio::spawn(async move {
+            let watchdog = s3_health::pause_while_unreachable(&state, &consumer, health_interval, shutdown.clone());
+            let (result, ()) = tokio::join!(consumer.run_with_handler(|msg| router.dispatch(msg), shutdown), watchdog);
+            if let Err(e) = result {
+                tracing::error!("Image event consumer failed: {e}");
+            }
+            consumer.close().await;
+        })
+    }
+
+    /// Runs the optional backfill, then tails the image events topic in its own consumer group. Its lag, the
+    /// events still to replicate, is exported as `kafka_consumer_lag` for that group.
+    async fn spawn_replication(
+        config: &ServerConfig,
+        shutdown: CancellationToken,
+    ) -> Result<Option<JoinHandle<()>>, ServerError> {
+        let Some(replication) = &config.replication else {
+            return Ok(None);
+        };
+        let consumer_config = ConsumerConfig::builder(&config.kafka.brokers, &replication.group_id, &config.kafka.topic)
+            .max_retries(replication.max_attempts)
+            .build()
+            .map_err(ServerError::kafka("replication consumer"))?;
+        let consumer = KafkaConsumer::new(consumer_config).map_err(ServerError::kafka("replication consumer"))?;
+
+        let probe = LagProbe::new(&config.kafka.brokers, &replication.group_id, &config.kafka.topic)
+            .map_err(ServerError::kafka("replication lag probe"))?;
+        tokio::spawn(lag::run(
+            probe,
+            Arc::new(LagWatcher::new(Duration::from_secs(config.kafka.lag_max_staleness_secs))),
+            Duration::from_secs(config.kafka.lag_interval_secs),
+            None,
+            shutdown.clone(),
+        ));
+
+        let replicator = Replicator::new(config.s3.connect().await, replication.replica.connect().await);
+        let backfill_prefix = replication.backfill_prefix.clone();
+        Ok(Some(tokio::spawn(async move {
+            if let Some(prefix) = backfill_prefix {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        tracing::info!(prefix, "Replication backfill interrupted by shutdown");
+                        return;
+                    }
+                    result = replicator.backfill(&prefix) => if let Err(e) = result {
+                        tracing::error!(prefix, "Replication backfill failed: {e}");
+                    },
+                }
+            }
+            if let Err(e) = replicator.run(&consumer, shutdown).await {
+                tracing::error!("Replication consumer failed: {e}");
+            }
+            consumer.close().await;
+        })))
+    }
+
+    /// Delivers image events to account webhooks from its own consumer group, with its lag exported like
+    /// the replication worker's.
+    fn spawn_webhook_dispatcher(
+        config: &ServerConfig,
+        state: &ServerState,
+        shutdown: CancellationToken,
+    ) -> Result<Option<JoinHandle<()>>, ServerError> {
+        let (Some(webhooks), Some(Webhooks { store, .. })) = (&config.webhooks, &state.webhooks) else {
+            return Ok(None);
+        };
+        let consumer_config = ConsumerConfig::builder(&config.kafka.brokers, &webhooks.group_id, &config.kafka.topic)
+            .build()
+            .map_err(ServerError::kafka("webhook consumer"))?;
+        let consumer = KafkaConsumer::new(consumer_config).map_err(ServerError::kafka("webhook consumer"))?;
+
+        let probe = LagProbe::new(&config.kafka.brokers, &webhooks.group_id, &config.kafka.topic)
+            .map_err(ServerError::kafka("webhook lag probe"))?;
+        tokio::spawn(lag::run(
+            probe,
+            Arc::new(LagWatcher::new(Duration::from_secs(config.kafka.lag_max_staleness_secs))),
+            Duration::from_secs(config.kafka.lag_interval_secs),
+            None,
+            shutdown.clone(),
+        ));
+
+        let dispatcher = WebhookDispatcher::new(Arc::clone(store), webhooks, shutdown.clone());
+        Ok(Some(tokio::spawn(async move {
+            if let Err(e) = dispatcher.run(&consumer, shutdown).await {
+                tracing::error!("Webhook consumer failed: {e}");
+            }
+            consumer.close().await;
+        })))
     }
 
-    async fn init_tcp_listener(config: &Config) -> TcpListener {
-        let addr = format!("{}:{}", config.host, config.port);
-        TcpListener::bind(addr).await.expect("the address is busy")
+    async fn init_listener(config: &ServerConfig) -> Result<Vec<Listener>, ServerError> {
+        Ok(listener::bind_all(&config.listen).await?)
     }
 
     pub fn init_router(state: ServerState) -> Router {
         Router::new()
             .route("/ping", routing::get(ping))
-            .route("/images/upload", routing::post(upload_image))
+            .route("/health", routing::get(health))
+            .route("/health/live", routing::get(live))
+            .route("/health/ready", routing::get(ready))
+            .route("/metrics/kafka-lag", routing::get(kafka_lag))
+            .route(openapi::OPENAPI_PATH, routing::get(openapi_json))
+            // The handler streams the file and enforces `MAX_FILE_SIZE` itself.
+            .route(
+                "/images/upload",
+                routing::post(upload_image).layer((
+                    DefaultBodyLimit::disable(),
+                    middleware::from_fn_with_state(Arc::clone(&state), rate_limit::limit_uploads),
+                )),
+            )
+            .route("/images", routing::get(list_images))
+            .route("/images/delete-batch", routing::post(delete_images_batch))
             .route("/images/{filename}", routing::get(download_image).delete(delete_image))
+            .route(
+                "/images/{user_id}/{filename}",
+                routing::get(download_image).delete(delete_image),
+            )
+            .route("/admin/kafka/topics", routing::get(list_kafka_topics))
+            .route("/admin/kafka/topics/{name}", routing::get(describe_kafka_topic))
+            .route("/admin/kafka/groups/{id}", routing::get(describe_kafka_group))
+            .route("/admin/storage", routing::get(storage_stats))
+            .route("/admin/objects/{key}", routing::get(describe_object))
+            .route("/admin/objects/{key}/legal-hold", routing::put(set_object_legal_hold))
+            .route("/admin/objects/{user_id}/{key}", routing::get(describe_object))
+            .route(
+                "/admin/objects/{user_id}/{key}/legal-hold",
+                routing::put(set_object_legal_hold),
+            )
+            .route("/admin/quotas/{user_id}", routing::get(get_quota).put(set_quota_limit))
+            .route("/admin/webhooks", routing::post(create_webhook))
+            .route("/admin/webhooks/{id}", routing::get(get_webhook).delete(delete_webhook))
+            .route("/admin/webhooks/{id}/deliveries", routing::get(list_webhook_deliveries))
             .with_state(state)
             .fallback(not_found)
+            .layer(middleware::from_fn(caching::not_modified))
+            .layer(middleware::from_fn(variant::track))
     }
 
-    pub fn with_cors<M: Into<AllowMethods>, H: Into<AllowHeaders>>(mut self, methods: M, headers: H) -> Self {
-        use axum::http::HeaderValue;
+    /// Allows the origins, methods and headers of [`CorsConfig`].
+    pub fn with_cors(mut self) -> Self {
         use tower_http::cors::CorsLayer;
 
-        let origins = self
-            .config
-            .origins
-            .split(',')
-            .map(|s| s.trim())
-            .map(|s| HeaderValue::from_str(s).expect("Invalid origin in ORIGINS"))
-            .collect::<Vec<_>>();
-
         let cors = CorsLayer::new()
-            .allow_methods(methods)
-            .allow_headers(headers)
-            .allow_origin(origins);
+            .allow_methods(self.cors.methods.clone())
+            .allow_headers(self.cors.headers.clone())
+            .allow_origin(self.cors.origins.allow_origin());
 
         self.router = self.router.layer(cors);
         self
     }
 
-    pub fn with_tracing(self) -> Self {
-        use tracing_subscriber::EnvFilter;
-
-        tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::from_default_env())
-            .compact()
-            .with_file(true)
-            .with_line_number(true)
-            .with_target(false)
-            .init();
-
+    /// Logs in the format and to the destination of [`LogConfig`].
+    pub fn with_tracing(mut self) -> Self {
+        self.log_guard = Some(logging::init(&self.log));
         self
     }
 
     pub fn with_prometheus(mut self) -> Self {
         use axum_prometheus::PrometheusMetricLayer;
 
-        let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
+        let metric_handle = metrics::install();
+        let prometheus_layer = PrometheusMetricLayer::new();
         self.router = self
             .router
             .route("/metrics", routing::get(|| async move { metric_handle.render() }))
@@ -105,19 +359,88 @@ impl ServerBuilder {
         self
     }
 
+    /// Handles image events with `handler` instead of [`events::handle_image_event`]. Failed events are
+    /// retried per the consumer's retry policy, then logged and skipped; each failure is counted in
+    /// `image_events_failed_total`.
+    pub fn with_kafka_worker<F, Fut, E>(mut self, handler: F) -> Self
+    where
+        F: Fn(ServerState, ConsumedMessage<KafkaMessage>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let event_consumer = &mut self.event_consumer;
+        let router = std::mem::take(&mut event_consumer.router);
+        event_consumer.router = image_event_route(router, &event_consumer.topic, &self.state, handler);
+        self
+    }
+
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
-        tracing::info!("listening on http://{}", self.tcp_listener.local_addr()?);
+        for listener in &self.listeners {
+            tracing::info!("listening on {}", listener.local_addr()?);
+        }
 
-        axum::serve(self.tcp_listener, self.router)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+        self.state.ready.store(true, Ordering::Release);
+        let state = Arc::clone(&self.state);
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            state.ready.store(false, Ordering::Release);
+        });
+
+        let consumer_task = Self::spawn_event_consumer(self.event_consumer, Arc::clone(&self.state), self.shutdown.clone());
+        tokio::spawn(shutdown_signal(self.shutdown.clone()));
+        // Outside every other layer, so timeouts, CORS preflights and `/metrics` are logged and echo the id too.
+        let router = self.router.layer(middleware::from_fn(request_id::propagate));
+        listener::serve(self.listeners, router, self.shutdown).await?;
+
+        // The consumer finishes the event in flight before it stops, so nothing is dropped half-handled.
+        if let Err(e) = consumer_task.await {
+            tracing::error!("Image event consumer task panicked: {e}");
+        }
+        if let Some(task) = self.replication_task
+            && let Err(e) = task.await
+        {
+            tracing::error!("Replication task panicked: {e}");
+        }
+        if let Some(task) = self.webhook_task
+            && let Err(e) = task.await
+        {
+            tracing::error!("Webhook task panicked: {e}");
+        }
 
         tracing::info!("Graceful shutdown complete");
         Ok(())
     }
 }
 
-async fn shutdown_signal() {
+/// Routes `topic` to `handler`, counting its failures.
+fn image_event_route<F, Fut, E>(
+    router: TopicRouter<KafkaMessage>,
+    topic: &str,
+    state: &ServerState,
+    handler: F,
+) -> TopicRouter<KafkaMessage>
+where
+    F: Fn(ServerState, ConsumedMessage<KafkaMessage>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let state = Arc::clone(state);
+    router.route(topic, move |event: ConsumedMessage<KafkaMessage>| {
+        let action = format!("{:?}", event.message.action).to_lowercase();
+        let handled = handler(Arc::clone(&state), event);
+        async move {
+            let result = handled.await;
+            if let Err(e) = &result {
+                tracing::warn!(%action, "Image event handler failed: {e}");
+                axum_prometheus::metrics::counter!("image_events_failed_total", "action" => action).increment(1);
+            }
+            result
+        }
+    })
+}
+
+async fn shutdown_signal(shutdown: CancellationToken) {
     let ctrl_c = async {
         tokio::signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
     };
@@ -139,4 +462,5 @@ async fn shutdown_signal() {
     }
 
     tracing::info!("Starting graceful shutdown");
+    shutdown.cancel();
 }
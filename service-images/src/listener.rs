@@ -0,0 +1,260 @@
+use axum::Router;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Permissions of a unix socket without a `mode` parameter: owner and group may connect.
+const DEFAULT_SOCKET_MODE: u32 = 0o660;
+const BACKLOG: i32 = 1024;
+
+/// One entry of `LISTEN`: `tcp://host:port` (IPv6 hosts in brackets, a bare `host:port` works too) or
+/// `unix:///path/to.sock`, optionally with `?mode=600` for the socket's permissions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(String),
+    Unix { path: PathBuf, mode: u32 },
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = s.strip_prefix("unix://") {
+            let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+            if !path.starts_with('/') {
+                return Err(format!("unix socket path must be absolute: {s:?}"));
+            }
+            let mode = match query.strip_prefix("mode=") {
+                Some(mode) => u32::from_str_radix(mode, 8).map_err(|_| format!("invalid socket mode in {s:?}"))?,
+                None if query.is_empty() => DEFAULT_SOCKET_MODE,
+                None => return Err(format!("unknown unix socket option in {s:?}")),
+            };
+            return Ok(Self::Unix {
+                path: PathBuf::from(path),
+                mode,
+            });
+        }
+        let addr = s.strip_prefix("tcp://").unwrap_or(s);
+        match addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && !host.contains('/') && port.parse::<u16>().is_ok() => {
+                Ok(Self::Tcp(addr.to_owned()))
+            }
+            _ => Err(format!("expected tcp://host:port or unix:///path, got {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "tcp://{addr}"),
+            Self::Unix { path, .. } => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Parses a comma-separated `LISTEN` value.
+pub fn parse_list(value: &str) -> Result<Vec<ListenAddr>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Where the listener actually ended up, e.g. with the ephemeral port filled in.
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match self {
+            Self::Tcp(listener) => Ok(ListenAddr::Tcp(listener.local_addr()?.to_string())),
+            #[cfg(unix)]
+            Self::Unix(_, path) => Ok(ListenAddr::Unix {
+                path: path.clone(),
+                mode: DEFAULT_SOCKET_MODE,
+            }),
+        }
+    }
+}
+
+/// Binds every address. `[::]` accepts IPv4 as well unless an IPv4 listener on the same port is configured,
+/// in which case it is restricted to IPv6 so both can bind.
+pub async fn bind_all(addrs: &[ListenAddr]) -> io::Result<Vec<Listener>> {
+    let mut resolved = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        resolved.push(match addr {
+            ListenAddr::Tcp(host_port) => Some(resolve(host_port).await?),
+            ListenAddr::Unix { .. } => None,
+        });
+    }
+    let v4_ports: Vec<u16> = resolved
+        .iter()
+        .flatten()
+        .filter(|a| a.is_ipv4())
+        .map(SocketAddr::port)
+        .collect();
+
+    let mut listeners = Vec::with_capacity(addrs.len());
+    for (addr, socket_addr) in addrs.iter().zip(resolved) {
+        let listener = match (addr, socket_addr) {
+            (_, Some(socket_addr)) => Listener::Tcp(bind_tcp(socket_addr, v4_ports.contains(&socket_addr.port()))?),
+            #[cfg(unix)]
+            (ListenAddr::Unix { path, mode }, None) => Listener::Unix(bind_unix(path, *mode)?, path.clone()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("cannot listen on {addr} on this platform"),
+                ));
+            }
+        };
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+async fn resolve(host_port: &str) -> io::Result<SocketAddr> {
+    tokio::net::lookup_host(host_port)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("{host_port} did not resolve")))
+}
+
+fn bind_tcp(addr: SocketAddr, v6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Removes a socket file left behind by a process that is gone, but refuses to take over one that still
+/// accepts connections or a path that is not a socket.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: u32) -> io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another process", path.display()),
+            ));
+        }
+        tracing::info!(path = %path.display(), "Removing stale unix socket");
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Serves `router` on every listener until `shutdown` is cancelled, then drains them all and removes the
+/// unix socket files.
+pub async fn serve(listeners: Vec<Listener>, router: Router, shutdown: CancellationToken) -> io::Result<()> {
+    let servers = listeners.into_iter().map(|listener| {
+        let router = router.clone();
+        let shutdown = shutdown.clone();
+        async move {
+            match listener {
+                Listener::Tcp(listener) => {
+                    axum::serve(listener, router)
+                        .with_graceful_shutdown(shutdown.cancelled_owned())
+                        .await
+                }
+                #[cfg(unix)]
+                Listener::Unix(listener, path) => {
+                    let result = axum::serve(listener, router)
+                        .with_graceful_shutdown(shutdown.cancelled_owned())
+                        .await;
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        tracing::warn!(path = %path.display(), "Failed to remove unix socket: {e}");
+                    }
+                    result
+                }
+            }
+        }
+    });
+    futures_util::future::try_join_all(servers).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tcp_and_unix_addresses() {
+        assert_eq!(
+            parse_list("tcp://0.0.0.0:3000, [::]:3000,unix:///run/images.sock?mode=600").unwrap(),
+            [
+                ListenAddr::Tcp("0.0.0.0:3000".into()),
+                ListenAddr::Tcp("[::]:3000".into()),
+                ListenAddr::Unix {
+                    path: "/run/images.sock".into(),
+                    mode: 0o600
+                },
+            ]
+        );
+        assert_eq!(
+            "unix:///tmp/a.sock".parse(),
+            Ok(ListenAddr::Unix {
+                path: "/tmp/a.sock".into(),
+                mode: DEFAULT_SOCKET_MODE
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for bad in [
+            "tcp://localhost",
+            "unix://relative.sock",
+            "unix:///a.sock?mode=9",
+            "http://x:1",
+            ":3000",
+        ] {
+            assert!(bad.parse::<ListenAddr>().is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[tokio::test]
+    async fn v6_wildcard_coexists_with_v4_on_the_same_port() {
+        let v4 = bind_all(&[ListenAddr::Tcp("0.0.0.0:0".into())]).await.unwrap();
+        let port = match &v4[0] {
+            Listener::Tcp(listener) => listener.local_addr().unwrap().port(),
+            #[cfg(unix)]
+            Listener::Unix(..) => unreachable!(),
+        };
+        drop(v4);
+        let addrs = [
+            ListenAddr::Tcp(format!("0.0.0.0:{port}")),
+            ListenAddr::Tcp(format!("[::]:{port}")),
+        ];
+        // Hosts without IPv6 cannot bind `[::]` at all.
+        if let Err(e) = bind_all(&addrs).await {
+            assert_eq!(e.kind(), io::ErrorKind::AddrNotAvailable, "{e}");
+        }
+    }
+}
@@ -1,17 +1,34 @@
-use service_images::{ServerBuilder, config::Config};
+use service_images::{ServerBuilder, config::ServerConfig, self_test};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    use axum::http::{Method, header};
-    dotenvy::dotenv()?;
+    // Without a `.env` the configuration has to come from the environment alone, as in CI.
+    if let Err(e) = dotenvy::dotenv()
+        && !e.not_found()
+    {
+        return Err(e.into());
+    }
 
-    let config = Config::from_env();
+    let mut config = ServerConfig::from_env().unwrap_or_else(|e| exit_with(e));
+    if has_flag("--self-test") {
+        let report = self_test::run(&config).await;
+        if has_flag("--json") {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{}", report.to_table());
+        }
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
+    if let Some(prefix) = backfill_prefix() {
+        let Some(replication) = config.replication.as_mut() else {
+            exit_with("--backfill requires REPLICA_ENDPOINT_URL to be set");
+        };
+        replication.backfill_prefix = Some(prefix);
+    }
     ServerBuilder::new(config)
         .await
-        .with_cors(
-            [Method::GET, Method::POST, Method::DELETE],
-            [header::CONTENT_TYPE, header::ACCEPT],
-        )
+        .unwrap_or_else(|e| exit_with(e))
+        .with_cors()
         .with_tracing()
         .with_prometheus()
         .run()
@@ -19,3 +36,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Startup errors are for whoever deploys the service, so they are printed as is rather than as a panic.
+fn exit_with(error: impl std::fmt::Display) -> ! {
+    eprintln!("{error}");
+    std::process::exit(1)
+}
+
+/// `--backfill <prefix>`: sync existing objects under `prefix` to the replica before tailing events.
+fn backfill_prefix() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--backfill" {
+            return Some(args.next().unwrap_or_default());
+        }
+    }
+    None
+}
+
+fn has_flag(flag: &str) -> bool {
+    std::env::args().skip(1).any(|arg| arg == flag)
+}
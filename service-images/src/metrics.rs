@@ -0,0 +1,81 @@
+use axum_prometheus::{
+    metrics::{Unit, counter, describe_counter, describe_histogram, histogram},
+    metrics_exporter_prometheus::PrometheusHandle,
+    utils::SECONDS_DURATION_BUCKETS,
+};
+use service_common::metrics::Recorder;
+use tokio::time::Instant;
+
+pub const IMAGES_UPLOADED_TOTAL: &str = "images_uploaded_total";
+pub const IMAGE_UPLOAD_BYTES: &str = "image_upload_bytes";
+pub const KAFKA_PUBLISH_FAILURES_TOTAL: &str = "kafka_publish_failures_total";
+pub const S3_OPERATION_DURATION_SECONDS: &str = "s3_operation_duration_seconds";
+pub const UPLOAD_SCANS_TOTAL: &str = "upload_scans_total";
+
+/// Powers of four from 1 KiB to 256 MiB, past `MAX_FILE_SIZE`'s default.
+const UPLOAD_BYTES_BUCKETS: &[f64] = &[
+    1024.0,
+    4096.0,
+    16384.0,
+    65536.0,
+    262144.0,
+    1048576.0,
+    4194304.0,
+    16777216.0,
+    67108864.0,
+    268435456.0,
+];
+
+/// Installs the global recorder rendered on `/metrics`, see [`service_common::metrics::install`], with histogram
+/// buckets for the business metrics too.
+pub fn install() -> PrometheusHandle {
+    service_common::metrics::install(&Recorder {
+        buckets: &[
+            (S3_OPERATION_DURATION_SECONDS, SECONDS_DURATION_BUCKETS),
+            (IMAGE_UPLOAD_BYTES, UPLOAD_BYTES_BUCKETS),
+        ],
+        on_install: describe,
+    })
+}
+
+fn describe() {
+    describe_counter!(IMAGES_UPLOADED_TOTAL, "Images stored, by content type");
+    describe_histogram!(IMAGE_UPLOAD_BYTES, Unit::Bytes, "Size of stored images");
+    describe_counter!(
+        KAFKA_PUBLISH_FAILURES_TOTAL,
+        "Image events that could not be published, by reason"
+    );
+    describe_histogram!(
+        S3_OPERATION_DURATION_SECONDS,
+        Unit::Seconds,
+        "Duration of S3 calls, by operation"
+    );
+    describe_counter!(
+        UPLOAD_SCANS_TOTAL,
+        "Uploads refused or let through unscanned by the interceptor, by outcome"
+    );
+}
+
+/// Records a stored upload. `content_type` is one of the allowed image types, never the client's claim.
+pub fn image_uploaded(content_type: &'static str, bytes: u64) {
+    counter!(IMAGES_UPLOADED_TOTAL, "content_type" => content_type).increment(1);
+    histogram!(IMAGE_UPLOAD_BYTES).record(bytes as f64);
+}
+
+/// `reason` is `unhealthy` when the producer was skipped, `error` when the send itself failed.
+pub fn kafka_publish_failed(reason: &'static str, events: u64) {
+    counter!(KAFKA_PUBLISH_FAILURES_TOTAL, "reason" => reason).increment(events);
+}
+
+/// `outcome` is `rejected`, `timeout_allowed` or `timeout_refused`; clean scans are not counted.
+pub fn upload_scanned(outcome: &'static str) {
+    counter!(UPLOAD_SCANS_TOTAL, "outcome" => outcome).increment(1);
+}
+
+/// Runs one S3 call, recording how long it took whether or not it succeeded.
+pub async fn s3_timed<T>(operation: &'static str, call: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let result = call.await;
+    histogram!(S3_OPERATION_DURATION_SECONDS, "operation" => operation).record(started.elapsed().as_secs_f64());
+    result
+}
@@ -0,0 +1,74 @@
+use crate::error::HttpError;
+use scylladb_client::{error::ScyllaResult, quotas::QuotaStore};
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Per-user storage quotas, when `QUOTA_SCYLLA_URL` is set. Originals count against their owner's quota from
+/// upload until delete; thumbnails do not count.
+pub struct Quotas {
+    pub store: Arc<QuotaStore>,
+    /// Applies to every user without a row in `user_quota_overrides`.
+    pub default_limit_bytes: u64,
+}
+
+/// A user's usage against their limit, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QuotaStatus {
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl QuotaStatus {
+    /// Refuses storing `size` more bytes past the limit. With `size` 0, before the upload's size is known,
+    /// only a quota that is already used up refuses.
+    pub fn admit(&self, size: u64) -> Result<(), HttpError> {
+        let remaining = self.limit.saturating_sub(self.used);
+        if remaining == 0 || size > remaining {
+            return Err(HttpError::QuotaExceeded(*self));
+        }
+        Ok(())
+    }
+}
+
+impl Quotas {
+    pub async fn status(&self, user_id: Uuid) -> ScyllaResult<QuotaStatus> {
+        let usage = self.store.usage(user_id).await?;
+        Ok(QuotaStatus {
+            // Deleting objects stored before quotas were enabled can take the counter below zero.
+            used: usage.used_bytes.max(0) as u64,
+            limit: usage
+                .limit_override
+                .map_or(self.default_limit_bytes, |limit| limit.max(0) as u64),
+        })
+    }
+
+    /// Counts `delta` bytes stored (or, negative, deleted) by `user_id`. The objects have changed by then, so a
+    /// failure is only logged and the counter stays off by `delta`.
+    pub async fn record(&self, user_id: Uuid, delta: i64) {
+        if delta == 0 {
+            return;
+        }
+        if let Err(e) = self.store.add_used(user_id, delta).await {
+            tracing::error!(%user_id, delta, "Failed to update quota usage: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads_are_admitted_up_to_the_limit() {
+        let status = QuotaStatus { used: 700, limit: 1000 };
+        assert!(status.admit(0).is_ok());
+        assert!(status.admit(300).is_ok());
+        assert!(matches!(status.admit(301), Err(HttpError::QuotaExceeded(s)) if s == status));
+
+        let full = QuotaStatus { used: 1000, limit: 1000 };
+        assert!(full.admit(0).is_err());
+        let over = QuotaStatus { used: 1200, limit: 1000 };
+        assert!(over.admit(0).is_err());
+    }
+}
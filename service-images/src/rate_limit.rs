@@ -0,0 +1,25 @@
+use crate::{auth::AuthUser, error::HttpError, state::ServerState};
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_prometheus::metrics;
+pub use service_common::rate_limit::RateLimiter;
+
+/// Refuses uploads over `UPLOAD_RATE_LIMIT_PER_MIN` with `429`. Requests without a valid token pass through
+/// unlimited, since the handler refuses them anyway.
+pub async fn limit_uploads(State(state): State<ServerState>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &state.upload_limiter else {
+        return next.run(request).await;
+    };
+    let (mut parts, body) = request.into_parts();
+    if let Ok(AuthUser { user_id, .. }) = AuthUser::from_request_parts(&mut parts, &state).await
+        && let Err(retry_after) = limiter.check(user_id)
+    {
+        tracing::warn!(%user_id, "Upload rate limit exceeded");
+        metrics::counter!("rate_limited_requests_total", "route" => "upload").increment(1);
+        return HttpError::TooManyRequests(retry_after).into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
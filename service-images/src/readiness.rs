@@ -0,0 +1,213 @@
+use crate::state::ServerState;
+use futures_util::future::{BoxFuture, join_all};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Longest a single dependency check may take before it counts as down.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Up,
+    Down,
+    /// Not configured, e.g. Scylla without webhooks.
+    Disabled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyReport {
+    pub status: DependencyStatus,
+    /// Whether the dependency being down makes the replica unready.
+    pub required: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, DependencyReport>,
+}
+
+/// One dependency to probe; `probe` is `None` when it is not configured.
+pub struct Check<'a> {
+    pub name: &'static str,
+    pub required: bool,
+    pub probe: Option<BoxFuture<'a, Result<(), String>>>,
+}
+
+/// Runs every check concurrently, each bounded by `timeout`. Ready unless a required dependency is down.
+pub async fn run_checks(checks: Vec<Check<'_>>, timeout: Duration) -> Readiness {
+    let reports = join_all(checks.into_iter().map(|check| async move {
+        let started = Instant::now();
+        let (status, error) = match check.probe {
+            None => (DependencyStatus::Disabled, None),
+            Some(probe) => match tokio::time::timeout(timeout, probe).await {
+                Ok(Ok(())) => (DependencyStatus::Up, None),
+                Ok(Err(e)) => (DependencyStatus::Down, Some(e)),
+                Err(_) => (
+                    DependencyStatus::Down,
+                    Some(format!("timed out after {}ms", timeout.as_millis())),
+                ),
+            },
+        };
+        let report = DependencyReport {
+            status,
+            required: check.required,
+            latency_ms: started.elapsed().as_millis() as u64,
+            error,
+        };
+        (check.name, report)
+    }))
+    .await;
+    Readiness {
+        ready: !reports
+            .iter()
+            .any(|(_, report)| report.required && report.status == DependencyStatus::Down),
+        checks: reports.into_iter().collect(),
+    }
+}
+
+/// Dependency checks behind `/health/ready`, cached for `ttl` so a storm of probes costs one round of checks.
+pub struct ReadinessProbe {
+    ttl: Duration,
+    kafka_required: bool,
+    /// Held while checks run, so concurrent probes wait for that round instead of starting their own.
+    last: Mutex<Option<(Instant, Readiness)>>,
+}
+
+impl ReadinessProbe {
+    pub fn new(ttl: Duration, kafka_required: bool) -> Self {
+        Self {
+            ttl,
+            kafka_required,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub async fn check(&self, state: &ServerState) -> Readiness {
+        let mut last = self.last.lock().await;
+        if let Some((at, readiness)) = &*last
+            && at.elapsed() < self.ttl
+        {
+            return readiness.clone();
+        }
+
+        let checks = vec![
+            Check {
+                name: state.storage.kind(),
+                required: true,
+                probe: Some(Box::pin(async { state.storage.check().await.map_err(|e| e.to_string()) })),
+            },
+            Check {
+                name: "kafka",
+                required: self.kafka_required,
+                probe: Some(Box::pin(async {
+                    match state.producer.check_health(CHECK_TIMEOUT).await {
+                        true => Ok(()),
+                        false => Err("metadata fetch failed".to_owned()),
+                    }
+                })),
+            },
+            Check {
+                name: "scylla",
+                required: true,
+                probe: state.webhooks.as_ref().map(|webhooks| -> BoxFuture<'_, _> {
+                    Box::pin(async {
+                        match webhooks.store.health_check().await {
+                            true => Ok(()),
+                            false => Err("system.local query failed".to_owned()),
+                        }
+                    })
+                }),
+            },
+            Check {
+                name: "quotas",
+                required: true,
+                probe: state.quotas.as_ref().map(|quotas| -> BoxFuture<'_, _> {
+                    Box::pin(async {
+                        match quotas.store.health_check().await {
+                            true => Ok(()),
+                            false => Err("system.local query failed".to_owned()),
+                        }
+                    })
+                }),
+            },
+        ];
+        let readiness = run_checks(checks, CHECK_TIMEOUT).await;
+        *last = Some((Instant::now(), readiness.clone()));
+        readiness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &'static str, required: bool, result: Result<(), &str>) -> Check<'static> {
+        let result = result.map_err(str::to_owned);
+        Check {
+            name,
+            required,
+            probe: Some(Box::pin(async move { result })),
+        }
+    }
+
+    #[tokio::test]
+    async fn required_failures_make_it_unready() {
+        let readiness = run_checks(
+            vec![check("s3", true, Ok(())), check("kafka", false, Err("metadata fetch failed"))],
+            CHECK_TIMEOUT,
+        )
+        .await;
+        assert!(readiness.ready, "an optional dependency does not count");
+        assert_eq!(readiness.checks["kafka"].status, DependencyStatus::Down);
+        assert_eq!(readiness.checks["kafka"].error.as_deref(), Some("metadata fetch failed"));
+
+        let readiness = run_checks(
+            vec![
+                check("s3", true, Err("unreachable")),
+                Check {
+                    name: "scylla",
+                    required: true,
+                    probe: None,
+                },
+            ],
+            CHECK_TIMEOUT,
+        )
+        .await;
+        assert!(!readiness.ready);
+        assert_eq!(readiness.checks["scylla"].status, DependencyStatus::Disabled);
+    }
+
+    #[tokio::test]
+    async fn slow_checks_time_out_concurrently() {
+        let slow = || Check {
+            name: "slow",
+            required: true,
+            probe: Some(Box::pin(std::future::pending())),
+        };
+        let timeout = Duration::from_millis(100);
+        let started = Instant::now();
+        let readiness = run_checks(
+            vec![
+                slow(),
+                Check {
+                    name: "slower",
+                    ..slow()
+                },
+            ],
+            timeout,
+        )
+        .await;
+        assert!(started.elapsed() < 2 * timeout, "checks ran one after the other");
+        assert!(!readiness.ready);
+        assert_eq!(readiness.checks["slower"].error.as_deref(), Some("timed out after 100ms"));
+    }
+}
@@ -0,0 +1,93 @@
+use axum_prometheus::metrics;
+use kafka_client::{
+    consumer::{ConsumedMessage, KafkaConsumer},
+    error::KafkaResult,
+    schemas::{Action, KafkaMessage, METADATA_OBJECT_KEY},
+};
+use s3_client::{
+    S3,
+    error::{S3Error, S3Result},
+    replication::Replication,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Objects handled by one [`Replicator::backfill`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    pub copied: usize,
+    pub up_to_date: usize,
+    pub failed: usize,
+}
+
+/// Mirrors image objects from the primary bucket into the replica as image events arrive.
+pub struct Replicator {
+    primary: S3,
+    replica: S3,
+}
+
+impl Replicator {
+    pub fn new(primary: S3, replica: S3) -> Self {
+        Self { primary, replica }
+    }
+
+    /// Copies created objects and deletes removed ones on the replica. Errors are returned so the consumer
+    /// retries the event with backoff.
+    pub async fn handle_event(&self, event: ConsumedMessage<KafkaMessage>) -> Result<(), S3Error> {
+        let message = &event.message;
+        let key = message
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get(METADATA_OBJECT_KEY))
+            .or(message.data.as_ref());
+        let Some(key) = key else {
+            tracing::warn!(
+                partition = event.partition,
+                offset = event.offset,
+                "Image event without an object key"
+            );
+            return Ok(());
+        };
+
+        let outcome = match message.action {
+            Action::Create | Action::Update => match self.primary.replicate_to(&self.replica, key).await? {
+                Replication::Copied { .. } => "copied",
+                Replication::UpToDate => "up_to_date",
+                Replication::SourceMissing => "source_missing",
+            },
+            Action::Delete => {
+                self.replica.delete_object(key).await?;
+                "deleted"
+            }
+            _ => return Ok(()),
+        };
+        tracing::debug!(%key, outcome, "Image event replicated");
+        metrics::counter!("image_replications_total", "outcome" => outcome).increment(1);
+        Ok(())
+    }
+
+    /// Syncs every primary object under `prefix`; failures are logged and counted, not retried.
+    pub async fn backfill(&self, prefix: &str) -> S3Result<BackfillReport> {
+        let keys = self.primary.list_objects_with_prefix(prefix, None).await?;
+        tracing::info!(prefix, objects = keys.len(), "Replication backfill started");
+
+        let mut report = BackfillReport::default();
+        for key in keys {
+            match self.primary.replicate_to(&self.replica, &key).await {
+                Ok(Replication::Copied { .. }) => report.copied += 1,
+                Ok(Replication::UpToDate | Replication::SourceMissing) => report.up_to_date += 1,
+                Err(e) => {
+                    tracing::error!(%key, "Failed to replicate object during backfill: {e}");
+                    report.failed += 1;
+                }
+            }
+        }
+
+        tracing::info!(prefix, ?report, "Replication backfill finished");
+        Ok(report)
+    }
+
+    /// Tails the image events topic until `shutdown` is cancelled.
+    pub async fn run(&self, consumer: &KafkaConsumer, shutdown: CancellationToken) -> KafkaResult<()> {
+        consumer.run_with_handler(|event| self.handle_event(event), shutdown).await
+    }
+}
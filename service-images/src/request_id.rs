@@ -0,0 +1,90 @@
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, HeaderValue, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use std::{convert::Infallible, time::Instant};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest inbound id that is kept; longer ones are replaced like malformed ones.
+const MAX_LEN: usize = 128;
+
+/// Correlation id of the request: the gateway's `X-Request-Id`, or a fresh UUIDv7 when the service is called
+/// directly. Set by [`propagate`]; extracting it outside of that middleware yields a fresh id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The inbound id if it is a short run of visible ASCII, so it is safe to log and echo; a new one otherwise.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let inbound = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty() && v.len() <= MAX_LEN && v.bytes().all(|b| b.is_ascii_graphic()));
+        Self(inbound.map_or_else(|| Uuid::now_v7().to_string(), str::to_owned))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Self>()
+            .cloned()
+            .unwrap_or_else(|| Self::from_headers(&parts.headers)))
+    }
+}
+
+/// Assigns the [`RequestId`], runs the request in a span carrying it, echoes it in `X-Request-Id` and writes one
+/// access log line per request.
+pub async fn propagate(mut request: Request, next: Next) -> Response {
+    let RequestId(id) = RequestId::from_headers(request.headers());
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let started = Instant::now();
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    span.in_scope(|| {
+        tracing::info!(
+            target: "access",
+            method = %method,
+            path = %path,
+            status = response.status().as_u16(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "Request completed"
+        );
+    });
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_for(value: &str) -> String {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(value).unwrap());
+        RequestId::from_headers(&headers).0
+    }
+
+    #[test]
+    fn keeps_well_formed_ids_only() {
+        assert_eq!(id_for("trace-123"), "trace-123");
+        for replaced in ["", "has space", &"a".repeat(MAX_LEN + 1)] {
+            let id = id_for(replaced);
+            assert_ne!(id, replaced);
+            assert!(Uuid::parse_str(&id).is_ok(), "{id}");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::state::ServerState;
+use axum_prometheus::metrics;
+use kafka_client::consumer::KafkaConsumer;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Probes the storage every `interval` until `shutdown` is cancelled, pausing `consumer` while the
+/// bucket is unreachable and resuming it once a probe succeeds again.
+pub async fn pause_while_unreachable(
+    state: &ServerState,
+    consumer: &KafkaConsumer,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+
+        let check = state.storage.check().await;
+        let result = match (&check, consumer.is_paused()) {
+            (Err(e), false) => {
+                tracing::warn!(storage = %state.storage.location(), "Storage is unreachable, pausing image event consumption: {e}");
+                consumer.pause()
+            }
+            (Ok(()), true) => {
+                tracing::info!(storage = %state.storage.location(), "Storage is reachable again, resuming image event consumption");
+                consumer.resume()
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to pause or resume the image event consumer: {e}");
+        }
+        metrics::gauge!("kafka_consumer_paused").set(if consumer.is_paused() { 1.0 } else { 0.0 });
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    config::{AllowedOrigins, KafkaConfig, S3Config, ServerConfig},
+    kafka_health::{CHECK_TIMEOUT, PublishFailurePolicy},
+    storage::{FsStorage, ObjectStorage},
+};
+use kafka_client::{admin::KafkaAdmin, config::ProducerConfig, error::KafkaError, producer::KafkaProducer};
+use serde::Serialize;
+use std::{fmt::Write as _, future::Future, path::Path, time::Instant};
+
+/// Shortest `JWT_SECRET` service-auth accepts.
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    /// The service starts, but something is degraded or disabled.
+    Warn,
+    /// The service cannot run as configured.
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// One line per check, padded into columns.
+    pub fn to_table(&self) -> String {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0).max("CHECK".len());
+        let mut out = format!("{:<width$}  STATUS  {:>8}  DETAIL\n", "CHECK", "TIME");
+        for check in &self.checks {
+            let elapsed = format!("{}ms", check.elapsed_ms);
+            let _ = writeln!(
+                out,
+                "{:<width$}  {:<6}  {elapsed:>8}  {}",
+                check.name,
+                check.status.as_str(),
+                check.detail
+            );
+        }
+        let _ = write!(out, "{}", if self.passed { "self-test passed" } else { "self-test failed" });
+        out
+    }
+}
+
+/// Validates `config` against the live dependencies with the same probes the running service uses for its
+/// health checks, for `--self-test`. Fails only on what would keep the service from starting or serving.
+pub async fn run(config: &ServerConfig) -> SelfTestReport {
+    let mut checks = vec![
+        timed("config", async { check_config(config) }).await,
+        timed("cors", async { check_cors(config) }).await,
+        timed("admin_token", async { check_admin_token(config) }).await,
+        timed("jwt_secret", async { check_jwt_secret(config) }).await,
+    ];
+
+    match &config.storage_dir {
+        Some(dir) => checks.push(timed("fs", check_dir(dir)).await),
+        None => {
+            let s3 = config.s3.connect().await;
+            checks.push(timed("s3", check_bucket(&config.s3, &s3)).await);
+            #[cfg(feature = "minio-admin")]
+            checks.push(timed("minio_admin", check_minio_admin(&config.s3)).await);
+        }
+    }
+    if let Some(replication) = &config.replication {
+        let replica = replication.replica.connect().await;
+        checks.push(timed("s3_replica", check_bucket(&replication.replica, &replica)).await);
+    }
+
+    let kafka = timed("kafka", check_brokers(&config.kafka)).await;
+    let brokers_up = kafka.status == CheckStatus::Pass;
+    checks.push(kafka);
+    for topic in std::iter::once(&config.kafka.topic).chain(&config.kafka.audit_topic) {
+        let name = format!("kafka_topic:{topic}");
+        if brokers_up {
+            checks.push(timed(&name, check_topic(&config.kafka, topic)).await);
+        } else {
+            checks.push(result(&name, CheckStatus::Warn, "not checked, brokers unreachable".into(), 0));
+        }
+    }
+
+    SelfTestReport {
+        passed: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+async fn timed(name: &str, check: impl Future<Output = (CheckStatus, String)>) -> CheckResult {
+    let started = Instant::now();
+    let (status, detail) = check.await;
+    result(name, status, detail, started.elapsed().as_millis() as u64)
+}
+
+fn result(name: &str, status: CheckStatus, detail: String, elapsed_ms: u64) -> CheckResult {
+    CheckResult {
+        name: name.to_owned(),
+        status,
+        detail,
+        elapsed_ms,
+    }
+}
+
+/// Settings that parse on their own but contradict each other.
+fn check_config(config: &ServerConfig) -> (CheckStatus, String) {
+    let mut failures = Vec::new();
+    let mut warnings = Vec::new();
+    if config.thumbnail_sizes.contains(&0) {
+        failures.push("THUMBNAIL_SIZES contains 0".to_owned());
+    }
+    if config.kafka.audit_topic.as_ref() == Some(&config.kafka.topic) {
+        failures.push("AUDIT_TOPIC is the same as TOPIC".to_owned());
+    }
+    if let Some(replication) = &config.replication
+        && replication.group_id == config.kafka.group_id
+    {
+        failures.push("REPLICATION_GROUP_ID is the same as GROUP_ID, so events would be split between them".to_owned());
+    }
+    if let Some(webhooks) = &config.webhooks {
+        if webhooks.group_id == config.kafka.group_id {
+            failures.push("WEBHOOKS_GROUP_ID is the same as GROUP_ID, so events would be split between them".to_owned());
+        }
+        if webhooks.allow_private_targets {
+            warnings.push("WEBHOOK_ALLOW_PRIVATE_TARGETS lets webhooks reach internal addresses".to_owned());
+        }
+    }
+    if config.kafka.publish_failure_policy == PublishFailurePolicy::Buffer && config.kafka.publish_buffer_size == 0 {
+        warnings.push("KAFKA_PUBLISH_FAILURE_POLICY is buffer but KAFKA_PUBLISH_BUFFER_SIZE is 0".to_owned());
+    }
+    if config.kafka.retention_strict && !config.kafka.require_existing_topic {
+        warnings.push("KAFKA_RETENTION_STRICT has no effect without KAFKA_REQUIRE_EXISTING_TOPIC".to_owned());
+    }
+
+    match (failures.is_empty(), warnings.is_empty()) {
+        (true, true) => (CheckStatus::Pass, "consistent".into()),
+        (true, false) => (CheckStatus::Warn, warnings.join("; ")),
+        (false, _) => (
+            CheckStatus::Fail,
+            failures.into_iter().chain(warnings).collect::<Vec<_>>().join("; "),
+        ),
+    }
+}
+
+/// Invalid origins already fail reading the configuration; this reports what is allowed.
+fn check_cors(config: &ServerConfig) -> (CheckStatus, String) {
+    match &config.cors.origins {
+        AllowedOrigins::Any => (CheckStatus::Pass, "any origin".into()),
+        AllowedOrigins::List(origins) => (CheckStatus::Pass, format!("{} origin(s)", origins.len())),
+    }
+}
+
+fn check_admin_token(config: &ServerConfig) -> (CheckStatus, String) {
+    match config.admin_token {
+        Some(_) => (CheckStatus::Pass, "set".into()),
+        None => (CheckStatus::Warn, "ADMIN_TOKEN is not set, admin routes are refused".into()),
+    }
+}
+
+/// service-auth refuses shorter secrets, so a short one here cannot be the shared one.
+fn check_jwt_secret(config: &ServerConfig) -> (CheckStatus, String) {
+    if config.jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+        return (
+            CheckStatus::Warn,
+            format!("JWT_SECRET is shorter than {MIN_JWT_SECRET_LENGTH} characters"),
+        );
+    }
+    (CheckStatus::Pass, "set".into())
+}
+
+async fn check_bucket(config: &S3Config, s3: &s3_client::S3) -> (CheckStatus, String) {
+    match s3.check_bucket().await {
+        Ok(()) => (
+            CheckStatus::Pass,
+            format!("bucket {} at {}", config.bucket, config.endpoint_url),
+        ),
+        Err(e) => (
+            CheckStatus::Fail,
+            format!("bucket {} at {}: {e}", config.bucket, config.endpoint_url),
+        ),
+    }
+}
+
+async fn check_dir(dir: &Path) -> (CheckStatus, String) {
+    let checked = match FsStorage::new(dir) {
+        Ok(storage) => storage.check().await,
+        Err(e) => Err(e.into()),
+    };
+    match checked {
+        Ok(()) => (CheckStatus::Pass, format!("directory {}", dir.display())),
+        Err(e) => (CheckStatus::Fail, format!("directory {}: {e}", dir.display())),
+    }
+}
+
+/// Optional: without the admin API, `/admin/storage` counts by listing the bucket.
+#[cfg(feature = "minio-admin")]
+async fn check_minio_admin(config: &S3Config) -> (CheckStatus, String) {
+    let minio = s3_client::minio_admin::MinioAdmin::new(
+        config.access_key.clone(),
+        config.secret_key.clone(),
+        config.region.clone(),
+        config.endpoint_url.clone(),
+    );
+    match minio.bucket_usage(&config.bucket).await {
+        Ok(usage) => (CheckStatus::Pass, format!("{} object(s)", usage.objects)),
+        Err(e) => (
+            CheckStatus::Warn,
+            format!("unavailable, storage stats fall back to listing: {e}"),
+        ),
+    }
+}
+
+async fn check_brokers(config: &KafkaConfig) -> (CheckStatus, String) {
+    let producer = ProducerConfig::builder(&config.brokers, &config.topic)
+        .auto_create_topics(!config.require_existing_topic)
+        .build()
+        .and_then(KafkaProducer::new);
+    match producer {
+        Ok(producer) if producer.check_health(CHECK_TIMEOUT).await => (CheckStatus::Pass, config.brokers.clone()),
+        Ok(_) => (
+            CheckStatus::Fail,
+            format!("{} unreachable within {CHECK_TIMEOUT:?}", config.brokers),
+        ),
+        Err(e) => (CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// A missing topic only fails when `KAFKA_REQUIRE_EXISTING_TOPIC` keeps it from being created; retention is
+/// judged as at startup.
+async fn check_topic(config: &KafkaConfig, topic: &str) -> (CheckStatus, String) {
+    let admin = match KafkaAdmin::new(&config.brokers) {
+        Ok(admin) => admin,
+        Err(e) => return (CheckStatus::Fail, e.to_string()),
+    };
+    if !config.require_existing_topic {
+        return match admin.describe_topic(topic).await {
+            Ok(description) => (CheckStatus::Pass, format!("{} partition(s)", description.partitions.len())),
+            Err(KafkaError::TopicNotFound(_)) => (CheckStatus::Warn, "missing, created on first publish".into()),
+            Err(e) => (CheckStatus::Fail, e.to_string()),
+        };
+    }
+
+    match admin
+        .verify_retention(topic, &config.retention_minimums(), config.retention_strict)
+        .await
+    {
+        Ok(report) if report.is_sufficient() => (CheckStatus::Pass, "exists, retention sufficient".into()),
+        Ok(report) => (CheckStatus::Warn, report.problems.join("; ")),
+        Err(e) => (CheckStatus::Fail, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_consistent() {
+        assert_eq!(check_config(&ServerConfig::default()).0, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn contradicting_settings_are_reported() {
+        let mut config = ServerConfig::default();
+        config.kafka.retention_strict = true;
+        assert_eq!(check_config(&config).0, CheckStatus::Warn);
+
+        config.kafka.audit_topic = Some(config.kafka.topic.clone());
+        let (status, detail) = check_config(&config);
+        assert_eq!(status, CheckStatus::Fail);
+        assert!(detail.contains("AUDIT_TOPIC") && detail.contains("KAFKA_RETENTION_STRICT"));
+    }
+
+    #[test]
+    fn cors_reports_the_allowed_origins() {
+        let mut config = ServerConfig::default();
+        assert_eq!(check_cors(&config), (CheckStatus::Pass, "2 origin(s)".to_owned()));
+        config.cors.origins = AllowedOrigins::Any;
+        assert_eq!(check_cors(&config).1, "any origin");
+    }
+}
@@ -1,26 +1,178 @@
-use s3_client::S3;
-use std::sync::Arc;
+use axum::http::HeaderValue;
+use kafka_client::{
+    admin::{KafkaAdmin, RetentionReport},
+    config::ProducerConfig,
+    producer::KafkaProducer,
+};
+use scylladb_client::{quotas::QuotaStore, webhooks::WebhookStore};
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+use uuid::Uuid;
 
-use crate::Config;
+use crate::{
+    ServerConfig,
+    auth::Authenticator,
+    downloads::DownloadCoalescer,
+    error::ServerError,
+    interceptor::{ScanPolicy, UploadInterceptor},
+    kafka_health::UnpublishedEvents,
+    lag::LagWatcher,
+    quota::Quotas,
+    rate_limit::RateLimiter,
+    readiness::ReadinessProbe,
+    storage::{FsStorage, ObjectStorage, StorageUsage},
+    webhooks::Webhooks,
+};
 
 pub type ServerState = Arc<ServerData>;
 
 pub struct ServerData {
-    pub s3: S3,
+    /// The S3 bucket, or `STORAGE_DIR`.
+    pub storage: Arc<dyn ObjectStorage>,
+    /// Every image download goes through this, so a hot key costs one S3 request per burst.
+    pub downloads: DownloadCoalescer,
+    /// Thumbnail sizes rendered on upload and removed along with the original.
+    pub thumbnail_sizes: Vec<u32>,
+    pub max_file_size: u64,
+    /// `Cache-Control` of image downloads.
+    pub cache_control: Option<HeaderValue>,
+    /// Per-user upload limit; `None` when `UPLOAD_RATE_LIMIT_PER_MIN` is 0.
+    pub upload_limiter: Option<RateLimiter<Uuid>>,
+    /// Inspects uploads before they are stored; `None` stores them unscanned, like a
+    /// [`crate::interceptor::NoopInterceptor`] would.
+    pub upload_interceptor: Option<Arc<dyn UploadInterceptor>>,
+    pub scan_policy: ScanPolicy,
+    pub producer: KafkaProducer,
+    /// Image events waiting for the producer to become healthy again.
+    pub unpublished: UnpublishedEvents,
+    pub kafka_admin: KafkaAdmin,
+    pub admin_token: Option<String>,
+    /// Verifies the bearer tokens of [`crate::auth::AuthUser`] routes.
+    pub auth: Authenticator,
+    /// Backs `/admin/webhooks`; `None` unless `WEBHOOKS_SCYLLA_URL` is set.
+    pub webhooks: Option<Webhooks>,
+    /// Upload quotas and `/admin/quotas`; `None` unless `QUOTA_SCYLLA_URL` is set.
+    pub quotas: Option<Quotas>,
+    /// Backs `/admin/storage`.
+    pub usage: StorageUsage,
+    pub lag: Arc<LagWatcher>,
+    /// Retention checks made at startup; empty unless `KAFKA_REQUIRE_EXISTING_TOPIC` is set.
+    pub kafka_retention: Vec<RetentionReport>,
+    /// Dependency checks of `/health/ready`, once the gate is open.
+    pub readiness: ReadinessProbe,
+    /// Readiness gate behind `/health/ready`: opened once the server accepts connections, closed when shutdown begins.
+    pub ready: AtomicBool,
 }
 
 impl ServerData {
-    pub async fn new(config: &Config) -> ServerState {
-        let bucket: &'static str = Box::leak(config.s3.bucket.clone().into_boxed_str());
-        let s3 = S3::new(
-            config.s3.access_key.clone(),
-            config.s3.secret_key.clone(),
-            config.s3.region.clone(),
-            config.s3.endpoint_url.clone(),
-            bucket,
-        )
-        .await;
-
-        Arc::new(ServerData { s3 })
+    pub async fn new(config: &ServerConfig) -> Result<ServerState, ServerError> {
+        let (storage, usage): (Arc<dyn ObjectStorage>, _) = match &config.storage_dir {
+            Some(dir) => (
+                Arc::new(FsStorage::new(dir).map_err(ServerError::Storage)?),
+                StorageUsage::listing(),
+            ),
+            None => (Arc::new(config.s3.connect().await), StorageUsage::new(&config.s3)),
+        };
+
+        let producer_config = ProducerConfig::builder(&config.kafka.brokers, &config.kafka.topic)
+            .auto_create_topics(!config.kafka.require_existing_topic)
+            .statistics_interval_ms(config.kafka.stats_interval_ms)
+            .build()
+            .map_err(ServerError::kafka("Kafka producer"))?;
+        let producer = KafkaProducer::new(producer_config).map_err(ServerError::kafka("Kafka producer"))?;
+
+        let kafka_admin = KafkaAdmin::new(&config.kafka.brokers).map_err(ServerError::kafka("Kafka admin client"))?;
+        let kafka_retention = if config.kafka.require_existing_topic {
+            Self::check_retention(&kafka_admin, config).await?
+        } else {
+            Vec::new()
+        };
+
+        let downloads = DownloadCoalescer::new(
+            Duration::from_millis(config.downloads.ttl_ms),
+            config.downloads.max_bytes,
+            config.downloads.max_object_bytes,
+        );
+
+        Ok(Arc::new(ServerData {
+            storage,
+            downloads,
+            thumbnail_sizes: config.thumbnail_sizes.clone(),
+            max_file_size: config.max_file_size,
+            cache_control: config.cache_control.clone(),
+            upload_limiter: (config.upload_rate_limit_per_min > 0).then(|| {
+                let per_min = config.upload_rate_limit_per_min;
+                RateLimiter::new(per_min, per_min, Duration::from_secs(60))
+            }),
+            upload_interceptor: Self::upload_interceptor(config),
+            scan_policy: ScanPolicy {
+                timeout: Duration::from_millis(config.upload_scan.timeout_ms),
+                fail_open: config.upload_scan.fail_open,
+            },
+            producer,
+            unpublished: UnpublishedEvents::new(config.kafka.publish_failure_policy, config.kafka.publish_buffer_size),
+            kafka_admin,
+            admin_token: config.admin_token.clone(),
+            auth: Authenticator::new(&config.jwt_secret),
+            webhooks: match &config.webhooks {
+                Some(webhooks) => Some(Webhooks {
+                    store: Arc::new(WebhookStore::new(&webhooks.scylla, true).await?),
+                    allow_private_targets: webhooks.allow_private_targets,
+                }),
+                None => None,
+            },
+            quotas: match &config.quotas {
+                Some(quotas) => Some(Quotas {
+                    store: Arc::new(
+                        QuotaStore::new(&quotas.scylla, true)
+                            .await
+                            .map_err(|e| ServerError::QuotaStore(Box::new(e)))?,
+                    ),
+                    default_limit_bytes: quotas.default_limit_bytes,
+                }),
+                None => None,
+            },
+            usage,
+            lag: Arc::new(LagWatcher::new(Duration::from_secs(config.kafka.lag_max_staleness_secs))),
+            kafka_retention,
+            readiness: ReadinessProbe::new(
+                Duration::from_millis(config.readiness.cache_ms),
+                config.readiness.require_kafka,
+            ),
+            ready: AtomicBool::new(false),
+        }))
+    }
+
+    #[cfg(feature = "clamav")]
+    fn upload_interceptor(config: &ServerConfig) -> Option<Arc<dyn UploadInterceptor>> {
+        use crate::interceptor::clamav::ClamAvInterceptor;
+
+        let scan = &config.upload_scan;
+        let addr = scan.clamav_addr.as_deref()?;
+        Some(Arc::new(ClamAvInterceptor::new(addr, scan.fail_open)))
+    }
+
+    /// `CLAMAV_ADDR` is refused by the configuration without the `clamav` feature.
+    #[cfg(not(feature = "clamav"))]
+    fn upload_interceptor(_config: &ServerConfig) -> Option<Arc<dyn UploadInterceptor>> {
+        None
+    }
+
+    async fn check_retention(kafka_admin: &KafkaAdmin, config: &ServerConfig) -> Result<Vec<RetentionReport>, ServerError> {
+        let minimums = config.kafka.retention_minimums();
+        let mut reports = Vec::new();
+        for topic in std::iter::once(&config.kafka.topic).chain(&config.kafka.audit_topic) {
+            let report = kafka_admin
+                .verify_retention(topic, &minimums, config.kafka.retention_strict)
+                .await
+                .map_err(|source| ServerError::KafkaTopic {
+                    topic: topic.clone(),
+                    source: Box::new(source),
+                })?;
+            reports.push(report);
+        }
+        Ok(reports)
     }
 }
@@ -0,0 +1,473 @@
+use super::{ObjectHead, ObjectPage, ObjectStorage, ObjectSummary, ObjectWriter, S3Object, StorageError, StorageResult};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _},
+};
+use uuid::Uuid;
+
+/// Next to each object, holding what S3 keeps as object metadata.
+const META_SUFFIX: &str = ".meta";
+/// Uploads in progress; renamed into place once complete.
+const TEMP_PREFIX: &str = ".tmp-";
+/// Uploads up to this size stay in memory until finished, like the first part of an S3 multipart upload, so
+/// thumbnails can be rendered from them.
+const BUFFER_LIMIT: usize = 5 * 1024 * 1024;
+
+/// Keeps objects as files in one directory, for local development and edge deployments without S3.
+///
+/// Each object is a file named after its key, with everything but ASCII letters, digits, `-` and `_`
+/// percent-encoded, so that `a/b` and its thumbnail `a/b/thumb_128` can both exist. Content type, user metadata
+/// and legal hold are kept in a `.meta` file next to it. Listing reads the whole directory; there are no versions.
+pub struct FsStorage {
+    root: PathBuf,
+    location: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Meta {
+    content_type: Option<String>,
+    e_tag: Option<String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    #[serde(default)]
+    legal_hold: bool,
+}
+
+impl FsStorage {
+    /// Creates `root` if it does not exist yet.
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self {
+            location: root.display().to_string(),
+            root,
+        })
+    }
+
+    fn data_path(&self, key: &str) -> PathBuf {
+        self.root.join(encode(key))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{}{META_SUFFIX}", encode(key)))
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.root.join(format!("{TEMP_PREFIX}{}", Uuid::now_v7()))
+    }
+
+    /// `None` when the object does not exist; objects without a `.meta` file have default metadata.
+    async fn read_meta(&self, key: &str) -> StorageResult<Option<Meta>> {
+        if !fs::try_exists(self.data_path(key)).await? {
+            return Ok(None);
+        }
+        match fs::read(self.meta_path(key)).await {
+            Ok(meta) => serde_json::from_slice(&meta)
+                .map(Some)
+                .map_err(|e| StorageError::Backend(Box::new(e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some(Meta::default())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write_meta(&self, key: &str, meta: &Meta) -> StorageResult<()> {
+        let temp = self.temp_path();
+        let meta = serde_json::to_vec(meta).map_err(|e| StorageError::Backend(Box::new(e)))?;
+        fs::write(&temp, meta).await?;
+        Ok(fs::rename(temp, self.meta_path(key)).await?)
+    }
+
+    /// Moves the complete upload at `temp` into place as `key`.
+    async fn store(&self, key: &str, temp: &Path, meta: &Meta) -> StorageResult<()> {
+        self.write_meta(key, meta).await?;
+        Ok(fs::rename(temp, self.data_path(key)).await?)
+    }
+
+    /// Every object under `prefix`, in key order.
+    async fn entries(&self, prefix: &str) -> StorageResult<Vec<ObjectSummary>> {
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(&self.root).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name();
+            let Some(key) = name
+                .to_str()
+                .filter(|n| !n.starts_with(TEMP_PREFIX) && !n.ends_with(META_SUFFIX))
+                .and_then(decode)
+            else {
+                continue;
+            };
+            if !key.starts_with(prefix) {
+                continue;
+            }
+            // Deleted since the directory was read.
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            entries.push(ObjectSummary {
+                key,
+                size: metadata.len() as i64,
+                last_modified: modified_millis(&metadata),
+            });
+        }
+        entries.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for FsStorage {
+    fn kind(&self) -> &'static str {
+        "fs"
+    }
+
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    async fn check(&self) -> StorageResult<()> {
+        if fs::metadata(&self.root).await?.is_dir() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", self.location)).into())
+        }
+    }
+
+    async fn upload(&self, key: &str, data: Bytes, content_type: &str) -> StorageResult<()> {
+        let temp = self.temp_path();
+        fs::write(&temp, &data).await?;
+        let meta = Meta {
+            content_type: Some(content_type.to_owned()),
+            e_tag: Some(e_tag(Sha256::digest(&data))),
+            ..Meta::default()
+        };
+        let stored = self.store(key, &temp, &meta).await;
+        if stored.is_err() {
+            let _ = fs::remove_file(&temp).await;
+        }
+        stored
+    }
+
+    fn writer<'a>(&'a self, key: &str, content_type: &str, metadata: HashMap<String, String>) -> Box<dyn ObjectWriter + 'a> {
+        Box::new(FsWriter {
+            storage: self,
+            key: key.to_owned(),
+            meta: Meta {
+                content_type: Some(content_type.to_owned()),
+                metadata,
+                ..Meta::default()
+            },
+            temp: self.temp_path(),
+            file: None,
+            hasher: Sha256::new(),
+            buffer: Some(Vec::new()),
+            written: 0,
+        })
+    }
+
+    async fn download(&self, key: &str) -> StorageResult<S3Object> {
+        let path = self.data_path(key);
+        let data = fs::read(&path).await.map_err(missing)?;
+        let meta = self.read_meta(key).await?.unwrap_or_default();
+        Ok(S3Object {
+            data,
+            content_type: meta.content_type,
+            e_tag: meta.e_tag,
+            last_modified: fs::metadata(&path).await.ok().and_then(|m| modified_millis(&m)),
+        })
+    }
+
+    async fn download_range(&self, key: &str, start: u64, end: u64) -> StorageResult<S3Object> {
+        let path = self.data_path(key);
+        let mut file = File::open(&path).await.map_err(missing)?;
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut data = Vec::new();
+        file.take(end.saturating_sub(start) + 1).read_to_end(&mut data).await?;
+        let meta = self.read_meta(key).await?.unwrap_or_default();
+        Ok(S3Object {
+            data,
+            content_type: meta.content_type,
+            e_tag: meta.e_tag,
+            last_modified: fs::metadata(&path).await.ok().and_then(|m| modified_millis(&m)),
+        })
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<Option<ObjectHead>> {
+        let metadata = match fs::metadata(self.data_path(key)).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(meta) = self.read_meta(key).await? else {
+            return Ok(None);
+        };
+        Ok(Some(ObjectHead {
+            metadata: meta.metadata,
+            version_id: None,
+            legal_hold: meta.legal_hold,
+            size: metadata.len() as i64,
+            e_tag: meta.e_tag,
+            content_type: meta.content_type,
+            last_modified: modified_millis(&metadata),
+        }))
+    }
+
+    async fn object_exists(&self, key: &str) -> StorageResult<bool> {
+        Ok(fs::try_exists(self.data_path(key)).await?)
+    }
+
+    async fn delete_object(&self, key: &str) -> StorageResult<()> {
+        if self.read_meta(key).await?.is_some_and(|meta| meta.legal_hold) {
+            return Err(StorageError::ObjectOnHold(key.to_owned()));
+        }
+        for path in [self.data_path(key), self.meta_path(key)] {
+            match fs::remove_file(path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_objects(&self, keys: &[String]) -> StorageResult<HashMap<String, StorageError>> {
+        let mut failed = HashMap::new();
+        for key in keys {
+            if let Err(e) = self.delete_object(key).await {
+                failed.insert(key.clone(), e);
+            }
+        }
+        Ok(failed)
+    }
+
+    async fn delete_versions(&self, objects: &[(String, Option<String>)]) -> StorageResult<HashMap<String, StorageError>> {
+        let keys: Vec<String> = objects.iter().map(|(key, _)| key.clone()).collect();
+        self.delete_objects(&keys).await
+    }
+
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<String>> {
+        Ok(self.entries(prefix).await?.into_iter().map(|o| o.key).collect())
+    }
+
+    async fn list_page(&self, prefix: &str, start_after: Option<&str>, max_keys: i32) -> StorageResult<ObjectPage> {
+        let mut objects: Vec<ObjectSummary> = self
+            .entries(prefix)
+            .await?
+            .into_iter()
+            .filter(|o| start_after.is_none_or(|after| o.key.as_str() > after))
+            .collect();
+        let max_keys = max_keys.max(0) as usize;
+        let truncated = objects.len() > max_keys;
+        objects.truncate(max_keys);
+        Ok(ObjectPage { objects, truncated })
+    }
+
+    async fn set_legal_hold(&self, key: &str, on: bool) -> StorageResult<()> {
+        let mut meta = self.read_meta(key).await?.ok_or(StorageError::NotFound)?;
+        meta.legal_hold = on;
+        self.write_meta(key, &meta).await?;
+        tracing::info!(%key, on, "Set object legal hold");
+        Ok(())
+    }
+}
+
+struct FsWriter<'a> {
+    storage: &'a FsStorage,
+    key: String,
+    meta: Meta,
+    temp: PathBuf,
+    /// Created with the first write.
+    file: Option<File>,
+    hasher: Sha256,
+    /// Everything written, until it grows past [`BUFFER_LIMIT`].
+    buffer: Option<Vec<u8>>,
+    written: u64,
+}
+
+#[async_trait]
+impl ObjectWriter for FsWriter<'_> {
+    fn written(&self) -> u64 {
+        self.written
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        self.buffer.as_deref()
+    }
+
+    async fn write(&mut self, data: Bytes) -> StorageResult<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(File::create(&self.temp).await?),
+        };
+        file.write_all(&data).await?;
+        self.hasher.update(&data);
+        self.written += data.len() as u64;
+        match &mut self.buffer {
+            Some(buffer) if buffer.len() + data.len() <= BUFFER_LIMIT => buffer.extend_from_slice(&data),
+            _ => self.buffer = None,
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> StorageResult<u64> {
+        match self.file.take() {
+            Some(file) => file.sync_all().await?,
+            None => fs::write(&self.temp, b"").await?,
+        }
+        self.meta.e_tag = Some(e_tag(std::mem::take(&mut self.hasher).finalize()));
+        if let Err(e) = self.storage.store(&self.key, &self.temp, &self.meta).await {
+            let _ = fs::remove_file(&self.temp).await;
+            return Err(e);
+        }
+        Ok(self.written)
+    }
+
+    async fn abort(mut self: Box<Self>) -> StorageResult<()> {
+        if self.file.take().is_some() {
+            fs::remove_file(&self.temp).await?;
+        }
+        Ok(())
+    }
+}
+
+fn missing(e: io::Error) -> StorageError {
+    match e.kind() {
+        io::ErrorKind::NotFound => StorageError::NotFound,
+        _ => e.into(),
+    }
+}
+
+/// Quoted like S3's, from the first half of the SHA-256 of the content.
+fn e_tag(digest: impl AsRef<[u8]>) -> String {
+    format!("\"{}\"", hex::encode(&digest.as_ref()[..16]))
+}
+
+fn modified_millis(metadata: &std::fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    i64::try_from(modified.as_millis()).ok()
+}
+
+fn encode(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            let _ = write!(name, "%{byte:02X}");
+        }
+    }
+    name
+}
+
+fn decode(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_round_trip_through_file_names() {
+        for key in ["legacy-name", "0190/0190-abc_def", "a/b/thumb_128", "dots.and spaces", "ü/%"] {
+            let name = encode(key);
+            assert!(!name.contains(['/', '.']), "{name}");
+            assert_eq!(decode(&name).as_deref(), Some(key));
+        }
+        assert_eq!(decode("bad%2"), None);
+    }
+
+    #[tokio::test]
+    async fn keys_can_be_prefixes_of_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(dir.path()).unwrap();
+        storage
+            .upload("u/image", Bytes::from_static(b"original"), "image/png")
+            .await
+            .unwrap();
+        storage
+            .upload("u/image/thumb_128", Bytes::from_static(b"thumb"), "image/png")
+            .await
+            .unwrap();
+        storage
+            .upload("u/other", Bytes::from_static(b"other"), "image/png")
+            .await
+            .unwrap();
+
+        assert_eq!(storage.list("u/image/").await.unwrap(), ["u/image/thumb_128"]);
+        let page = storage.list_page("u/", Some("u/image"), 1).await.unwrap();
+        assert_eq!(page.objects[0].key, "u/image/thumb_128");
+        assert!(page.truncated);
+        assert_eq!(storage.download("u/image").await.unwrap().data, b"original");
+    }
+
+    #[tokio::test]
+    async fn streamed_uploads_appear_once_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(dir.path()).unwrap();
+        let metadata = HashMap::from([("owner".to_owned(), "alice".to_owned())]);
+        let mut writer = storage.writer("u/image", "image/png", metadata);
+        writer.write(Bytes::from_static(b"hello ")).await.unwrap();
+        writer.write(Bytes::from_static(b"world")).await.unwrap();
+        assert_eq!(writer.buffered(), Some(&b"hello world"[..]));
+        assert!(!storage.object_exists("u/image").await.unwrap());
+        assert_eq!(writer.finish().await.unwrap(), 11);
+
+        let head = storage.head("u/image").await.unwrap().unwrap();
+        assert_eq!((head.size, head.content_type.as_deref()), (11, Some("image/png")));
+        assert_eq!(head.metadata["owner"], "alice");
+        assert_eq!(storage.download_range("u/image", 6, 10).await.unwrap().data, b"world");
+
+        let mut aborted = storage.writer("u/aborted", "image/png", HashMap::new());
+        aborted.write(Bytes::from_static(b"partial")).await.unwrap();
+        aborted.abort().await.unwrap();
+        assert_eq!(storage.list("").await.unwrap(), ["u/image"]);
+    }
+
+    #[tokio::test]
+    async fn held_objects_are_not_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = FsStorage::new(dir.path()).unwrap();
+        storage
+            .upload("held", Bytes::from_static(b"data"), "image/png")
+            .await
+            .unwrap();
+        storage.set_legal_hold("held", true).await.unwrap();
+
+        assert!(matches!(
+            storage.delete_object("held").await,
+            Err(StorageError::ObjectOnHold(_))
+        ));
+        let failed = storage
+            .delete_objects(&["held".to_owned(), "missing".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        storage.set_legal_hold("held", false).await.unwrap();
+        storage.delete_object("held").await.unwrap();
+        assert!(storage.head("held").await.unwrap().is_none());
+        assert!(matches!(storage.download("held").await, Err(StorageError::NotFound)));
+        assert!(storage.set_legal_hold("held", true).await.unwrap_err().is_not_found());
+    }
+}
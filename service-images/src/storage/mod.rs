@@ -0,0 +1,99 @@
+mod fs;
+mod s3;
+mod usage;
+
+pub use fs::FsStorage;
+pub use s3_client::{ObjectHead, ObjectPage, ObjectSummary, S3Object};
+pub use usage::{StorageStats, StorageUsage};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use std::{collections::HashMap, io};
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Where images and their thumbnails are kept: the S3 bucket, or a directory for development and edge
+/// deployments. Keys are `/`-separated like S3 keys, and a key may also be the prefix of others.
+#[async_trait]
+pub trait ObjectStorage: Send + Sync {
+    /// `s3` or `fs`; names the storage check of `/health/ready`.
+    fn kind(&self) -> &'static str;
+
+    /// Bucket or directory, for logs.
+    fn location(&self) -> &str;
+
+    /// Succeeds when the storage can be reached.
+    async fn check(&self) -> StorageResult<()>;
+
+    async fn upload(&self, key: &str, data: Bytes, content_type: &str) -> StorageResult<()>;
+
+    /// Streams an object of unknown size into `key`; nothing is visible until [`ObjectWriter::finish`].
+    fn writer<'a>(&'a self, key: &str, content_type: &str, metadata: HashMap<String, String>) -> Box<dyn ObjectWriter + 'a>;
+
+    async fn download(&self, key: &str) -> StorageResult<S3Object>;
+
+    /// Bytes `start` to `end` of `key`, both included.
+    async fn download_range(&self, key: &str, start: u64, end: u64) -> StorageResult<S3Object>;
+
+    /// Metadata and legal hold of `key`, or `None` if it does not exist.
+    async fn head(&self, key: &str) -> StorageResult<Option<ObjectHead>>;
+
+    async fn object_exists(&self, key: &str) -> StorageResult<bool>;
+
+    /// Deleting a missing key succeeds.
+    async fn delete_object(&self, key: &str) -> StorageResult<()>;
+
+    /// Deletes every key it can; the ones that failed are returned with their error.
+    async fn delete_objects(&self, keys: &[String]) -> StorageResult<HashMap<String, StorageError>>;
+
+    /// Like [`Self::delete_objects`], each key at the version [`Self::head`] reported, so that objects put on
+    /// hold since are refused.
+    async fn delete_versions(&self, objects: &[(String, Option<String>)]) -> StorageResult<HashMap<String, StorageError>>;
+
+    /// Every key under `prefix`, in key order.
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<String>>;
+
+    /// Up to `max_keys` keys under `prefix` that sort after `start_after`.
+    async fn list_page(&self, prefix: &str, start_after: Option<&str>, max_keys: i32) -> StorageResult<ObjectPage>;
+
+    async fn set_legal_hold(&self, key: &str, on: bool) -> StorageResult<()>;
+}
+
+/// An upload in progress, from [`ObjectStorage::writer`]. Dropping it without `finish` or `abort` may leave
+/// partial data behind.
+#[async_trait]
+pub trait ObjectWriter: Send {
+    /// Bytes accepted so far.
+    fn written(&self) -> u64;
+
+    /// Everything written, while it is still held in memory.
+    fn buffered(&self) -> Option<&[u8]>;
+
+    async fn write(&mut self, data: Bytes) -> StorageResult<()>;
+
+    /// Stores the object; returns its size.
+    async fn finish(self: Box<Self>) -> StorageResult<u64>;
+
+    /// Discards everything written.
+    async fn abort(self: Box<Self>) -> StorageResult<()>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Object not found")]
+    NotFound,
+    #[error("Object {0} is under legal hold")]
+    ObjectOnHold(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Any other failure of the backend.
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl StorageError {
+    /// The object does not exist, as opposed to the storage failing to answer.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::NotFound)
+    }
+}
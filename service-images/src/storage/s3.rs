@@ -0,0 +1,106 @@
+use super::{ObjectHead, ObjectPage, ObjectStorage, ObjectWriter, S3Object, StorageError, StorageResult};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use s3_client::{S3, error::S3Error, sink::MultipartSink};
+use std::collections::HashMap;
+
+impl From<S3Error> for StorageError {
+    fn from(e: S3Error) -> Self {
+        match e {
+            e if e.is_not_found() => Self::NotFound,
+            S3Error::ObjectOnHold(key) => Self::ObjectOnHold(key),
+            S3Error::IO(e) => Self::Io(e),
+            e => Self::Backend(Box::new(e)),
+        }
+    }
+}
+
+fn reported(failed: HashMap<String, S3Error>) -> HashMap<String, StorageError> {
+    failed.into_iter().map(|(key, e)| (key, e.into())).collect()
+}
+
+#[async_trait]
+impl ObjectStorage for S3 {
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
+    fn location(&self) -> &str {
+        self.bucket()
+    }
+
+    async fn check(&self) -> StorageResult<()> {
+        Ok(self.check_bucket().await?)
+    }
+
+    async fn upload(&self, key: &str, data: Bytes, content_type: &str) -> StorageResult<()> {
+        Ok(S3::upload(self, key, data, content_type).await?)
+    }
+
+    fn writer<'a>(&'a self, key: &str, content_type: &str, metadata: HashMap<String, String>) -> Box<dyn ObjectWriter + 'a> {
+        Box::new(self.multipart_sink(key, content_type, metadata))
+    }
+
+    async fn download(&self, key: &str) -> StorageResult<S3Object> {
+        Ok(S3::download(self, key).await?)
+    }
+
+    async fn download_range(&self, key: &str, start: u64, end: u64) -> StorageResult<S3Object> {
+        Ok(S3::download_range(self, key, start, end).await?)
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<Option<ObjectHead>> {
+        Ok(S3::head(self, key).await?)
+    }
+
+    async fn object_exists(&self, key: &str) -> StorageResult<bool> {
+        Ok(S3::object_exists(self, key).await?)
+    }
+
+    async fn delete_object(&self, key: &str) -> StorageResult<()> {
+        Ok(S3::delete_object(self, key).await?)
+    }
+
+    async fn delete_objects(&self, keys: &[String]) -> StorageResult<HashMap<String, StorageError>> {
+        Ok(reported(self.delete_objects_reporting(keys).await?))
+    }
+
+    async fn delete_versions(&self, objects: &[(String, Option<String>)]) -> StorageResult<HashMap<String, StorageError>> {
+        Ok(reported(self.delete_versions_reporting(objects).await?))
+    }
+
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<String>> {
+        Ok(self.list_objects_with_prefix(prefix, None).await?)
+    }
+
+    async fn list_page(&self, prefix: &str, start_after: Option<&str>, max_keys: i32) -> StorageResult<ObjectPage> {
+        Ok(self.list_objects_page(prefix, start_after, max_keys).await?)
+    }
+
+    async fn set_legal_hold(&self, key: &str, on: bool) -> StorageResult<()> {
+        Ok(S3::set_legal_hold(self, key, on).await?)
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for MultipartSink<'_> {
+    fn written(&self) -> u64 {
+        MultipartSink::written(self)
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        MultipartSink::buffered(self)
+    }
+
+    async fn write(&mut self, data: Bytes) -> StorageResult<()> {
+        Ok(MultipartSink::write(self, data).await?)
+    }
+
+    async fn finish(self: Box<Self>) -> StorageResult<u64> {
+        Ok(MultipartSink::finish(*self).await?)
+    }
+
+    async fn abort(self: Box<Self>) -> StorageResult<()> {
+        Ok(MultipartSink::abort(*self).await?)
+    }
+}
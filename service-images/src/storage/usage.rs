@@ -0,0 +1,89 @@
+use super::{ObjectStorage, StorageResult};
+use crate::config::S3Config;
+use serde::Serialize;
+
+/// Keys requested per `ListObjectsV2` call when counting by listing.
+const LISTING_PAGE_SIZE: i32 = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageStats {
+    pub bucket: String,
+    pub objects: u64,
+    pub bytes: u64,
+    /// Hard quota in bytes; only known through the MinIO admin API.
+    pub quota_bytes: Option<u64>,
+    /// `minio-admin` or `listing`.
+    pub source: &'static str,
+}
+
+/// Counts the objects and bytes in the storage for `/admin/storage`.
+///
+/// Built with the `minio-admin` feature, MinIO's data usage is asked first, which costs one request but trails
+/// recent writes until its scanner catches up. Otherwise, or when that call fails, the whole bucket is listed.
+pub struct StorageUsage {
+    #[cfg(feature = "minio-admin")]
+    minio: Option<s3_client::minio_admin::MinioAdmin>,
+}
+
+impl StorageUsage {
+    /// Always counts by listing the storage.
+    pub fn listing() -> Self {
+        Self {
+            #[cfg(feature = "minio-admin")]
+            minio: None,
+        }
+    }
+
+    #[cfg_attr(not(feature = "minio-admin"), allow(unused_variables))]
+    pub fn new(config: &S3Config) -> Self {
+        Self {
+            #[cfg(feature = "minio-admin")]
+            minio: Some(s3_client::minio_admin::MinioAdmin::new(
+                config.access_key.clone(),
+                config.secret_key.clone(),
+                config.region.clone(),
+                config.endpoint_url.clone(),
+            )),
+        }
+    }
+
+    pub async fn stats(&self, storage: &dyn ObjectStorage) -> StorageResult<StorageStats> {
+        #[cfg(feature = "minio-admin")]
+        if let Some(minio) = &self.minio {
+            let bucket = storage.location();
+            match tokio::try_join!(minio.bucket_usage(bucket), minio.get_bucket_quota(bucket)) {
+                Ok((usage, quota_bytes)) => {
+                    return Ok(StorageStats {
+                        bucket: bucket.to_owned(),
+                        objects: usage.objects,
+                        bytes: usage.bytes,
+                        quota_bytes,
+                        source: "minio-admin",
+                    });
+                }
+                Err(e) => tracing::warn!("MinIO admin API unavailable, counting storage by listing: {e}"),
+            }
+        }
+        Self::count_by_listing(storage).await
+    }
+
+    async fn count_by_listing(storage: &dyn ObjectStorage) -> StorageResult<StorageStats> {
+        let mut stats = StorageStats {
+            bucket: storage.location().to_owned(),
+            objects: 0,
+            bytes: 0,
+            quota_bytes: None,
+            source: "listing",
+        };
+        let mut start_after = None;
+        loop {
+            let page = storage.list_page("", start_after.as_deref(), LISTING_PAGE_SIZE).await?;
+            stats.objects += page.objects.len() as u64;
+            stats.bytes += page.objects.iter().map(|o| o.size.max(0) as u64).sum::<u64>();
+            match page.objects.last() {
+                Some(last) if page.truncated => start_after = Some(last.key.clone()),
+                _ => return Ok(stats),
+            }
+        }
+    }
+}
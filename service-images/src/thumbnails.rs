@@ -0,0 +1,132 @@
+use axum::body::Bytes;
+use image::{DynamicImage, ImageFormat, codecs::jpeg::JpegEncoder};
+use std::io::Cursor;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Thumbnails are stored next to their original, which keeps them out of the flat key space of uploads.
+pub fn thumbnail_key(key: &str, size: u32) -> String {
+    format!("{key}/thumb_{size}")
+}
+
+pub fn thumbnail_keys<'a>(key: &'a str, sizes: &'a [u32]) -> impl Iterator<Item = String> + 'a {
+    sizes.iter().map(move |&size| thumbnail_key(key, size))
+}
+
+#[derive(Debug)]
+pub struct Thumbnail {
+    /// Longest side in pixels.
+    pub size: u32,
+    pub data: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ThumbnailError {
+    #[error("Unsupported image type {0}")]
+    UnsupportedType(String),
+    #[error("Failed to decode image: {0}")]
+    Decode(image::ImageError),
+    #[error("Failed to encode thumbnail: {0}")]
+    Encode(image::ImageError),
+    #[error("Thumbnail task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// Decodes `data` and scales it down to each of `sizes` on its longest side, keeping the aspect ratio. Sizes
+/// at or above the image's own are skipped; downloads fall back to the original for them. JPEGs stay JPEGs,
+/// everything else (including the first frame of a GIF) becomes a PNG.
+pub fn generate(data: &[u8], content_type: &str, sizes: &[u32]) -> Result<Vec<Thumbnail>, ThumbnailError> {
+    let format =
+        ImageFormat::from_mime_type(content_type).ok_or_else(|| ThumbnailError::UnsupportedType(content_type.to_owned()))?;
+    let image = image::load_from_memory_with_format(data, format).map_err(ThumbnailError::Decode)?;
+    let longest = image.width().max(image.height());
+
+    sizes
+        .iter()
+        .filter(|&&size| size > 0 && size < longest)
+        .map(|&size| {
+            let resized = image.thumbnail(size, size);
+            let (data, content_type) = encode(&resized, format).map_err(ThumbnailError::Encode)?;
+            Ok(Thumbnail {
+                size,
+                data,
+                content_type,
+            })
+        })
+        .collect()
+}
+
+/// [`generate`] on the blocking pool; decoding and resizing a large image takes long enough to stall the runtime.
+pub async fn generate_blocking(data: Bytes, content_type: String, sizes: Vec<u32>) -> Result<Vec<Thumbnail>, ThumbnailError> {
+    tokio::task::spawn_blocking(move || generate(&data, &content_type, &sizes)).await?
+}
+
+fn encode(image: &DynamicImage, source: ImageFormat) -> image::ImageResult<(Vec<u8>, &'static str)> {
+    let mut out = Vec::new();
+    if source == ImageFormat::Jpeg {
+        let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+        rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY))?;
+        Ok((out, "image/jpeg"))
+    } else {
+        image.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?;
+        Ok((out, "image/png"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = ImageBuffer::from_pixel(width, height, Rgba([200u8, 40, 40, 255]));
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    fn dimensions(thumbnail: &Thumbnail) -> (u32, u32) {
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn longest_side_is_scaled_to_each_size() {
+        let thumbnails = generate(&png(1024, 512), "image/png", &[128, 512]).unwrap();
+        let sizes: Vec<_> = thumbnails.iter().map(|t| (t.size, dimensions(t))).collect();
+        assert_eq!(sizes, [(128, (128, 64)), (512, (512, 256))]);
+        assert!(thumbnails.iter().all(|t| t.content_type == "image/png"));
+    }
+
+    #[test]
+    fn sizes_not_smaller_than_the_image_are_skipped() {
+        let thumbnails = generate(&png(200, 300), "image/png", &[128, 300, 512]).unwrap();
+        assert_eq!(thumbnails.len(), 1);
+        assert_eq!(dimensions(&thumbnails[0]), (85, 128));
+    }
+
+    #[test]
+    fn jpegs_stay_jpegs() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(ImageBuffer::new(256, 256))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        let thumbnails = generate(&jpeg, "image/jpeg", &[64]).unwrap();
+        assert_eq!(thumbnails[0].content_type, "image/jpeg");
+        assert_eq!(dimensions(&thumbnails[0]), (64, 64));
+    }
+
+    #[test]
+    fn corrupt_data_is_a_decode_error() {
+        let result = generate(b"\x89PNG not really", "image/png", &[128]);
+        assert!(matches!(result, Err(ThumbnailError::Decode(_))));
+    }
+
+    #[test]
+    fn thumbnails_live_under_the_original_key() {
+        assert_eq!(thumbnail_key("0196-abc", 128), "0196-abc/thumb_128");
+    }
+}
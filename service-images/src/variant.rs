@@ -0,0 +1,98 @@
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use axum_prometheus::metrics;
+use tracing::Span;
+
+/// Set by the proxy on requests it routes to a canary; echoed on every response.
+pub const VARIANT_HEADER: &str = "X-Served-By-Variant";
+
+/// Deployment variant a request was routed to. Anything the proxy may send beyond the known names collapses into
+/// [`Variant::Other`], so the header cannot blow up metric cardinality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Stable,
+    Canary,
+    Other,
+}
+
+impl Variant {
+    /// A missing header means the request did not go through a canary rollout.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(value) = headers.get(VARIANT_HEADER) else {
+            return Self::Stable;
+        };
+        match value.to_str().map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Ok("stable") => Self::Stable,
+            Ok("canary") => Self::Canary,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Canary => "canary",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// `TraceLayer` span with the fields of tower-http's default plus the variant.
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        variant = Variant::from_headers(request.headers()).as_str(),
+    )
+}
+
+/// Counts responses in `http_requests_by_variant_total` by variant and status class, and echoes the variant.
+pub async fn track(request: Request, next: Next) -> Response {
+    let variant = Variant::from_headers(request.headers());
+    let mut response = next.run(request).await;
+
+    let status = match response.status().as_u16() {
+        500.. => "5xx",
+        400..500 => "4xx",
+        300..400 => "3xx",
+        200..300 => "2xx",
+        _ => "1xx",
+    };
+    metrics::counter!("http_requests_by_variant_total", "variant" => variant.as_str(), "status" => status).increment(1);
+    response
+        .headers_mut()
+        .insert(VARIANT_HEADER, HeaderValue::from_static(variant.as_str()));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(value: Option<&str>) -> Variant {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = value {
+            headers.insert(VARIANT_HEADER, HeaderValue::from_str(value).unwrap());
+        }
+        Variant::from_headers(&headers)
+    }
+
+    #[test]
+    fn known_variants_are_kept() {
+        assert_eq!(variant(None), Variant::Stable);
+        assert_eq!(variant(Some("stable")), Variant::Stable);
+        assert_eq!(variant(Some(" Canary ")), Variant::Canary);
+    }
+
+    #[test]
+    fn unknown_variants_collapse_to_other() {
+        assert_eq!(variant(Some("blue-42")), Variant::Other);
+        assert_eq!(variant(Some("")), Variant::Other);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::config::WebhookConfig;
+use axum_prometheus::metrics;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use kafka_client::{
+    consumer::{ConsumedMessage, KafkaConsumer},
+    error::KafkaResult,
+    schemas::{Action, KafkaMessage, METADATA_OBJECT_KEY},
+};
+use reqwest::{
+    Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+};
+use scylladb_client::{
+    error::ScyllaError,
+    webhooks::{Webhook, WebhookDelivery, WebhookStore},
+};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// `sha256=<hex>` HMAC of `<timestamp>.<body>` under the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Unix seconds the payload was signed at; part of the signed content so a captured request cannot be replayed later.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// Same on every attempt at one event, so receivers can drop duplicates.
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Whole-request limit of one attempt, connecting included.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Events a webhook can subscribe to.
+pub const EVENTS: [&str; 3] = ["image.created", "image.updated", "image.deleted"];
+
+/// Longest wait between two attempts, however many there are.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What the admin routes need when webhooks are enabled.
+pub struct Webhooks {
+    pub store: Arc<WebhookStore>,
+    /// Skips the private address checks, for local receivers in development and tests.
+    pub allow_private_targets: bool,
+}
+
+pub fn event_name(action: &Action) -> Option<&'static str> {
+    match action {
+        Action::Create => Some("image.created"),
+        Action::Update => Some("image.updated"),
+        Action::Delete => Some("image.deleted"),
+        _ => None,
+    }
+}
+
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TargetError {
+    #[error("Invalid webhook URL: {0}")]
+    InvalidUrl(String),
+    #[error("Webhook URLs must use http or https")]
+    UnsupportedScheme,
+    #[error("Webhook host {0} does not resolve")]
+    Unresolvable(String),
+    #[error("Webhook host resolves to non-public address {0}")]
+    PrivateAddress(IpAddr),
+}
+
+/// Whether `ip` is reachable on the public internet, as opposed to loopback, private, link-local, shared
+/// (CGNAT), documentation, multicast and other special-purpose ranges a webhook must never be pointed at.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || a >= 240
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        || (a == 198 && (18..20).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || first == 0x2001 && ip.segments()[1] == 0x0db8
+        || first == 0x0064 && ip.segments()[1] == 0xff9b)
+}
+
+/// Parses `url` and checks that every address it resolves to is public. Deliveries check again through
+/// [`PublicResolver`], since the DNS answer can change after registration.
+pub async fn validate_url(url: &str, allow_private: bool) -> Result<Url, TargetError> {
+    let parsed = Url::parse(url).map_err(|e| TargetError::InvalidUrl(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(TargetError::UnsupportedScheme);
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| TargetError::InvalidUrl("missing host".into()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_owned();
+    if allow_private {
+        return Ok(parsed);
+    }
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|_| TargetError::Unresolvable(host.clone()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(TargetError::Unresolvable(host));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(TargetError::PrivateAddress(addr.ip()));
+    }
+    Ok(parsed)
+}
+
+/// Resolves delivery hosts and drops non-public addresses, so the connection goes to an address that was
+/// checked rather than to whatever a second lookup returns.
+struct PublicResolver {
+    allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{host} has no public address").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Base delay doubled for every attempt after the first, capped at [`MAX_BACKOFF`].
+pub fn backoff(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_BACKOFF)
+}
+
+/// Per-endpoint circuit: after `threshold` consecutive failed attempts it opens and deliveries to the endpoint
+/// are skipped for `cooldown`; the first attempt after that decides whether it closes or opens again.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    endpoints: Mutex<HashMap<Uuid, Circuit>>,
+}
+
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether an attempt may be made; a circuit past its cooldown lets one trial through per cooldown.
+    pub fn allow(&self, endpoint: Uuid) -> bool {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let Some(circuit) = endpoints.get_mut(&endpoint) else {
+            return true;
+        };
+        match circuit.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                circuit.open_until = Some(Instant::now() + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record(&self, endpoint: Uuid, success: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if success {
+            endpoints.remove(&endpoint);
+            return;
+        }
+        let circuit = endpoints.entry(endpoint).or_default();
+        circuit.failures += 1;
+        if circuit.failures >= self.threshold {
+            if circuit.open_until.is_none() {
+                tracing::warn!(webhook_id = %endpoint, failures = circuit.failures, "Webhook circuit opened");
+            }
+            circuit.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    id: Uuid,
+    event: &'a str,
+    account_id: Uuid,
+    key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a HashMap<String, String>>,
+    /// Unix milliseconds the event was published at, when Kafka recorded it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    occurred_at: Option<i64>,
+}
+
+/// Delivers image events to the webhooks of the account they belong to, logging every attempt.
+pub struct WebhookDispatcher {
+    store: Arc<WebhookStore>,
+    client: reqwest::Client,
+    breaker: CircuitBreaker,
+    max_attempts: u32,
+    retry_backoff: Duration,
+    allow_private_targets: bool,
+    shutdown: CancellationToken,
+}
+
+impl WebhookDispatcher {
+    pub fn new(store: Arc<WebhookStore>, config: &WebhookConfig, shutdown: CancellationToken) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver {
+                allow_private: config.allow_private_targets,
+            }))
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        Self {
+            store,
+            client,
+            breaker: CircuitBreaker::new(
+                config.circuit_failure_threshold,
+                Duration::from_secs(config.circuit_cooldown_secs),
+            ),
+            max_attempts: config.max_attempts.max(1),
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            allow_private_targets: config.allow_private_targets,
+            shutdown,
+        }
+    }
+
+    /// Delivers the event to every matching webhook of its account. Only a failure to look the webhooks up
+    /// is returned, so the consumer retries the event; delivery failures end up in the delivery log.
+    pub async fn handle_event(&self, event: ConsumedMessage<KafkaMessage>) -> Result<(), ScyllaError> {
+        let message = &event.message;
+        let Some(name) = event_name(&message.action) else {
+            return Ok(());
+        };
+        let Ok(account_id) = message.user_id.parse::<Uuid>() else {
+            tracing::warn!(user_id = %message.user_id, offset = event.offset, "Image event without an account UUID");
+            return Ok(());
+        };
+
+        let webhooks = self.store.account_webhooks(account_id).await?;
+        let key = message
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get(METADATA_OBJECT_KEY))
+            .or(message.data.as_ref());
+        let deliveries = webhooks.iter().filter(|w| w.subscribes_to(name)).map(|webhook| {
+            let delivery_id = Uuid::now_v7();
+            let body = serde_json::to_vec(&Payload {
+                id: delivery_id,
+                event: name,
+                account_id,
+                key: key.map(String::as_str),
+                metadata: message.metadata.as_ref(),
+                occurred_at: event.timestamp,
+            })
+            .expect("webhook payloads serialize");
+            self.deliver(webhook, name, delivery_id, body)
+        });
+        futures_util::future::join_all(deliveries).await;
+        Ok(())
+    }
+
+    /// Attempts delivery until it succeeds, the attempts run out, the circuit opens or shutdown begins.
+    async fn deliver(&self, webhook: &Webhook, event: &str, delivery_id: Uuid, body: Vec<u8>) {
+        for attempt in 1..=self.max_attempts {
+            let record = |status_code: Option<u16>, error: Option<String>, duration: Duration| WebhookDelivery {
+                webhook_id: webhook.webhook_id,
+                attempt_id: Uuid::now_v7(),
+                delivery_id,
+                event: event.to_owned(),
+                attempt: attempt as i32,
+                status_code: status_code.map(i32::from),
+                error,
+                duration_ms: duration.as_millis() as i64,
+                attempted_at: Utc::now(),
+            };
+
+            if !self.breaker.allow(webhook.webhook_id) {
+                metrics::counter!("webhook_delivery_attempts_total", "outcome" => "circuit_open").increment(1);
+                self.log(record(None, Some("Circuit open".into()), Duration::ZERO)).await;
+                return;
+            }
+
+            let started = Instant::now();
+            let (status_code, error) = match self.send(webhook, event, delivery_id, &body).await {
+                Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+                Ok(status) => (Some(status.as_u16()), Some(format!("HTTP {}", status.as_u16()))),
+                Err(e) => (None, Some(e)),
+            };
+            let success = error.is_none();
+            self.breaker.record(webhook.webhook_id, success);
+            metrics::counter!(
+                "webhook_delivery_attempts_total",
+                "outcome" => if success { "success" } else { "failure" }
+            )
+            .increment(1);
+            self.log(record(status_code, error, started.elapsed())).await;
+
+            if success {
+                return;
+            }
+            if attempt < self.max_attempts {
+                tokio::select! {
+                    _ = self.shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(backoff(self.retry_backoff, attempt)) => {}
+                }
+            }
+        }
+        tracing::warn!(webhook_id = %webhook.webhook_id, %delivery_id, event, "Webhook delivery failed");
+    }
+
+    async fn send(&self, webhook: &Webhook, event: &str, delivery_id: Uuid, body: &[u8]) -> Result<reqwest::StatusCode, String> {
+        // Literal addresses never reach the resolver, so they are checked here.
+        let url = Url::parse(&webhook.url).map_err(|e| e.to_string())?;
+        if let Some(ip) = url
+            .host_str()
+            .and_then(|host| host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok())
+            && !self.allow_private_targets
+            && !is_public(ip)
+        {
+            return Err(TargetError::PrivateAddress(ip).to_string());
+        }
+
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, body))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(EVENT_HEADER, event)
+            .header(DELIVERY_HEADER, delivery_id.to_string())
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    "Timed out".to_owned()
+                } else {
+                    e.to_string()
+                }
+            })?;
+        Ok(response.status())
+    }
+
+    async fn log(&self, delivery: WebhookDelivery) {
+        if let Err(e) = self.store.record_delivery(&delivery).await {
+            tracing::error!(webhook_id = %delivery.webhook_id, attempt = delivery.attempt, "Failed to log webhook delivery: {e}");
+        }
+    }
+
+    /// Tails the image events topic until `shutdown` is cancelled.
+    pub async fn run(&self, consumer: &KafkaConsumer, shutdown: CancellationToken) -> KafkaResult<()> {
+        consumer.run_with_handler(|event| self.handle_event(event), shutdown).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("secret", 1_700_000_000, b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(signature, sign("secret", 1_700_000_000, b"{}"));
+        assert_ne!(signature, sign("secret", 1_700_000_001, b"{}"));
+        assert_ne!(signature, sign("other", 1_700_000_000, b"{}"));
+    }
+
+    #[test]
+    fn special_purpose_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip} passed as public");
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip} refused");
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_to_private_hosts_are_refused() {
+        assert_eq!(
+            validate_url("http://127.0.0.1:8080/hook", false).await,
+            Err(TargetError::PrivateAddress("127.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(
+            validate_url("http://[::1]/hook", false).await,
+            Err(TargetError::PrivateAddress("::1".parse().unwrap()))
+        );
+        assert_eq!(
+            validate_url("ftp://example.com/hook", false).await,
+            Err(TargetError::UnsupportedScheme)
+        );
+        assert!(validate_url("http://127.0.0.1:8080/hook", true).await.is_ok());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let base = Duration::from_millis(500);
+        assert_eq!(backoff(base, 1), Duration::from_millis(500));
+        assert_eq!(backoff(base, 2), Duration::from_secs(1));
+        assert_eq!(backoff(base, 4), Duration::from_secs(4));
+        assert_eq!(backoff(base, 40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        let endpoint = Uuid::now_v7();
+
+        breaker.record(endpoint, false);
+        assert!(breaker.allow(endpoint));
+        breaker.record(endpoint, false);
+        assert!(!breaker.allow(endpoint));
+        assert!(breaker.allow(Uuid::now_v7()));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow(endpoint), "trial attempt after the cooldown");
+        assert!(!breaker.allow(endpoint), "only one trial per cooldown");
+        breaker.record(endpoint, true);
+        assert!(breaker.allow(endpoint));
+    }
+}
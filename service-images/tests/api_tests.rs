@@ -1,32 +1,89 @@
+mod common;
+
 use axum_test::{
     TestServer,
     multipart::{MultipartForm, Part},
 };
+use common::JWT_SECRET;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use kafka_client::{
+    admin::KafkaAdmin,
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    producer::KafkaProducer,
+    schemas::{Action, CURRENT_VERSION, KafkaMessage, METADATA_CONTENT_TYPE, METADATA_OBJECT_KEY, METADATA_SIZE},
+};
 use s3_client::S3;
 use service_images::{
     ServerBuilder,
+    auth::mint_token,
+    events,
+    kafka_health::{self, PublishFailurePolicy, UnpublishedEvents},
+    readiness::ReadinessProbe,
     state::{ServerData, ServerState},
 };
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use testcontainers_modules::{
     kafka::Kafka,
     minio::MinIO,
     testcontainers::{ContainerAsync, runners::AsyncRunner as _},
 };
+use tokio_util::sync::CancellationToken;
 
 const ACCESS_KEY: &str = "minioadmin";
 const SECRET_KEY: &str = "minioadmin";
 const REGION: &str = "us-east-1";
 const BUCKET: &str = "test-images";
 const KAFKA_TOPIC: &str = "images-test";
+const ADMIN_TOKEN: &str = "test-admin-token";
+const CACHE_CONTROL: &str = "public, max-age=60";
+
+/// Bearer token for `user_id`, valid for an hour.
+fn token(user_id: &str) -> String {
+    mint_token(JWT_SECRET, user_id, 3600)
+}
+
+/// A solid `width`x`height` image; uploads are decoded, so test bodies have to be real images.
+fn image_fixture(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(ImageBuffer::from_pixel(width, height, Rgb([30, 120, 200])))
+        .write_to(&mut std::io::Cursor::new(&mut out), format)
+        .expect("fixture encodes");
+    out
+}
+
+fn png_fixture() -> Vec<u8> {
+    image_fixture(4, 4, ImageFormat::Png)
+}
+
+fn jpeg_fixture() -> Vec<u8> {
+    image_fixture(4, 4, ImageFormat::Jpeg)
+}
+
+/// `len` bytes that sniff as a PNG without being one.
+fn png_signed(len: usize) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    data.extend((data.len()..len).map(|i| (i % 251) as u8));
+    data
+}
 
 struct TestContext {
     server: TestServer,
-    _minio: ContainerAsync<MinIO>,
-    _kafka: ContainerAsync<Kafka>,
+    state: ServerState,
+    brokers: String,
+    minio: ContainerAsync<MinIO>,
+    kafka: ContainerAsync<Kafka>,
 }
 
 async fn setup() -> anyhow::Result<TestContext> {
+    setup_with(false).await
+}
+
+/// With `object_lock`, the bucket is created with object lock enabled so legal holds can be placed.
+async fn setup_with(object_lock: bool) -> anyhow::Result<TestContext> {
     let (minio, kafka) = tokio::join!(MinIO::default().start(), Kafka::default().start());
     let minio = minio?;
     let kafka = kafka?;
@@ -34,20 +91,43 @@ async fn setup() -> anyhow::Result<TestContext> {
     let endpoint = format!("http://127.0.0.1:{}", minio_port);
     let bucket: &'static str = Box::leak(BUCKET.to_string().into_boxed_str());
     let s3 = S3::new(ACCESS_KEY, SECRET_KEY, REGION, &endpoint, bucket).await;
-    s3.create_bucket().await?;
+    if object_lock {
+        s3.create_bucket_with_object_lock().await?;
+    } else {
+        s3.create_bucket().await?;
+    }
     let kafka_host = kafka.get_host().await?;
     let kafka_port = kafka.get_host_port_ipv4(9093).await?;
     let brokers = format!("{}:{}", kafka_host, kafka_port);
 
-    let state: ServerState = Arc::new(ServerData { s3 });
+    let kafka_admin = KafkaAdmin::new(&brokers)?;
+    kafka_admin.ensure_topic(KAFKA_TOPIC, 1, 1, None).await?;
 
-    let router = ServerBuilder::init_router(state);
+    let producer_config = ProducerConfig::builder(&brokers, KAFKA_TOPIC).build()?;
+    let producer = KafkaProducer::new(producer_config)?;
+
+    let state: ServerState = Arc::new(ServerData {
+        thumbnail_sizes: vec![128, 512],
+        max_file_size: 50 * 1024 * 1024,
+        cache_control: Some(axum::http::HeaderValue::from_static(CACHE_CONTROL)),
+        producer,
+        unpublished: UnpublishedEvents::new(PublishFailurePolicy::Buffer, 100),
+        kafka_admin,
+        admin_token: Some(ADMIN_TOKEN.to_owned()),
+        // Kafka is optional here, as uploads are tested to keep working while it is down.
+        readiness: ReadinessProbe::new(Duration::ZERO, false),
+        ..common::server_data(s3)?
+    });
+
+    let router = ServerBuilder::init_router(Arc::clone(&state));
     let server = TestServer::new(router);
 
     Ok(TestContext {
         server,
-        _minio: minio,
-        _kafka: kafka,
+        state,
+        brokers,
+        minio,
+        kafka,
     })
 }
 
@@ -75,15 +155,13 @@ async fn test_upload_jpeg_success() -> anyhow::Result<()> {
     let ctx = setup().await?;
     let user_id = uuid::Uuid::now_v7().to_string();
 
-    let part = Part::bytes(vec![0xFF, 0xD8, 0xFF, 0xE0])
-        .file_name("test.jpg")
-        .mime_type("image/jpeg");
+    let part = Part::bytes(jpeg_fixture()).file_name("test.jpg").mime_type("image/jpeg");
     let form = MultipartForm::new().add_part("file", part);
 
     let response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", user_id)
+        .authorization_bearer(token(&user_id))
         .multipart(form)
         .await;
 
@@ -93,20 +171,130 @@ async fn test_upload_jpeg_success() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_upload_publishes_event_with_request_id() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let image = png_fixture();
+    let size = image.len().to_string();
+    let part = Part::bytes(image).file_name("test.png").mime_type("image/png");
+    let form = MultipartForm::new().add_part("file", part);
+
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .add_header("X-Request-Id", "trace-123")
+        .multipart(form)
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+
+    let consumer_config = ConsumerConfig::builder(&ctx.brokers, "images-test-group", KAFKA_TOPIC).build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let received = consumer.consume_message::<KafkaMessage>().await?;
+
+    assert_eq!(received.message.user_id, user_id);
+    assert_eq!(received.message.action, Action::Create);
+    assert_eq!(received.message.data.as_deref(), body["filename"].as_str());
+    assert_eq!(received.headers.get("request_id").map(String::as_str), Some("trace-123"));
+    assert_eq!(received.message.version, CURRENT_VERSION);
+    assert_eq!(received.message.metadata(METADATA_OBJECT_KEY), body["filename"].as_str());
+    assert_eq!(received.message.metadata(METADATA_CONTENT_TYPE), Some("image/png"));
+    assert_eq!(received.message.metadata(METADATA_SIZE), Some(size.as_str()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_skips_kafka_while_brokers_are_down() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let upload = || {
+        let part = Part::bytes(png_fixture()).file_name("test.png").mime_type("image/png");
+        ctx.server
+            .post("/images/upload")
+            .authorization_bearer(token(&uuid::Uuid::now_v7().to_string()))
+            .multipart(MultipartForm::new().add_part("file", part))
+    };
+
+    ctx.kafka.pause().await?;
+    assert!(!kafka_health::check(&ctx.state).await);
+    let response = ctx.server.get("/health/ready").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["kafka_producer"], "unhealthy");
+    assert_eq!(body["checks"]["kafka"]["status"], "down");
+
+    let started = Instant::now();
+    for _ in 0..3 {
+        upload().await.assert_status(axum::http::StatusCode::CREATED);
+    }
+    assert!(
+        started.elapsed() < Duration::from_secs(3),
+        "uploads waited on Kafka: {:?}",
+        started.elapsed()
+    );
+    assert_eq!(ctx.state.unpublished.len(), 3);
+
+    ctx.kafka.unpause().await?;
+    let mut recovered = false;
+    for _ in 0..20 {
+        if kafka_health::check(&ctx.state).await {
+            recovered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(recovered, "producer did not recover after the broker came back");
+    assert!(ctx.state.unpublished.is_empty());
+
+    let consumer_config = ConsumerConfig::builder(&ctx.brokers, "images-health-group", KAFKA_TOPIC).build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    for _ in 0..3 {
+        assert_eq!(consumer.consume::<KafkaMessage>().await?.action, Action::Create);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ready_checks_dependencies() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    server_ready_is(&ctx, 200).await;
+    let body: serde_json::Value = ctx.server.get("/health/ready").await.json();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["s3"]["status"], "up");
+    assert_eq!(body["checks"]["kafka"]["status"], "up");
+    assert_eq!(body["checks"]["scylla"]["status"], "disabled");
+
+    ctx.minio.pause().await?;
+    let body: serde_json::Value = server_ready_is(&ctx, 503).await;
+    assert_eq!(body["checks"]["s3"]["status"], "down");
+    assert!(body["checks"]["s3"]["latency_ms"].as_u64().unwrap() <= 2500);
+    ctx.server.get("/health/live").await.assert_status_ok();
+
+    ctx.minio.unpause().await?;
+    server_ready_is(&ctx, 200).await;
+    Ok(())
+}
+
+async fn server_ready_is(ctx: &TestContext, status: u16) -> serde_json::Value {
+    let response = ctx.server.get("/health/ready").await;
+    response.assert_status(axum::http::StatusCode::from_u16(status).unwrap());
+    response.json()
+}
+
 #[tokio::test]
 async fn test_upload_png_success() -> anyhow::Result<()> {
     let ctx = setup().await?;
     let user_id = uuid::Uuid::now_v7().to_string();
 
-    let part = Part::bytes(vec![0x89, 0x50, 0x4E, 0x47])
-        .file_name("test.png")
-        .mime_type("image/png");
+    let part = Part::bytes(png_fixture()).file_name("test.png").mime_type("image/png");
     let form = MultipartForm::new().add_part("file", part);
 
     let response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", user_id)
+        .authorization_bearer(token(&user_id))
         .multipart(form)
         .await;
 
@@ -127,7 +315,7 @@ async fn test_upload_unsupported_content_type() -> anyhow::Result<()> {
     let response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", user_id)
+        .authorization_bearer(token(&user_id))
         .multipart(form)
         .await;
 
@@ -135,23 +323,45 @@ async fn test_upload_unsupported_content_type() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_upload_content_must_match_its_type() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let disguised = [
+        (png_fixture(), "image/jpeg"),
+        (b"\x7fELF\x02\x01\x01\0 not an image".to_vec(), "image/png"),
+        (Vec::new(), "image/png"),
+    ];
+    for (data, claimed) in disguised {
+        let part = Part::bytes(data).file_name("upload").mime_type(claimed);
+        let response = ctx
+            .server
+            .post("/images/upload")
+            .authorization_bearer(token(&user_id))
+            .multipart(MultipartForm::new().add_part("file", part))
+            .await;
+        response.assert_status(axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    assert!(ctx.state.storage.list("").await?.is_empty());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_upload_invalid_user_id() -> anyhow::Result<()> {
     let ctx = setup().await?;
 
-    let part = Part::bytes(vec![0xFF, 0xD8, 0xFF, 0xE0])
-        .file_name("test.jpg")
-        .mime_type("image/jpeg");
+    let part = Part::bytes(jpeg_fixture()).file_name("test.jpg").mime_type("image/jpeg");
     let form = MultipartForm::new().add_part("file", part);
 
     let response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", "not-a-uuid")
+        .authorization_bearer(token("not-a-uuid"))
         .multipart(form)
         .await;
 
-    response.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    response.assert_status(axum::http::StatusCode::UNAUTHORIZED);
     Ok(())
 }
 
@@ -160,14 +370,14 @@ async fn test_upload_and_download() -> anyhow::Result<()> {
     let ctx = setup().await?;
     let user_id = uuid::Uuid::now_v7().to_string();
 
-    let image_data = b"fake png content for testing".to_vec();
+    let image_data = png_fixture();
     let part = Part::bytes(image_data.clone()).file_name("test.png").mime_type("image/png");
     let form = MultipartForm::new().add_part("file", part);
 
     let upload_response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", user_id)
+        .authorization_bearer(token(&user_id))
         .multipart(form)
         .await;
     upload_response.assert_status(axum::http::StatusCode::CREATED);
@@ -188,6 +398,204 @@ async fn test_upload_and_download() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_download_revalidates_with_e_tag_and_date() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let part = Part::bytes(png_fixture()).file_name("test.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&uuid::Uuid::now_v7().to_string()))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    let body: serde_json::Value = response.json();
+    let path = format!("/images/{}", body["filename"].as_str().unwrap());
+
+    let first = ctx.server.get(&path).await;
+    first.assert_status_ok();
+    let e_tag = first.header("ETag");
+    let last_modified = first.header("Last-Modified");
+    assert!(e_tag.to_str()?.starts_with('"'), "{e_tag:?}");
+    assert!(last_modified.to_str()?.ends_with(" GMT"), "{last_modified:?}");
+    assert_eq!(first.header("Cache-Control"), CACHE_CONTROL);
+
+    let revalidated = ctx.server.get(&path).add_header("If-None-Match", e_tag.clone()).await;
+    revalidated.assert_status(axum::http::StatusCode::NOT_MODIFIED);
+    assert!(revalidated.as_bytes().is_empty());
+    assert_eq!(revalidated.header("ETag"), e_tag);
+    assert_eq!(revalidated.header("Cache-Control"), CACHE_CONTROL);
+
+    let by_date = ctx.server.get(&path).add_header("If-Modified-Since", last_modified).await;
+    by_date.assert_status(axum::http::StatusCode::NOT_MODIFIED);
+
+    let changed = ctx.server.get(&path).add_header("If-None-Match", "\"stale\"").await;
+    changed.assert_status_ok();
+    assert_eq!(changed.as_bytes(), first.as_bytes());
+
+    // Errors are left alone.
+    let missing = ctx.server.get("/images/missing.png").add_header("If-None-Match", "*").await;
+    missing.assert_status_not_found();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_download_ranges() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let image_data = image_fixture(64, 64, ImageFormat::Jpeg);
+    assert!(image_data.len() > 200);
+    let part = Part::bytes(image_data.clone()).file_name("test.jpg").mime_type("image/jpeg");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&uuid::Uuid::now_v7().to_string()))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+    let path = format!("/images/{}", body["filename"].as_str().unwrap());
+    let size = image_data.len();
+
+    let full = ctx.server.get(&path).await;
+    full.assert_status_ok();
+    assert_eq!(full.header("Accept-Ranges"), "bytes");
+    assert_eq!(full.header("Content-Length"), size.to_string().as_str());
+
+    let head = ctx.server.get(&path).add_header("Range", "bytes=0-99").await;
+    head.assert_status(axum::http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(head.as_bytes(), &image_data[..100]);
+    assert_eq!(head.header("Content-Range"), format!("bytes 0-99/{size}").as_str());
+    assert_eq!(head.header("Content-Length"), "100");
+    assert_eq!(head.header("Content-Type"), "image/jpeg");
+
+    let tail = ctx.server.get(&path).add_header("Range", "bytes=-50").await;
+    tail.assert_status(axum::http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(tail.as_bytes(), &image_data[size - 50..]);
+    assert_eq!(
+        tail.header("Content-Range"),
+        format!("bytes {}-{}/{size}", size - 50, size - 1).as_str()
+    );
+
+    for unsatisfiable in [format!("bytes={size}-"), "bytes=0-1,5-9".to_owned()] {
+        let response = ctx.server.get(&path).add_header("Range", unsatisfiable).await;
+        response.assert_status(axum::http::StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.header("Content-Range"), format!("bytes */{size}").as_str());
+    }
+
+    // A malformed header is ignored.
+    let ignored = ctx.server.get(&path).add_header("Range", "bytes=9-1").await;
+    ignored.assert_status_ok();
+    assert_eq!(ignored.as_bytes(), image_data.as_slice());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_renders_thumbnails() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let original = image_fixture(1024, 768, ImageFormat::Png);
+    let part = Part::bytes(original.clone()).file_name("photo.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    let filename = response.json::<serde_json::Value>()["filename"].as_str().unwrap().to_owned();
+
+    for key in [
+        filename.clone(),
+        format!("{filename}/thumb_128"),
+        format!("{filename}/thumb_512"),
+    ] {
+        assert!(ctx.state.storage.object_exists(&key).await?, "{key} should exist");
+    }
+    for (size, expected) in [(128, (128, 96)), (512, (512, 384))] {
+        let response = ctx.server.get(&format!("/images/{filename}?size={size}")).await;
+        response.assert_status_ok();
+        assert_eq!(response.header("Content-Type"), "image/png");
+        let thumbnail = image::load_from_memory(response.as_bytes())?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), expected);
+    }
+    // No 64px thumbnail is rendered, so the original is served.
+    let response = ctx.server.get(&format!("/images/{filename}?size=64")).await;
+    response.assert_status_ok();
+    assert_eq!(response.as_bytes(), original.as_slice());
+
+    ctx.server
+        .delete(&format!("/images/{filename}"))
+        .authorization_bearer(token(&user_id))
+        .await
+        .assert_status_ok();
+    assert!(!ctx.state.storage.object_exists(&format!("{filename}/thumb_128")).await?);
+    assert!(!ctx.state.storage.object_exists(&format!("{filename}/thumb_512")).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_corrupt_image_is_rejected() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let part = Part::bytes(b"\x89PNG\r\n\x1a\n truncated".to_vec())
+        .file_name("broken.png")
+        .mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    assert!(ctx.state.storage.list("").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_streams_large_file() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    // Past a single part, uploads are not decoded, so only the signature has to be a real image's.
+    let data = png_signed(20 * 1024 * 1024);
+    let part = Part::bytes(data.clone()).file_name("large.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::CREATED);
+    let keys = ctx.state.storage.list("").await?;
+    assert_eq!(keys.len(), 1, "no thumbnails for multipart uploads: {keys:?}");
+    assert_eq!(ctx.state.storage.download(&keys[0]).await?.data, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_over_limit_is_aborted() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let part = Part::bytes(png_signed(100 * 1024 * 1024))
+        .file_name("huge.png")
+        .mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+    response.assert_json(&serde_json::json!({"code": "payload_too_large", "error": "File exceeds the limit of 52428800 bytes"}));
+    assert!(ctx.state.storage.list("").await?.is_empty());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_download_nonexistent() -> anyhow::Result<()> {
     let ctx = setup().await?;
@@ -211,7 +619,7 @@ async fn test_delete_after_upload() -> anyhow::Result<()> {
     let ctx = setup().await?;
     let user_id = uuid::Uuid::now_v7().to_string();
 
-    let part = Part::bytes(b"delete me".to_vec())
+    let part = Part::bytes(image_fixture(4, 4, ImageFormat::Gif))
         .file_name("test.gif")
         .mime_type("image/gif");
     let form = MultipartForm::new().add_part("file", part);
@@ -219,7 +627,7 @@ async fn test_delete_after_upload() -> anyhow::Result<()> {
     let upload_response = ctx
         .server
         .post("/images/upload")
-        .add_header("X-User-Id", &user_id)
+        .authorization_bearer(token(&user_id))
         .multipart(form)
         .await;
     upload_response.assert_status(axum::http::StatusCode::CREATED);
@@ -227,10 +635,17 @@ async fn test_delete_after_upload() -> anyhow::Result<()> {
     let body: serde_json::Value = upload_response.json();
     let filename = body["filename"].as_str().unwrap();
 
+    let stranger = uuid::Uuid::now_v7().to_string();
+    ctx.server
+        .delete(&format!("/images/{}", filename))
+        .authorization_bearer(token(&stranger))
+        .await
+        .assert_status(axum::http::StatusCode::FORBIDDEN);
+
     let delete_response = ctx
         .server
         .delete(&format!("/images/{}", filename))
-        .add_header("X-User-Id", &user_id)
+        .authorization_bearer(token(&user_id))
         .await;
     delete_response.assert_status_ok();
 
@@ -243,6 +658,91 @@ async fn test_delete_after_upload() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_delete_publishes_event_and_removes_derived_objects() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let part = Part::bytes(png_fixture()).file_name("test.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    let filename = response.json::<serde_json::Value>()["filename"].as_str().unwrap().to_owned();
+    // Left over from when 64px thumbnails were configured.
+    let stale = format!("{filename}/thumb_64");
+    ctx.state.storage.upload(&stale, png_fixture().into(), "image/png").await?;
+
+    ctx.server
+        .delete(&format!("/images/{filename}"))
+        .authorization_bearer(token(&user_id))
+        .add_header("X-Request-Id", "trace-456")
+        .await
+        .assert_status_ok();
+    for key in [format!("{filename}/thumb_128"), format!("{filename}/thumb_512"), stale] {
+        assert!(!ctx.state.storage.object_exists(&key).await?, "{key} should be gone");
+    }
+
+    let consumer_config = ConsumerConfig::builder(&ctx.brokers, "images-delete-group", KAFKA_TOPIC).build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    assert_eq!(consumer.consume::<KafkaMessage>().await?.action, Action::Create);
+    let received = consumer.consume_message::<KafkaMessage>().await?;
+    assert_eq!(received.message.action, Action::Delete);
+    assert_eq!(received.message.user_id, user_id);
+    assert_eq!(received.message.data.as_deref(), Some(filename.as_str()));
+    assert_eq!(received.headers.get("request_id").map(String::as_str), Some("trace-456"));
+
+    // Deleting it again is a plain 404.
+    ctx.server
+        .delete(&format!("/images/{filename}"))
+        .authorization_bearer(token(&user_id))
+        .await
+        .assert_status_not_found();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_event_removes_the_object() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    ctx.state
+        .storage
+        .upload("evented.png", png_fixture().into(), "image/png")
+        .await?;
+
+    let consumer_config = ConsumerConfig::builder(&ctx.brokers, "images-worker-group", KAFKA_TOPIC).build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn({
+        let state = Arc::clone(&ctx.state);
+        let shutdown = shutdown.clone();
+        async move {
+            consumer
+                .run_with_handler(|event| events::handle_image_event(Arc::clone(&state), event), shutdown)
+                .await
+        }
+    });
+
+    ctx.state
+        .producer
+        .send(
+            "evented.png",
+            &KafkaMessage::new("user-1".into(), Action::Delete, Some("evented.png".to_owned())),
+        )
+        .await?;
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while ctx.state.storage.object_exists("evented.png").await? {
+        assert!(Instant::now() < deadline, "object still there");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    shutdown.cancel();
+    worker.await??;
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_delete_nonexistent() -> anyhow::Result<()> {
     let ctx = setup().await?;
@@ -250,7 +750,7 @@ async fn test_delete_nonexistent() -> anyhow::Result<()> {
     let response = ctx
         .server
         .delete("/images/does-not-exist-uuid")
-        .add_header("X-User-Id", user_id)
+        .authorization_bearer(token(&user_id))
         .await;
 
     response.assert_status_not_found();
@@ -261,8 +761,268 @@ async fn test_delete_nonexistent() -> anyhow::Result<()> {
 async fn test_delete_invalid_filename() -> anyhow::Result<()> {
     let ctx = setup().await?;
     let user_id = uuid::Uuid::now_v7().to_string();
-    let response = ctx.server.delete("/images/bad..name").add_header("X-User-Id", user_id).await;
+    let response = ctx
+        .server
+        .delete("/images/bad..name")
+        .authorization_bearer(token(&user_id))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+async fn upload_as(ctx: &TestContext, user_id: &str) -> String {
+    let part = Part::bytes(png_fixture()).file_name("batch.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    response.json::<serde_json::Value>()["filename"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_batch_delete_mixed_outcomes() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let other = uuid::Uuid::now_v7().to_string();
+
+    let mine = [upload_as(&ctx, &owner).await, upload_as(&ctx, &owner).await];
+    let theirs = upload_as(&ctx, &other).await;
+
+    let response = ctx
+        .server
+        .post("/images/delete-batch")
+        .authorization_bearer(token(&owner))
+        .json(&serde_json::json!({"keys": [mine[0], theirs, "does-not-exist", mine[1]]}))
+        .await;
+    response.assert_status_ok();
+    response.assert_json(&serde_json::json!({
+        "deleted": 2,
+        "results": [
+            {"key": mine[0], "status": "deleted"},
+            {"key": theirs, "status": "forbidden"},
+            {"key": "does-not-exist", "status": "not_found"},
+            {"key": mine[1], "status": "deleted"},
+        ]
+    }));
+
+    ctx.server
+        .get(&format!("/images/{}", mine[0]))
+        .await
+        .assert_status_not_found();
+    ctx.server.get(&format!("/images/{theirs}")).await.assert_status_ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_delete_rejects_over_limit() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let keys: Vec<String> = (0..101).map(|i| format!("key{i}")).collect();
 
+    let response = ctx
+        .server
+        .post("/images/delete-batch")
+        .authorization_bearer(token(&uuid::Uuid::now_v7().to_string()))
+        .json(&serde_json::json!({ "keys": keys }))
+        .await;
     response.assert_status(axum::http::StatusCode::BAD_REQUEST);
     Ok(())
 }
+
+#[tokio::test]
+async fn test_list_images_follows_cursor() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let uploaded = [
+        upload_as(&ctx, &owner).await,
+        upload_as(&ctx, &owner).await,
+        upload_as(&ctx, &owner).await,
+    ];
+    upload_as(&ctx, &uuid::Uuid::now_v7().to_string()).await;
+    assert!(uploaded.iter().all(|key| key.starts_with(&format!("{owner}/"))));
+
+    let response = ctx.server.get("/images?limit=2").authorization_bearer(token(&owner)).await;
+    response.assert_status_ok();
+    assert_eq!(response.headers()["cache-control"], "private, no-store");
+    let page: serde_json::Value = response.json();
+    let keys: Vec<&str> = page["images"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, [uploaded[0].as_str(), uploaded[1].as_str()]);
+    assert_eq!(page["images"][0]["content_type"], "image/png");
+    assert_eq!(page["images"][0]["size"], png_fixture().len());
+    assert!(page["images"][0]["uploaded_at"].is_i64());
+    assert_eq!(page["next_cursor"], uploaded[1].as_str());
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let response = ctx
+        .server
+        .get(&format!("/images?user_id={owner}&limit=2&cursor={cursor}"))
+        .authorization_bearer(token(&owner))
+        .await;
+    response.assert_status_ok();
+    let page: serde_json::Value = response.json();
+    let keys: Vec<&str> = page["images"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, [uploaded[2].as_str()]);
+    assert!(page["next_cursor"].is_null());
+
+    // Prefixed keys are downloadable and deletable like the unprefixed ones.
+    ctx.server.get(&format!("/images/{}", uploaded[0])).await.assert_status_ok();
+    ctx.server
+        .delete(&format!("/images/{}", uploaded[0]))
+        .authorization_bearer(token(&owner))
+        .await
+        .assert_status_ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_requires_token() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+
+    let response = ctx.server.get("/admin/kafka/topics").await;
+    response.assert_status(axum::http::StatusCode::UNAUTHORIZED);
+
+    let response = ctx
+        .server
+        .get("/admin/kafka/topics")
+        .add_header("Authorization", "Bearer wrong")
+        .await;
+    response.assert_status(axum::http::StatusCode::FORBIDDEN);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_storage_counts_by_listing() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    upload_as(&ctx, &owner).await;
+    upload_as(&ctx, &owner).await;
+
+    let response = ctx
+        .server
+        .get("/admin/storage")
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["bucket"], BUCKET);
+    assert_eq!(body["source"], "listing");
+    // Two originals plus a thumbnail per configured size for each.
+    assert_eq!(body["objects"], 6);
+    assert!(body["bytes"].as_u64().unwrap() > 0);
+    assert!(body["quota_bytes"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_describe_topic() -> anyhow::Result<()> {
+    let ctx = setup().await?;
+    let user_id = uuid::Uuid::now_v7().to_string();
+
+    let part = Part::bytes(jpeg_fixture()).file_name("test.jpg").mime_type("image/jpeg");
+    ctx.server
+        .post("/images/upload")
+        .authorization_bearer(token(&user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+
+    let response = ctx
+        .server
+        .get(&format!("/admin/kafka/topics/{KAFKA_TOPIC}"))
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["name"], KAFKA_TOPIC);
+    assert!(body["partitions"].as_array().is_some_and(|p| !p.is_empty()));
+
+    let response = ctx
+        .server
+        .get("/admin/kafka/topics/missing-topic")
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_not_found();
+
+    let response = ctx
+        .server
+        .get("/admin/kafka/groups/missing-group")
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_not_found();
+    Ok(())
+}
+
+async fn set_hold(ctx: &TestContext, key: &str, on: bool) -> serde_json::Value {
+    let response = ctx
+        .server
+        .put(&format!("/admin/objects/{key}/legal-hold"))
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .json(&serde_json::json!({ "on": on }))
+        .await;
+    response.assert_status_ok();
+    response.json()
+}
+
+#[tokio::test]
+async fn test_legal_hold_blocks_delete_until_released() -> anyhow::Result<()> {
+    let ctx = setup_with(true).await?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let held = upload_as(&ctx, &owner).await;
+    let free = upload_as(&ctx, &owner).await;
+
+    let status = set_hold(&ctx, &held, true).await;
+    assert_eq!(status["legal_hold"], true);
+    let response = ctx
+        .server
+        .get(&format!("/admin/objects/{held}"))
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["metadata"]["owner"], owner.as_str());
+
+    let response = ctx
+        .server
+        .delete(&format!("/images/{held}"))
+        .authorization_bearer(token(&owner))
+        .await;
+    response.assert_status(axum::http::StatusCode::LOCKED);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "object_on_hold");
+
+    let response = ctx
+        .server
+        .post("/images/delete-batch")
+        .authorization_bearer(token(&owner))
+        .json(&serde_json::json!({"keys": [held, free]}))
+        .await;
+    response.assert_json(&serde_json::json!({
+        "deleted": 1,
+        "results": [
+            {"key": held, "status": "locked"},
+            {"key": free, "status": "deleted"},
+        ]
+    }));
+    ctx.server.get(&format!("/images/{held}")).await.assert_status_ok();
+
+    let status = set_hold(&ctx, &held, false).await;
+    assert_eq!(status["legal_hold"], false);
+    ctx.server
+        .delete(&format!("/images/{held}"))
+        .authorization_bearer(token(&owner))
+        .await
+        .assert_status_ok();
+    ctx.server.get(&format!("/images/{held}")).await.assert_status_not_found();
+    Ok(())
+}
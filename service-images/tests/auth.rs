@@ -0,0 +1,104 @@
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use s3_client::S3;
+use serde_json::json;
+use service_images::{ServerBuilder, auth::mint_token, state::ServerState};
+use std::sync::Arc;
+
+/// Every request here is settled before storage or Kafka would be reached, so neither has to exist.
+async fn setup() -> anyhow::Result<TestServer> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    let state: ServerState = Arc::new(common::server_data(s3)?);
+    Ok(TestServer::new(ServerBuilder::init_router(state)))
+}
+
+fn text_upload() -> MultipartForm {
+    MultipartForm::new().add_part("file", Part::text("hello").file_name("a.txt").mime_type("text/plain"))
+}
+
+#[tokio::test]
+async fn test_valid_token_is_accepted() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let token = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 60);
+
+    // Past authentication, both are refused for their content instead.
+    let response = server
+        .post("/images/upload")
+        .authorization_bearer(&token)
+        .multipart(text_upload())
+        .await;
+    response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let response = server
+        .post("/images/delete-batch")
+        .authorization_bearer(&token)
+        .json(&json!({"keys": []}))
+        .await;
+    response.assert_status_bad_request();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expired_token_is_refused() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let token = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), -60);
+
+    let response = server
+        .post("/images/upload")
+        .authorization_bearer(token)
+        .multipart(text_upload())
+        .await;
+
+    response.assert_status_unauthorized();
+    response.assert_json(&json!({"code": "unauthorized", "error": "Token expired"}));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_token_is_refused() -> anyhow::Result<()> {
+    let server = setup().await?;
+
+    for response in [
+        server.post("/images/upload").multipart(text_upload()).await,
+        server.delete("/images/abc123").await,
+        server.post("/images/delete-batch").json(&json!({"keys": ["abc123"]})).await,
+        server.get("/images").await,
+    ] {
+        response.assert_status_unauthorized();
+        response.assert_json(&json!({"code": "unauthorized", "error": "Missing bearer token"}));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_images_of_another_user_cannot_be_listed() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let token = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 60);
+
+    let response = server
+        .get(&format!("/images?user_id={}", uuid::Uuid::now_v7()))
+        .authorization_bearer(token)
+        .await;
+
+    response.assert_status_forbidden();
+    response.assert_json(&json!({"code": "forbidden", "error": "Images of another user cannot be listed"}));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_signed_with_another_secret_is_refused() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let token = mint_token("some-other-secret-of-32-characters!", &uuid::Uuid::now_v7().to_string(), 60);
+
+    let response = server.delete("/images/abc123").authorization_bearer(token).await;
+
+    response.assert_status_unauthorized();
+    response.assert_json(&json!({"code": "unauthorized", "error": "Invalid token"}));
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Setup shared by the service-images integration tests; each test binary uses part of it.
+#![allow(dead_code)]
+
+use kafka_client::{admin::KafkaAdmin, config::ProducerConfig, producer::KafkaProducer};
+use service_images::{
+    auth::Authenticator,
+    downloads::DownloadCoalescer,
+    interceptor::ScanPolicy,
+    kafka_health::{PublishFailurePolicy, UnpublishedEvents},
+    lag::LagWatcher,
+    readiness::ReadinessProbe,
+    state::ServerData,
+    storage::{ObjectStorage, StorageUsage},
+};
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+pub const JWT_SECRET: &str = "test-secret-of-at-least-32-characters";
+
+/// Serves `storage` with an open readiness gate, no admin token and none of the optional limits, scanning, thumbnails,
+/// webhooks or quotas; tests override what they exercise with struct update syntax. The Kafka clients point at a closed
+/// port, which is fine as long as nothing is produced, since librdkafka connects lazily.
+pub fn server_data(storage: impl ObjectStorage + 'static) -> anyhow::Result<ServerData> {
+    Ok(ServerData {
+        storage: Arc::new(storage),
+        downloads: DownloadCoalescer::new(Duration::ZERO, 0, 0),
+        thumbnail_sizes: Vec::new(),
+        max_file_size: 10 * 1024 * 1024,
+        cache_control: None,
+        upload_limiter: None,
+        upload_interceptor: None,
+        scan_policy: ScanPolicy {
+            timeout: Duration::from_secs(5),
+            fail_open: false,
+        },
+        producer: KafkaProducer::new(ProducerConfig::builder("127.0.0.1:1", "images-test").build()?)?,
+        unpublished: UnpublishedEvents::new(PublishFailurePolicy::Ignore, 0),
+        kafka_admin: KafkaAdmin::new("127.0.0.1:1")?,
+        admin_token: None,
+        auth: Authenticator::new(JWT_SECRET),
+        webhooks: None,
+        quotas: None,
+        usage: StorageUsage::listing(),
+        lag: Arc::new(LagWatcher::new(Duration::from_secs(60))),
+        kafka_retention: Vec::new(),
+        readiness: ReadinessProbe::new(Duration::ZERO, true),
+        ready: AtomicBool::new(true),
+    })
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use axum_test::TestServer;
+use common::JWT_SECRET;
+use s3_client::S3;
+use service_images::{ServerBuilder, auth::mint_token, deadline::DEADLINE_HEADER, state::ServerState};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+
+/// S3 stand-in that accepts connections, counts them and never answers.
+async fn hanging_s3() -> anyhow::Result<(String, Arc<AtomicUsize>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn({
+        let connections = Arc::clone(&connections);
+        async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.fetch_add(1, Ordering::Relaxed);
+                held.push(socket);
+            }
+        }
+    });
+    Ok((endpoint, connections))
+}
+
+async fn setup() -> anyhow::Result<(TestServer, Arc<AtomicUsize>)> {
+    let (endpoint, connections) = hanging_s3().await?;
+    let s3 = S3::new("test", "test", "us-east-1", &endpoint, "test-images").await;
+    let state: ServerState = Arc::new(common::server_data(s3)?);
+    Ok((TestServer::new(ServerBuilder::init_router(state)), connections))
+}
+
+#[tokio::test]
+async fn test_expired_deadline_skips_s3() -> anyhow::Result<()> {
+    let (server, connections) = setup().await?;
+
+    let response = server.get("/images/abc123").add_header(DEADLINE_HEADER, "0").await;
+
+    response.assert_status(axum::http::StatusCode::GATEWAY_TIMEOUT);
+    response.assert_json(&serde_json::json!({"code": "deadline_exceeded", "error": "Request deadline exceeded"}));
+    assert_eq!(connections.load(Ordering::Relaxed), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deadline_cuts_off_hanging_s3() -> anyhow::Result<()> {
+    let (server, _connections) = setup().await?;
+
+    let started = Instant::now();
+    let response = server
+        .delete("/images/abc123")
+        .authorization_bearer(mint_token(JWT_SECRET, "0193a0e4-7f41-7c3b-9d2e-4a1b2c3d4e5f", 60))
+        .add_header(DEADLINE_HEADER, "100")
+        .await;
+
+    response.assert_status(axum::http::StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! The handlers against a `STORAGE_DIR`, with no containers: Kafka points at a closed port, so events are dropped.
+
+mod common;
+
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use kafka_client::{config::ProducerConfig, producer::KafkaProducer};
+use service_images::{
+    ServerBuilder,
+    auth::mint_token,
+    readiness::ReadinessProbe,
+    state::{ServerData, ServerState},
+    storage::FsStorage,
+};
+use std::{sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+const BROKERS: &str = "127.0.0.1:1";
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+fn token(user_id: &str) -> String {
+    mint_token(JWT_SECRET, user_id, 3600)
+}
+
+fn image_fixture(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(ImageBuffer::from_pixel(width, height, Rgb([30, 120, 200])))
+        .write_to(&mut std::io::Cursor::new(&mut out), format)
+        .expect("fixture encodes");
+    out
+}
+
+/// A PNG of noise, which does not compress, so it is larger than the one part uploads are buffered up to.
+fn large_png() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let image = ImageBuffer::from_fn(1600, 1200, |_, _| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let [r, g, b, ..] = state.to_le_bytes();
+        Rgb([r, g, b])
+    });
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Png)
+        .expect("fixture encodes");
+    assert!(out.len() > 5 * 1024 * 1024);
+    out
+}
+
+struct TestContext {
+    server: TestServer,
+    state: ServerState,
+    dir: TempDir,
+}
+
+fn setup() -> anyhow::Result<TestContext> {
+    let dir = tempfile::tempdir()?;
+    let storage = FsStorage::new(dir.path())?;
+    let producer_config = ProducerConfig::builder(BROKERS, "images-test")
+        .message_timeout_ms(100)
+        .build()?;
+
+    let state: ServerState = Arc::new(ServerData {
+        thumbnail_sizes: vec![128],
+        max_file_size: 50 * 1024 * 1024,
+        producer: KafkaProducer::new(producer_config)?,
+        admin_token: Some(ADMIN_TOKEN.to_owned()),
+        readiness: ReadinessProbe::new(Duration::ZERO, false),
+        ..common::server_data(storage)?
+    });
+
+    let server = TestServer::new(ServerBuilder::init_router(Arc::clone(&state)));
+    Ok(TestContext { server, state, dir })
+}
+
+async fn upload_as(ctx: &TestContext, user_id: &str, image: Vec<u8>) -> String {
+    let part = Part::bytes(image).file_name("photo.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(user_id))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status(axum::http::StatusCode::CREATED);
+    response.json::<serde_json::Value>()["filename"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_upload_download_and_delete() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let original = image_fixture(256, 192, ImageFormat::Png);
+    let key = upload_as(&ctx, &owner, original.clone()).await;
+    assert!(ctx.state.storage.object_exists(&format!("{key}/thumb_128")).await?);
+
+    let response = ctx.server.get(&format!("/images/{key}")).await;
+    response.assert_status_ok();
+    assert_eq!(response.as_bytes(), original.as_slice());
+    assert_eq!(response.header("Content-Type"), "image/png");
+
+    let thumbnail = ctx.server.get(&format!("/images/{key}?size=128")).await;
+    thumbnail.assert_status_ok();
+    let thumbnail = image::load_from_memory(thumbnail.as_bytes())?;
+    assert_eq!((thumbnail.width(), thumbnail.height()), (128, 96));
+
+    let e_tag = response.header("ETag");
+    ctx.server
+        .get(&format!("/images/{key}"))
+        .add_header("If-None-Match", e_tag)
+        .await
+        .assert_status(axum::http::StatusCode::NOT_MODIFIED);
+
+    let size = original.len();
+    let tail = ctx
+        .server
+        .get(&format!("/images/{key}"))
+        .add_header("Range", "bytes=-10")
+        .await;
+    tail.assert_status(axum::http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(tail.as_bytes(), &original[size - 10..]);
+    assert_eq!(
+        tail.header("Content-Range"),
+        format!("bytes {}-{}/{size}", size - 10, size - 1).as_str()
+    );
+
+    ctx.server
+        .delete(&format!("/images/{key}"))
+        .authorization_bearer(token(&owner))
+        .await
+        .assert_status_ok();
+    ctx.server.get(&format!("/images/{key}")).await.assert_status_not_found();
+    assert!(ctx.state.storage.list("").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_uploads_past_one_part_are_decoded_too() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let key = upload_as(&ctx, &owner, large_png()).await;
+    assert!(ctx.state.storage.object_exists(&format!("{key}/thumb_128")).await?);
+
+    // A PNG signature in front of garbage passes the sniffing but not the decoder.
+    let mut corrupt = image_fixture(4, 4, ImageFormat::Png)[..16].to_vec();
+    corrupt.resize(6 * 1024 * 1024, 0);
+    let part = Part::bytes(corrupt).file_name("photo.png").mime_type("image/png");
+    let response = ctx
+        .server
+        .post("/images/upload")
+        .authorization_bearer(token(&owner))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await;
+    response.assert_status_bad_request();
+    let mut stored = ctx.state.storage.list(&owner).await?;
+    stored.retain(|stored| !stored.starts_with(&key));
+    assert_eq!(stored, Vec::<String>::new());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_and_batch_delete() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let other = uuid::Uuid::now_v7().to_string();
+    let mine = [
+        upload_as(&ctx, &owner, image_fixture(4, 4, ImageFormat::Png)).await,
+        upload_as(&ctx, &owner, image_fixture(4, 4, ImageFormat::Png)).await,
+    ];
+    let theirs = upload_as(&ctx, &other, image_fixture(4, 4, ImageFormat::Png)).await;
+
+    let page: serde_json::Value = ctx
+        .server
+        .get("/images?limit=1")
+        .authorization_bearer(token(&owner))
+        .await
+        .json();
+    assert_eq!(page["images"][0]["key"], mine[0].as_str());
+    assert_eq!(page["images"][0]["content_type"], "image/png");
+    assert_eq!(page["next_cursor"], mine[0].as_str());
+    let page: serde_json::Value = ctx
+        .server
+        .get(&format!("/images?user_id={owner}&limit=1&cursor={}", mine[0]))
+        .authorization_bearer(token(&owner))
+        .await
+        .json();
+    assert_eq!(page["images"][0]["key"], mine[1].as_str());
+
+    let response = ctx
+        .server
+        .post("/images/delete-batch")
+        .authorization_bearer(token(&owner))
+        .json(&serde_json::json!({"keys": [mine[0], theirs, "does-not-exist", mine[1]]}))
+        .await;
+    response.assert_status_ok();
+    response.assert_json(&serde_json::json!({
+        "deleted": 2,
+        "results": [
+            {"key": mine[0], "status": "deleted"},
+            {"key": theirs, "status": "forbidden"},
+            {"key": "does-not-exist", "status": "not_found"},
+            {"key": mine[1], "status": "deleted"},
+        ]
+    }));
+    assert_eq!(ctx.state.storage.list(&owner).await?, Vec::<String>::new());
+    ctx.server.get(&format!("/images/{theirs}")).await.assert_status_ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_legal_hold_blocks_delete() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    let owner = uuid::Uuid::now_v7().to_string();
+    let key = upload_as(&ctx, &owner, image_fixture(4, 4, ImageFormat::Png)).await;
+
+    let set_hold = |on: bool| {
+        ctx.server
+            .put(&format!("/admin/objects/{key}/legal-hold"))
+            .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+            .json(&serde_json::json!({ "on": on }))
+    };
+    set_hold(true).await.assert_status_ok();
+    let response = ctx
+        .server
+        .get(&format!("/admin/objects/{key}"))
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["legal_hold"], true);
+    assert_eq!(body["metadata"]["owner"], owner.as_str());
+
+    let response = ctx
+        .server
+        .delete(&format!("/images/{key}"))
+        .authorization_bearer(token(&owner))
+        .await;
+    response.assert_status(axum::http::StatusCode::LOCKED);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "object_on_hold");
+
+    set_hold(false).await.assert_status_ok();
+    ctx.server
+        .delete(&format!("/images/{key}"))
+        .authorization_bearer(token(&owner))
+        .await
+        .assert_status_ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_storage_counts_the_directory() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    upload_as(
+        &ctx,
+        &uuid::Uuid::now_v7().to_string(),
+        image_fixture(256, 192, ImageFormat::Png),
+    )
+    .await;
+
+    let response = ctx
+        .server
+        .get("/admin/storage")
+        .add_header("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["bucket"], ctx.dir.path().to_str().unwrap());
+    // The original and its thumbnail.
+    assert_eq!(body["objects"], 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ready_checks_the_directory() -> anyhow::Result<()> {
+    let ctx = setup()?;
+    let response = ctx.server.get("/health/ready").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["checks"]["fs"]["status"], "up");
+    assert_eq!(body["checks"]["kafka"]["status"], "down");
+    assert!(body["checks"].get("s3").is_none());
+
+    std::fs::remove_dir_all(ctx.dir.path())?;
+    let response = ctx.server.get("/health/ready").await;
+    response.assert_status(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json::<serde_json::Value>()["checks"]["fs"]["status"], "down");
+    Ok(())
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use s3_client::S3;
+use service_images::{
+    ServerBuilder,
+    auth::mint_token,
+    interceptor::{RejectReason, ScanPolicy, UploadInterceptor},
+    state::{ServerData, ServerState},
+};
+use std::{sync::Arc, time::Duration};
+
+const SIGNATURE: &[u8] = b"X5O!FAKE-MALWARE-SIGNATURE";
+
+/// Rejects files containing [`SIGNATURE`], after `delay`.
+struct SignatureScanner {
+    delay: Duration,
+}
+
+#[async_trait]
+impl UploadInterceptor for SignatureScanner {
+    async fn inspect(&self, _content_type: &str, data: &[u8]) -> Result<(), RejectReason> {
+        tokio::time::sleep(self.delay).await;
+        if data.windows(SIGNATURE.len()).any(|window| window == SIGNATURE) {
+            return Err(RejectReason("Signature found".into()));
+        }
+        Ok(())
+    }
+}
+
+/// S3 is unreachable, so an upload that got past the interceptor would fail with `500` instead.
+async fn setup(scanner: SignatureScanner) -> anyhow::Result<TestServer> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    let state: ServerState = Arc::new(ServerData {
+        upload_interceptor: Some(Arc::new(scanner)),
+        scan_policy: ScanPolicy {
+            timeout: Duration::from_millis(200),
+            fail_open: false,
+        },
+        ..common::server_data(s3)?
+    });
+    Ok(TestServer::new(ServerBuilder::init_router(state)))
+}
+
+/// Sniffs as a PNG; thumbnails are off, so it is never decoded.
+fn png_with(payload: &[u8]) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    data.extend_from_slice(&[0; 64]);
+    data.extend_from_slice(payload);
+    data
+}
+
+async fn upload(server: &TestServer, data: Vec<u8>) -> axum_test::TestResponse {
+    let part = Part::bytes(data).file_name("test.png").mime_type("image/png");
+    server
+        .post("/images/upload")
+        .authorization_bearer(mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 3600))
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await
+}
+
+#[tokio::test]
+async fn test_rejected_upload_answers_422_with_the_reason() -> anyhow::Result<()> {
+    let server = setup(SignatureScanner { delay: Duration::ZERO }).await?;
+
+    let response = upload(&server, png_with(SIGNATURE)).await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    response
+        .assert_json(&serde_json::json!({"code": "upload_rejected", "error": "Upload rejected", "message": "Signature found"}));
+
+    let response = upload(&server, png_with(b"clean")).await;
+    assert_ne!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scan_timeout_fails_closed() -> anyhow::Result<()> {
+    let server = setup(SignatureScanner {
+        delay: Duration::from_secs(5),
+    })
+    .await?;
+
+    let response = upload(&server, png_with(b"clean")).await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use s3_client::S3;
+use service_images::{
+    ServerBuilder,
+    listener::{self, ListenAddr},
+    state::{ServerData, ServerState},
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+};
+use tokio_util::sync::CancellationToken;
+
+/// State whose S3 and Kafka clients are never used; `/ping` does not touch them.
+async fn state() -> anyhow::Result<ServerState> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    Ok(Arc::new(ServerData {
+        ready: AtomicBool::new(false),
+        ..common::server_data(s3)?
+    }))
+}
+
+fn socket_path() -> PathBuf {
+    std::env::temp_dir().join(format!("service-images-{}.sock", uuid::Uuid::now_v7()))
+}
+
+async fn ping<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> anyhow::Result<String> {
+    stream
+        .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn test_ping_over_tcp_and_unix_socket() -> anyhow::Result<()> {
+    let path = socket_path();
+    let addrs = [
+        ListenAddr::Tcp("127.0.0.1:0".into()),
+        ListenAddr::Unix {
+            path: path.clone(),
+            mode: 0o600,
+        },
+    ];
+    let listeners = listener::bind_all(&addrs).await?;
+    let ListenAddr::Tcp(tcp_addr) = listeners[0].local_addr()? else {
+        unreachable!("first listener is TCP");
+    };
+    let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path)?.permissions());
+    assert_eq!(mode & 0o777, 0o600);
+
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(listener::serve(
+        listeners,
+        ServerBuilder::init_router(state().await?),
+        shutdown.clone(),
+    ));
+
+    let response = ping(TcpStream::connect(&tcp_addr).await?).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    let response = ping(UnixStream::connect(&path).await?).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), server).await???;
+    assert!(TcpStream::connect(&tcp_addr).await.is_err());
+    assert!(!path.exists(), "socket file is removed on shutdown");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stale_socket_is_replaced_but_live_one_is_not() -> anyhow::Result<()> {
+    let path = socket_path();
+    let addrs = [ListenAddr::Unix {
+        path: path.clone(),
+        mode: 0o660,
+    }];
+
+    // A socket file nobody listens on any more, as left by a killed process.
+    drop(std::os::unix::net::UnixListener::bind(&path)?);
+    assert!(path.exists());
+    let listeners = listener::bind_all(&addrs).await?;
+
+    let err = listener::bind_all(&addrs).await.err().expect("socket is still in use");
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+
+    drop(listeners);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_regular_file_is_not_overwritten() -> anyhow::Result<()> {
+    let path = socket_path();
+    std::fs::write(&path, b"not a socket")?;
+
+    let addrs = [ListenAddr::Unix {
+        path: path.clone(),
+        mode: 0o660,
+    }];
+    assert!(listener::bind_all(&addrs).await.is_err());
+    assert_eq!(std::fs::read(&path)?, b"not a socket");
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
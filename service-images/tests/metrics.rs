@@ -0,0 +1,70 @@
+mod common;
+
+use axum::routing;
+use axum_prometheus::PrometheusMetricLayer;
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use s3_client::S3;
+use service_images::{ServerBuilder, auth::mint_token, kafka_health, metrics, state::ServerState};
+use std::sync::Arc;
+use testcontainers_modules::{minio::MinIO, testcontainers::runners::AsyncRunner as _};
+
+fn png_fixture() -> Vec<u8> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, Rgb([30, 120, 200])))
+        .write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Png)
+        .expect("fixture encodes");
+    out
+}
+
+/// Real S3, but a producer with no broker behind it, so every image event counts as a publish failure.
+async fn state(endpoint: &str) -> anyhow::Result<ServerState> {
+    let s3 = S3::new("minioadmin", "minioadmin", "us-east-1", endpoint, "test-images").await;
+    s3.create_bucket().await?;
+    Ok(Arc::new(common::server_data(s3)?))
+}
+
+#[tokio::test]
+async fn test_upload_is_counted_on_metrics() -> anyhow::Result<()> {
+    let minio = MinIO::default().start().await?;
+    let endpoint = format!("http://127.0.0.1:{}", minio.get_host_port_ipv4(9000).await?);
+    let state = state(&endpoint).await?;
+    assert!(!kafka_health::check(&state).await);
+
+    // The only test of the binary, as the recorder is global.
+    let handle = metrics::install();
+    let router = ServerBuilder::init_router(Arc::clone(&state))
+        .route("/metrics", routing::get(move || async move { handle.render() }))
+        .layer(PrometheusMetricLayer::new());
+    let server = TestServer::new(router);
+
+    let image = png_fixture();
+    let token = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 3600);
+    for _ in 0..2 {
+        let part = Part::bytes(image.clone()).file_name("test.png").mime_type("image/png");
+        server
+            .post("/images/upload")
+            .authorization_bearer(&token)
+            .multipart(MultipartForm::new().add_part("file", part))
+            .await
+            .assert_status(axum::http::StatusCode::CREATED);
+    }
+
+    let rendered = server.get("/metrics").await.text();
+    for line in [
+        r#"images_uploaded_total{content_type="image/png"} 2"#.to_owned(),
+        "image_upload_bytes_count 2".to_owned(),
+        format!("image_upload_bytes_sum {}", 2 * image.len()),
+        r#"kafka_publish_failures_total{reason="unhealthy"} 2"#.to_owned(),
+        r#"s3_operation_duration_seconds_count{operation="upload"} 2"#.to_owned(),
+        "# HELP images_uploaded_total Images stored, by content type".to_owned(),
+    ] {
+        assert!(rendered.lines().any(|l| l == line), "missing {line} in:\n{rendered}");
+    }
+    assert!(rendered.contains(r#"image_upload_bytes_bucket{le="1024"} 2"#), "{rendered}");
+    Ok(())
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use axum_test::TestServer;
+use s3_client::S3;
+use service_images::{ServerBuilder, state::ServerState};
+use std::sync::Arc;
+
+/// State whose S3 and Kafka clients are never used; the spec does not touch them.
+async fn state() -> anyhow::Result<ServerState> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    Ok(Arc::new(common::server_data(s3)?))
+}
+
+#[tokio::test]
+async fn test_spec_documents_the_upload() -> anyhow::Result<()> {
+    let server = TestServer::new(ServerBuilder::init_router(state().await?));
+
+    let response = server.get("/openapi.json").await;
+    response.assert_status_ok();
+    let spec: serde_json::Value = serde_json::from_str(&response.text())?;
+    assert!(spec["openapi"].as_str().is_some_and(|v| v.starts_with("3.")));
+
+    let upload = &spec["paths"]["/images/upload"]["post"];
+    let form = &upload["requestBody"]["content"]["multipart/form-data"]["schema"];
+    assert_eq!(form["$ref"], "#/components/schemas/UploadForm");
+    let file = &spec["components"]["schemas"]["UploadForm"]["properties"]["file"];
+    assert_eq!(file["format"], "binary");
+    assert_eq!(
+        upload["responses"]["201"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ImageName"
+    );
+    assert_eq!(
+        upload["responses"]["413"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorBody"
+    );
+    let error = &spec["components"]["schemas"]["ErrorBody"];
+    assert!(error["properties"]["error"].is_object() && error["properties"]["message"].is_object());
+    assert_eq!(error["required"], serde_json::json!(["code", "error"]));
+    Ok(())
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use s3_client::S3;
+use scylladb_client::{ScyllaConfig, quotas::QuotaStore};
+use serde_json::json;
+use service_images::{
+    ServerBuilder,
+    auth::mint_token,
+    kafka_health,
+    quota::Quotas,
+    state::{ServerData, ServerState},
+};
+use std::sync::Arc;
+use testcontainers_modules::{minio::MinIO, scylladb::ScyllaDB, testcontainers::runners::AsyncRunner as _};
+use uuid::Uuid;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+fn png_fixture() -> Vec<u8> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, Rgb([30, 120, 200])))
+        .write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Png)
+        .expect("fixture encodes");
+    out
+}
+
+/// Real S3 and Scylla; Kafka is unreachable and skipped.
+async fn state(endpoint: &str, scylla_port: u16, default_limit_bytes: u64) -> anyhow::Result<ServerState> {
+    let s3 = S3::new("minioadmin", "minioadmin", "us-east-1", endpoint, "test-images").await;
+    s3.create_bucket().await?;
+    let scylla = ScyllaConfig {
+        uri: format!("127.0.0.1:{scylla_port}"),
+        keyspace: "images".into(),
+        replication_factor: 1,
+        ..Default::default()
+    };
+
+    let state = Arc::new(ServerData {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        quotas: Some(Quotas {
+            store: Arc::new(QuotaStore::new(&scylla, true).await?),
+            default_limit_bytes,
+        }),
+        ..common::server_data(s3)?
+    });
+    assert!(!kafka_health::check(&state).await);
+    Ok(state)
+}
+
+async fn upload(server: &TestServer, token: &str, image: &[u8]) -> axum_test::TestResponse {
+    let part = Part::bytes(image.to_vec()).file_name("test.png").mime_type("image/png");
+    server
+        .post("/images/upload")
+        .authorization_bearer(token)
+        .multipart(MultipartForm::new().add_part("file", part))
+        .await
+}
+
+#[tokio::test]
+async fn test_quota_refuses_uploads_until_space_is_freed() -> anyhow::Result<()> {
+    let (minio, scylla) = tokio::join!(MinIO::default().start(), ScyllaDB::default().start());
+    let (minio, scylla) = (minio?, scylla?);
+    let endpoint = format!("http://127.0.0.1:{}", minio.get_host_port_ipv4(9000).await?);
+    let image = png_fixture();
+    let size = image.len() as u64;
+    // Room for two and a half images.
+    let limit = 2 * size + size / 2;
+    let state = state(&endpoint, scylla.get_host_port_ipv4(9042).await?, limit).await?;
+    let server = TestServer::new(ServerBuilder::init_router(Arc::clone(&state)));
+
+    let user_id = Uuid::now_v7();
+    let token = mint_token(JWT_SECRET, &user_id.to_string(), 3600);
+    let mut stored = Vec::new();
+    let refused = loop {
+        let response = upload(&server, &token, &image).await;
+        if response.status_code() != StatusCode::CREATED {
+            break response;
+        }
+        stored.push(response.json::<serde_json::Value>()["filename"].as_str().unwrap().to_owned());
+        assert!(stored.len() <= 2, "uploaded past the quota");
+    };
+    refused.assert_status(StatusCode::FORBIDDEN);
+    refused.assert_json(&json!({"code": "quota_exceeded", "error": "Upload quota exceeded", "used": 2 * size, "limit": limit}));
+    // Nothing of the refused upload was kept.
+    assert_eq!(state.storage.list(&format!("{user_id}/")).await?.len(), 2);
+
+    server
+        .delete(&format!("/images/{}", stored[0]))
+        .authorization_bearer(&token)
+        .await
+        .assert_status_ok();
+    upload(&server, &token, &image).await.assert_status(StatusCode::CREATED);
+
+    // A paid tier gets its own limit.
+    let response = server
+        .put(&format!("/admin/quotas/{user_id}"))
+        .authorization_bearer(ADMIN_TOKEN)
+        .json(&json!({"limit_bytes": 10 * size}))
+        .await;
+    response.assert_status_ok();
+    response.assert_json(&json!({"used": 2 * size, "limit": 10 * size}));
+    upload(&server, &token, &image).await.assert_status(StatusCode::CREATED);
+
+    // Other users are unaffected.
+    let other_id = Uuid::now_v7();
+    let other = mint_token(JWT_SECRET, &other_id.to_string(), 3600);
+    upload(&server, &other, &image).await.assert_status(StatusCode::CREATED);
+
+    // Streaming stops at the room left, well before the size limit, and nothing of it is kept.
+    let mut large = image.clone();
+    large.resize(state.max_file_size as usize + 1, 0);
+    let refused = upload(&server, &other, &large).await;
+    refused.assert_status(StatusCode::FORBIDDEN);
+    refused.assert_json(&json!({"code": "quota_exceeded", "error": "Upload quota exceeded", "used": size, "limit": limit}));
+    assert_eq!(state.storage.list(&format!("{other_id}/")).await?.len(), 1);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::{
+    TestServer,
+    multipart::{MultipartForm, Part},
+};
+use common::JWT_SECRET;
+use s3_client::S3;
+use service_images::{
+    ServerBuilder,
+    auth::mint_token,
+    rate_limit::RateLimiter,
+    state::{ServerData, ServerState},
+};
+use std::{sync::Arc, time::Duration};
+
+const UPLOADS_PER_MIN: u32 = 2;
+
+/// Text uploads are refused before storage or Kafka would be reached, so neither has to exist.
+async fn setup() -> anyhow::Result<TestServer> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    let state: ServerState = Arc::new(ServerData {
+        upload_limiter: Some(RateLimiter::new(UPLOADS_PER_MIN, UPLOADS_PER_MIN, Duration::from_secs(60))),
+        ..common::server_data(s3)?
+    });
+    Ok(TestServer::new(ServerBuilder::init_router(state)))
+}
+
+fn text_upload() -> MultipartForm {
+    MultipartForm::new().add_part("file", Part::text("hello").file_name("a.txt").mime_type("text/plain"))
+}
+
+#[tokio::test]
+async fn test_uploads_over_the_limit_get_429() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let token = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 60);
+
+    for _ in 0..UPLOADS_PER_MIN {
+        let response = server
+            .post("/images/upload")
+            .authorization_bearer(&token)
+            .multipart(text_upload())
+            .await;
+        response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let response = server
+        .post("/images/upload")
+        .authorization_bearer(&token)
+        .multipart(text_upload())
+        .await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    response.assert_json(&serde_json::json!({"code": "too_many_requests", "error": "Too many requests"}));
+    let retry_after: u64 = response.header("retry-after").to_str()?.parse()?;
+    assert!((1..=30).contains(&retry_after), "Retry-After: {retry_after}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_limit_is_per_user() -> anyhow::Result<()> {
+    let server = setup().await?;
+    let busy = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 60);
+    let other = mint_token(JWT_SECRET, &uuid::Uuid::now_v7().to_string(), 60);
+
+    for _ in 0..=UPLOADS_PER_MIN {
+        server
+            .post("/images/upload")
+            .authorization_bearer(&busy)
+            .multipart(text_upload())
+            .await;
+    }
+
+    let response = server
+        .post("/images/upload")
+        .authorization_bearer(&other)
+        .multipart(text_upload())
+        .await;
+    response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // Unauthenticated requests are not counted, and still refused by the handler.
+    for _ in 0..=UPLOADS_PER_MIN {
+        server
+            .post("/images/upload")
+            .multipart(text_upload())
+            .await
+            .assert_status_unauthorized();
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use s3_client::S3;
+use service_images::{
+    ServerBuilder,
+    state::{ServerData, ServerState},
+};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// State whose S3 and Kafka are unreachable.
+async fn state() -> anyhow::Result<ServerState> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    Ok(Arc::new(ServerData {
+        ready: AtomicBool::new(false),
+        ..common::server_data(s3)?
+    }))
+}
+
+#[tokio::test]
+async fn test_ready_follows_gate_and_dependencies() -> anyhow::Result<()> {
+    let state = state().await?;
+    let server = TestServer::new(ServerBuilder::init_router(Arc::clone(&state)));
+
+    // Dependencies are not checked before the gate opens.
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    response.assert_json(&serde_json::json!({"status": "not ready", "kafka_producer": "healthy"}));
+
+    state.ready.store(true, Ordering::Release);
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["status"], "not ready");
+    assert_eq!(body["checks"]["s3"]["status"], "down");
+    assert_eq!(body["checks"]["s3"]["required"], true);
+    assert_eq!(body["checks"]["kafka"]["status"], "down");
+    assert_eq!(
+        body["checks"]["scylla"],
+        serde_json::json!({"status": "disabled", "required": true, "latency_ms": 0})
+    );
+
+    // Liveness is independent of both.
+    server.get("/health/live").await.assert_status_ok();
+    state.ready.store(false, Ordering::Release);
+    server.get("/ping").await.assert_status_ok();
+    Ok(())
+}
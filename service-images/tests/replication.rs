@@ -0,0 +1,152 @@
+use kafka_client::{
+    admin::KafkaAdmin,
+    config::{ConsumerConfig, ProducerConfig},
+    consumer::KafkaConsumer,
+    producer::KafkaProducer,
+    schemas::{Action, KafkaMessage},
+};
+use s3_client::{S3, replication::SOURCE_ETAG_METADATA};
+use service_images::replication::{BackfillReport, Replicator};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use testcontainers_modules::{
+    kafka::Kafka,
+    minio::MinIO,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+use tokio_util::sync::CancellationToken;
+
+const ACCESS_KEY: &str = "minioadmin";
+const SECRET_KEY: &str = "minioadmin";
+const REGION: &str = "us-east-1";
+const KAFKA_TOPIC: &str = "images-replication-test";
+
+async fn bucket(minio: &ContainerAsync<MinIO>, name: &'static str) -> anyhow::Result<S3> {
+    let port = minio.get_host_port_ipv4(9000).await?;
+    let s3 = S3::new(ACCESS_KEY, SECRET_KEY, REGION, format!("http://127.0.0.1:{port}"), name).await;
+    s3.create_bucket().await?;
+    Ok(s3)
+}
+
+/// Polls until `check` holds, for at most 30 seconds.
+async fn eventually<F, Fut>(mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..60 {
+        if check().await {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_backfill_is_idempotent() -> anyhow::Result<()> {
+    let (primary_minio, replica_minio) = tokio::join!(MinIO::default().start(), MinIO::default().start());
+    let (primary_minio, replica_minio) = (primary_minio?, replica_minio?);
+    let primary = bucket(&primary_minio, "primary").await?;
+    let replica = bucket(&replica_minio, "replica").await?;
+
+    let owner = HashMap::from([("owner".to_string(), "user-1".to_string())]);
+    primary
+        .upload_with_metadata("img-small", b"small image".to_vec(), "image/png", owner.clone())
+        .await?;
+    // Larger than one multipart chunk, so the copy goes through the streamed multipart path.
+    let large: Vec<u8> = (0..6 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    primary.upload("img-large", large.clone(), "image/jpeg").await?;
+    primary.upload("other-file", b"not an image".to_vec(), "text/plain").await?;
+
+    let replicator = Replicator::new(primary, replica);
+    let first = replicator.backfill("img-").await?;
+    assert_eq!(
+        first,
+        BackfillReport {
+            copied: 2,
+            up_to_date: 0,
+            failed: 0
+        }
+    );
+    let second = replicator.backfill("img-").await?;
+    assert_eq!(second.copied, 0);
+    assert_eq!(second.up_to_date, 2);
+
+    let replica = S3::new(
+        ACCESS_KEY,
+        SECRET_KEY,
+        REGION,
+        format!("http://127.0.0.1:{}", replica_minio.get_host_port_ipv4(9000).await?),
+        "replica",
+    )
+    .await;
+    let small = replica.head("img-small").await?.expect("small image replicated");
+    assert_eq!(small.content_type.as_deref(), Some("image/png"));
+    assert_eq!(small.metadata.get("owner"), owner.get("owner"));
+    assert!(small.metadata.contains_key(SOURCE_ETAG_METADATA));
+    assert_eq!(replica.download("img-large").await?.data, large);
+    assert!(!replica.object_exists("other-file").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_events_replicate_creates_and_deletes() -> anyhow::Result<()> {
+    let (primary_minio, replica_minio, kafka) =
+        tokio::join!(MinIO::default().start(), MinIO::default().start(), Kafka::default().start());
+    let (primary_minio, replica_minio, kafka) = (primary_minio?, replica_minio?, kafka?);
+    let primary = bucket(&primary_minio, "primary").await?;
+    let replica_port = replica_minio.get_host_port_ipv4(9000).await?;
+    let replica = bucket(&replica_minio, "replica").await?;
+    let brokers = format!("{}:{}", kafka.get_host().await?, kafka.get_host_port_ipv4(9093).await?);
+    KafkaAdmin::new(&brokers)?.ensure_topic(KAFKA_TOPIC, 1, 1, None).await?;
+    let producer = KafkaProducer::new(ProducerConfig::builder(&brokers, KAFKA_TOPIC).build()?)?;
+
+    primary.upload("img-1", b"first".to_vec(), "image/png").await?;
+    primary.upload("img-2", b"second".to_vec(), "image/png").await?;
+    for key in ["img-1", "img-2"] {
+        producer
+            .send(
+                key,
+                &KafkaMessage::new("user-1".into(), Action::Create, Some(key.to_string())),
+            )
+            .await?;
+    }
+
+    let consumer_config = ConsumerConfig::builder(&brokers, "replication-test", KAFKA_TOPIC)
+        .max_retries(3)
+        .retry_backoff_ms(10, 100)
+        .build()?;
+    let consumer = KafkaConsumer::new(consumer_config)?;
+    let replicator = Arc::new(Replicator::new(primary, replica));
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn({
+        let replicator = Arc::clone(&replicator);
+        let shutdown = shutdown.clone();
+        async move { replicator.run(&consumer, shutdown).await }
+    });
+
+    let check = S3::new(
+        ACCESS_KEY,
+        SECRET_KEY,
+        REGION,
+        format!("http://127.0.0.1:{replica_port}"),
+        "replica",
+    )
+    .await;
+    assert!(eventually(|| async { check.object_exists("img-2").await.unwrap_or(false) }).await);
+    assert_eq!(check.download("img-1").await?.data, b"first");
+
+    producer
+        .send(
+            "img-1",
+            &KafkaMessage::new("user-1".into(), Action::Delete, Some("img-1".to_string())),
+        )
+        .await?;
+    assert!(eventually(|| async { !check.object_exists("img-1").await.unwrap_or(true) }).await);
+    assert!(check.object_exists("img-2").await?);
+
+    shutdown.cancel();
+    worker.await??;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use axum_test::TestServer;
+use s3_client::S3;
+use service_images::{
+    ServerBuilder,
+    request_id::{REQUEST_ID_HEADER, propagate},
+    state::ServerState,
+};
+use std::sync::Arc;
+
+/// State whose S3 and Kafka clients are never used; `/ping` and unknown routes do not touch them.
+async fn state() -> anyhow::Result<ServerState> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    Ok(Arc::new(common::server_data(s3)?))
+}
+
+#[tokio::test]
+async fn test_request_id_is_echoed() -> anyhow::Result<()> {
+    let router = ServerBuilder::init_router(state().await?).layer(axum::middleware::from_fn(propagate));
+    let server = TestServer::new(router);
+
+    let response = server.get("/ping").add_header(REQUEST_ID_HEADER, "trace-123").await;
+    response.assert_header(REQUEST_ID_HEADER, "trace-123");
+    let response = server.get("/missing").add_header(REQUEST_ID_HEADER, "trace-456").await;
+    response.assert_status_not_found();
+    response.assert_header(REQUEST_ID_HEADER, "trace-456");
+
+    let generated = server.get("/ping").await.header(REQUEST_ID_HEADER);
+    assert!(uuid::Uuid::parse_str(generated.to_str()?).is_ok(), "{generated:?}");
+    let replaced = server
+        .get("/ping")
+        .add_header(REQUEST_ID_HEADER, "a b")
+        .await
+        .header(REQUEST_ID_HEADER);
+    assert_ne!(replaced, "a b");
+    Ok(())
+}
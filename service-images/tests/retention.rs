@@ -0,0 +1,67 @@
+use axum_test::TestServer;
+use kafka_client::admin::KafkaAdmin;
+use service_images::{ServerBuilder, config::ServerConfig, error::ServerError, state::ServerData};
+use testcontainers_modules::{
+    kafka::Kafka,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+
+const KAFKA_TOPIC: &str = "images-short-retention";
+
+/// Kafka with a topic whose retention is one minute, and a config that requires a week.
+async fn setup() -> anyhow::Result<(ContainerAsync<Kafka>, ServerConfig)> {
+    let kafka = Kafka::default().start().await?;
+    let host = kafka.get_host().await?;
+    let port = kafka.get_host_port_ipv4(9093).await?;
+    let brokers = format!("{}:{}", host, port);
+
+    KafkaAdmin::new(&brokers)?
+        .ensure_topic(KAFKA_TOPIC, 1, 1, Some(60_000))
+        .await?;
+
+    let mut config = ServerConfig::default();
+    config.kafka.brokers = brokers;
+    config.kafka.topic = KAFKA_TOPIC.into();
+    config.kafka.require_existing_topic = true;
+    Ok((kafka, config))
+}
+
+#[tokio::test]
+async fn test_short_retention_degrades_health() -> anyhow::Result<()> {
+    let (_kafka, config) = setup().await?;
+
+    let state = ServerData::new(&config).await?;
+    assert_eq!(state.kafka_retention.len(), 1);
+    assert_eq!(state.kafka_retention[0].retention_ms, Some(60_000));
+    assert!(!state.kafka_retention[0].is_sufficient());
+
+    let server = TestServer::new(ServerBuilder::init_router(state));
+    let response = server.get("/health").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["kafka"]["retention"][0]["topic"], KAFKA_TOPIC);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_short_retention_fails_strict_startup() -> anyhow::Result<()> {
+    let (_kafka, mut config) = setup().await?;
+    config.kafka.retention_strict = true;
+
+    let startup = ServerData::new(&config).await;
+    assert!(matches!(startup, Err(ServerError::KafkaTopic { topic, .. }) if topic == KAFKA_TOPIC));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sufficient_retention_is_healthy() -> anyhow::Result<()> {
+    let (_kafka, mut config) = setup().await?;
+    config.kafka.min_retention_ms = 30_000;
+
+    let state = ServerData::new(&config).await?;
+    let server = TestServer::new(ServerBuilder::init_router(state));
+    let body: serde_json::Value = server.get("/health").await.json();
+    assert_eq!(body["status"], "ok");
+    Ok(())
+}
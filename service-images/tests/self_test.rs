@@ -0,0 +1,97 @@
+use kafka_client::admin::KafkaAdmin;
+use s3_client::S3;
+use serde_json::Value;
+use std::process::{Command, Output};
+use testcontainers_modules::{kafka::Kafka, minio::MinIO, testcontainers::runners::AsyncRunner as _};
+
+const BUCKET: &str = "test-images";
+const KAFKA_TOPIC: &str = "images-test";
+
+/// Runs `service-images --self-test --json` with only `env` as its configuration.
+fn self_test(env: &[(&str, &str)]) -> anyhow::Result<(Output, Value)> {
+    let output = Command::new(env!("CARGO_BIN_EXE_service-images"))
+        .args(["--self-test", "--json"])
+        .current_dir(std::env::temp_dir())
+        .env_clear()
+        .envs(env.iter().copied())
+        .output()?;
+    let report = serde_json::from_slice(&output.stdout)?;
+    Ok((output, report))
+}
+
+fn status<'a>(report: &'a Value, name: &str) -> &'a str {
+    report["checks"]
+        .as_array()
+        .and_then(|checks| checks.iter().find(|c| c["name"] == name))
+        .and_then(|c| c["status"].as_str())
+        .unwrap_or_else(|| panic!("no {name} check in {report}"))
+}
+
+fn env<'a>(endpoint: &'a str, brokers: &'a str, origins: &'a str) -> Vec<(&'a str, &'a str)> {
+    vec![
+        ("HOST", "127.0.0.1"),
+        ("PORT", "0"),
+        ("ORIGINS", origins),
+        ("ACCESS_KEY", "minioadmin"),
+        ("SECRET_KEY", "minioadmin"),
+        ("REGION", "us-east-1"),
+        ("ENDPOINT_URL", endpoint),
+        ("BUCKET", BUCKET),
+        ("BROKERS", brokers),
+        ("TOPIC", KAFKA_TOPIC),
+        ("GROUP_ID", "service-images-test"),
+        ("KAFKA_REQUIRE_EXISTING_TOPIC", "true"),
+        ("KAFKA_MIN_RETENTION_MS", "1"),
+        ("JWT_SECRET", "self-test-secret-of-at-least-32-chars"),
+    ]
+}
+
+#[tokio::test]
+async fn test_self_test_passes_against_live_dependencies() -> anyhow::Result<()> {
+    let (minio, kafka) = tokio::join!(MinIO::default().start(), Kafka::default().start());
+    let (minio, kafka) = (minio?, kafka?);
+    let endpoint = format!("http://127.0.0.1:{}", minio.get_host_port_ipv4(9000).await?);
+    S3::new("minioadmin", "minioadmin", "us-east-1", &endpoint, BUCKET)
+        .await
+        .create_bucket()
+        .await?;
+    let brokers = format!("{}:{}", kafka.get_host().await?, kafka.get_host_port_ipv4(9093).await?);
+    KafkaAdmin::new(&brokers)?.ensure_topic(KAFKA_TOPIC, 1, 1, None).await?;
+
+    let (output, report) = self_test(&env(&endpoint, &brokers, "http://localhost:8080"))?;
+    assert_eq!(output.status.code(), Some(0), "{report}");
+    assert_eq!(report["passed"], true);
+    assert_eq!(status(&report, "s3"), "pass");
+    assert_eq!(status(&report, "kafka"), "pass");
+    assert_eq!(status(&report, &format!("kafka_topic:{KAFKA_TOPIC}")), "pass");
+    // Informational only.
+    assert_eq!(status(&report, "admin_token"), "warn");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_self_test_fails_on_broken_config() -> anyhow::Result<()> {
+    let (output, report) = self_test(&env("http://127.0.0.1:1", "127.0.0.1:1", "*"))?;
+    assert_eq!(output.status.code(), Some(1), "{report}");
+    assert_eq!(report["passed"], false);
+    assert_eq!(status(&report, "cors"), "pass");
+    assert_eq!(status(&report, "s3"), "fail");
+    assert_eq!(status(&report, "kafka"), "fail");
+    assert_eq!(status(&report, &format!("kafka_topic:{KAFKA_TOPIC}")), "warn");
+    assert_eq!(status(&report, "config"), "pass");
+    Ok(())
+}
+
+#[test]
+fn test_invalid_origins_are_a_config_error() -> anyhow::Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_service-images"))
+        .arg("--self-test")
+        .current_dir(std::env::temp_dir())
+        .env_clear()
+        .envs(env("http://127.0.0.1:1", "127.0.0.1:1", "http://bad\u{1}origin"))
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ORIGINS is invalid"), "{stderr}");
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use service_images::{ServerBuilder, config::ServerConfig, error::ServerError, listener::ListenAddr};
+use tokio::net::TcpListener;
+
+/// Local config whose Kafka and S3 are unreachable; the clients connect lazily, so startup does not notice.
+fn config() -> ServerConfig {
+    let mut config = ServerConfig {
+        listen: vec![ListenAddr::Tcp("127.0.0.1:0".into())],
+        ..ServerConfig::default()
+    };
+    config.kafka.brokers = "127.0.0.1:1".into();
+    config.s3.endpoint_url = "http://127.0.0.1:1".into();
+    config
+}
+
+#[tokio::test]
+async fn test_builder_starts_from_a_programmatic_config() {
+    assert!(ServerBuilder::new(config()).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_busy_address_is_an_error() -> anyhow::Result<()> {
+    let taken = TcpListener::bind("127.0.0.1:0").await?;
+    let config = ServerConfig {
+        listen: vec![ListenAddr::Tcp(taken.local_addr()?.to_string())],
+        ..config()
+    };
+    match ServerBuilder::new(config).await {
+        Err(e @ ServerError::Bind(_)) => assert!(e.to_string().starts_with("Failed to bind listeners"), "{e}"),
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("bound an address that is in use"),
+    }
+    Ok(())
+}
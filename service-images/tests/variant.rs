@@ -0,0 +1,38 @@
+mod common;
+
+use axum_prometheus::PrometheusMetricLayer;
+use axum_test::TestServer;
+use s3_client::S3;
+use service_images::{ServerBuilder, state::ServerState, variant::VARIANT_HEADER};
+use std::sync::Arc;
+
+/// State whose S3 and Kafka clients are never used; `/ping` does not touch them.
+async fn state() -> anyhow::Result<ServerState> {
+    let s3 = S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await;
+    Ok(Arc::new(common::server_data(s3)?))
+}
+
+#[tokio::test]
+async fn test_variant_is_echoed_and_labelled() -> anyhow::Result<()> {
+    // Installs the global recorder, so this is the only test of the binary that may call it.
+    let (_, metrics) = PrometheusMetricLayer::pair();
+    let server = TestServer::new(ServerBuilder::init_router(state().await?));
+
+    let response = server.get("/ping").await;
+    response.assert_header(VARIANT_HEADER, "stable");
+    let response = server.get("/ping").add_header(VARIANT_HEADER, "canary").await;
+    response.assert_header(VARIANT_HEADER, "canary");
+    let response = server.get("/missing").add_header(VARIANT_HEADER, "blue-42").await;
+    response.assert_header(VARIANT_HEADER, "other");
+
+    let rendered = metrics.render();
+    for line in [
+        r#"http_requests_by_variant_total{variant="stable",status="2xx"} 1"#,
+        r#"http_requests_by_variant_total{variant="canary",status="2xx"} 1"#,
+        r#"http_requests_by_variant_total{variant="other",status="4xx"} 1"#,
+    ] {
+        assert!(rendered.lines().any(|l| l == line), "missing {line} in:\n{rendered}");
+    }
+    assert!(!rendered.contains("blue-42"));
+    Ok(())
+}
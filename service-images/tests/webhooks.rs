@@ -0,0 +1,271 @@
+mod common;
+
+use axum::{
+    Router,
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+};
+use axum_test::TestServer;
+use kafka_client::{
+    consumer::ConsumedMessage,
+    schemas::{Action, KafkaMessage},
+};
+use s3_client::S3;
+use scylladb_client::{ScyllaConfig, webhooks::WebhookStore};
+use serde_json::{Value, json};
+use service_images::{
+    ServerBuilder,
+    config::WebhookConfig,
+    state::{ServerData, ServerState},
+    webhooks::{self, WebhookDispatcher, Webhooks},
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use testcontainers_modules::{
+    scylladb::ScyllaDB,
+    testcontainers::{ContainerAsync, runners::AsyncRunner as _},
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const ADMIN_TOKEN: &str = "admin-token";
+const SECRET: &str = "webhook-secret-0123456789";
+
+type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+/// Receiver that records every request and fails the first `failures` of them with `503`.
+async fn stub_receiver(failures: usize) -> anyhow::Result<(String, Received)> {
+    let received: Received = Arc::default();
+    let seen = Arc::new(AtomicUsize::new(0));
+    let router = Router::new().fallback({
+        let received = Arc::clone(&received);
+        move |headers: HeaderMap, body: Bytes| {
+            let received = Arc::clone(&received);
+            let seen = Arc::clone(&seen);
+            async move {
+                received.lock().unwrap().push((headers, body));
+                if seen.fetch_add(1, Ordering::SeqCst) < failures {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::NO_CONTENT
+                }
+            }
+        }
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr: SocketAddr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok((format!("http://{addr}/hook"), received))
+}
+
+async fn store(scylla: &ContainerAsync<ScyllaDB>) -> anyhow::Result<(ScyllaConfig, Arc<WebhookStore>)> {
+    let config = ScyllaConfig {
+        uri: format!("127.0.0.1:{}", scylla.get_host_port_ipv4(9042).await?),
+        keyspace: "images".into(),
+        replication_factor: 1,
+        ..Default::default()
+    };
+    let store = Arc::new(WebhookStore::new(&config, true).await?);
+    Ok((config, store))
+}
+
+/// Admin API over `store`; S3 and Kafka are never reached by the webhook routes.
+async fn server(store: &Arc<WebhookStore>, allow_private_targets: bool) -> anyhow::Result<TestServer> {
+    let state: ServerState = Arc::new(ServerData {
+        admin_token: Some(ADMIN_TOKEN.into()),
+        webhooks: Some(Webhooks {
+            store: Arc::clone(store),
+            allow_private_targets,
+        }),
+        ..common::server_data(S3::new("test", "test", "us-east-1", "http://127.0.0.1:1", "test-images").await)?
+    });
+    Ok(TestServer::new(ServerBuilder::init_router(state)))
+}
+
+fn dispatcher(
+    scylla: ScyllaConfig,
+    store: &Arc<WebhookStore>,
+    max_attempts: u32,
+    circuit_failure_threshold: u32,
+) -> WebhookDispatcher {
+    let config = WebhookConfig {
+        scylla,
+        group_id: "images-webhooks-test".into(),
+        max_attempts,
+        retry_backoff_ms: 20,
+        circuit_failure_threshold,
+        circuit_cooldown_secs: 60,
+        allow_private_targets: true,
+    };
+    WebhookDispatcher::new(Arc::clone(store), &config, CancellationToken::new())
+}
+
+async fn register(server: &TestServer, account_id: Uuid, url: &str, events: &[&str]) -> String {
+    let response = server
+        .post("/admin/webhooks")
+        .authorization_bearer(ADMIN_TOKEN)
+        .json(&json!({"account_id": account_id, "url": url, "secret": SECRET, "events": events}))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let webhook: Value = response.json();
+    assert!(webhook.get("secret").is_none(), "secret echoed back");
+    webhook["webhook_id"].as_str().unwrap().to_owned()
+}
+
+fn event(account_id: Uuid, action: Action, key: &str) -> ConsumedMessage<KafkaMessage> {
+    ConsumedMessage {
+        message: KafkaMessage::new(account_id.to_string(), action, Some(key.to_owned())),
+        headers: HashMap::new(),
+        topic: "images".into(),
+        partition: 0,
+        offset: 0,
+        timestamp: Some(1_700_000_000_000),
+    }
+}
+
+async fn deliveries(server: &TestServer, webhook_id: &str) -> Vec<Value> {
+    let response = server
+        .get(&format!("/admin/webhooks/{webhook_id}/deliveries"))
+        .authorization_bearer(ADMIN_TOKEN)
+        .await;
+    response.assert_status_ok();
+    response.json()
+}
+
+#[tokio::test]
+async fn test_matching_webhooks_get_signed_payloads() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let (config, store) = store(&scylla).await?;
+    let server = server(&store, true).await?;
+    let (url, received) = stub_receiver(0).await?;
+
+    let account_id = Uuid::now_v7();
+    let created = register(&server, account_id, &url, &["image.created"]).await;
+    let deleted_only = register(&server, account_id, &url, &["image.deleted"]).await;
+    register(&server, Uuid::now_v7(), &url, &["image.created"]).await;
+
+    dispatcher(config, &store, 3, 5)
+        .handle_event(event(account_id, Action::Create, "photo.png"))
+        .await?;
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1, "only the matching webhook of the account is called");
+    let (headers, body) = &received[0];
+    let timestamp: i64 = headers[webhooks::TIMESTAMP_HEADER].to_str()?.parse()?;
+    assert_eq!(
+        headers[webhooks::SIGNATURE_HEADER].to_str()?,
+        webhooks::sign(SECRET, timestamp, body)
+    );
+    assert_eq!(headers[webhooks::EVENT_HEADER], "image.created");
+
+    let payload: Value = serde_json::from_slice(body)?;
+    assert_eq!(payload["event"], "image.created");
+    assert_eq!(payload["account_id"], account_id.to_string());
+    assert_eq!(payload["key"], "photo.png");
+    assert_eq!(payload["id"], headers[webhooks::DELIVERY_HEADER].to_str()?);
+
+    let log = deliveries(&server, &created).await;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0]["status_code"], 204);
+    assert_eq!(log[0]["attempt"], 1);
+    assert!(log[0]["error"].is_null());
+    assert!(deliveries(&server, &deleted_only).await.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failing_endpoint_is_retried_with_backoff() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let (config, store) = store(&scylla).await?;
+    let server = server(&store, true).await?;
+    let (url, received) = stub_receiver(2).await?;
+
+    let account_id = Uuid::now_v7();
+    let webhook_id = register(&server, account_id, &url, &["image.deleted"]).await;
+    dispatcher(config, &store, 4, 10)
+        .handle_event(event(account_id, Action::Delete, "photo.png"))
+        .await?;
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 3);
+    let ids: Vec<_> = received.iter().map(|(h, _)| h[webhooks::DELIVERY_HEADER].clone()).collect();
+    assert!(ids.iter().all(|id| *id == ids[0]), "retries keep the delivery id");
+
+    let log = deliveries(&server, &webhook_id).await;
+    let attempts: Vec<_> = log
+        .iter()
+        .map(|d| (d["attempt"].as_i64().unwrap(), d["status_code"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(attempts, [(3, 204), (2, 503), (1, 503)]);
+    assert_eq!(log[1]["error"], "HTTP 503");
+    assert!(log.iter().all(|d| d["delivery_id"] == log[0]["delivery_id"]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_circuit_opens_for_a_failing_endpoint() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let (config, store) = store(&scylla).await?;
+    let server = server(&store, true).await?;
+    let (url, received) = stub_receiver(usize::MAX).await?;
+
+    let account_id = Uuid::now_v7();
+    let webhook_id = register(&server, account_id, &url, &["image.created"]).await;
+    let dispatcher = dispatcher(config, &store, 2, 3);
+    for key in ["a.png", "b.png", "c.png"] {
+        dispatcher.handle_event(event(account_id, Action::Create, key)).await?;
+    }
+
+    // Two failed attempts at the first event, one at the second, then the circuit is open.
+    assert_eq!(received.lock().unwrap().len(), 3);
+    let log = deliveries(&server, &webhook_id).await;
+    assert_eq!(log.len(), 5);
+    assert_eq!(log[0]["error"], "Circuit open");
+    assert_eq!(log[1]["error"], "Circuit open");
+    assert!(log[0]["status_code"].is_null());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registration_is_validated() -> anyhow::Result<()> {
+    let scylla = ScyllaDB::default().start().await?;
+    let (_, store) = store(&scylla).await?;
+    let server = server(&store, false).await?;
+    let account_id = Uuid::now_v7();
+
+    for (url, secret, events) in [
+        ("http://127.0.0.1:8080/hook", SECRET, json!(["image.created"])),
+        ("http://169.254.169.254/latest", SECRET, json!(["image.created"])),
+        ("file:///etc/passwd", SECRET, json!(["image.created"])),
+        ("https://example.com/hook", "short", json!(["image.created"])),
+        ("https://example.com/hook", SECRET, json!(["image.viewed"])),
+        ("https://example.com/hook", SECRET, json!([])),
+    ] {
+        let response = server
+            .post("/admin/webhooks")
+            .authorization_bearer(ADMIN_TOKEN)
+            .json(&json!({"account_id": account_id, "url": url, "secret": secret, "events": events}))
+            .await;
+        response.assert_status_bad_request();
+    }
+
+    let response = server
+        .post("/admin/webhooks")
+        .json(&json!({"account_id": account_id, "url": "https://example.com", "secret": SECRET, "events": ["image.created"]}))
+        .await;
+    response.assert_status_forbidden();
+
+    let unknown = server
+        .delete(&format!("/admin/webhooks/{}", Uuid::now_v7()))
+        .authorization_bearer(ADMIN_TOKEN)
+        .await;
+    unknown.assert_status_not_found();
+    Ok(())
+}